@@ -14,9 +14,15 @@ use serde::{Deserialize, Serialize};
 use std::env;
 use std::path::PathBuf;
 use std::process::Command;
+use std::sync::Arc;
 use tokio::fs;
 use tokio::task;
 
+use crate::connection::Connection;
+use crate::storagebrowse::{self, BrowseReason, Message as BrowseMsg, StorageBrowser};
+use crate::tmpfile;
+use crate::xmlapi::Element as XmlElement;
+
 // =====================
 // Serde XML structures
 // =====================
@@ -52,7 +58,7 @@ struct GlAttr {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ListenAttr {
     #[serde(rename = "type")]
-    ltype: String, // "address"|"none"
+    ltype: String, // "address"|"socket"|"none"
 
     #[serde(skip_serializing_if = "Option::is_none")]
     address: Option<String>,
@@ -72,9 +78,9 @@ impl DeviceGraphicsXml {
             None
         };
 
-        let listen = if s.gfx_listen_kind == "none" {
+        let listen = if s.gfx_listen_kind != "address" {
             Some(ListenAttr {
-                ltype: "none".into(),
+                ltype: s.gfx_listen_kind.clone(),
                 address: None,
             })
         } else {
@@ -89,7 +95,7 @@ impl DeviceGraphicsXml {
             })
         };
 
-        let port = if s.gfx_listen_kind == "none" {
+        let port = if s.gfx_listen_kind != "address" {
             None
         } else if s.gfx_port_auto {
             Some(-1)
@@ -223,6 +229,7 @@ pub enum Message {
     NetworkChanged(NetworkMsg),
     GraphicsChanged(GraphicsMsg),
     GraphicsEdited(Result<DeviceGraphicsXml, String>),
+    Browser(BrowseMsg),
 }
 
 /// Storage page messages (placeholder)
@@ -231,6 +238,8 @@ pub enum StorageMsg {
     DeviceTypeChanged(String),
     BusChanged(String),
     PathChanged(String),
+    /// Open the storage browser for the path
+    Browse,
 }
 
 /// Network page messages (placeholder)
@@ -245,7 +254,7 @@ pub enum NetworkMsg {
 #[derive(Debug, Clone)]
 pub enum GraphicsMsg {
     TypeChanged(String),
-    ListenKindChanged(String), // "address" | "none"
+    ListenKindChanged(String), // "address" | "socket" | "none"
     AddressChanged(String),
     PortAutoToggle(bool),
     PortChanged(i32),
@@ -260,6 +269,10 @@ pub enum GraphicsMsg {
 pub struct AddHardwareApp {
     entries: Vec<HwEntry>,
     current: Page,
+    /// Needed for the storage browser, which is unavailable without one
+    conn: Option<Arc<dyn Connection>>,
+    /// The storage browser, while open
+    browser: Option<StorageBrowser>,
 
     // Storage state (minimal placeholders)
     storage_device_type: String,
@@ -274,7 +287,7 @@ pub struct AddHardwareApp {
 
     // Graphics state
     gfx_type: String,                 // spice | vnc
-    gfx_listen_kind: String,          // address | none
+    gfx_listen_kind: String,          // address | socket | none
     gfx_address_options: Vec<String>, // includes "Default"
     gfx_address_selected: String,
     gfx_port_auto: bool,
@@ -398,6 +411,8 @@ impl AddHardwareApp {
         let state = AddHardwareApp {
             entries,
             current: Page::Storage,
+            conn: None,
+            browser: None,
             storage_device_type: "disk".to_string(),
             storage_bus: "virtio".to_string(),
             storage_path: String::new(),
@@ -428,6 +443,10 @@ impl AddHardwareApp {
         (state, Task::none())
     }
 
+    pub fn set_connection(&mut self, conn: Arc<dyn Connection>) {
+        self.conn = Some(conn);
+    }
+
     fn update(&mut self, msg: Message) -> Task<Message> {
         match msg {
             Message::SelectPage(p) => {
@@ -444,9 +463,37 @@ impl AddHardwareApp {
                     StorageMsg::DeviceTypeChanged(s) => self.storage_device_type = s,
                     StorageMsg::BusChanged(s) => self.storage_bus = s,
                     StorageMsg::PathChanged(s) => self.storage_path = s,
+                    StorageMsg::Browse => {
+                        let reason = match self.storage_device_type.as_str() {
+                            "cdrom" => BrowseReason::IsoMedia,
+                            "floppy" => BrowseReason::FloppyMedia,
+                            _ => BrowseReason::Image,
+                        };
+                        self.browser = self
+                            .conn
+                            .clone()
+                            .map(|conn| StorageBrowser::new(conn, reason, None));
+                    }
                 }
                 Task::none()
             }
+            Message::Browser(BrowseMsg::Cancel) => {
+                self.browser = None;
+                Task::none()
+            }
+            Message::Browser(BrowseMsg::Finish) => {
+                match self.browser.as_mut().and_then(StorageBrowser::finish) {
+                    Some(path) => {
+                        self.browser = None;
+                        Task::done(Message::StorageChanged(StorageMsg::PathChanged(path)))
+                    }
+                    None => Task::none(),
+                }
+            }
+            Message::Browser(inner) => match &mut self.browser {
+                Some(browser) => browser.update(inner).map(Message::Browser),
+                None => Task::none(),
+            },
             Message::NetworkChanged(nmsg) => {
                 match nmsg {
                     NetworkMsg::ModelChanged(m) => self.net_model_selected = m,
@@ -552,7 +599,7 @@ impl AddHardwareApp {
             .into()
     }
 
-    fn view_storage_page(&self) -> Element<'_, Message> {
+    pub(crate) fn view_storage_page(&self) -> Element<'_, Message> {
         // Simple placeholders for Device Type, Bus, and Path
         let device_types = vec![
             "disk".to_string(),
@@ -581,6 +628,11 @@ impl AddHardwareApp {
         let path = text_input("/path/to/disk.img", &self.storage_path)
             .on_input(|s| Message::StorageChanged(StorageMsg::PathChanged(s)))
             .padding(8);
+        let browse = button(text("Browse...")).on_press_maybe(
+            self.conn
+                .as_ref()
+                .map(|_| Message::StorageChanged(StorageMsg::Browse)),
+        );
 
         let grid = column![
             row![text("Device type:"), dev_type]
@@ -589,17 +641,23 @@ impl AddHardwareApp {
             row![text("Bus:"), bus]
                 .spacing(8)
                 .align_y(Alignment::Center),
-            row![text("Source:"), path]
+            row![text("Source:"), path, browse]
                 .spacing(8)
                 .align_y(Alignment::Center),
         ]
         .spacing(10)
         .padding(8);
 
-        container(grid).into()
+        match &self.browser {
+            Some(browser) => storagebrowse::modal(
+                container(grid).height(Length::Fill),
+                browser.view().map(Message::Browser),
+            ),
+            None => container(grid).into(),
+        }
     }
 
-    fn view_network_page(&self) -> Element<'_, Message> {
+    pub(crate) fn view_network_page(&self) -> Element<'_, Message> {
         let model_pick = pick_list(
             self.net_model_options.clone(),
             Some(self.net_model_selected.clone()),
@@ -635,7 +693,7 @@ impl AddHardwareApp {
         container(grid).into()
     }
 
-    fn view_graphics_page(&self) -> Element<'_, Message> {
+    pub(crate) fn view_graphics_page(&self) -> Element<'_, Message> {
         // Graphics type
         let gfx_types = vec!["spice".to_string(), "vnc".to_string()];
         let gfx_type_pick = pick_list(gfx_types, Some(self.gfx_type.clone()), |v| {
@@ -643,7 +701,11 @@ impl AddHardwareApp {
         });
 
         // Listen kind and address
-        let listen_kinds = vec!["address".to_string(), "none".to_string()];
+        let listen_kinds = vec![
+            "address".to_string(),
+            "socket".to_string(),
+            "none".to_string(),
+        ];
         let listen_pick = pick_list(listen_kinds, Some(self.gfx_listen_kind.clone()), |v| {
             Message::GraphicsChanged(GraphicsMsg::ListenKindChanged(v))
         });
@@ -745,25 +807,14 @@ impl AddHardwareApp {
     fn launch_graphics_xml_editor(&mut self) -> Task<Message> {
         // Build current graphics XML
         let xml = self.graphics_xml_string();
-        return match tempfile::Builder::new()
-            .prefix("vmm-graphics-")
-            .suffix(".xml")
-            .tempfile()
-        {
-            Ok(mut tf) => {
+        match tmpfile::mkstemp(&env::temp_dir(), "vmm-graphics-", ".xml") {
+            Ok((mut tf, pathbuf)) => {
                 use std::io::Write;
-                if let Err(e) = write!(tf, "{}\n", xml) {
+                if let Err(e) = writeln!(tf, "{}", xml) {
+                    let _ = std::fs::remove_file(&pathbuf);
                     self.gfx_status = Some(format!("Failed writing temp XML: {}", e));
                     return Task::none();
                 }
-                // Persist the temp file so it isn't deleted when `tf` is dropped
-                let (_file, pathbuf) = match tf.keep() {
-                    Ok((f, p)) => (f, p),
-                    Err(e) => {
-                        self.gfx_status = Some(format!("Failed to persist temp XML: {}", e.error));
-                        return Task::none();
-                    }
-                };
                 self.gfx_temp_xml_path = Some(pathbuf.clone());
 
                 // Choose editor
@@ -812,7 +863,7 @@ impl AddHardwareApp {
                 self.gfx_status = Some(format!("Failed to create temp file: {}", e));
                 Task::none()
             }
-        };
+        }
     }
 
     fn graphics_xml_string(&self) -> String {
@@ -838,8 +889,8 @@ impl AddHardwareApp {
 
         if let Some(lst) = x.listen {
             match lst.ltype.as_str() {
-                "none" => {
-                    self.gfx_listen_kind = "none".into();
+                "none" | "socket" => {
+                    self.gfx_listen_kind = lst.ltype;
                     self.gfx_address_selected = "Default".into();
                     self.gfx_port_auto = true;
                 }
@@ -879,6 +930,161 @@ impl AddHardwareApp {
         self.gfx_rendernode_selected = x.rendernode.unwrap_or_else(|| "Auto".into());
     }
 
+    /// The page that edits devices with this element name
+    pub(crate) fn page_for_device(tag: &str) -> Option<Page> {
+        match tag {
+            "disk" => Some(Page::Storage),
+            "interface" => Some(Page::Network),
+            "graphics" => Some(Page::Graphics),
+            _ => None,
+        }
+    }
+
+    /// The (listen kind, address) of a graphics device, as the page
+    /// shows them
+    fn device_listen(dev: &XmlElement) -> (String, String) {
+        let kind = match dev.get("./listen/@type").as_deref() {
+            Some(kind @ ("none" | "socket")) => kind.to_string(),
+            _ => "address".to_string(),
+        };
+        let address = dev
+            .get("./listen/@address")
+            .or_else(|| dev.attr("listen").map(str::to_string))
+            .unwrap_or_else(|| "Default".into());
+        (kind, address)
+    }
+
+    /// Fill the page state from an existing device, for editing it
+    pub(crate) fn load_device(&mut self, dev: &XmlElement) {
+        let Some(page) = Self::page_for_device(&dev.name) else {
+            return;
+        };
+        self.current = page;
+        match page {
+            Page::Storage => {
+                self.storage_device_type = dev.attr("device").unwrap_or("disk").to_string();
+                self.storage_bus = dev.get("./target/@bus").unwrap_or_default();
+                self.storage_path = ["file", "dev", "dir"]
+                    .iter()
+                    .find_map(|a| dev.get(&format!("./source/@{}", a)))
+                    .unwrap_or_default();
+            }
+            Page::Network => {
+                self.net_model_selected =
+                    dev.get("./model/@type").unwrap_or_else(|| "Default".into());
+                if !self.net_model_options.contains(&self.net_model_selected) {
+                    self.net_model_options.push(self.net_model_selected.clone());
+                }
+                self.net_mac = dev.get("./mac/@address").unwrap_or_default();
+                self.net_mac_enabled = !self.net_mac.is_empty();
+            }
+            _ => {
+                self.gfx_type = dev.attr("type").unwrap_or("vnc").to_string();
+                (self.gfx_listen_kind, self.gfx_address_selected) = Self::device_listen(dev);
+                if !self
+                    .gfx_address_options
+                    .contains(&self.gfx_address_selected)
+                {
+                    self.gfx_address_options
+                        .push(self.gfx_address_selected.clone());
+                }
+                let port = dev.attr("port").and_then(|p| p.parse::<i32>().ok());
+                self.gfx_port_auto =
+                    dev.attr("autoport") == Some("yes") || port.is_none_or(|p| p == -1);
+                self.gfx_port_value = port.filter(|p| *p > 0).unwrap_or(0);
+                self.gfx_password = dev.attr("passwd").unwrap_or_default().to_string();
+                self.gfx_password_enabled = dev.attr("passwd").is_some();
+                self.gfx_opengl = dev.get("./gl/@enable").as_deref() == Some("yes");
+                self.gfx_rendernode_selected =
+                    dev.get("./gl/@rendernode").unwrap_or_else(|| "Auto".into());
+                if !self
+                    .gfx_rendernode_options
+                    .contains(&self.gfx_rendernode_selected)
+                {
+                    self.gfx_rendernode_options
+                        .push(self.gfx_rendernode_selected.clone());
+                }
+            }
+        }
+    }
+
+    /// Write the page state back onto a device loaded with `load_device`,
+    /// leaving the settings the page doesn't cover alone
+    pub(crate) fn apply_to_device(&self, dev: &mut XmlElement) {
+        match Self::page_for_device(&dev.name) {
+            Some(Page::Storage) => {
+                if dev.attr("device") != Some(self.storage_device_type.as_str()) {
+                    dev.set_attr("device", Some(&self.storage_device_type));
+                }
+                if dev.get("./target/@bus").unwrap_or_default() != self.storage_bus {
+                    // The old address doesn't fit the new bus
+                    dev.set("./target/@bus", Some(&self.storage_bus));
+                    dev.force_remove("./address");
+                }
+                let srcattr = match dev.attr("type") {
+                    Some("block") => "dev",
+                    Some("dir") => "dir",
+                    Some("file") | None => "file",
+                    // Network and volume sources have no path to edit
+                    Some(_) => return,
+                };
+                let path = self.storage_path.trim();
+                let xpath = format!("./source/@{}", srcattr);
+                if dev.get(&xpath).unwrap_or_default() != path {
+                    dev.set(&xpath, Some(path).filter(|p| !p.is_empty()));
+                }
+            }
+            Some(Page::Network) => {
+                let model = Some(self.net_model_selected.as_str()).filter(|m| *m != "Default");
+                dev.set("./model/@type", model);
+                if self.net_mac_enabled && !self.net_mac.trim().is_empty() {
+                    dev.set("./mac/@address", Some(self.net_mac.trim()));
+                }
+            }
+            Some(_) => {
+                dev.set_attr("type", Some(&self.gfx_type));
+                let (kind, address) = Self::device_listen(dev);
+                if kind != self.gfx_listen_kind {
+                    // The old listen settings don't apply to another type
+                    dev.set_attr("listen", None);
+                    dev.force_remove("./listen");
+                    dev.set("./listen/@type", Some(&self.gfx_listen_kind));
+                }
+                if self.gfx_listen_kind != "address" {
+                    dev.set_attr("port", None);
+                    dev.set_attr("autoport", None);
+                } else {
+                    if kind != "address" || address != self.gfx_address_selected {
+                        let addr =
+                            Some(self.gfx_address_selected.as_str()).filter(|a| *a != "Default");
+                        dev.set_attr("listen", addr);
+                        if addr.is_some() || dev.find("./listen").is_some() {
+                            dev.set("./listen/@type", Some("address"));
+                            dev.set("./listen/@address", addr);
+                        }
+                    }
+                    if self.gfx_port_auto {
+                        dev.set_attr("port", None);
+                        dev.set_attr("autoport", Some("yes"));
+                    } else {
+                        dev.set_attr("port", Some(&self.gfx_port_value.to_string()));
+                        dev.set_attr("autoport", Some("no"));
+                    }
+                }
+                let passwd = Some(self.gfx_password.as_str()).filter(|_| self.gfx_password_enabled);
+                dev.set_attr("passwd", passwd);
+                if self.gfx_type == "spice" && self.gfx_opengl {
+                    dev.set("./gl/@enable", Some("yes"));
+                    let node = Some(self.gfx_rendernode_selected.as_str()).filter(|n| *n != "Auto");
+                    dev.set("./gl/@rendernode", node);
+                } else {
+                    dev.force_remove("./gl");
+                }
+            }
+            None => {}
+        }
+    }
+
     // Static adapter functions for iced::application (public for embedding)
     pub fn new_static() -> (Self, Task<Message>) {
         Self::new()
@@ -890,3 +1096,47 @@ impl AddHardwareApp {
         state.view()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load `xml` into the graphics page, edit it, and write it back
+    fn edit_graphics(xml: &str, edit: impl FnOnce(&mut AddHardwareApp)) -> XmlElement {
+        let (mut app, _) = AddHardwareApp::new_static();
+        let mut dev = XmlElement::parse(xml).unwrap();
+        app.load_device(&dev);
+        edit(&mut app);
+        app.apply_to_device(&mut dev);
+        dev
+    }
+
+    #[test]
+    fn test_graphics_listen() {
+        let socket = "<graphics type=\"spice\">\n  <listen type=\"socket\" socket=\"/tmp/spice.sock\"/>\n</graphics>\n";
+        let dev = edit_graphics(socket, |app| assert_eq!(app.gfx_listen_kind, "socket"));
+        assert_eq!(dev.get_xml(), socket);
+        let dev = edit_graphics(socket, |app| app.gfx_password_enabled = true);
+        assert_eq!(
+            dev.get("./listen/@socket").as_deref(),
+            Some("/tmp/spice.sock")
+        );
+
+        // Other listen settings survive an address edit
+        let address = "<graphics type=\"vnc\" port=\"5901\" autoport=\"no\" listen=\"1.2.3.4\">\n  <listen type=\"address\" address=\"1.2.3.4\" fromConfig=\"0\"/>\n</graphics>\n";
+        let dev = edit_graphics(address, |_| {});
+        assert_eq!(dev.get_xml(), address);
+        let dev = edit_graphics(address, |app| {
+            app.gfx_address_selected = "0.0.0.0".into();
+        });
+        assert_eq!(dev.attr("listen"), Some("0.0.0.0"));
+        assert_eq!(dev.get("./listen/@address").as_deref(), Some("0.0.0.0"));
+        assert_eq!(dev.get("./listen/@fromConfig").as_deref(), Some("0"));
+
+        let dev = edit_graphics(address, |app| app.gfx_listen_kind = "none".into());
+        assert_eq!(dev.attr("listen"), None);
+        assert_eq!(dev.attr("port"), None);
+        assert_eq!(dev.get("./listen/@type").as_deref(), Some("none"));
+        assert_eq!(dev.get("./listen/@address"), None);
+    }
+}
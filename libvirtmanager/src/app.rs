@@ -1,31 +1,41 @@
 // Main Iced parent application scaffold
 // Mirrors high-level behavior of virtManager/virtmanager.py at a minimal level.
 
-use iced::widget::{Column, button, column, container, row, scrollable, text};
-use iced::{Alignment, Element, Length, Task, Theme};
+use iced::widget::{button, column, container, row, scrollable, text};
+use iced::{Alignment, Element, Length, Subscription, Task, Theme};
 use log::debug;
 
 use crate::addhardware::{AddHardwareApp, Message as AddHwMsg};
+use crate::createvm::{CreateVmApp, Message as CreateVmMsg};
+use crate::manager::{ManagerPage, Message as ManagerMsg};
 
 #[derive(Debug, Clone)]
 pub enum Message {
     ShowAddHardware,
     CloseAddHardware,
     AddHardware(AddHwMsg),
+    ShowCreateVm,
+    CreateVm(CreateVmMsg),
+    Manager(ManagerMsg),
 }
 
 pub struct MainApp {
     show_add_hw: bool,
     add_hw: AddHardwareApp,
+    /// The New VM wizard, while open
+    create_vm: Option<CreateVmApp>,
+    manager: ManagerPage,
 }
 
 impl MainApp {
-    pub fn new() -> (Self, Task<Message>) {
+    pub fn new(uri: Option<&str>) -> (Self, Task<Message>) {
         let (add_hw, _t) = AddHardwareApp::new_static();
         (
             Self {
                 show_add_hw: false,
                 add_hw,
+                create_vm: None,
+                manager: ManagerPage::new(uri),
             },
             Task::none(),
         )
@@ -44,6 +54,21 @@ impl MainApp {
             Message::AddHardware(inner) => {
                 AddHardwareApp::update_static(&mut self.add_hw, inner).map(Message::AddHardware)
             }
+            Message::ShowCreateVm => {
+                let (wizard, task) = CreateVmApp::new_static();
+                self.create_vm = Some(wizard);
+                task.map(Message::CreateVm)
+            }
+            // Closing the wizard must not close the main window
+            Message::CreateVm(CreateVmMsg::Close) => {
+                self.create_vm = None;
+                Task::none()
+            }
+            Message::CreateVm(inner) => match self.create_vm.as_mut() {
+                Some(wizard) => CreateVmApp::update_static(wizard, inner).map(Message::CreateVm),
+                None => Task::none(),
+            },
+            Message::Manager(inner) => self.manager.update(inner).map(Message::Manager),
         }
     }
 
@@ -51,38 +76,68 @@ impl MainApp {
         let header = row![
             text("Virtual Machine Manager (Rust)").size(20),
             iced::widget::Space::with_width(Length::Fill),
+            button("New VM").on_press(Message::ShowCreateVm),
             button("Add Hardware").on_press(Message::ShowAddHardware),
         ]
         .align_y(Alignment::Center)
         .spacing(10);
 
-        let mut body: Column<Message> =
-            column![text("Connections and VMs list (placeholder)")].spacing(8);
-
-        if self.show_add_hw {
-            body = body.push(
-                container(AddHardwareApp::view_static(&self.add_hw).map(Message::AddHardware))
-                    .padding(10)
-                    .width(Length::Fill),
-            );
-        }
-
-        let content = column![header, scrollable(body).height(Length::Fill)]
+        // The wizard scrolls by itself, so it replaces the scrolled body
+        if let Some(wizard) = &self.create_vm {
+            let content = column![
+                header,
+                CreateVmApp::view_static(wizard).map(Message::CreateVm)
+            ]
             .padding(12)
             .spacing(12);
+            return container(content)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into();
+        }
+
+        let body: Element<Message> = if self.show_add_hw {
+            column![
+                scrollable(
+                    container(AddHardwareApp::view_static(&self.add_hw).map(Message::AddHardware))
+                        .padding(10)
+                        .width(Length::Fill)
+                )
+                .height(Length::Fill),
+                button("Close").on_press(Message::CloseAddHardware),
+            ]
+            .spacing(8)
+            .into()
+        } else {
+            self.manager.view().map(Message::Manager)
+        };
+
+        let content = column![header, body].padding(12).spacing(12);
 
         container(content)
             .width(Length::Fill)
             .height(Length::Fill)
             .into()
     }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        let wizard = match &self.create_vm {
+            Some(wizard) => wizard.subscription().map(Message::CreateVm),
+            None => Subscription::none(),
+        };
+        Subscription::batch([wizard, self.manager.subscription().map(Message::Manager)])
+    }
 }
 
-pub fn run() -> Result<(), String> {
+/// Run the main window, listing the VMs of `uri` or of virsh's default
+/// connection
+pub fn run(uri: Option<&str>) -> Result<(), String> {
     use iced::{application, window};
 
     debug!("Starting parent Iced MainApp");
+    let uri = uri.map(str::to_string);
     application("Virtual Machine Manager", update, view)
+        .subscription(MainApp::subscription)
         .theme(|_| Theme::default())
         .window(window::Settings {
             size: iced::Size::new(1200.0, 800.0),
@@ -91,7 +146,7 @@ pub fn run() -> Result<(), String> {
             decorations: true,
             ..Default::default()
         })
-        .run_with(MainApp::new)
+        .run_with(move || MainApp::new(uri.as_deref()))
         .map_err(|e| format!("Error starting main app: {}", e))
 }
 
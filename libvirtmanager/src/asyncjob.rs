@@ -0,0 +1,212 @@
+// Background jobs with progress and cancellation (port of the non-UI
+// parts of virtManager/asyncjob.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! An `AsyncJob` runs blocking work like disk copies on a worker thread.
+//! The work reports through the job, which is a `Meter`, and the UI reads
+//! `progress()` on a timer to draw its progress bar. Cancelling sets a
+//! flag that cancellable work polls through `Meter::is_cancelled`.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::progress::{Meter, format_number};
+
+/// Snapshot of a job's progress, for drawing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobProgress {
+    /// Status text of the current step, like "Cloning foo.img"
+    pub text: String,
+    pub amount: u64,
+    /// Total of the current step, None when it can only pulse
+    pub size: Option<u64>,
+    pub finished: bool,
+}
+
+impl JobProgress {
+    /// Fraction done, for a progress bar. None means pulse.
+    pub fn fraction(&self) -> Option<f32> {
+        match self.size {
+            Some(0) => Some(1.0),
+            Some(size) => Some((self.amount as f64 / size as f64).min(1.0) as f32),
+            None => None,
+        }
+    }
+
+    /// Progress bar label, like ` 42%  1.5 GB`
+    pub fn label(&self) -> String {
+        match self.fraction() {
+            Some(frac) => format!(
+                "{:3}% {:>5}B",
+                (frac * 100.0) as u32,
+                format_number(self.amount)
+            ),
+            None => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct JobInner {
+    title: String,
+    cancellable: bool,
+    cancelled: AtomicBool,
+    amount: AtomicU64,
+    state: Mutex<JobProgress>,
+}
+
+/// Handle to a background job. Clones share the same job.
+#[derive(Debug, Clone, Default)]
+pub struct AsyncJob {
+    inner: Arc<JobInner>,
+}
+
+impl AsyncJob {
+    /// `cancellable` says whether the UI should offer a cancel button
+    pub fn new(title: &str, cancellable: bool) -> Self {
+        Self {
+            inner: Arc::new(JobInner {
+                title: title.to_string(),
+                cancellable,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.inner.title
+    }
+
+    pub fn can_cancel(&self) -> bool {
+        self.inner.cancellable
+    }
+
+    /// Ask the work to stop. It notices at its next progress check.
+    pub fn cancel(&self) {
+        if self.inner.cancellable {
+            log::debug!("Cancelling job '{}'", self.inner.title);
+            self.inner.cancelled.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub fn progress(&self) -> JobProgress {
+        let mut ret = self
+            .inner
+            .state
+            .lock()
+            .map(|s| s.clone())
+            .unwrap_or_default();
+        ret.amount = self.inner.amount.load(Ordering::Relaxed);
+        ret
+    }
+
+    /// Set the status text without a known size, which pulses the bar
+    pub fn pulse(&self, text: &str) {
+        self.start(text, None);
+    }
+
+    fn set_state(&self, f: impl FnOnce(&mut JobProgress)) {
+        if let Ok(mut state) = self.inner.state.lock() {
+            f(&mut state);
+        }
+    }
+
+    /// Run `work` on a blocking worker thread, handing it this job to
+    /// report through. Resolves once the work is done; when the job was
+    /// cancelled, errors are replaced by a cancellation error.
+    pub async fn run<T, F>(self, work: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&AsyncJob) -> Result<T, String> + Send + 'static,
+    {
+        let job = self.clone();
+        let ret = tokio::task::spawn_blocking(move || work(&job))
+            .await
+            .map_err(|e| format!("Job '{}' failed: {}", self.inner.title, e))
+            .and_then(|r| r);
+        self.set_state(|s| s.finished = true);
+        match ret {
+            Err(e) if self.is_cancelled() => {
+                log::debug!("Job '{}' cancelled: {}", self.inner.title, e);
+                Err(format!("{} cancelled", self.inner.title))
+            }
+            ret => ret,
+        }
+    }
+}
+
+impl Meter for AsyncJob {
+    fn start(&self, text: &str, size: Option<u64>) {
+        self.inner.amount.store(0, Ordering::Relaxed);
+        self.set_state(|s| {
+            s.text = text.to_string();
+            s.size = size;
+        });
+    }
+
+    fn update(&self, amount: u64) {
+        self.inner.amount.store(amount, Ordering::Relaxed);
+    }
+
+    fn end(&self) {
+        let done = self.progress();
+        log::debug!(
+            "Job step '{}' done: {}B",
+            done.text,
+            format_number(done.amount)
+        );
+        if let Some(size) = done.size {
+            self.inner.amount.store(size, Ordering::Relaxed);
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_job_progress_and_cancel() {
+        let job = AsyncJob::new("Copying", true);
+        let ret = job
+            .clone()
+            .run(|job| {
+                job.start("Copying foo", Some(100));
+                job.update(50);
+                assert_eq!(job.progress().fraction(), Some(0.5));
+                job.end();
+                Ok(7)
+            })
+            .await;
+        assert_eq!(ret, Ok(7));
+        let progress = job.progress();
+        assert!(progress.finished);
+        assert_eq!(progress.amount, 100);
+        assert_eq!(progress.label(), "100%  100 B");
+
+        let job = AsyncJob::new("Copying", true);
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let handle = tokio::spawn(job.clone().run(move |job| {
+            job.pulse("Waiting");
+            rx.recv().unwrap();
+            if job.is_cancelled() {
+                return Err("interrupted".to_string());
+            }
+            Ok(())
+        }));
+        job.cancel();
+        tx.send(()).unwrap();
+        assert_eq!(handle.await.unwrap(), Err("Copying cancelled".to_string()));
+        assert_eq!(job.progress().fraction(), None);
+
+        let job = AsyncJob::new("Not cancellable", false);
+        job.cancel();
+        assert!(!job.is_cancelled());
+    }
+}
@@ -0,0 +1,179 @@
+// Plumbing shared by the command line tools (port of the output and
+// domain lookup helpers in virtinst/cli.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+use std::io::{BufRead, Write};
+
+use super::OptionSpec;
+use crate::connection::{Connection, DomainInfo};
+use crate::guest::Guest;
+
+/// The standard streams of a CLI run. Tools write through this instead
+/// of the process streams so tests can drive them in memory.
+pub struct CliIo<'a> {
+    /// None when stdin is a terminal, or otherwise not usable for input
+    pub stdin: Option<&'a mut dyn BufRead>,
+    pub stdout: &'a mut dyn Write,
+    pub stderr: &'a mut dyn Write,
+}
+
+impl CliIo<'_> {
+    /// Print a message to stdout. A single trailing newline in `msg` is
+    /// not doubled.
+    pub fn print(&mut self, msg: &str) {
+        let _ = writeln!(self.stdout, "{}", msg.strip_suffix('\n').unwrap_or(msg));
+    }
+
+    pub fn warn(&mut self, msg: &str) {
+        let _ = writeln!(self.stderr, "WARNING  {}", msg);
+    }
+
+    pub fn error(&mut self, msg: &str) {
+        let _ = writeln!(self.stderr, "ERROR    {}", msg);
+    }
+
+    /// Read everything left on stdin
+    pub fn read_stdin(&mut self) -> Result<String, String> {
+        let mut ret = String::new();
+        if let Some(stdin) = self.stdin.as_mut() {
+            stdin
+                .read_to_string(&mut ret)
+                .map_err(|e| format!("Error reading stdin: {}", e))?;
+        }
+        Ok(ret)
+    }
+
+    /// Ask a yes/no question on stdout, and read the answer from stdin.
+    /// EOF counts as 'no'.
+    pub fn prompt_yes_or_no(&mut self, msg: &str) -> Result<bool, String> {
+        loop {
+            let _ = write!(self.stdout, "{} (y/n): ", msg);
+            let _ = self.stdout.flush();
+            let mut line = String::new();
+            let read = match self.stdin.as_mut() {
+                Some(stdin) => stdin
+                    .read_line(&mut line)
+                    .map_err(|e| format!("Error reading stdin: {}", e))?,
+                None => 0,
+            };
+            if read == 0 {
+                return Ok(false);
+            }
+            match line.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => self.print("Please enter 'yes' or 'no'."),
+            }
+        }
+    }
+}
+
+/// Validation checks `--check` can turn off
+const CHECK_NAMES: &[&str] = &["path_in_use", "disk_size", "path_exists", "mac_in_use"];
+
+/// Parsed `--check` options, deciding which validation failures are fatal
+#[derive(Debug, Clone, Default)]
+pub struct ValidationChecks {
+    all: Option<bool>,
+    checks: Vec<(String, bool)>,
+}
+
+impl ValidationChecks {
+    /// Parse `--check` values like `path_exists=off,mac_in_use=off`
+    pub fn parse(optstrs: &[String]) -> Result<Self, String> {
+        let mut ret = Self::default();
+        let mut unknown = Vec::new();
+        for optstr in optstrs {
+            for (key, val) in super::parse_optstr_tuples(optstr)? {
+                let val = super::on_off_convert(&key, val.as_deref().unwrap_or(""))?;
+                if key == "all" {
+                    ret.all = Some(val);
+                } else if CHECK_NAMES.contains(&key.as_str()) {
+                    ret.checks.push((key, val));
+                } else {
+                    unknown.push(key);
+                }
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(format!("Unknown --check options: {:?}", unknown));
+        }
+        Ok(ret)
+    }
+
+    /// Whether a failed check should be fatal. `all=` wins over
+    /// individual settings.
+    pub fn enabled(&self, checkname: &str) -> bool {
+        if let Some(all) = self.all {
+            return all;
+        }
+        self.checks
+            .iter()
+            .rev()
+            .find(|(k, _)| k == checkname)
+            .is_none_or(|(_, v)| *v)
+    }
+
+    /// Fail with `msg` if `checkname` is enabled, otherwise just warn
+    pub fn optional_fail(
+        &self,
+        io: &mut CliIo,
+        msg: &str,
+        checkname: &str,
+        warn_on_skip: bool,
+    ) -> Result<(), String> {
+        if self.enabled(checkname) {
+            return Err(format!(
+                "{} (Use --check {}=off or --check all=off to override)",
+                msg, checkname
+            ));
+        }
+        log::debug!("Skipping --check {} error condition '{}'", checkname, msg);
+        if warn_on_skip {
+            io.warn(msg);
+        }
+        Ok(())
+    }
+}
+
+pub fn fail_conflicting(option1: &str, option2: &str) -> String {
+    format!("Cannot use {} and {} at the same time", option1, option2)
+}
+
+/// Print the suboption listing for every `--disk help` style value.
+/// Returns whether anything was printed.
+pub fn check_option_introspection(
+    values: &[(&'static OptionSpec, Vec<String>)],
+    io: &mut CliIo,
+) -> bool {
+    let mut ret = false;
+    for (spec, optstrs) in values {
+        for optstr in optstrs {
+            if super::is_introspection(optstr) {
+                io.print(&spec.introspection());
+                ret = true;
+            }
+        }
+    }
+    ret
+}
+
+/// Look up a domain by name, ID or UUID and parse its XML. Returns the
+/// domain, its inactive XML, and its live XML if it's running.
+pub fn get_domain_and_guest(
+    conn: &dyn Connection,
+    domstr: &str,
+) -> Result<(DomainInfo, Guest, Option<Guest>), String> {
+    let info = conn
+        .lookup_domain(domstr)
+        .map_err(|e| format!("Could not find domain '{}': {}", domstr, e))?;
+    let inactive = Guest::parse(&conn.domain_xml(&info.name, true)?)?;
+    let active = if info.state.is_active() {
+        Some(Guest::parse(&conn.domain_xml(&info.name, false)?)?)
+    } else {
+        None
+    };
+    Ok((info, inactive, active))
+}
@@ -0,0 +1,2449 @@
+// Option tables for the shared suboption parser (port of the Parser*
+// classes in virtinst/cli.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+use super::{ArgCtx, OptDict, OptionSpec, SubArg, on_off_convert, raw_on_off_convert};
+use crate::cloudinit::CloudInitData;
+use crate::diskbackend::local_dev_type;
+use crate::osdict::{GENERIC, osdb};
+use crate::unattended::UnattendedData;
+use crate::xmlapi::Element;
+
+pub(super) const CLEARXML_ARG: SubArg = SubArg::cb("clearxml", noset_cb);
+
+pub(super) const XPATH_ARGS: &[SubArg] = &[
+    SubArg::cb("xpath[0-9]*.delete", xpath_cb).comma(),
+    SubArg::cb("xpath[0-9]*.set", xpath_cb).comma(),
+    SubArg::cb("xpath[0-9]*.create", xpath_cb).comma(),
+    SubArg::cb("xpath[0-9]*.value", xpath_cb).comma(),
+];
+
+pub(super) const DEVICE_COMMON_ARGS: &[SubArg] = &[
+    SubArg::new("address.type", "./address/@type"),
+    SubArg::new("address.domain", "./address/@domain"),
+    SubArg::new("address.bus", "./address/@bus"),
+    SubArg::new("address.slot", "./address/@slot"),
+    SubArg::new("address.multifunction", "./address/@multifunction").onoff(),
+    SubArg::new("address.function", "./address/@function"),
+    SubArg::new("address.controller", "./address/@controller"),
+    SubArg::new("address.unit", "./address/@unit"),
+    SubArg::new("address.port", "./address/@port"),
+    SubArg::new("address.target", "./address/@target"),
+    SubArg::new("address.reg", "./address/@reg"),
+    SubArg::new("address.cssid", "./address/@cssid"),
+    SubArg::new("address.ssid", "./address/@ssid"),
+    SubArg::new("address.devno", "./address/@devno"),
+    SubArg::new("address.iobase", "./address/@iobase"),
+    SubArg::new("address.irq", "./address/@irq"),
+    SubArg::new("address.base", "./address/@base"),
+    SubArg::new("address.zpci.uid", "./address/zpci/@uid"),
+    SubArg::new("address.zpci.fid", "./address/zpci/@fid"),
+    SubArg::new("alias.name", "./alias/@name"),
+    SubArg::new("boot.order", "./boot/@order")
+        .with_cb(set_boot_order_cb)
+        .alias(&["boot_order"]),
+    SubArg::new("boot.loadparm", "./boot/@loadparm"),
+];
+
+fn noset_cb(_ctx: &mut ArgCtx) -> Result<(), String> {
+    Ok(())
+}
+
+/// Remove every element an xpath matches
+fn remove_all(root: &mut Element, xpath: &str) {
+    while root.find(xpath).is_some() {
+        root.force_remove(xpath);
+    }
+}
+
+fn require_val<'a>(ctx: &ArgCtx<'a>) -> Result<&'a str, String> {
+    ctx.val
+        .ok_or_else(|| format!("Option '{}' had no value set.", ctx.key))
+}
+
+/// `xpath.set=./foo/@bar=VAL`, `xpath.create=./baz`, `xpath.delete=./foo`
+/// and `xpath.set=./foo,xpath.value=VAL`. The actions are queued and run
+/// once all other suboptions are applied, like virtinst does.
+fn xpath_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let idx = ctx.indexes.first().copied().unwrap_or(0);
+    let action = ctx.cliname.rsplit('.').next().unwrap_or_default();
+    ctx.result.xml_actions.push(super::XmlAction {
+        index: idx,
+        action: action.to_string(),
+        value: ctx.val.map(|v| v.to_string()),
+    });
+    Ok(())
+}
+
+/// Device `boot.order=N`. When editing a guest, other devices are
+/// renumbered and the legacy `<os><boot dev=.../>` order is dropped, since
+/// libvirt rejects mixing the two.
+fn set_boot_order_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let Some(val) = ctx.val else {
+        ctx.set("./boot/@order", None);
+        return Ok(());
+    };
+    let order: u32 = val
+        .parse()
+        .map_err(|_| format!("invalid literal for int() with base 10: '{}'", val))?;
+
+    if ctx.base == "." || !ctx.base.starts_with("./devices/") {
+        ctx.set("./boot/@order", Some(val));
+        return Ok(());
+    }
+
+    remove_all(ctx.root, "./os/boot");
+    let mut others: Vec<(String, u32)> = Vec::new();
+    if let Some(devices) = ctx.root.find("./devices") {
+        let mut seen: std::collections::HashMap<&str, usize> = Default::default();
+        for dev in devices.child_elements() {
+            let n = seen.entry(dev.name.as_str()).or_insert(0);
+            *n += 1;
+            let xpath = format!("./devices/{}[{}]", dev.name, n);
+            if xpath == ctx.base {
+                continue;
+            }
+            if let Some(o) = dev.get("./boot/@order").and_then(|o| o.parse().ok()) {
+                others.push((xpath, o));
+            }
+        }
+    }
+    others.sort_by_key(|(_, o)| *o);
+    ctx.set("./boot/@order", Some(val));
+
+    let mut next: Option<u32> = None;
+    for (xpath, o) in others {
+        if Some(o) == next || o == order {
+            let newval = o + 1;
+            next = Some(newval);
+            ctx.root
+                .set(&format!("{}/boot/@order", xpath), Some(&newval.to_string()));
+            continue;
+        }
+        break;
+    }
+    Ok(())
+}
+
+/// Convert a MiB value from the command line to the KiB the XML stores
+fn mib_to_kib(key: &str, val: &str) -> Result<String, String> {
+    let mib: f64 = val
+        .parse()
+        .map_err(|_| format!("Improper value for '{}': {}", key, val))?;
+    Ok(((mib * 1024.0) as u64).to_string())
+}
+
+fn set_mib_cb(ctx: &mut ArgCtx, rel: &str) -> Result<(), String> {
+    let kib = ctx.val.map(|v| mib_to_kib(ctx.key, v)).transpose()?;
+    ctx.set(rel, kib.as_deref());
+    Ok(())
+}
+
+/////////////////////
+// Guest level     //
+/////////////////////
+
+static METADATA: OptionSpec = OptionSpec {
+    name: "metadata",
+    aliases: &[],
+    xpath: None,
+    is_list: false,
+    remove_first: &[],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::new("name", "./name").comma(),
+        SubArg::new("title", "./title").comma(),
+        SubArg::new("uuid", "./uuid"),
+        SubArg::new("genid", "./genid"),
+        SubArg::new("genid_enable", "./genid").present(),
+        SubArg::new("description", "./description").comma(),
+        SubArg::cb("os_full_id", set_os_full_id_cb),
+    ],
+    prepare: None,
+};
+
+fn set_os_full_id_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    ctx.set(
+        "./metadata/libosinfo:libosinfo/libosinfo:os/@id",
+        ctx.val.map(|v| v.to_string()).as_deref(),
+    );
+    Ok(())
+}
+
+static EVENTS: OptionSpec = OptionSpec {
+    name: "events",
+    aliases: &[],
+    xpath: None,
+    is_list: false,
+    remove_first: &[],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::new("on_poweroff", "./on_poweroff"),
+        SubArg::new("on_reboot", "./on_reboot"),
+        SubArg::new("on_crash", "./on_crash"),
+        SubArg::new("on_lockfailure", "./on_lockfailure"),
+    ],
+    prepare: None,
+};
+
+static SYSINFO: OptionSpec = OptionSpec {
+    name: "sysinfo",
+    aliases: &[],
+    xpath: Some("./sysinfo"),
+    is_list: false,
+    remove_first: &["type"],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::cb("type", set_sysinfo_type_cb).comma(),
+        SubArg::new("bios.vendor", "./bios/entry[@name='vendor']"),
+        SubArg::new("bios.version", "./bios/entry[@name='version']"),
+        SubArg::new("bios.date", "./bios/entry[@name='date']"),
+        SubArg::new("bios.release", "./bios/entry[@name='release']"),
+        SubArg::new("system.manufacturer", "./system/entry[@name='manufacturer']"),
+        SubArg::new("system.product", "./system/entry[@name='product']"),
+        SubArg::new("system.version", "./system/entry[@name='version']"),
+        SubArg::new("system.serial", "./system/entry[@name='serial']"),
+        SubArg::cb("system.uuid", set_sysinfo_uuid_cb),
+        SubArg::new("system.sku", "./system/entry[@name='sku']"),
+        SubArg::new("system.family", "./system/entry[@name='family']"),
+        SubArg::new("baseBoard.manufacturer", "./baseBoard/entry[@name='manufacturer']"),
+        SubArg::new("baseBoard.product", "./baseBoard/entry[@name='product']"),
+        SubArg::new("baseBoard.version", "./baseBoard/entry[@name='version']"),
+        SubArg::new("baseBoard.serial", "./baseBoard/entry[@name='serial']"),
+        SubArg::new("baseBoard.asset", "./baseBoard/entry[@name='asset']"),
+        SubArg::new("baseBoard.location", "./baseBoard/entry[@name='location']"),
+        SubArg::new("chassis.manufacturer", "./chassis/entry[@name='manufacturer']"),
+        SubArg::new("chassis.version", "./chassis/entry[@name='version']"),
+        SubArg::new("chassis.serial", "./chassis/entry[@name='serial']"),
+        SubArg::new("chassis.asset", "./chassis/entry[@name='asset']"),
+        SubArg::new("chassis.sku", "./chassis/entry[@name='sku']"),
+    ],
+    prepare: Some(default_sysinfo_type),
+};
+
+/// Any `--sysinfo` string means smbios, unless it says otherwise, since
+/// libvirt rejects a sysinfo without a type
+fn default_sysinfo_type(d: &mut OptDict, optstr: &str) -> Result<(), String> {
+    if !optstr.is_empty() && !d.contains("type") {
+        d.insert("type", Some("smbios".into()));
+    }
+    Ok(())
+}
+
+/// `type=host|emulate` only pick the smbios mode, `type=smbios` points
+/// the smbios mode at the sysinfo
+fn set_sysinfo_type_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let val = require_val(ctx)?;
+    if val == "host" || val == "emulate" {
+        ctx.set("/os/smbios/@mode", Some(val));
+        if ctx.inst().is_some_and(|s| !s.has_content()) {
+            let base = ctx.base.to_string();
+            ctx.root.force_remove(&base);
+        }
+        return Ok(());
+    }
+    if val == "smbios" {
+        ctx.set("/os/smbios/@mode", Some("sysinfo"));
+    }
+    ctx.set("./@type", Some(val));
+    Ok(())
+}
+
+/// The sysinfo UUID has to match the guest's, so it replaces it
+fn set_sysinfo_uuid_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    ctx.set("./system/entry[@name='uuid']", ctx.val);
+    ctx.set("/uuid", ctx.val);
+    Ok(())
+}
+
+static MEMORY: OptionSpec = OptionSpec {
+    name: "memory",
+    aliases: &[],
+    xpath: None,
+    is_list: false,
+    remove_first: &["memory"],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::new("memory", "./memory").with_cb(|c| set_mib_cb(c, "./memory")),
+        SubArg::new("currentMemory", "./currentMemory")
+            .with_cb(|c| set_mib_cb(c, "./currentMemory")),
+        SubArg::new("maxMemory", "./maxMemory")
+            .with_cb(|c| set_mib_cb(c, "./maxMemory"))
+            .alias(&["hotplugmemorymax"]),
+        SubArg::new("maxMemory.slots", "./maxMemory/@slots").alias(&["hotplugmemoryslots"]),
+        SubArg::cb("maxmemory", noset_cb),
+        SubArg::new("hugepages", "./memoryBacking/hugepages").present(),
+    ],
+    prepare: Some(convert_old_memory_options),
+};
+
+/// Historically `memory` meant `<currentMemory>` and `maxmemory` meant
+/// `<memory>`. Only honor the new names if currentMemory is passed.
+fn convert_old_memory_options(d: &mut OptDict, _optstr: &str) -> Result<(), String> {
+    let havecur = d.contains("currentMemory");
+    let havemax = d.contains("maxmemory");
+    let havemem = d.contains("memory");
+    if havecur {
+        if havemax {
+            let v = d.pop("maxmemory").flatten();
+            d.insert("memory", v);
+        }
+    } else if havemax {
+        if havemem {
+            d.rename("memory", "currentMemory");
+        }
+        d.rename("maxmemory", "memory");
+    } else if havemem {
+        d.rename("memory", "currentMemory");
+    }
+    Ok(())
+}
+
+static VCPUS: OptionSpec = OptionSpec {
+    name: "vcpus",
+    aliases: &[],
+    xpath: None,
+    is_list: false,
+    remove_first: &["vcpu"],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::cb("maxvcpus", noset_cb),
+        SubArg::cb("cpuset", noset_cb).comma(),
+        SubArg::cb("vcpus", noset_cb),
+        SubArg::new("sockets", "./cpu/topology/@sockets"),
+        SubArg::new("dies", "./cpu/topology/@dies"),
+        SubArg::new("clusters", "./cpu/topology/@clusters"),
+        SubArg::new("cores", "./cpu/topology/@cores"),
+        SubArg::new("threads", "./cpu/topology/@threads"),
+        SubArg::new("vcpu", "./vcpu"),
+        SubArg::new("vcpu.current", "./vcpu/@current"),
+        SubArg::new("vcpu.cpuset", "./vcpu/@cpuset")
+            .with_cb(set_cpuset_cb)
+            .comma(),
+        SubArg::new("vcpu.placement", "./vcpu/@placement").alias(&["placement"]),
+        SubArg::new("vcpus.vcpu[0-9]*.id", "./vcpus/vcpu[{}]/@id"),
+        SubArg::new("vcpus.vcpu[0-9]*.enabled", "./vcpus/vcpu[{}]/@enabled").yesno(),
+        SubArg::new(
+            "vcpus.vcpu[0-9]*.hotpluggable",
+            "./vcpus/vcpu[{}]/@hotpluggable",
+        )
+        .yesno(),
+        SubArg::new("vcpus.vcpu[0-9]*.order", "./vcpus/vcpu[{}]/@order"),
+    ],
+    prepare: Some(convert_old_vcpu_opts),
+};
+
+fn convert_old_vcpu_opts(d: &mut OptDict, _optstr: &str) -> Result<(), String> {
+    d.rename("cpuset", "vcpu.cpuset");
+    d.rename("vcpus", "vcpu");
+
+    let havemax = d.contains("maxvcpus");
+    if d.contains("vcpu.current") {
+        if havemax {
+            d.rename("maxvcpus", "vcpu");
+        }
+    } else if havemax {
+        d.rename("vcpu", "vcpu.current");
+        d.rename("maxvcpus", "vcpu");
+    }
+    Ok(())
+}
+
+/// cpuset=auto is an old way to ask for placement=auto
+fn set_cpuset_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    if ctx.val == Some("auto") {
+        ctx.set("./vcpu/@placement", Some("auto"));
+    } else {
+        let val = ctx.val.map(|v| v.to_string());
+        ctx.set("./vcpu/@cpuset", val.as_deref());
+    }
+    Ok(())
+}
+
+/// CPU modes that replace the model instead of naming one
+pub const CPU_SPECIAL_MODES: &[&str] = &["host-model", "host-passthrough", "maximum", "clear"];
+
+/// Policies a `<cpu><feature>` can have
+pub const CPU_FEATURE_POLICIES: &[&str] = &["force", "require", "optional", "disable", "forbid"];
+
+static CPU: OptionSpec = OptionSpec {
+    name: "cpu",
+    aliases: &[],
+    xpath: Some("./cpu"),
+    is_list: false,
+    remove_first: &["model"],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::new("model", "./model").with_cb(set_cpu_model_cb),
+        SubArg::new("model.fallback", "./model/@fallback"),
+        SubArg::new("model.vendor_id", "./model/@vendor_id"),
+        SubArg::new("vendor", "./vendor"),
+        SubArg::new("mode", "./@mode"),
+        SubArg::new("match", "./@match"),
+        SubArg::new("check", "./@check"),
+        SubArg::new("migratable", "./@migratable").onoff(),
+        SubArg::new("topology.sockets", "./topology/@sockets"),
+        SubArg::new("topology.dies", "./topology/@dies"),
+        SubArg::new("topology.clusters", "./topology/@clusters"),
+        SubArg::new("topology.cores", "./topology/@cores"),
+        SubArg::new("topology.threads", "./topology/@threads"),
+        SubArg::new("cache.level", "./cache/@level"),
+        SubArg::new("cache.mode", "./cache/@mode"),
+        SubArg::new("maxphysaddr.mode", "./maxphysaddr/@mode"),
+        SubArg::new("maxphysaddr.bits", "./maxphysaddr/@bits"),
+        SubArg::cb("force", set_feature_cb),
+        SubArg::cb("require", set_feature_cb),
+        SubArg::cb("optional", set_feature_cb),
+        SubArg::cb("disable", set_feature_cb),
+        SubArg::cb("forbid", set_feature_cb),
+        SubArg::new("numa.cell[0-9]*.id", "./numa/cell[{}]/@id").alias(&["cell[0-9]*.id"]),
+        SubArg::new("numa.cell[0-9]*.cpus", "./numa/cell[{}]/@cpus")
+            .alias(&["cell[0-9]*.cpus"])
+            .comma(),
+        SubArg::new("numa.cell[0-9]*.memory", "./numa/cell[{}]/@memory")
+            .alias(&["cell[0-9]*.memory"]),
+        SubArg::new("numa.cell[0-9]*.unit", "./numa/cell[{}]/@unit"),
+        SubArg::new("numa.cell[0-9]*.memAccess", "./numa/cell[{}]/@memAccess"),
+        SubArg::new("numa.cell[0-9]*.discard", "./numa/cell[{}]/@discard").yesno(),
+        SubArg::new(
+            "numa.cell[0-9]*.distances.sibling[0-9]*.id",
+            "./numa/cell[{}]/distances/sibling[{}]/@id",
+        ),
+        SubArg::new(
+            "numa.cell[0-9]*.distances.sibling[0-9]*.value",
+            "./numa/cell[{}]/distances/sibling[{}]/@value",
+        ),
+        SubArg::new(
+            "numa.cell[0-9]*.cache[0-9]*.level",
+            "./numa/cell[{}]/cache[{}]/@level",
+        ),
+        SubArg::new(
+            "numa.cell[0-9]*.cache[0-9]*.associativity",
+            "./numa/cell[{}]/cache[{}]/@associativity",
+        ),
+        SubArg::new(
+            "numa.cell[0-9]*.cache[0-9]*.policy",
+            "./numa/cell[{}]/cache[{}]/@policy",
+        ),
+        SubArg::new(
+            "numa.cell[0-9]*.cache[0-9]*.size.value",
+            "./numa/cell[{}]/cache[{}]/size/@value",
+        ),
+        SubArg::new(
+            "numa.cell[0-9]*.cache[0-9]*.size.unit",
+            "./numa/cell[{}]/cache[{}]/size/@unit",
+        ),
+        SubArg::new(
+            "numa.cell[0-9]*.cache[0-9]*.line.value",
+            "./numa/cell[{}]/cache[{}]/line/@value",
+        ),
+        SubArg::new(
+            "numa.cell[0-9]*.cache[0-9]*.line.unit",
+            "./numa/cell[{}]/cache[{}]/line/@unit",
+        ),
+    ],
+    prepare: Some(convert_old_feature_options),
+};
+
+/// `--cpu foo,+x2apic,-vmx` is shorthand for force=x2apic,disable=vmx
+fn convert_old_feature_options(d: &mut OptDict, _optstr: &str) -> Result<(), String> {
+    let mut converted = Vec::new();
+    d.0.retain(|(key, val)| {
+        if val.is_some() || key.len() == 1 {
+            return true;
+        }
+        let policy = match key.as_bytes()[0] {
+            b'+' => "force",
+            b'-' => "disable",
+            _ => return true,
+        };
+        converted.push((policy.to_string(), Some(key[1..].to_string())));
+        false
+    });
+    d.0.extend(converted);
+    Ok(())
+}
+
+fn set_cpu_model_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let mut val = ctx.val.map(|v| v.to_string());
+    if val.as_deref() == Some("host") {
+        log::warn!("CPU model=host is deprecated, use model=host-model");
+        val = Some("host-model".into());
+    }
+    if val.as_deref() == Some("none") {
+        val = Some("clear".into());
+    }
+    if val.as_deref() == Some("host-copy") {
+        log::warn!("CPU mode=host-copy no longer supported, using mode=host-model");
+        val = Some("host-model".into());
+    }
+
+    match val.as_deref() {
+        Some("clear") => {
+            if let Some(cpu) = ctx.root.find_mut(ctx.base) {
+                cpu.clear();
+            }
+        }
+        Some(mode) if CPU_SPECIAL_MODES.contains(&mode) => {
+            for rel in [
+                "./model",
+                "./vendor",
+                "./@migratable",
+                "./@check",
+                "./@match",
+            ] {
+                ctx.set(rel, None);
+            }
+            let feature = ctx.xpath("./feature");
+            remove_all(ctx.root, &feature);
+            // Keep the stub around even if it's otherwise empty
+            let base = ctx.base.to_string();
+            ctx.root.set_bool(&base, true);
+            ctx.set("./@mode", Some(mode));
+        }
+        Some(model) => {
+            ctx.set("./@migratable", None);
+            ctx.set("./@mode", Some("custom"));
+            if ctx.get("./@match").is_none() {
+                ctx.set("./@match", Some("exact"));
+            }
+            ctx.set("./model", Some(model));
+        }
+        None => ctx.set("./model", None),
+    }
+    Ok(())
+}
+
+fn set_feature_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let policy = ctx.cliname;
+    let Some(name) = ctx.val else {
+        return Ok(());
+    };
+    let existing = ctx.xpath(&format!("./feature[@name='{}']", name));
+    if ctx.root.find(&existing).is_some() {
+        ctx.root.set(&format!("{}/@policy", existing), Some(policy));
+        return Ok(());
+    }
+    // Like virtinst, new features are written as policy= then name=
+    let count = ctx.inst().map(|i| i.count("./feature")).unwrap_or(0);
+    let xpath = ctx.xpath(&format!("./feature[{}]", count + 1));
+    ctx.root.set(&format!("{}/@policy", xpath), Some(policy));
+    ctx.root.set(&format!("{}/@name", xpath), Some(name));
+    Ok(())
+}
+
+/// Boot device names for the legacy `--boot hd,cdrom` order
+pub const BOOT_DEVICES: &[&str] = &["hd", "cdrom", "fd", "network"];
+
+static BOOT: OptionSpec = OptionSpec {
+    name: "boot",
+    aliases: &[],
+    xpath: Some("./os"),
+    is_list: false,
+    remove_first: &[],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::cb("hd", noset_cb),
+        SubArg::cb("cdrom", noset_cb),
+        SubArg::cb("fd", noset_cb),
+        SubArg::cb("network", noset_cb),
+        SubArg::cb("_bootorder", set_bootorder_cb),
+        SubArg::new("os_type", "./type"),
+        SubArg::new("arch", "./type/@arch"),
+        SubArg::new("machine", "./type/@machine"),
+        SubArg::new("bootloader", "/bootloader"),
+        SubArg::new("bootloader_args", "/bootloader_args"),
+        SubArg::new("domain_type", "/@type"),
+        SubArg::new("emulator", "/devices/emulator"),
+        SubArg::cb("uefi", set_uefi_cb),
+        SubArg::new("loader", "./loader"),
+        SubArg::new("loader.readonly", "./loader/@readonly")
+            .yesno()
+            .alias(&["loader_ro"]),
+        SubArg::new("loader.type", "./loader/@type").alias(&["loader_type"]),
+        SubArg::new("loader.secure", "./loader/@secure")
+            .yesno()
+            .alias(&["loader_secure"]),
+        SubArg::new("loader.stateless", "./loader/@stateless").yesno(),
+        SubArg::new("firmware", "./@firmware"),
+        SubArg::new(
+            "firmware.feature[0-9]*.enabled",
+            "./firmware/feature[{}]/@enabled",
+        )
+        .yesno(),
+        SubArg::new(
+            "firmware.feature[0-9]*.name",
+            "./firmware/feature[{}]/@name",
+        ),
+        SubArg::new("nvram", "./nvram"),
+        SubArg::new("nvram.template", "./nvram/@template").alias(&["nvram_template"]),
+        SubArg::new("nvram.templateFormat", "./nvram/@templateFormat"),
+        SubArg::new("boot[0-9]*.dev", "./boot[{}]/@dev"),
+        SubArg::new("bootmenu.enable", "./bootmenu/@enable")
+            .yesno()
+            .alias(&["menu"]),
+        SubArg::new("bootmenu.timeout", "./bootmenu/@timeout"),
+        SubArg::new("bios.useserial", "./bios/@useserial")
+            .yesno()
+            .alias(&["useserial"]),
+        SubArg::new("bios.rebootTimeout", "./bios/@rebootTimeout").alias(&["rebootTimeout"]),
+        SubArg::new("smbios.mode", "./smbios/@mode").alias(&["smbios_mode"]),
+        SubArg::new("kernel", "./kernel"),
+        SubArg::new("initrd", "./initrd"),
+        SubArg::new("cmdline", "./cmdline")
+            .alias(&["extra_args", "kernel_args"])
+            .comma(),
+        SubArg::new("dtb", "./dtb"),
+        SubArg::new("acpi.table", "./acpi/table"),
+        SubArg::new("acpi.table.type", "./acpi/table/@type"),
+        SubArg::new("init", "./init"),
+        SubArg::cb("initargs", set_initargs_cb),
+        SubArg::new("initarg[0-9]*", "./initarg[{}]"),
+        SubArg::new("initenv[0-9]*", "./initenv[{}]"),
+        SubArg::new("initenv[0-9]*.name", "./initenv[{}]/@name"),
+        SubArg::new("initdir", "./initdir"),
+        SubArg::new("inituser", "./inituser"),
+        SubArg::new("initgroup", "./initgroup"),
+    ],
+    prepare: Some(convert_boot_order),
+};
+
+fn convert_boot_order(d: &mut OptDict, _optstr: &str) -> Result<(), String> {
+    let mut order: Vec<String> = Vec::new();
+    for key in d.keys() {
+        if !BOOT_DEVICES.contains(&key.as_str()) {
+            continue;
+        }
+        d.pop(&key);
+        if !order.contains(&key) {
+            order.push(key);
+        }
+    }
+    if !order.is_empty() {
+        d.insert("_bootorder", Some(order.join(",")));
+    }
+    // Back compat to allow uefi to have no value specified
+    if d.get("uefi") == Some(None) {
+        d.insert("uefi", Some("on".into()));
+    }
+    Ok(())
+}
+
+fn set_bootorder_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let boot = ctx.xpath("./boot");
+    remove_all(ctx.root, &boot);
+    for (i, dev) in ctx.val.unwrap_or_default().split(',').enumerate() {
+        ctx.set(&format!("./boot[{}]/@dev", i + 1), Some(dev));
+    }
+    Ok(())
+}
+
+/// uefi=on requests firmware autoselection; libvirt picks the actual
+/// loader and nvram template from domain capabilities.
+fn set_uefi_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let Some(enable) = ctx.val_onoff()? else {
+        return Ok(());
+    };
+    if enable {
+        ctx.set("./@firmware", Some("efi"));
+    } else {
+        ctx.set("./@firmware", None);
+        for rel in ["./loader", "./nvram", "./firmware"] {
+            let xpath = ctx.xpath(rel);
+            ctx.root.force_remove(&xpath);
+        }
+    }
+    Ok(())
+}
+
+fn set_initargs_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let initarg = ctx.xpath("./initarg");
+    remove_all(ctx.root, &initarg);
+    let args = super::shlex_split(ctx.val.unwrap_or_default())?;
+    for (i, arg) in args.iter().enumerate() {
+        ctx.set(&format!("./initarg[{}]", i + 1), Some(arg));
+    }
+    Ok(())
+}
+
+static FEATURES: OptionSpec = OptionSpec {
+    name: "features",
+    aliases: &[],
+    xpath: Some("./features"),
+    is_list: false,
+    remove_first: &[],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::new("acpi", "./acpi").present(),
+        SubArg::new("apic", "./apic").present(),
+        SubArg::new("pae", "./pae").present(),
+        SubArg::new("privnet", "./privnet").present(),
+        SubArg::new("hap", "./hap").present(),
+        SubArg::new("viridian", "./viridian").present(),
+        SubArg::new("apic.eoi", "./apic/@eoi")
+            .onoff()
+            .alias(&["eoi"]),
+        SubArg::new("pmu.state", "./pmu/@state")
+            .onoff()
+            .alias(&["pmu"]),
+        SubArg::new("hyperv.relaxed.state", "./hyperv/relaxed/@state")
+            .onoff()
+            .alias(&["hyperv_relaxed"]),
+        SubArg::new("hyperv.vapic.state", "./hyperv/vapic/@state")
+            .onoff()
+            .alias(&["hyperv_vapic"]),
+        SubArg::new("hyperv.spinlocks.state", "./hyperv/spinlocks/@state")
+            .onoff()
+            .alias(&["hyperv_spinlocks"]),
+        SubArg::new("hyperv.spinlocks.retries", "./hyperv/spinlocks/@retries")
+            .alias(&["hyperv_spinlocks_retries"]),
+        SubArg::new("hyperv.vpindex.state", "./hyperv/vpindex/@state").onoff(),
+        SubArg::new("hyperv.runtime.state", "./hyperv/runtime/@state").onoff(),
+        SubArg::new("hyperv.synic.state", "./hyperv/synic/@state")
+            .onoff()
+            .alias(&["hyperv_synic"]),
+        SubArg::new("hyperv.stimer.state", "./hyperv/stimer/@state").onoff(),
+        SubArg::new(
+            "hyperv.stimer.direct.state",
+            "./hyperv/stimer/direct/@state",
+        )
+        .onoff(),
+        SubArg::new("hyperv.reset.state", "./hyperv/reset/@state")
+            .onoff()
+            .alias(&["hyperv_reset"]),
+        SubArg::new("hyperv.frequencies.state", "./hyperv/frequencies/@state").onoff(),
+        SubArg::new(
+            "hyperv.reenlightenment.state",
+            "./hyperv/reenlightenment/@state",
+        )
+        .onoff(),
+        SubArg::new("hyperv.tlbflush.state", "./hyperv/tlbflush/@state").onoff(),
+        SubArg::new("hyperv.ipi.state", "./hyperv/ipi/@state").onoff(),
+        SubArg::new("hyperv.evmcs.state", "./hyperv/evmcs/@state").onoff(),
+        SubArg::new("hyperv.avic.state", "./hyperv/avic/@state").onoff(),
+        SubArg::new("vmport.state", "./vmport/@state")
+            .onoff()
+            .alias(&["vmport"]),
+        SubArg::new("kvm.hidden.state", "./kvm/hidden/@state")
+            .onoff()
+            .alias(&["kvm_hidden"]),
+        SubArg::new("kvm.hint-dedicated.state", "./kvm/hint-dedicated/@state").onoff(),
+        SubArg::new("kvm.poll-control.state", "./kvm/poll-control/@state").onoff(),
+        SubArg::new("kvm.pv-ipi.state", "./kvm/pv-ipi/@state").onoff(),
+        SubArg::new("pvspinlock.state", "./pvspinlock/@state").onoff(),
+        SubArg::new("gic.version", "./gic/@version").alias(&["gic_version"]),
+        SubArg::new("smm.state", "./smm/@state")
+            .onoff()
+            .alias(&["smm"]),
+        SubArg::new("vmcoreinfo.state", "./vmcoreinfo/@state")
+            .onoff()
+            .alias(&["vmcoreinfo"]),
+        SubArg::new("ioapic.driver", "./ioapic/@driver"),
+        SubArg::new("msrs.unknown", "./msrs/@unknown"),
+    ],
+    prepare: None,
+};
+
+static CLOCK: OptionSpec = OptionSpec {
+    name: "clock",
+    aliases: &[],
+    xpath: Some("./clock"),
+    is_list: false,
+    remove_first: &[],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::cb("pit_tickpolicy", set_timer_cb),
+        SubArg::cb("rtc_tickpolicy", set_timer_cb),
+        SubArg::cb("platform_present", set_timer_cb),
+        SubArg::cb("pit_present", set_timer_cb),
+        SubArg::cb("rtc_present", set_timer_cb),
+        SubArg::cb("hpet_present", set_timer_cb),
+        SubArg::cb("tsc_present", set_timer_cb),
+        SubArg::cb("kvmclock_present", set_timer_cb),
+        SubArg::cb("hypervclock_present", set_timer_cb),
+        SubArg::new("offset", "./@offset"),
+        SubArg::new("timer[0-9]*.name", "./timer[{}]/@name"),
+        SubArg::new("timer[0-9]*.present", "./timer[{}]/@present").yesno(),
+        SubArg::new("timer[0-9]*.tickpolicy", "./timer[{}]/@tickpolicy"),
+        SubArg::new("timer[0-9]*.track", "./timer[{}]/@track"),
+        SubArg::new("timer[0-9]*.mode", "./timer[{}]/@mode"),
+        SubArg::new("timer[0-9]*.frequency", "./timer[{}]/@frequency"),
+        SubArg::new(
+            "timer[0-9]*.catchup.threshold",
+            "./timer[{}]/catchup/@threshold",
+        ),
+        SubArg::new("timer[0-9]*.catchup.slew", "./timer[{}]/catchup/@slew"),
+        SubArg::new("timer[0-9]*.catchup.limit", "./timer[{}]/catchup/@limit"),
+    ],
+    prepare: Some(remove_old_clock_options),
+};
+
+/// The *_tickpolicy options for these timers never had any effect
+fn remove_old_clock_options(d: &mut OptDict, _optstr: &str) -> Result<(), String> {
+    for key in [
+        "platform_tickpolicy",
+        "hpet_tickpolicy",
+        "tsc_tickpolicy",
+        "kvmclock_tickpolicy",
+        "hypervclock_tickpolicy",
+    ] {
+        d.pop(key);
+    }
+    Ok(())
+}
+
+/// `rtc_tickpolicy=catchup` style helpers for a single named timer
+fn set_timer_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let (tname, propname) = ctx.cliname.split_once('_').unwrap_or((ctx.cliname, ""));
+    let val = match propname {
+        "present" => ctx
+            .val_onoff()?
+            .map(|b| if b { "yes" } else { "no" }.to_string()),
+        _ => ctx.val.map(|v| v.to_string()),
+    };
+    let timer = format!("./timer[@name='{}']/@{}", tname, propname);
+    ctx.set(&timer, val.as_deref());
+    Ok(())
+}
+
+static QEMU_COMMANDLINE: OptionSpec = OptionSpec {
+    name: "qemu-commandline",
+    aliases: &["qemu_commandline"],
+    xpath: Some("./qemu:commandline"),
+    is_list: false,
+    remove_first: &[],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::cb("args", qemu_args_cb).comma(),
+        SubArg::cb("env", qemu_env_cb).comma(),
+    ],
+    prepare: Some(convert_qemu_commandline),
+};
+
+/// The whole option string is a single value, commas and all
+fn convert_qemu_commandline(d: &mut OptDict, optstr: &str) -> Result<(), String> {
+    d.0.clear();
+    let (key, val) = match optstr.split_once('=') {
+        Some((k, v)) if ["env", "args", "clearxml"].contains(&k) => (k, v),
+        _ => ("args", optstr),
+    };
+    d.insert(key, Some(val.to_string()));
+    Ok(())
+}
+
+fn qemu_args_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    for arg in super::shlex_split(require_val(ctx)?)? {
+        let count = ctx.inst().map(|i| i.count("./qemu:arg")).unwrap_or(0);
+        ctx.set(&format!("./qemu:arg[{}]/@value", count + 1), Some(&arg));
+    }
+    Ok(())
+}
+
+fn qemu_env_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let val = require_val(ctx)?;
+    let (name, envval) = val
+        .split_once('=')
+        .ok_or_else(|| format!("not enough values to unpack: '{}'", val))?;
+    let count = ctx.inst().map(|i| i.count("./qemu:env")).unwrap_or(0);
+    let xpath = format!("./qemu:env[{}]", count + 1);
+    ctx.set(&format!("{}/@name", xpath), Some(name));
+    ctx.set(&format!("{}/@value", xpath), Some(envval));
+    Ok(())
+}
+
+static XML: OptionSpec = OptionSpec {
+    name: "xml",
+    aliases: &[],
+    xpath: None,
+    is_list: false,
+    remove_first: &[],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::cb("xpath.delete", xpath_cb).comma(),
+        SubArg::cb("xpath.set", xpath_cb).comma(),
+        SubArg::cb("xpath.create", xpath_cb).comma(),
+        SubArg::cb("xpath.value", xpath_cb).comma(),
+    ],
+    prepare: Some(convert_xml_option),
+};
+
+/// `--xml FOO` is shorthand for `--xml xpath.set=FOO`
+fn convert_xml_option(d: &mut OptDict, optstr: &str) -> Result<(), String> {
+    if !optstr.starts_with("xpath.") {
+        d.0.clear();
+        d.insert("xpath.set", Some(optstr.to_string()));
+    }
+    Ok(())
+}
+
+/////////////////////
+// Devices         //
+/////////////////////
+
+static DISK: OptionSpec = OptionSpec {
+    name: "disk",
+    aliases: &[],
+    xpath: Some("./devices/disk"),
+    is_list: true,
+    remove_first: &["path"],
+    stub_none: true,
+    device_common: true,
+    args: &[
+        SubArg::new("type", "./@type"),
+        SubArg::extra("backing_store"),
+        SubArg::extra("backing_format"),
+        SubArg::extra("pool"),
+        SubArg::extra("vol"),
+        SubArg::extra("size"),
+        SubArg::extra("format"),
+        SubArg::extra("sparse"),
+        SubArg::new("bus", "./target/@bus"),
+        SubArg::new("cache", "./driver/@cache"),
+        SubArg::new("source.dir", "./source/@dir"),
+        SubArg::new("source.file", "./source/@file"),
+        SubArg::new("source.dev", "./source/@dev"),
+        SubArg::new("source.pool", "./source/@pool").alias(&["source_pool"]),
+        SubArg::new("source.volume", "./source/@volume").alias(&["source_volume"]),
+        SubArg::new("source.name", "./source/@name").alias(&["source_name"]),
+        SubArg::new("source.protocol", "./source/@protocol").alias(&["source_protocol"]),
+        SubArg::new("source.startupPolicy", "./source/@startupPolicy").alias(&["startup_policy"]),
+        SubArg::new("source.type", "./source/@type"),
+        SubArg::new("source.namespace", "./source/@namespace"),
+        SubArg::new("source.managed", "./source/@managed").yesno(),
+        SubArg::new("source.address.domain", "./source/address/@domain"),
+        SubArg::new("source.address.bus", "./source/address/@bus"),
+        SubArg::new("source.address.slot", "./source/address/@slot"),
+        SubArg::new("source.address.function", "./source/address/@function"),
+        SubArg::new("source.host[0-9]*.name", "./source/host[{}]/@name")
+            .alias(&["source_host_name"]),
+        SubArg::new("source.host[0-9]*.port", "./source/host[{}]/@port")
+            .alias(&["source_host_port"]),
+        SubArg::new("source.host[0-9]*.socket", "./source/host[{}]/@socket")
+            .alias(&["source_host_socket"]),
+        SubArg::new(
+            "source.host[0-9]*.transport",
+            "./source/host[{}]/@transport",
+        )
+        .alias(&["source_host_transport"]),
+        SubArg::new(
+            "source.seclabel[0-9]*.model",
+            "./source/seclabel[{}]/@model",
+        ),
+        SubArg::new(
+            "source.seclabel[0-9]*.relabel",
+            "./source/seclabel[{}]/@relabel",
+        )
+        .yesno(),
+        SubArg::new("source.seclabel[0-9]*.label", "./source/seclabel[{}]/label").comma(),
+        SubArg::cb("path", set_disk_path_cb).lookup(disk_path_lookup),
+        SubArg::new("device", "./@device"),
+        SubArg::new("snapshot", "./@snapshot").alias(&["snapshot_policy"]),
+        SubArg::new("sgio", "./@sgio"),
+        SubArg::new("rawio", "./@rawio"),
+        SubArg::new("serial", "./serial"),
+        SubArg::new("wwn", "./wwn"),
+        SubArg::new("readonly", "./readonly").present(),
+        SubArg::new("shareable", "./shareable").present(),
+        SubArg::new("transient", "./transient").present(),
+        SubArg::new("transient.shareBacking", "./transient/@shareBacking").yesno(),
+        SubArg::new("target.bus", "./target/@bus"),
+        SubArg::new("target.removable", "./target/@removable")
+            .onoff()
+            .alias(&["removable"]),
+        SubArg::new("target.dev", "./target/@dev").alias(&["target"]),
+        SubArg::new("target.rotation_rate", "./target/@rotation_rate").alias(&["rotation_rate"]),
+        SubArg::new("driver.cache", "./driver/@cache"),
+        SubArg::new("driver.discard", "./driver/@discard").alias(&["discard"]),
+        SubArg::new("driver.detect_zeroes", "./driver/@detect_zeroes").alias(&["detect_zeroes"]),
+        SubArg::new("driver.name", "./driver/@name").alias(&["driver_name"]),
+        SubArg::new("driver.type", "./driver/@type").alias(&["driver_type"]),
+        SubArg::new("driver.copy_on_read", "./driver/@copy_on_read").onoff(),
+        SubArg::new("driver.io", "./driver/@io").alias(&["io"]),
+        SubArg::new("driver.iothread", "./driver/@iothread"),
+        SubArg::new("driver.queues", "./driver/@queues"),
+        SubArg::new("driver.error_policy", "./driver/@error_policy").alias(&["error_policy"]),
+        SubArg::new("driver.discard_no_unref", "./driver/@discard_no_unref").onoff(),
+        SubArg::new("driver.queue_size", "./driver/@queue_size"),
+        SubArg::new("driver.ats", "./driver/@ats").onoff(),
+        SubArg::new("driver.iommu", "./driver/@iommu").onoff(),
+        SubArg::new("driver.packed", "./driver/@packed").onoff(),
+        SubArg::new("driver.page_per_vq", "./driver/@page_per_vq").onoff(),
+        SubArg::new("iotune.read_bytes_sec", "./iotune/read_bytes_sec").alias(&["read_bytes_sec"]),
+        SubArg::new("iotune.write_bytes_sec", "./iotune/write_bytes_sec")
+            .alias(&["write_bytes_sec"]),
+        SubArg::new("iotune.total_bytes_sec", "./iotune/total_bytes_sec")
+            .alias(&["total_bytes_sec"]),
+        SubArg::new("iotune.read_iops_sec", "./iotune/read_iops_sec").alias(&["read_iops_sec"]),
+        SubArg::new("iotune.write_iops_sec", "./iotune/write_iops_sec").alias(&["write_iops_sec"]),
+        SubArg::new("iotune.total_iops_sec", "./iotune/total_iops_sec").alias(&["total_iops_sec"]),
+        SubArg::new(
+            "blockio.logical_block_size",
+            "./blockio/@logical_block_size",
+        )
+        .alias(&["logical_block_size"]),
+        SubArg::new(
+            "blockio.physical_block_size",
+            "./blockio/@physical_block_size",
+        )
+        .alias(&["physical_block_size"]),
+        SubArg::new(
+            "blockio.discard_granularity",
+            "./blockio/@discard_granularity",
+        ),
+        SubArg::new("geometry.cyls", "./geometry/@cyls"),
+        SubArg::new("geometry.heads", "./geometry/@heads"),
+        SubArg::new("geometry.secs", "./geometry/@secs"),
+        SubArg::new("geometry.trans", "./geometry/@trans"),
+        SubArg::new(
+            "source.reservations.managed",
+            "./source/reservations/@managed",
+        ),
+        SubArg::new(
+            "source.reservations.source.type",
+            "./source/reservations/source/@type",
+        ),
+        SubArg::new(
+            "source.reservations.source.path",
+            "./source/reservations/source/@path",
+        ),
+        SubArg::new(
+            "source.reservations.source.mode",
+            "./source/reservations/source/@mode",
+        ),
+    ],
+    prepare: Some(convert_disk_options),
+};
+
+/// `perms=ro|sh|rw` predates readonly= and shareable=
+fn convert_disk_options(d: &mut OptDict, _optstr: &str) -> Result<(), String> {
+    if let Some(perms) = d.pop("perms") {
+        match perms.as_deref() {
+            Some("ro") => d.insert("readonly", Some("on".into())),
+            Some("sh") => d.insert("shareable", Some("on".into())),
+            Some("rw") | None => {}
+            Some(v) => return Err(format!("Unknown 'perms' value '{}'", v)),
+        }
+    }
+    if let Some(Some(vol)) = d.get("vol")
+        && vol.matches('/').count() != 1
+    {
+        return Err("Storage volume must be specified as vol=poolname/volname".into());
+    }
+    if let Some(Some(size)) = d.get("size")
+        && size.parse::<f64>().is_err()
+    {
+        return Err(format!(
+            "Improper value for 'size': could not convert string to float: '{}'",
+            size
+        ));
+    }
+    if let Some(Some(sparse)) = d.get("sparse") {
+        on_off_convert("sparse", sparse)?;
+    }
+    Ok(())
+}
+
+/// Source path of a disk, whatever `<source>` attribute holds it
+pub fn disk_source_path(disk: &Element) -> Option<String> {
+    ["./source/@file", "./source/@dev", "./source/@dir"]
+        .iter()
+        .find_map(|x| disk.get(x))
+}
+
+fn disk_path_lookup(disk: &Element, val: Option<&str>) -> bool {
+    disk_source_path(disk).as_deref() == val
+}
+
+fn set_disk_path_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    for rel in ["./source/@file", "./source/@dev", "./source/@dir"] {
+        ctx.set(rel, None);
+    }
+    let Some(path) = ctx.val else {
+        return Ok(());
+    };
+    let disktype = match ctx.get("./@type") {
+        Some(t) if ctx.cliname == "path" && !ctx.editing => t,
+        _ => {
+            let t = local_dev_type(path);
+            ctx.set("./@type", Some(t));
+            t.to_string()
+        }
+    };
+    let attr = match disktype.as_str() {
+        "block" => "./source/@dev",
+        "dir" => "./source/@dir",
+        _ => "./source/@file",
+    };
+    ctx.set(attr, Some(path));
+    Ok(())
+}
+
+static NETWORK: OptionSpec = OptionSpec {
+    name: "network",
+    aliases: &[],
+    xpath: Some("./devices/interface"),
+    is_list: true,
+    remove_first: &["type"],
+    stub_none: true,
+    device_common: true,
+    args: &[
+        SubArg::new("model", "./model/@type"),
+        SubArg::new("mac", "./mac/@address").with_cb(set_mac_cb),
+        SubArg::cb("network", noset_cb),
+        SubArg::cb("bridge", noset_cb),
+        SubArg::new("type", "./@type").with_cb(set_net_type_cb),
+        SubArg::new("backend.type", "./backend/@type"),
+        SubArg::new("backend.logFile", "./backend/@logFile"),
+        SubArg::new("trustGuestRxFilters", "./@trustGuestRxFilters").yesno(),
+        SubArg::cb("source", set_net_source_cb).lookup(net_source_lookup),
+        SubArg::new("source.mode", "./source/@mode").alias(&["source_mode"]),
+        SubArg::new("source.type", "./source/@type").alias(&["source_type"]),
+        SubArg::new("source.path", "./source/@path").alias(&["source_path"]),
+        SubArg::new("source.portgroup", "./source/@portgroup").alias(&["portgroup"]),
+        SubArg::new("source.address.type", "./source/address/@type"),
+        SubArg::new("source.address.domain", "./source/address/@domain"),
+        SubArg::new("source.address.bus", "./source/address/@bus"),
+        SubArg::new("source.address.slot", "./source/address/@slot"),
+        SubArg::new("source.address.function", "./source/address/@function"),
+        SubArg::new("target.dev", "./target/@dev").alias(&["target"]),
+        SubArg::new("model.type", "./model/@type"),
+        SubArg::new("mac.address", "./mac/@address").with_cb(set_mac_cb),
+        SubArg::new("filterref.filter", "./filterref/@filter").alias(&["filterref"]),
+        SubArg::new("link.state", "./link/@state")
+            .with_cb(set_link_state_cb)
+            .alias(&["link_state"]),
+        SubArg::new("driver.name", "./driver/@name").alias(&["driver_name"]),
+        SubArg::new("driver.queues", "./driver/@queues").alias(&["driver_queues"]),
+        SubArg::new("driver.ats", "./driver/@ats").onoff(),
+        SubArg::new("driver.iommu", "./driver/@iommu").onoff(),
+        SubArg::new("driver.packed", "./driver/@packed").onoff(),
+        SubArg::new("driver.page_per_vq", "./driver/@page_per_vq").onoff(),
+        SubArg::new("rom.file", "./rom/@file").alias(&["rom_file"]),
+        SubArg::new("rom.bar", "./rom/@bar")
+            .onoff()
+            .alias(&["rom_bar"]),
+        SubArg::new("mtu.size", "./mtu/@size"),
+        SubArg::new("virtualport.type", "./virtualport/@type").alias(&["virtualport_type"]),
+        SubArg::new(
+            "virtualport.parameters.managerid",
+            "./virtualport/parameters/@managerid",
+        ),
+        SubArg::new(
+            "virtualport.parameters.typeid",
+            "./virtualport/parameters/@typeid",
+        ),
+        SubArg::new(
+            "virtualport.parameters.typeidversion",
+            "./virtualport/parameters/@typeidversion",
+        ),
+        SubArg::new(
+            "virtualport.parameters.instanceid",
+            "./virtualport/parameters/@instanceid",
+        ),
+        SubArg::new(
+            "virtualport.parameters.profileid",
+            "./virtualport/parameters/@profileid",
+        ),
+        SubArg::new(
+            "virtualport.parameters.interfaceid",
+            "./virtualport/parameters/@interfaceid",
+        ),
+        SubArg::new("portForward[0-9]*.proto", "./portForward[{}]/@proto"),
+        SubArg::new("portForward[0-9]*.address", "./portForward[{}]/@address"),
+        SubArg::new("portForward[0-9]*.dev", "./portForward[{}]/@dev"),
+        SubArg::new(
+            "portForward[0-9]*.range[0-9]*.start",
+            "./portForward[{}]/range[{}]/@start",
+        ),
+        SubArg::new(
+            "portForward[0-9]*.range[0-9]*.end",
+            "./portForward[{}]/range[{}]/@end",
+        ),
+        SubArg::new(
+            "portForward[0-9]*.range[0-9]*.to",
+            "./portForward[{}]/range[{}]/@to",
+        ),
+        SubArg::new(
+            "portForward[0-9]*.range[0-9]*.exclude",
+            "./portForward[{}]/range[{}]/@exclude",
+        )
+        .yesno(),
+    ],
+    prepare: Some(convert_network_options),
+};
+
+/// Back compat with the old `network=NAME` and `bridge=NAME` syntax
+fn convert_network_options(d: &mut OptDict, _optstr: &str) -> Result<(), String> {
+    if !d.contains("type") {
+        if let Some(net) = d.pop("network") {
+            d.insert("type", Some("network".into()));
+            d.insert("source", net);
+        } else if let Some(br) = d.pop("bridge") {
+            d.insert("type", Some("bridge".into()));
+            d.insert("source", br);
+        }
+    } else {
+        d.pop("network");
+        d.pop("bridge");
+    }
+    Ok(())
+}
+
+fn set_mac_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    match ctx.val {
+        Some("RANDOM") => Ok(()),
+        val => {
+            let val = val.map(|v| v.to_string());
+            ctx.set("./mac/@address", val.as_deref());
+            Ok(())
+        }
+    }
+}
+
+fn set_net_type_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    match ctx.val {
+        Some("default") => {
+            ctx.set("./@type", Some("network"));
+            ctx.set("./source/@network", Some("default"));
+        }
+        Some("passt") => {
+            ctx.set("./@type", Some("user"));
+            ctx.set("./backend/@type", Some("passt"));
+        }
+        val => {
+            let val = val.map(|v| v.to_string());
+            ctx.set("./@type", val.as_deref());
+        }
+    }
+    Ok(())
+}
+
+/// `<source>` attribute holding the interface source for each type
+fn net_source_xpath(nettype: Option<&str>) -> Option<&'static str> {
+    match nettype {
+        Some("network") => Some("./source/@network"),
+        Some("bridge") => Some("./source/@bridge"),
+        Some("direct") | Some("ethernet") => Some("./source/@dev"),
+        Some("vdpa") => Some("./source/@dev"),
+        Some("vhostuser") => Some("./source/@path"),
+        _ => None,
+    }
+}
+
+fn net_source_lookup(iface: &Element, val: Option<&str>) -> bool {
+    let nettype = iface.attr("type");
+    let source = net_source_xpath(nettype).and_then(|x| iface.get(x));
+    source.as_deref() == val
+}
+
+fn set_net_source_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let nettype = ctx.get("./@type");
+    match net_source_xpath(nettype.as_deref()) {
+        Some(xpath) => {
+            let val = ctx.val.map(|v| v.to_string());
+            ctx.set(xpath, val.as_deref());
+            Ok(())
+        }
+        None => Err(format!(
+            "Don't know how to set source for network type '{}'",
+            nettype.unwrap_or_default()
+        )),
+    }
+}
+
+fn set_link_state_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let val = match ctx.val {
+        Some(v) if v == "up" || v == "down" => Some(v.to_string()),
+        Some(v) => match raw_on_off_convert(v) {
+            Some(true) => Some("up".to_string()),
+            Some(false) => Some("down".to_string()),
+            None => Some(v.to_string()),
+        },
+        None => None,
+    };
+    ctx.set("./link/@state", val.as_deref());
+    Ok(())
+}
+
+static GRAPHICS: OptionSpec = OptionSpec {
+    name: "graphics",
+    aliases: &[],
+    xpath: Some("./devices/graphics"),
+    is_list: true,
+    remove_first: &["type"],
+    stub_none: true,
+    device_common: true,
+    args: &[
+        SubArg::new("type", "./@type").with_cb(set_graphics_type_cb),
+        SubArg::new("port", "./@port"),
+        SubArg::new("tlsPort", "./@tlsPort").alias(&["tlsport"]),
+        SubArg::new("websocket", "./@websocket"),
+        SubArg::new("listen", "./@listen").with_cb(set_listen_cb),
+        SubArg::new("keymap", "./@keymap").with_cb(set_keymap_cb),
+        SubArg::new("password", "./@passwd").alias(&["passwd"]),
+        SubArg::new("passwordValidTo", "./@passwdValidTo").alias(&["passwdValidTo"]),
+        SubArg::new("connected", "./@connected"),
+        SubArg::new("defaultMode", "./@defaultMode"),
+        SubArg::new("listens[0-9]*.type", "./listen[{}]/@type"),
+        SubArg::new("listens[0-9]*.address", "./listen[{}]/@address"),
+        SubArg::new("listens[0-9]*.network", "./listen[{}]/@network"),
+        SubArg::new("listens[0-9]*.socket", "./listen[{}]/@socket"),
+        SubArg::new("image.compression", "./image/@compression").alias(&["image_compression"]),
+        SubArg::new("streaming.mode", "./streaming/@mode").alias(&["streaming_mode"]),
+        SubArg::new("clipboard.copypaste", "./clipboard/@copypaste")
+            .yesno()
+            .alias(&["clipboard_copypaste"]),
+        SubArg::new("mouse.mode", "./mouse/@mode").alias(&["mouse_mode"]),
+        SubArg::new("filetransfer.enable", "./filetransfer/@enable")
+            .yesno()
+            .alias(&["filetransfer_enable"]),
+        SubArg::new("zlib.compression", "./zlib/@compression"),
+        SubArg::new("gl.enable", "./gl/@enable")
+            .yesno()
+            .alias(&["gl"]),
+        SubArg::new("gl.rendernode", "./gl/@rendernode").alias(&["rendernode"]),
+    ],
+    prepare: None,
+};
+
+fn set_graphics_type_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    match ctx.val {
+        Some("default") if !ctx.editing => Ok(()),
+        val => {
+            let val = val.map(|v| v.to_string());
+            ctx.set("./@type", val.as_deref());
+            Ok(())
+        }
+    }
+}
+
+fn set_listen_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let listen = ctx.xpath("./listen");
+    remove_all(ctx.root, &listen);
+    match ctx.val {
+        Some("none") => {
+            for rel in [
+                "./@listen",
+                "./@port",
+                "./@tlsPort",
+                "./@autoport",
+                "./@socket",
+            ] {
+                ctx.set(rel, None);
+            }
+            ctx.set("./listen/@type", Some("none"));
+        }
+        Some("socket") => ctx.set("./listen/@type", Some("socket")),
+        val => {
+            let val = val.map(|v| v.to_string());
+            ctx.set("./@listen", val.as_deref());
+        }
+    }
+    Ok(())
+}
+
+fn set_keymap_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let val = match ctx.val {
+        Some(v) if v.eq_ignore_ascii_case("local") => {
+            log::debug!("keymap=local is no longer implemented. Using None.");
+            None
+        }
+        Some(v) if v.eq_ignore_ascii_case("none") => None,
+        v => v.map(|v| v.to_string()),
+    };
+    ctx.set("./@keymap", val.as_deref());
+    Ok(())
+}
+
+static CONTROLLER: OptionSpec = OptionSpec {
+    name: "controller",
+    aliases: &[],
+    xpath: Some("./devices/controller"),
+    is_list: true,
+    remove_first: &["type"],
+    stub_none: false,
+    device_common: true,
+    args: &[
+        SubArg::new("type", "./@type"),
+        SubArg::new("model", "./@model"),
+        SubArg::new("index", "./@index"),
+        SubArg::new("master.startport", "./master/@startport").alias(&["master"]),
+        SubArg::new("ports", "./@ports"),
+        SubArg::new("vectors", "./@vectors"),
+        SubArg::new("maxGrantFrames", "./@maxGrantFrames"),
+        SubArg::new("driver.queues", "./driver/@queues").alias(&["driver_queues"]),
+        SubArg::new("driver.iothread", "./driver/@iothread"),
+        SubArg::new("target.chassisNr", "./target/@chassisNr"),
+        SubArg::new("target.chassis", "./target/@chassis"),
+        SubArg::new("target.port", "./target/@port"),
+        SubArg::new("target.hotplug", "./target/@hotplug").onoff(),
+        SubArg::new("target.busNr", "./target/@busNr"),
+        SubArg::new("target.index", "./target/@index"),
+        SubArg::new("target.node", "./target/@node"),
+        SubArg::cb("address", set_controller_address_cb),
+    ],
+    prepare: None,
+};
+
+/// Convenience `address=DDDD:BB:SS.F` PCI address parsing
+fn set_controller_address_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let addrstr = require_val(ctx)?;
+    let colons = addrstr.matches(':').count();
+    if !((colons == 1 || colons == 2) && addrstr.contains('.')) {
+        return Err(format!("Expected PCI format string for '{}'", addrstr));
+    }
+    let (rest, function) = addrstr.split_once('.').unwrap_or_default();
+    let (rest, slot) = rest.rsplit_once(':').unwrap_or_default();
+    ctx.set("./address/@type", Some("pci"));
+    let (domain, bus) = match rest.split_once(':') {
+        Some((d, b)) => (d, Some(b)),
+        None => ("0", None),
+    };
+    ctx.set("./address/@domain", Some(domain));
+    if let Some(bus) = bus {
+        ctx.set("./address/@bus", Some(bus));
+    }
+    ctx.set("./address/@slot", Some(slot));
+    ctx.set("./address/@function", Some(function));
+    Ok(())
+}
+
+static INPUT: OptionSpec = OptionSpec {
+    name: "input",
+    aliases: &[],
+    xpath: Some("./devices/input"),
+    is_list: true,
+    remove_first: &["type"],
+    stub_none: false,
+    device_common: true,
+    args: &[
+        SubArg::new("type", "./@type"),
+        SubArg::new("bus", "./@bus"),
+        SubArg::new("model", "./@model"),
+        SubArg::new("source.evdev", "./source/@evdev"),
+        SubArg::new("source.dev", "./source/@dev"),
+        SubArg::new("source.repeat", "./source/@repeat").onoff(),
+        SubArg::new("source.grab", "./source/@grab"),
+        SubArg::new("source.grabToggle", "./source/@grabToggle"),
+    ],
+    prepare: None,
+};
+
+static SMARTCARD: OptionSpec = OptionSpec {
+    name: "smartcard",
+    aliases: &[],
+    xpath: Some("./devices/smartcard"),
+    is_list: true,
+    remove_first: &["mode"],
+    stub_none: true,
+    device_common: true,
+    args: &[
+        SubArg::new("mode", "./@mode"),
+        SubArg::new("type", "./@type"),
+        SubArg::new("database", "./database"),
+    ],
+    prepare: None,
+};
+
+static REDIRDEV: OptionSpec = OptionSpec {
+    name: "redirdev",
+    aliases: &[],
+    xpath: Some("./devices/redirdev"),
+    is_list: true,
+    remove_first: &["bus"],
+    stub_none: true,
+    device_common: true,
+    args: &[
+        SubArg::new("bus", "./@bus"),
+        SubArg::new("type", "./@type"),
+        SubArg::cb("server", set_redir_server_cb),
+        SubArg::new("source.host", "./source/@host"),
+        SubArg::new("source.service", "./source/@service"),
+    ],
+    prepare: None,
+};
+
+fn set_redir_server_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let (host, service) = split_host_service(require_val(ctx)?);
+    ctx.set("./source/@host", Some(&host));
+    ctx.set("./source/@service", service.as_deref());
+    Ok(())
+}
+
+/// Split `host:port` into parts, allowing `[v6addr]:port`
+pub fn split_host_service(val: &str) -> (String, Option<String>) {
+    if let Some(rest) = val.strip_prefix('[')
+        && let Some((host, tail)) = rest.split_once(']')
+    {
+        let service = tail.strip_prefix(':').map(|s| s.to_string());
+        return (host.to_string(), service);
+    }
+    match val.rsplit_once(':') {
+        Some((h, s)) if !h.contains(':') => (h.to_string(), Some(s.to_string())),
+        _ => (val.to_string(), None),
+    }
+}
+
+static TPM: OptionSpec = OptionSpec {
+    name: "tpm",
+    aliases: &[],
+    xpath: Some("./devices/tpm"),
+    is_list: true,
+    remove_first: &["type"],
+    stub_none: true,
+    device_common: true,
+    args: &[
+        SubArg::new("type", "./backend/@type").alias(&["backend.type"]),
+        SubArg::new("model", "./@model"),
+        SubArg::new("backend.version", "./backend/@version").alias(&["version"]),
+        SubArg::new("backend.device.path", "./backend/device/@path")
+            .with_cb(set_tpm_path_cb)
+            .alias(&["path"]),
+        SubArg::new("backend.encryption.secret", "./backend/encryption/@secret"),
+        SubArg::new("backend.persistent_state", "./backend/@persistent_state").yesno(),
+        SubArg::new(
+            "backend.active_pcr_banks.sha1",
+            "./backend/active_pcr_banks/sha1",
+        )
+        .present(),
+        SubArg::new(
+            "backend.active_pcr_banks.sha256",
+            "./backend/active_pcr_banks/sha256",
+        )
+        .present(),
+        SubArg::new(
+            "backend.active_pcr_banks.sha384",
+            "./backend/active_pcr_banks/sha384",
+        )
+        .present(),
+        SubArg::new(
+            "backend.active_pcr_banks.sha512",
+            "./backend/active_pcr_banks/sha512",
+        )
+        .present(),
+        SubArg::new("backend.source.type", "./backend/source/@type"),
+        SubArg::new("backend.source.path", "./backend/source/@path"),
+    ],
+    prepare: Some(convert_tpm_options),
+};
+
+/// `--tpm /dev/tpm0` is shorthand for a passthrough device path
+fn convert_tpm_options(d: &mut OptDict, _optstr: &str) -> Result<(), String> {
+    if let Some(Some(t)) = d.get("type")
+        && t.starts_with('/')
+    {
+        d.rename("type", "path");
+    }
+    Ok(())
+}
+
+fn set_tpm_path_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    if ctx.val.is_some() && ctx.get("./backend/@type").is_none() {
+        ctx.set("./backend/@type", Some("passthrough"));
+    }
+    let val = ctx.val.map(|v| v.to_string());
+    ctx.set("./backend/device/@path", val.as_deref());
+    Ok(())
+}
+
+static RNG: OptionSpec = OptionSpec {
+    name: "rng",
+    aliases: &[],
+    xpath: Some("./devices/rng"),
+    is_list: true,
+    remove_first: &["backend.model"],
+    stub_none: true,
+    device_common: true,
+    args: &[
+        SubArg::cb("type", noset_cb),
+        SubArg::cb("device", noset_cb),
+        SubArg::new("model", "./@model"),
+        SubArg::new("backend", "./backend"),
+        SubArg::new("backend.model", "./backend/@model"),
+        SubArg::new("backend.type", "./backend/@type"),
+        SubArg::new("backend.source.mode", "./backend/source/@mode"),
+        SubArg::new("backend.source.host", "./backend/source/@host"),
+        SubArg::new("backend.source.service", "./backend/source/@service"),
+        SubArg::new("backend.protocol.type", "./backend/protocol/@type"),
+        SubArg::new("rate.bytes", "./rate/@bytes"),
+        SubArg::new("rate.period", "./rate/@period"),
+        SubArg::new("driver.ats", "./driver/@ats").onoff(),
+        SubArg::new("driver.iommu", "./driver/@iommu").onoff(),
+        SubArg::new("driver.packed", "./driver/@packed").onoff(),
+        SubArg::new("driver.page_per_vq", "./driver/@page_per_vq").onoff(),
+    ],
+    prepare: Some(convert_rng_options),
+};
+
+/// `--rng /dev/urandom` means the random backend with that device
+fn convert_rng_options(d: &mut OptDict, _optstr: &str) -> Result<(), String> {
+    d.rename("type", "backend.model");
+    d.rename("device", "backend");
+    if let Some(Some(m)) = d.get("backend.model")
+        && m.starts_with('/')
+    {
+        d.rename("backend.model", "backend");
+        d.insert("backend.model", Some("random".into()));
+    }
+    Ok(())
+}
+
+static WATCHDOG: OptionSpec = OptionSpec {
+    name: "watchdog",
+    aliases: &[],
+    xpath: Some("./devices/watchdog"),
+    is_list: true,
+    remove_first: &["model"],
+    stub_none: false,
+    device_common: true,
+    args: &[
+        SubArg::new("model", "./@model"),
+        SubArg::new("action", "./@action"),
+    ],
+    prepare: None,
+};
+
+static MEMDEV: OptionSpec = OptionSpec {
+    name: "memdev",
+    aliases: &[],
+    xpath: Some("./devices/memory"),
+    is_list: true,
+    remove_first: &["model"],
+    stub_none: false,
+    device_common: true,
+    args: &[
+        SubArg::new("model", "./@model"),
+        SubArg::new("access", "./@access"),
+        SubArg::new("discard", "./@discard").yesno(),
+        SubArg::new("uuid", "./uuid"),
+        SubArg::new("source.pagesize", "./source/pagesize"),
+        SubArg::new("source.path", "./source/path"),
+        SubArg::new("source.alignsize", "./source/alignsize")
+            .with_cb(|c| set_mib_cb(c, "./source/alignsize")),
+        SubArg::new("source.pmem", "./source/pmem").present(),
+        SubArg::new("source.nodemask", "./source/nodemask").comma(),
+        SubArg::new("target.size", "./target/size")
+            .with_cb(|c| set_mib_cb(c, "./target/size"))
+            .alias(&["size"]),
+        SubArg::new("target.node", "./target/node").alias(&["node"]),
+        SubArg::new("target.label_size", "./target/label/size")
+            .with_cb(|c| set_mib_cb(c, "./target/label/size"))
+            .alias(&["label_size"]),
+        SubArg::new("target.block", "./target/block"),
+        SubArg::new("target.requested", "./target/requested")
+            .with_cb(|c| set_mib_cb(c, "./target/requested")),
+        SubArg::new("target.readonly", "./target/readonly").present(),
+        SubArg::new("target.address.base", "./target/address/@base"),
+    ],
+    prepare: None,
+};
+
+static MEMBALLOON: OptionSpec = OptionSpec {
+    name: "memballoon",
+    aliases: &[],
+    xpath: Some("./devices/memballoon"),
+    is_list: true,
+    remove_first: &["model"],
+    stub_none: false,
+    device_common: true,
+    args: &[
+        SubArg::new("model", "./@model"),
+        SubArg::new("autodeflate", "./@autodeflate").onoff(),
+        SubArg::new("stats.period", "./stats/@period"),
+        SubArg::new("freePageReporting", "./@freePageReporting").onoff(),
+        SubArg::new("driver.ats", "./driver/@ats").onoff(),
+        SubArg::new("driver.iommu", "./driver/@iommu").onoff(),
+        SubArg::new("driver.packed", "./driver/@packed").onoff(),
+        SubArg::new("driver.page_per_vq", "./driver/@page_per_vq").onoff(),
+    ],
+    prepare: None,
+};
+
+static PANIC: OptionSpec = OptionSpec {
+    name: "panic",
+    aliases: &[],
+    xpath: Some("./devices/panic"),
+    is_list: true,
+    remove_first: &["model"],
+    stub_none: false,
+    device_common: true,
+    args: &[
+        SubArg::new("model", "./@model").with_cb(set_panic_model_cb),
+        SubArg::new("iobase", "./address/@iobase"),
+    ],
+    prepare: None,
+};
+
+/// `--panic default` leaves the model for libvirt to pick
+fn set_panic_model_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let val = ctx.val.filter(|v| *v != "default").map(|v| v.to_string());
+    ctx.set("./@model", val.as_deref());
+    Ok(())
+}
+
+static VSOCK: OptionSpec = OptionSpec {
+    name: "vsock",
+    aliases: &[],
+    xpath: Some("./devices/vsock"),
+    is_list: true,
+    remove_first: &["model"],
+    stub_none: false,
+    device_common: true,
+    args: &[
+        SubArg::new("model", "./@model"),
+        SubArg::new("cid.auto", "./cid/@auto").yesno(),
+        SubArg::new("cid.address", "./cid/@address"),
+        SubArg::new("driver.ats", "./driver/@ats").onoff(),
+        SubArg::new("driver.iommu", "./driver/@iommu").onoff(),
+        SubArg::new("driver.packed", "./driver/@packed").onoff(),
+        SubArg::new("driver.page_per_vq", "./driver/@page_per_vq").onoff(),
+    ],
+    prepare: None,
+};
+
+/// `--cloud-init` doesn't touch the XML; everything is handed back as
+/// extras for `parse_cloud_init`
+pub static CLOUD_INIT: OptionSpec = OptionSpec {
+    name: "cloud-init",
+    aliases: &[],
+    xpath: None,
+    is_list: false,
+    remove_first: &[],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::extra("root-password-generate"),
+        SubArg::extra("root-password-file"),
+        SubArg::extra("disable"),
+        SubArg::extra("root-ssh-key").alias(&["ssh-key"]),
+        SubArg::extra("clouduser-ssh-key"),
+        SubArg::extra("user-data"),
+        SubArg::extra("meta-data"),
+        SubArg::extra("network-config"),
+    ],
+    prepare: None,
+};
+
+/// Parse a `--cloud-init` value. None is the bare option, which means
+/// `root-password-generate=yes,disable=yes`.
+pub fn parse_cloud_init(optstr: Option<&str>) -> Result<CloudInitData, String> {
+    let Some(optstr) = optstr else {
+        return Ok(CloudInitData {
+            root_password_generate: Some(true),
+            disable: Some(true),
+            ..Default::default()
+        });
+    };
+    let parser = super::VirtCliParser::new(&CLOUD_INIT, optstr)?;
+    let mut scratch = Element::new("domain");
+    let res = parser.apply(&mut scratch, ".", false)?;
+    let onoff = |key: &str| {
+        res.extra(key)
+            .map(|v| on_off_convert(key, v).map_err(|e| CLOUD_INIT.error(optstr, &e)))
+            .transpose()
+    };
+    let string = |key: &str| res.extra(key).map(str::to_string);
+    Ok(CloudInitData {
+        root_password_generate: onoff("root-password-generate")?,
+        disable: onoff("disable")?,
+        root_password_file: string("root-password-file"),
+        root_ssh_key: string("root-ssh-key").or_else(|| string("ssh-key")),
+        clouduser_ssh_key: string("clouduser-ssh-key"),
+        user_data: string("user-data"),
+        meta_data: string("meta-data"),
+        network_config: string("network-config"),
+        ..Default::default()
+    })
+}
+
+/// `--unattended` doesn't touch the XML either; see `parse_unattended`
+pub static UNATTENDED: OptionSpec = OptionSpec {
+    name: "unattended",
+    aliases: &[],
+    xpath: None,
+    is_list: false,
+    remove_first: &["profile"],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::extra("profile"),
+        SubArg::extra("admin-password-file"),
+        SubArg::extra("user-login"),
+        SubArg::extra("user-password-file"),
+        SubArg::extra("product-key"),
+        SubArg::extra("reg-login"),
+    ],
+    prepare: None,
+};
+
+/// Parse an `--unattended` value. None is the bare option, which leaves
+/// every choice to the OS defaults.
+pub fn parse_unattended(optstr: Option<&str>) -> Result<UnattendedData, String> {
+    let Some(optstr) = optstr else {
+        return Ok(UnattendedData::default());
+    };
+    let parser = super::VirtCliParser::new(&UNATTENDED, optstr)?;
+    let mut scratch = Element::new("domain");
+    let res = parser.apply(&mut scratch, ".", false)?;
+    let string = |key: &str| res.extra(key).map(str::to_string);
+    Ok(UnattendedData {
+        profile: string("profile"),
+        admin_password_file: string("admin-password-file"),
+        user_login: string("user-login"),
+        user_password_file: string("user-password-file"),
+        product_key: string("product-key"),
+        reg_login: string("reg-login"),
+    })
+}
+
+pub static LOCATION: OptionSpec = OptionSpec {
+    name: "location",
+    aliases: &[],
+    xpath: None,
+    is_list: false,
+    remove_first: &["location"],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::extra("location").comma(),
+        SubArg::extra("kernel").comma(),
+        SubArg::extra("initrd").comma(),
+    ],
+    prepare: None,
+};
+
+/// A parsed `--location`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocationData {
+    pub location: Option<String>,
+    pub kernel: Option<String>,
+    pub initrd: Option<String>,
+}
+
+pub fn parse_location(optstr: &str) -> Result<LocationData, String> {
+    let parser = super::VirtCliParser::new(&LOCATION, optstr)?;
+    let mut scratch = Element::new("domain");
+    let res = parser.apply(&mut scratch, ".", false)?;
+    let string = |key: &str| res.extra(key).map(str::to_string);
+    Ok(LocationData {
+        location: string("location"),
+        kernel: string("kernel"),
+        initrd: string("initrd"),
+    })
+}
+
+static OSINFO: OptionSpec = OptionSpec {
+    name: "osinfo",
+    aliases: &[],
+    xpath: None,
+    is_list: false,
+    remove_first: &[],
+    stub_none: false,
+    device_common: false,
+    args: &[
+        SubArg::extra("name").alias(&["short-id"]),
+        SubArg::extra("id"),
+        SubArg::extra("detect"),
+        SubArg::extra("require"),
+    ],
+    prepare: None,
+};
+
+/// A parsed `--osinfo`, with the OS name checked against the OS database
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OsInfoData {
+    /// Short id of the requested OS, also when it was given by full id
+    pub name: Option<String>,
+    /// Prefer the OS detected from the install media
+    pub detect: bool,
+    /// `require=on`: an OS name must be set or detected, even for
+    /// non-HVM guests
+    pub require: bool,
+}
+
+/// Parse `--osinfo`. No value is the same as `auto`: detect from the
+/// media, and require an OS name for HVM guests.
+pub fn parse_osinfo(optstr: Option<&str>) -> Result<OsInfoData, String> {
+    let mut data = OsInfoData::default();
+    let mut id = None;
+    let mut require_from_cli = None;
+    match optstr {
+        None | Some("auto") => data.detect = true,
+        Some("none") => data.name = Some(GENERIC.to_string()),
+        Some(v) if !v.contains('=') && v.contains("://") => id = Some(v.to_string()),
+        Some(v) if !v.contains('=') => data.name = Some(v.to_string()),
+        Some(optstr) => {
+            let parser = super::VirtCliParser::new(&OSINFO, optstr)?;
+            let mut scratch = Element::new("domain");
+            let res = parser.apply(&mut scratch, ".", false)?;
+            let onoff = |key: &str| {
+                res.extra(key)
+                    .map(|v| on_off_convert(key, v).map_err(|e| OSINFO.error(optstr, &e)))
+                    .transpose()
+            };
+            data.name = res.extra("name").map(str::to_string);
+            id = res.extra("id").map(str::to_string);
+            data.detect = onoff("detect")?.unwrap_or(false);
+            require_from_cli = onoff("require")?;
+        }
+    }
+
+    let os = match (&id, &data.name) {
+        (Some(id), _) => Some(osdb().require_os_by_full_id(id)?),
+        (None, Some(name)) => Some(osdb().require_os(name)?),
+        _ => None,
+    };
+    if let Some(os) = os {
+        data.name = Some(os.name.clone());
+    }
+    match require_from_cli {
+        Some(false) if data.name.is_none() => {
+            log::debug!("Converting `require=off` to fallback `name=generic`");
+            data.name = Some(GENERIC.to_string());
+        }
+        Some(require) => data.require = require,
+        None => {}
+    }
+    Ok(data)
+}
+
+/// Arguments shared by all the character device options
+macro_rules! char_args {
+    ($($extra:expr),* $(,)?) => {
+        &[
+            SubArg::new("type", "./@type"),
+            SubArg::new("source.path", "./source/@path").alias(&["path"]),
+            SubArg::new("source.host", "./source/@host")
+                .with_cb(set_char_host_cb)
+                .alias(&["host"]),
+            SubArg::new("source.service", "./source/@service"),
+            SubArg::new("source.bind_host", "./source[@mode='bind']/@host")
+                .with_cb(set_char_bind_cb)
+                .alias(&["bind_host"]),
+            SubArg::new("source.bind_service", "./source[@mode='bind']/@service"),
+            SubArg::new("source.connect_host", "./source[@mode='connect']/@host")
+                .with_cb(set_char_connect_cb),
+            SubArg::new("source.connect_service", "./source[@mode='connect']/@service"),
+            SubArg::new("source.mode", "./source/@mode").alias(&["mode"]),
+            SubArg::new("source.master", "./source/@master"),
+            SubArg::new("source.slave", "./source/@slave"),
+            SubArg::new("source.channel", "./source/@channel"),
+            SubArg::new("source.tls", "./source/@tls").yesno(),
+            SubArg::new("protocol.type", "./protocol/@type").alias(&["protocol"]),
+            SubArg::new("log.file", "./log/@file"),
+            SubArg::new("log.append", "./log/@append").onoff(),
+            SubArg::new("target.address", "./target/@address")
+                .with_cb(set_char_target_address_cb)
+                .alias(&["target_address"]),
+            SubArg::new("target.port", "./target/@port"),
+            SubArg::new("target.type", "./target/@type").alias(&["target_type"]),
+            SubArg::new("target.name", "./target/@name").alias(&["name"]),
+            SubArg::new("target.state", "./target/@state"),
+            SubArg::new("target.model.name", "./target/model/@name"),
+            $($extra,)*
+        ]
+    };
+}
+
+fn set_char_host_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let (host, service) = match ctx.val {
+        Some(v) => split_host_service(v),
+        None => (String::new(), None),
+    };
+    ctx.set(
+        "./source/@host",
+        Some(&host).filter(|h| !h.is_empty()).map(|h| h.as_str()),
+    );
+    if service.is_some() {
+        ctx.set("./source/@service", service.as_deref());
+    }
+    Ok(())
+}
+
+fn set_char_bind_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let (host, service) = split_host_service(require_val(ctx)?);
+    ctx.set("./source[@mode='bind']/@host", Some(&host));
+    if service.is_some() {
+        ctx.set("./source[@mode='bind']/@service", service.as_deref());
+    }
+    Ok(())
+}
+
+fn set_char_connect_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let (host, service) = split_host_service(require_val(ctx)?);
+    ctx.set("./source[@mode='connect']/@host", Some(&host));
+    if service.is_some() {
+        ctx.set("./source[@mode='connect']/@service", service.as_deref());
+    }
+    Ok(())
+}
+
+/// guestfwd channels take `target.address=HOST:PORT`
+fn set_char_target_address_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let Some(val) = ctx.val else {
+        ctx.set("./target/@address", None);
+        ctx.set("./target/@port", None);
+        return Ok(());
+    };
+    let (addr, port) = split_host_service(val);
+    ctx.set("./target/@address", Some(&addr));
+    if port.is_some() {
+        ctx.set("./target/@port", port.as_deref());
+    }
+    Ok(())
+}
+
+static SERIAL: OptionSpec = OptionSpec {
+    name: "serial",
+    aliases: &[],
+    xpath: Some("./devices/serial"),
+    is_list: true,
+    remove_first: &["type"],
+    stub_none: true,
+    device_common: true,
+    args: char_args!(),
+    prepare: None,
+};
+
+static PARALLEL: OptionSpec = OptionSpec {
+    name: "parallel",
+    aliases: &[],
+    xpath: Some("./devices/parallel"),
+    is_list: true,
+    remove_first: &["type"],
+    stub_none: true,
+    device_common: true,
+    args: char_args!(),
+    prepare: None,
+};
+
+static CHANNEL: OptionSpec = OptionSpec {
+    name: "channel",
+    aliases: &[],
+    xpath: Some("./devices/channel"),
+    is_list: true,
+    remove_first: &["type"],
+    stub_none: false,
+    device_common: true,
+    args: char_args!(),
+    prepare: None,
+};
+
+static CONSOLE: OptionSpec = OptionSpec {
+    name: "console",
+    aliases: &[],
+    xpath: Some("./devices/console"),
+    is_list: true,
+    remove_first: &["type"],
+    stub_none: true,
+    device_common: true,
+    args: char_args!(),
+    prepare: None,
+};
+
+static FILESYSTEM: OptionSpec = OptionSpec {
+    name: "filesystem",
+    aliases: &[],
+    xpath: Some("./devices/filesystem"),
+    is_list: true,
+    remove_first: &["source", "target"],
+    stub_none: false,
+    device_common: true,
+    args: &[
+        SubArg::new("type", "./@type"),
+        SubArg::new("accessmode", "./@accessmode").alias(&["mode"]),
+        SubArg::new("model", "./@model"),
+        SubArg::new("multidevs", "./@multidevs"),
+        SubArg::new("readonly", "./readonly").present(),
+        SubArg::new("space_hard_limit", "./space_hard_limit"),
+        SubArg::new("space_soft_limit", "./space_soft_limit"),
+        SubArg::cb("source", set_fs_source_cb).lookup(fs_source_lookup),
+        SubArg::new("target.dir", "./target/@dir").alias(&["target"]),
+        SubArg::new("source.dir", "./source/@dir"),
+        SubArg::new("source.file", "./source/@file"),
+        SubArg::new("source.dev", "./source/@dev"),
+        SubArg::new("source.name", "./source/@name"),
+        SubArg::new("source.socket", "./source/@socket"),
+        SubArg::new("source.usage", "./source/@usage"),
+        SubArg::new("source.units", "./source/@units"),
+        SubArg::new("source.pool", "./source/@pool"),
+        SubArg::new("source.volume", "./source/@volume"),
+        SubArg::new("binary.path", "./binary/@path"),
+        SubArg::new("binary.xattr", "./binary/@xattr").onoff(),
+        SubArg::new("binary.cache.mode", "./binary/cache/@mode"),
+        SubArg::new("binary.lock.posix", "./binary/lock/@posix").onoff(),
+        SubArg::new("binary.lock.flock", "./binary/lock/@flock").onoff(),
+        SubArg::new("binary.sandbox.mode", "./binary/sandbox/@mode"),
+        SubArg::new("driver.type", "./driver/@type"),
+        SubArg::new("driver.format", "./driver/@format"),
+        SubArg::new("driver.wrpolicy", "./driver/@wrpolicy"),
+        SubArg::new("driver.queue", "./driver/@queue"),
+        SubArg::new("driver.ats", "./driver/@ats").onoff(),
+        SubArg::new("driver.iommu", "./driver/@iommu").onoff(),
+        SubArg::new("driver.packed", "./driver/@packed").onoff(),
+        SubArg::new("driver.page_per_vq", "./driver/@page_per_vq").onoff(),
+    ],
+    prepare: None,
+};
+
+/// `<source>` attribute naming the filesystem source for each type
+fn fs_source_xpath(fstype: Option<&str>) -> &'static str {
+    match fstype {
+        Some("template") => "./source/@name",
+        Some("file") => "./source/@file",
+        Some("block") => "./source/@dev",
+        Some("ram") => "./source/@usage",
+        Some("volume") => "./source/@volume",
+        _ => "./source/@dir",
+    }
+}
+
+fn fs_source_lookup(fs: &Element, val: Option<&str>) -> bool {
+    fs.get(fs_source_xpath(fs.attr("type"))).as_deref() == val
+}
+
+fn set_fs_source_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let fstype = ctx.get("./@type");
+    let val = ctx.val.map(|v| v.to_string());
+    ctx.set(fs_source_xpath(fstype.as_deref()), val.as_deref());
+    Ok(())
+}
+
+static VIDEO: OptionSpec = OptionSpec {
+    name: "video",
+    aliases: &[],
+    xpath: Some("./devices/video"),
+    is_list: true,
+    remove_first: &["model.type"],
+    stub_none: true,
+    device_common: true,
+    args: &[
+        SubArg::new("model.type", "./model/@type").alias(&["model"]),
+        SubArg::new("model.heads", "./model/@heads").alias(&["heads"]),
+        SubArg::new("model.ram", "./model/@ram").alias(&["ram"]),
+        SubArg::new("model.vram", "./model/@vram").alias(&["vram"]),
+        SubArg::new("model.vram64", "./model/@vram64").alias(&["vram64"]),
+        SubArg::new("model.vgamem", "./model/@vgamem").alias(&["vgamem"]),
+        SubArg::new("model.primary", "./model/@primary").yesno(),
+        SubArg::new(
+            "model.acceleration.accel3d",
+            "./model/acceleration/@accel3d",
+        )
+        .yesno()
+        .alias(&["accel3d"]),
+        SubArg::new("model.blob", "./model/@blob").onoff(),
+        SubArg::new("driver.ats", "./driver/@ats").onoff(),
+        SubArg::new("driver.iommu", "./driver/@iommu").onoff(),
+        SubArg::new("driver.packed", "./driver/@packed").onoff(),
+        SubArg::new("driver.page_per_vq", "./driver/@page_per_vq").onoff(),
+    ],
+    prepare: None,
+};
+
+static SOUND: OptionSpec = OptionSpec {
+    name: "sound",
+    aliases: &[],
+    xpath: Some("./devices/sound"),
+    is_list: true,
+    remove_first: &["model"],
+    stub_none: true,
+    device_common: true,
+    args: &[
+        SubArg::new("model", "./@model").with_cb(set_sound_model_cb),
+        SubArg::new("audio.id", "./audio/@id"),
+        SubArg::new("codec[0-9]*.type", "./codec[{}]/@type"),
+    ],
+    prepare: None,
+};
+
+/// `--sound default` leaves the model for the defaults code to pick
+fn set_sound_model_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let val = ctx.val.filter(|v| *v != "default").map(|v| v.to_string());
+    ctx.set("./@model", val.as_deref());
+    Ok(())
+}
+
+static AUDIO: OptionSpec = OptionSpec {
+    name: "audio",
+    aliases: &[],
+    xpath: Some("./devices/audio"),
+    is_list: true,
+    remove_first: &["type"],
+    stub_none: false,
+    device_common: false,
+    args: &[SubArg::new("type", "./@type"), SubArg::new("id", "./@id")],
+    prepare: None,
+};
+
+static HOSTDEV: OptionSpec = OptionSpec {
+    name: "host-device",
+    aliases: &["hostdev"],
+    xpath: Some("./devices/hostdev"),
+    is_list: true,
+    remove_first: &["name"],
+    stub_none: false,
+    device_common: true,
+    args: &[
+        SubArg::new("type", "./@type"),
+        SubArg::cb("name", set_hostdev_name_cb).lookup(hostdev_name_lookup),
+        SubArg::new("driver.name", "./driver/@name").alias(&["driver_name"]),
+        SubArg::new("rom.bar", "./rom/@bar")
+            .onoff()
+            .alias(&["rom_bar"]),
+        SubArg::new("rom.file", "./rom/@file"),
+        SubArg::new("startupPolicy", "./source/@startupPolicy"),
+        SubArg::new("managed", "./@managed").yesno(),
+        SubArg::new("model", "./@model"),
+        SubArg::new("display", "./@display").onoff(),
+        SubArg::new("source.vendor.id", "./source/vendor/@id"),
+        SubArg::new("source.product.id", "./source/product/@id"),
+        SubArg::new("source.address.type", "./source/address/@type"),
+        SubArg::new("source.address.domain", "./source/address/@domain"),
+        SubArg::new("source.address.bus", "./source/address/@bus"),
+        SubArg::new("source.address.slot", "./source/address/@slot"),
+        SubArg::new("source.address.function", "./source/address/@function"),
+        SubArg::new("source.address.device", "./source/address/@device"),
+        SubArg::new("source.address.uuid", "./source/address/@uuid"),
+    ],
+    prepare: None,
+};
+
+/// A host device as given to `--hostdev`, resolved without a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostdevSpec {
+    Pci {
+        domain: String,
+        bus: String,
+        slot: String,
+        function: String,
+    },
+    UsbId {
+        vendor: String,
+        product: String,
+    },
+    UsbAddress {
+        bus: String,
+        device: String,
+    },
+    /// Anything else is a node device name like `usb_5_20`, which needs
+    /// the connection to resolve
+    Nodedev(String),
+}
+
+fn hexnum(val: &str, width: usize) -> Option<String> {
+    let digits = val.trim_start_matches("0x");
+    let n = u32::from_str_radix(digits, 16).ok()?;
+    Some(format!("0x{:0width$x}", n, width = width))
+}
+
+impl HostdevSpec {
+    pub fn parse(val: &str) -> Self {
+        // pci_0000_00_1f_2 nodedev names map straight to an address
+        if let Some(rest) = val.strip_prefix("pci_") {
+            let parts: Vec<&str> = rest.split('_').collect();
+            if let [d, b, s, f] = parts.as_slice()
+                && let (Some(domain), Some(bus), Some(slot), Some(function)) =
+                    (hexnum(d, 4), hexnum(b, 2), hexnum(s, 2), hexnum(f, 1))
+            {
+                return Self::Pci {
+                    domain,
+                    bus,
+                    slot,
+                    function,
+                };
+            }
+        }
+
+        // [DDDD:]BB:SS.F
+        if let Some((rest, f)) = val.rsplit_once('.') {
+            let parts: Vec<&str> = rest.split(':').collect();
+            let (d, b, s) = match parts.as_slice() {
+                [b, s] => ("0", *b, *s),
+                [d, b, s] => (*d, *b, *s),
+                _ => ("", "", ""),
+            };
+            if let (Some(domain), Some(bus), Some(slot), Some(function)) =
+                (hexnum(d, 4), hexnum(b, 2), hexnum(s, 2), hexnum(f, 1))
+            {
+                return Self::Pci {
+                    domain,
+                    bus,
+                    slot,
+                    function,
+                };
+            }
+
+            // BBB.DDD usb bus.device
+            if let (Ok(bus), Ok(dev)) = (rest.parse::<u32>(), f.parse::<u32>()) {
+                return Self::UsbAddress {
+                    bus: bus.to_string(),
+                    device: dev.to_string(),
+                };
+            }
+        }
+
+        // VVVV:PPPP usb vendor:product
+        if let Some((v, p)) = val.split_once(':') {
+            let ishex = |s: &str| {
+                let s = s.trim_start_matches("0x");
+                !s.is_empty() && s.len() <= 4 && s.chars().all(|c| c.is_ascii_hexdigit())
+            };
+            if ishex(v) && ishex(p) {
+                return Self::UsbId {
+                    vendor: hexnum(v, 4).unwrap_or_default(),
+                    product: hexnum(p, 4).unwrap_or_default(),
+                };
+            }
+        }
+        Self::Nodedev(val.to_string())
+    }
+}
+
+fn hostdev_name_lookup(hostdev: &Element, val: Option<&str>) -> bool {
+    let Some(val) = val else {
+        return false;
+    };
+    let mut probe = Element::new("hostdev");
+    if apply_hostdev_spec(&mut probe, ".", &HostdevSpec::parse(val)).is_err() {
+        return false;
+    }
+    let xpaths = [
+        "./source/vendor/@id",
+        "./source/product/@id",
+        "./source/address/@bus",
+        "./source/address/@device",
+        "./source/address/@domain",
+        "./source/address/@slot",
+        "./source/address/@function",
+    ];
+    xpaths.iter().all(|x| {
+        let want = probe.get(x);
+        want.is_none() || hostdev.get(x) == want
+    })
+}
+
+fn apply_hostdev_spec(root: &mut Element, base: &str, spec: &HostdevSpec) -> Result<(), String> {
+    let x = |rel: &str| super::join_xpath(base, rel);
+    match spec {
+        HostdevSpec::Pci {
+            domain,
+            bus,
+            slot,
+            function,
+        } => {
+            root.set(&x("./@mode"), Some("subsystem"));
+            root.set(&x("./@type"), Some("pci"));
+            root.set(&x("./@managed"), Some("yes"));
+            root.set(&x("./source/address/@domain"), Some(domain));
+            root.set(&x("./source/address/@bus"), Some(bus));
+            root.set(&x("./source/address/@slot"), Some(slot));
+            root.set(&x("./source/address/@function"), Some(function));
+        }
+        HostdevSpec::UsbId { vendor, product } => {
+            root.set(&x("./@mode"), Some("subsystem"));
+            root.set(&x("./@type"), Some("usb"));
+            root.set(&x("./@managed"), Some("yes"));
+            root.set(&x("./source/vendor/@id"), Some(vendor));
+            root.set(&x("./source/product/@id"), Some(product));
+        }
+        HostdevSpec::UsbAddress { bus, device } => {
+            root.set(&x("./@mode"), Some("subsystem"));
+            root.set(&x("./@type"), Some("usb"));
+            root.set(&x("./@managed"), Some("yes"));
+            root.set(&x("./source/address/@bus"), Some(bus));
+            root.set(&x("./source/address/@device"), Some(device));
+        }
+        HostdevSpec::Nodedev(name) => {
+            return Err(format!(
+                "Did not find a matching node device for '{}'",
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn set_hostdev_name_cb(ctx: &mut ArgCtx) -> Result<(), String> {
+    let val = require_val(ctx)?;
+    // Capability hostdevs name the host resource directly
+    match ctx.get("./@type").as_deref() {
+        Some("net") => {
+            ctx.set("./@mode", Some("capabilities"));
+            ctx.set("./source/interface", Some(val));
+            return Ok(());
+        }
+        Some("storage") => {
+            ctx.set("./@mode", Some("capabilities"));
+            ctx.set("./source/block", Some(val));
+            return Ok(());
+        }
+        Some("misc") => {
+            ctx.set("./@mode", Some("capabilities"));
+            ctx.set("./source/char", Some(val));
+            return Ok(());
+        }
+        _ => {}
+    }
+    let base = ctx.base.to_string();
+    apply_hostdev_spec(ctx.root, &base, &HostdevSpec::parse(val))
+}
+
+static ALL_PARSERS: &[&OptionSpec] = &[
+    &METADATA,
+    &EVENTS,
+    &SYSINFO,
+    &MEMORY,
+    &VCPUS,
+    &CPU,
+    &BOOT,
+    &FEATURES,
+    &CLOCK,
+    &QEMU_COMMANDLINE,
+    &XML,
+    &DISK,
+    &NETWORK,
+    &GRAPHICS,
+    &CONTROLLER,
+    &INPUT,
+    &SERIAL,
+    &PARALLEL,
+    &CHANNEL,
+    &CONSOLE,
+    &HOSTDEV,
+    &FILESYSTEM,
+    &SOUND,
+    &AUDIO,
+    &WATCHDOG,
+    &VIDEO,
+    &SMARTCARD,
+    &REDIRDEV,
+    &MEMBALLOON,
+    &TPM,
+    &RNG,
+    &PANIC,
+    &MEMDEV,
+    &VSOCK,
+];
+
+/// Every option spec, in the order virtinst applies them
+pub fn all_parsers() -> &'static [&'static OptionSpec] {
+    ALL_PARSERS
+}
+
+/// Look up an option spec by name or alias, with or without dashes
+pub fn lookup_parser(name: &str) -> Option<&'static OptionSpec> {
+    ALL_PARSERS.iter().copied().find(|p| p.matches_name(name))
+}
@@ -0,0 +1,786 @@
+// Cloning a virtual machine (port of virtinst/cloner.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `Cloner` turns the XML of a shutoff VM into XML for a new VM with a
+//! fresh name, UUID and MACs, and works out what to do with each disk:
+//! copy its contents to a new path next to the original, share the
+//! original unchanged, or point at an existing path whose contents are
+//! kept as is.
+//!
+//! Storage is handled as local paths. Without storage pool support a
+//! remote connection can't see the files behind its disks, so those are
+//! reported as missing rather than guessed at.
+
+use std::path::Path;
+
+use crate::cli::parsers::disk_source_path;
+use crate::connection::{Connection, DomainInfo};
+use crate::diskbackend::local_dev_type;
+use crate::diskcopy::copy_disk;
+use crate::generatename::generate_name;
+use crate::guest::{Guest, generate_mac, generate_uuid};
+use crate::progress::Meter;
+use crate::xmlapi::Element;
+
+/// Remove the existing VM with the same name, for `--replace`
+fn replace_vm(conn: &dyn Connection, name: &str) -> Result<(), String> {
+    let Ok(info) = conn.lookup_domain(name) else {
+        return Ok(());
+    };
+    log::debug!("Explicitly replacing guest '{}'", name);
+    let remove = || -> Result<(), String> {
+        if info.state.is_active() {
+            log::debug!("Destroying guest '{}'", name);
+            conn.destroy_domain(name)?;
+        }
+        if info.persistent {
+            log::debug!("Undefining guest '{}'", name);
+            conn.undefine_domain(name)?;
+        }
+        Ok(())
+    };
+    remove().map_err(|e| format!("Could not remove old vm '{}': {}", name, e))
+}
+
+/// If the original name is "foo-clone", we don't want the clone to be
+/// "foo-clone-clone", we want "foo-clone1"
+pub fn generate_clone_name(conn: &dyn Connection, basename: &str) -> Result<String, String> {
+    let mut base = basename;
+    let mut start_num = 1;
+    let mut force_num = false;
+    if let Some(pos) = basename.rfind("-clone") {
+        let tail = &basename[pos + "-clone".len()..];
+        if tail.chars().all(|c| ('1'..='9').contains(&c)) {
+            force_num = true;
+            if !tail.is_empty() {
+                start_num = tail.parse::<u32>().map_or(1, |n| n + 1);
+            }
+            base = &basename[..pos];
+        }
+    }
+    generate_name(
+        &format!("{}-clone", base),
+        |n| conn.lookup_domain(n).is_ok(),
+        "",
+        start_num,
+        "",
+        force_num,
+    )
+}
+
+/// Generate a path for a cloned disk or auxiliary file, derived from the
+/// original path, original VM name, and proposed new VM name
+pub fn generate_clone_path(
+    origname: &str,
+    newname: &str,
+    origpath: Option<&str>,
+    exists: impl Fn(&str) -> bool,
+) -> Result<Option<String>, String> {
+    let Some(origpath) = origpath else {
+        return Ok(None);
+    };
+
+    // Try to split the suffix off the existing disk name. Ex.
+    // foobar.img -> foobar-clone.img
+    //
+    // If the suffix is greater than 7 characters, assume it isn't
+    // a file extension and is part of the disk name, at which point
+    // just stick '-clone' on the end.
+    let (path, suffix) = match origpath.rsplit_once('.') {
+        Some((p, s)) if s.chars().count() <= 7 => (p, format!(".{}", s)),
+        _ => (origpath, String::new()),
+    };
+    let (dirname, basename) = match path.rsplit_once('/') {
+        Some((d, b)) => (d, b),
+        None => ("", path),
+    };
+
+    let mut clonebase = format!("{}-clone", basename);
+    if !origname.is_empty() && basename == origname {
+        clonebase = newname.to_string();
+    }
+    let clonebase = match (dirname, path.contains('/')) {
+        (_, false) => clonebase,
+        ("", true) => format!("/{}", clonebase),
+        (d, true) => format!("{}/{}", d, clonebase),
+    };
+    generate_name(&clonebase, exists, &suffix, 1, "-", false).map(Some)
+}
+
+/// Whether `path` is known to exist. Paths on remote hosts can't be
+/// checked, so they never count.
+fn path_definitely_exists(remote: bool, path: &str) -> bool {
+    !remote && Path::new(path).symlink_metadata().is_ok()
+}
+
+/// Point a `<disk>` at a new path, keeping `<source>` where it is
+fn set_disk_source(disk: &mut Element, disktype: &str, path: Option<&str>) {
+    disk.set("./@type", Some(disktype));
+    let attr = match disktype {
+        "block" => "dev",
+        "dir" => "dir",
+        _ => "file",
+    };
+    match (path, disk.find_mut("./source")) {
+        (Some(path), Some(source)) => {
+            for a in ["file", "dev", "dir"] {
+                source.set_attr(a, None);
+            }
+            source.set_attr(attr, Some(path));
+        }
+        (Some(path), None) => disk.set(&format!("./source/@{}", attr), Some(path)),
+        (None, _) => {
+            for a in ["file", "dev", "dir"] {
+                disk.set(&format!("./source/@{}", a), None);
+            }
+        }
+    }
+}
+
+/// If the disk storage is not cloneable, explain why
+fn cloneable_msg(disk: &Element, remote: bool) -> Option<String> {
+    if disk.get("./@type").as_deref() == Some("network") {
+        let proto = disk.get("./source/@protocol").unwrap_or_default();
+        if proto == "rbd" {
+            return Some(
+                "Cloning rbd volumes is not yet supported. https://github.com/virt-manager/virt-manager/issues/177"
+                    .to_string(),
+            );
+        }
+        return Some(format!("Disk network type '{}' is not cloneable.", proto));
+    }
+    match disk_source_path(disk) {
+        Some(path) if !path_definitely_exists(remote, &path) => {
+            Some(format!("Disk path '{}' does not exist.", path))
+        }
+        _ => None,
+    }
+}
+
+fn shareable_msg(disk: &Element) -> Option<String> {
+    if disk.get("./@type").as_deref() != Some("network") && disk_source_path(disk).is_none() {
+        return Some("No storage to clone.".to_string());
+    }
+    if disk.get_bool("./readonly") {
+        return Some("Read Only".to_string());
+    }
+    if disk.get_bool("./shareable")
+        || disk.get("./transient/@shareBacking").as_deref() == Some("yes")
+    {
+        return Some("Marked as shareable".to_string());
+    }
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CloneAction {
+    /// Original disk XML is used unchanged for the new disk
+    Share,
+    /// Copy contents from src to dst, creating or overwriting dst
+    Clone,
+    /// Destination is an existing path, nothing is copied
+    Preserve,
+}
+
+/// Where a cloned disk ends up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloneDisk {
+    pub path: Option<String>,
+    pub disktype: String,
+    /// Source to copy contents from, when they get copied
+    pub clone_from: Option<String>,
+}
+
+/// How one disk of the source VM is handled
+#[derive(Debug, Clone)]
+pub struct CloneDiskInfo {
+    /// The source `<disk>`
+    pub disk: Element,
+    pub new_disk: Option<CloneDisk>,
+    action: CloneAction,
+    remote: bool,
+    share_msg: Option<String>,
+    cloneable_msg: Option<String>,
+}
+
+impl CloneDiskInfo {
+    fn new(disk: Element, remote: bool) -> Self {
+        let share_msg = shareable_msg(&disk);
+        let cloneable_msg = cloneable_msg(&disk, remote);
+        let action = if share_msg.is_some() {
+            CloneAction::Share
+        } else {
+            CloneAction::Clone
+        };
+        Self {
+            disk,
+            new_disk: None,
+            action,
+            remote,
+            share_msg,
+            cloneable_msg,
+        }
+    }
+
+    pub fn target(&self) -> Option<String> {
+        self.disk.get("./target/@dev")
+    }
+
+    pub fn source_path(&self) -> Option<String> {
+        disk_source_path(&self.disk)
+    }
+
+    pub fn is_clone_requested(&self) -> bool {
+        self.action == CloneAction::Clone
+    }
+
+    pub fn is_share_requested(&self) -> bool {
+        self.action == CloneAction::Share
+    }
+
+    pub fn is_preserve_requested(&self) -> bool {
+        self.action == CloneAction::Preserve
+    }
+
+    pub fn set_clone_requested(&mut self) {
+        self.action = CloneAction::Clone;
+    }
+
+    pub fn set_share_requested(&mut self) {
+        self.action = CloneAction::Share;
+    }
+
+    pub fn set_preserve_requested(&mut self) {
+        self.action = CloneAction::Preserve;
+    }
+
+    /// Why the disk defaults to being shared, if it does
+    pub fn share_msg(&self) -> Option<&str> {
+        self.share_msg.as_deref()
+    }
+
+    /// Why the disk contents can't be copied, if they can't
+    pub fn cloneable_msg(&self) -> Option<&str> {
+        self.cloneable_msg.as_deref()
+    }
+
+    /// Set the destination path. Errors for uncloneable disks are
+    /// reported later by `raise_error`.
+    pub fn set_new_path(&mut self, path: Option<String>) {
+        let allow_create = !self.is_preserve_requested();
+        if allow_create && self.cloneable_msg.is_some() {
+            return;
+        }
+        // Local paths are made absolute, like libvirt would need them
+        let path = match path {
+            Some(p) if !self.remote => Some(
+                std::path::absolute(&p)
+                    .map(|a| a.to_string_lossy().to_string())
+                    .unwrap_or(p),
+            ),
+            p => p,
+        };
+        let disktype = match &path {
+            Some(p) if !self.remote => local_dev_type(p).to_string(),
+            _ => self
+                .disk
+                .get("./@type")
+                .unwrap_or_else(|| "file".to_string()),
+        };
+        let clone_from = if allow_create && path.is_some() {
+            self.source_path()
+        } else {
+            None
+        };
+        self.new_disk = Some(CloneDisk {
+            path,
+            disktype,
+            clone_from,
+        });
+    }
+
+    pub fn raise_error(&self) -> Result<(), String> {
+        if self.is_clone_requested()
+            && let Some(msg) = &self.cloneable_msg
+        {
+            return Err(format!(
+                "Could not determine original disk information: {}",
+                msg
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub struct Cloner<'a> {
+    conn: &'a dyn Connection,
+    src_guest: Guest,
+    new_guest: Guest,
+    diskinfos: Vec<CloneDiskInfo>,
+    nvram_diskinfo: Option<CloneDiskInfo>,
+    new_nvram_path: Option<String>,
+    sparse: bool,
+    replace: bool,
+    reflink: bool,
+    warnings: Vec<String>,
+}
+
+impl<'a> Cloner<'a> {
+    /// Set up cloning the VM named `src_name`, or the VM described by
+    /// `src_xml` if passed
+    pub fn new(
+        conn: &'a dyn Connection,
+        src_name: Option<&str>,
+        src_xml: Option<&str>,
+    ) -> Result<Self, String> {
+        let src_xml = match src_xml {
+            Some(xml) => xml.to_string(),
+            None => {
+                let name = src_name.unwrap_or_default();
+                let info = conn
+                    .lookup_domain(name)
+                    .map_err(|_| format!("Domain '{}' was not found.", name))?;
+                if info.state.is_active() {
+                    return Err("Domain to clone must be shutoff.".to_string());
+                }
+                conn.domain_xml(&info.name, false)?
+            }
+        };
+        log::debug!("Original XML:\n{}", src_xml);
+
+        let src_guest = Guest::parse(&src_xml)?;
+        let remote = conn.is_remote();
+        let diskinfos = src_guest
+            .devices("disk")
+            .into_iter()
+            .map(|d| CloneDiskInfo::new(d.clone(), remote))
+            .collect();
+        let nvram_diskinfo = src_guest.xml.get("./os/nvram").map(|path| {
+            let mut disk = Element::new("disk");
+            disk.set("./@type", Some("file"));
+            disk.set("./source/@file", Some(path.trim()));
+            CloneDiskInfo::new(disk, remote)
+        });
+
+        let mut cloner = Self {
+            conn,
+            new_guest: src_guest.clone(),
+            src_guest,
+            diskinfos,
+            nvram_diskinfo,
+            new_nvram_path: None,
+            sparse: true,
+            replace: false,
+            reflink: false,
+            warnings: Vec::new(),
+        };
+        cloner.init_new_guest()?;
+        Ok(cloner)
+    }
+
+    /// The unconditional changes every clone gets
+    fn init_new_guest(&mut self) -> Result<(), String> {
+        let guest = &mut self.new_guest;
+        guest.xml.set_attr("id", None);
+        guest.xml.set("./title", None);
+
+        for i in 0..guest.devices("graphics").len() {
+            let xpath = Guest::device_xpath("graphics", i);
+            let port = guest.xml.get(&format!("{}/@port", xpath));
+            if port.is_some_and(|p| p != "-1") {
+                self.warnings.push(
+                    "Setting the graphics device port to autoport, in order to avoid conflicting."
+                        .to_string(),
+                );
+                guest.xml.set(&format!("{}/@port", xpath), Some("-1"));
+            }
+        }
+
+        for i in 0..guest.devices("interface").len() {
+            let xpath = Guest::device_xpath("interface", i);
+            guest.xml.set(&format!("{}/target/@dev", xpath), None);
+            guest
+                .xml
+                .set(&format!("{}/mac/@address", xpath), Some(&generate_mac()));
+        }
+
+        // For guest agent channel, remove a path to generate a new one with
+        // new guest name
+        for i in 0..guest.devices("channel").len() {
+            let xpath = Guest::device_xpath("channel", i);
+            let Some(channel) = guest.xml.find(&xpath) else {
+                continue;
+            };
+            let target_name = channel.get("./target/@name").unwrap_or_default();
+            let path = channel.get("./source/@path").unwrap_or_default();
+            if channel.get("./@type").as_deref() == Some("unix")
+                && !target_name.is_empty()
+                && path.contains(&target_name)
+            {
+                guest.xml.set(&format!("{}/source/@path", xpath), None);
+            }
+        }
+
+        // swtpm state lives in a per-VM directory, drop any explicit
+        // location so the clone gets its own
+        for i in 0..guest.devices("tpm").len() {
+            let xpath = Guest::device_xpath("tpm", i);
+            if guest
+                .xml
+                .get(&format!("{}/backend/@type", xpath))
+                .as_deref()
+                == Some("emulator")
+            {
+                guest.xml.force_remove(&format!("{}/backend/source", xpath));
+            }
+        }
+
+        // A fixed vsock CID would collide with the original VM
+        for i in 0..guest.devices("vsock").len() {
+            let xpath = Guest::device_xpath("vsock", i);
+            if guest.xml.find(&format!("{}/cid", xpath)).is_some() {
+                guest.xml.set(&format!("{}/cid/@address", xpath), None);
+                guest.xml.set(&format!("{}/cid/@auto", xpath), Some("yes"));
+            }
+        }
+
+        let mut uuid = generate_uuid();
+        for _ in 0..256 {
+            if self.conn.lookup_domain(&uuid).is_err() {
+                break;
+            }
+            uuid = generate_uuid();
+        }
+        self.set_clone_uuid(&uuid);
+
+        let new_name = generate_clone_name(self.conn, &self.src_name())?;
+        log::debug!("Auto-generated clone name '{}'", new_name);
+        self.set_clone_name(&new_name);
+        Ok(())
+    }
+
+    /// The name of the original VM we are cloning
+    pub fn src_name(&self) -> String {
+        self.src_guest.name().unwrap_or_default()
+    }
+
+    /// The XML of the new VM
+    pub fn new_guest(&self) -> &Guest {
+        &self.new_guest
+    }
+
+    pub fn new_guest_mut(&mut self) -> &mut Guest {
+        &mut self.new_guest
+    }
+
+    /// Warnings collected while setting up the clone
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn set_clone_name(&mut self, name: &str) {
+        self.new_guest.set_name(name);
+    }
+
+    /// Override the new VM's generated UUID
+    pub fn set_clone_uuid(&mut self, uuid: &str) {
+        self.new_guest.set_uuid(Some(uuid));
+        for i in 0..self.new_guest.xml.count("./sysinfo") {
+            let xpath = format!("./sysinfo[{}]/system/entry[@name='uuid']", i + 1);
+            if self.new_guest.xml.find(&xpath).is_some() {
+                self.new_guest.xml.set(&xpath, Some(uuid));
+            }
+        }
+    }
+
+    /// Don't check for clone name collision, undefine any conflicting
+    /// guest instead
+    pub fn set_replace(&mut self, val: bool) {
+        self.replace = val;
+    }
+
+    /// Use copy-on-write lightweight copies
+    pub fn set_reflink(&mut self, val: bool) {
+        self.reflink = val;
+    }
+
+    /// Attempt sparse allocation when copying
+    pub fn set_sparse(&mut self, val: bool) {
+        self.sparse = val;
+    }
+
+    /// Override where cloned nvram content goes
+    pub fn set_nvram_path(&mut self, path: &str) {
+        self.new_nvram_path = Some(path.to_string());
+    }
+
+    pub fn diskinfos(&self) -> &[CloneDiskInfo] {
+        &self.diskinfos
+    }
+
+    pub fn diskinfos_mut(&mut self) -> &mut [CloneDiskInfo] {
+        &mut self.diskinfos
+    }
+
+    /// The disks that aren't shared with the original VM
+    pub fn nonshare_diskinfos_mut(&mut self) -> impl Iterator<Item = &mut CloneDiskInfo> {
+        self.diskinfos
+            .iter_mut()
+            .filter(|d| !d.is_share_requested())
+    }
+
+    pub fn nvram_diskinfo_mut(&mut self) -> Option<&mut CloneDiskInfo> {
+        self.nvram_diskinfo.as_mut()
+    }
+
+    fn prepare_serial_files(&mut self) -> Result<(), String> {
+        let src_name = self.src_name();
+        let new_name = self.new_guest.name().unwrap_or_default();
+        for tag in ["console", "serial"] {
+            for i in 0..self.new_guest.devices(tag).len() {
+                let xpath = Guest::device_xpath(tag, i);
+                if self
+                    .new_guest
+                    .xml
+                    .get(&format!("{}/@type", xpath))
+                    .as_deref()
+                    != Some("file")
+                {
+                    continue;
+                }
+                let pathxpath = format!("{}/source/@path", xpath);
+                let orig = self.new_guest.xml.get(&pathxpath);
+                // Existing serial files aren't a problem, don't check
+                let newpath =
+                    generate_clone_path(&src_name, &new_name, orig.as_deref(), |_| false)?;
+                self.new_guest.xml.set(&pathxpath, newpath.as_deref());
+            }
+        }
+        Ok(())
+    }
+
+    fn prepare_nvram(&mut self) -> Result<(), String> {
+        let Some(diskinfo) = self.nvram_diskinfo.as_mut() else {
+            return Ok(());
+        };
+        let old_path = diskinfo.source_path().unwrap_or_default();
+        let new_path = match &self.new_nvram_path {
+            Some(p) => p.clone(),
+            None => {
+                let (dir, base) = match old_path.rsplit_once('/') {
+                    Some(("", b)) => ("/", b),
+                    Some((d, b)) => (d, b),
+                    None => ("", old_path.as_str()),
+                };
+                let ext = match base.rfind('.') {
+                    Some(pos) if pos > 0 && base[..pos].chars().any(|c| c != '.') => &base[pos..],
+                    _ => ".fd",
+                };
+                let name = self.new_guest.name().unwrap_or_default();
+                let name = name.rsplit('/').next().unwrap_or_default();
+                let file = format!("{}_VARS{}", name, ext);
+                match dir {
+                    "" => file,
+                    "/" => format!("/{}", file),
+                    d => format!("{}/{}", d, file),
+                }
+            }
+        };
+
+        let remote = self.conn.is_remote();
+        if diskinfo.is_clone_requested()
+            && !path_definitely_exists(remote, &new_path)
+            && path_definitely_exists(remote, &old_path)
+        {
+            // Only copy when there's existing nvram. It's valid for it to
+            // not exist at define time, libvirt creates it from the template
+            diskinfo.set_new_path(Some(new_path.clone()));
+            diskinfo.raise_error()?;
+        } else {
+            // Nothing to copy, so drop it
+            self.nvram_diskinfo = None;
+        }
+        self.new_guest.xml.set("./os/nvram", Some(&new_path));
+        Ok(())
+    }
+
+    /// Validate and set up everything for the new VM XML
+    pub fn prepare(&mut self) -> Result<(), String> {
+        let new_name = self.new_guest.name().unwrap_or_default();
+        if !self.replace && self.conn.lookup_domain(&new_name).is_ok() {
+            return Err(format!(
+                "Invalid name for new guest: Guest name '{}' is already in use.",
+                new_name
+            ));
+        }
+
+        let src_name = self.src_name();
+        let remote = self.conn.is_remote();
+        for idx in 0..self.diskinfos.len() {
+            let diskinfo = &mut self.diskinfos[idx];
+            if diskinfo.is_share_requested() {
+                continue;
+            }
+            if diskinfo.new_disk.is_none() {
+                // User didn't set a path, generate one
+                let newpath = generate_clone_path(
+                    &src_name,
+                    &new_name,
+                    diskinfo.source_path().as_deref(),
+                    |p| path_definitely_exists(remote, p),
+                )?;
+                diskinfo.set_new_path(newpath);
+            }
+            let Some(new_disk) = &diskinfo.new_disk else {
+                // We hit an error, callers raise it later
+                continue;
+            };
+            log::debug!(
+                "Cloning srcpath={:?} dstpath={:?}",
+                diskinfo.source_path(),
+                new_disk.path
+            );
+
+            let target = diskinfo.target();
+            let pos = self
+                .new_guest
+                .devices("disk")
+                .iter()
+                .position(|d| d.get("./target/@dev") == target);
+            if let Some(pos) = pos
+                && let Some(xmldisk) = self
+                    .new_guest
+                    .xml
+                    .find_mut(&Guest::device_xpath("disk", pos))
+            {
+                set_disk_source(xmldisk, &new_disk.disktype, new_disk.path.as_deref());
+            }
+        }
+
+        self.prepare_nvram()?;
+        self.prepare_serial_files()?;
+        Ok(())
+    }
+
+    /// Define the new VM and copy any disk contents, reporting copy
+    /// progress to `meter`. The new VM is undefined again if copying
+    /// fails or `meter` cancels.
+    pub fn start_duplicate(&self, meter: &dyn Meter) -> Result<DomainInfo, String> {
+        log::debug!("Starting duplicate.");
+        let name = self.new_guest.name().unwrap_or_default();
+        if self.replace {
+            replace_vm(self.conn, &name)?;
+        }
+
+        // Define domain early to catch any xml errors before duping storage
+        let info = self.conn.define_xml(&self.new_guest.get_xml())?;
+
+        let copy = || -> Result<(), String> {
+            for diskinfo in self.diskinfos.iter().chain(self.nvram_diskinfo.iter()) {
+                if !diskinfo.is_clone_requested() {
+                    continue;
+                }
+                if let Some(CloneDisk {
+                    path: Some(dst),
+                    clone_from: Some(src),
+                    ..
+                }) = &diskinfo.new_disk
+                {
+                    copy_disk(src, dst, self.sparse, self.reflink, meter)?;
+                }
+            }
+            Ok(())
+        };
+        if let Err(e) = copy() {
+            log::debug!("Duplicate failed: {}", e);
+            let _ = self.conn.undefine_domain(&info.name);
+            return Err(e);
+        }
+        log::debug!("Duplicating finished.");
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::TestConnection;
+
+    #[test]
+    fn test_new_guest_per_vm_state() {
+        let conn = TestConnection::open("test:///default").unwrap();
+        let xml = "<domain type='kvm' id='3'>
+  <name>test</name>
+  <title>Original</title>
+  <devices>
+    <interface type='network'>
+      <mac address='52:54:00:aa:bb:cc'/>
+      <target dev='vnet0'/>
+    </interface>
+    <channel type='unix'>
+      <source mode='bind' path='/var/lib/libvirt/qemu/channel/target/test.org.qemu.guest_agent.0'/>
+      <target type='virtio' name='org.qemu.guest_agent.0'/>
+    </channel>
+    <tpm model='tpm-crb'>
+      <backend type='emulator' version='2.0'>
+        <source type='dir' path='/var/lib/swtpm/test'/>
+      </backend>
+    </tpm>
+    <graphics type='vnc' port='5900'/>
+    <vsock model='virtio'>
+      <cid auto='no' address='3'/>
+    </vsock>
+  </devices>
+</domain>";
+        let mut cloner = Cloner::new(&conn, None, Some(xml)).unwrap();
+        assert_eq!(cloner.take_warnings().len(), 1);
+        let guest = cloner.new_guest();
+        assert_eq!(guest.name().as_deref(), Some("test-clone"));
+        assert_eq!(guest.xml.attr("id"), None);
+        assert_eq!(guest.xml.get("./title"), None);
+        let iface = guest.devices("interface")[0];
+        assert_ne!(
+            iface.get("./mac/@address").as_deref(),
+            Some("52:54:00:aa:bb:cc")
+        );
+        assert_eq!(iface.get("./target/@dev"), None);
+        assert_eq!(guest.devices("channel")[0].get("./source/@path"), None);
+        assert!(guest.devices("tpm")[0].find("./backend/source").is_none());
+        assert_eq!(
+            guest.devices("graphics")[0].get("./@port").as_deref(),
+            Some("-1")
+        );
+        let vsock = guest.devices("vsock")[0];
+        assert_eq!(vsock.get("./cid/@auto").as_deref(), Some("yes"));
+        assert_eq!(vsock.get("./cid/@address"), None);
+    }
+
+    #[test]
+    fn test_generate_clone_path() {
+        let none = |_: &str| false;
+        let clone_path = |origname, newname, path| {
+            generate_clone_path(origname, newname, Some(path), none).unwrap()
+        };
+        assert_eq!(
+            clone_path("vm", "vm-clone", "/pool/disk.qcow2").as_deref(),
+            Some("/pool/disk-clone.qcow2")
+        );
+        assert_eq!(
+            clone_path("vm", "newvm", "/pool/vm.img").as_deref(),
+            Some("/pool/newvm.img")
+        );
+        assert_eq!(
+            clone_path("vm", "newvm", "/dev/vg/some.longsuffix").as_deref(),
+            Some("/dev/vg/some.longsuffix-clone")
+        );
+        assert_eq!(
+            generate_clone_path("vm", "x", Some("/pool/a.img"), |p| p == "/pool/a-clone.img")
+                .unwrap()
+                .as_deref(),
+            Some("/pool/a-clone-1.img")
+        );
+        assert_eq!(generate_clone_path("vm", "x", None, none), Ok(None));
+    }
+}
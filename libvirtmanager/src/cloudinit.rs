@@ -0,0 +1,298 @@
+// cloud-init NoCloud seed data (port of virtinst/install/cloudinit.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `CloudInitData` holds the `--cloud-init` settings and turns them into
+//! the meta-data, user-data and network-config files of a NoCloud seed.
+//! User supplied files replace the generated content wholesale.
+
+use std::path::{Path, PathBuf};
+
+use crate::progress::NullMeter;
+use crate::tmpfile;
+use crate::urlfetcher::{self, Fetcher};
+
+const PASSWORD_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CloudInitData {
+    pub disable: Option<bool>,
+    pub root_password_generate: Option<bool>,
+    pub root_password_file: Option<String>,
+    pub generated_root_password: Option<String>,
+    pub root_ssh_key: Option<String>,
+    pub clouduser_ssh_key: Option<String>,
+    pub user_data: Option<String>,
+    pub meta_data: Option<String>,
+    pub network_config: Option<String>,
+}
+
+/// First line of a file, like a password or ssh key file
+fn read_first_line(path: &str) -> Result<String, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("Error reading '{}': {}", path, e))?;
+    let line = content.split_inclusive('\n').next().unwrap_or_default();
+    Ok(line.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Scrub the root password from userdata, for logging
+pub(crate) fn scrub_password(content: &str) -> String {
+    content
+        .lines()
+        .map(|line| match line.find("root:") {
+            Some(idx) => format!("{}root:[SCRUBBLED]", &line[..idx]),
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        + if content.ends_with('\n') { "\n" } else { "" }
+}
+
+impl CloudInitData {
+    fn generate_password(&mut self) -> String {
+        self.generated_root_password
+            .get_or_insert_with(|| {
+                let mut buf = [0u8; 16];
+                crate::guest::fill_random(&mut buf);
+                buf.iter()
+                    .map(|b| PASSWORD_CHARS[*b as usize % PASSWORD_CHARS.len()] as char)
+                    .collect()
+            })
+            .clone()
+    }
+
+    pub fn get_password_if_generated(&mut self) -> Option<String> {
+        if self.root_password_generate == Some(true) {
+            return Some(self.generate_password());
+        }
+        None
+    }
+
+    pub fn get_root_password(&mut self) -> Result<Option<String>, String> {
+        if let Some(path) = &self.root_password_file {
+            return read_first_line(path).map(Some);
+        }
+        Ok(self.get_password_if_generated())
+    }
+
+    pub fn get_root_ssh_key(&self) -> Result<Option<String>, String> {
+        self.root_ssh_key
+            .as_deref()
+            .map(read_first_line)
+            .transpose()
+    }
+
+    pub fn get_clouduser_ssh_key(&self) -> Result<Option<String>, String> {
+        self.clouduser_ssh_key
+            .as_deref()
+            .map(read_first_line)
+            .transpose()
+    }
+
+    fn create_metadata_content(&mut self) -> Result<Option<String>, String> {
+        Ok(Some(String::new()))
+    }
+
+    /// The generated `#cloud-config` userdata
+    pub fn create_userdata_content(&mut self) -> Result<Option<String>, String> {
+        let mut content = "#cloud-config\n".to_string();
+        let generate = self.root_password_generate == Some(true);
+
+        if generate || self.root_password_file.is_some() {
+            let rootpass = self.get_root_password()?.unwrap_or_default();
+            content += "chpasswd:\n";
+            content += "  list: |\n";
+            content += &format!("    root:{}\n", rootpass);
+        }
+        if generate {
+            content += "  expire: True\n";
+        } else if self.root_password_file.is_some() {
+            content += "  expire: False\n";
+        }
+
+        if let Some(rootkey) = self.get_root_ssh_key()? {
+            content += "users:\n";
+            content += "  - default\n";
+            content += "  - name: root\n";
+            content += "    ssh_authorized_keys:\n";
+            content += &format!("      - {}\n", rootkey);
+        }
+        if let Some(userkey) = self.get_clouduser_ssh_key()? {
+            content += "ssh_authorized_keys:\n";
+            content += &format!("  - {}\n", userkey);
+        }
+        if self.disable == Some(true) {
+            content += "runcmd:\n";
+            content += "- echo \"Disabled by virt-install\" > /etc/cloud/cloud-init.disabled\n";
+        }
+
+        log::debug!(
+            "Generated cloud-init userdata: \n{}",
+            scrub_password(&content)
+        );
+        Ok(Some(content))
+    }
+
+    fn create_network_config_content(&mut self) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+
+    /// Add the (path, destname) of one seed file to `filepairs`. A user
+    /// supplied `config` is fetched, otherwise `content` is written out;
+    /// empty content means no file.
+    fn add_filepair(
+        &mut self,
+        filepairs: &mut Vec<(PathBuf, String)>,
+        destfile: &str,
+        config: Option<String>,
+        content: fn(&mut Self) -> Result<Option<String>, String>,
+        scratchdir: &Path,
+        fetcher: &dyn Fetcher,
+    ) -> Result<(), String> {
+        let path = match config {
+            Some(url) => {
+                log::debug!("Using '{}' content from path='{}'", destfile, url);
+                urlfetcher::acquire_file(&url, scratchdir, &NullMeter, fetcher)?
+            }
+            None => {
+                let content = match content(self)? {
+                    Some(c) if !c.is_empty() => c,
+                    _ => return Ok(()),
+                };
+                let (_, path) =
+                    tmpfile::mkstemp(scratchdir, "virtinst-", &format!("-{}", destfile))
+                        .map_err(|e| e.to_string())?;
+                std::fs::write(&path, content).map_err(|e| e.to_string())?;
+                path
+            }
+        };
+        filepairs.push((path, destfile.to_string()));
+        Ok(())
+    }
+
+    /// Write the seed files into `scratchdir`, returning (path, destname)
+    /// pairs for the ISO. On error nothing is left behind.
+    pub fn create_files(
+        &mut self,
+        scratchdir: &Path,
+        fetcher: &dyn Fetcher,
+    ) -> Result<Vec<(PathBuf, String)>, String> {
+        let mut filepairs = vec![];
+        let configs = [
+            (
+                "meta-data",
+                self.meta_data.clone(),
+                Self::create_metadata_content as fn(&mut Self) -> _,
+            ),
+            (
+                "user-data",
+                self.user_data.clone(),
+                Self::create_userdata_content,
+            ),
+            (
+                "network-config",
+                self.network_config.clone(),
+                Self::create_network_config_content,
+            ),
+        ];
+        for (destfile, config, content) in configs {
+            if let Err(e) = self.add_filepair(
+                &mut filepairs,
+                destfile,
+                config,
+                content,
+                scratchdir,
+                fetcher,
+            ) {
+                for (path, _) in &filepairs {
+                    let _ = std::fs::remove_file(path);
+                }
+                return Err(e);
+            }
+        }
+        Ok(filepairs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::urlfetcher::CurlFetcher;
+
+    fn datadir() -> String {
+        format!("{}/../tests/data/cli/cloudinit", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn test_userdata_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let pwfile = dir.path().join("pw");
+        std::fs::write(&pwfile, "foobar\r\nignored\n").unwrap();
+        let mut data = CloudInitData {
+            root_password_file: Some(pwfile.display().to_string()),
+            root_ssh_key: Some(format!("{}/ssh-key.txt", datadir())),
+            clouduser_ssh_key: Some(format!("{}/ssh-key2.txt", datadir())),
+            ..Default::default()
+        };
+        let content = data.create_userdata_content().unwrap().unwrap();
+        let key1 = read_first_line(&format!("{}/ssh-key.txt", datadir())).unwrap();
+        let key2 = read_first_line(&format!("{}/ssh-key2.txt", datadir())).unwrap();
+        assert_eq!(
+            content,
+            format!(
+                "#cloud-config\nchpasswd:\n  list: |\n    root:foobar\n  expire: False\n\
+                 users:\n  - default\n  - name: root\n    ssh_authorized_keys:\n      - {}\n\
+                 ssh_authorized_keys:\n  - {}\n",
+                key1, key2
+            )
+        );
+        assert!(scrub_password(&content).contains("    root:[SCRUBBLED]\n"));
+
+        let mut data = CloudInitData {
+            root_password_generate: Some(true),
+            disable: Some(true),
+            ..Default::default()
+        };
+        let password = data.get_password_if_generated().unwrap();
+        assert_eq!(password.len(), 16);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+        let content = data.create_userdata_content().unwrap().unwrap();
+        assert!(content.contains(&format!("    root:{}\n  expire: True\n", password)));
+        assert!(content.ends_with(
+            "runcmd:\n- echo \"Disabled by virt-install\" > /etc/cloud/cloud-init.disabled\n"
+        ));
+    }
+
+    #[test]
+    fn test_create_files() {
+        let scratch = tempfile::tempdir().unwrap();
+        let mut data = CloudInitData {
+            user_data: Some(format!("{}/user-data.txt", datadir())),
+            meta_data: Some(format!("{}/meta-data.txt", datadir())),
+            network_config: Some(format!("{}/network-config.txt", datadir())),
+            ..Default::default()
+        };
+        let pairs = data.create_files(scratch.path(), &CurlFetcher).unwrap();
+        let names: Vec<&str> = pairs.iter().map(|(_, n)| n.as_str()).collect();
+        assert_eq!(names, ["meta-data", "user-data", "network-config"]);
+        let expected = std::fs::read(format!("{}/meta-data.txt", datadir())).unwrap();
+        assert_eq!(std::fs::read(&pairs[0].0).unwrap(), expected);
+
+        // Empty metadata and no network config by default
+        let mut data = CloudInitData::default();
+        let pairs = data.create_files(scratch.path(), &CurlFetcher).unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].1, "user-data");
+
+        let mut data = CloudInitData {
+            meta_data: Some(format!("{}/meta-data.txt", datadir())),
+            user_data: Some("badurl://example.com".to_string()),
+            ..Default::default()
+        };
+        let before = std::fs::read_dir(scratch.path()).unwrap().count();
+        let err = data.create_files(scratch.path(), &CurlFetcher).unwrap_err();
+        assert!(err.contains("Couldn't acquire file badurl://example.com"));
+        assert_eq!(std::fs::read_dir(scratch.path()).unwrap().count(), before);
+    }
+}
@@ -0,0 +1,688 @@
+// Hypervisor connection abstraction (port of the parts of
+// virtinst/connection.py the CLIs need)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! The tools talk to libvirt through the `Connection` trait. Two
+//! implementations exist: `TestConnection`, an in-process mock modelled
+//! on libvirt's `test:///` driver that the test suite uses, and
+//! `VirshConnection`, which shells out to `virsh` for real hypervisors.
+
+mod testdriver;
+mod virsh;
+
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+
+pub use testdriver::TestConnection;
+#[cfg(test)]
+pub(crate) use testdriver::testdriver;
+pub use virsh::VirshConnection;
+
+/// Domain run state, with the numbering of libvirt's virDomainState
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DomainState {
+    #[default]
+    NoState,
+    Running,
+    Blocked,
+    Paused,
+    Shutdown,
+    Shutoff,
+    Crashed,
+    PmSuspended,
+}
+
+impl DomainState {
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            1 => Self::Running,
+            2 => Self::Blocked,
+            3 => Self::Paused,
+            4 => Self::Shutdown,
+            5 => Self::Shutoff,
+            6 => Self::Crashed,
+            7 => Self::PmSuspended,
+            _ => Self::NoState,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::NoState => "No state",
+            Self::Running => "Running",
+            Self::Blocked => "Idle",
+            Self::Paused => "Paused",
+            Self::Shutdown => "Shutting Down",
+            Self::Shutoff => "Shutoff",
+            Self::Crashed => "Crashed",
+            Self::PmSuspended => "Suspended",
+        }
+    }
+
+    /// Whether the domain has a live instance, and thus live XML
+    pub fn is_active(&self) -> bool {
+        !matches!(self, Self::Shutoff | Self::NoState)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainInfo {
+    pub name: String,
+    pub uuid: String,
+    /// Hypervisor ID, only set while the domain is active
+    pub id: Option<u32>,
+    pub state: DomainState,
+    pub persistent: bool,
+}
+
+/// Name and run state of a network, storage pool or node device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub name: String,
+    pub active: bool,
+}
+
+/// The kinds of objects a connection reports events for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectKind {
+    Domain,
+    Network,
+    Pool,
+    NodeDevice,
+}
+
+impl ObjectKind {
+    pub const ALL: [Self; 4] = [Self::Domain, Self::Network, Self::Pool, Self::NodeDevice];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Domain => "domain",
+            Self::Network => "network",
+            Self::Pool => "storage pool",
+            Self::NodeDevice => "node device",
+        }
+    }
+}
+
+/// Lifecycle event types, the union of libvirt's domain, network, pool
+/// and node device ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    Defined,
+    Undefined,
+    Started,
+    Suspended,
+    Resumed,
+    Stopped,
+    Shutdown,
+    PmSuspended,
+    Crashed,
+    Created,
+    Deleted,
+}
+
+impl Lifecycle {
+    /// Parse the event names virsh prints, like "Started"
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Defined" => Self::Defined,
+            "Undefined" => Self::Undefined,
+            "Started" => Self::Started,
+            "Suspended" => Self::Suspended,
+            "Resumed" => Self::Resumed,
+            "Stopped" => Self::Stopped,
+            "Shutdown" => Self::Shutdown,
+            "PMSuspended" => Self::PmSuspended,
+            "Crashed" => Self::Crashed,
+            "Created" => Self::Created,
+            "Deleted" => Self::Deleted,
+            _ => return None,
+        })
+    }
+}
+
+/// An event about an object of the connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnEvent {
+    Lifecycle(ObjectKind, String, Lifecycle),
+    /// The guest agent of a domain (dis)connected
+    AgentLifecycle {
+        domain: String,
+        connected: bool,
+    },
+    /// A device with this alias was hotplugged into a running domain
+    DeviceAdded {
+        domain: String,
+        alias: String,
+    },
+    DeviceRemoved {
+        domain: String,
+        alias: String,
+    },
+}
+
+impl ConnEvent {
+    pub fn kind(&self) -> ObjectKind {
+        match self {
+            Self::Lifecycle(kind, ..) => *kind,
+            _ => ObjectKind::Domain,
+        }
+    }
+
+    /// Name of the object the event is about
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Lifecycle(_, name, _) => name,
+            Self::AgentLifecycle { domain, .. }
+            | Self::DeviceAdded { domain, .. }
+            | Self::DeviceRemoved { domain, .. } => domain,
+        }
+    }
+}
+
+/// Where a device change should apply, like VIR_DOMAIN_AFFECT_*
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AffectFlags {
+    Live,
+    Config,
+    Both,
+}
+
+/// How create_snapshot takes the snapshot, like
+/// VIR_DOMAIN_SNAPSHOT_CREATE_*
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SnapshotFlags {
+    /// Replace the metadata of an existing snapshot
+    pub redefine: bool,
+    /// Only snapshot the disks, skipping the memory state
+    pub disk_only: bool,
+    /// Freeze guest filesystems through the guest agent first
+    pub quiesce: bool,
+}
+
+/// Which stats groups all_domain_stats collects, like the
+/// VIR_DOMAIN_STATS_* flags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct StatsFlags {
+    /// CPU time and vCPU count
+    pub cpu: bool,
+    /// Balloon size and unused guest memory
+    pub memory: bool,
+    pub disk: bool,
+    pub net: bool,
+}
+
+impl StatsFlags {
+    pub fn all() -> Self {
+        Self {
+            cpu: true,
+            memory: true,
+            disk: true,
+            net: true,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A DHCP lease handed out by a virtual network
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DhcpLease {
+    /// Seconds since the epoch
+    pub expirytime: i64,
+    pub iface: String,
+    /// "ipv4" or "ipv6"
+    pub family: String,
+    pub mac: String,
+    pub ipaddr: String,
+    pub prefix: u8,
+    pub hostname: Option<String>,
+    pub clientid: Option<String>,
+}
+
+/// Counters of one domain, as virConnectGetAllDomainStats reports them.
+/// Groups that weren't asked for stay zero.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DomainStats {
+    pub name: String,
+    pub state: DomainState,
+    /// CPU time used since the domain started, in nanoseconds
+    pub cpu_time: u64,
+    pub vcpus: u32,
+    /// Current balloon size in KiB
+    pub balloon_current: u64,
+    /// Memory the guest reports unused in KiB, if it reports it
+    pub balloon_unused: Option<u64>,
+    /// Byte counters summed over all disks and interfaces
+    pub disk_rd_bytes: u64,
+    pub disk_wr_bytes: u64,
+    pub net_rx_bytes: u64,
+    pub net_tx_bytes: u64,
+}
+
+/// Keeps an event subscription alive. Dropping it stops the events,
+/// for backends that need more than a dropped receiver to notice.
+pub struct EventSubscription {
+    stop: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl EventSubscription {
+    /// A subscription that ends once its receiver is dropped
+    pub fn detached() -> Self {
+        Self { stop: None }
+    }
+
+    /// A subscription that runs `stop` when dropped
+    pub fn on_drop(stop: impl FnOnce() + Send + 'static) -> Self {
+        Self {
+            stop: Some(Box::new(stop)),
+        }
+    }
+}
+
+impl Drop for EventSubscription {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop();
+        }
+    }
+}
+
+/// The hypervisor operations the tools need. Anything a backend can't
+/// do reports an error rather than silently succeeding.
+pub trait Connection: Send + Sync {
+    fn uri(&self) -> &str;
+
+    fn is_remote(&self) -> bool {
+        uri_is_remote(self.uri())
+    }
+
+    fn list_domains(&self) -> Result<Vec<DomainInfo>, String>;
+
+    /// Look up a domain by name, ID or UUID
+    fn lookup_domain(&self, key: &str) -> Result<DomainInfo, String>;
+
+    /// The domain XML. `inactive` asks for the persistent config rather
+    /// than the live XML of a running domain.
+    fn domain_xml(&self, name: &str, inactive: bool) -> Result<String, String>;
+
+    /// Define (or redefine) a persistent domain from XML
+    fn define_xml(&self, xml: &str) -> Result<DomainInfo, String>;
+
+    /// Start a transient domain from XML
+    fn create_xml(&self, xml: &str) -> Result<DomainInfo, String>;
+
+    fn start_domain(&self, name: &str) -> Result<(), String>;
+
+    /// Hard power off a running domain. Transient domains go away.
+    fn destroy_domain(&self, name: &str) -> Result<(), String>;
+
+    /// Remove a persistent domain's config. A running domain keeps
+    /// running as a transient one.
+    fn undefine_domain(&self, name: &str) -> Result<(), String>;
+
+    /// Whether the domain starts when the host boots
+    fn domain_autostart(&self, _name: &str) -> Result<bool, String> {
+        Err(format!("Autostart is not supported by '{}'", self.uri()))
+    }
+
+    fn set_domain_autostart(&self, _name: &str, _autostart: bool) -> Result<(), String> {
+        Err(format!("Autostart is not supported by '{}'", self.uri()))
+    }
+
+    /// The `<domainCapabilities>` XML for an emulator, arch, machine and
+    /// virt type combination. Unset values pick the hypervisor default.
+    fn domain_capabilities(
+        &self,
+        _emulator: Option<&str>,
+        _arch: Option<&str>,
+        _machine: Option<&str>,
+        _virttype: Option<&str>,
+    ) -> Result<String, String> {
+        Err(format!(
+            "Domain capabilities are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// XML of every snapshot of the domain
+    fn list_snapshots(&self, _name: &str) -> Result<Vec<String>, String> {
+        Err(format!("Snapshots are not supported by '{}'", self.uri()))
+    }
+
+    /// Name of the snapshot the domain was last created or reverted to
+    fn current_snapshot(&self, _name: &str) -> Result<Option<String>, String> {
+        Err(format!("Snapshots are not supported by '{}'", self.uri()))
+    }
+
+    /// Create a snapshot from `<domainsnapshot>` XML, returning its name
+    fn create_snapshot(
+        &self,
+        _name: &str,
+        _xml: &str,
+        _flags: SnapshotFlags,
+    ) -> Result<String, String> {
+        Err(format!("Snapshots are not supported by '{}'", self.uri()))
+    }
+
+    fn revert_snapshot(&self, _name: &str, _snapshot: &str) -> Result<(), String> {
+        Err(format!("Snapshots are not supported by '{}'", self.uri()))
+    }
+
+    /// Delete a snapshot. Without `children` its children are kept and
+    /// move up to its parent.
+    fn delete_snapshot(&self, _name: &str, _snapshot: &str, _children: bool) -> Result<(), String> {
+        Err(format!("Snapshots are not supported by '{}'", self.uri()))
+    }
+
+    fn list_networks(&self) -> Result<Vec<ObjectInfo>, String> {
+        Err(format!("Networks are not supported by '{}'", self.uri()))
+    }
+
+    fn network_xml(&self, _name: &str, _inactive: bool) -> Result<String, String> {
+        Err(format!("Networks are not supported by '{}'", self.uri()))
+    }
+
+    /// Define a new network or replace the config of one
+    fn define_network(&self, _xml: &str) -> Result<ObjectInfo, String> {
+        Err(format!("Networks are not supported by '{}'", self.uri()))
+    }
+
+    fn start_network(&self, _name: &str) -> Result<(), String> {
+        Err(format!("Networks are not supported by '{}'", self.uri()))
+    }
+
+    fn destroy_network(&self, _name: &str) -> Result<(), String> {
+        Err(format!("Networks are not supported by '{}'", self.uri()))
+    }
+
+    fn undefine_network(&self, _name: &str) -> Result<(), String> {
+        Err(format!("Networks are not supported by '{}'", self.uri()))
+    }
+
+    fn network_autostart(&self, _name: &str) -> Result<bool, String> {
+        Err(format!("Networks are not supported by '{}'", self.uri()))
+    }
+
+    fn set_network_autostart(&self, _name: &str, _autostart: bool) -> Result<(), String> {
+        Err(format!("Networks are not supported by '{}'", self.uri()))
+    }
+
+    fn network_dhcp_leases(&self, _name: &str) -> Result<Vec<DhcpLease>, String> {
+        Err(format!("DHCP leases are not supported by '{}'", self.uri()))
+    }
+
+    fn list_pools(&self) -> Result<Vec<ObjectInfo>, String> {
+        Err(format!(
+            "Storage pools are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn pool_xml(&self, _name: &str, _inactive: bool) -> Result<String, String> {
+        Err(format!(
+            "Storage pools are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// Define a new storage pool or replace the config of one
+    fn define_pool(&self, _xml: &str) -> Result<ObjectInfo, String> {
+        Err(format!(
+            "Storage pools are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// Set up what the pool needs on the host, like its directory
+    fn build_pool(&self, _name: &str) -> Result<(), String> {
+        Err(format!(
+            "Storage pools are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn start_pool(&self, _name: &str) -> Result<(), String> {
+        Err(format!(
+            "Storage pools are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn destroy_pool(&self, _name: &str) -> Result<(), String> {
+        Err(format!(
+            "Storage pools are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn undefine_pool(&self, _name: &str) -> Result<(), String> {
+        Err(format!(
+            "Storage pools are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// Rescan the volumes of an active pool
+    fn refresh_pool(&self, _name: &str) -> Result<(), String> {
+        Err(format!(
+            "Storage pools are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn pool_autostart(&self, _name: &str) -> Result<bool, String> {
+        Err(format!(
+            "Storage pools are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn set_pool_autostart(&self, _name: &str, _autostart: bool) -> Result<(), String> {
+        Err(format!(
+            "Storage pools are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// Names of the volumes of an active pool
+    fn list_volumes(&self, _pool: &str) -> Result<Vec<String>, String> {
+        Err(format!(
+            "Storage volumes are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn volume_xml(&self, _pool: &str, _name: &str) -> Result<String, String> {
+        Err(format!(
+            "Storage volumes are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn create_volume(&self, _pool: &str, _xml: &str) -> Result<(), String> {
+        Err(format!(
+            "Storage volumes are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// Create a volume in `pool` with the contents of `input_vol` of
+    /// `input_pool`
+    fn clone_volume(
+        &self,
+        _pool: &str,
+        _xml: &str,
+        _input_pool: &str,
+        _input_vol: &str,
+    ) -> Result<(), String> {
+        Err(format!(
+            "Storage volumes are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn delete_volume(&self, _pool: &str, _name: &str) -> Result<(), String> {
+        Err(format!(
+            "Storage volumes are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn list_nodedevs(&self) -> Result<Vec<ObjectInfo>, String> {
+        Err(format!(
+            "Node devices are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn nodedev_xml(&self, _name: &str) -> Result<String, String> {
+        Err(format!(
+            "Node devices are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// Send the events about objects of `kind` to `tx`, until the
+    /// returned subscription is dropped. An error means the caller has to
+    /// poll instead, and so does dropping `tx` when the events stop.
+    fn subscribe_events(
+        &self,
+        kind: ObjectKind,
+        _tx: Sender<ConnEvent>,
+    ) -> Result<EventSubscription, String> {
+        Err(format!(
+            "{} events are not supported by '{}'",
+            kind.label(),
+            self.uri()
+        ))
+    }
+
+    /// Stats of every domain in one call, cheap enough to poll
+    fn all_domain_stats(&self, _flags: StatsFlags) -> Result<Vec<DomainStats>, String> {
+        Err(format!(
+            "Domain statistics are not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// Number of active host CPUs
+    fn host_cpu_count(&self) -> Result<u32, String> {
+        Err(format!(
+            "Host CPU information is not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// A socket connected to graphics device `idx` of a running local
+    /// domain, for displays that don't listen anywhere
+    fn open_graphics_fd(&self, _name: &str, _idx: usize) -> Result<OwnedFd, String> {
+        Err(format!(
+            "Opening graphics connections is not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// A stream to a text console of a running domain, the serial or
+    /// console device with alias `devname`, or else the first one
+    fn open_console(&self, _name: &str, _devname: Option<&str>) -> Result<OwnedFd, String> {
+        Err(format!(
+            "Opening text consoles is not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    /// Grab the first screen of a running domain, as (mime type, data)
+    fn domain_screenshot(&self, _name: &str) -> Result<(String, Vec<u8>), String> {
+        Err(format!("Screenshots are not supported by '{}'", self.uri()))
+    }
+
+    fn attach_device(&self, _name: &str, _xml: &str, _flags: AffectFlags) -> Result<(), String> {
+        Err(format!(
+            "Device hotplug is not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn detach_device(&self, _name: &str, _xml: &str, _flags: AffectFlags) -> Result<(), String> {
+        Err(format!(
+            "Device hotunplug is not supported by '{}'",
+            self.uri()
+        ))
+    }
+
+    fn update_device(&self, _name: &str, _xml: &str, _flags: AffectFlags) -> Result<(), String> {
+        Err(format!(
+            "Device update is not supported by '{}'",
+            self.uri()
+        ))
+    }
+}
+
+/// Whether the URI points at another host, like `qemu+ssh://host/system`
+pub fn uri_is_remote(uri: &str) -> bool {
+    uri.split_once("://")
+        .map(|(_, rest)| {
+            let host = rest.split('/').next().unwrap_or("");
+            !host.is_empty() && host != "localhost"
+        })
+        .unwrap_or(false)
+}
+
+/// Open a connection. `test:///path/to/driver.xml` and `test:///default`
+/// use the built in test driver, optionally with `,domcaps=PATH` to
+/// report that domain capabilities XML. Everything else goes through
+/// virsh.
+/// With no URI, virsh picks its default.
+pub fn open(uri: Option<&str>) -> Result<Box<dyn Connection>, String> {
+    match uri {
+        Some(u) if u.starts_with("test://") => Ok(Box::new(TestConnection::open(u)?)),
+        _ => Ok(Box::new(VirshConnection::open(uri)?)),
+    }
+}
+
+/// Per user cache dir of the tools, `$XDG_CACHE_HOME/virt-manager`
+pub fn get_app_cache_dir() -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("virt-manager")
+}
+
+/// Cache dir for data about one connection's objects
+pub fn get_cache_dir(uri: &str) -> PathBuf {
+    get_app_cache_dir().join(uri.replace('/', "_"))
+}
+
+/// Whether `key` looks like a UUID string
+pub fn is_uuid(key: &str) -> bool {
+    let parts: Vec<&str> = key.split('-').collect();
+    let lens = [8, 4, 4, 4, 12];
+    parts.len() == lens.len()
+        && parts
+            .iter()
+            .zip(lens)
+            .all(|(p, l)| p.len() == l && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri_helpers() {
+        assert!(uri_is_remote("qemu+ssh://root@example.com/system"));
+        assert!(!uri_is_remote("qemu:///system"));
+        assert!(!uri_is_remote("test:///default"));
+        assert!(is_uuid("4a64cc71-19c4-2fd0-2323-3050941ea3c3"));
+        assert!(!is_uuid("test"));
+        assert_eq!(DomainState::from_id(5), DomainState::Shutoff);
+        assert!(!DomainState::Shutoff.is_active());
+    }
+}
@@ -0,0 +1,1721 @@
+// In-process mock of libvirt's test:/// driver
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Loads the same `<node>` files the libvirt test driver accepts, like
+//! tests/testdriver.xml, and keeps all state in memory. The driver
+//! specific `<test:runstate>`, `<test:transient/>`,
+//! `<test:hasmanagedsave/>` and `<test:domainsnapshot>` domain children
+//! set up the initial state. Like the real driver, all networks and
+//! pools of the file start out active, and every change of state is
+//! reported to the event subscribers. The `<volume>` children of a
+//! `<pool>` are its volumes.
+
+use std::io::{Read, Write};
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+
+use super::{
+    AffectFlags, ConnEvent, Connection, DhcpLease, DomainInfo, DomainState, DomainStats,
+    EventSubscription, Lifecycle, ObjectInfo, ObjectKind, SnapshotFlags, StatsFlags, is_uuid,
+};
+use crate::guest::{Guest, generate_uuid};
+use crate::snapshot::{DomainSnapshot, SnapshotDisk, SnapshotDisks, SnapshotMemory, state_name};
+use crate::storage;
+use crate::xmlapi::{Element, Node};
+
+/// Stand-in for the driver's builtin `test:///default` config
+const DEFAULT_NODE: &str = "<node>
+<domain type='test'>
+  <name>test</name>
+  <uuid>6695eb01-f6a4-8304-79aa-97f2502e193f</uuid>
+  <memory unit='KiB'>8388608</memory>
+  <currentMemory unit='KiB'>2097152</currentMemory>
+  <vcpu placement='static'>2</vcpu>
+  <os>
+    <type arch='i686'>hvm</type>
+    <boot dev='hd'/>
+  </os>
+  <clock offset='utc'/>
+  <on_poweroff>destroy</on_poweroff>
+  <on_reboot>restart</on_reboot>
+  <on_crash>destroy</on_crash>
+  <devices>
+  </devices>
+</domain>
+<network>
+  <name>default</name>
+  <uuid>dd8fe884-6c02-601e-7551-cca97df1c5df</uuid>
+  <bridge name='virbr0'/>
+  <forward/>
+  <ip address='192.168.122.1' netmask='255.255.255.0'>
+    <dhcp>
+      <range start='192.168.122.2' end='192.168.122.254'/>
+    </dhcp>
+  </ip>
+</network>
+</node>
+";
+
+/// Reported by domain_capabilities() when the URI has no `domcaps=`
+const DEFAULT_DOMCAPS: &str = "<domainCapabilities>
+  <path>/usr/bin/qemu-system-x86_64</path>
+  <domain>test</domain>
+  <machine>pc</machine>
+  <arch>x86_64</arch>
+  <vcpu max='255'/>
+  <os supported='yes'>
+    <enum name='firmware'>
+      <value>efi</value>
+    </enum>
+    <loader supported='yes'>
+      <value>/usr/share/edk2/ovmf/OVMF_CODE.secboot.fd</value>
+      <value>/usr/share/edk2/ovmf/OVMF_CODE.fd</value>
+      <enum name='type'>
+        <value>rom</value>
+        <value>pflash</value>
+      </enum>
+      <enum name='readonly'>
+        <value>yes</value>
+        <value>no</value>
+      </enum>
+    </loader>
+  </os>
+  <cpu>
+    <mode name='host-passthrough' supported='yes'/>
+    <mode name='host-model' supported='yes'>
+      <model fallback='forbid'>Skylake-Client-IBRS</model>
+    </mode>
+    <mode name='custom' supported='yes'>
+      <model usable='yes'>qemu64</model>
+      <model usable='yes'>core2duo</model>
+      <model usable='yes'>Nehalem</model>
+      <model usable='yes'>Skylake-Client-IBRS</model>
+    </mode>
+  </cpu>
+</domainCapabilities>
+";
+
+#[derive(Debug, Clone)]
+struct TestDomain {
+    id: Option<u32>,
+    state: DomainState,
+    persistent: bool,
+    has_managed_save: bool,
+    autostart: bool,
+    /// Persistent config, or the boot XML of a transient domain
+    config: Guest,
+    /// XML of the running instance
+    live: Option<Guest>,
+    snapshots: Vec<DomainSnapshot>,
+    current_snapshot: Option<String>,
+    /// Stats polls seen while running since the last boot, which the
+    /// fake counters grow with
+    stats_ticks: u64,
+}
+
+impl TestDomain {
+    fn name(&self) -> String {
+        self.config.name().unwrap_or_default()
+    }
+
+    fn uuid(&self) -> String {
+        self.config.uuid().unwrap_or_default()
+    }
+
+    fn snapshot(&self, name: &str) -> Result<usize, String> {
+        self.snapshots
+            .iter()
+            .position(|s| s.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Domain snapshot not found: no domain snapshot with matching name '{}'",
+                    name
+                )
+            })
+    }
+
+    /// Made up but steadily growing counters, each VM busy to its own
+    /// degree
+    fn stats(&mut self, flags: StatsFlags) -> DomainStats {
+        let mut stats = DomainStats {
+            name: self.name(),
+            state: self.state,
+            ..Default::default()
+        };
+        let Some(live) = self.live.as_ref() else {
+            return stats;
+        };
+        if self.state == DomainState::Running {
+            self.stats_ticks += 1;
+        }
+        let ticks = self.stats_ticks;
+        let load = u64::from(self.id.unwrap_or(0) % 4 + 1);
+        if flags.cpu {
+            stats.vcpus = live.vcpus().unwrap_or(1);
+            stats.cpu_time = ticks * load * 250_000_000;
+        }
+        if flags.memory {
+            let current = live.current_memory().or(live.memory()).unwrap_or(0);
+            let used_pct = 25 + (ticks * load * 7) % 50;
+            stats.balloon_current = current;
+            stats.balloon_unused = Some(current - current * used_pct / 100);
+        }
+        if flags.disk {
+            let disks = live.devices("disk").len() as u64;
+            stats.disk_rd_bytes = disks * ticks * load * 512 * 1024;
+            stats.disk_wr_bytes = disks * ticks * load * 256 * 1024;
+        }
+        if flags.net {
+            let nics = live.devices("interface").len() as u64;
+            stats.net_rx_bytes = nics * ticks * load * 128 * 1024;
+            stats.net_tx_bytes = nics * ticks * load * 64 * 1024;
+        }
+        stats
+    }
+
+    fn info(&self) -> DomainInfo {
+        DomainInfo {
+            name: self.name(),
+            uuid: self.uuid(),
+            id: self.id,
+            state: self.state,
+            persistent: self.persistent,
+        }
+    }
+}
+
+/// Like the real driver, pools without sizes have 100 GiB of space
+const DEFAULT_POOL_CAPACITY: u64 = 100 << 30;
+
+/// Bytes of a `<capacity>`, `<allocation>` or `<available>` element
+fn size_bytes(xml: &Element, field: &str) -> u64 {
+    let value = xml
+        .get(&format!("./{}", field))
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0);
+    let unit = xml.get(&format!("./{}/@unit", field));
+    storage::to_bytes(value, unit.as_deref()).unwrap_or(0)
+}
+
+fn set_size_bytes(xml: &mut Element, field: &str, bytes: u64) {
+    xml.set(&format!("./{}", field), Some(&bytes.to_string()));
+    xml.set(&format!("./{}/@unit", field), Some("bytes"));
+}
+
+/// A network, storage pool or node device
+#[derive(Debug, Clone)]
+struct TestObject {
+    xml: Element,
+    active: bool,
+    persistent: bool,
+    autostart: bool,
+    /// Volumes of a pool
+    volumes: Vec<Element>,
+}
+
+impl TestObject {
+    fn new(xml: Element) -> Self {
+        Self {
+            xml,
+            active: false,
+            persistent: true,
+            autostart: false,
+            volumes: vec![],
+        }
+    }
+
+    /// A pool, taking its volumes out of its XML
+    fn new_pool(mut xml: Element) -> Self {
+        let mut volumes = vec![];
+        while let Some(idx) = xml.child_index("volume", 0) {
+            volumes.extend(xml.remove_child_at(idx));
+        }
+        if xml.find("./capacity").is_none() {
+            set_size_bytes(&mut xml, "capacity", DEFAULT_POOL_CAPACITY);
+            set_size_bytes(&mut xml, "allocation", 0);
+            set_size_bytes(&mut xml, "available", DEFAULT_POOL_CAPACITY);
+        }
+        xml.prettify();
+        let mut pool = Self::new(xml);
+        for vol in volumes {
+            pool.add_volume(vol);
+        }
+        pool
+    }
+
+    /// Add a volume, filling in its path and key like the real driver,
+    /// and take its allocation off the free space of the pool
+    fn add_volume(&mut self, mut vol: Element) {
+        let name = vol.get("./name").unwrap_or_default();
+        if vol.get("./target/path").is_none() {
+            let dir = self
+                .xml
+                .get("./target/path")
+                .or_else(|| self.xml.get("./source/name"));
+            let path = match dir {
+                Some(dir) => format!("{}/{}", dir.trim_end_matches('/'), name),
+                None => name,
+            };
+            vol.set("./target/path", Some(&path));
+        }
+        if vol.get("./key").is_none() {
+            let path = vol.get("./target/path");
+            vol.set("./key", path.as_deref());
+        }
+        // Partitions of disk pools don't tell the kind of their contents
+        if self.xml.attr("type") == Some("disk") && vol.get("./target/format/@type").is_none() {
+            vol.set("./target/format/@type", Some("none"));
+        }
+        self.resize_pool(size_bytes(&vol, "allocation") as i128);
+        vol.prettify();
+        self.volumes.push(vol);
+    }
+
+    fn resize_pool(&mut self, allocated: i128) {
+        let alloc = size_bytes(&self.xml, "allocation") as i128 + allocated;
+        let avail = size_bytes(&self.xml, "available") as i128 - allocated;
+        set_size_bytes(&mut self.xml, "allocation", alloc.max(0) as u64);
+        set_size_bytes(&mut self.xml, "available", avail.max(0) as u64);
+    }
+
+    fn volume(&self, name: &str) -> Result<usize, String> {
+        self.volumes
+            .iter()
+            .position(|v| v.get("./name").as_deref() == Some(name))
+            .ok_or_else(|| {
+                format!(
+                    "Storage volume not found: no storage vol with matching name '{}'",
+                    name
+                )
+            })
+    }
+
+    fn check_active(&self) -> Result<(), String> {
+        if !self.active {
+            return Err(format!(
+                "Requested operation is not valid: storage pool '{}' is not active",
+                self.name()
+            ));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.xml.get("./name").unwrap_or_default()
+    }
+
+    fn info(&self) -> ObjectInfo {
+        ObjectInfo {
+            name: self.name(),
+            active: self.active,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    domains: Vec<TestDomain>,
+    networks: Vec<TestObject>,
+    pools: Vec<TestObject>,
+    nodedevs: Vec<TestObject>,
+    next_id: u32,
+    subscribers: Vec<(ObjectKind, Sender<ConnEvent>)>,
+}
+
+impl State {
+    /// Report `event` to its subscribers, forgetting the disconnected ones
+    fn emit(&mut self, event: ConnEvent) {
+        let kind = event.kind();
+        self.subscribers
+            .retain(|(k, tx)| *k != kind || tx.send(event.clone()).is_ok());
+    }
+
+    fn emit_lifecycle(&mut self, name: &str, event: Lifecycle) {
+        self.emit(ConnEvent::Lifecycle(
+            ObjectKind::Domain,
+            name.to_string(),
+            event,
+        ));
+    }
+
+    fn find(&self, key: &str) -> Option<usize> {
+        let by_id = key.parse::<u32>().ok();
+        self.domains
+            .iter()
+            .position(|d| d.name() == key)
+            .or_else(|| by_id.and_then(|id| self.domains.iter().position(|d| d.id == Some(id))))
+            .or_else(|| {
+                is_uuid(key)
+                    .then(|| {
+                        self.domains
+                            .iter()
+                            .position(|d| d.uuid().eq_ignore_ascii_case(key))
+                    })
+                    .flatten()
+            })
+    }
+
+    fn get(&self, key: &str) -> Result<usize, String> {
+        self.find(key)
+            .ok_or_else(|| format!("Domain not found: no domain with matching name '{}'", key))
+    }
+
+    fn get_network(&self, name: &str) -> Result<usize, String> {
+        self.networks
+            .iter()
+            .position(|n| n.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Network not found: no network with matching name '{}'",
+                    name
+                )
+            })
+    }
+
+    fn emit_network(&mut self, name: &str, event: Lifecycle) {
+        self.emit(ConnEvent::Lifecycle(
+            ObjectKind::Network,
+            name.to_string(),
+            event,
+        ));
+    }
+
+    fn get_pool(&self, name: &str) -> Result<usize, String> {
+        self.pools
+            .iter()
+            .position(|p| p.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Storage pool not found: no storage pool with matching name '{}'",
+                    name
+                )
+            })
+    }
+
+    fn emit_pool(&mut self, name: &str, event: Lifecycle) {
+        self.emit(ConnEvent::Lifecycle(
+            ObjectKind::Pool,
+            name.to_string(),
+            event,
+        ));
+    }
+
+    fn activate(&mut self, idx: usize, live: Guest) {
+        self.next_id += 1;
+        let id = self.next_id;
+        let dom = &mut self.domains[idx];
+        let mut live = live;
+        live.xml.set_attr("id", Some(&id.to_string()));
+        dom.id = Some(id);
+        dom.state = DomainState::Running;
+        dom.live = Some(live);
+        dom.stats_ticks = 0;
+    }
+}
+
+/// A fresh connection to tests/testdriver.xml, which most unit tests
+/// run against
+#[cfg(test)]
+pub(crate) fn testdriver() -> TestConnection {
+    TestConnection::open(&format!(
+        "test://{}/../tests/testdriver.xml",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap()
+}
+
+/// Connection backed by an in-memory copy of a test driver XML file
+#[derive(Debug)]
+pub struct TestConnection {
+    uri: String,
+    domcaps_path: Option<String>,
+    /// Active CPUs of the node, `<cpu><active>` in the XML
+    host_cpus: u32,
+    state: Mutex<State>,
+}
+
+impl TestConnection {
+    /// Open `test:///default` or `test:///abs/path/to/node.xml`, with an
+    /// optional `,domcaps=/path/to/domcaps.xml` suffix
+    pub fn open(uri: &str) -> Result<Self, String> {
+        let rest = uri
+            .strip_prefix("test://")
+            .ok_or_else(|| format!("Not a test driver URI: {}", uri))?;
+        let mut opts = rest.split(',');
+        let path = opts.next().unwrap_or_default();
+        let mut domcaps_path = None;
+        for opt in opts {
+            match opt.split_once('=') {
+                Some(("domcaps", val)) => domcaps_path = Some(val.to_string()),
+                _ => return Err(format!("Unknown test driver URI option '{}'", opt)),
+            }
+        }
+        let xml = if path == "/default" {
+            DEFAULT_NODE.to_string()
+        } else {
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read test driver file '{}': {}", path, e))?
+        };
+        let mut conn = Self::from_xml(uri, &xml)?;
+        conn.domcaps_path = domcaps_path;
+        Ok(conn)
+    }
+
+    /// Build a connection from `<node>` XML content
+    pub fn from_xml(uri: &str, xml: &str) -> Result<Self, String> {
+        let node = Element::parse(xml)?;
+        if node.name != "node" {
+            return Err(format!(
+                "Test driver XML must have a <node> root, found '{}'",
+                node.name
+            ));
+        }
+        let mut state = State::default();
+        for el in node.child_elements().filter(|e| e.name == "domain") {
+            let (dom, runstate) = parse_domain(el.clone())?;
+            let active = runstate.is_active();
+            state.domains.push(dom);
+            if active {
+                let idx = state.domains.len() - 1;
+                let live = state.domains[idx].config.clone();
+                state.activate(idx, live);
+                state.domains[idx].state = runstate;
+            }
+        }
+        for el in node.child_elements() {
+            let (list, obj) = match el.name.as_str() {
+                "network" => (&mut state.networks, TestObject::new(el.clone())),
+                "pool" => (&mut state.pools, TestObject::new_pool(el.clone())),
+                "device" => (&mut state.nodedevs, TestObject::new(el.clone())),
+                _ => continue,
+            };
+            list.push(TestObject {
+                active: true,
+                ..obj
+            });
+        }
+        // The driver's default node has 16 CPUs
+        let host_cpus = node
+            .get("./cpu/active")
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(16);
+        Ok(Self {
+            uri: uri.to_string(),
+            domcaps_path: None,
+            host_cpus,
+            state: Mutex::new(state),
+        })
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut State) -> Result<T, String>) -> Result<T, String> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| "Test driver state is poisoned".to_string())?;
+        f(&mut state)
+    }
+
+    /// Whether the domain has a managed save image
+    pub fn has_managed_save(&self, name: &str) -> Result<bool, String> {
+        self.with_state(|s| {
+            let idx = s.get(name)?;
+            Ok(s.domains[idx].has_managed_save)
+        })
+    }
+
+    /// Drop the event subscribers of `kind`, like a dying event stream
+    pub fn end_events(&self, kind: ObjectKind) {
+        let _ = self.with_state(|s| {
+            s.subscribers.retain(|(k, _)| *k != kind);
+            Ok(())
+        });
+    }
+
+    /// Apply `op` to the device `xml`. Live changes report `event`, if
+    /// given, with the device alias.
+    fn modify_device(
+        &self,
+        name: &str,
+        flags: AffectFlags,
+        op: fn(&mut Guest, Element) -> Result<(), String>,
+        xml: &str,
+        event: Option<fn(String, String) -> ConnEvent>,
+    ) -> Result<(), String> {
+        let dev = Element::parse(xml)?;
+        self.with_state(|s| {
+            let idx = s.get(name)?;
+            let dom = &mut s.domains[idx];
+            if matches!(flags, AffectFlags::Live | AffectFlags::Both) {
+                let live = dom.live.as_mut().ok_or_else(|| {
+                    "Requested operation is not valid: domain is not running".to_string()
+                })?;
+                // Hot unplug of a device given by its target only still
+                // reports the alias of the device that's gone
+                let alias = dev
+                    .get("./alias/@name")
+                    .or_else(|| {
+                        find_device(live, &dev)
+                            .and_then(|i| live.devices(&dev.name)[i].get("./alias/@name"))
+                    })
+                    .unwrap_or_default();
+                op(live, dev.clone())?;
+                let domname = dom.name();
+                if let Some(event) = event {
+                    s.emit(event(domname, alias));
+                }
+            }
+            let dom = &mut s.domains[idx];
+            if matches!(flags, AffectFlags::Config | AffectFlags::Both) {
+                if !dom.persistent {
+                    return Err(
+                        "Requested operation is not valid: cannot change persistent config of a transient domain"
+                            .to_string(),
+                    );
+                }
+                op(&mut dom.config, dev)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Split the test driver's private children off a `<domain>` element
+fn parse_domain(mut el: Element) -> Result<(TestDomain, DomainState), String> {
+    let mut runstate = DomainState::Running;
+    let mut persistent = true;
+    let mut has_managed_save = false;
+    let mut snapshots = vec![];
+    let mut current_snapshot = None;
+    let private: Vec<usize> = el
+        .children
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| matches!(c, Node::Element(e) if e.name.starts_with("test:")))
+        .map(|(i, _)| i)
+        .collect();
+    for idx in private.into_iter().rev() {
+        let Some(child) = el.remove_child_at(idx) else {
+            continue;
+        };
+        match child.name.as_str() {
+            "test:runstate" => {
+                let id = child.text().trim().parse::<u32>().map_err(|_| {
+                    format!("Invalid test:runstate value '{}'", child.text().trim())
+                })?;
+                runstate = DomainState::from_id(id);
+            }
+            "test:transient" => persistent = false,
+            "test:hasmanagedsave" => has_managed_save = true,
+            "test:domainsnapshot" => {
+                let mut child = child;
+                child.name = "domainsnapshot".to_string();
+                let mut snap = DomainSnapshot::parse(&child.to_xml())?;
+                if snap.active.take() == Some(1) {
+                    current_snapshot = snap.name.clone();
+                }
+                snapshots.insert(0, snap);
+            }
+            _ => {}
+        }
+    }
+    el.set_attr("xmlns:test", None);
+
+    let mut config = Guest::from_element(el);
+    if config.name().is_none() {
+        return Err("Test driver domain is missing a <name>".to_string());
+    }
+    if config.uuid().is_none() {
+        let uuid = generate_uuid();
+        let mut uuid_el = Element::new("uuid");
+        uuid_el.set_text(Some(&uuid));
+        match config.xml.child_index("name", 0) {
+            Some(idx) => {
+                config.xml.insert_child_after(idx, uuid_el);
+            }
+            None => config.xml.set("./uuid", Some(&uuid)),
+        }
+    }
+    let dom = TestDomain {
+        id: None,
+        state: DomainState::Shutoff,
+        persistent,
+        has_managed_save,
+        autostart: false,
+        config,
+        live: None,
+        snapshots,
+        current_snapshot,
+        stats_ticks: 0,
+    };
+    Ok((dom, runstate))
+}
+
+/// The screen of every running test domain: a 64x48 gradient as PPM,
+/// the format QEMU screenshots come in
+fn test_screenshot() -> Vec<u8> {
+    let (width, height) = (64, 48);
+    let mut data = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for y in 0..height {
+        for x in 0..width {
+            data.extend([(x * 4) as u8, (y * 5) as u8, 0x80]);
+        }
+    }
+    data
+}
+
+/// The guest end of every test text console: it echoes what is typed,
+/// with a newline after each carriage return
+fn test_console(mut stream: UnixStream) {
+    let mut buf = [0; 1024];
+    while let Ok(n @ 1..) = stream.read(&mut buf) {
+        let mut out = Vec::with_capacity(n);
+        for &b in &buf[..n] {
+            out.push(b);
+            if b == b'\r' {
+                out.push(b'\n');
+            }
+        }
+        if stream.write_all(&out).is_err() {
+            break;
+        }
+    }
+}
+
+/// Copy of `el` without whitespace-only text, for layout independent
+/// comparisons
+fn strip_whitespace(el: &Element) -> Element {
+    let mut ret = Element {
+        name: el.name.clone(),
+        attrs: el.attrs.clone(),
+        children: vec![],
+    };
+    for c in &el.children {
+        match c {
+            Node::Text(t) if t.trim().is_empty() => {}
+            Node::Element(e) => ret.children.push(Node::Element(strip_whitespace(e))),
+            other => ret.children.push(other.clone()),
+        }
+    }
+    ret
+}
+
+/// Find the device in `guest` that `dev` refers to: an identical device,
+/// else one of the same type with the same alias, target or MAC address.
+fn find_device(guest: &Guest, dev: &Element) -> Option<usize> {
+    let candidates = guest.devices(&dev.name);
+    let want = strip_whitespace(dev);
+    if let Some(idx) = candidates.iter().position(|c| strip_whitespace(c) == want) {
+        return Some(idx);
+    }
+    for key in ["./alias/@name", "./target/@dev", "./mac/@address"] {
+        if let Some(val) = dev.get(key)
+            && let Some(idx) = candidates
+                .iter()
+                .position(|c| c.get(key).as_deref() == Some(val.as_str()))
+        {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+fn attach(guest: &mut Guest, dev: Element) -> Result<(), String> {
+    guest.add_device(dev);
+    Ok(())
+}
+
+fn detach(guest: &mut Guest, dev: Element) -> Result<(), String> {
+    let idx = find_device(guest, &dev)
+        .ok_or_else(|| "operation failed: matching device not found".to_string())?;
+    guest.remove_device(&dev.name, idx);
+    Ok(())
+}
+
+fn update(guest: &mut Guest, dev: Element) -> Result<(), String> {
+    let idx = find_device(guest, &dev)
+        .ok_or_else(|| "operation failed: matching device not found".to_string())?;
+    let tag = dev.name.clone();
+    let devices = guest.xml.find_mut("./devices").expect("device was found");
+    let pos = devices.child_index(&tag, idx).expect("device was found");
+    let mut dev = dev;
+    if let Node::Element(old) = &devices.children[pos] {
+        // Keep the existing layout when the new XML is a one-liner
+        if !dev.to_xml().contains('\n') && old.to_xml().contains('\n') {
+            dev.prettify();
+        }
+    }
+    devices.children[pos] = Node::Element(dev);
+    Ok(())
+}
+
+impl Connection for TestConnection {
+    fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    fn is_remote(&self) -> bool {
+        false
+    }
+
+    fn list_domains(&self) -> Result<Vec<DomainInfo>, String> {
+        self.with_state(|s| Ok(s.domains.iter().map(TestDomain::info).collect()))
+    }
+
+    fn lookup_domain(&self, key: &str) -> Result<DomainInfo, String> {
+        self.with_state(|s| Ok(s.domains[s.get(key)?].info()))
+    }
+
+    fn domain_xml(&self, name: &str, inactive: bool) -> Result<String, String> {
+        self.with_state(|s| {
+            let dom = &s.domains[s.get(name)?];
+            let guest = match (&dom.live, inactive) {
+                (Some(live), false) => live,
+                _ => &dom.config,
+            };
+            Ok(guest.get_xml())
+        })
+    }
+
+    fn define_xml(&self, xml: &str) -> Result<DomainInfo, String> {
+        let mut guest = Guest::parse(xml)?;
+        let name = guest
+            .name()
+            .ok_or_else(|| "XML error: missing domain name information".to_string())?;
+        self.with_state(|s| {
+            if let Some(uuid) = guest.uuid()
+                && let Some(other) = s
+                    .domains
+                    .iter()
+                    .find(|d| d.uuid().eq_ignore_ascii_case(&uuid) && d.name() != name)
+            {
+                return Err(format!(
+                    "operation failed: domain '{}' already exists with uuid {}",
+                    other.name(),
+                    uuid
+                ));
+            }
+            if let Some(idx) = s.find(&name) {
+                let dom = &mut s.domains[idx];
+                let olduuid = dom.uuid();
+                match guest.uuid() {
+                    Some(u) if !u.eq_ignore_ascii_case(&olduuid) => {
+                        return Err(format!(
+                            "operation failed: domain '{}' already exists with uuid {}",
+                            name, olduuid
+                        ));
+                    }
+                    Some(_) => {}
+                    None => guest.set_uuid(Some(&olduuid)),
+                }
+                guest.xml.set_attr("id", None);
+                dom.config = guest;
+                dom.persistent = true;
+                let info = dom.info();
+                s.emit_lifecycle(&name, Lifecycle::Defined);
+                return Ok(info);
+            }
+            if guest.uuid().is_none() {
+                guest.set_uuid(Some(&generate_uuid()));
+            }
+            guest.xml.set_attr("id", None);
+            s.domains.push(TestDomain {
+                id: None,
+                state: DomainState::Shutoff,
+                persistent: true,
+                has_managed_save: false,
+                autostart: false,
+                config: guest,
+                live: None,
+                snapshots: vec![],
+                current_snapshot: None,
+                stats_ticks: 0,
+            });
+            s.emit_lifecycle(&name, Lifecycle::Defined);
+            Ok(s.domains.last().expect("just pushed").info())
+        })
+    }
+
+    fn create_xml(&self, xml: &str) -> Result<DomainInfo, String> {
+        let mut guest = Guest::parse(xml)?;
+        let name = guest
+            .name()
+            .ok_or_else(|| "XML error: missing domain name information".to_string())?;
+        self.with_state(|s| {
+            let idx = match s.find(&name) {
+                Some(idx) if s.domains[idx].state.is_active() => {
+                    return Err(format!(
+                        "Requested operation is not valid: domain '{}' is already active",
+                        name
+                    ));
+                }
+                Some(idx) => idx,
+                None => {
+                    if guest.uuid().is_none() {
+                        guest.set_uuid(Some(&generate_uuid()));
+                    }
+                    s.domains.push(TestDomain {
+                        id: None,
+                        state: DomainState::Shutoff,
+                        persistent: false,
+                        has_managed_save: false,
+                        autostart: false,
+                        config: guest.clone(),
+                        live: None,
+                        snapshots: vec![],
+                        current_snapshot: None,
+                        stats_ticks: 0,
+                    });
+                    s.domains.len() - 1
+                }
+            };
+            s.activate(idx, guest);
+            s.emit_lifecycle(&name, Lifecycle::Started);
+            Ok(s.domains[idx].info())
+        })
+    }
+
+    fn start_domain(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get(name)?;
+            if s.domains[idx].state.is_active() {
+                return Err("Requested operation is not valid: domain is already running".into());
+            }
+            let live = s.domains[idx].config.clone();
+            s.activate(idx, live);
+            s.domains[idx].has_managed_save = false;
+            s.emit_lifecycle(name, Lifecycle::Started);
+            Ok(())
+        })
+    }
+
+    fn destroy_domain(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get(name)?;
+            let dom = &mut s.domains[idx];
+            if !dom.state.is_active() {
+                return Err("Requested operation is not valid: domain is not running".into());
+            }
+            let domname = dom.name();
+            if dom.persistent {
+                dom.id = None;
+                dom.state = DomainState::Shutoff;
+                dom.live = None;
+            } else {
+                s.domains.remove(idx);
+            }
+            s.emit_lifecycle(&domname, Lifecycle::Stopped);
+            Ok(())
+        })
+    }
+
+    fn undefine_domain(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get(name)?;
+            let dom = &mut s.domains[idx];
+            if !dom.persistent {
+                return Err(
+                    "Requested operation is not valid: cannot undefine transient domain".into(),
+                );
+            }
+            let domname = dom.name();
+            if dom.state.is_active() {
+                dom.persistent = false;
+            } else {
+                s.domains.remove(idx);
+            }
+            s.emit_lifecycle(&domname, Lifecycle::Undefined);
+            Ok(())
+        })
+    }
+
+    fn domain_autostart(&self, name: &str) -> Result<bool, String> {
+        self.with_state(|s| {
+            let idx = s.get(name)?;
+            Ok(s.domains[idx].autostart)
+        })
+    }
+
+    fn set_domain_autostart(&self, name: &str, autostart: bool) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get(name)?;
+            let dom = &mut s.domains[idx];
+            if !dom.persistent {
+                return Err(
+                    "Requested operation is not valid: cannot set autostart for transient domain"
+                        .into(),
+                );
+            }
+            dom.autostart = autostart;
+            Ok(())
+        })
+    }
+
+    fn domain_capabilities(
+        &self,
+        _emulator: Option<&str>,
+        _arch: Option<&str>,
+        _machine: Option<&str>,
+        _virttype: Option<&str>,
+    ) -> Result<String, String> {
+        match &self.domcaps_path {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read domain capabilities '{}': {}", path, e)),
+            None => Ok(DEFAULT_DOMCAPS.to_string()),
+        }
+    }
+
+    fn list_snapshots(&self, name: &str) -> Result<Vec<String>, String> {
+        self.with_state(|s| {
+            let dom = &s.domains[s.get(name)?];
+            dom.snapshots.iter().map(DomainSnapshot::get_xml).collect()
+        })
+    }
+
+    fn current_snapshot(&self, name: &str) -> Result<Option<String>, String> {
+        self.with_state(|s| Ok(s.domains[s.get(name)?].current_snapshot.clone()))
+    }
+
+    fn create_snapshot(
+        &self,
+        name: &str,
+        xml: &str,
+        flags: SnapshotFlags,
+    ) -> Result<String, String> {
+        let mut snap = DomainSnapshot::parse(xml)?;
+        snap.active = None;
+        self.with_state(|s| {
+            let idx = s.get(name)?;
+            let dom = &mut s.domains[idx];
+            if flags.redefine {
+                let pos = dom.snapshot(snap.name())?;
+                let old = &dom.snapshots[pos];
+                snap.parent = snap.parent.take().or_else(|| old.parent.clone());
+                snap.domain = snap.domain.take().or_else(|| old.domain.clone());
+                dom.snapshots[pos] = snap;
+                return Ok(dom.snapshots[pos].name().to_string());
+            }
+            if flags.quiesce && !flags.disk_only {
+                return Err("invalid argument: quiesce requires disk-only".into());
+            }
+            if flags.quiesce && !dom.state.is_active() {
+                return Err("Requested operation is not valid: domain is not running".into());
+            }
+
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64);
+            let snapname = snap.name.clone().unwrap_or_else(|| now.to_string());
+            if dom.snapshot(&snapname).is_ok() {
+                return Err(format!(
+                    "Requested operation is not valid: domain snapshot '{}' already exists",
+                    snapname
+                ));
+            }
+            let guest = dom.live.as_ref().unwrap_or(&dom.config);
+            let external = flags.disk_only || snap.memory_type() == Some("external");
+            if snap.disks.is_none() {
+                let disks = guest
+                    .devices("disk")
+                    .iter()
+                    .filter_map(|d| d.get("./target/@dev"))
+                    .map(|target| SnapshotDisk {
+                        name: target,
+                        snapshot: Some(if external { "external" } else { "internal" }.into()),
+                        ..Default::default()
+                    })
+                    .collect();
+                snap.disks = Some(SnapshotDisks { disks });
+            }
+            if flags.disk_only {
+                snap.state = Some("disk-snapshot".to_string());
+                snap.memory = None;
+            } else {
+                snap.state = Some(state_name(dom.state).to_string());
+            }
+            if snap.memory.is_none() {
+                let memory = if snap.has_run_state() {
+                    "internal"
+                } else {
+                    "no"
+                };
+                snap.memory = Some(SnapshotMemory {
+                    snapshot: Some(memory.to_string()),
+                    file: None,
+                });
+            }
+            snap.name = Some(snapname.clone());
+            snap.creation_time = Some(now);
+            snap.set_parent(dom.current_snapshot.as_deref());
+            let mut domain = guest.xml.clone();
+            domain.set_attr("id", None);
+            snap.domain = Some(domain);
+            dom.snapshots.push(snap);
+            dom.current_snapshot = Some(snapname.clone());
+            Ok(snapname)
+        })
+    }
+
+    fn revert_snapshot(&self, name: &str, snapshot: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get(name)?;
+            let dom = &mut s.domains[idx];
+            let snap = &dom.snapshots[dom.snapshot(snapshot)?];
+            let domain = snap.domain.clone().ok_or_else(|| {
+                format!(
+                    "Snapshot '{}' lacks domain '{}' rollback info",
+                    snapshot, name
+                )
+            })?;
+            let state = snap.domain_state();
+            let run = snap.has_run_state();
+            let guest = Guest::from_element(domain);
+            if dom.persistent {
+                dom.config = guest.clone();
+            }
+            dom.has_managed_save = false;
+            dom.current_snapshot = Some(snapshot.to_string());
+            if run {
+                s.activate(idx, guest);
+                s.domains[idx].state = state;
+            } else {
+                dom.id = None;
+                dom.state = DomainState::Shutoff;
+                dom.live = None;
+            }
+            Ok(())
+        })
+    }
+
+    fn delete_snapshot(&self, name: &str, snapshot: &str, children: bool) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get(name)?;
+            let dom = &mut s.domains[idx];
+            let removed = dom.snapshots.remove(dom.snapshot(snapshot)?);
+            let parent = removed.parent_name();
+            let mut deleted = vec![snapshot.to_string()];
+            if children {
+                // Drop descendants until no snapshot has a deleted parent
+                while let Some(pos) = dom.snapshots.iter().position(|c| {
+                    c.parent_name()
+                        .is_some_and(|p| deleted.iter().any(|d| d == p))
+                }) {
+                    deleted.push(dom.snapshots.remove(pos).name().to_string());
+                }
+            } else {
+                for child in &mut dom.snapshots {
+                    if child.parent_name() == Some(snapshot) {
+                        child.set_parent(parent);
+                    }
+                }
+            }
+            if dom
+                .current_snapshot
+                .as_ref()
+                .is_some_and(|c| deleted.contains(c))
+            {
+                dom.current_snapshot = parent.map(str::to_string);
+            }
+            Ok(())
+        })
+    }
+
+    fn domain_screenshot(&self, name: &str) -> Result<(String, Vec<u8>), String> {
+        self.with_state(|s| {
+            if !s.domains[s.get(name)?].state.is_active() {
+                return Err("Requested operation is not valid: domain is not running".into());
+            }
+            Ok(("image/x-portable-pixmap".to_string(), test_screenshot()))
+        })
+    }
+
+    fn all_domain_stats(&self, flags: StatsFlags) -> Result<Vec<DomainStats>, String> {
+        self.with_state(|s| Ok(s.domains.iter_mut().map(|d| d.stats(flags)).collect()))
+    }
+
+    fn host_cpu_count(&self) -> Result<u32, String> {
+        Ok(self.host_cpus)
+    }
+
+    fn list_networks(&self) -> Result<Vec<ObjectInfo>, String> {
+        self.with_state(|s| Ok(s.networks.iter().map(TestObject::info).collect()))
+    }
+
+    fn network_xml(&self, name: &str, _inactive: bool) -> Result<String, String> {
+        self.with_state(|s| Ok(s.networks[s.get_network(name)?].xml.get_xml()))
+    }
+
+    fn define_network(&self, xml: &str) -> Result<ObjectInfo, String> {
+        let mut el = Element::parse(xml)?;
+        let name = el
+            .get("./name")
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| "XML error: missing network name information".to_string())?;
+        self.with_state(|s| {
+            let existing = s.get_network(&name).ok();
+            let olduuid = existing.and_then(|idx| s.networks[idx].xml.get("./uuid"));
+            match (el.get("./uuid"), &olduuid) {
+                (Some(new), Some(old)) if !new.eq_ignore_ascii_case(old) => {
+                    return Err(format!(
+                        "operation failed: network '{}' already exists with uuid {}",
+                        name, old
+                    ));
+                }
+                (Some(_), _) => {}
+                (None, _) => el.set("./uuid", Some(&olduuid.unwrap_or_else(generate_uuid))),
+            }
+            let info = match existing {
+                Some(idx) => {
+                    let net = &mut s.networks[idx];
+                    net.xml = el;
+                    net.persistent = true;
+                    net.info()
+                }
+                None => {
+                    let net = TestObject::new(el);
+                    let info = net.info();
+                    s.networks.push(net);
+                    info
+                }
+            };
+            s.emit_network(&name, Lifecycle::Defined);
+            Ok(info)
+        })
+    }
+
+    fn start_network(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get_network(name)?;
+            if s.networks[idx].active {
+                return Err("Requested operation is not valid: network is already active".into());
+            }
+            s.networks[idx].active = true;
+            s.emit_network(name, Lifecycle::Started);
+            Ok(())
+        })
+    }
+
+    fn destroy_network(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get_network(name)?;
+            if !s.networks[idx].active {
+                return Err(format!(
+                    "Requested operation is not valid: network '{}' is not active",
+                    name
+                ));
+            }
+            if s.networks[idx].persistent {
+                s.networks[idx].active = false;
+            } else {
+                s.networks.remove(idx);
+            }
+            s.emit_network(name, Lifecycle::Stopped);
+            Ok(())
+        })
+    }
+
+    fn undefine_network(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get_network(name)?;
+            if s.networks[idx].active {
+                s.networks[idx].persistent = false;
+            } else {
+                s.networks.remove(idx);
+            }
+            s.emit_network(name, Lifecycle::Undefined);
+            Ok(())
+        })
+    }
+
+    fn network_autostart(&self, name: &str) -> Result<bool, String> {
+        self.with_state(|s| Ok(s.networks[s.get_network(name)?].autostart))
+    }
+
+    fn set_network_autostart(&self, name: &str, autostart: bool) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get_network(name)?;
+            s.networks[idx].autostart = autostart;
+            Ok(())
+        })
+    }
+
+    /// The test driver has no DHCP server, so active networks report the
+    /// same made up leases as virt-manager's test mocks
+    fn network_dhcp_leases(&self, name: &str) -> Result<Vec<DhcpLease>, String> {
+        self.with_state(|s| {
+            if !s.networks[s.get_network(name)?].active {
+                return Ok(vec![]);
+            }
+            Ok(vec![
+                DhcpLease {
+                    expirytime: 1598570993,
+                    iface: "virbr1".into(),
+                    family: "ipv6".into(),
+                    mac: "BAD".into(),
+                    ipaddr: "fd00:beef::2".into(),
+                    prefix: 64,
+                    hostname: None,
+                    clientid: Some("XXX".into()),
+                },
+                DhcpLease {
+                    expirytime: 1598570993,
+                    iface: "virbr1".into(),
+                    family: "ipv4".into(),
+                    mac: "NOPE".into(),
+                    ipaddr: "10.0.0.2".into(),
+                    prefix: 24,
+                    hostname: None,
+                    clientid: Some("YYY".into()),
+                },
+            ])
+        })
+    }
+
+    fn list_pools(&self) -> Result<Vec<ObjectInfo>, String> {
+        self.with_state(|s| Ok(s.pools.iter().map(TestObject::info).collect()))
+    }
+
+    fn pool_xml(&self, name: &str, _inactive: bool) -> Result<String, String> {
+        self.with_state(|s| Ok(s.pools[s.get_pool(name)?].xml.get_xml()))
+    }
+
+    fn define_pool(&self, xml: &str) -> Result<ObjectInfo, String> {
+        let mut el = Element::parse(xml)?;
+        let name = el
+            .get("./name")
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| "XML error: missing storage pool name information".to_string())?;
+        self.with_state(|s| {
+            let existing = s.get_pool(&name).ok();
+            let olduuid = existing.and_then(|idx| s.pools[idx].xml.get("./uuid"));
+            match (el.get("./uuid"), &olduuid) {
+                (Some(new), Some(old)) if !new.eq_ignore_ascii_case(old) => {
+                    return Err(format!(
+                        "operation failed: pool '{}' already exists with uuid {}",
+                        name, old
+                    ));
+                }
+                (Some(_), _) => {}
+                (None, _) => el.set("./uuid", Some(&olduuid.unwrap_or_else(generate_uuid))),
+            }
+            let info = match existing {
+                Some(idx) => {
+                    let pool = &mut s.pools[idx];
+                    let volumes = std::mem::take(&mut pool.volumes);
+                    *pool = TestObject {
+                        active: pool.active,
+                        autostart: pool.autostart,
+                        volumes,
+                        ..TestObject::new_pool(el)
+                    };
+                    pool.info()
+                }
+                None => {
+                    let pool = TestObject::new_pool(el);
+                    let info = pool.info();
+                    s.pools.push(pool);
+                    info
+                }
+            };
+            s.emit_pool(&name, Lifecycle::Defined);
+            Ok(info)
+        })
+    }
+
+    /// There's nothing to set up on the host, so this only checks the
+    /// pool is inactive
+    fn build_pool(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get_pool(name)?;
+            if s.pools[idx].active {
+                return Err(format!(
+                    "Requested operation is not valid: storage pool '{}' is already active",
+                    name
+                ));
+            }
+            s.emit_pool(name, Lifecycle::Created);
+            Ok(())
+        })
+    }
+
+    fn start_pool(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get_pool(name)?;
+            if s.pools[idx].active {
+                return Err(format!(
+                    "Requested operation is not valid: storage pool '{}' is already active",
+                    name
+                ));
+            }
+            s.pools[idx].active = true;
+            s.emit_pool(name, Lifecycle::Started);
+            Ok(())
+        })
+    }
+
+    fn destroy_pool(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get_pool(name)?;
+            s.pools[idx].check_active()?;
+            if s.pools[idx].persistent {
+                s.pools[idx].active = false;
+            } else {
+                s.pools.remove(idx);
+            }
+            s.emit_pool(name, Lifecycle::Stopped);
+            Ok(())
+        })
+    }
+
+    fn undefine_pool(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get_pool(name)?;
+            if s.pools[idx].active {
+                return Err(format!(
+                    "Requested operation is not valid: storage pool '{}' is still active",
+                    name
+                ));
+            }
+            s.pools.remove(idx);
+            s.emit_pool(name, Lifecycle::Undefined);
+            Ok(())
+        })
+    }
+
+    fn refresh_pool(&self, name: &str) -> Result<(), String> {
+        self.with_state(|s| s.pools[s.get_pool(name)?].check_active())
+    }
+
+    fn pool_autostart(&self, name: &str) -> Result<bool, String> {
+        self.with_state(|s| Ok(s.pools[s.get_pool(name)?].autostart))
+    }
+
+    fn set_pool_autostart(&self, name: &str, autostart: bool) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get_pool(name)?;
+            s.pools[idx].autostart = autostart;
+            Ok(())
+        })
+    }
+
+    fn list_volumes(&self, pool: &str) -> Result<Vec<String>, String> {
+        self.with_state(|s| {
+            let pool = &s.pools[s.get_pool(pool)?];
+            pool.check_active()?;
+            Ok(pool
+                .volumes
+                .iter()
+                .filter_map(|v| v.get("./name"))
+                .collect())
+        })
+    }
+
+    fn volume_xml(&self, pool: &str, name: &str) -> Result<String, String> {
+        self.with_state(|s| {
+            let pool = &s.pools[s.get_pool(pool)?];
+            pool.check_active()?;
+            Ok(pool.volumes[pool.volume(name)?].get_xml())
+        })
+    }
+
+    fn create_volume(&self, pool: &str, xml: &str) -> Result<(), String> {
+        let vol = Element::parse(xml)?;
+        let name = vol
+            .get("./name")
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| "XML error: missing storage volume name information".to_string())?;
+        self.with_state(|s| {
+            let idx = s.get_pool(pool)?;
+            let pool = &mut s.pools[idx];
+            pool.check_active()?;
+            if pool.volume(&name).is_ok() {
+                return Err(format!("storage volume name '{}' already in use.", name));
+            }
+            let needed = size_bytes(&vol, "allocation");
+            let available = size_bytes(&pool.xml, "available");
+            if needed > available {
+                return Err(format!(
+                    "Not enough free space in pool for volume '{}'",
+                    name
+                ));
+            }
+            pool.add_volume(vol);
+            Ok(())
+        })
+    }
+
+    fn clone_volume(
+        &self,
+        pool: &str,
+        xml: &str,
+        input_pool: &str,
+        input_vol: &str,
+    ) -> Result<(), String> {
+        self.volume_xml(input_pool, input_vol)?;
+        self.create_volume(pool, xml)
+    }
+
+    fn delete_volume(&self, pool: &str, name: &str) -> Result<(), String> {
+        self.with_state(|s| {
+            let idx = s.get_pool(pool)?;
+            let pool = &mut s.pools[idx];
+            pool.check_active()?;
+            let vol = pool.volumes.remove(pool.volume(name)?);
+            pool.resize_pool(-(size_bytes(&vol, "allocation") as i128));
+            Ok(())
+        })
+    }
+
+    fn list_nodedevs(&self) -> Result<Vec<ObjectInfo>, String> {
+        self.with_state(|s| Ok(s.nodedevs.iter().map(TestObject::info).collect()))
+    }
+
+    fn nodedev_xml(&self, name: &str) -> Result<String, String> {
+        self.with_state(|s| {
+            s.nodedevs
+                .iter()
+                .find(|d| d.name() == name)
+                .map(|d| d.xml.get_xml())
+                .ok_or_else(|| {
+                    format!(
+                        "Node device not found: no node device with matching name '{}'",
+                        name
+                    )
+                })
+        })
+    }
+
+    fn subscribe_events(
+        &self,
+        kind: ObjectKind,
+        tx: Sender<ConnEvent>,
+    ) -> Result<EventSubscription, String> {
+        self.with_state(|s| {
+            s.subscribers.push((kind, tx));
+            Ok(EventSubscription::detached())
+        })
+    }
+
+    fn open_console(&self, name: &str, devname: Option<&str>) -> Result<OwnedFd, String> {
+        self.with_state(|s| {
+            let dom = &s.domains[s.get(name)?];
+            if !dom.state.is_active() {
+                return Err("Requested operation is not valid: domain is not running".into());
+            }
+            let guest = dom.live.as_ref().unwrap_or(&dom.config);
+            let mut devs = guest.devices("serial");
+            devs.extend(guest.devices("console"));
+            let found = match devname {
+                Some(alias) => devs
+                    .iter()
+                    .any(|d| d.get("./alias/@name").as_deref() == Some(alias)),
+                None => !devs.is_empty(),
+            };
+            if !found {
+                return Err(format!(
+                    "internal error: cannot find character device {}",
+                    devname.unwrap_or("<null>")
+                ));
+            }
+            Ok(())
+        })?;
+        let (ours, theirs) =
+            UnixStream::pair().map_err(|e| format!("Failed to open console stream: {}", e))?;
+        std::thread::spawn(move || test_console(theirs));
+        Ok(ours.into())
+    }
+
+    fn attach_device(&self, name: &str, xml: &str, flags: AffectFlags) -> Result<(), String> {
+        let event = |domain, alias| ConnEvent::DeviceAdded { domain, alias };
+        self.modify_device(name, flags, attach, xml, Some(event))
+    }
+
+    fn detach_device(&self, name: &str, xml: &str, flags: AffectFlags) -> Result<(), String> {
+        let event = |domain, alias| ConnEvent::DeviceRemoved { domain, alias };
+        self.modify_device(name, flags, detach, xml, Some(event))
+    }
+
+    fn update_device(&self, name: &str, xml: &str, flags: AffectFlags) -> Result<(), String> {
+        self.modify_device(name, flags, update, xml, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_testdriver_states() {
+        let conn = testdriver();
+        let doms = conn.list_domains().unwrap();
+        assert!(doms.iter().any(|d| d.name == "test-many-devices"));
+        let info = conn.lookup_domain("test").unwrap();
+        assert_eq!(info.state, DomainState::Running);
+        assert_eq!(info.id, Some(1));
+        assert_eq!(conn.lookup_domain("1").unwrap().name, "test");
+        let info = conn.lookup_domain("test-state-shutoff").unwrap();
+        assert_eq!(info.state, DomainState::Shutoff);
+        assert!(
+            !conn
+                .lookup_domain("test-state-transient")
+                .unwrap()
+                .persistent
+        );
+        assert!(conn.has_managed_save("test-state-managedsave").unwrap());
+
+        let xml = conn.domain_xml("test-state-shutoff", true).unwrap();
+        assert!(!xml.contains("test:"));
+        assert!(xml.contains("<uuid>"));
+        assert!(conn.lookup_domain("idontexist").is_err());
+    }
+
+    #[test]
+    fn test_all_domain_stats() {
+        let conn = testdriver();
+        assert_eq!(conn.host_cpu_count().unwrap(), 4);
+        let find = |stats: &[DomainStats], name: &str| {
+            stats.iter().find(|s| s.name == name).unwrap().clone()
+        };
+        let first = conn.all_domain_stats(StatsFlags::all()).unwrap();
+        let second = conn.all_domain_stats(StatsFlags::all()).unwrap();
+        assert_eq!(first.len(), conn.list_domains().unwrap().len());
+
+        let (a, b) = (find(&first, "test"), find(&second, "test"));
+        assert_eq!(b.state, DomainState::Running);
+        assert!(b.cpu_time > a.cpu_time);
+        assert!(b.balloon_current > 0);
+        assert!(b.balloon_unused.unwrap() < b.balloon_current);
+
+        let off = find(&second, "test-state-shutoff");
+        assert_eq!(off.state, DomainState::Shutoff);
+        assert_eq!(off.cpu_time, 0);
+
+        // Only the requested groups are filled in
+        let cpu_only = StatsFlags {
+            cpu: true,
+            ..Default::default()
+        };
+        let stats = conn.all_domain_stats(cpu_only).unwrap();
+        let many = find(&stats, "test-many-devices");
+        assert!(many.cpu_time > 0);
+        assert_eq!((many.balloon_current, many.disk_rd_bytes), (0, 0));
+    }
+
+    #[test]
+    fn test_define_start_hotplug() {
+        let conn = TestConnection::open("test:///default").unwrap();
+        let xml = conn.domain_xml("test", true).unwrap();
+        let newxml = xml
+            .replace("<name>test</name>", "<name>test-new</name>")
+            .replace(
+                "6695eb01-f6a4-8304-79aa-97f2502e193f",
+                "6695eb01-f6a4-8304-79aa-97f2502e193e",
+            );
+        let info = conn.define_xml(&newxml).unwrap();
+        assert_eq!(info.state, DomainState::Shutoff);
+        assert!(conn.define_xml(&xml.replace(">test<", ">clash<")).is_err());
+
+        let dev = "<interface type=\"network\">\n  <mac address=\"52:54:00:11:22:33\"/>\n  <source network=\"default\"/>\n</interface>";
+        assert!(
+            conn.attach_device("test-new", dev, AffectFlags::Live)
+                .is_err()
+        );
+        conn.start_domain("test-new").unwrap();
+        conn.attach_device("test-new", dev, AffectFlags::Live)
+            .unwrap();
+        assert!(
+            conn.domain_xml("test-new", false)
+                .unwrap()
+                .contains("52:54:00:11:22:33")
+        );
+        assert!(
+            !conn
+                .domain_xml("test-new", true)
+                .unwrap()
+                .contains("52:54:00:11:22:33")
+        );
+        conn.detach_device(
+            "test-new",
+            "<interface type='network'><mac address='52:54:00:11:22:33'/></interface>",
+            AffectFlags::Live,
+        )
+        .unwrap();
+        assert!(
+            !conn
+                .domain_xml("test-new", false)
+                .unwrap()
+                .contains("52:54:00:11:22:33")
+        );
+
+        conn.undefine_domain("test-new").unwrap();
+        assert!(!conn.lookup_domain("test-new").unwrap().persistent);
+        conn.destroy_domain("test-new").unwrap();
+        assert!(conn.lookup_domain("test-new").is_err());
+    }
+
+    #[test]
+    fn test_domcaps_uri_option() {
+        let conn = TestConnection::open("test:///default").unwrap();
+        let caps = conn.domain_capabilities(None, None, None, None).unwrap();
+        assert!(caps.contains("<domainCapabilities>"));
+        let path = format!(
+            "{}/../tests/data/capabilities/kvm-x86_64-domcaps-latest.xml",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let conn = TestConnection::open(&format!("test:///default,domcaps={}", path)).unwrap();
+        let caps = conn.domain_capabilities(None, None, None, None).unwrap();
+        assert!(caps.contains("Skylake-Client-noTSX-IBRS"));
+        assert!(TestConnection::open("test:///default,bogus=1").is_err());
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let conn = testdriver();
+        let name = "test-snapshots";
+        let snaps = conn.list_snapshots(name).unwrap();
+        assert_eq!(snaps.len(), 10);
+        let current = conn.current_snapshot(name).unwrap();
+        assert_eq!(current.as_deref(), Some("internal-root-child1&"));
+
+        let flags = SnapshotFlags::default();
+        let xml = "<domainsnapshot><name>new</name></domainsnapshot>";
+        assert_eq!(conn.create_snapshot(name, xml, flags).unwrap(), "new");
+        assert!(conn.create_snapshot(name, xml, flags).is_err());
+        let quiesce = SnapshotFlags {
+            quiesce: true,
+            ..flags
+        };
+        assert!(conn.create_snapshot(name, xml, quiesce).is_err());
+        let snap = DomainSnapshot::parse(conn.list_snapshots(name).unwrap().last().unwrap());
+        let snap = snap.unwrap();
+        assert_eq!(snap.parent_name(), Some("internal-root-child1&"));
+        assert_eq!(snap.state.as_deref(), Some("running"));
+        assert_eq!(snap.memory_type(), Some("internal"));
+        assert!(conn.domain_screenshot(name).unwrap().1.starts_with(b"P6"));
+
+        conn.revert_snapshot(name, "offline-root").unwrap();
+        assert_eq!(
+            conn.lookup_domain(name).unwrap().state,
+            DomainState::Shutoff
+        );
+        assert!(conn.domain_screenshot(name).is_err());
+        conn.delete_snapshot(name, "offline-root", false).unwrap();
+        assert_eq!(conn.current_snapshot(name).unwrap(), None);
+        conn.delete_snapshot(name, "internal-root", true).unwrap();
+        let names: Vec<String> = conn
+            .list_snapshots(name)
+            .unwrap()
+            .iter()
+            .map(|x| DomainSnapshot::parse(x).unwrap().name().to_string())
+            .collect();
+        assert!(
+            !names
+                .iter()
+                .any(|n| n.starts_with("internal-root") || n == "new")
+        );
+        assert!(names.iter().any(|n| n == "offline-root-child1"));
+    }
+}
@@ -0,0 +1,983 @@
+// Connection backend that drives the virsh command line tool
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Until there are libvirt bindings, real hypervisors are reached by
+//! running `virsh -c URI ...`. XML is passed through temporary files,
+//! and virsh's error output becomes the error string. Events come from
+//! long running `virsh event --loop` style processes.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{
+    AffectFlags, ConnEvent, Connection, DhcpLease, DomainInfo, DomainState, DomainStats,
+    EventSubscription, Lifecycle, ObjectInfo, ObjectKind, SnapshotFlags, StatsFlags,
+};
+use crate::tmpfile::TempFile;
+
+/// How long an event process gets to fail registering its callback
+const EVENT_STARTUP: Duration = Duration::from_millis(500);
+
+/// Connection that runs virsh for every operation
+#[derive(Debug, Clone)]
+pub struct VirshConnection {
+    uri: String,
+    /// virsh binary to run, overridable for tests
+    virsh: String,
+}
+
+impl VirshConnection {
+    /// Open a connection. Without a URI, virsh's default is used and
+    /// queried with `virsh uri`.
+    pub fn open(uri: Option<&str>) -> Result<Self, String> {
+        let virsh = std::env::var("VIRT_MANAGER_VIRSH").unwrap_or_else(|_| "virsh".to_string());
+        let mut conn = Self {
+            uri: uri.unwrap_or_default().to_string(),
+            virsh,
+        };
+        if conn.uri.is_empty() {
+            conn.uri = conn.run(&["uri"])?.trim().to_string();
+        }
+        Ok(conn)
+    }
+
+    /// virsh connected to our URI
+    fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.virsh);
+        if !self.uri.is_empty() {
+            cmd.arg("-c").arg(&self.uri);
+        }
+        cmd
+    }
+
+    fn run(&self, args: &[&str]) -> Result<String, String> {
+        let mut cmd = self.command();
+        cmd.arg("-q").args(args);
+        let out = cmd
+            .output()
+            .map_err(|e| format!("Failed to run '{}': {}", self.virsh, e))?;
+        if !out.status.success() {
+            let err = String::from_utf8_lossy(&out.stderr);
+            let err = err
+                .lines()
+                .map(|l| l.trim_start_matches("error: ").trim())
+                .filter(|l| !l.is_empty())
+                .collect::<Vec<_>>()
+                .join(": ");
+            return Err(if err.is_empty() {
+                format!("virsh {} failed", args.join(" "))
+            } else {
+                err
+            });
+        }
+        Ok(String::from_utf8_lossy(&out.stdout).to_string())
+    }
+
+    /// Run a virsh command that takes an XML file argument
+    fn run_with_xml(&self, args: &[&str], xml: &str, trailing: &[&str]) -> Result<String, String> {
+        let mut tmp = TempFile::new("virt-manager-", ".xml")
+            .map_err(|e| format!("Failed to create temporary file: {}", e))?;
+        tmp.file
+            .write_all(xml.as_bytes())
+            .map_err(|e| format!("Failed to write temporary file: {}", e))?;
+        let path = tmp.path().to_string_lossy().to_string();
+        let mut full: Vec<&str> = args.to_vec();
+        full.push(&path);
+        full.extend_from_slice(trailing);
+        self.run(&full)
+    }
+
+    /// Objects of `virsh net-list` or `pool-list`, which only tell the
+    /// inactive ones apart
+    fn list_objects(&self, cmd: &str) -> Result<Vec<ObjectInfo>, String> {
+        let names = |flag: &str| -> Result<Vec<String>, String> {
+            Ok(self
+                .run(&[cmd, flag, "--name"])?
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_string)
+                .collect())
+        };
+        let inactive = names("--inactive")?;
+        Ok(names("--all")?
+            .into_iter()
+            .map(|name| ObjectInfo {
+                active: !inactive.contains(&name),
+                name,
+            })
+            .collect())
+    }
+
+    /// The `Key: value` lines of `virsh net-info` and the like
+    fn info_value(&self, args: &[&str], key: &str) -> Result<Option<String>, String> {
+        Ok(self
+            .run(args)?
+            .lines()
+            .filter_map(|l| l.split_once(':'))
+            .find(|(k, _)| k.trim() == key)
+            .map(|(_, v)| v.trim().to_string()))
+    }
+
+    fn name_from_xml(xml: &str) -> Result<String, String> {
+        crate::guest::Guest::parse(xml)?
+            .name()
+            .ok_or_else(|| "XML error: missing domain name information".to_string())
+    }
+}
+
+fn affect_args(flags: AffectFlags) -> &'static [&'static str] {
+    match flags {
+        AffectFlags::Live => &["--live"],
+        AffectFlags::Config => &["--config"],
+        AffectFlags::Both => &["--live", "--config"],
+    }
+}
+
+/// Image type of `virsh screenshot` output, which quiet mode doesn't print
+fn screenshot_mime(data: &[u8]) -> Result<&'static str, String> {
+    if data.starts_with(b"P6") {
+        Ok("image/x-portable-pixmap")
+    } else if data.starts_with(b"\x89PNG") {
+        Ok("image/png")
+    } else {
+        Err("Unknown screenshot image format".to_string())
+    }
+}
+
+/// Map `virsh dominfo` state strings back to the enum
+fn parse_state(s: &str) -> DomainState {
+    match s {
+        "running" => DomainState::Running,
+        "idle" | "blocked" => DomainState::Blocked,
+        "paused" => DomainState::Paused,
+        "in shutdown" => DomainState::Shutdown,
+        "shut off" => DomainState::Shutoff,
+        "crashed" => DomainState::Crashed,
+        "pmsuspended" => DomainState::PmSuspended,
+        _ => DomainState::NoState,
+    }
+}
+
+/// Parse `virsh dominfo` output
+fn parse_dominfo(out: &str) -> Result<DomainInfo, String> {
+    let mut info = DomainInfo {
+        name: String::new(),
+        uuid: String::new(),
+        id: None,
+        state: DomainState::NoState,
+        persistent: true,
+    };
+    for line in out.lines() {
+        let Some((key, val)) = line.split_once(':') else {
+            continue;
+        };
+        let val = val.trim();
+        match key.trim() {
+            "Id" => info.id = val.parse().ok(),
+            "Name" => info.name = val.to_string(),
+            "UUID" => info.uuid = val.to_string(),
+            "State" => info.state = parse_state(val),
+            "Persistent" => info.persistent = val == "yes",
+            _ => {}
+        }
+    }
+    if info.name.is_empty() {
+        return Err(format!("Unexpected virsh dominfo output: {}", out));
+    }
+    Ok(info)
+}
+
+/// Parse `virsh domstats` output: a `Domain: 'name'` line per domain
+/// followed by its `key=value` lines
+fn parse_domstats(out: &str) -> Result<Vec<DomainStats>, String> {
+    let mut ret: Vec<DomainStats> = vec![];
+    for line in out.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(name) = line.strip_prefix("Domain:") {
+            ret.push(DomainStats {
+                name: name.trim().trim_matches('\'').to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let (Some(stats), Some((key, val))) = (ret.last_mut(), line.split_once('=')) else {
+            return Err(format!("Unexpected virsh domstats output: {}", line));
+        };
+        let Ok(val) = val.parse::<u64>() else {
+            continue;
+        };
+        let parts: Vec<&str> = key.split('.').collect();
+        match parts[..] {
+            ["state", "state"] => stats.state = DomainState::from_id(val as u32),
+            ["cpu", "time"] => stats.cpu_time = val,
+            ["vcpu", "current"] => stats.vcpus = val as u32,
+            ["balloon", "current"] => stats.balloon_current = val,
+            ["balloon", "unused"] => stats.balloon_unused = Some(val),
+            ["block", _, "rd", "bytes"] => stats.disk_rd_bytes += val,
+            ["block", _, "wr", "bytes"] => stats.disk_wr_bytes += val,
+            ["net", _, "rx", "bytes"] => stats.net_rx_bytes += val,
+            ["net", _, "tx", "bytes"] => stats.net_tx_bytes += val,
+            _ => {}
+        }
+    }
+    Ok(ret)
+}
+
+/// Parse an event line of `virsh event` and friends, like
+/// `event 'lifecycle' for domain 'fedora': Started Booted` or
+/// `event 'lifecycle' for network default: Stopped`, optionally after a
+/// timestamp. Events we have no use for give None.
+fn parse_event(kind: ObjectKind, line: &str) -> Option<ConnEvent> {
+    let rest = &line[line.find("event '")? + "event '".len()..];
+    let (evtype, rest) = rest.split_once('\'')?;
+    let rest = rest
+        .strip_prefix(" for ")?
+        .strip_prefix(kind.label())?
+        .trim_start();
+    let (name, detail) = match rest.strip_prefix('\'') {
+        Some(quoted) => {
+            let (name, detail) = quoted.split_once('\'')?;
+            (name, detail.strip_prefix(':')?)
+        }
+        None => rest.split_once(": ")?,
+    };
+    let (name, detail) = (name.to_string(), detail.trim());
+    match evtype {
+        "lifecycle" => {
+            let event = Lifecycle::from_name(detail.split_whitespace().next()?)?;
+            Some(ConnEvent::Lifecycle(kind, name, event))
+        }
+        "agent-lifecycle" => {
+            let state = detail.strip_prefix("state: '")?.split('\'').next()?;
+            Some(ConnEvent::AgentLifecycle {
+                domain: name,
+                connected: state == "connected",
+            })
+        }
+        "device-added" => Some(ConnEvent::DeviceAdded {
+            domain: name,
+            alias: detail.to_string(),
+        }),
+        "device-removed" => Some(ConnEvent::DeviceRemoved {
+            domain: name,
+            alias: detail.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Seconds since the epoch of a local time like `2025-01-01 10:00:00`
+fn parse_local_time(date: &str, time: &str) -> Option<i64> {
+    let d: Vec<i32> = date
+        .split('-')
+        .map(|v| v.parse().ok())
+        .collect::<Option<_>>()?;
+    let t: Vec<i32> = time
+        .split(':')
+        .map(|v| v.parse().ok())
+        .collect::<Option<_>>()?;
+    let (&[year, mon, mday], &[hour, min, sec]) = (&d[..], &t[..]) else {
+        return None;
+    };
+    // SAFETY: mktime only reads and normalizes the passed tm
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = year - 1900;
+    tm.tm_mon = mon - 1;
+    tm.tm_mday = mday;
+    tm.tm_hour = hour;
+    tm.tm_min = min;
+    tm.tm_sec = sec;
+    tm.tm_isdst = -1;
+    let ret = unsafe { libc::mktime(&mut tm) };
+    (ret != -1).then_some(ret as i64)
+}
+
+/// Parse the table of `virsh net-dhcp-leases`
+fn parse_dhcp_leases(out: &str) -> Result<Vec<DhcpLease>, String> {
+    let mut ret = vec![];
+    let rows = out
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with("Expiry") && !l.starts_with('-'));
+    for line in rows {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [date, time, mac, family, ipaddr, hostname, clientid, ..] = fields[..] else {
+            return Err(format!("Unexpected virsh net-dhcp-leases output: {}", line));
+        };
+        let (ipaddr, prefix) = ipaddr.split_once('/').unwrap_or((ipaddr, ""));
+        let optional = |v: &str| (v != "-").then(|| v.to_string());
+        ret.push(DhcpLease {
+            expirytime: parse_local_time(date, time).unwrap_or_default(),
+            iface: String::new(),
+            family: family.to_string(),
+            mac: mac.to_string(),
+            ipaddr: ipaddr.to_string(),
+            prefix: prefix.parse().unwrap_or_default(),
+            hostname: optional(hostname),
+            clientid: optional(clientid),
+        });
+    }
+    Ok(ret)
+}
+
+impl Connection for VirshConnection {
+    fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    fn list_domains(&self) -> Result<Vec<DomainInfo>, String> {
+        self.run(&["list", "--all", "--name"])?
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|name| self.lookup_domain(name))
+            .collect()
+    }
+
+    fn lookup_domain(&self, key: &str) -> Result<DomainInfo, String> {
+        parse_dominfo(&self.run(&["dominfo", key])?)
+    }
+
+    fn domain_xml(&self, name: &str, inactive: bool) -> Result<String, String> {
+        let mut args = vec!["dumpxml", "--security-info"];
+        if inactive {
+            args.push("--inactive");
+        }
+        args.push(name);
+        self.run(&args)
+    }
+
+    fn define_xml(&self, xml: &str) -> Result<DomainInfo, String> {
+        let name = Self::name_from_xml(xml)?;
+        self.run_with_xml(&["define"], xml, &[])?;
+        self.lookup_domain(&name)
+    }
+
+    fn create_xml(&self, xml: &str) -> Result<DomainInfo, String> {
+        let name = Self::name_from_xml(xml)?;
+        self.run_with_xml(&["create"], xml, &[])?;
+        self.lookup_domain(&name)
+    }
+
+    fn start_domain(&self, name: &str) -> Result<(), String> {
+        self.run(&["start", name]).map(|_| ())
+    }
+
+    fn destroy_domain(&self, name: &str) -> Result<(), String> {
+        self.run(&["destroy", name]).map(|_| ())
+    }
+
+    fn undefine_domain(&self, name: &str) -> Result<(), String> {
+        self.run(&["undefine", name]).map(|_| ())
+    }
+
+    fn domain_autostart(&self, name: &str) -> Result<bool, String> {
+        let out = self.run(&["dominfo", name])?;
+        Ok(out
+            .lines()
+            .filter_map(|l| l.split_once(':'))
+            .any(|(key, val)| key.trim() == "Autostart" && val.trim() == "enable"))
+    }
+
+    fn set_domain_autostart(&self, name: &str, autostart: bool) -> Result<(), String> {
+        let args: &[&str] = if autostart {
+            &["autostart", name]
+        } else {
+            &["autostart", "--disable", name]
+        };
+        self.run(args).map(|_| ())
+    }
+
+    fn domain_capabilities(
+        &self,
+        emulator: Option<&str>,
+        arch: Option<&str>,
+        machine: Option<&str>,
+        virttype: Option<&str>,
+    ) -> Result<String, String> {
+        let mut args = vec!["domcapabilities"];
+        for (flag, val) in [
+            ("--emulatorbin", emulator),
+            ("--arch", arch),
+            ("--machine", machine),
+            ("--virttype", virttype),
+        ] {
+            if let Some(val) = val {
+                args.extend([flag, val]);
+            }
+        }
+        self.run(&args)
+    }
+
+    fn list_snapshots(&self, name: &str) -> Result<Vec<String>, String> {
+        self.run(&["snapshot-list", name, "--name"])?
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|snap| self.run(&["snapshot-dumpxml", name, snap]))
+            .collect()
+    }
+
+    fn current_snapshot(&self, name: &str) -> Result<Option<String>, String> {
+        match self.run(&["snapshot-current", name, "--name"]) {
+            Ok(out) => Ok(Some(out.trim().to_string())),
+            Err(e) if e.contains("no current snapshot") => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn create_snapshot(
+        &self,
+        name: &str,
+        xml: &str,
+        flags: SnapshotFlags,
+    ) -> Result<String, String> {
+        let mut trailing = vec![];
+        for (set, arg) in [
+            (flags.redefine, "--redefine"),
+            (flags.disk_only, "--disk-only"),
+            (flags.quiesce, "--quiesce"),
+        ] {
+            if set {
+                trailing.push(arg);
+            }
+        }
+        self.run_with_xml(&["snapshot-create", name, "--xmlfile"], xml, &trailing)?;
+        // Quiet mode doesn't report the name libvirt picked
+        match crate::snapshot::DomainSnapshot::parse(xml)?.name {
+            Some(snap) => Ok(snap),
+            None => self
+                .current_snapshot(name)?
+                .ok_or_else(|| "Created snapshot is not the current one".to_string()),
+        }
+    }
+
+    fn revert_snapshot(&self, name: &str, snapshot: &str) -> Result<(), String> {
+        self.run(&["snapshot-revert", name, snapshot]).map(|_| ())
+    }
+
+    fn delete_snapshot(&self, name: &str, snapshot: &str, children: bool) -> Result<(), String> {
+        let mut args = vec!["snapshot-delete", name, snapshot];
+        if children {
+            args.push("--children");
+        }
+        self.run(&args).map(|_| ())
+    }
+
+    fn all_domain_stats(&self, flags: StatsFlags) -> Result<Vec<DomainStats>, String> {
+        let mut args = vec!["domstats", "--state"];
+        if flags.cpu {
+            args.extend(["--cpu-total", "--vcpu"]);
+        }
+        if flags.memory {
+            args.push("--balloon");
+        }
+        if flags.disk {
+            args.push("--block");
+        }
+        if flags.net {
+            args.push("--interface");
+        }
+        parse_domstats(&self.run(&args)?)
+    }
+
+    fn host_cpu_count(&self) -> Result<u32, String> {
+        let out = self.run(&["nodeinfo"])?;
+        out.lines()
+            .find_map(|l| l.strip_prefix("CPU(s):"))
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(|| format!("Unexpected virsh nodeinfo output: {}", out))
+    }
+
+    fn list_networks(&self) -> Result<Vec<ObjectInfo>, String> {
+        self.list_objects("net-list")
+    }
+
+    fn network_xml(&self, name: &str, inactive: bool) -> Result<String, String> {
+        let mut args = vec!["net-dumpxml", name];
+        if inactive {
+            args.push("--inactive");
+        }
+        self.run(&args)
+    }
+
+    fn define_network(&self, xml: &str) -> Result<ObjectInfo, String> {
+        let name = crate::network::Network::parse(xml)?.name;
+        self.run_with_xml(&["net-define"], xml, &[])?;
+        let active = self.info_value(&["net-info", &name], "Active")?;
+        Ok(ObjectInfo {
+            name,
+            active: active.as_deref() == Some("yes"),
+        })
+    }
+
+    fn start_network(&self, name: &str) -> Result<(), String> {
+        self.run(&["net-start", name]).map(|_| ())
+    }
+
+    fn destroy_network(&self, name: &str) -> Result<(), String> {
+        self.run(&["net-destroy", name]).map(|_| ())
+    }
+
+    fn undefine_network(&self, name: &str) -> Result<(), String> {
+        self.run(&["net-undefine", name]).map(|_| ())
+    }
+
+    fn network_autostart(&self, name: &str) -> Result<bool, String> {
+        let autostart = self.info_value(&["net-info", name], "Autostart")?;
+        Ok(autostart.as_deref() == Some("yes"))
+    }
+
+    fn set_network_autostart(&self, name: &str, autostart: bool) -> Result<(), String> {
+        let mut args = vec!["net-autostart", name];
+        if !autostart {
+            args.push("--disable");
+        }
+        self.run(&args).map(|_| ())
+    }
+
+    fn network_dhcp_leases(&self, name: &str) -> Result<Vec<DhcpLease>, String> {
+        let mut leases = parse_dhcp_leases(&self.run(&["net-dhcp-leases", name])?)?;
+        let bridge = self
+            .network_xml(name, false)
+            .and_then(|xml| crate::network::Network::parse(&xml))
+            .ok()
+            .and_then(|net| net.bridge_name().map(str::to_string))
+            .unwrap_or_default();
+        for lease in &mut leases {
+            lease.iface = bridge.clone();
+        }
+        Ok(leases)
+    }
+
+    fn list_pools(&self) -> Result<Vec<ObjectInfo>, String> {
+        self.list_objects("pool-list")
+    }
+
+    fn pool_xml(&self, name: &str, inactive: bool) -> Result<String, String> {
+        let mut args = vec!["pool-dumpxml", name];
+        if inactive {
+            args.push("--inactive");
+        }
+        self.run(&args)
+    }
+
+    fn define_pool(&self, xml: &str) -> Result<ObjectInfo, String> {
+        let name = crate::storage::StoragePool::parse(xml)?.name;
+        self.run_with_xml(&["pool-define"], xml, &[])?;
+        let state = self.info_value(&["pool-info", &name], "State")?;
+        Ok(ObjectInfo {
+            name,
+            active: state.is_some_and(|s| s != "inactive"),
+        })
+    }
+
+    fn build_pool(&self, name: &str) -> Result<(), String> {
+        self.run(&["pool-build", name]).map(|_| ())
+    }
+
+    fn start_pool(&self, name: &str) -> Result<(), String> {
+        self.run(&["pool-start", name]).map(|_| ())
+    }
+
+    fn destroy_pool(&self, name: &str) -> Result<(), String> {
+        self.run(&["pool-destroy", name]).map(|_| ())
+    }
+
+    fn undefine_pool(&self, name: &str) -> Result<(), String> {
+        self.run(&["pool-undefine", name]).map(|_| ())
+    }
+
+    fn refresh_pool(&self, name: &str) -> Result<(), String> {
+        self.run(&["pool-refresh", name]).map(|_| ())
+    }
+
+    fn pool_autostart(&self, name: &str) -> Result<bool, String> {
+        let autostart = self.info_value(&["pool-info", name], "Autostart")?;
+        Ok(autostart.as_deref() == Some("yes"))
+    }
+
+    fn set_pool_autostart(&self, name: &str, autostart: bool) -> Result<(), String> {
+        let mut args = vec!["pool-autostart", name];
+        if !autostart {
+            args.push("--disable");
+        }
+        self.run(&args).map(|_| ())
+    }
+
+    fn list_volumes(&self, pool: &str) -> Result<Vec<String>, String> {
+        Ok(self
+            .run(&["vol-list", pool, "--name"])?
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn volume_xml(&self, pool: &str, name: &str) -> Result<String, String> {
+        self.run(&["vol-dumpxml", "--pool", pool, name])
+    }
+
+    fn create_volume(&self, pool: &str, xml: &str) -> Result<(), String> {
+        self.run_with_xml(&["vol-create", pool], xml, &[])
+            .map(|_| ())
+    }
+
+    fn clone_volume(
+        &self,
+        pool: &str,
+        xml: &str,
+        input_pool: &str,
+        input_vol: &str,
+    ) -> Result<(), String> {
+        self.run_with_xml(
+            &["vol-create-from", pool],
+            xml,
+            &["--inputpool", input_pool, input_vol],
+        )
+        .map(|_| ())
+    }
+
+    fn delete_volume(&self, pool: &str, name: &str) -> Result<(), String> {
+        self.run(&["vol-delete", "--pool", pool, name]).map(|_| ())
+    }
+
+    fn list_nodedevs(&self) -> Result<Vec<ObjectInfo>, String> {
+        Ok(self
+            .run(&["nodedev-list"])?
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|name| ObjectInfo {
+                name: name.to_string(),
+                active: true,
+            })
+            .collect())
+    }
+
+    fn nodedev_xml(&self, name: &str) -> Result<String, String> {
+        self.run(&["nodedev-dumpxml", name])
+    }
+
+    fn subscribe_events(
+        &self,
+        kind: ObjectKind,
+        tx: Sender<ConnEvent>,
+    ) -> Result<EventSubscription, String> {
+        let args: &[&str] = match kind {
+            ObjectKind::Domain => &["event", "--all", "--loop"],
+            ObjectKind::Network => &["net-event", "--event", "lifecycle", "--loop"],
+            ObjectKind::Pool => &["pool-event", "--event", "lifecycle", "--loop"],
+            ObjectKind::NodeDevice => &["nodedev-event", "--event", "lifecycle", "--loop"],
+        };
+        let err = |e: std::io::Error| format!("Failed to run '{}': {}", self.virsh, e);
+        let mut child = self
+            .command()
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(err)?;
+        // Connection errors and event types the server lacks make virsh
+        // exit right away
+        let started = Instant::now();
+        while started.elapsed() < EVENT_STARTUP {
+            if let Some(status) = child.try_wait().map_err(err)? {
+                let mut msg = String::new();
+                if let Some(mut stderr) = child.stderr.take() {
+                    let _ = stderr.read_to_string(&mut msg);
+                }
+                let msg = msg.trim().trim_start_matches("error: ").to_string();
+                return Err(if msg.is_empty() {
+                    format!("virsh {} failed: {}", args[0], status)
+                } else {
+                    msg
+                });
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let stdout = child.stdout.take().expect("stdout is piped");
+        // Hold on to stderr, so late errors don't hit a closed pipe
+        let stderr = child.stderr.take();
+        let child = Arc::new(Mutex::new(child));
+        let reader_child = child.clone();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if let Some(event) = parse_event(kind, &line)
+                    && tx.send(event).is_err()
+                {
+                    break;
+                }
+            }
+            // Dropping tx tells the watcher to poll, if it's still there
+            let status = {
+                let mut child = reader_child.lock().unwrap_or_else(|e| e.into_inner());
+                let _ = child.kill();
+                child.wait()
+            };
+            let mut msg = String::new();
+            if let Some(mut stderr) = stderr {
+                let _ = stderr.read_to_string(&mut msg);
+            }
+            log::debug!(
+                "Stopped watching {} events ({:?}): {}",
+                kind.label(),
+                status,
+                msg.trim()
+            );
+        });
+        // virsh blocks waiting for events, so it has to be killed for the
+        // reader to notice the subscription ended
+        Ok(EventSubscription::on_drop(move || {
+            let _ = child.lock().unwrap_or_else(|e| e.into_inner()).kill();
+        }))
+    }
+
+    fn domain_screenshot(&self, name: &str) -> Result<(String, Vec<u8>), String> {
+        let tmp = TempFile::new("virt-manager-screenshot-", "")
+            .map_err(|e| format!("Failed to create temporary file: {}", e))?;
+        let path = tmp.path().to_string_lossy().to_string();
+        self.run(&["screenshot", name, "--file", &path])?;
+        let data = std::fs::read(&path).map_err(|e| format!("Failed to read screenshot: {}", e))?;
+        Ok((screenshot_mime(&data)?.to_string(), data))
+    }
+
+    fn open_console(&self, name: &str, devname: Option<&str>) -> Result<OwnedFd, String> {
+        // virsh only runs a console on a terminal, so give it a pty
+        let (mut master, mut slave) = (-1, -1);
+        // SAFETY: master and slave are valid out-params for the new fds,
+        // and the name, termios and winsize pointers may be null
+        let ret = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+            )
+        };
+        if ret != 0 {
+            return Err(format!(
+                "Failed to open a pty: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        // SAFETY: openpty succeeded, so both fds are freshly opened, and
+        // nothing else owns them
+        let (master, slave) =
+            unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        let err = |e: std::io::Error| format!("Failed to run '{}': {}", self.virsh, e);
+        let mut cmd = self.command();
+        cmd.args(["console", "--force"]);
+        if let Some(devname) = devname {
+            cmd.args(["--devname", devname]);
+        }
+        cmd.arg(name)
+            .stdin(slave.try_clone().map_err(err)?)
+            .stdout(slave.try_clone().map_err(err)?)
+            .stderr(slave);
+        // SAFETY: the hook only makes async-signal-safe calls
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut child = cmd.spawn().map_err(err)?;
+        std::thread::spawn(move || {
+            let _ = child.wait();
+        });
+        Ok(master)
+    }
+
+    fn attach_device(&self, name: &str, xml: &str, flags: AffectFlags) -> Result<(), String> {
+        self.run_with_xml(&["attach-device", name], xml, affect_args(flags))
+            .map(|_| ())
+    }
+
+    fn detach_device(&self, name: &str, xml: &str, flags: AffectFlags) -> Result<(), String> {
+        self.run_with_xml(&["detach-device", name], xml, affect_args(flags))
+            .map(|_| ())
+    }
+
+    fn update_device(&self, name: &str, xml: &str, flags: AffectFlags) -> Result<(), String> {
+        self.run_with_xml(&["update-device", name], xml, affect_args(flags))
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dominfo() {
+        let out = "Id:             3\nName:           fedora\nUUID:           4a64cc71-19c4-2fd0-2323-3050941ea3c3\nOS Type:        hvm\nState:          running\nCPU(s):         2\nPersistent:     no\nAutostart:      disable\n";
+        let info = parse_dominfo(out).unwrap();
+        assert_eq!(info.id, Some(3));
+        assert_eq!(info.name, "fedora");
+        assert_eq!(info.state, DomainState::Running);
+        assert!(!info.persistent);
+
+        let info =
+            parse_dominfo("Id:             -\nName:           off\nState:          shut off\n")
+                .unwrap();
+        assert_eq!(info.id, None);
+        assert_eq!(info.state, DomainState::Shutoff);
+    }
+
+    #[test]
+    fn test_parse_dhcp_leases() {
+        let out = " Expiry Time           MAC address         Protocol   IP address           Hostname   Client ID or DUID
+-------------------------------------------------------------------------------------------------------------
+ 2025-03-01 12:30:00   52:54:00:aa:bb:cc   ipv4       192.168.122.50/24    fedora     01:52:54:00:aa:bb:cc
+ 2025-03-01 12:31:00   52:54:00:aa:bb:dd   ipv6       fd00::10/64          -          -
+";
+        let leases = parse_dhcp_leases(out).unwrap();
+        assert_eq!(leases.len(), 2);
+        assert_eq!(leases[0].ipaddr, "192.168.122.50");
+        assert_eq!(leases[0].prefix, 24);
+        assert_eq!(leases[0].hostname.as_deref(), Some("fedora"));
+        assert_eq!(leases[1].expirytime - leases[0].expirytime, 60);
+        assert_eq!(
+            (leases[1].hostname.clone(), leases[1].clientid.clone()),
+            (None, None)
+        );
+        assert!(parse_dhcp_leases("bogus line").is_err());
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let domain = |line| parse_event(ObjectKind::Domain, line);
+        assert_eq!(
+            domain("event 'lifecycle' for domain 'my vm': Started Booted"),
+            Some(ConnEvent::Lifecycle(
+                ObjectKind::Domain,
+                "my vm".into(),
+                Lifecycle::Started
+            ))
+        );
+        assert_eq!(
+            domain(
+                "2025-01-01 10:00:00.123+0000: event 'agent-lifecycle' for domain 'f': state: 'disconnected' reason: 'domain started'"
+            ),
+            Some(ConnEvent::AgentLifecycle {
+                domain: "f".into(),
+                connected: false
+            })
+        );
+        assert_eq!(
+            domain("event 'device-removed' for domain 'f': virtio-disk1"),
+            Some(ConnEvent::DeviceRemoved {
+                domain: "f".into(),
+                alias: "virtio-disk1".into()
+            })
+        );
+        assert_eq!(domain("event 'reboot' for domain 'f'"), None);
+        assert_eq!(domain("events received: 3"), None);
+        assert_eq!(
+            parse_event(
+                ObjectKind::Pool,
+                "event 'lifecycle' for storage pool default: Stopped"
+            ),
+            Some(ConnEvent::Lifecycle(
+                ObjectKind::Pool,
+                "default".into(),
+                Lifecycle::Stopped
+            ))
+        );
+        assert_eq!(
+            parse_event(
+                ObjectKind::Network,
+                "event 'lifecycle' for storage pool default: Stopped"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_domstats() {
+        let out = "Domain: 'fedora'
+  state.state=1
+  state.reason=1
+  cpu.time=2418823000
+  vcpu.current=2
+  vcpu.maximum=2
+  vcpu.0.state=1
+  balloon.current=2097152
+  balloon.unused=1048576
+  block.count=2
+  block.0.name=vda
+  block.0.rd.bytes=1000
+  block.0.wr.bytes=10
+  block.1.name=sda
+  block.1.rd.bytes=24
+  net.count=1
+  net.0.name=vnet0
+  net.0.rx.bytes=512
+  net.0.tx.bytes=256
+
+Domain: 'off'
+  state.state=5
+  state.reason=1
+";
+        let stats = parse_domstats(out).unwrap();
+        assert_eq!(stats.len(), 2);
+        let fedora = &stats[0];
+        assert_eq!(fedora.name, "fedora");
+        assert_eq!(fedora.state, DomainState::Running);
+        assert_eq!((fedora.cpu_time, fedora.vcpus), (2418823000, 2));
+        assert_eq!(fedora.balloon_unused, Some(1048576));
+        assert_eq!((fedora.disk_rd_bytes, fedora.disk_wr_bytes), (1024, 10));
+        assert_eq!((fedora.net_rx_bytes, fedora.net_tx_bytes), (512, 256));
+        assert_eq!(stats[1].state, DomainState::Shutoff);
+        assert!(parse_domstats("cpu.time=1").is_err());
+    }
+
+    #[test]
+    fn test_event_process_stops() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A virsh that waits for events forever
+        let dir = tempfile::tempdir().unwrap();
+        let pidfile = dir.path().join("pid");
+        let script = dir.path().join("virsh");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho $$ > {}\nexec sleep 60\n",
+                pidfile.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let conn = VirshConnection {
+            uri: "test:///default".to_string(),
+            virsh: script.to_string_lossy().to_string(),
+        };
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let subscription = conn.subscribe_events(ObjectKind::Domain, tx).unwrap();
+        let pid = std::fs::read_to_string(&pidfile).unwrap();
+        let proc = format!("/proc/{}", pid.trim());
+        assert!(std::path::Path::new(&proc).exists());
+        drop(subscription);
+        // The reader sees the process go and hangs up
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_err());
+        assert!(!std::path::Path::new(&proc).exists());
+    }
+}
@@ -0,0 +1,311 @@
+// Live object events of a connection (port of the event handling of
+// virtManager/connection.py and of virtinst/pollhelpers.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `watch` delivers the lifecycle, agent and device events of a
+//! connection's domains, networks, pools and node devices. Each kind the
+//! backend can't report events for is polled instead, and the difference
+//! between two polls is turned into the events that explain it, so
+//! listeners never need to tell the two apart.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use iced::Subscription;
+use iced::futures::channel::mpsc::{UnboundedReceiver, unbounded};
+use iced::futures::{StreamExt, stream};
+use log::debug;
+
+use crate::connection::{ConnEvent, Connection, DomainState, Lifecycle, ObjectInfo, ObjectKind};
+
+/// How often the kinds without events are polled
+pub const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// States of the objects of one kind by name. Networks, pools and node
+/// devices are only ever Running or Shutoff.
+pub type ObjectStates = BTreeMap<String, DomainState>;
+
+/// Current states of the objects of `kind`. Listing errors give no
+/// objects, like an unsupported kind in pollhelpers.
+pub fn fetch_states(conn: &dyn Connection, kind: ObjectKind) -> ObjectStates {
+    let objects = |list: Result<Vec<ObjectInfo>, String>| {
+        list.map(|objs| {
+            objs.into_iter()
+                .map(|o| {
+                    let state = if o.active {
+                        DomainState::Running
+                    } else {
+                        DomainState::Shutoff
+                    };
+                    (o.name, state)
+                })
+                .collect()
+        })
+    };
+    let ret = match kind {
+        ObjectKind::Domain => conn
+            .list_domains()
+            .map(|doms| doms.into_iter().map(|d| (d.name, d.state)).collect()),
+        ObjectKind::Network => objects(conn.list_networks()),
+        ObjectKind::Pool => objects(conn.list_pools()),
+        ObjectKind::NodeDevice => objects(conn.list_nodedevs()),
+    };
+    ret.unwrap_or_else(|e| {
+        debug!("Unable to list all {}s: {}", kind.label(), e);
+        ObjectStates::new()
+    })
+}
+
+/// The event an object reports when its state changes from `old` to `new`
+fn state_event(old: DomainState, new: DomainState) -> Option<Lifecycle> {
+    if old == new {
+        return None;
+    }
+    Some(match new {
+        DomainState::Running | DomainState::Blocked if old == DomainState::Paused => {
+            Lifecycle::Resumed
+        }
+        DomainState::Running | DomainState::Blocked if old.is_active() => return None,
+        DomainState::Running | DomainState::Blocked => Lifecycle::Started,
+        DomainState::Paused => Lifecycle::Suspended,
+        DomainState::Shutdown => Lifecycle::Shutdown,
+        DomainState::Shutoff => Lifecycle::Stopped,
+        DomainState::Crashed => Lifecycle::Crashed,
+        DomainState::PmSuspended => Lifecycle::PmSuspended,
+        DomainState::NoState => return None,
+    })
+}
+
+/// Events explaining the change from poll `old` to poll `new`: objects
+/// that are gone were undefined, new ones defined and maybe started
+pub fn diff_events(kind: ObjectKind, old: &ObjectStates, new: &ObjectStates) -> Vec<ConnEvent> {
+    let event = |name: &str, ev| ConnEvent::Lifecycle(kind, name.to_string(), ev);
+    let (created, deleted) = match kind {
+        ObjectKind::NodeDevice => (Lifecycle::Created, Lifecycle::Deleted),
+        _ => (Lifecycle::Defined, Lifecycle::Undefined),
+    };
+    let mut ret: Vec<ConnEvent> = old
+        .keys()
+        .filter(|name| !new.contains_key(*name))
+        .map(|name| event(name, deleted))
+        .collect();
+    for (name, state) in new {
+        match old.get(name) {
+            Some(oldstate) => ret.extend(state_event(*oldstate, *state).map(|ev| event(name, ev))),
+            None => {
+                ret.push(event(name, created));
+                if kind != ObjectKind::NodeDevice && state.is_active() {
+                    ret.push(event(name, Lifecycle::Started));
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// Pass the events of `kind` from `rx` on to `tx`, then report the end
+/// of the stream with `None`
+fn forward_events(
+    kind: ObjectKind,
+    rx: mpsc::Receiver<ConnEvent>,
+    tx: mpsc::Sender<(ObjectKind, Option<ConnEvent>)>,
+) {
+    std::thread::spawn(move || {
+        for event in rx {
+            if tx.send((kind, Some(event))).is_err() {
+                return;
+            }
+        }
+        let _ = tx.send((kind, None));
+    });
+}
+
+/// Register for the events of every kind, and poll the kinds that fail
+/// or whose events stop every `interval`, until the subscription ends
+fn start_watcher(conn: Arc<dyn Connection>, interval: Duration) -> UnboundedReceiver<ConnEvent> {
+    let (tx, rx) = unbounded();
+    std::thread::spawn(move || {
+        let (etx, erx) = mpsc::channel();
+        let mut polled = vec![];
+        let mut subscriptions = vec![];
+        for kind in ObjectKind::ALL {
+            let (ktx, krx) = mpsc::channel();
+            match conn.subscribe_events(kind, ktx) {
+                Ok(subscription) => {
+                    debug!("Watching {} events of {}", kind.label(), conn.uri());
+                    subscriptions.push(subscription);
+                    forward_events(kind, krx, etx.clone());
+                }
+                Err(e) => {
+                    debug!("Polling {}s of {} instead: {}", kind.label(), conn.uri(), e);
+                    polled.push((kind, fetch_states(conn.as_ref(), kind)));
+                }
+            }
+        }
+        // Backends drop their senders once we drop the receiver
+        drop(etx);
+
+        let mut next_poll = Instant::now() + interval;
+        while !tx.is_closed() {
+            let timeout = next_poll.saturating_duration_since(Instant::now());
+            let mut events = match erx.recv_timeout(timeout) {
+                Ok((_, Some(event))) => vec![event],
+                Ok((kind, None)) => {
+                    debug!(
+                        "{} events of {} stopped, polling instead",
+                        kind.label(),
+                        conn.uri()
+                    );
+                    polled.push((kind, fetch_states(conn.as_ref(), kind)));
+                    vec![]
+                }
+                Err(RecvTimeoutError::Timeout) => vec![],
+                // No event source left
+                Err(RecvTimeoutError::Disconnected) => {
+                    std::thread::sleep(timeout);
+                    vec![]
+                }
+            };
+            if Instant::now() >= next_poll {
+                for (kind, states) in &mut polled {
+                    let current = fetch_states(conn.as_ref(), *kind);
+                    events.extend(diff_events(*kind, states, &current));
+                    *states = current;
+                }
+                next_poll = Instant::now() + interval;
+            }
+            for event in events {
+                debug!("{}: {:?}", conn.uri(), event);
+                let _ = tx.unbounded_send(event);
+            }
+        }
+        // Stop the event sources too, rather than waiting for their next
+        // event to find the receiver gone
+        drop(subscriptions);
+        debug!("Stopped watching events of {}", conn.uri());
+    });
+    rx
+}
+
+/// The object events of `conn`
+pub fn watch(conn: Arc<dyn Connection>) -> Subscription<ConnEvent> {
+    let id = ("connevents", conn.uri().to_string());
+    let events = stream::once(async move { start_watcher(conn, POLL_INTERVAL) }).flatten();
+    Subscription::run_with_id(id, events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+    use iced::futures::executor::block_on;
+
+    #[test]
+    fn test_diff_events() {
+        let conn = testdriver();
+        let old = fetch_states(&conn, ObjectKind::Domain);
+        assert_eq!(old["test-state-shutoff"], DomainState::Shutoff);
+        assert!(fetch_states(&conn, ObjectKind::Pool)["pool-dir"].is_active());
+
+        let xml = conn.domain_xml("test-state-shutoff", true).unwrap();
+        conn.undefine_domain("test-state-shutoff").unwrap();
+        conn.destroy_domain("test").unwrap();
+        conn.start_domain("test-clone").unwrap();
+        let new = fetch_states(&conn, ObjectKind::Domain);
+        let event = |name: &str, ev| ConnEvent::Lifecycle(ObjectKind::Domain, name.to_string(), ev);
+        assert_eq!(
+            diff_events(ObjectKind::Domain, &old, &new),
+            [
+                event("test-state-shutoff", Lifecycle::Undefined),
+                event("test", Lifecycle::Stopped),
+                event("test-clone", Lifecycle::Started),
+            ]
+        );
+
+        conn.define_xml(&xml).unwrap();
+        conn.start_domain("test-state-shutoff").unwrap();
+        let newer = fetch_states(&conn, ObjectKind::Domain);
+        assert_eq!(
+            diff_events(ObjectKind::Domain, &new, &newer),
+            [
+                event("test-state-shutoff", Lifecycle::Defined),
+                event("test-state-shutoff", Lifecycle::Started),
+            ]
+        );
+        assert_eq!(
+            state_event(DomainState::Paused, DomainState::Running),
+            Some(Lifecycle::Resumed)
+        );
+        assert_eq!(
+            state_event(DomainState::Running, DomainState::Blocked),
+            None
+        );
+    }
+
+    #[test]
+    fn test_watcher() {
+        let conn = Arc::new(testdriver());
+        let mut events = start_watcher(conn.clone(), Duration::from_millis(10));
+        // Registration happens on the watcher thread, so toggle the VM
+        // until its events come through
+        let mut seen = vec![];
+        while seen.is_empty() {
+            if conn.lookup_domain("test").unwrap().state.is_active() {
+                conn.destroy_domain("test").unwrap();
+            } else {
+                conn.start_domain("test").unwrap();
+            }
+            std::thread::sleep(Duration::from_millis(20));
+            while let Ok(Some(event)) = events.try_next() {
+                seen.push(event);
+            }
+        }
+        assert!(seen.iter().all(|ev| matches!(
+            ev,
+            ConnEvent::Lifecycle(ObjectKind::Domain, name, Lifecycle::Started | Lifecycle::Stopped)
+                if name == "test"
+        )));
+        conn.detach_device(
+            "test-many-devices",
+            "<disk type='file'><target dev='vdb'/></disk>",
+            crate::connection::AffectFlags::Live,
+        )
+        .unwrap();
+        assert!(matches!(
+            block_on(events.next()),
+            Some(ConnEvent::DeviceRemoved { domain, .. }) if domain == "test-many-devices"
+        ));
+    }
+
+    #[test]
+    fn test_watcher_stream_end() {
+        let conn = Arc::new(testdriver());
+        let mut events = start_watcher(conn.clone(), Duration::from_millis(10));
+        let toggle_until_event = |events: &mut UnboundedReceiver<ConnEvent>| loop {
+            if conn.lookup_domain("test").unwrap().state.is_active() {
+                conn.destroy_domain("test").unwrap();
+            } else {
+                conn.start_domain("test").unwrap();
+            }
+            std::thread::sleep(Duration::from_millis(20));
+            if let Ok(Some(event)) = events.try_next() {
+                return event;
+            }
+        };
+        toggle_until_event(&mut events);
+
+        // Without the event stream, the VM changes are polled
+        conn.end_events(ObjectKind::Domain);
+        std::thread::sleep(Duration::from_millis(50));
+        while let Ok(Some(_)) = events.try_next() {}
+        assert!(matches!(
+            toggle_until_event(&mut events),
+            ConnEvent::Lifecycle(ObjectKind::Domain, name, Lifecycle::Started | Lifecycle::Stopped)
+                if name == "test"
+        ));
+    }
+}
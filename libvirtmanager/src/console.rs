@@ -0,0 +1,836 @@
+// VM console page (Iced port of virtManager/details/console.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Shows the graphical console of a running VM. The page connects to the
+//! first `<graphics>` device, VNC or SPICE, when it is shown, asks for a
+//! password if the server wants one, and then embeds the remote display.
+//! The toolbar has the scaling mode, the Send Key combinations,
+//! clipboard paste and fullscreen. When there is nothing to show, a
+//! message says why.
+//!
+//! The console menu switches to a text terminal on a serial or console
+//! device instead, which is the default for guests without graphics.
+
+use std::fmt;
+use std::sync::Arc;
+
+use iced::widget::{Column, Space, button, column, container, pick_list, row, text, text_input};
+use iced::window::{self, Mode};
+use iced::{Alignment, Element, Length, Subscription, Task};
+use log::debug;
+
+use crate::connection::{Connection, DomainState};
+use crate::framebuffer::Framebuffer;
+use crate::guest::Guest;
+use crate::rfb::keysym_from_name;
+use crate::serialcon::{SerialConsole, SerialDevice, SerialEvent, get_serialcon_devices};
+use crate::sshtunnels::SshTunnels;
+use crate::terminal::{Terminal, TerminalInput, terminal};
+use crate::viewers::{
+    ConnectionInfo, DisplayHandle, DisplayInput, KEY_COMBOS, Viewer, ViewerEvent, ViewerStream,
+    display,
+};
+
+/// When the display is scaled to the window, like virt-manager's
+/// console scaling preference
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scaling {
+    Never,
+    #[default]
+    Always,
+    Fullscreen,
+}
+
+impl Scaling {
+    pub const ALL: [Scaling; 3] = [Scaling::Never, Scaling::Always, Scaling::Fullscreen];
+}
+
+impl fmt::Display for Scaling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scaling::Never => "Never",
+            Scaling::Always => "Always",
+            Scaling::Fullscreen => "Only when Fullscreen",
+        })
+    }
+}
+
+/// Entry of the Send Key menu, an index into `KEY_COMBOS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCombo(usize);
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(KEY_COMBOS[self.0].0)
+    }
+}
+
+/// What the page shows: the graphical console, or the text console of a
+/// serial or console device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleChoice {
+    Graphical,
+    /// Device kind, "serial" or "console", and index among its kind
+    Text(String, usize),
+}
+
+/// Entry of the console menu
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsoleItem {
+    label: String,
+    /// None for the placeholder entry that can't be picked
+    choice: Option<ConsoleChoice>,
+}
+
+impl fmt::Display for ConsoleItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Connect if the VM runs and nothing is connected yet
+    Refresh,
+    ConsoleSelected(ConsoleItem),
+    Viewer(ViewerEvent),
+    Input(DisplayInput),
+    Serial(SerialEvent),
+    Terminal(TerminalInput),
+    CopyText,
+    PasswordChanged(String),
+    Login,
+    ScalingSelected(Scaling),
+    SendKey(KeyCombo),
+    PasteClipboard,
+    ClipboardRead(Option<String>),
+    ToggleFullscreen,
+    /// The VM state, and its live config when it runs
+    Loaded(Result<(DomainState, Option<Guest>), String>),
+    /// The VM state after the viewer or text console closed
+    StateChecked(Result<DomainState, String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Page {
+    /// Nothing to show, and why
+    Unavailable(String),
+    Auth,
+    Viewer,
+}
+
+pub struct ConsolePage {
+    conn: Arc<dyn Connection>,
+    name: String,
+    page: Page,
+    viewer: Option<Viewer>,
+    tunnels: Option<Arc<SshTunnels>>,
+    handle: Option<DisplayHandle>,
+    /// Copy of the remote framebuffer, taken on each update
+    fb: Framebuffer,
+    password: String,
+    /// Password to answer the next auth request with, after a reconnect
+    pending_password: Option<String>,
+    auth_error: Option<String>,
+    pub scaling: Scaling,
+    fullscreen: bool,
+    /// Picked from the console menu, or by default on the first refresh
+    choice: Option<ConsoleChoice>,
+    serial_devs: Vec<SerialDevice>,
+    serial: Option<SerialConsole>,
+    /// Why the text console isn't available
+    serial_error: Option<String>,
+    term: Terminal,
+    /// Text last selected in the terminal
+    selection: String,
+}
+
+impl ConsolePage {
+    pub fn new(conn: Arc<dyn Connection>, name: &str) -> Self {
+        Self {
+            conn,
+            name: name.to_string(),
+            page: Page::Unavailable(String::new()),
+            viewer: None,
+            tunnels: None,
+            handle: None,
+            fb: Framebuffer::default(),
+            password: String::new(),
+            pending_password: None,
+            auth_error: None,
+            scaling: Scaling::default(),
+            fullscreen: false,
+            choice: None,
+            serial_devs: vec![],
+            serial: None,
+            serial_error: None,
+            term: Terminal::default(),
+            selection: String::new(),
+        }
+    }
+
+    fn close_viewer(&mut self) {
+        self.viewer = None;
+        self.handle = None;
+        if let Some(tunnels) = self.tunnels.take() {
+            tunnels.close_all();
+        }
+    }
+
+    fn vm_unavailable_msg(state: DomainState) -> &'static str {
+        if state == DomainState::Crashed {
+            "Guest has crashed."
+        } else {
+            "Guest is not running."
+        }
+    }
+
+    fn set_unavailable(&mut self, msg: String) {
+        self.close_viewer();
+        self.serial = None;
+        self.serial_error = Some(msg.clone());
+        self.page = Page::Unavailable(msg);
+    }
+
+    fn is_down(state: DomainState) -> bool {
+        !state.is_active() || state == DomainState::Crashed
+    }
+
+    /// Read the VM state, and the live config of a running VM
+    fn load_vm(conn: &dyn Connection, name: &str) -> Result<(DomainState, Option<Guest>), String> {
+        let state = conn.lookup_domain(name)?.state;
+        if Self::is_down(state) {
+            return Ok((state, None));
+        }
+        let guest = conn
+            .domain_xml(name, false)
+            .and_then(|xml| Guest::parse(&xml))?;
+        Ok((state, Some(guest)))
+    }
+
+    /// Follow the VM state: connect to a running VM, drop the viewer and
+    /// text console of a stopped one. The VM is read off the UI thread.
+    pub fn refresh(&self) -> Task<Message> {
+        let conn = self.conn.clone();
+        let name = self.name.clone();
+        Task::perform(
+            async move {
+                tokio::task::spawn_blocking(move || Self::load_vm(conn.as_ref(), &name))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|r| r)
+            },
+            Message::Loaded,
+        )
+    }
+
+    /// Look up the VM state off the UI thread
+    fn check_state(&self) -> Task<Message> {
+        let conn = self.conn.clone();
+        let name = self.name.clone();
+        Task::perform(
+            async move {
+                tokio::task::spawn_blocking(move || conn.lookup_domain(&name).map(|i| i.state))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|r| r)
+            },
+            Message::StateChecked,
+        )
+    }
+
+    fn loaded(&mut self, ret: Result<(DomainState, Option<Guest>), String>) {
+        let guest = match ret {
+            Ok((_, Some(guest))) => guest,
+            Ok((state, None)) => {
+                return self.set_unavailable(Self::vm_unavailable_msg(state).to_string());
+            }
+            Err(e) => return self.set_unavailable(e),
+        };
+        self.serial_devs = get_serialcon_devices(&guest);
+        // Headless guests get their first usable text console
+        let choice = self.choice.get_or_insert_with(|| {
+            self.serial_devs
+                .iter()
+                .find(|d| d.can_connect().is_none())
+                .filter(|_| guest.devices("graphics").is_empty())
+                .map(|d| ConsoleChoice::Text(d.devtype.clone(), d.idx))
+                .unwrap_or(ConsoleChoice::Graphical)
+        });
+        match choice.clone() {
+            ConsoleChoice::Graphical if self.viewer.is_none() => self.connect(&guest),
+            ConsoleChoice::Text(devtype, idx) if self.serial.is_none() => {
+                self.open_serial(&devtype, idx)
+            }
+            _ => {}
+        }
+    }
+
+    fn open_serial(&mut self, devtype: &str, idx: usize) {
+        self.serial = None;
+        let Some(dev) = self
+            .serial_devs
+            .iter()
+            .find(|d| d.devtype == devtype && d.idx == idx)
+        else {
+            debug!("No devices found for {} {}", devtype, idx);
+            self.serial_error = Some("No text console available".to_string());
+            return;
+        };
+        if let Some(err) = dev.can_connect() {
+            self.serial_error = Some(err);
+            return;
+        }
+        match SerialConsole::open(&*self.conn, &self.name, Some(dev)) {
+            Ok(serial) => {
+                self.serial = Some(serial);
+                self.serial_error = None;
+            }
+            Err(e) => {
+                debug!("Error opening serial console: {}", e);
+                self.serial_error = Some(format!("Error connecting to text console: {}", e));
+            }
+        }
+    }
+
+    fn console_items(&self) -> Vec<ConsoleItem> {
+        let mut items = vec![ConsoleItem {
+            label: "Graphical Console".to_string(),
+            choice: Some(ConsoleChoice::Graphical),
+        }];
+        items.extend(self.serial_devs.iter().map(|d| ConsoleItem {
+            label: d.label(),
+            choice: Some(ConsoleChoice::Text(d.devtype.clone(), d.idx)),
+        }));
+        if items.len() == 1 {
+            items.push(ConsoleItem {
+                label: "No text console available".to_string(),
+                choice: None,
+            });
+        }
+        items
+    }
+
+    fn select_console(&mut self, choice: ConsoleChoice) -> Task<Message> {
+        if self.choice.as_ref() == Some(&choice) {
+            return Task::none();
+        }
+        self.serial = None;
+        self.serial_error = None;
+        let (cols, rows) = self.term.size();
+        self.term = Terminal::new(cols, rows);
+        self.selection.clear();
+        self.choice = Some(choice);
+        self.refresh()
+    }
+
+    fn serial_event(&mut self, event: SerialEvent) -> Task<Message> {
+        match event {
+            SerialEvent::Data(data) => {
+                self.term.feed(&data);
+                let reply = self.term.take_output();
+                if !reply.is_empty() {
+                    self.send_text(&reply);
+                }
+            }
+            SerialEvent::Closed(err) => {
+                debug!("Text console closed: {:?}", err);
+                self.serial = None;
+                // Keep showing what the guest wrote, unless it went away
+                return self.check_state();
+            }
+        }
+        Task::none()
+    }
+
+    fn send_text(&self, data: &[u8]) {
+        if let Some(serial) = &self.serial
+            && let Err(e) = serial.send(data)
+        {
+            // The reader thread reports the stream closing
+            debug!("Error sending console input: {}", e);
+        }
+    }
+
+    fn is_text(&self) -> bool {
+        matches!(self.choice, Some(ConsoleChoice::Text(..)))
+    }
+
+    fn connect(&mut self, guest: &Guest) {
+        self.close_viewer();
+        self.auth_error = None;
+        let Some(gdev) = guest.devices("graphics").into_iter().next() else {
+            self.page = Page::Unavailable("Graphical console not configured for guest".to_string());
+            return;
+        };
+        let ginfo = ConnectionInfo::new(self.conn.uri(), gdev, 0);
+        if !["vnc", "spice"].contains(&ginfo.gtype.as_str()) {
+            self.page = Page::Unavailable(format!(
+                "Cannot display graphical console type '{}'",
+                ginfo.gtype
+            ));
+            return;
+        }
+        // GL frames are handed over as local dmabufs we can't draw
+        if gdev.get("./gl/@enable").as_deref() == Some("yes") {
+            self.page = Page::Unavailable(
+                "Cannot display a SPICE console with OpenGL enabled. Turn off OpenGL for \
+                 the guest's display to use it here."
+                    .to_string(),
+            );
+            return;
+        }
+        debug!("Starting connect process for {}", ginfo.logstring());
+        let tunnels = Arc::new(SshTunnels::new(&ginfo));
+        self.tunnels = Some(tunnels.clone());
+        let ret = ViewerStream::opener(self.conn.clone(), &self.name, &ginfo, &tunnels).and_then(
+            |open| {
+                if ginfo.gtype == "spice" {
+                    Ok(Viewer::spice(open))
+                } else {
+                    open().map(Viewer::vnc)
+                }
+            },
+        );
+        match ret {
+            Ok(viewer) => {
+                self.viewer = Some(viewer);
+                self.page =
+                    Page::Unavailable("Connecting to graphical console for guest".to_string());
+            }
+            Err(e) => {
+                self.page =
+                    Page::Unavailable(format!("Error connecting to graphical console:\n{}", e));
+            }
+        }
+    }
+
+    fn sync_framebuffer(&mut self) {
+        if let Some(handle) = &self.handle {
+            self.fb = handle.fb.lock().unwrap().clone();
+        }
+    }
+
+    fn viewer_event(&mut self, event: ViewerEvent) -> Task<Message> {
+        match event {
+            ViewerEvent::NeedAuth => match (&self.viewer, self.pending_password.take()) {
+                (Some(viewer), Some(password)) => viewer.set_password(Some(password)),
+                _ => self.page = Page::Auth,
+            },
+            ViewerEvent::AuthError(e) => {
+                self.auth_error = Some(format!("Viewer authentication error: {}", e));
+                self.page = Page::Auth;
+            }
+            ViewerEvent::Connected(handle) => {
+                debug!("Viewer connected");
+                self.handle = Some(handle);
+                self.sync_framebuffer();
+                self.page = Page::Viewer;
+            }
+            ViewerEvent::Update | ViewerEvent::DesktopResize(..) => self.sync_framebuffer(),
+            ViewerEvent::Bell => {}
+            ViewerEvent::CutText(text) => return iced::clipboard::write(text),
+            ViewerEvent::Disconnected(err) => {
+                debug!("Viewer disconnected: {:?}", err);
+                let ssherr = self
+                    .tunnels
+                    .as_ref()
+                    .map(|t| t.get_err_output())
+                    .unwrap_or_default();
+                self.close_viewer();
+                // A failed login keeps the password prompt up
+                if self.page == Page::Auth && self.auth_error.is_some() {
+                    return Task::none();
+                }
+                let mut msg = "Viewer was disconnected.".to_string();
+                if let Some(err) = err {
+                    msg = format!("{}\n{}", msg, err);
+                }
+                if !ssherr.is_empty() {
+                    debug!("SSH tunnel error output: {}", ssherr);
+                    msg = format!("{}\n\nSSH tunnel error output: {}", msg, ssherr);
+                }
+                self.page = Page::Unavailable(msg);
+                // The guest shutting down closes the connection too
+                return self.check_state();
+            }
+        }
+        Task::none()
+    }
+
+    fn send(&self, f: impl FnOnce(&DisplayHandle) -> Result<(), String>) {
+        if let Some(handle) = &self.handle
+            && let Err(e) = f(handle)
+        {
+            // The viewer reports the disconnect itself
+            debug!("Error sending console input: {}", e);
+        }
+    }
+
+    pub fn update(&mut self, msg: Message) -> Task<Message> {
+        match msg {
+            Message::Refresh => return self.refresh(),
+            Message::Loaded(ret) => self.loaded(ret),
+            Message::StateChecked(ret) => match ret {
+                Ok(state) if Self::is_down(state) => {
+                    self.set_unavailable(Self::vm_unavailable_msg(state).to_string())
+                }
+                Ok(_) => {}
+                Err(e) => debug!("Error checking VM state: {}", e),
+            },
+            Message::ConsoleSelected(item) => {
+                if let Some(choice) = item.choice {
+                    return self.select_console(choice);
+                }
+            }
+            Message::Viewer(event) => return self.viewer_event(event),
+            Message::Serial(event) => return self.serial_event(event),
+            Message::Terminal(input) => match input {
+                TerminalInput::Data(data) => self.send_text(&data),
+                TerminalInput::Resize(cols, rows) => self.term.resize(cols, rows),
+                TerminalInput::Selected(text) => self.selection = text,
+            },
+            Message::CopyText => return iced::clipboard::write(self.selection.clone()),
+            Message::Input(input) => self.send(|h| match input {
+                DisplayInput::Key { down, keysym } => h.input.key_event(down, keysym),
+                DisplayInput::Pointer { buttons, x, y } => h.input.pointer_event(buttons, x, y),
+            }),
+            Message::PasswordChanged(v) => self.password = v,
+            Message::Login => {
+                let password = std::mem::take(&mut self.password);
+                match &self.viewer {
+                    Some(viewer) if self.auth_error.is_none() => {
+                        viewer.set_password(Some(password));
+                    }
+                    _ => {
+                        // Reconnect, answering the auth request with it
+                        self.pending_password = Some(password);
+                        self.close_viewer();
+                        self.auth_error = None;
+                        return self.refresh();
+                    }
+                }
+            }
+            Message::ScalingSelected(scaling) => self.scaling = scaling,
+            Message::SendKey(combo) => {
+                let keysyms: Vec<u32> = KEY_COMBOS[combo.0]
+                    .1
+                    .iter()
+                    .filter_map(|name| keysym_from_name(name))
+                    .collect();
+                self.send(|h| h.input.send_keys(&keysyms));
+            }
+            Message::PasteClipboard => return iced::clipboard::read().map(Message::ClipboardRead),
+            Message::ClipboardRead(text) => match text {
+                Some(text) if self.is_text() => self.send_text(text.as_bytes()),
+                Some(text) => self.send(|h| h.input.cut_text(&text)),
+                None => {}
+            },
+            Message::ToggleFullscreen => {
+                self.fullscreen = !self.fullscreen;
+                let mode = if self.fullscreen {
+                    Mode::Fullscreen
+                } else {
+                    Mode::Windowed
+                };
+                return window::get_latest().and_then(move |id| window::change_mode(id, mode));
+            }
+        }
+        Task::none()
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        let mut subs = vec![];
+        if let Some(viewer) = &self.viewer {
+            subs.push(viewer.subscription().map(Message::Viewer));
+        }
+        if let Some(serial) = &self.serial {
+            subs.push(serial.subscription().map(Message::Serial));
+        }
+        Subscription::batch(subs)
+    }
+
+    fn scaled(&self) -> bool {
+        match self.scaling {
+            Scaling::Never => false,
+            Scaling::Always => true,
+            Scaling::Fullscreen => self.fullscreen,
+        }
+    }
+
+    fn console_menu(&self) -> Element<'_, Message> {
+        let items = self.console_items();
+        let selected = items.iter().find(|i| i.choice == self.choice).cloned();
+        pick_list(items, selected, Message::ConsoleSelected).into()
+    }
+
+    fn text_view(&self) -> Element<'_, Message> {
+        let copy = (!self.selection.is_empty()).then_some(Message::CopyText);
+        let paste = self.serial.is_some().then_some(Message::PasteClipboard);
+        let toolbar = row![
+            self.console_menu(),
+            button(text("Copy")).on_press_maybe(copy),
+            button(text("Paste")).on_press_maybe(paste),
+        ]
+        .spacing(8)
+        .align_y(Alignment::Center);
+        let body: Element<Message> = match &self.serial_error {
+            Some(err) => container(text(err.clone())).center(Length::Fill).into(),
+            None => terminal(&self.term, Message::Terminal).into(),
+        };
+        column![toolbar, body].spacing(8).into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        if self.is_text() {
+            return self.text_view();
+        }
+        if self.page == Page::Viewer {
+            return self.graphics_view();
+        }
+        column![self.console_menu(), self.graphics_view()]
+            .spacing(8)
+            .into()
+    }
+
+    fn graphics_view(&self) -> Element<'_, Message> {
+        match &self.page {
+            Page::Unavailable(msg) => {
+                let mut col: Column<Message> = column![text(msg.clone())]
+                    .spacing(12)
+                    .align_x(Alignment::Center);
+                if self.viewer.is_none() {
+                    col = col.push(button(text("Connect")).on_press(Message::Refresh));
+                }
+                container(col).center(Length::Fill).into()
+            }
+            Page::Auth => {
+                let mut col: Column<Message> = column![
+                    text("Authentication is required to access the graphical console"),
+                    row![
+                        text("Password:"),
+                        text_input("", &self.password)
+                            .secure(true)
+                            .on_input(Message::PasswordChanged)
+                            .on_submit(Message::Login)
+                            .padding(6)
+                            .width(Length::Fixed(240.0)),
+                        button(text("Login")).on_press(Message::Login),
+                    ]
+                    .spacing(8)
+                    .align_y(Alignment::Center),
+                ]
+                .spacing(12)
+                .align_x(Alignment::Center);
+                if let Some(err) = &self.auth_error {
+                    col = col.push(text(err.clone()).size(14));
+                }
+                container(col).center(Length::Fill).into()
+            }
+            Page::Viewer => {
+                let combos: Vec<KeyCombo> = (0..KEY_COMBOS.len()).map(KeyCombo).collect();
+                let fullscreen = if self.fullscreen {
+                    "Leave Fullscreen"
+                } else {
+                    "Fullscreen"
+                };
+                let toolbar = row![
+                    self.console_menu(),
+                    text("Scaling:"),
+                    pick_list(Scaling::ALL, Some(self.scaling), Message::ScalingSelected),
+                    pick_list(combos, None::<KeyCombo>, Message::SendKey).placeholder("Send Key"),
+                    button(text("Paste Clipboard")).on_press(Message::PasteClipboard),
+                    Space::with_width(Length::Fill),
+                    button(text(fullscreen)).on_press(Message::ToggleFullscreen),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center);
+                let screen: Element<Message> = if self.scaled() {
+                    display(&self.fb, true, Message::Input).into()
+                } else {
+                    container(display(&self.fb, false, Message::Input))
+                        .center(Length::Fill)
+                        .into()
+                };
+                column![toolbar, screen].spacing(8).into()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+    use crate::viewers::ViewerInput;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl ViewerInput for Recorder {
+        fn key_event(&self, down: bool, keysym: u32) -> Result<(), String> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("key {} {:#x}", down, keysym));
+            Ok(())
+        }
+
+        fn pointer_event(&self, buttons: u8, x: u32, y: u32) -> Result<(), String> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("pointer {} {} {}", buttons, x, y));
+            Ok(())
+        }
+
+        fn cut_text(&self, text: &str) -> Result<(), String> {
+            self.0.lock().unwrap().push(format!("cut {}", text));
+            Ok(())
+        }
+    }
+
+    fn open(name: &str) -> ConsolePage {
+        let conn = testdriver();
+        let mut page = ConsolePage::new(Arc::new(conn), name);
+        load(&mut page);
+        page
+    }
+
+    /// Deliver the refresh task result, as the runtime would
+    fn load(page: &mut ConsolePage) {
+        let ret = ConsolePage::load_vm(page.conn.as_ref(), &page.name);
+        let _ = page.update(Message::Loaded(ret));
+    }
+
+    fn unavailable(page: &ConsolePage) -> &str {
+        match &page.page {
+            Page::Unavailable(msg) => msg,
+            other => panic!("unexpected page {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_console_pages() {
+        assert_eq!(
+            unavailable(&open("test-state-shutoff")),
+            "Guest is not running."
+        );
+        assert_eq!(
+            unavailable(&open("test-state-crashed")),
+            "Guest has crashed."
+        );
+        assert_eq!(
+            unavailable(&open("test")),
+            "Graphical console not configured for guest"
+        );
+        assert!(unavailable(&open("test-alternate-devs")).contains("OpenGL enabled"));
+        // Without a port the test driver can't open a connection for us
+        assert!(
+            unavailable(&open("test-many-devices"))
+                .contains("Opening graphics connections is not supported")
+        );
+    }
+
+    #[test]
+    fn test_console_events() {
+        let mut page = open("test");
+        let _ = page.update(Message::Viewer(ViewerEvent::NeedAuth));
+        assert_eq!(page.page, Page::Auth);
+        let _ = page.update(Message::Viewer(ViewerEvent::AuthError("bad".into())));
+        assert_eq!(
+            page.auth_error.as_deref(),
+            Some("Viewer authentication error: bad")
+        );
+        let _ = page.update(Message::Viewer(ViewerEvent::Disconnected(None)));
+        assert_eq!(page.page, Page::Auth);
+
+        let recorder = Arc::new(Recorder::default());
+        let mut fb = Framebuffer::new(4, 3);
+        fb.set_pixel(1, 1, [1, 2, 3]);
+        let handle = DisplayHandle {
+            fb: Arc::new(std::sync::Mutex::new(fb)),
+            input: recorder.clone(),
+        };
+        let _ = page.update(Message::Viewer(ViewerEvent::Connected(handle.clone())));
+        assert_eq!(page.page, Page::Viewer);
+        assert_eq!(page.fb.pixel(1, 1), [1, 2, 3]);
+        *handle.fb.lock().unwrap() = Framebuffer::new(8, 6);
+        let _ = page.update(Message::Viewer(ViewerEvent::DesktopResize(8, 6)));
+        assert_eq!((page.fb.width, page.fb.height), (8, 6));
+
+        let _ = page.update(Message::Input(DisplayInput::Pointer {
+            buttons: 1,
+            x: 2,
+            y: 3,
+        }));
+        let _ = page.update(Message::SendKey(KeyCombo(1)));
+        let _ = page.update(Message::ClipboardRead(Some("hi".into())));
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "pointer 1 2 3",
+                "key true 0xffe3",
+                "key true 0xffe9",
+                "key true 0xffff",
+                "key false 0xffff",
+                "key false 0xffe9",
+                "key false 0xffe3",
+                "cut hi",
+            ]
+        );
+
+        let _ = page.update(Message::Viewer(ViewerEvent::Disconnected(Some(
+            "boom".into(),
+        ))));
+        assert_eq!(unavailable(&page), "Viewer was disconnected.\nboom");
+        assert!(page.handle.is_none());
+        let _ = page.update(Message::StateChecked(Ok(DomainState::Crashed)));
+        assert_eq!(unavailable(&page), "Guest has crashed.");
+    }
+
+    #[test]
+    fn test_text_console() {
+        let mut page = open("test-alternate-devs");
+        let items = page.console_items();
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, ["Graphical Console", "Serial 1"]);
+        let _ = page.update(Message::ConsoleSelected(items[1].clone()));
+        load(&mut page);
+        assert!(page.serial.is_some() && page.serial_error.is_none());
+        let _ = page.update(Message::Serial(SerialEvent::Data(
+            b"login: \x1b[1mroot\x1b[0m\r\n".to_vec(),
+        )));
+        assert!(page.term.screen_text().starts_with("login: root\n"));
+        let _ = page.update(Message::Terminal(TerminalInput::Selected("root".into())));
+        assert_eq!(page.selection, "root");
+        let _ = page.update(Message::Serial(SerialEvent::Closed(None)));
+        let _ = page.update(Message::StateChecked(Ok(DomainState::Running)));
+        assert!(page.serial.is_none() && page.serial_error.is_none());
+        let _ = page.update(Message::StateChecked(Ok(DomainState::Shutoff)));
+        assert_eq!(page.serial_error.as_deref(), Some("Guest is not running."));
+
+        assert_eq!(
+            open("test").console_items()[1].label,
+            "No text console available"
+        );
+
+        // Guests without graphics start on their text console
+        let conn = Arc::new(testdriver());
+        conn.create_xml(
+            "<domain type='test'><name>headless</name><memory>65536</memory>\
+             <os><type>hvm</type></os><devices><serial type='pty'/></devices></domain>",
+        )
+        .unwrap();
+        let mut page = ConsolePage::new(conn, "headless");
+        load(&mut page);
+        assert_eq!(
+            page.choice,
+            Some(ConsoleChoice::Text("serial".to_string(), 0))
+        );
+        assert!(page.serial.is_some());
+    }
+}
@@ -0,0 +1,741 @@
+// New virtual network wizard (Iced port of virtManager/createnet.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Builds a `<network>` from a forward mode, its forward device, and the
+//! IPv4/IPv6 subnets with their DHCP ranges, then defines, starts and
+//! autostarts it. The XML tab shows what the form builds, and any edits
+//! made there are what gets defined.
+
+use std::fmt;
+use std::sync::Arc;
+
+use iced::widget::{
+    Column, Space, button, checkbox, column, pick_list, row, text, text_editor, text_input,
+};
+use iced::{Alignment, Element, Length};
+use log::debug;
+
+use crate::connection::Connection;
+use crate::generatename::generate_name;
+use crate::network::{
+    Network, NetworkBridge, NetworkDhcp, NetworkDhcpRange, NetworkDomain, NetworkForward,
+    NetworkForwardPf, NetworkIp, ip_add, parse_ip_network, prefix_netmask,
+};
+use crate::xmlapi::{Element as XmlElement, validate_generic_name};
+
+/// How the network reaches the outside
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForwardMode {
+    #[default]
+    Nat,
+    Route,
+    Open,
+    Isolated,
+    Bridge,
+    Hostdev,
+}
+
+impl ForwardMode {
+    pub const ALL: [ForwardMode; 6] = [
+        ForwardMode::Nat,
+        ForwardMode::Route,
+        ForwardMode::Open,
+        ForwardMode::Isolated,
+        ForwardMode::Bridge,
+        ForwardMode::Hostdev,
+    ];
+
+    /// The `<forward mode>`, None for isolated networks
+    pub fn mode(&self) -> Option<&'static str> {
+        match self {
+            ForwardMode::Nat => Some("nat"),
+            ForwardMode::Route => Some("route"),
+            ForwardMode::Open => Some("open"),
+            ForwardMode::Isolated => None,
+            ForwardMode::Bridge => Some("bridge"),
+            ForwardMode::Hostdev => Some("hostdev"),
+        }
+    }
+
+    /// Whether traffic can be sent out a chosen physical device
+    fn has_forward_dev(&self) -> bool {
+        matches!(self, ForwardMode::Nat | ForwardMode::Route)
+    }
+
+    /// Whether libvirt manages addresses for the network. Bridge and
+    /// SR-IOV networks leave that to the host.
+    fn has_ip(&self) -> bool {
+        !matches!(self, ForwardMode::Bridge | ForwardMode::Hostdev)
+    }
+}
+
+impl fmt::Display for ForwardMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ForwardMode::Nat => "NAT",
+            ForwardMode::Route => "Routed",
+            ForwardMode::Open => "Open",
+            ForwardMode::Isolated => "Isolated",
+            ForwardMode::Bridge => "Bridge",
+            ForwardMode::Hostdev => "SR-IOV pool",
+        })
+    }
+}
+
+/// Whether NAT and routed traffic may leave through any host device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForwardDevice {
+    #[default]
+    Any,
+    Manual,
+}
+
+impl ForwardDevice {
+    pub const ALL: [ForwardDevice; 2] = [ForwardDevice::Any, ForwardDevice::Manual];
+}
+
+impl fmt::Display for ForwardDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ForwardDevice::Any => "Any physical device",
+            ForwardDevice::Manual => "Physical device...",
+        })
+    }
+}
+
+/// Entry of the SR-IOV device list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhysicalFunction {
+    /// Interface name, None for the placeholder when there are none
+    pub ifname: Option<String>,
+    pub label: String,
+}
+
+impl fmt::Display for PhysicalFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+/// "0000:81:00:1 Intel Corporation 82599ES ..." for a PCI node device
+fn pci_pretty_name(dev: &XmlElement) -> String {
+    let num = |tag: &str| {
+        let val = dev
+            .get(&format!("./capability/{}", tag))
+            .unwrap_or_default();
+        match val.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).unwrap_or(0),
+            None => val.parse().unwrap_or(0),
+        }
+    };
+    format!(
+        "{:04X}:{:02X}:{:02X}:{:X} {} {}",
+        num("domain"),
+        num("bus"),
+        num("slot"),
+        num("function"),
+        dev.get("./capability/vendor").unwrap_or_default(),
+        dev.get("./capability/product").unwrap_or_default()
+    )
+}
+
+/// The interfaces of the host's SR-IOV capable PCI devices, which can
+/// back a pool of virtual functions
+pub fn sriov_interfaces(conn: &dyn Connection) -> Vec<PhysicalFunction> {
+    let devs: Vec<XmlElement> = conn
+        .list_nodedevs()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|dev| conn.nodedev_xml(&dev.name).ok())
+        .filter_map(|xml| XmlElement::parse(&xml).ok())
+        .collect();
+    let of_type = |t: &'static str| {
+        devs.iter()
+            .filter(move |d| d.get("./capability/@type").as_deref() == Some(t))
+    };
+    let mut ret = vec![];
+    for pcidev in of_type("pci") {
+        if pcidev
+            .find("./capability/capability[@type='virt_functions']")
+            .is_none()
+        {
+            continue;
+        }
+        let ifname = of_type("net")
+            .find(|n| n.get("./parent").is_some() && n.get("./parent") == pcidev.get("./name"))
+            .and_then(|n| n.get("./capability/interface"));
+        if let Some(ifname) = ifname {
+            ret.push(PhysicalFunction {
+                label: format!("{} ({})", ifname, pci_pretty_name(pcidev)),
+                ifname: Some(ifname),
+            });
+        }
+    }
+    if ret.is_empty() {
+        ret.push(PhysicalFunction {
+            ifname: None,
+            label: "No available device".into(),
+        });
+    }
+    ret
+}
+
+/// Settings of one address family
+#[derive(Debug, Clone, Default)]
+pub struct IpConfig {
+    pub enable: bool,
+    /// Subnet as "address/prefix"
+    pub network: String,
+    pub dhcp: bool,
+    pub dhcp_start: String,
+    pub dhcp_end: String,
+}
+
+impl IpConfig {
+    /// Take a new subnet, moving the DHCP range into it when it's valid
+    fn set_network(&mut self, network: String, v6: bool) {
+        if let Some((addr, prefix)) = parse_ip_network(&network).filter(|(a, _)| a.is_ipv6() == v6)
+        {
+            let (start, end) = if v6 {
+                (256, 512 - 1)
+            } else {
+                let num = 1u128 << (32 - u32::from(prefix));
+                (num / 2, num.saturating_sub(2))
+            };
+            self.dhcp_start = ip_add(addr, start, prefix).to_string();
+            self.dhcp_end = ip_add(addr, end, prefix).to_string();
+        }
+        self.network = network;
+    }
+
+    /// The `<ip>` of the settings, or an error naming `label` if the
+    /// addresses don't parse
+    fn build(&self, label: &str, v6: bool) -> Result<NetworkIp, String> {
+        let (net, prefix) = parse_ip_network(&self.network)
+            .filter(|(a, _)| a.is_ipv6() == v6)
+            .ok_or_else(|| format!("Invalid {} network address '{}'", label, self.network))?;
+        let mut ip = NetworkIp {
+            address: Some(ip_add(net, 1, prefix).to_string()),
+            ..Default::default()
+        };
+        if v6 {
+            ip.family = Some("ipv6".into());
+            ip.prefix = Some(prefix);
+        } else {
+            ip.netmask = Some(prefix_netmask(prefix));
+        }
+        if self.dhcp {
+            let addr = |val: &str, what: &str| {
+                parse_ip_network(val)
+                    .map(|(a, _)| a.to_string())
+                    .ok_or_else(|| format!("Invalid {} DHCP {} address '{}'", label, what, val))
+            };
+            ip.dhcp = Some(NetworkDhcp {
+                ranges: vec![NetworkDhcpRange {
+                    start: Some(addr(&self.dhcp_start, "start")?),
+                    end: Some(addr(&self.dhcp_end, "end")?),
+                }],
+                ..Default::default()
+            });
+        }
+        Ok(ip)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    NameChanged(String),
+    ModeSelected(ForwardMode),
+    ForwardDeviceSelected(ForwardDevice),
+    ForwardManualChanged(String),
+    BridgeChanged(String),
+    PhysicalFunctionSelected(PhysicalFunction),
+    DnsUseNetnameToggle(bool),
+    DomainNameChanged(String),
+    Ipv4Toggle(bool),
+    Ipv4NetworkChanged(String),
+    Dhcpv4Toggle(bool),
+    Dhcpv4StartChanged(String),
+    Dhcpv4EndChanged(String),
+    Ipv6Toggle(bool),
+    Ipv6NetworkChanged(String),
+    Dhcpv6Toggle(bool),
+    Dhcpv6StartChanged(String),
+    Dhcpv6EndChanged(String),
+    ShowXml(bool),
+    XmlEdited(text_editor::Action),
+    /// Handled by the owner, which drops the wizard
+    Cancel,
+    /// Handled by the owner through `finish`
+    Finish,
+}
+
+pub struct CreateNetwork {
+    conn: Arc<dyn Connection>,
+    pub name: String,
+    pub mode: ForwardMode,
+    pub forward_device: ForwardDevice,
+    pub forward_manual: String,
+    pub bridge: String,
+    physical_functions: Vec<PhysicalFunction>,
+    pub physical_function: Option<PhysicalFunction>,
+    pub dns_use_netname: bool,
+    pub domain_name: String,
+    pub ipv4: IpConfig,
+    pub ipv6: IpConfig,
+    show_xml: bool,
+    xml_content: text_editor::Content,
+    error: Option<String>,
+}
+
+impl CreateNetwork {
+    pub fn new(conn: Arc<dyn Connection>) -> Result<Self, String> {
+        debug!("Showing new network wizard");
+        let name = generate_name(
+            "network",
+            |n| conn.network_xml(n, false).is_ok(),
+            "",
+            1,
+            "",
+            false,
+        )?;
+        let physical_functions = sriov_interfaces(conn.as_ref());
+        let mut ipv4 = IpConfig {
+            enable: true,
+            dhcp: true,
+            ..Default::default()
+        };
+        ipv4.set_network("192.168.100.0/24".into(), false);
+        Ok(Self {
+            physical_function: physical_functions.first().cloned(),
+            physical_functions,
+            conn,
+            name,
+            mode: ForwardMode::default(),
+            forward_device: ForwardDevice::default(),
+            forward_manual: String::new(),
+            bridge: String::new(),
+            dns_use_netname: true,
+            domain_name: String::new(),
+            ipv4,
+            ipv6: IpConfig::default(),
+            show_xml: false,
+            xml_content: text_editor::Content::new(),
+            error: None,
+        })
+    }
+
+    fn forward_dev(&self) -> Option<String> {
+        (self.mode.has_forward_dev() && self.forward_device == ForwardDevice::Manual)
+            .then(|| self.forward_manual.trim().to_string())
+            .filter(|dev| !dev.is_empty())
+    }
+
+    /// The network the form describes
+    pub fn build(&self) -> Result<Network, String> {
+        let mut net = Network::default();
+        net.name = self.name.clone();
+        net.forward = self.mode.mode().map(|mode| NetworkForward {
+            mode: Some(mode.to_string()),
+            dev: self.forward_dev(),
+            ..Default::default()
+        });
+        match self.mode {
+            ForwardMode::Hostdev => {
+                let forward = net.forward.as_mut().expect("hostdev networks forward");
+                forward.managed = Some("yes".into());
+                let dev = self
+                    .physical_function
+                    .as_ref()
+                    .and_then(|p| p.ifname.clone());
+                forward.pf = dev
+                    .map(|dev| NetworkForwardPf { dev })
+                    .into_iter()
+                    .collect();
+                return Ok(net);
+            }
+            ForwardMode::Bridge => {
+                net.bridge = Some(NetworkBridge {
+                    name: Some(self.bridge.trim().to_string()),
+                    ..Default::default()
+                });
+                return Ok(net);
+            }
+            _ => {}
+        }
+
+        let domain = if self.dns_use_netname {
+            &self.name
+        } else {
+            &self.domain_name
+        };
+        if !domain.is_empty() {
+            net.domain = Some(NetworkDomain {
+                name: domain.clone(),
+            });
+        }
+        if self.ipv4.enable {
+            net.ips.push(self.ipv4.build("IPv4", false)?);
+        }
+        if self.ipv6.enable {
+            net.ips.push(self.ipv6.build("IPv6", true)?);
+        }
+        Ok(net)
+    }
+
+    fn validate(&self, net: &Network) -> Result<(), String> {
+        validate_generic_name("Network", &net.name)?;
+        if self.conn.network_xml(&net.name, false).is_ok() {
+            return Err(format!(
+                "Name '{}' already in use by another network.",
+                net.name
+            ));
+        }
+        if self.mode == ForwardMode::Bridge && net.bridge_name().is_some_and(str::is_empty) {
+            return Err("A host bridge device must be specified".into());
+        }
+        Ok(())
+    }
+
+    /// The XML to define: the XML tab's if it's shown, else the form's
+    fn build_xml(&self) -> Result<(Network, String), String> {
+        let err = |e: String| format!("Error building XML: {}", e);
+        if self.show_xml {
+            let xml = self.xml_content.text();
+            debug!("Using XML from xmleditor:\n{}", xml);
+            return Ok((Network::parse(&xml).map_err(err)?, xml));
+        }
+        let net = self.build().map_err(err)?;
+        let xml = net.get_xml();
+        Ok((net, xml))
+    }
+
+    fn create(&self) -> Result<String, String> {
+        let (net, xml) = self.build_xml()?;
+        self.validate(&net)
+            .map_err(|e| format!("Error validating network: {}", e))?;
+        debug!("Creating virtual network '{}' with xml:\n{}", net.name, xml);
+        let err = |e: String| format!("Error creating virtual network: {}", e);
+        let info = self.conn.define_network(&xml).map_err(err)?;
+        let ret = self
+            .conn
+            .start_network(&info.name)
+            .and_then(|_| self.conn.set_network_autostart(&info.name, true));
+        if let Err(e) = ret {
+            let _ = self.conn.undefine_network(&info.name);
+            return Err(err(e));
+        }
+        Ok(info.name)
+    }
+
+    /// Create the network, returning its name. Failures stay in the
+    /// wizard.
+    pub fn finish(&mut self) -> Option<String> {
+        match self.create() {
+            Ok(name) => Some(name),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    pub fn update(&mut self, msg: Message) {
+        match msg {
+            Message::NameChanged(v) => self.name = v,
+            Message::ModeSelected(v) => self.mode = v,
+            Message::ForwardDeviceSelected(v) => self.forward_device = v,
+            Message::ForwardManualChanged(v) => self.forward_manual = v,
+            Message::BridgeChanged(v) => self.bridge = v,
+            Message::PhysicalFunctionSelected(v) => self.physical_function = Some(v),
+            Message::DnsUseNetnameToggle(v) => self.dns_use_netname = v,
+            Message::DomainNameChanged(v) => self.domain_name = v,
+            Message::Ipv4Toggle(v) => self.ipv4.enable = v,
+            Message::Ipv4NetworkChanged(v) => self.ipv4.set_network(v, false),
+            Message::Dhcpv4Toggle(v) => self.ipv4.dhcp = v,
+            Message::Dhcpv4StartChanged(v) => self.ipv4.dhcp_start = v,
+            Message::Dhcpv4EndChanged(v) => self.ipv4.dhcp_end = v,
+            Message::Ipv6Toggle(v) => self.ipv6.enable = v,
+            Message::Ipv6NetworkChanged(v) => self.ipv6.set_network(v, true),
+            Message::Dhcpv6Toggle(v) => self.ipv6.dhcp = v,
+            Message::Dhcpv6StartChanged(v) => self.ipv6.dhcp_start = v,
+            Message::Dhcpv6EndChanged(v) => self.ipv6.dhcp_end = v,
+            Message::ShowXml(show) => {
+                if show && !self.show_xml {
+                    let xml = self.build().map(|net| net.get_xml());
+                    self.xml_content = text_editor::Content::with_text(&xml.unwrap_or_default());
+                }
+                self.show_xml = show;
+            }
+            Message::XmlEdited(action) => self.xml_content.perform(action),
+            Message::Cancel | Message::Finish => {}
+        }
+    }
+
+    fn labeled<'a>(
+        label: &'a str,
+        widget: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        row![text(label).width(Length::Fixed(120.0)), widget.into()]
+            .spacing(8)
+            .align_y(Alignment::Center)
+            .into()
+    }
+
+    fn view_ip<'a>(
+        &'a self,
+        v6: bool,
+        toggle: fn(bool) -> Message,
+        network: fn(String) -> Message,
+        dhcp: fn(bool) -> Message,
+        start: fn(String) -> Message,
+        end: fn(String) -> Message,
+    ) -> Element<'a, Message> {
+        let (ip, family) = if v6 {
+            (&self.ipv6, "IPv6")
+        } else {
+            (&self.ipv4, "IPv4")
+        };
+        let mut col: Column<Message> =
+            column![checkbox(format!("Enable {}", family), ip.enable).on_toggle(toggle)].spacing(8);
+        if !ip.enable {
+            return col.into();
+        }
+        let placeholder = if v6 { "fd00:beef::/64" } else { "" };
+        col = col.push(Self::labeled(
+            "Network:",
+            text_input(placeholder, &ip.network)
+                .on_input(network)
+                .padding(6),
+        ));
+        col = col.push(checkbox(format!("Enable DHCP{}", &family[2..]), ip.dhcp).on_toggle(dhcp));
+        if ip.dhcp {
+            col = col.push(Self::labeled(
+                "Start:",
+                text_input("", &ip.dhcp_start).on_input(start).padding(6),
+            ));
+            col = col.push(Self::labeled(
+                "End:",
+                text_input("", &ip.dhcp_end).on_input(end).padding(6),
+            ));
+        }
+        col.into()
+    }
+
+    fn view_details(&self) -> Element<'_, Message> {
+        let mut col: Column<Message> = column![
+            Self::labeled(
+                "Name:",
+                text_input("", &self.name)
+                    .on_input(Message::NameChanged)
+                    .padding(6),
+            ),
+            Self::labeled(
+                "Mode:",
+                pick_list(ForwardMode::ALL, Some(self.mode), Message::ModeSelected),
+            ),
+        ]
+        .spacing(10);
+        match self.mode {
+            mode if mode.has_forward_dev() => {
+                col = col.push(Self::labeled(
+                    "Forward to:",
+                    pick_list(
+                        ForwardDevice::ALL,
+                        Some(self.forward_device),
+                        Message::ForwardDeviceSelected,
+                    ),
+                ));
+                if self.forward_device == ForwardDevice::Manual {
+                    col = col.push(Self::labeled(
+                        "Device:",
+                        text_input("eth0", &self.forward_manual)
+                            .on_input(Message::ForwardManualChanged)
+                            .padding(6),
+                    ));
+                }
+            }
+            ForwardMode::Bridge => {
+                col = col.push(Self::labeled(
+                    "Host bridge:",
+                    text_input("br0", &self.bridge)
+                        .on_input(Message::BridgeChanged)
+                        .padding(6),
+                ));
+            }
+            ForwardMode::Hostdev => {
+                col = col.push(Self::labeled(
+                    "Device:",
+                    pick_list(
+                        self.physical_functions.clone(),
+                        self.physical_function.clone(),
+                        Message::PhysicalFunctionSelected,
+                    ),
+                ));
+            }
+            _ => {}
+        }
+        if !self.mode.has_ip() {
+            return col.into();
+        }
+
+        col = col.push(text("IPv4 configuration").size(16));
+        col = col.push(self.view_ip(
+            false,
+            Message::Ipv4Toggle,
+            Message::Ipv4NetworkChanged,
+            Message::Dhcpv4Toggle,
+            Message::Dhcpv4StartChanged,
+            Message::Dhcpv4EndChanged,
+        ));
+        col = col.push(text("IPv6 configuration").size(16));
+        col = col.push(self.view_ip(
+            true,
+            Message::Ipv6Toggle,
+            Message::Ipv6NetworkChanged,
+            Message::Dhcpv6Toggle,
+            Message::Dhcpv6StartChanged,
+            Message::Dhcpv6EndChanged,
+        ));
+        col = col.push(text("DNS domain name").size(16));
+        col = col.push(
+            checkbox("Use the network name", self.dns_use_netname)
+                .on_toggle(Message::DnsUseNetnameToggle),
+        );
+        if !self.dns_use_netname {
+            col = col.push(Self::labeled(
+                "Domain name:",
+                text_input("", &self.domain_name)
+                    .on_input(Message::DomainNameChanged)
+                    .padding(6),
+            ));
+        }
+        col.into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let tab = |label, xml: bool| {
+            let style = if self.show_xml == xml {
+                button::primary
+            } else {
+                button::text
+            };
+            button(text(label))
+                .style(style)
+                .on_press(Message::ShowXml(xml))
+        };
+        let body = if self.show_xml {
+            text_editor(&self.xml_content)
+                .on_action(Message::XmlEdited)
+                .height(Length::Fixed(380.0))
+                .into()
+        } else {
+            self.view_details()
+        };
+        let mut col: Column<Message> = column![
+            text("Create a new virtual network").size(18),
+            row![tab("Details", false), tab("XML", true)].spacing(4),
+            body,
+        ]
+        .spacing(10);
+        if let Some(err) = &self.error {
+            col = col.push(text(err.clone()).size(14));
+        }
+        col.push(
+            row![
+                Space::with_width(Length::Fill),
+                button(text("Cancel")).on_press(Message::Cancel),
+                button(text("Finish"))
+                    .on_press_maybe((!self.name.is_empty()).then_some(Message::Finish)),
+            ]
+            .spacing(8),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+
+    fn open() -> (Arc<dyn Connection>, CreateNetwork) {
+        let conn: Arc<dyn Connection> = Arc::new(testdriver());
+        (conn.clone(), CreateNetwork::new(conn).unwrap())
+    }
+
+    #[test]
+    fn test_createnet_build() {
+        let (_conn, mut wiz) = open();
+        assert_eq!(wiz.name, "network");
+        assert_eq!(
+            (wiz.ipv4.dhcp_start.as_str(), wiz.ipv4.dhcp_end.as_str()),
+            ("192.168.100.128", "192.168.100.254")
+        );
+        wiz.update(Message::Ipv4NetworkChanged("10.1.0.0/16".into()));
+        wiz.update(Message::Ipv6Toggle(true));
+        wiz.update(Message::Ipv6NetworkChanged("fd00:beef::/64".into()));
+        assert_eq!(wiz.ipv6.dhcp_start, "fd00:beef::100");
+        assert_eq!(wiz.ipv6.dhcp_end, "fd00:beef::1ff");
+        wiz.update(Message::Dhcpv6Toggle(true));
+        wiz.update(Message::ForwardDeviceSelected(ForwardDevice::Manual));
+        wiz.update(Message::ForwardManualChanged("eth0".into()));
+        let net = wiz.build().unwrap();
+        assert_eq!(net.pretty_forward_mode(), "NAT to eth0");
+        assert_eq!(net.domain_name(), Some("network"));
+        assert_eq!(
+            net.ip_network("ipv4"),
+            (
+                Some("10.1.0.0/16".into()),
+                Some(("10.1.128.0".into(), "10.1.255.254".into()))
+            )
+        );
+        assert_eq!(net.ips[0].netmask.as_deref(), Some("255.255.0.0"));
+        assert_eq!(net.ips[1].address.as_deref(), Some("fd00:beef::1"));
+        assert_eq!(net.ips[1].prefix, Some(64));
+        let xml = net.get_xml();
+        assert_eq!(Network::parse(&xml).unwrap().get_xml(), xml);
+
+        wiz.update(Message::ModeSelected(ForwardMode::Isolated));
+        wiz.update(Message::Ipv4NetworkChanged("bogus".into()));
+        assert!(wiz.build().unwrap_err().contains("Invalid IPv4 network"));
+        wiz.update(Message::Ipv4Toggle(false));
+        let net = wiz.build().unwrap();
+        assert_eq!((net.forward, net.ips.len()), (None, 1));
+
+        // The test driver's SR-IOV card
+        wiz.update(Message::ModeSelected(ForwardMode::Hostdev));
+        let pf = wiz.physical_function.clone().unwrap();
+        assert_eq!(pf.ifname.as_deref(), Some("eth3"));
+        assert!(pf.label.starts_with("eth3 (0000:81:00:1 Intel Corporation"));
+        let net = wiz.build().unwrap();
+        let forward = net.forward.unwrap();
+        assert_eq!(forward.managed.as_deref(), Some("yes"));
+        assert_eq!(forward.pf[0].dev, "eth3");
+        assert!(net.ips.is_empty() && net.domain.is_none());
+    }
+
+    #[test]
+    fn test_createnet_finish() {
+        let (conn, mut wiz) = open();
+        wiz.update(Message::NameChanged("default".into()));
+        assert_eq!(wiz.finish(), None);
+        assert!(wiz.error.as_deref().unwrap().contains("already in use"));
+
+        wiz.update(Message::NameChanged("newnet".into()));
+        wiz.update(Message::ModeSelected(ForwardMode::Bridge));
+        assert_eq!(wiz.finish(), None);
+        wiz.update(Message::BridgeChanged("br0".into()));
+        // Edits in the XML tab win over the form
+        wiz.update(Message::ShowXml(true));
+        assert!(wiz.xml_content.text().contains("<bridge name=\"br0\"/>"));
+        wiz.xml_content =
+            text_editor::Content::with_text(&wiz.xml_content.text().replace("newnet", "xmlnet"));
+        assert_eq!(wiz.finish().as_deref(), Some("xmlnet"));
+        assert!(conn.network_autostart("xmlnet").unwrap());
+        let active = conn.list_networks().unwrap();
+        assert!(active.iter().any(|n| n.name == "xmlnet" && n.active));
+    }
+}
@@ -0,0 +1,399 @@
+// New storage pool wizard (Iced port of virtManager/createpool.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Builds a `<pool>` from its type and the target, source, host, source
+//! name and initiator IQN the type takes, then defines, builds, starts
+//! and autostarts it. As in the network wizard, edits made in the XML
+//! tab are what gets defined.
+
+use std::fmt;
+use std::sync::Arc;
+
+use iced::widget::{
+    Column, Space, button, checkbox, column, pick_list, row, text, text_editor, text_input,
+};
+use iced::{Alignment, Element, Length};
+use log::debug;
+
+use crate::connection::Connection;
+use crate::storage::{POOL_TYPES, StoragePool, pretty_pool_type};
+
+/// A pool type in the type list, shown as "dir: Filesystem Directory"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolType(pub &'static str);
+
+impl fmt::Display for PoolType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.0, pretty_pool_type(self.0))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    NameChanged(String),
+    TypeSelected(PoolType),
+    TargetPathChanged(String),
+    SourcePathChanged(String),
+    HostChanged(String),
+    SourceNameChanged(String),
+    IqnToggle(bool),
+    IqnChanged(String),
+    ShowXml(bool),
+    XmlEdited(text_editor::Action),
+    /// Handled by the owner, which drops the wizard
+    Cancel,
+    /// Handled by the owner through `finish`
+    Finish,
+}
+
+pub struct CreatePool {
+    conn: Arc<dyn Connection>,
+    pub name: String,
+    pub ptype: PoolType,
+    pub target_path: String,
+    /// Whether the target path was typed in rather than the default
+    target_edited: bool,
+    pub source_path: String,
+    pub host: String,
+    pub source_name: String,
+    pub use_iqn: bool,
+    pub iqn: String,
+    show_xml: bool,
+    xml_content: text_editor::Content,
+    error: Option<String>,
+}
+
+impl CreatePool {
+    pub fn new(conn: Arc<dyn Connection>) -> Result<Self, String> {
+        debug!("Showing new pool wizard");
+        let name = StoragePool::find_free_name(conn.as_ref(), "pool")?;
+        let mut wizard = Self {
+            conn,
+            name,
+            ptype: PoolType(POOL_TYPES[0]),
+            target_path: String::new(),
+            target_edited: false,
+            source_path: String::new(),
+            host: String::new(),
+            source_name: String::new(),
+            use_iqn: false,
+            iqn: String::new(),
+            show_xml: false,
+            xml_content: text_editor::Content::new(),
+            error: None,
+        };
+        wizard.show_options_by_pool();
+        Ok(wizard)
+    }
+
+    fn stub_pool(&self) -> StoragePool {
+        StoragePool::new(self.ptype.0, &self.name)
+    }
+
+    /// Reset the defaults that depend on the type
+    fn show_options_by_pool(&mut self) {
+        let pool = self.stub_pool();
+        self.target_edited = false;
+        self.target_path = pool
+            .default_target_path(self.conn.uri())
+            .unwrap_or_default();
+        self.source_name = pool.default_source_name().unwrap_or_default();
+    }
+
+    /// Whether builds create something, which for these types is
+    /// simply a directory
+    fn build_default(ptype: &str) -> bool {
+        matches!(ptype, "dir" | "fs" | "netfs")
+    }
+
+    /// The pool the form describes, before validation fills in the
+    /// defaults
+    pub fn build(&self) -> StoragePool {
+        let mut pool = self.stub_pool();
+        let value = |v: &str| Some(v.trim().to_string()).filter(|v| !v.is_empty());
+        if pool.supports_target_path() {
+            pool.set_target_path(value(&self.target_path).as_deref());
+        }
+        if pool.supports_hosts()
+            && let Some(host) = value(&self.host)
+        {
+            pool.add_host(&host);
+        }
+        if pool.supports_source_path()
+            && let Some(source) = value(&self.source_path)
+        {
+            pool.set_source_path(Some(&source));
+        }
+        if pool.supports_format() {
+            pool.set_format(Some("auto"));
+        }
+        if pool.supports_iqn() && self.use_iqn {
+            pool.set_iqn(value(&self.iqn).as_deref());
+        }
+        if pool.supports_source_name() {
+            pool.set_source_name(value(&self.source_name).as_deref());
+        }
+        pool
+    }
+
+    /// The pool to define: the XML tab's if it's shown, else the form's
+    fn build_xmlobj(&self) -> Result<StoragePool, String> {
+        if self.show_xml {
+            let xml = self.xml_content.text();
+            debug!("Using XML from xmleditor:\n{}", xml);
+            return StoragePool::parse(&xml).map_err(|e| format!("Error building XML: {}", e));
+        }
+        Ok(self.build())
+    }
+
+    fn create(&self) -> Result<String, String> {
+        let mut pool = self.build_xmlobj()?;
+        pool.validate(self.conn.as_ref())
+            .map_err(|e| format!("Error validating pool: {}", e))?;
+        debug!("Starting pool creation");
+        pool.install(
+            self.conn.as_ref(),
+            Self::build_default(&pool.ptype),
+            true,
+            true,
+        )
+        .map_err(|e| format!("Error creating pool: {}", e))?;
+        debug!("Pool creation succeeded");
+        Ok(pool.name)
+    }
+
+    /// Create the pool, returning its name. Failures stay in the wizard.
+    pub fn finish(&mut self) -> Option<String> {
+        match self.create() {
+            Ok(name) => Some(name),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    pub fn update(&mut self, msg: Message) {
+        match msg {
+            Message::NameChanged(v) => {
+                self.name = v;
+                if !self.target_edited {
+                    let pool = self.stub_pool();
+                    self.target_path = pool
+                        .default_target_path(self.conn.uri())
+                        .unwrap_or_default();
+                }
+            }
+            Message::TypeSelected(v) => {
+                self.ptype = v;
+                self.show_options_by_pool();
+            }
+            Message::TargetPathChanged(v) => {
+                self.target_path = v;
+                self.target_edited = true;
+            }
+            Message::SourcePathChanged(v) => self.source_path = v,
+            Message::HostChanged(v) => self.host = v,
+            Message::SourceNameChanged(v) => self.source_name = v,
+            Message::IqnToggle(v) => self.use_iqn = v,
+            Message::IqnChanged(v) => self.iqn = v,
+            Message::ShowXml(show) => {
+                if show && !self.show_xml {
+                    let xml = self.build().get_xml();
+                    self.xml_content = text_editor::Content::with_text(&xml.unwrap_or_default());
+                }
+                self.show_xml = show;
+            }
+            Message::XmlEdited(action) => self.xml_content.perform(action),
+            Message::Cancel | Message::Finish => {}
+        }
+    }
+
+    fn labeled<'a>(
+        label: &'a str,
+        widget: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        row![text(label).width(Length::Fixed(120.0)), widget.into()]
+            .spacing(8)
+            .align_y(Alignment::Center)
+            .into()
+    }
+
+    fn view_details(&self) -> Element<'_, Message> {
+        let pool = self.stub_pool();
+        let types: Vec<PoolType> = POOL_TYPES.iter().map(|t| PoolType(t)).collect();
+        let mut col: Column<Message> = column![
+            Self::labeled(
+                "Name:",
+                text_input("", &self.name)
+                    .on_input(Message::NameChanged)
+                    .padding(6),
+            ),
+            Self::labeled(
+                "Type:",
+                pick_list(types, Some(self.ptype), Message::TypeSelected),
+            ),
+        ]
+        .spacing(10);
+        if pool.supports_target_path() {
+            col = col.push(Self::labeled(
+                "Target Path:",
+                text_input("", &self.target_path)
+                    .on_input(Message::TargetPathChanged)
+                    .padding(6),
+            ));
+        }
+        if pool.supports_hosts() {
+            col = col.push(Self::labeled(
+                "Host Name:",
+                text_input("", &self.host)
+                    .on_input(Message::HostChanged)
+                    .padding(6),
+            ));
+        }
+        if pool.supports_source_path() {
+            let label = if pool.supports_iqn() {
+                "Source IQN:"
+            } else {
+                "Source Path:"
+            };
+            col = col.push(Self::labeled(
+                label,
+                text_input("", &self.source_path)
+                    .on_input(Message::SourcePathChanged)
+                    .padding(6),
+            ));
+        }
+        if pool.supports_source_name() {
+            let label = if pool.ptype == "logical" {
+                "Volgroup Name:"
+            } else {
+                "Source Name:"
+            };
+            col = col.push(Self::labeled(
+                label,
+                text_input("", &self.source_name)
+                    .on_input(Message::SourceNameChanged)
+                    .padding(6),
+            ));
+        }
+        if pool.supports_iqn() {
+            let mut iqn = text_input("", &self.iqn).padding(6);
+            if self.use_iqn {
+                iqn = iqn.on_input(Message::IqnChanged);
+            }
+            col = col.push(Self::labeled(
+                "Initiator IQN:",
+                row![
+                    checkbox("", self.use_iqn).on_toggle(Message::IqnToggle),
+                    iqn
+                ]
+                .spacing(4)
+                .align_y(Alignment::Center),
+            ));
+        }
+        col.into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let tab = |label, xml: bool| {
+            let style = if self.show_xml == xml {
+                button::primary
+            } else {
+                button::text
+            };
+            button(text(label))
+                .style(style)
+                .on_press(Message::ShowXml(xml))
+        };
+        let body = if self.show_xml {
+            text_editor(&self.xml_content)
+                .on_action(Message::XmlEdited)
+                .height(Length::Fixed(380.0))
+                .into()
+        } else {
+            self.view_details()
+        };
+        let mut col: Column<Message> = column![
+            text("Create a new storage pool").size(18),
+            row![tab("Details", false), tab("XML", true)].spacing(4),
+            body,
+        ]
+        .spacing(10);
+        if let Some(err) = &self.error {
+            col = col.push(text(err.clone()).size(14));
+        }
+        col.push(
+            row![
+                Space::with_width(Length::Fill),
+                button(text("Cancel")).on_press(Message::Cancel),
+                button(text("Finish"))
+                    .on_press_maybe((!self.name.is_empty()).then_some(Message::Finish)),
+            ]
+            .spacing(8),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::TestConnection;
+
+    #[test]
+    fn test_createpool() {
+        let conn: Arc<dyn Connection> = Arc::new(TestConnection::open("test:///default").unwrap());
+        let mut wiz = CreatePool::new(conn.clone()).unwrap();
+        assert_eq!(wiz.name, "pool");
+        assert_eq!(wiz.target_path, "/var/lib/libvirt/images/pool");
+        wiz.update(Message::NameChanged("mydir".into()));
+        assert_eq!(wiz.target_path, "/var/lib/libvirt/images/mydir");
+        assert_eq!(wiz.finish().as_deref(), Some("mydir"));
+        assert!(conn.pool_autostart("mydir").unwrap());
+        assert!(
+            conn.list_pools()
+                .unwrap()
+                .iter()
+                .any(|p| p.name == "mydir" && p.active)
+        );
+        assert!(wiz.finish().is_none());
+        assert!(wiz.error.as_deref().unwrap().contains("already in use"));
+
+        wiz.update(Message::TypeSelected(PoolType("iscsi")));
+        assert_eq!(wiz.target_path, "/dev/disk/by-path");
+        wiz.update(Message::NameChanged("iscsi".into()));
+        wiz.update(Message::HostChanged("iscsi.example.com".into()));
+        wiz.update(Message::SourcePathChanged(
+            "iqn.2024-01.com.example:t".into(),
+        ));
+        wiz.update(Message::IqnToggle(true));
+        wiz.update(Message::IqnChanged("iqn.2024-01.com.example:init".into()));
+        let pool = wiz.build();
+        assert_eq!(pool.source_path(), Some("iqn.2024-01.com.example:t"));
+        assert_eq!(pool.iqn(), Some("iqn.2024-01.com.example:init"));
+        assert_eq!(
+            pool.source.as_ref().unwrap().hosts[0].name,
+            "iscsi.example.com"
+        );
+
+        wiz.update(Message::TypeSelected(PoolType("rbd")));
+        assert_eq!(
+            (wiz.target_path.as_str(), wiz.source_name.as_str()),
+            ("", "rbd")
+        );
+        // Edits in the XML tab win over the form
+        wiz.update(Message::ShowXml(true));
+        assert!(wiz.xml_content.text().contains("<name>rbd</name>"));
+        wiz.xml_content = text_editor::Content::with_text(
+            &wiz.xml_content
+                .text()
+                .replace("<name>iscsi</name>", "<name>ceph</name>"),
+        );
+        assert_eq!(wiz.finish().as_deref(), Some("ceph"));
+        let xml = conn.pool_xml("ceph", false).unwrap();
+        assert_eq!(StoragePool::parse(&xml).unwrap().ptype, "rbd");
+    }
+}
@@ -0,0 +1,1728 @@
+// Create a new VM wizard (Iced port of virtManager/createvm.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! The wizard collects the same choices virt-install-rs takes on its
+//! command line. `CreateVmConfig::build_args` turns them into
+//! virt-install arguments and `virtinstall::build_guest` builds the
+//! guest from those, so a VM made here matches one made with the CLI.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use iced::widget::{
+    Column, Space, button, checkbox, column, container, pick_list, progress_bar, radio, row,
+    scrollable, text, text_editor, text_input,
+};
+use iced::{Alignment, Element, Length, Subscription, Task, Theme, window};
+use log::debug;
+
+use crate::asyncjob::AsyncJob;
+use crate::cli::{CliIo, quote_optval};
+use crate::connection::{self, Connection};
+use crate::diskbackend::{self, SearchData};
+use crate::generatename::generate_name;
+use crate::guest::{Guest, host_arch};
+use crate::installer::{self, INSTALL_POLL_INTERVAL, Installer};
+use crate::installertreemedia::InstallerTreeMedia;
+use crate::osdict::{OsVariant, osdb};
+use crate::progress::Meter;
+use crate::storage::default_pool_path;
+use crate::storagebrowse::{self, BrowseReason, Message as BrowseMsg, StorageBrowser};
+use crate::virtinstall;
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+const ARCHES: &[&str] = &[
+    "x86_64", "i686", "aarch64", "armv7l", "ppc64le", "ppc64", "s390x", "riscv64",
+];
+
+const NET_DEFAULT: &str = "Virtual network 'default': NAT";
+const NET_USER: &str = "Usermode networking";
+const NET_BRIDGE: &str = "Bridge device...";
+const NET_NONE: &str = "None";
+
+/// Machine types offered for `arch`, the first being the default
+fn machines_for_arch(arch: &str) -> &'static [&'static str] {
+    match arch {
+        "x86_64" | "i686" => &["q35", "pc"],
+        "aarch64" | "armv7l" | "riscv64" => &["virt"],
+        "ppc64le" | "ppc64" => &["pseries"],
+        "s390x" => &["s390-ccw-virtio"],
+        _ => &[],
+    }
+}
+
+/// The wizard pages, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Connection,
+    Method,
+    Source,
+    Resources,
+    Storage,
+    Finish,
+    Customize,
+}
+
+impl Step {
+    fn title(&self) -> &'static str {
+        match self {
+            Step::Connection => "Connection and architecture",
+            Step::Method => "Choose how you would like to install the operating system",
+            Step::Source => "Choose the install source and operating system",
+            Step::Resources => "Choose memory and CPU settings",
+            Step::Storage => "Enable storage for this virtual machine",
+            Step::Finish => "Ready to begin the installation",
+            Step::Customize => "Customize configuration before install",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallMethod {
+    /// Local ISO or CDROM
+    Media,
+    /// HTTP/HTTPS/FTP install tree
+    Network,
+    /// Build around an existing disk image
+    Import,
+    /// No install media, boot whatever gets attached later
+    Manual,
+    /// Run a single application in a container
+    ContainerApp,
+    /// Boot an OS tree in a container
+    ContainerOs,
+}
+
+impl InstallMethod {
+    fn label(&self) -> &'static str {
+        match self {
+            InstallMethod::Media => "Local install media (ISO image or CDROM)",
+            InstallMethod::Network => "Network Install (HTTP, HTTPS, or FTP)",
+            InstallMethod::Import => "Import existing disk image",
+            InstallMethod::Manual => "Manual install",
+            InstallMethod::ContainerApp => "Application container",
+            InstallMethod::ContainerOs => "Operating system container",
+        }
+    }
+
+    pub fn is_container(&self) -> bool {
+        matches!(
+            self,
+            InstallMethod::ContainerApp | InstallMethod::ContainerOs
+        )
+    }
+
+    /// Whether the install media can tell us the OS
+    fn can_detect(&self) -> bool {
+        matches!(self, InstallMethod::Media | InstallMethod::Network)
+    }
+
+    /// The methods a connection offers: containers for LXC, full
+    /// virtualization installs for everything else
+    fn available(uri: &str) -> &'static [InstallMethod] {
+        if uri.starts_with("lxc") {
+            &[InstallMethod::ContainerApp, InstallMethod::ContainerOs]
+        } else {
+            &[
+                InstallMethod::Media,
+                InstallMethod::Network,
+                InstallMethod::Import,
+                InstallMethod::Manual,
+            ]
+        }
+    }
+}
+
+/// Everything the wizard asks for
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateVmConfig {
+    pub uri: String,
+    pub arch: String,
+    /// None for the hypervisor default
+    pub machine: Option<String>,
+    pub method: InstallMethod,
+    pub media_path: String,
+    pub url: String,
+    pub kernel_args: String,
+    pub import_path: String,
+    pub container_init: String,
+    pub container_root: String,
+    /// Fill `os_name` from the install media
+    pub detect_os: bool,
+    pub os_name: Option<String>,
+    pub memory_mib: u64,
+    pub vcpus: u64,
+    pub storage_enabled: bool,
+    pub storage_size_gib: f64,
+    /// An existing or new image; None creates one in the default dir
+    pub storage_path: Option<String>,
+    pub name: String,
+    pub network: String,
+    pub bridge: String,
+    pub customize: bool,
+}
+
+impl Default for CreateVmConfig {
+    fn default() -> Self {
+        Self {
+            uri: "qemu:///system".to_string(),
+            arch: host_arch().to_string(),
+            machine: None,
+            method: InstallMethod::Media,
+            media_path: String::new(),
+            url: String::new(),
+            kernel_args: String::new(),
+            import_path: String::new(),
+            container_init: "/bin/sh".to_string(),
+            container_root: String::new(),
+            detect_os: true,
+            os_name: None,
+            memory_mib: 2048,
+            vcpus: 2,
+            storage_enabled: true,
+            storage_size_gib: 20.0,
+            storage_path: None,
+            name: String::new(),
+            network: NET_DEFAULT.to_string(),
+            bridge: String::new(),
+            customize: false,
+        }
+    }
+}
+
+impl CreateVmConfig {
+    fn osinfo(&self) -> Option<&'static OsVariant> {
+        self.os_name.as_deref().and_then(|n| osdb().lookup_os(n))
+    }
+
+    /// Whether the storage page applies: imports bring their own disk
+    /// and containers run from the host filesystem
+    fn wants_storage(&self) -> bool {
+        !self.method.is_container() && self.method != InstallMethod::Import
+    }
+
+    /// The install location to detect the OS from, if any
+    fn detect_location(&self) -> Option<&str> {
+        let loc = match self.method {
+            InstallMethod::Media => self.media_path.trim(),
+            InstallMethod::Network => self.url.trim(),
+            _ => return None,
+        };
+        Some(loc).filter(|l| !l.is_empty())
+    }
+
+    /// Memory, vCPU and disk size recommended by the chosen OS
+    pub fn apply_os_defaults(&mut self) {
+        let Some(osinfo) = self.osinfo() else {
+            return;
+        };
+        let res = osinfo.get_recommended_resources();
+        if let Some(ram) = res.get_recommended_ram(&self.arch) {
+            self.memory_mib = ram / (1024 * 1024);
+        }
+        if let Some(ncpus) = res.get_recommended_ncpus(&self.arch) {
+            self.vcpus = ncpus;
+        }
+        if let Some(storage) = res.get_recommended_storage(&self.arch) {
+            self.storage_size_gib = (storage / GIB) as f64;
+        }
+    }
+
+    /// Check the choices made on `step`, before moving past it
+    pub fn validate(&self, step: Step, conn: &dyn Connection) -> Result<(), String> {
+        match step {
+            Step::Connection | Step::Method | Step::Customize => Ok(()),
+            Step::Source => self.validate_source(conn),
+            Step::Resources => {
+                if self.memory_mib == 0 {
+                    return Err("Memory must be at least 1 MiB".to_string());
+                }
+                if self.vcpus == 0 {
+                    return Err("At least one vCPU is required".to_string());
+                }
+                Ok(())
+            }
+            Step::Storage => {
+                if !self.storage_enabled || !self.wants_storage() {
+                    return Ok(());
+                }
+                if self
+                    .storage_path
+                    .as_deref()
+                    .is_some_and(|p| p.trim().is_empty())
+                {
+                    return Err("A storage path must be specified.".to_string());
+                }
+                if self.storage_path.is_none() && self.storage_size_gib <= 0.0 {
+                    return Err("A disk size must be specified.".to_string());
+                }
+                Ok(())
+            }
+            Step::Finish => {
+                let name = self.name.trim();
+                if name.is_empty() {
+                    return Err("A name must be specified.".to_string());
+                }
+                if conn.lookup_domain(name).is_ok() {
+                    return Err(format!("Guest name '{}' is already in use.", name));
+                }
+                if self.network == NET_BRIDGE && self.bridge.trim().is_empty() {
+                    return Err("A bridge device name must be specified.".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn validate_source(&self, conn: &dyn Connection) -> Result<(), String> {
+        let missing = match self.method {
+            InstallMethod::Media if self.media_path.trim().is_empty() => {
+                Some("An install media selection is required.")
+            }
+            InstallMethod::Network if self.url.trim().is_empty() => {
+                Some("An install tree is required.")
+            }
+            InstallMethod::Import if self.import_path.trim().is_empty() => {
+                Some("A storage path to import is required.")
+            }
+            InstallMethod::ContainerApp if self.container_init.trim().is_empty() => {
+                Some("An application path is required.")
+            }
+            InstallMethod::ContainerOs if self.container_root.trim().is_empty() => {
+                Some("An OS directory path is required.")
+            }
+            _ => None,
+        };
+        if let Some(msg) = missing {
+            return Err(msg.to_string());
+        }
+        if self.method == InstallMethod::Import
+            && !diskbackend::path_definitely_exists(conn, self.import_path.trim())
+        {
+            return Err("The import path must point to an existing storage.".to_string());
+        }
+        if !self.method.is_container() && self.osinfo().is_none() {
+            let mut msg = "You must select an OS.".to_string();
+            if self.detect_os {
+                msg += "\n\nNo OS was detected from the install media. Uncheck \
+                        'Automatically detect' and choose one from the list.";
+            }
+            return Err(msg);
+        }
+        Ok(())
+    }
+
+    /// Path for a new disk image in `dir`, named after the VM and not
+    /// clashing with existing files
+    pub fn default_disk_path(&self, dir: &Path) -> Result<String, String> {
+        let name = generate_name(
+            self.name.trim(),
+            |n| dir.join(n).exists(),
+            ".qcow2",
+            1,
+            "-",
+            false,
+        )?;
+        Ok(dir.join(name).to_string_lossy().into_owned())
+    }
+
+    /// The virt-install arguments for this config. `disk_path` is the
+    /// VM's disk when the storage page applies, see `default_disk_path`.
+    pub fn build_args(&self, disk_path: Option<&str>) -> Vec<String> {
+        let mut args: Vec<String> = vec![];
+        let push = |args: &mut Vec<String>, flag: &str, val: &str| {
+            args.push(flag.to_string());
+            args.push(val.to_string());
+        };
+        push(&mut args, "--name", self.name.trim());
+        push(&mut args, "--memory", &self.memory_mib.to_string());
+        push(&mut args, "--vcpus", &self.vcpus.to_string());
+
+        let mut boot = vec![];
+        if self.uri.starts_with("lxc") {
+            boot.push("domain_type=lxc".to_string());
+        }
+        if self.method.is_container() {
+            boot.push("os_type=exe".to_string());
+        }
+        if self.arch != host_arch() {
+            boot.push(format!("arch={}", self.arch));
+        }
+        if let Some(machine) = &self.machine {
+            boot.push(format!("machine={}", machine));
+        }
+        match self.method {
+            InstallMethod::ContainerApp => {
+                boot.push(format!("init={}", quote_optval(self.container_init.trim())))
+            }
+            InstallMethod::ContainerOs => boot.push("init=/sbin/init".to_string()),
+            _ => {}
+        }
+        if !boot.is_empty() {
+            push(&mut args, "--boot", &boot.join(","));
+        }
+
+        match self.method {
+            InstallMethod::Media => push(&mut args, "--cdrom", self.media_path.trim()),
+            InstallMethod::Network => {
+                push(&mut args, "--location", &quote_optval(self.url.trim()));
+                if !self.kernel_args.trim().is_empty() {
+                    push(&mut args, "--extra-args", self.kernel_args.trim());
+                }
+            }
+            InstallMethod::Import => {
+                args.push("--import".to_string());
+                push(
+                    &mut args,
+                    "--disk",
+                    &format!("path={}", quote_optval(self.import_path.trim())),
+                );
+            }
+            InstallMethod::Manual => args.push("--import".to_string()),
+            InstallMethod::ContainerApp => push(&mut args, "--disk", "none"),
+            InstallMethod::ContainerOs => push(
+                &mut args,
+                "--filesystem",
+                &format!("{},/", quote_optval(self.container_root.trim())),
+            ),
+        }
+        if let Some(name) = &self.os_name
+            && !self.method.is_container()
+        {
+            push(&mut args, "--osinfo", name);
+        }
+
+        if self.wants_storage() {
+            match disk_path.filter(|_| self.storage_enabled) {
+                Some(path) if !Path::new(path).exists() => push(
+                    &mut args,
+                    "--disk",
+                    &format!(
+                        "path={},size={},format=qcow2",
+                        quote_optval(path),
+                        self.storage_size_gib
+                    ),
+                ),
+                Some(path) => push(&mut args, "--disk", &format!("path={}", quote_optval(path))),
+                None => push(&mut args, "--disk", "none"),
+            }
+        }
+
+        match self.network.as_str() {
+            NET_USER => push(&mut args, "--network", "user"),
+            NET_BRIDGE => push(
+                &mut args,
+                "--network",
+                &format!("bridge={}", quote_optval(self.bridge.trim())),
+            ),
+            NET_NONE => args.push("--nonetworks".to_string()),
+            _ => push(&mut args, "--network", "network=default"),
+        }
+        args
+    }
+
+    /// Build the guest and installer the same way virt-install does.
+    /// Returns them with any warnings virt-install would print.
+    pub fn prepare(
+        &self,
+        conn: &dyn Connection,
+        disk_path: Option<&str>,
+    ) -> Result<(Guest, Installer, Vec<String>), String> {
+        let args = self.build_args(disk_path);
+        debug!("Create VM virt-install equivalent args: {:?}", args);
+        let mut opts = virtinstall::parse_args(&args)?;
+        opts.quiet = true;
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let (guest, installer) = {
+            let mut io = CliIo {
+                stdin: None,
+                stdout: &mut out,
+                stderr: &mut err,
+            };
+            virtinstall::build_guest(&opts, conn, &mut io)?
+        };
+        let warnings = String::from_utf8_lossy(&err)
+            .lines()
+            .filter_map(|l| l.strip_prefix("WARNING  "))
+            .map(str::to_string)
+            .collect();
+        Ok((guest, installer, warnings))
+    }
+}
+
+/// The OS variant detected from `location`, like virt-install's
+/// `--osinfo detect=on`. Unreadable media just detects nothing.
+pub fn detect_media_os(conn: &dyn Connection, location: &str, arch: &str) -> Option<String> {
+    let ret = InstallerTreeMedia::new(conn, Some(location), None, None, None, None, None)
+        .and_then(|mut media| media.detect_distro(arch, "hvm"));
+    match ret {
+        Ok(ret) => ret,
+        Err(e) => {
+            debug!("Error detecting distro from '{}': {}", location, e);
+            None
+        }
+    }
+}
+
+/// Create the domain, along with the disk images virt-install queued.
+/// Returns the name, and the final XML if the guest needs a restart
+/// once the install boot shuts off.
+fn run_install(
+    conn: &dyn Connection,
+    mut guest: Guest,
+    mut installer: Installer,
+    meter: &dyn Meter,
+) -> Result<(String, Option<String>), String> {
+    let (_, final_xml) = installer.start_install(conn, &mut guest, meter, false)?;
+    let restart = installer.requires_postboot_xml_changes();
+    Ok((
+        guest.name().unwrap_or_default(),
+        restart.then_some(final_xml),
+    ))
+}
+
+/// Wait for the install boot to shut off, then boot the final XML
+async fn restart_after_install(
+    conn: Arc<dyn Connection>,
+    name: String,
+    final_xml: String,
+) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        installer::wait_for_install(conn.as_ref(), &name, INSTALL_POLL_INTERVAL)?;
+        installer::restart_installed(conn.as_ref(), &name, &final_xml)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|r| r)
+}
+
+/// Public facade to show the Create VM wizard in its own window
+pub struct VmmCreateVm;
+
+impl VmmCreateVm {
+    pub fn show_instance() -> Result<(), String> {
+        debug!("Launching Create VM wizard window");
+        iced::application(
+            "New VM",
+            CreateVmApp::update_static,
+            CreateVmApp::view_static,
+        )
+        .subscription(CreateVmApp::subscription)
+        .theme(|_| Theme::default())
+        .window(window::Settings {
+            size: iced::Size::new(700.0, 640.0),
+            position: window::Position::Centered,
+            resizable: true,
+            decorations: true,
+            ..Default::default()
+        })
+        .run_with(CreateVmApp::new_static)
+        .map_err(|e| format!("Error launching 'New VM' wizard: {}", e))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Back,
+    Forward,
+    Finish,
+    /// Cancel, or close once the VM is created
+    Close,
+    ConnectionChanged(ConnectionMsg),
+    MethodSelected(InstallMethod),
+    SourceChanged(SourceMsg),
+    OsDetected(String, Option<String>),
+    ResourcesChanged(ResourcesMsg),
+    StorageChanged(StorageMsg),
+    FinishChanged(FinishMsg),
+    XmlEdited(text_editor::Action),
+    /// Open the storage browser for a path field
+    Browse(BrowseReason, BrowseField),
+    Browser(BrowseMsg),
+    /// Answer to the offer to fix the emulator's search permissions
+    FixPerms(bool),
+    BeginInstall,
+    Tick,
+    Installed(Result<(String, Option<String>), String>),
+    Restarted(Result<(), String>),
+}
+
+#[derive(Debug, Clone)]
+pub enum ConnectionMsg {
+    UriChanged(String),
+    ArchChanged(String),
+    MachineChanged(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum SourceMsg {
+    MediaPathChanged(String),
+    UrlChanged(String),
+    KernelArgsChanged(String),
+    ImportPathChanged(String),
+    ContainerInitChanged(String),
+    ContainerRootChanged(String),
+    DetectToggle(bool),
+    SearchChanged(String),
+    EolToggle(bool),
+    OsSelected(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum ResourcesMsg {
+    MemoryChanged(String),
+    VcpusChanged(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum StorageMsg {
+    EnableToggle(bool),
+    SizeChanged(String),
+    CustomToggle(bool),
+    PathChanged(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum FinishMsg {
+    NameChanged(String),
+    NetworkChanged(String),
+    BridgeChanged(String),
+    CustomizeToggle(bool),
+}
+
+/// Fills a path field with the path chosen in the storage browser
+type BrowseField = fn(String) -> Message;
+
+pub struct CreateVmApp {
+    step: Step,
+    config: CreateVmConfig,
+    conn: Option<Arc<dyn Connection>>,
+    /// Location the last detection ran on
+    detected_for: Option<String>,
+    detecting: bool,
+    os_search: String,
+    os_include_eol: bool,
+    memory_text: String,
+    vcpus_text: String,
+    size_text: String,
+    /// The name was typed by the user, so OS changes keep it
+    name_edited: bool,
+    error: Option<String>,
+    warnings: Vec<String>,
+    /// Installer waiting on "Begin Installation", the guest being in
+    /// the XML editor
+    pending: Option<Installer>,
+    xml_content: text_editor::Content,
+    job: Option<AsyncJob>,
+    finished: Option<String>,
+    /// The storage browser and the field it fills, while open
+    browser: Option<(StorageBrowser, BrowseField)>,
+    /// A path the emulator may not be able to reach, while asking
+    /// whether to fix that
+    perms_prompt: Option<(String, SearchData)>,
+}
+
+impl CreateVmApp {
+    fn new() -> (Self, Task<Message>) {
+        let config = CreateVmConfig::default();
+        let state = Self {
+            step: Step::Connection,
+            memory_text: config.memory_mib.to_string(),
+            vcpus_text: config.vcpus.to_string(),
+            size_text: config.storage_size_gib.to_string(),
+            config,
+            conn: None,
+            detected_for: None,
+            detecting: false,
+            os_search: String::new(),
+            os_include_eol: false,
+            name_edited: false,
+            error: None,
+            warnings: vec![],
+            pending: None,
+            xml_content: text_editor::Content::new(),
+            job: None,
+            finished: None,
+            browser: None,
+            perms_prompt: None,
+        };
+        (state, Task::none())
+    }
+
+    fn set_os(&mut self, name: Option<String>) {
+        self.config.os_name = name;
+        self.config.apply_os_defaults();
+        self.memory_text = self.config.memory_mib.to_string();
+        self.vcpus_text = self.config.vcpus.to_string();
+        self.size_text = self.config.storage_size_gib.to_string();
+    }
+
+    /// Name from the OS, like `fedora29` or `fedora29-1`, unused on the
+    /// connection
+    fn default_name(&self) -> Result<String, String> {
+        let base = match (self.config.method, &self.config.os_name) {
+            (m, _) if m.is_container() => "container",
+            (_, Some(name)) => name.as_str(),
+            _ => "vm",
+        };
+        let conn = self.conn.clone();
+        generate_name(
+            base,
+            |n| conn.as_ref().is_some_and(|c| c.lookup_domain(n).is_ok()),
+            "",
+            1,
+            "-",
+            false,
+        )
+    }
+
+    fn next_step(&self) -> Step {
+        match self.step {
+            Step::Connection => Step::Method,
+            Step::Method => Step::Source,
+            Step::Source => Step::Resources,
+            Step::Resources if self.config.wants_storage() => Step::Storage,
+            Step::Resources | Step::Storage => Step::Finish,
+            Step::Finish | Step::Customize => Step::Customize,
+        }
+    }
+
+    fn prev_step(&self) -> Step {
+        match self.step {
+            Step::Connection | Step::Method => Step::Connection,
+            Step::Source => Step::Method,
+            Step::Resources => Step::Source,
+            Step::Storage => Step::Resources,
+            Step::Finish if self.config.wants_storage() => Step::Storage,
+            Step::Finish => Step::Resources,
+            Step::Customize => Step::Finish,
+        }
+    }
+
+    fn connect(&mut self) -> Result<(), String> {
+        let uri = self.config.uri.trim().to_string();
+        if self.conn.as_ref().is_some_and(|c| c.uri() == uri) {
+            return Ok(());
+        }
+        let conn: Arc<dyn Connection> = Arc::from(connection::open(Some(&uri))?);
+        self.conn = Some(conn);
+        if !InstallMethod::available(&uri).contains(&self.config.method) {
+            self.config.method = InstallMethod::available(&uri)[0];
+        }
+        Ok(())
+    }
+
+    fn forward(&mut self) -> Task<Message> {
+        self.error = None;
+        if self.step == Step::Connection
+            && let Err(e) = self.connect()
+        {
+            self.error = Some(format!("Error connecting to '{}': {}", self.config.uri, e));
+            return Task::none();
+        }
+        let Some(conn) = self.conn.clone() else {
+            return Task::none();
+        };
+
+        if self.step == Step::Source
+            && self.config.detect_os
+            && self.config.method.can_detect()
+            && let Some(location) = self.config.detect_location()
+            && self.detected_for.as_deref() != Some(location)
+        {
+            // Detect in the background, then try moving forward again
+            self.detecting = true;
+            let location = location.to_string();
+            let arch = self.config.arch.clone();
+            return Task::perform(
+                async move {
+                    let loc = location.clone();
+                    let ret = tokio::task::spawn_blocking(move || {
+                        detect_media_os(conn.as_ref(), &loc, &arch)
+                    })
+                    .await
+                    .unwrap_or(None);
+                    (location, ret)
+                },
+                |(location, ret)| Message::OsDetected(location, ret),
+            );
+        }
+
+        if let Err(e) = self.config.validate(self.step, conn.as_ref()) {
+            self.error = Some(e);
+            return Task::none();
+        }
+        if let Some(path) = self.search_path() {
+            let search = diskbackend::check_path_search(conn.as_ref(), &path);
+            if !search.fixlist.is_empty() {
+                debug!("No search access for dirs: {:?}", search.fixlist);
+                self.perms_prompt = Some((path, search));
+                return Task::none();
+            }
+        }
+        self.advance()
+    }
+
+    /// Move past a validated step
+    fn advance(&mut self) -> Task<Message> {
+        if self.step == Step::Finish {
+            return self.finish();
+        }
+        self.step = self.next_step();
+        if self.step == Step::Finish && !self.name_edited {
+            match self.default_name() {
+                Ok(name) => self.config.name = name,
+                Err(e) => self.error = Some(e),
+            }
+        }
+        Task::none()
+    }
+
+    /// The local path the emulator has to reach for the current step
+    fn search_path(&self) -> Option<String> {
+        let path = match (self.step, self.config.method) {
+            (Step::Source, InstallMethod::Media) => self.config.media_path.trim().to_string(),
+            (Step::Source, InstallMethod::Import) => self.config.import_path.trim().to_string(),
+            (Step::Storage, _) => self.disk_path().ok().flatten()?,
+            _ => return None,
+        };
+        (!path.is_empty()).then_some(path)
+    }
+
+    /// Fix the search permissions if asked to, then carry on either way
+    fn fix_perms(&mut self, fix: bool) -> Task<Message> {
+        let Some((_, search)) = self.perms_prompt.take() else {
+            return Task::none();
+        };
+        if fix {
+            debug!("Attempting to correct permission issues.");
+            let errors = diskbackend::fix_path_search(&search);
+            if !errors.is_empty() {
+                let details: String = errors
+                    .iter()
+                    .map(|(dir, e)| format!("{} : {}\n", dir, e))
+                    .collect();
+                debug!("Permission errors:\n{}", details);
+                self.error = Some(format!(
+                    "Errors were encountered changing permissions for the following \
+                     directories:\n{}\nIt is very likely the VM will fail to start up.",
+                    details
+                ));
+            }
+        }
+        self.advance()
+    }
+
+    fn disk_path(&self) -> Result<Option<String>, String> {
+        if !self.config.wants_storage() || !self.config.storage_enabled {
+            return Ok(None);
+        }
+        Ok(Some(match &self.config.storage_path {
+            Some(path) => path.trim().to_string(),
+            None => self
+                .config
+                .default_disk_path(&default_pool_path(&self.config.uri))?,
+        }))
+    }
+
+    /// Build the guest, then install it or show it for customizing
+    fn finish(&mut self) -> Task<Message> {
+        let Some(conn) = self.conn.clone() else {
+            return Task::none();
+        };
+        if let Err(e) = self.config.validate(Step::Finish, conn.as_ref()) {
+            self.error = Some(e);
+            return Task::none();
+        }
+        let ret = self
+            .disk_path()
+            .and_then(|disk_path| self.config.prepare(conn.as_ref(), disk_path.as_deref()));
+        let (guest, installer, warnings) = match ret {
+            Ok(ret) => ret,
+            Err(e) => {
+                self.error = Some(format!("Unable to complete install: {}", e));
+                return Task::none();
+            }
+        };
+        self.warnings = warnings;
+        if self.config.customize {
+            self.xml_content = text_editor::Content::with_text(&guest.get_xml());
+            self.pending = Some(installer);
+            self.step = Step::Customize;
+            return Task::none();
+        }
+        self.start_install(conn, guest, installer)
+    }
+
+    fn begin_install(&mut self) -> Task<Message> {
+        let Some(conn) = self.conn.clone() else {
+            return Task::none();
+        };
+        let guest = match Guest::parse(&self.xml_content.text()) {
+            Ok(guest) => guest,
+            Err(e) => {
+                self.error = Some(format!("Error parsing the edited XML: {}", e));
+                return Task::none();
+            }
+        };
+        let Some(installer) = self.pending.take() else {
+            return Task::none();
+        };
+        self.start_install(conn, guest, installer)
+    }
+
+    fn start_install(
+        &mut self,
+        conn: Arc<dyn Connection>,
+        guest: Guest,
+        installer: Installer,
+    ) -> Task<Message> {
+        self.error = None;
+        let job = AsyncJob::new("Creating Virtual Machine", false);
+        self.job = Some(job.clone());
+        Task::perform(
+            job.run(move |job| run_install(conn.as_ref(), guest, installer, job)),
+            Message::Installed,
+        )
+    }
+
+    fn update(&mut self, msg: Message) -> Task<Message> {
+        match msg {
+            Message::Back => {
+                self.error = None;
+                self.perms_prompt = None;
+                if self.step == Step::Customize {
+                    self.pending = None;
+                }
+                self.step = self.prev_step();
+                Task::none()
+            }
+            Message::Forward => self.forward(),
+            Message::Finish => self.forward(),
+            Message::Close => window::get_latest().and_then(window::close),
+            Message::ConnectionChanged(cmsg) => {
+                match cmsg {
+                    ConnectionMsg::UriChanged(uri) => self.config.uri = uri,
+                    ConnectionMsg::ArchChanged(arch) => {
+                        self.config.arch = arch;
+                        self.config.machine = None;
+                    }
+                    ConnectionMsg::MachineChanged(machine) => {
+                        self.config.machine = Some(machine).filter(|m| m != "Default");
+                    }
+                }
+                Task::none()
+            }
+            Message::MethodSelected(method) => {
+                self.config.method = method;
+                Task::none()
+            }
+            Message::SourceChanged(smsg) => {
+                match smsg {
+                    SourceMsg::MediaPathChanged(v) => self.config.media_path = v,
+                    SourceMsg::UrlChanged(v) => self.config.url = v,
+                    SourceMsg::KernelArgsChanged(v) => self.config.kernel_args = v,
+                    SourceMsg::ImportPathChanged(v) => self.config.import_path = v,
+                    SourceMsg::ContainerInitChanged(v) => self.config.container_init = v,
+                    SourceMsg::ContainerRootChanged(v) => self.config.container_root = v,
+                    SourceMsg::DetectToggle(v) => {
+                        self.config.detect_os = v;
+                        self.detected_for = None;
+                    }
+                    SourceMsg::SearchChanged(v) => self.os_search = v,
+                    SourceMsg::EolToggle(v) => self.os_include_eol = v,
+                    SourceMsg::OsSelected(name) => self.set_os(Some(name)),
+                }
+                Task::none()
+            }
+            Message::OsDetected(location, ret) => {
+                self.detecting = false;
+                debug!("Detected OS {:?} from '{}'", ret, location);
+                self.detected_for = Some(location);
+                self.set_os(ret);
+                self.forward()
+            }
+            Message::ResourcesChanged(rmsg) => {
+                match rmsg {
+                    ResourcesMsg::MemoryChanged(v) => {
+                        self.config.memory_mib = v.trim().parse().unwrap_or(0);
+                        self.memory_text = v;
+                    }
+                    ResourcesMsg::VcpusChanged(v) => {
+                        self.config.vcpus = v.trim().parse().unwrap_or(0);
+                        self.vcpus_text = v;
+                    }
+                }
+                Task::none()
+            }
+            Message::StorageChanged(smsg) => {
+                match smsg {
+                    StorageMsg::EnableToggle(v) => self.config.storage_enabled = v,
+                    StorageMsg::SizeChanged(v) => {
+                        self.config.storage_size_gib = v.trim().parse().unwrap_or(0.0);
+                        self.size_text = v;
+                    }
+                    StorageMsg::CustomToggle(v) => {
+                        self.config.storage_path = v.then(String::new);
+                    }
+                    StorageMsg::PathChanged(v) => self.config.storage_path = Some(v),
+                }
+                Task::none()
+            }
+            Message::FinishChanged(fmsg) => {
+                match fmsg {
+                    FinishMsg::NameChanged(v) => {
+                        self.name_edited = true;
+                        self.config.name = v;
+                    }
+                    FinishMsg::NetworkChanged(v) => self.config.network = v,
+                    FinishMsg::BridgeChanged(v) => self.config.bridge = v,
+                    FinishMsg::CustomizeToggle(v) => self.config.customize = v,
+                }
+                Task::none()
+            }
+            Message::XmlEdited(action) => {
+                self.xml_content.perform(action);
+                Task::none()
+            }
+            Message::Browse(reason, field) => {
+                if let Some(conn) = self.conn.clone() {
+                    // The name is only a suggestion, so go without on errors
+                    let hint = match self.config.name.trim() {
+                        "" => self.default_name().ok(),
+                        name => Some(name.to_string()),
+                    };
+                    let browser = StorageBrowser::new(conn, reason, hint.as_deref());
+                    self.browser = Some((browser, field));
+                }
+                Task::none()
+            }
+            Message::Browser(BrowseMsg::Cancel) => {
+                self.browser = None;
+                Task::none()
+            }
+            Message::Browser(BrowseMsg::Finish) => {
+                let Some((browser, field)) = &mut self.browser else {
+                    return Task::none();
+                };
+                match browser.finish() {
+                    Some(path) => {
+                        let msg = field(path);
+                        self.browser = None;
+                        Task::done(msg)
+                    }
+                    None => Task::none(),
+                }
+            }
+            Message::Browser(inner) => match &mut self.browser {
+                Some((browser, _)) => browser.update(inner).map(Message::Browser),
+                None => Task::none(),
+            },
+            Message::FixPerms(fix) => self.fix_perms(fix),
+            Message::BeginInstall => self.begin_install(),
+            Message::Tick => Task::none(),
+            Message::Installed(ret) => {
+                self.job = None;
+                match ret {
+                    Ok((name, final_xml)) => {
+                        debug!("Created VM '{}'", name);
+                        self.finished = Some(name.clone());
+                        if let Some(final_xml) = final_xml
+                            && let Some(conn) = self.conn.clone()
+                        {
+                            return Task::perform(
+                                restart_after_install(conn, name, final_xml),
+                                Message::Restarted,
+                            );
+                        }
+                    }
+                    Err(e) => self.error = Some(format!("Unable to complete install: {}", e)),
+                }
+                Task::none()
+            }
+            Message::Restarted(ret) => {
+                if let Err(e) = ret {
+                    self.error = Some(format!("Error continuing install: {}", e));
+                }
+                Task::none()
+            }
+        }
+    }
+
+    /// Redraw the progress bar while the install job runs
+    pub fn subscription(&self) -> Subscription<Message> {
+        if self.job.is_some() {
+            iced::time::every(Duration::from_millis(100)).map(|_| Message::Tick)
+        } else {
+            Subscription::none()
+        }
+    }
+
+    fn view(&self) -> Element<'_, Message> {
+        let header = column![
+            text("Create a new virtual machine").size(22),
+            text(format!(
+                "Step {}: {}",
+                self.step_number(),
+                self.step.title()
+            ))
+            .size(14),
+        ]
+        .spacing(4);
+
+        let mut body: Column<Message> = column![].spacing(12);
+        if let Some(name) = &self.finished {
+            body = body.push(text(format!(
+                "Domain creation completed. The VM '{}' was created.",
+                name
+            )));
+        } else {
+            body = body.push(match self.step {
+                Step::Connection => self.view_connection_page(),
+                Step::Method => self.view_method_page(),
+                Step::Source => self.view_source_page(),
+                Step::Resources => self.view_resources_page(),
+                Step::Storage => self.view_storage_page(),
+                Step::Finish => self.view_finish_page(),
+                Step::Customize => self.view_customize_page(),
+            });
+        }
+        for warning in &self.warnings {
+            body = body.push(text(format!("Warning: {}", warning)).size(13));
+        }
+        if let Some(err) = &self.error {
+            body = body.push(text(err.clone()).size(14));
+        }
+        if let Some((path, _)) = &self.perms_prompt {
+            body = body.push(
+                column![
+                    text(format!(
+                        "The emulator may not have search permissions for the path '{}'.",
+                        path
+                    )),
+                    text("Do you want to correct this now?").size(13),
+                    row![
+                        button(text("No")).on_press(Message::FixPerms(false)),
+                        button(text("Yes")).on_press(Message::FixPerms(true)),
+                    ]
+                    .spacing(8),
+                ]
+                .spacing(6),
+            );
+        }
+        if let Some(job) = &self.job {
+            let progress = job.progress();
+            body = body.push(text(progress.text.clone()).size(13));
+            body = body.push(progress_bar(0.0..=1.0, progress.fraction().unwrap_or(0.0)));
+        }
+
+        let content = column![
+            header,
+            scrollable(body).height(Length::Fill),
+            self.view_footer()
+        ]
+        .padding(16)
+        .spacing(12);
+        let window = container(content).width(Length::Fill).height(Length::Fill);
+        match &self.browser {
+            Some((browser, _)) => {
+                storagebrowse::modal(window, browser.view().map(Message::Browser))
+            }
+            None => window.into(),
+        }
+    }
+
+    fn step_number(&self) -> usize {
+        let mut steps = vec![
+            Step::Connection,
+            Step::Method,
+            Step::Source,
+            Step::Resources,
+        ];
+        if self.config.wants_storage() {
+            steps.push(Step::Storage);
+        }
+        steps.extend([Step::Finish, Step::Customize]);
+        steps.iter().position(|s| *s == self.step).unwrap_or(0) + 1
+    }
+
+    fn view_footer(&self) -> Element<'_, Message> {
+        if self.finished.is_some() {
+            return row![
+                Space::with_width(Length::Fill),
+                button(text("Close")).on_press(Message::Close),
+            ]
+            .into();
+        }
+        let busy = self.detecting || self.job.is_some();
+        let back = button(text("Back"))
+            .on_press_maybe((self.step != Step::Connection && !busy).then_some(Message::Back));
+        let next = match self.step {
+            Step::Finish => {
+                button(text("Finish")).on_press_maybe((!busy).then_some(Message::Finish))
+            }
+            Step::Customize => button(text("Begin Installation"))
+                .on_press_maybe((!busy).then_some(Message::BeginInstall)),
+            _ => button(text(if self.detecting {
+                "Detecting…"
+            } else {
+                "Forward"
+            }))
+            .on_press_maybe((!busy && self.perms_prompt.is_none()).then_some(Message::Forward)),
+        };
+        row![
+            button(text("Cancel")).on_press_maybe((!busy).then_some(Message::Close)),
+            Space::with_width(Length::Fill),
+            back,
+            next,
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center)
+        .into()
+    }
+
+    fn view_connection_page(&self) -> Element<'_, Message> {
+        let uri = text_input("qemu:///system", &self.config.uri)
+            .on_input(|v| Message::ConnectionChanged(ConnectionMsg::UriChanged(v)))
+            .padding(8);
+        let arches: Vec<String> = ARCHES.iter().map(|a| a.to_string()).collect();
+        let arch = pick_list(arches, Some(self.config.arch.clone()), |v| {
+            Message::ConnectionChanged(ConnectionMsg::ArchChanged(v))
+        });
+        let mut machines = vec!["Default".to_string()];
+        machines.extend(
+            machines_for_arch(&self.config.arch)
+                .iter()
+                .map(|m| m.to_string()),
+        );
+        let machine = pick_list(
+            machines,
+            Some(
+                self.config
+                    .machine
+                    .clone()
+                    .unwrap_or_else(|| "Default".to_string()),
+            ),
+            |v| Message::ConnectionChanged(ConnectionMsg::MachineChanged(v)),
+        );
+        column![
+            row![text("Connection:"), uri]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            row![text("Architecture:"), arch]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            row![text("Machine type:"), machine]
+                .spacing(8)
+                .align_y(Alignment::Center),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn view_method_page(&self) -> Element<'_, Message> {
+        let mut col: Column<Message> = column![].spacing(10);
+        for method in InstallMethod::available(&self.config.uri) {
+            col = col.push(radio(
+                method.label(),
+                *method,
+                Some(self.config.method),
+                Message::MethodSelected,
+            ));
+        }
+        col.into()
+    }
+
+    fn view_source_page(&self) -> Element<'_, Message> {
+        let source = |placeholder: &str, value: &str, f: fn(String) -> SourceMsg| {
+            text_input(placeholder, value)
+                .on_input(move |v| Message::SourceChanged(f(v)))
+                .padding(8)
+        };
+        let browsable = |input, reason, field| {
+            row![
+                input,
+                button(text("Browse...")).on_press(Message::Browse(reason, field)),
+            ]
+            .spacing(8)
+            .align_y(Alignment::Center)
+        };
+        let mut col: Column<Message> = column![].spacing(10);
+        match self.config.method {
+            InstallMethod::Media => {
+                col = col.push(text("Choose ISO or CDROM install media:"));
+                col = col.push(browsable(
+                    source(
+                        "/path/to/install.iso",
+                        &self.config.media_path,
+                        SourceMsg::MediaPathChanged,
+                    ),
+                    BrowseReason::IsoMedia,
+                    |p| Message::SourceChanged(SourceMsg::MediaPathChanged(p)),
+                ));
+            }
+            InstallMethod::Network => {
+                col = col.push(text("Provide the operating system install URL:"));
+                col = col.push(source(
+                    "https://host/path",
+                    &self.config.url,
+                    SourceMsg::UrlChanged,
+                ));
+                col = col.push(text("Kernel arguments:"));
+                col = col.push(source(
+                    "",
+                    &self.config.kernel_args,
+                    SourceMsg::KernelArgsChanged,
+                ));
+            }
+            InstallMethod::Import => {
+                col = col.push(text("Provide the existing storage path:"));
+                col = col.push(browsable(
+                    source(
+                        "/path/to/disk.img",
+                        &self.config.import_path,
+                        SourceMsg::ImportPathChanged,
+                    ),
+                    BrowseReason::Image,
+                    |p| Message::SourceChanged(SourceMsg::ImportPathChanged(p)),
+                ));
+            }
+            InstallMethod::Manual => {
+                col = col.push(text("The VM is created without install media."));
+            }
+            InstallMethod::ContainerApp => {
+                col = col.push(text("Provide the application path:"));
+                col = col.push(source(
+                    "/bin/sh",
+                    &self.config.container_init,
+                    SourceMsg::ContainerInitChanged,
+                ));
+            }
+            InstallMethod::ContainerOs => {
+                col = col.push(text("Provide the existing OS root directory:"));
+                col = col.push(browsable(
+                    source(
+                        "/path/to/rootfs",
+                        &self.config.container_root,
+                        SourceMsg::ContainerRootChanged,
+                    ),
+                    BrowseReason::Fs,
+                    |p| Message::SourceChanged(SourceMsg::ContainerRootChanged(p)),
+                ));
+            }
+        }
+        if !self.config.method.is_container() {
+            col = col.push(self.view_os_picker());
+        }
+        col.into()
+    }
+
+    fn view_os_picker(&self) -> Element<'_, Message> {
+        let label = match self.config.osinfo() {
+            Some(os) => format!("{} ({})", os.label, os.name),
+            None if self.config.detect_os && self.config.method.can_detect() => {
+                "None detected yet".to_string()
+            }
+            None => "None selected".to_string(),
+        };
+        let mut col: Column<Message> = column![
+            text("Choose the operating system you are installing:"),
+            text(label).size(14),
+        ]
+        .spacing(8);
+        if self.config.method.can_detect() {
+            col = col.push(
+                checkbox(
+                    "Automatically detect from the installation media / source",
+                    self.config.detect_os,
+                )
+                .on_toggle(|v| Message::SourceChanged(SourceMsg::DetectToggle(v))),
+            );
+        }
+        if self.config.detect_os && self.config.method.can_detect() {
+            return col.into();
+        }
+
+        col = col.push(
+            text_input("Type to start searching...", &self.os_search)
+                .on_input(|v| Message::SourceChanged(SourceMsg::SearchChanged(v)))
+                .padding(8),
+        );
+        col = col.push(
+            checkbox("Include end of life operating systems", self.os_include_eol)
+                .on_toggle(|v| Message::SourceChanged(SourceMsg::EolToggle(v))),
+        );
+        let mut list: Column<Message> = column![].spacing(2);
+        for os in osdb()
+            .search_os(&self.os_search, self.os_include_eol)
+            .into_iter()
+            .take(50)
+        {
+            list = list.push(
+                button(text(format!("{} ({})", os.label, os.name)).width(Length::Fill))
+                    .on_press(Message::SourceChanged(SourceMsg::OsSelected(
+                        os.name.clone(),
+                    )))
+                    .padding(4),
+            );
+        }
+        col.push(scrollable(list).height(Length::Fixed(200.0)))
+            .into()
+    }
+
+    fn view_resources_page(&self) -> Element<'_, Message> {
+        let memory = text_input("2048", &self.memory_text)
+            .on_input(|v| Message::ResourcesChanged(ResourcesMsg::MemoryChanged(v)))
+            .padding(8);
+        let vcpus = text_input("2", &self.vcpus_text)
+            .on_input(|v| Message::ResourcesChanged(ResourcesMsg::VcpusChanged(v)))
+            .padding(8);
+        column![
+            row![text("Memory (MiB):"), memory]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            row![text("CPUs:"), vcpus]
+                .spacing(8)
+                .align_y(Alignment::Center),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn view_storage_page(&self) -> Element<'_, Message> {
+        let mut col: Column<Message> = column![
+            checkbox(
+                "Enable storage for this virtual machine",
+                self.config.storage_enabled
+            )
+            .on_toggle(|v| Message::StorageChanged(StorageMsg::EnableToggle(v)))
+        ]
+        .spacing(10);
+        if !self.config.storage_enabled {
+            return col.into();
+        }
+        col = col.push(
+            checkbox(
+                "Select or create custom storage",
+                self.config.storage_path.is_some(),
+            )
+            .on_toggle(|v| Message::StorageChanged(StorageMsg::CustomToggle(v))),
+        );
+        if let Some(path) = &self.config.storage_path {
+            col = col.push(
+                row![
+                    text_input("/path/to/disk.qcow2", path)
+                        .on_input(|v| Message::StorageChanged(StorageMsg::PathChanged(v)))
+                        .padding(8),
+                    button(text("Browse...")).on_press(Message::Browse(BrowseReason::Image, |p| {
+                        Message::StorageChanged(StorageMsg::PathChanged(p))
+                    },)),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        } else {
+            col = col.push(text(format!(
+                "A disk image is created in {}",
+                default_pool_path(&self.config.uri).display()
+            )));
+        }
+        col.push(
+            row![
+                text("Size (GiB):"),
+                text_input("20", &self.size_text)
+                    .on_input(|v| Message::StorageChanged(StorageMsg::SizeChanged(v)))
+                    .padding(8),
+            ]
+            .spacing(8)
+            .align_y(Alignment::Center),
+        )
+        .into()
+    }
+
+    fn view_finish_page(&self) -> Element<'_, Message> {
+        let install = match self.config.method {
+            InstallMethod::Media => format!("Local CDROM/ISO: {}", self.config.media_path),
+            InstallMethod::Network => format!("URL: {}", self.config.url),
+            InstallMethod::Import => format!("Import: {}", self.config.import_path),
+            InstallMethod::Manual => "Manual install".to_string(),
+            InstallMethod::ContainerApp => format!("Application: {}", self.config.container_init),
+            InstallMethod::ContainerOs => format!("OS root: {}", self.config.container_root),
+        };
+        let storage = match (self.config.wants_storage(), self.config.storage_enabled) {
+            (true, true) => match &self.config.storage_path {
+                Some(path) => path.clone(),
+                None => format!("{} GiB", self.config.storage_size_gib),
+            },
+            (true, false) => "None".to_string(),
+            (false, _) => "-".to_string(),
+        };
+        let os = self
+            .config
+            .osinfo()
+            .map_or("-".to_string(), |o| o.label.clone());
+        let networks = vec![
+            NET_DEFAULT.to_string(),
+            NET_USER.to_string(),
+            NET_BRIDGE.to_string(),
+            NET_NONE.to_string(),
+        ];
+        let mut col: Column<Message> = column![
+            row![
+                text("Name:"),
+                text_input("vm", &self.config.name)
+                    .on_input(|v| Message::FinishChanged(FinishMsg::NameChanged(v)))
+                    .padding(8),
+            ]
+            .spacing(8)
+            .align_y(Alignment::Center),
+            text(format!("OS: {}", os)),
+            text(format!("Install: {}", install)),
+            text(format!("Memory: {} MiB", self.config.memory_mib)),
+            text(format!("CPUs: {}", self.config.vcpus)),
+            text(format!("Storage: {}", storage)),
+            row![
+                text("Network selection:"),
+                pick_list(networks, Some(self.config.network.clone()), |v| {
+                    Message::FinishChanged(FinishMsg::NetworkChanged(v))
+                }),
+            ]
+            .spacing(8)
+            .align_y(Alignment::Center),
+        ]
+        .spacing(10);
+        if self.config.network == NET_BRIDGE {
+            col = col.push(
+                row![
+                    text("Device name:"),
+                    text_input("br0", &self.config.bridge)
+                        .on_input(|v| Message::FinishChanged(FinishMsg::BridgeChanged(v)))
+                        .padding(8),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+        col.push(
+            checkbox(
+                "Customize configuration before install",
+                self.config.customize,
+            )
+            .on_toggle(|v| Message::FinishChanged(FinishMsg::CustomizeToggle(v))),
+        )
+        .into()
+    }
+
+    fn view_customize_page(&self) -> Element<'_, Message> {
+        column![
+            text("Edit the domain XML, then begin the installation:"),
+            text_editor(&self.xml_content)
+                .on_action(Message::XmlEdited)
+                .height(Length::Fixed(380.0)),
+        ]
+        .spacing(8)
+        .into()
+    }
+
+    // Static adapter functions for iced::application (public for embedding)
+    pub fn new_static() -> (Self, Task<Message>) {
+        Self::new()
+    }
+    pub fn update_static(state: &mut Self, msg: Message) -> Task<Message> {
+        state.update(msg)
+    }
+    pub fn view_static(state: &Self) -> Element<'_, Message> {
+        state.view()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+    use crate::connection::testdriver;
+
+    #[test]
+    fn test_media_install_matches_cli() {
+        let conn = testdriver();
+        let iso = format!(
+            "{}/../tests/data/fakemedia/fake-win7.iso",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let mut config = CreateVmConfig {
+            uri: conn.uri().to_string(),
+            media_path: iso.clone(),
+            detect_os: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(Step::Source, &conn),
+            Err("You must select an OS.".to_string())
+        );
+        config.os_name = Some("fedora29".to_string());
+        config.apply_os_defaults();
+        config.validate(Step::Source, &conn).unwrap();
+        config.name = "test".to_string();
+        assert_eq!(
+            config.validate(Step::Finish, &conn),
+            Err("Guest name 'test' is already in use.".to_string())
+        );
+        config.name = "newvm".to_string();
+        config.validate(Step::Finish, &conn).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let disk = config.default_disk_path(dir.path()).unwrap();
+        assert!(disk.ends_with("/newvm.qcow2"));
+        let args = config.build_args(Some(&disk));
+        assert_eq!(
+            args[..6],
+            ["--name", "newvm", "--memory", "2048", "--vcpus", "2"]
+        );
+        assert!(args.contains(&"--cdrom".to_string()));
+        assert!(args.contains(&format!("path=\"{}\",size=20,format=qcow2", disk)));
+        assert_eq!(args[args.len() - 2..], ["--network", "network=default"]);
+
+        let (guest, _, warnings) = config.prepare(&conn, Some(&disk)).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        let mut opts = virtinstall::parse_args(&args).unwrap();
+        opts.quiet = true;
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let mut io = CliIo {
+            stdin: None,
+            stdout: &mut out,
+            stderr: &mut err,
+        };
+        let (cli_guest, _) = virtinstall::build_guest(&opts, &conn, &mut io).unwrap();
+        // Only the random bits differ from the CLI
+        let scrub = |g: &Guest| {
+            let mut g = g.clone();
+            g.set_uuid(None);
+            g.xml.force_remove("./devices/interface/mac");
+            g.get_xml()
+        };
+        assert_eq!(scrub(&guest), scrub(&cli_guest));
+        assert_eq!(guest.osinfo().map(|o| o.name.as_str()), Some("fedora29"));
+        let disks = guest.devices("disk");
+        assert_eq!(disks[0].get("./source/@file"), Some(disk));
+        assert_eq!(disks[0].get("./driver/@type").as_deref(), Some("qcow2"));
+        assert_eq!(disks[1].get("./source/@file"), Some(iso));
+    }
+
+    #[test]
+    fn test_install_restart() {
+        let conn: Arc<dyn Connection> = Arc::new(testdriver());
+        let mut config = CreateVmConfig {
+            uri: conn.uri().to_string(),
+            media_path: "/dev/null".to_string(),
+            os_name: Some("fedora29".to_string()),
+            name: "restartvm".to_string(),
+            storage_enabled: false,
+            ..Default::default()
+        };
+        config.apply_os_defaults();
+        let (guest, installer, _) = config.prepare(conn.as_ref(), None).unwrap();
+        let (name, final_xml) =
+            run_install(conn.as_ref(), guest, installer, &crate::progress::NullMeter).unwrap();
+        let final_xml = final_xml.unwrap();
+
+        let stopper = conn.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            stopper.destroy_domain("restartvm").unwrap();
+        });
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(restart_after_install(conn.clone(), name, final_xml.clone()))
+            .unwrap();
+        assert!(conn.lookup_domain("restartvm").unwrap().state.is_active());
+        assert_eq!(conn.domain_xml("restartvm", true).unwrap(), final_xml);
+    }
+
+    #[test]
+    fn test_container_args() {
+        let conn = testdriver();
+        let config = CreateVmConfig {
+            uri: "lxc:///".to_string(),
+            method: InstallMethod::ContainerOs,
+            container_root: "/srv/rootfs".to_string(),
+            name: "ct".to_string(),
+            network: NET_NONE.to_string(),
+            ..Default::default()
+        };
+        assert!(!config.wants_storage());
+        assert_eq!(config.validate_source(&conn), Ok(()));
+        assert_eq!(
+            config.build_args(None)[6..],
+            [
+                "--boot",
+                "domain_type=lxc,os_type=exe,init=/sbin/init",
+                "--filesystem",
+                "\"/srv/rootfs\",/",
+                "--nonetworks",
+            ]
+        );
+        let mut config = CreateVmConfig {
+            method: InstallMethod::Import,
+            import_path: "/idontexist.img".to_string(),
+            os_name: Some("fedora29".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate_source(&conn),
+            Err("The import path must point to an existing storage.".to_string())
+        );
+        // Volumes count as existing wherever they live
+        config.import_path = "/pool-dir/testvol1.img".to_string();
+        assert_eq!(config.validate_source(&conn), Ok(()));
+    }
+
+    #[test]
+    fn test_args_quoting() {
+        let conn = testdriver();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a,b=\"c\".img");
+        std::fs::write(&path, b"").unwrap();
+        let path = path.to_str().unwrap();
+        let mut config = CreateVmConfig {
+            uri: conn.uri().to_string(),
+            method: InstallMethod::Import,
+            import_path: path.to_string(),
+            os_name: Some("fedora29".to_string()),
+            name: "quoted".to_string(),
+            network: NET_BRIDGE.to_string(),
+            bridge: "br,0".to_string(),
+            ..Default::default()
+        };
+        config.apply_os_defaults();
+        let (guest, _, _) = config.prepare(&conn, None).unwrap();
+        let disks = guest.devices("disk");
+        assert_eq!(disks[0].get("./source/@file").as_deref(), Some(path));
+        let nets = guest.devices("interface");
+        assert_eq!(nets[0].get("./source/@bridge").as_deref(), Some("br,0"));
+
+        // --cdrom takes its value whole, with no suboptions to quote
+        config.method = InstallMethod::Media;
+        config.media_path = path.to_string();
+        let opts = virtinstall::parse_args(&config.build_args(None)).unwrap();
+        assert_eq!(opts.cdrom.as_deref(), Some(path));
+
+        let config = CreateVmConfig {
+            uri: "lxc:///".to_string(),
+            method: InstallMethod::ContainerApp,
+            container_init: "/bin/sh,x".to_string(),
+            ..Default::default()
+        };
+        let args = config.build_args(None);
+        let boot = &args[args.iter().position(|a| a == "--boot").unwrap() + 1];
+        let tuples = cli::parse_optstr_tuples(boot).unwrap();
+        assert_eq!(
+            tuples[2],
+            ("init".to_string(), Some("/bin/sh,x".to_string()))
+        );
+    }
+}
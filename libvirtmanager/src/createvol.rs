@@ -0,0 +1,352 @@
+// New storage volume wizard (Iced port of virtManager/createvol.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Builds a `<volume>` for a pool from its name, format, capacity and
+//! backing store. File pools get the format's extension added to the
+//! name, and raw volumes default to being fully allocated.
+
+use std::sync::Arc;
+
+use iced::widget::{
+    Column, Space, button, checkbox, column, pick_list, row, text, text_editor, text_input,
+};
+use iced::{Alignment, Element, Length};
+use log::debug;
+
+use crate::connection::Connection;
+use crate::storage::{StoragePool, StorageVolume, pretty_bytes};
+
+const FORMATS: [&str; 2] = ["raw", "qcow2"];
+const DEFAULT_FORMAT: &str = "qcow2";
+const DEFAULT_CAPACITY_GIB: u64 = 20;
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    NameChanged(String),
+    FormatSelected(&'static str),
+    CapacityChanged(String),
+    NonsparseToggle(bool),
+    BackingChanged(String),
+    ShowXml(bool),
+    XmlEdited(text_editor::Action),
+    /// Handled by the owner, which drops the wizard
+    Cancel,
+    /// Handled by the owner through `finish`
+    Finish,
+}
+
+pub struct CreateVolume {
+    conn: Arc<dyn Connection>,
+    pool: StoragePool,
+    pub name: String,
+    pub format: &'static str,
+    /// In GiB
+    pub capacity: String,
+    pub nonsparse: bool,
+    pub backing_store: String,
+    show_xml: bool,
+    xml_content: text_editor::Content,
+    error: Option<String>,
+}
+
+impl CreateVolume {
+    /// A wizard for a new volume of `pool`, named after `name_hint`
+    pub fn new(
+        conn: Arc<dyn Connection>,
+        pool: &str,
+        name_hint: Option<&str>,
+    ) -> Result<Self, String> {
+        let xml = conn.pool_xml(pool, false)?;
+        debug!("Showing new volume wizard for parent_pool=\n{}", xml);
+        let pool = StoragePool::parse(&xml)?;
+        let avail_gib = pool.available() / (1 << 30);
+        let mut wizard = Self {
+            conn,
+            pool,
+            name: String::new(),
+            format: DEFAULT_FORMAT,
+            capacity: DEFAULT_CAPACITY_GIB.min(avail_gib).to_string(),
+            nonsparse: false,
+            backing_store: String::new(),
+            show_xml: false,
+            xml_content: text_editor::Content::new(),
+            error: None,
+        };
+        wizard.nonsparse = !wizard.should_default_sparse();
+        wizard.name = wizard.default_vol_name(name_hint.unwrap_or("vol"))?;
+        Ok(wizard)
+    }
+
+    fn stub_vol(&self) -> StorageVolume {
+        StorageVolume::new(&self.name)
+    }
+
+    fn format(&self) -> Option<&'static str> {
+        self.stub_vol()
+            .supports_format(&self.pool)
+            .then_some(self.format)
+    }
+
+    fn default_suffix(&self) -> String {
+        if self.stub_vol().file_type(&self.pool) != "file" {
+            return String::new();
+        }
+        StorageVolume::file_extension_for_format(self.format())
+    }
+
+    /// The extension added to the typed name, unless it has its own
+    pub fn name_suffix(&self) -> String {
+        if self.name.contains('.') {
+            return String::new();
+        }
+        self.default_suffix()
+    }
+
+    fn default_vol_name(&self, hint: &str) -> Result<String, String> {
+        let suffix = self.default_suffix();
+        let name =
+            StorageVolume::find_free_name(self.conn.as_ref(), &self.pool.name, hint, &suffix)?;
+        Ok(match name.rsplit_once('.') {
+            Some((base, _)) if !suffix.is_empty() => base.to_string(),
+            _ => name,
+        })
+    }
+
+    fn should_default_sparse(&self) -> bool {
+        self.format() == Some("qcow2")
+    }
+
+    fn can_sparse(&self) -> bool {
+        self.pool.disk_type() == "file" || self.pool.ptype == "zfs"
+    }
+
+    fn can_backing(&self) -> bool {
+        self.pool.ptype == "logical" || self.format() == Some("qcow2")
+    }
+
+    /// The volume the form describes
+    pub fn build(&self) -> Result<StorageVolume, String> {
+        let cap: f64 = self
+            .capacity
+            .trim()
+            .parse()
+            .ok()
+            .filter(|c: &f64| *c > 0.0)
+            .ok_or_else(|| format!("Invalid capacity '{}'", self.capacity))?;
+        let mut vol = StorageVolume::new(&format!("{}{}", self.name, self.name_suffix()));
+        vol.set_capacity((cap * GIB) as u64);
+        vol.set_allocation(if self.nonsparse {
+            (cap * GIB) as u64
+        } else {
+            0
+        });
+        let backing = self.backing_store.trim();
+        if self.can_backing() && !backing.is_empty() {
+            vol.set_backing_store(Some(backing));
+        }
+        if let Some(fmt) = self.format() {
+            vol.set_format(Some(fmt));
+        }
+        Ok(vol)
+    }
+
+    /// The volume to create: the XML tab's if it's shown, else the form's
+    fn build_xmlobj(&self) -> Result<StorageVolume, String> {
+        let err = |e: String| format!("Error building XML: {}", e);
+        if self.show_xml {
+            let xml = self.xml_content.text();
+            debug!("Using XML from xmleditor:\n{}", xml);
+            return StorageVolume::parse(&xml).map_err(err);
+        }
+        self.build().map_err(err)
+    }
+
+    fn create(&self) -> Result<String, String> {
+        let mut vol = self.build_xmlobj()?;
+        vol.validate(self.conn.as_ref(), &self.pool.name)
+            .map_err(|e| format!("Error validating volume: {}", e))?;
+        debug!("Starting vol creation");
+        vol.install(self.conn.as_ref(), &self.pool.name, None)
+            .map_err(|e| format!("Error creating vol: {}", e))?;
+        debug!("vol creation complete");
+        Ok(vol.name)
+    }
+
+    /// Create the volume, returning its name. Failures stay in the
+    /// wizard.
+    pub fn finish(&mut self) -> Option<String> {
+        match self.create() {
+            Ok(name) => Some(name),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    pub fn update(&mut self, msg: Message) {
+        match msg {
+            Message::NameChanged(v) => self.name = v,
+            Message::FormatSelected(v) => {
+                self.format = v;
+                self.nonsparse = !self.should_default_sparse();
+            }
+            Message::CapacityChanged(v) => self.capacity = v,
+            Message::NonsparseToggle(v) => self.nonsparse = v,
+            Message::BackingChanged(v) => self.backing_store = v,
+            Message::ShowXml(show) => {
+                if show && !self.show_xml {
+                    let xml = self.build().and_then(|vol| vol.get_xml());
+                    self.xml_content = text_editor::Content::with_text(&xml.unwrap_or_default());
+                }
+                self.show_xml = show;
+            }
+            Message::XmlEdited(action) => self.xml_content.perform(action),
+            Message::Cancel | Message::Finish => {}
+        }
+    }
+
+    fn labeled<'a>(
+        label: &'a str,
+        widget: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        row![text(label).width(Length::Fixed(120.0)), widget.into()]
+            .spacing(8)
+            .align_y(Alignment::Center)
+            .into()
+    }
+
+    fn view_details(&self) -> Element<'_, Message> {
+        let mut col: Column<Message> = column![
+            Self::labeled(
+                "Name:",
+                row![
+                    text_input("", &self.name)
+                        .on_input(Message::NameChanged)
+                        .padding(6),
+                    text(self.name_suffix()),
+                ]
+                .spacing(4)
+                .align_y(Alignment::Center),
+            ),
+            text(format!(
+                "{}'s available space: {}",
+                self.pool.name,
+                pretty_bytes(self.pool.available())
+            ))
+            .size(13),
+        ]
+        .spacing(10);
+        if self.format().is_some() {
+            col = col.push(Self::labeled(
+                "Format:",
+                pick_list(&FORMATS[..], Some(self.format), Message::FormatSelected),
+            ));
+        }
+        col = col.push(Self::labeled(
+            "Capacity (GiB):",
+            text_input("", &self.capacity)
+                .on_input(Message::CapacityChanged)
+                .padding(6)
+                .width(Length::Fixed(120.0)),
+        ));
+        if self.can_sparse() {
+            col = col.push(
+                checkbox("Allocate entire volume now", self.nonsparse)
+                    .on_toggle(Message::NonsparseToggle),
+            );
+        }
+        if self.can_backing() {
+            col = col.push(Self::labeled(
+                "Backing store:",
+                text_input("/path/to/backing.img", &self.backing_store)
+                    .on_input(Message::BackingChanged)
+                    .padding(6),
+            ));
+        }
+        col.into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let tab = |label, xml: bool| {
+            let style = if self.show_xml == xml {
+                button::primary
+            } else {
+                button::text
+            };
+            button(text(label))
+                .style(style)
+                .on_press(Message::ShowXml(xml))
+        };
+        let body = if self.show_xml {
+            text_editor(&self.xml_content)
+                .on_action(Message::XmlEdited)
+                .height(Length::Fixed(300.0))
+                .into()
+        } else {
+            self.view_details()
+        };
+        let mut col: Column<Message> = column![
+            text("Create storage volume").size(18),
+            row![tab("Details", false), tab("XML", true)].spacing(4),
+            body,
+        ]
+        .spacing(10);
+        if let Some(err) = &self.error {
+            col = col.push(text(err.clone()).size(14));
+        }
+        col.push(
+            row![
+                Space::with_width(Length::Fill),
+                button(text("Cancel")).on_press(Message::Cancel),
+                button(text("Finish"))
+                    .on_press_maybe((!self.name.is_empty()).then_some(Message::Finish)),
+            ]
+            .spacing(8),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+
+    #[test]
+    fn test_createvol() {
+        let conn: Arc<dyn Connection> = Arc::new(testdriver());
+        let mut wiz = CreateVolume::new(conn.clone(), "pool-dir", Some("testvol1")).unwrap();
+        assert_eq!(
+            (wiz.name.as_str(), wiz.name_suffix().as_str()),
+            ("testvol1", ".qcow2")
+        );
+        assert_eq!(wiz.capacity, "20");
+        assert!(!wiz.nonsparse);
+        wiz.update(Message::FormatSelected("raw"));
+        assert!(wiz.nonsparse && !wiz.can_backing());
+        wiz.update(Message::FormatSelected("qcow2"));
+        wiz.update(Message::CapacityChanged("1.5".into()));
+        wiz.update(Message::BackingChanged("/pool-dir/backingl1.img".into()));
+        assert_eq!(wiz.finish().as_deref(), Some("testvol1.qcow2"));
+        let xml = conn.volume_xml("pool-dir", "testvol1.qcow2").unwrap();
+        let vol = StorageVolume::parse(&xml).unwrap();
+        assert_eq!((vol.capacity(), vol.allocation()), (3 << 29, 0));
+        assert_eq!(vol.backing_store_path(), Some("/pool-dir/backingl1.img"));
+
+        // Block volumes have no format, and LVM ones no sparse allocation
+        let mut wiz = CreateVolume::new(conn.clone(), "pool-logical", None).unwrap();
+        assert_eq!((wiz.name.as_str(), wiz.name_suffix().as_str()), ("vol", ""));
+        assert!(wiz.format().is_none() && !wiz.can_sparse());
+        wiz.update(Message::CapacityChanged("bogus".into()));
+        assert_eq!(wiz.finish(), None);
+        assert!(wiz.error.as_deref().unwrap().contains("Invalid capacity"));
+        wiz.update(Message::CapacityChanged("2".into()));
+        assert_eq!(wiz.finish().as_deref(), Some("vol"));
+        let vol = StorageVolume::parse(&conn.volume_xml("pool-logical", "vol").unwrap()).unwrap();
+        assert_eq!(vol.allocation(), 2 << 30);
+    }
+}
@@ -0,0 +1,2299 @@
+// VM details window (Iced port of virtManager/details/details.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! The hardware list on the left holds the VM-wide pages and one entry
+//! per device. Edits to a page stay pending until Apply, which redefines
+//! the persistent config and, for devices of a running VM, tries a live
+//! update too. Whatever couldn't change live is flagged as taking effect
+//! after the next boot. Disk, NIC and graphics pages are the Add Hardware
+//! dialog's pages, loaded from the device. The CPU page offers the modes
+//! and models from the domain capabilities when libvirt reports them.
+//! The Performance page graphs the VM's CPU, memory, disk and network
+//! use. The Console tab shows the VM's graphical or text console and the
+//! Snapshots tab manages its snapshots. Events about the VM reload the
+//! open page, unless it has unapplied edits.
+
+use std::sync::Arc;
+
+use iced::widget::{
+    Column, Space, button, checkbox, column, container, mouse_area, pick_list, row, scrollable,
+    text, text_input,
+};
+use iced::{Alignment, Element, Length, Subscription, Task, Theme, event, mouse, window};
+use log::debug;
+
+use crate::addhardware::{AddHardwareApp, Message as AddHwMsg, Page, StorageMsg};
+use crate::cli::parsers::CPU_FEATURE_POLICIES;
+use crate::connection::{
+    self, AffectFlags, ConnEvent, Connection, DomainState, ObjectKind, StatsFlags,
+};
+use crate::connevents;
+use crate::console::{ConsolePage, Message as ConsoleMsg};
+use crate::domain::numatune::NUMATUNE_MODES;
+use crate::domain::os::is_uefi;
+use crate::domain::{
+    CpuFeature, CpuMode, CpuTopology, DomainCpu, DomainMemory, DomainMemoryBacking, DomainNumatune,
+    DomainOs, DomainVcpus, Firmware, NumaCell,
+};
+use crate::domcapabilities::DomainCapabilities;
+use crate::graphwidgets::{in_out_graph, sparkline};
+use crate::guest::Guest;
+use crate::snapshots::{Message as SnapshotsMsg, SnapshotsPage};
+use crate::statsmanager::{self, StatsManager, StatsOptions, StatsSample};
+use crate::storagebrowse::{self, BrowseReason, Message as BrowseMsg, StorageBrowser};
+use crate::xmlapi::{Element as XmlElement, unindent_device_xml};
+
+/// Device types listed in the hardware list, in display order
+const HW_LIST_ORDER: &[&str] = &[
+    "disk",
+    "interface",
+    "input",
+    "graphics",
+    "sound",
+    "serial",
+    "parallel",
+    "console",
+    "channel",
+    "hostdev",
+    "redirdev",
+    "video",
+    "watchdog",
+    "controller",
+    "filesystem",
+    "smartcard",
+    "tpm",
+    "rng",
+    "panic",
+    "vsock",
+];
+
+const REBOOT_MSG: &str = "Some changes may require a guest shutdown to take effect.";
+
+const REINSTALL_MSG: &str =
+    "Switching from BIOS to UEFI requires reinstalling the guest operating system.";
+
+/// An entry of the hardware list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HwItem {
+    Overview,
+    Performance,
+    Cpu,
+    Memory,
+    Boot,
+    /// The nth (0 based) device with this element name
+    Device(String, usize),
+}
+
+pub fn pretty_disk_bus(bus: &str) -> String {
+    match bus {
+        "ide" | "sata" | "scsi" | "usb" | "sd" => bus.to_uppercase(),
+        "virtio" => "VirtIO".to_string(),
+        "xen" => "Xen".to_string(),
+        "fdc" => "Floppy".to_string(),
+        _ => bus.to_string(),
+    }
+}
+
+/// Memory size in KiB for display, like "512 MiB" or "16.00 GiB"
+pub fn pretty_mem(kib: u64) -> String {
+    if kib > 10 * 1024 * 1024 {
+        format!("{:.2} GiB", kib as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.0} MiB", kib as f64 / 1024.0)
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Hardware list label for a device. The bool says whether devices with
+/// the same label get numbered, like "VirtIO Disk 1".
+fn device_label(dev: &XmlElement) -> (String, bool) {
+    let attr = |name: &str| dev.attr(name).unwrap_or_default();
+    match dev.name.as_str() {
+        "disk" => {
+            let device = dev.attr("device").unwrap_or("disk");
+            if device == "floppy" {
+                return ("Floppy".to_string(), true);
+            }
+            let bus = pretty_disk_bus(&dev.get("./target/@bus").unwrap_or_default());
+            let devlabel = match device {
+                "cdrom" => "CDROM".to_string(),
+                "lun" => "LUN".to_string(),
+                other => capitalize(other),
+            };
+            (format!("{} {}", bus, devlabel).trim().to_string(), true)
+        }
+        "interface" => match dev.get("./mac/@address") {
+            Some(mac) if mac.len() >= 9 => (format!("NIC {}", &mac[mac.len() - 9..]), false),
+            _ => ("NIC".to_string(), true),
+        },
+        "input" => {
+            let label = match attr("type") {
+                "tablet" => "Tablet",
+                "mouse" => "Mouse",
+                "keyboard" => "Keyboard",
+                _ => "Input",
+            };
+            (label.to_string(), false)
+        }
+        "graphics" => {
+            let gtype = match attr("type") {
+                "vnc" | "sdl" | "rdp" => attr("type").to_uppercase(),
+                other => capitalize(other),
+            };
+            (format!("Display {}", gtype), false)
+        }
+        "sound" => (format!("Sound {}", attr("model")), false),
+        "serial" | "parallel" | "console" => (capitalize(&dev.name), true),
+        "channel" => {
+            let target = dev.get("./target/@name").unwrap_or_default();
+            let name = match target.as_str() {
+                "org.qemu.guest_agent.0" => "qemu-ga",
+                "com.redhat.spice.0" => "spice",
+                "org.libguestfs.channel.0" => "libguestfs",
+                "" => attr("type"),
+                other => other,
+            };
+            (format!("Channel {}", name), false)
+        }
+        "hostdev" => {
+            let label = match attr("type") {
+                "pci" => {
+                    let addr = |a: &str| {
+                        let v = dev.get(&format!("./source/address/@{}", a));
+                        v.and_then(|v| u32::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+                            .unwrap_or(0)
+                    };
+                    format!(
+                        "PCI {:04x}:{:02x}:{:02x}.{:x}",
+                        addr("domain"),
+                        addr("bus"),
+                        addr("slot"),
+                        addr("function")
+                    )
+                }
+                "usb" => match (
+                    dev.get("./source/vendor/@id"),
+                    dev.get("./source/product/@id"),
+                ) {
+                    (Some(v), Some(p)) => format!(
+                        "USB {}:{}",
+                        v.trim_start_matches("0x"),
+                        p.trim_start_matches("0x")
+                    ),
+                    _ => "USB".to_string(),
+                },
+                other => other.to_uppercase(),
+            };
+            (label, false)
+        }
+        "redirdev" => ("USB Redirector".to_string(), true),
+        "video" => {
+            let model = dev.get("./model/@type").unwrap_or_default();
+            let model = match model.as_str() {
+                "qxl" | "vga" => model.to_uppercase(),
+                other => capitalize(other),
+            };
+            (format!("Video {}", model).trim().to_string(), false)
+        }
+        "controller" => {
+            let model = attr("model");
+            let ctype = match (attr("type"), model) {
+                ("scsi", "virtio-scsi") => "VirtIO SCSI".to_string(),
+                ("pci", m) if m.starts_with("pcie") => "PCIe".to_string(),
+                ("virtio-serial", _) => "VirtIO Serial".to_string(),
+                ("fdc", _) => "Floppy".to_string(),
+                (t, _) => pretty_disk_bus(t),
+            };
+            (format!("Controller {} {}", ctype, attr("index")), false)
+        }
+        "filesystem" => (
+            format!(
+                "Filesystem {}",
+                dev.get("./target/@dir").unwrap_or_default()
+            ),
+            false,
+        ),
+        "smartcard" => ("Smartcard".to_string(), false),
+        "tpm" => ("TPM".to_string(), false),
+        "rng" => ("RNG".to_string(), false),
+        "panic" => ("Panic Notifier".to_string(), false),
+        "vsock" => ("VirtIO VSOCK".to_string(), false),
+        "watchdog" => ("Watchdog".to_string(), false),
+        other => (capitalize(other), false),
+    }
+}
+
+/// The hardware list for a guest: the VM-wide pages, then its devices
+pub fn hw_items(guest: &Guest) -> Vec<(HwItem, String)> {
+    let mut ret = vec![
+        (HwItem::Overview, "Overview".to_string()),
+        (HwItem::Performance, "Performance".to_string()),
+        (HwItem::Cpu, "CPUs".to_string()),
+        (HwItem::Memory, "Memory".to_string()),
+        (HwItem::Boot, "Boot Options".to_string()),
+    ];
+    let mut counts: Vec<(String, usize)> = vec![];
+    let has_serial = !guest.devices("serial").is_empty();
+    for tag in HW_LIST_ORDER {
+        for (idx, dev) in guest.devices(tag).into_iter().enumerate() {
+            // The first console just mirrors the first serial port
+            if *tag == "console"
+                && idx == 0
+                && has_serial
+                && dev.get("./target/@type").is_none_or(|t| t == "serial")
+            {
+                continue;
+            }
+            let (mut label, numbered) = device_label(dev);
+            if numbered {
+                let num = match counts.iter_mut().find(|(l, _)| *l == label) {
+                    Some((_, n)) => {
+                        *n += 1;
+                        *n
+                    }
+                    None => {
+                        counts.push((label.clone(), 1));
+                        1
+                    }
+                };
+                label = format!("{} {}", label, num);
+            }
+            ret.push((HwItem::Device(tag.to_string(), idx), label));
+        }
+    }
+    ret
+}
+
+/// Public facade to show the details window of one VM
+pub struct VmmDetails;
+
+impl VmmDetails {
+    pub fn show_instance(uri: Option<&str>, name: &str) -> Result<(), String> {
+        Self::launch(uri, name, HwItem::Overview)
+    }
+
+    /// Show the details window at the Performance page
+    pub fn show_performance(uri: Option<&str>, name: &str) -> Result<(), String> {
+        Self::launch(uri, name, HwItem::Performance)
+    }
+
+    fn launch(uri: Option<&str>, name: &str, item: HwItem) -> Result<(), String> {
+        debug!("Launching details window for '{}'", name);
+        let conn: Arc<dyn Connection> = Arc::from(connection::open(uri)?);
+        let mut app = DetailsApp::new(conn, name)?;
+        if let Some(idx) = app.items.iter().position(|i| i.0 == item) {
+            app.select(idx);
+        }
+        let title = format!("{} on {}", name, app.conn.uri());
+        iced::application(
+            move |_: &DetailsApp| title.clone(),
+            DetailsApp::update_static,
+            DetailsApp::view_static,
+        )
+        .theme(|_| Theme::default())
+        .subscription(DetailsApp::subscription)
+        .window(window::Settings {
+            size: iced::Size::new(1000.0, 700.0),
+            position: window::Position::Centered,
+            resizable: true,
+            decorations: true,
+            ..Default::default()
+        })
+        .run_with(move || (app, Task::none()))
+        .map_err(|e| format!("Error launching details window: {}", e))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    ShowTab(Tab),
+    Select(usize),
+    /// Answer to the unapplied changes prompt when switching pages
+    SwitchApply,
+    SwitchDiscard,
+    SwitchCancel,
+    Apply,
+    Revert,
+    Remove,
+    RemoveConfirmed(bool),
+    Refresh,
+    Close,
+    OverviewChanged(OverviewMsg),
+    CpuChanged(CpuMsg),
+    MemoryChanged(MemoryMsg),
+    BootChanged(BootMsg),
+    Hardware(AddHwMsg),
+    /// Open the storage browser for a boot path field
+    Browse(BrowseField),
+    Browser(BrowseMsg),
+    Console(ConsoleMsg),
+    Snapshots(SnapshotsMsg),
+    Stats(StatsSample),
+    Event(ConnEvent),
+    /// The VM re-read by a refresh
+    Loaded(Result<LoadedVm, String>),
+    /// Whether a saved change also updated the running VM
+    Saved(ConfigChange, Result<bool, String>),
+    /// The hotunplug warning of a removed device
+    Removed(DeviceRemoval, Result<Option<String>, String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Console,
+    Details,
+    Snapshots,
+}
+
+#[derive(Debug, Clone)]
+pub enum OverviewMsg {
+    TitleChanged(String),
+    DescriptionChanged(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum CpuMsg {
+    VcpusChanged(String),
+    CurrentVcpusChanged(String),
+    ModeSelected(CpuMode),
+    ModelChanged(String),
+    NewFeatureChanged(String),
+    AddFeature,
+    FeaturePolicySelected(usize, &'static str),
+    RemoveFeature(usize),
+    TopologyToggle(bool),
+    /// Index into sockets, dies, cores, threads
+    TopologyChanged(usize, String),
+}
+
+#[derive(Debug, Clone)]
+pub enum MemoryMsg {
+    CurrentChanged(String),
+    MaximumChanged(String),
+    HugepagesToggle(bool),
+    SharedToggle(bool),
+    AddCell,
+    RemoveCell(usize),
+    CellCpusChanged(usize, String),
+    CellMemoryChanged(usize, String),
+    NumatuneModeSelected(String),
+    NumatuneNodesetChanged(String),
+}
+
+/// Memory page entry for a NUMA cell, with memory in MiB
+#[derive(Debug, Clone, Default)]
+struct CellEntry {
+    id: u32,
+    cpus: String,
+    memory: String,
+}
+
+const TOPOLOGY_LABELS: [&str; 4] = ["Sockets:", "Dies:", "Cores:", "Threads:"];
+
+/// Numatune mode entry for leaving the mode unset
+const NUMATUNE_DEFAULT: &str = "default";
+
+#[derive(Debug, Clone)]
+pub enum BootMsg {
+    AutostartToggle(bool),
+    MenuToggle(bool),
+    DeviceToggle((String, usize), bool),
+    /// Move the nth enabled boot device up or down one
+    MoveUp(usize),
+    MoveDown(usize),
+    /// Drag the nth enabled boot device, moving it to wherever the
+    /// pointer enters another enabled device until the button is released
+    DragStart(usize),
+    DragOver(usize),
+    DragEnd,
+    FirmwareSelected(FirmwareChoice),
+    NvramTemplateChanged(String),
+    KernelToggle(bool),
+    KernelChanged(String),
+    InitrdChanged(String),
+    DtbChanged(String),
+    KernelArgsChanged(String),
+    InitChanged(String),
+    InitArgsChanged(String),
+}
+
+/// Firmware picker entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareChoice {
+    label: String,
+    firmware: Firmware,
+}
+
+impl std::fmt::Display for FirmwareChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+/// The firmware with any NVRAM template dropped, for matching choices
+fn firmware_kind(fw: &Firmware) -> Firmware {
+    match fw {
+        Firmware::Loader { path, .. } => Firmware::Loader {
+            path: path.clone(),
+            nvram_template: None,
+        },
+        fw => fw.clone(),
+    }
+}
+
+/// Fills a path field with the path chosen in the storage browser
+type BrowseField = fn(String) -> BootMsg;
+
+/// The VM as read from libvirt
+#[derive(Debug, Clone)]
+pub struct LoadedVm {
+    state: DomainState,
+    guest: Guest,
+    live: Option<Guest>,
+    autostart: Option<bool>,
+    domcaps: Option<DomainCapabilities>,
+}
+
+/// An applied page, ready to be written to libvirt
+#[derive(Debug, Clone)]
+pub struct ConfigChange {
+    item: HwItem,
+    /// Autostart flag to set, for the Boot page
+    autostart: Option<bool>,
+    /// The new config, unless it didn't change
+    xml: Option<String>,
+    /// The edited device, to update the running VM with
+    live_device: Option<String>,
+    /// The VM is running
+    active: bool,
+    reinstall: bool,
+}
+
+/// A device to remove from the config
+#[derive(Debug, Clone)]
+pub struct DeviceRemoval {
+    tag: String,
+    idx: usize,
+    /// The config without the device
+    xml: String,
+    /// The device, to unplug from the running VM
+    live_device: Option<String>,
+}
+
+/// Run `work` on the connection without blocking the UI thread
+async fn blocking<T, F>(conn: Arc<dyn Connection>, name: String, work: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn Connection, &str) -> Result<T, String> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || work(conn.as_ref(), &name))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r)
+}
+
+pub struct DetailsApp {
+    conn: Arc<dyn Connection>,
+    name: String,
+    /// The persistent config, which edits apply to
+    guest: Guest,
+    /// Live XML while the VM runs
+    live: Option<Guest>,
+    state: DomainState,
+    /// The autostart flag as libvirt has it
+    saved_autostart: Option<bool>,
+    items: Vec<(HwItem, String)>,
+    selected: usize,
+    /// The current page has unapplied edits
+    pending: bool,
+    /// Page waiting on the unapplied changes prompt
+    switch_to: Option<usize>,
+    confirm_remove: bool,
+    /// Items changed in the config that the running VM doesn't have yet
+    needs_reboot: Vec<HwItem>,
+    status: Option<String>,
+    error: Option<String>,
+
+    /// Capabilities of the VM's emulator, when libvirt reports them
+    domcaps: Option<DomainCapabilities>,
+
+    // VM-wide page state
+    title: String,
+    description: String,
+    vcpus: String,
+    vcpus_current: String,
+    cpu: DomainCpu,
+    new_feature: String,
+    topology_manual: bool,
+    topology: [String; 4],
+    memory_current: String,
+    memory_max: String,
+    hugepages: bool,
+    shared_memory: bool,
+    cells: Vec<CellEntry>,
+    numatune_mode: String,
+    numatune_nodeset: String,
+    /// None when the connection can't report autostart
+    autostart: Option<bool>,
+    boot_menu: bool,
+    /// Enabled boot devices, in boot order
+    boot_devs: Vec<(String, usize)>,
+    /// Index in `boot_devs` of the device being dragged
+    boot_drag: Option<usize>,
+    firmware: Firmware,
+    nvram_template: String,
+    direct_kernel: bool,
+    kernel: String,
+    initrd: String,
+    dtb: String,
+    kernel_args: String,
+    init: String,
+    init_args: String,
+
+    /// Disk, NIC and graphics pages
+    hw: AddHardwareApp,
+    /// The storage browser and the field it fills, while open
+    browser: Option<(StorageBrowser, BrowseField)>,
+
+    tab: Tab,
+    console: ConsolePage,
+    snapshots: SnapshotsPage,
+    stats: StatsManager,
+}
+
+impl DetailsApp {
+    pub fn new(conn: Arc<dyn Connection>, name: &str) -> Result<Self, String> {
+        let (mut hw, _) = AddHardwareApp::new_static();
+        hw.set_connection(conn.clone());
+        let console = ConsolePage::new(conn.clone(), name);
+        let snapshots = SnapshotsPage::new(conn.clone(), name);
+        let mut app = Self {
+            conn,
+            name: name.to_string(),
+            guest: Guest::new(),
+            live: None,
+            state: DomainState::default(),
+            saved_autostart: None,
+            items: vec![],
+            selected: 0,
+            pending: false,
+            switch_to: None,
+            confirm_remove: false,
+            needs_reboot: vec![],
+            status: None,
+            error: None,
+            domcaps: None,
+            title: String::new(),
+            description: String::new(),
+            vcpus: String::new(),
+            vcpus_current: String::new(),
+            cpu: DomainCpu::from_xml(&XmlElement::new("domain")),
+            new_feature: String::new(),
+            topology_manual: false,
+            topology: Default::default(),
+            memory_current: String::new(),
+            memory_max: String::new(),
+            hugepages: false,
+            shared_memory: false,
+            cells: vec![],
+            numatune_mode: String::new(),
+            numatune_nodeset: String::new(),
+            autostart: None,
+            boot_menu: false,
+            boot_devs: vec![],
+            boot_drag: None,
+            firmware: Firmware::Bios,
+            nvram_template: String::new(),
+            direct_kernel: false,
+            kernel: String::new(),
+            initrd: String::new(),
+            dtb: String::new(),
+            kernel_args: String::new(),
+            init: String::new(),
+            init_args: String::new(),
+            hw,
+            browser: None,
+            tab: Tab::Details,
+            console,
+            snapshots,
+            stats: StatsManager::new(),
+        };
+        // Nothing is shown yet, so read the VM right away
+        let vm = Self::load_vm(app.conn.as_ref(), name, true)?;
+        app.loaded(vm);
+        Ok(app)
+    }
+
+    fn is_active(&self) -> bool {
+        self.live.is_some()
+    }
+
+    fn current_item(&self) -> HwItem {
+        self.items[self.selected].0.clone()
+    }
+
+    /// Read the VM's state and XML, and its domain capabilities if
+    /// `domcaps` is set
+    fn load_vm(conn: &dyn Connection, name: &str, domcaps: bool) -> Result<LoadedVm, String> {
+        let state = conn.lookup_domain(name)?.state;
+        let guest = Guest::parse(&conn.domain_xml(name, true)?)?;
+        let live = if state.is_active() {
+            Some(Guest::parse(&conn.domain_xml(name, false)?)?)
+        } else {
+            None
+        };
+        let domcaps = domcaps
+            .then(|| {
+                DomainCapabilities::for_guest(conn, &guest)
+                    .map_err(|e| debug!("No domain capabilities: {}", e))
+                    .ok()
+            })
+            .flatten();
+        let autostart = conn
+            .domain_autostart(name)
+            .map_err(|e| debug!("Autostart unavailable: {}", e))
+            .ok();
+        Ok(LoadedVm {
+            state,
+            guest,
+            live,
+            autostart,
+            domcaps,
+        })
+    }
+
+    /// Re-read the VM off the UI thread, to reload the current page
+    fn refresh(&self) -> Task<Message> {
+        let domcaps = self.domcaps.is_none();
+        Task::perform(
+            blocking(self.conn.clone(), self.name.clone(), move |conn, name| {
+                Self::load_vm(conn, name, domcaps)
+            }),
+            Message::Loaded,
+        )
+    }
+
+    fn loaded(&mut self, vm: LoadedVm) {
+        self.state = vm.state;
+        self.guest = vm.guest;
+        self.live = vm.live;
+        if self.live.is_none() {
+            // A fresh boot picked up every change
+            self.needs_reboot.clear();
+        }
+        self.saved_autostart = vm.autostart;
+        if self.domcaps.is_none() {
+            self.domcaps = vm.domcaps;
+        }
+        let current = self.items.get(self.selected).map(|i| i.0.clone());
+        self.items = hw_items(&self.guest);
+        self.selected = current
+            .and_then(|c| self.items.iter().position(|i| i.0 == c))
+            .unwrap_or(self.selected.min(self.items.len() - 1));
+        self.load_page();
+    }
+
+    /// Fill the current page from the config, dropping pending edits
+    fn load_page(&mut self) {
+        self.pending = false;
+        self.confirm_remove = false;
+        let guest = &self.guest;
+        match self.current_item() {
+            HwItem::Overview => {
+                self.title = guest.xml.get("./title").unwrap_or_default();
+                self.description = guest.xml.get("./description").unwrap_or_default();
+            }
+            HwItem::Performance => {}
+            HwItem::Cpu => {
+                let vcpus = DomainVcpus::from_xml(&guest.xml);
+                self.vcpus = vcpus.max.to_string();
+                self.vcpus_current = vcpus.current.to_string();
+                self.cpu = DomainCpu::from_xml(&guest.xml);
+                self.new_feature.clear();
+                let topo = self.cpu.topology;
+                self.topology_manual = topo.is_set();
+                self.topology = [topo.sockets, topo.dies, topo.cores, topo.threads]
+                    .map(|v| v.unwrap_or(1).to_string());
+            }
+            HwItem::Memory => {
+                let mem = DomainMemory::from_xml(&guest.xml);
+                self.memory_max = (mem.memory / 1024).to_string();
+                self.memory_current = (mem.current / 1024).to_string();
+                let backing = DomainMemoryBacking::from_xml(&guest.xml);
+                self.hugepages = backing.hugepages;
+                self.shared_memory = backing.is_shared();
+                self.cells = DomainCpu::from_xml(&guest.xml)
+                    .cells
+                    .into_iter()
+                    .map(|c| CellEntry {
+                        id: c.id,
+                        cpus: c.cpus,
+                        memory: (c.memory / 1024).to_string(),
+                    })
+                    .collect();
+                let tune = DomainNumatune::from_xml(&guest.xml);
+                self.numatune_mode = tune.memory_mode.unwrap_or(NUMATUNE_DEFAULT.to_string());
+                self.numatune_nodeset = tune.memory_nodeset.unwrap_or_default();
+            }
+            HwItem::Boot => {
+                self.autostart = self.saved_autostart;
+                self.boot_devs = guest.boot_order();
+                self.boot_drag = None;
+                self.firmware = Firmware::from_xml(&guest.xml);
+                self.nvram_template = match &self.firmware {
+                    Firmware::Loader {
+                        nvram_template: Some(t),
+                        ..
+                    } => t.clone(),
+                    _ => String::new(),
+                };
+                let os = DomainOs::from_xml(&guest.xml);
+                self.boot_menu = os.bootmenu;
+                self.direct_kernel = os.kernel.is_some();
+                self.kernel = os.kernel.unwrap_or_default();
+                self.initrd = os.initrd.unwrap_or_default();
+                self.dtb = os.dtb.unwrap_or_default();
+                self.kernel_args = os.kernel_args.unwrap_or_default();
+                self.init = os.init.unwrap_or_default();
+                self.init_args = os.init_args.join(" ");
+            }
+            HwItem::Device(tag, idx) => {
+                if let Some(dev) = guest.devices(&tag).get(idx) {
+                    self.hw.load_device(dev);
+                }
+            }
+        }
+    }
+
+    /// Write the current page into `guest`
+    fn apply_page(&self, guest: &mut Guest) -> Result<(), String> {
+        let xml = &mut guest.xml;
+        match self.current_item() {
+            HwItem::Overview => {
+                let title = self.title.trim();
+                let desc = self.description.trim();
+                xml.set("./title", Some(title).filter(|t| !t.is_empty()));
+                xml.set("./description", Some(desc).filter(|d| !d.is_empty()));
+            }
+            HwItem::Performance => {}
+            HwItem::Cpu => {
+                let count = |v: &str, what: &str| {
+                    v.trim()
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|v| *v > 0)
+                        .ok_or_else(|| format!("Invalid {} '{}'", what, v))
+                };
+                let vcpus = DomainVcpus {
+                    max: count(&self.vcpus, "vCPU allocation")?,
+                    current: count(&self.vcpus_current, "current vCPU allocation")?,
+                };
+                vcpus.validate(self.domcaps.as_ref().and_then(|c| c.max_vcpus()))?;
+
+                let mut cpu = self.cpu.clone();
+                cpu.model = cpu
+                    .model
+                    .map(|m| m.trim().to_string())
+                    .filter(|m| !m.is_empty());
+                cpu.topology = if self.topology_manual {
+                    let old = self.cpu.topology;
+                    let mut vals = [0; 4];
+                    for (val, s) in vals.iter_mut().zip(&self.topology) {
+                        *val = count(s, "topology value")?;
+                    }
+                    let [sockets, dies, cores, threads] = vals;
+                    CpuTopology {
+                        sockets: Some(sockets),
+                        // Dies default to 1, don't add them needlessly
+                        dies: Some(dies).filter(|d| *d != 1 || old.dies.is_some()),
+                        clusters: old.clusters,
+                        cores: Some(cores),
+                        threads: Some(threads),
+                    }
+                } else {
+                    CpuTopology::default()
+                };
+                cpu.validate(vcpus.max)?;
+                vcpus.apply(xml);
+                cpu.apply(xml);
+            }
+            HwItem::Memory => {
+                let parse = |v: &str, what: &str| {
+                    v.trim()
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|m| *m > 0)
+                        .ok_or_else(|| format!("Invalid {} memory '{}'", what, v))
+                };
+                let max = parse(&self.memory_max, "maximum")?;
+                let cur = parse(&self.memory_current, "current")?;
+                // Only rewrite values the user changed, keeping their units
+                let mut mem = DomainMemory::from_xml(xml);
+                if mem.memory / 1024 != max {
+                    mem.memory = max * 1024;
+                }
+                if mem.current / 1024 != cur {
+                    mem.current = cur * 1024;
+                }
+                mem.validate()?;
+
+                let mut backing = DomainMemoryBacking::from_xml(xml);
+                backing.hugepages = self.hugepages;
+                if backing.is_shared() != self.shared_memory {
+                    let memfd = self
+                        .domcaps
+                        .as_ref()
+                        .is_none_or(|c| c.supports_memorybacking_memfd());
+                    backing.set_shared(self.shared_memory, memfd);
+                }
+
+                let mut cpu = DomainCpu::from_xml(xml);
+                let old_cells = std::mem::take(&mut cpu.cells);
+                for entry in &self.cells {
+                    let mib = parse(&entry.memory, &format!("NUMA cell {}", entry.id))?;
+                    let memory = old_cells
+                        .iter()
+                        .find(|c| c.id == entry.id && c.memory / 1024 == mib)
+                        .map_or(mib * 1024, |c| c.memory);
+                    cpu.cells.push(NumaCell {
+                        id: entry.id,
+                        cpus: entry.cpus.trim().to_string(),
+                        memory,
+                    });
+                }
+                cpu.validate(DomainVcpus::from_xml(xml).max)?;
+
+                let nodeset = self.numatune_nodeset.trim();
+                let mode = Some(self.numatune_mode.clone()).filter(|m| m != NUMATUNE_DEFAULT);
+                let tune = DomainNumatune {
+                    // Without a nodeset, let libvirt pick the host nodes
+                    memory_placement: Some("auto".to_string())
+                        .filter(|_| nodeset.is_empty() && mode.is_some()),
+                    memory_mode: mode,
+                    memory_nodeset: Some(nodeset.to_string()).filter(|n| !n.is_empty()),
+                };
+                tune.validate()?;
+
+                mem.apply(xml);
+                backing.apply(xml);
+                cpu.apply(xml);
+                tune.apply(xml);
+            }
+            HwItem::Boot => {
+                let value = |v: &str| Some(v.trim().to_string()).filter(|v| !v.is_empty());
+                let kernel = |v: &str| value(v).filter(|_| self.direct_kernel);
+                let os = DomainOs {
+                    bootmenu: self.boot_menu,
+                    kernel: kernel(&self.kernel),
+                    initrd: kernel(&self.initrd),
+                    dtb: kernel(&self.dtb),
+                    kernel_args: kernel(&self.kernel_args),
+                    init: value(&self.init),
+                    init_args: self
+                        .init_args
+                        .split_whitespace()
+                        .map(str::to_string)
+                        .collect(),
+                };
+                os.validate(guest.is_container())?;
+                let firmware = match &self.firmware {
+                    Firmware::Loader { path, .. } => Firmware::Loader {
+                        path: path.clone(),
+                        nvram_template: value(&self.nvram_template),
+                    },
+                    fw => fw.clone(),
+                };
+                let x86 = guest.is_x86();
+                if guest.boot_order() != self.boot_devs {
+                    guest.set_boot_order(&self.boot_devs);
+                }
+                os.apply(&mut guest.xml);
+                if !guest.is_container() {
+                    firmware.apply(&mut guest.xml, x86);
+                }
+            }
+            HwItem::Device(tag, idx) => {
+                let dev = xml
+                    .find_mut(&Guest::device_xpath(&tag, idx))
+                    .ok_or_else(|| format!("Device {} {} no longer exists", tag, idx))?;
+                self.hw.apply_to_device(dev);
+            }
+        }
+        Ok(())
+    }
+
+    /// Turn the current page into the change to write
+    fn config_change(&self) -> Result<ConfigChange, String> {
+        let item = self.current_item();
+        let mut newguest = self.guest.clone();
+        self.apply_page(&mut newguest)?;
+        let live_device = match &item {
+            HwItem::Device(tag, idx) if self.is_active() => newguest
+                .devices(tag)
+                .get(*idx)
+                .map(|dev| unindent_device_xml(&dev.to_xml())),
+            // The VM-wide settings have no live API here
+            _ => None,
+        };
+        Ok(ConfigChange {
+            autostart: self.autostart.filter(|_| item == HwItem::Boot),
+            item,
+            xml: (newguest != self.guest).then(|| newguest.get_xml()),
+            live_device,
+            active: self.is_active(),
+            reinstall: !is_uefi(&self.guest.xml) && is_uefi(&newguest.xml),
+        })
+    }
+
+    /// Define the edited config, and update the running VM when possible.
+    /// Returns whether the running VM has the change too.
+    fn save_config(
+        conn: &dyn Connection,
+        name: &str,
+        change: &ConfigChange,
+    ) -> Result<bool, String> {
+        if let Some(autostart) = change.autostart
+            && conn.domain_autostart(name).ok() != Some(autostart)
+        {
+            conn.set_domain_autostart(name, autostart)?;
+        }
+        let Some(xml) = &change.xml else {
+            return Ok(true);
+        };
+        conn.define_xml(xml)?;
+        Ok(change.live_device.as_ref().is_some_and(|dev| {
+            conn.update_device(name, dev, AffectFlags::Live)
+                .map_err(|e| debug!("Live device update failed: {}", e))
+                .is_ok()
+        }))
+    }
+
+    /// Write the current page off the UI thread
+    fn apply(&mut self) -> Task<Message> {
+        match self.config_change() {
+            Ok(change) => Task::perform(
+                blocking(self.conn.clone(), self.name.clone(), {
+                    let change = change.clone();
+                    move |conn, name| Self::save_config(conn, name, &change)
+                }),
+                move |ret| Message::Saved(change.clone(), ret),
+            ),
+            Err(e) => {
+                self.error = Some(e);
+                self.switch_to = None;
+                Task::none()
+            }
+        }
+    }
+
+    fn saved(&mut self, change: ConfigChange, updated_live: bool) -> Task<Message> {
+        self.error = None;
+        self.pending = false;
+        if change.xml.is_some() {
+            self.status = change.reinstall.then(|| REINSTALL_MSG.to_string());
+            if change.active && !updated_live && !self.needs_reboot.contains(&change.item) {
+                self.needs_reboot.push(change.item);
+            }
+        }
+        if let Some(idx) = self.switch_to.take() {
+            self.select(idx);
+        }
+        self.refresh()
+    }
+
+    /// The current device's removal, for a device page
+    fn device_removal(&self) -> Result<Option<DeviceRemoval>, String> {
+        let HwItem::Device(tag, idx) = self.current_item() else {
+            return Ok(None);
+        };
+        let mut newguest = self.guest.clone();
+        let dev = newguest
+            .remove_device(&tag, idx)
+            .ok_or_else(|| format!("Device {} {} no longer exists", tag, idx))?;
+        Ok(Some(DeviceRemoval {
+            tag,
+            idx,
+            xml: newguest.get_xml(),
+            live_device: self.is_active().then(|| unindent_device_xml(&dev.to_xml())),
+        }))
+    }
+
+    /// Remove a device, unplugging it from a running VM too. Returns the
+    /// warning when the running VM still has it.
+    fn remove_device(
+        conn: &dyn Connection,
+        name: &str,
+        removal: &DeviceRemoval,
+    ) -> Result<Option<String>, String> {
+        let mut status = None;
+        if let Some(xml) = &removal.live_device
+            && let Err(e) = conn.detach_device(name, xml, AffectFlags::Live)
+        {
+            debug!("Device hotunplug failed: {}", e);
+            status = Some(format!(
+                "Device could not be removed from the running machine: {}\n\
+                 This change will take effect after the next guest shutdown.",
+                e
+            ));
+        }
+        conn.define_xml(&removal.xml)?;
+        Ok(status)
+    }
+
+    /// Remove the current device off the UI thread
+    fn remove(&mut self) -> Task<Message> {
+        match self.device_removal() {
+            Ok(Some(removal)) => Task::perform(
+                blocking(self.conn.clone(), self.name.clone(), {
+                    let removal = removal.clone();
+                    move |conn, name| Self::remove_device(conn, name, &removal)
+                }),
+                move |ret| Message::Removed(removal.clone(), ret),
+            ),
+            Ok(None) => Task::none(),
+            Err(e) => {
+                self.error = Some(e);
+                Task::none()
+            }
+        }
+    }
+
+    fn removed(&mut self, removal: DeviceRemoval, status: Option<String>) -> Task<Message> {
+        let DeviceRemoval { tag, idx, .. } = removal;
+        self.error = None;
+        self.status = status;
+        // Later devices of the same type moved up one
+        self.needs_reboot.retain_mut(|item| match item {
+            HwItem::Device(t, i) if *t == tag && *i == idx => false,
+            HwItem::Device(t, i) if *t == tag && *i > idx => {
+                *i -= 1;
+                true
+            }
+            _ => true,
+        });
+        self.selected -= 1;
+        self.refresh()
+    }
+
+    fn select(&mut self, idx: usize) {
+        self.selected = idx.min(self.items.len() - 1);
+        self.status = None;
+        self.error = None;
+        self.load_page();
+    }
+
+    fn edited(&mut self) -> Task<Message> {
+        self.pending = true;
+        Task::none()
+    }
+
+    fn update(&mut self, msg: Message) -> Task<Message> {
+        match msg {
+            Message::ShowTab(tab) => {
+                let mut tasks = vec![];
+                // Reverting to a snapshot changes the config under us
+                if tab == Tab::Details && self.tab != tab && !self.pending {
+                    tasks.push(self.refresh());
+                }
+                if tab == Tab::Console {
+                    tasks.push(self.console.refresh().map(Message::Console));
+                }
+                self.tab = tab;
+                Task::batch(tasks)
+            }
+            Message::Select(idx) => {
+                if idx != self.selected {
+                    if self.pending {
+                        self.switch_to = Some(idx);
+                    } else {
+                        self.select(idx);
+                    }
+                }
+                Task::none()
+            }
+            // The page switch waits for the change to be saved
+            Message::SwitchApply | Message::Apply => self.apply(),
+            Message::SwitchDiscard => {
+                if let Some(idx) = self.switch_to.take() {
+                    self.select(idx);
+                }
+                Task::none()
+            }
+            Message::SwitchCancel => {
+                self.switch_to = None;
+                Task::none()
+            }
+            Message::Revert => {
+                self.error = None;
+                self.load_page();
+                Task::none()
+            }
+            Message::Remove => {
+                self.confirm_remove = true;
+                Task::none()
+            }
+            Message::RemoveConfirmed(yes) => {
+                self.confirm_remove = false;
+                if yes {
+                    return self.remove();
+                }
+                Task::none()
+            }
+            Message::Loaded(ret) => {
+                // Don't drop edits made while the VM was read
+                match ret {
+                    Ok(vm) if !self.pending => self.loaded(vm),
+                    Ok(_) => {}
+                    Err(e) => self.error = Some(e),
+                }
+                Task::none()
+            }
+            Message::Saved(change, ret) => match ret {
+                Ok(updated_live) => self.saved(change, updated_live),
+                Err(e) => {
+                    self.error = Some(e);
+                    self.switch_to = None;
+                    Task::none()
+                }
+            },
+            Message::Removed(removal, ret) => match ret {
+                Ok(status) => self.removed(removal, status),
+                Err(e) => {
+                    self.error = Some(e);
+                    Task::none()
+                }
+            },
+            Message::Refresh if self.tab == Tab::Console => self
+                .console
+                .update(ConsoleMsg::Refresh)
+                .map(Message::Console),
+            Message::Console(inner) => self.console.update(inner).map(Message::Console),
+            Message::Refresh if self.tab == Tab::Snapshots => self
+                .snapshots
+                .update(SnapshotsMsg::Refresh)
+                .map(Message::Snapshots),
+            Message::Snapshots(inner) => self.snapshots.update(inner).map(Message::Snapshots),
+            Message::Stats(sample) => {
+                self.stats.append_sample(&sample);
+                Task::none()
+            }
+            Message::Event(event) => {
+                if event.kind() != ObjectKind::Domain || event.name() != self.name {
+                    return Task::none();
+                }
+                let mut tasks = vec![];
+                if self.tab == Tab::Details && !self.pending {
+                    tasks.push(self.refresh());
+                }
+                if self.tab == Tab::Console && matches!(event, ConnEvent::Lifecycle(..)) {
+                    tasks.push(self.console.refresh().map(Message::Console));
+                }
+                Task::batch(tasks)
+            }
+            Message::Refresh if !self.pending => self.refresh(),
+            Message::Refresh => Task::none(),
+            Message::Close => window::get_latest().and_then(window::close),
+            Message::OverviewChanged(omsg) => {
+                match omsg {
+                    OverviewMsg::TitleChanged(v) => self.title = v,
+                    OverviewMsg::DescriptionChanged(v) => self.description = v,
+                }
+                self.edited()
+            }
+            Message::CpuChanged(cmsg) => {
+                match cmsg {
+                    CpuMsg::VcpusChanged(v) => {
+                        // Keep current in step while it matches the maximum
+                        if self.vcpus_current == self.vcpus {
+                            self.vcpus_current = v.clone();
+                        }
+                        self.vcpus = v;
+                    }
+                    CpuMsg::CurrentVcpusChanged(v) => self.vcpus_current = v,
+                    CpuMsg::ModeSelected(mode) => {
+                        self.cpu.mode = mode;
+                        if mode == CpuMode::Custom && self.cpu.model.is_none() {
+                            self.cpu.model = self
+                                .domcaps
+                                .as_ref()
+                                .and_then(|c| c.get_cpu_models().into_iter().next());
+                        }
+                    }
+                    CpuMsg::ModelChanged(v) => self.cpu.model = Some(v),
+                    CpuMsg::NewFeatureChanged(v) => self.new_feature = v,
+                    CpuMsg::AddFeature => {
+                        let name = self.new_feature.trim().to_string();
+                        if name.is_empty() || self.cpu.features.iter().any(|f| f.name == name) {
+                            return Task::none();
+                        }
+                        self.cpu.features.push(CpuFeature {
+                            name,
+                            policy: "require".to_string(),
+                        });
+                        self.new_feature.clear();
+                    }
+                    CpuMsg::FeaturePolicySelected(idx, policy) => {
+                        if let Some(f) = self.cpu.features.get_mut(idx) {
+                            f.policy = policy.to_string();
+                        }
+                    }
+                    CpuMsg::RemoveFeature(idx) => {
+                        if idx < self.cpu.features.len() {
+                            self.cpu.features.remove(idx);
+                        }
+                    }
+                    CpuMsg::TopologyToggle(on) => {
+                        self.topology_manual = on;
+                        if on && !self.cpu.topology.is_set() {
+                            // Start from a topology matching the vCPU count
+                            let mut topo = CpuTopology::default();
+                            if let Ok(max) = self.vcpus.trim().parse()
+                                && topo.set_defaults_from_vcpus(max).is_ok()
+                            {
+                                self.topology = [topo.sockets, topo.dies, topo.cores, topo.threads]
+                                    .map(|v| v.unwrap_or(1).to_string());
+                            }
+                        }
+                    }
+                    CpuMsg::TopologyChanged(idx, v) => {
+                        if let Some(val) = self.topology.get_mut(idx) {
+                            *val = v;
+                        }
+                    }
+                }
+                self.edited()
+            }
+            Message::MemoryChanged(mmsg) => {
+                match mmsg {
+                    MemoryMsg::CurrentChanged(v) => self.memory_current = v,
+                    MemoryMsg::MaximumChanged(v) => self.memory_max = v,
+                    MemoryMsg::HugepagesToggle(v) => self.hugepages = v,
+                    MemoryMsg::SharedToggle(v) => self.shared_memory = v,
+                    MemoryMsg::AddCell => {
+                        let id = self.cells.iter().map(|c| c.id + 1).max().unwrap_or(0);
+                        self.cells.push(CellEntry {
+                            id,
+                            ..Default::default()
+                        });
+                    }
+                    MemoryMsg::RemoveCell(idx) => {
+                        if idx < self.cells.len() {
+                            self.cells.remove(idx);
+                        }
+                    }
+                    MemoryMsg::CellCpusChanged(idx, v) => {
+                        if let Some(c) = self.cells.get_mut(idx) {
+                            c.cpus = v;
+                        }
+                    }
+                    MemoryMsg::CellMemoryChanged(idx, v) => {
+                        if let Some(c) = self.cells.get_mut(idx) {
+                            c.memory = v;
+                        }
+                    }
+                    MemoryMsg::NumatuneModeSelected(v) => self.numatune_mode = v,
+                    MemoryMsg::NumatuneNodesetChanged(v) => self.numatune_nodeset = v,
+                }
+                self.edited()
+            }
+            Message::BootChanged(bmsg) => {
+                // Picking up or dropping a device doesn't move it
+                let dragging = matches!(bmsg, BootMsg::DragStart(_) | BootMsg::DragEnd);
+                match bmsg {
+                    BootMsg::AutostartToggle(v) => self.autostart = Some(v),
+                    BootMsg::MenuToggle(v) => self.boot_menu = v,
+                    BootMsg::DeviceToggle(dev, on) => {
+                        self.boot_devs.retain(|d| *d != dev);
+                        if on {
+                            self.boot_devs.push(dev);
+                        }
+                    }
+                    BootMsg::MoveUp(idx) => {
+                        if idx > 0 && idx < self.boot_devs.len() {
+                            self.boot_devs.swap(idx - 1, idx);
+                        }
+                    }
+                    BootMsg::MoveDown(idx) => {
+                        if idx + 1 < self.boot_devs.len() {
+                            self.boot_devs.swap(idx, idx + 1);
+                        }
+                    }
+                    BootMsg::DragStart(idx) => {
+                        self.boot_drag = (idx < self.boot_devs.len()).then_some(idx);
+                    }
+                    BootMsg::DragOver(idx) => {
+                        if let Some(from) = self.boot_drag
+                            && from != idx
+                            && idx < self.boot_devs.len()
+                        {
+                            let dev = self.boot_devs.remove(from);
+                            self.boot_devs.insert(idx, dev);
+                            self.boot_drag = Some(idx);
+                        }
+                    }
+                    BootMsg::DragEnd => self.boot_drag = None,
+                    BootMsg::FirmwareSelected(choice) => self.firmware = choice.firmware,
+                    BootMsg::NvramTemplateChanged(v) => self.nvram_template = v,
+                    BootMsg::KernelToggle(v) => self.direct_kernel = v,
+                    BootMsg::KernelChanged(v) => self.kernel = v,
+                    BootMsg::InitrdChanged(v) => self.initrd = v,
+                    BootMsg::DtbChanged(v) => self.dtb = v,
+                    BootMsg::KernelArgsChanged(v) => self.kernel_args = v,
+                    BootMsg::InitChanged(v) => self.init = v,
+                    BootMsg::InitArgsChanged(v) => self.init_args = v,
+                }
+                if dragging {
+                    return Task::none();
+                }
+                self.edited()
+            }
+            Message::Hardware(inner) => {
+                // Browsing storage changes nothing until a path is chosen
+                let browsing = matches!(
+                    inner,
+                    AddHwMsg::Browser(_) | AddHwMsg::StorageChanged(StorageMsg::Browse)
+                );
+                if !browsing {
+                    self.pending = true;
+                }
+                AddHardwareApp::update_static(&mut self.hw, inner).map(Message::Hardware)
+            }
+            Message::Browse(field) => {
+                let browser = StorageBrowser::new(self.conn.clone(), BrowseReason::Image, None);
+                self.browser = Some((browser, field));
+                Task::none()
+            }
+            Message::Browser(BrowseMsg::Cancel) => {
+                self.browser = None;
+                Task::none()
+            }
+            Message::Browser(BrowseMsg::Finish) => {
+                let Some((browser, field)) = &mut self.browser else {
+                    return Task::none();
+                };
+                match browser.finish() {
+                    Some(path) => {
+                        let msg = Message::BootChanged(field(path));
+                        self.browser = None;
+                        Task::done(msg)
+                    }
+                    None => Task::none(),
+                }
+            }
+            Message::Browser(inner) => match &mut self.browser {
+                Some((browser, _)) => browser.update(inner).map(Message::Browser),
+                None => Task::none(),
+            },
+        }
+    }
+
+    fn view(&self) -> Element<'_, Message> {
+        let tab = |label, tab| {
+            let style = if self.tab == tab {
+                button::primary
+            } else {
+                button::text
+            };
+            button(text(label))
+                .style(style)
+                .on_press(Message::ShowTab(tab))
+        };
+        let tabs = row![
+            tab("Console", Tab::Console),
+            tab("Details", Tab::Details),
+            tab("Snapshots", Tab::Snapshots)
+        ]
+        .spacing(4);
+        let content: Element<Message> = match self.tab {
+            Tab::Details => row![self.view_sidebar(), self.view_page()]
+                .spacing(16)
+                .height(Length::Fill)
+                .into(),
+            Tab::Console => self.console.view().map(Message::Console),
+            Tab::Snapshots => self.snapshots.view().map(Message::Snapshots),
+        };
+        let footer = row![
+            button(text("Refresh")).on_press(Message::Refresh),
+            Space::with_width(Length::Fill),
+            button(text("Close")).on_press(Message::Close),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+        let window = column![tabs, content, footer].padding(16).spacing(10);
+        match &self.browser {
+            Some((browser, _)) => {
+                storagebrowse::modal(window, browser.view().map(Message::Browser))
+            }
+            None => window.into(),
+        }
+    }
+
+    fn view_sidebar(&self) -> Element<'_, Message> {
+        let mut col: Column<Message> = column![].spacing(4);
+        for (idx, (_, label)) in self.items.iter().enumerate() {
+            let style = if idx == self.selected {
+                button::primary
+            } else {
+                button::text
+            };
+            col = col.push(
+                button(text(label.clone()).width(Length::Fill))
+                    .style(style)
+                    .padding(6)
+                    .on_press(Message::Select(idx)),
+            );
+        }
+        container(scrollable(col))
+            .width(Length::Fixed(220.0))
+            .height(Length::Fill)
+            .padding(8)
+            .into()
+    }
+
+    fn view_page(&self) -> Element<'_, Message> {
+        let item = self.current_item();
+        let title = text(self.items[self.selected].1.clone()).size(20);
+        let body = match &item {
+            HwItem::Overview => self.view_overview_page(),
+            HwItem::Performance => self.view_performance_page(),
+            HwItem::Cpu => self.view_cpu_page(),
+            HwItem::Memory => self.view_memory_page(),
+            HwItem::Boot => self.view_boot_page(),
+            HwItem::Device(tag, _) => match AddHardwareApp::page_for_device(tag) {
+                Some(Page::Storage) => self.hw.view_storage_page().map(Message::Hardware),
+                Some(Page::Network) => self.hw.view_network_page().map(Message::Hardware),
+                Some(_) => self.hw.view_graphics_page().map(Message::Hardware),
+                None => self.view_device_xml(&item),
+            },
+        };
+
+        let mut col: Column<Message> = column![title, body].spacing(12);
+        if self.is_active() && self.needs_reboot.contains(&item) {
+            col = col.push(text(REBOOT_MSG).size(14));
+        }
+        if let Some(status) = &self.status {
+            col = col.push(text(status.clone()).size(14));
+        }
+        if let Some(err) = &self.error {
+            col = col.push(text(err.clone()).size(14));
+        }
+        if self.switch_to.is_some() {
+            col = col.push(
+                row![
+                    text("There are unapplied changes. Would you like to apply them now?"),
+                    button(text("Apply")).on_press(Message::SwitchApply),
+                    button(text("Discard")).on_press(Message::SwitchDiscard),
+                    button(text("Cancel")).on_press(Message::SwitchCancel),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+        if self.confirm_remove {
+            col = col.push(
+                row![
+                    text("Are you sure you want to remove this device?"),
+                    button(text("Remove")).on_press(Message::RemoveConfirmed(true)),
+                    button(text("Cancel")).on_press(Message::RemoveConfirmed(false)),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+
+        let mut actions = row![].spacing(10).align_y(Alignment::Center);
+        if matches!(item, HwItem::Device(..)) {
+            actions = actions.push(
+                button(text("Remove"))
+                    .on_press_maybe((!self.confirm_remove).then_some(Message::Remove)),
+            );
+        }
+        actions = actions.push(Space::with_width(Length::Fill));
+        actions = actions
+            .push(button(text("Revert")).on_press_maybe(self.pending.then_some(Message::Revert)));
+        actions = actions
+            .push(button(text("Apply")).on_press_maybe(self.pending.then_some(Message::Apply)));
+
+        container(column![scrollable(col).height(Length::Fill), actions].spacing(10))
+            .width(Length::Fill)
+            .padding(8)
+            .into()
+    }
+
+    fn labeled<'a>(
+        label: &'a str,
+        widget: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        row![text(label).width(Length::Fixed(180.0)), widget.into()]
+            .spacing(8)
+            .align_y(Alignment::Center)
+            .into()
+    }
+
+    fn view_overview_page(&self) -> Element<'_, Message> {
+        let guest = &self.guest;
+        let state = self.state.label();
+        let info = |v: Option<String>| text(v.unwrap_or_else(|| "Unknown".to_string()));
+        column![
+            Self::labeled("Name:", text(self.name.clone())),
+            Self::labeled("UUID:", info(guest.uuid())),
+            Self::labeled("Status:", text(state)),
+            Self::labeled(
+                "Title:",
+                text_input("", &self.title)
+                    .on_input(|v| Message::OverviewChanged(OverviewMsg::TitleChanged(v)))
+                    .padding(6),
+            ),
+            Self::labeled(
+                "Description:",
+                text_input("", &self.description)
+                    .on_input(|v| Message::OverviewChanged(OverviewMsg::DescriptionChanged(v)))
+                    .padding(6),
+            ),
+            text("Hypervisor Details").size(16),
+            Self::labeled("Hypervisor:", info(guest.domain_type())),
+            Self::labeled("Architecture:", info(guest.arch())),
+            Self::labeled("Emulator:", info(guest.xml.get("./devices/emulator"))),
+            Self::labeled("Chipset:", info(guest.machine())),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    /// Current CPU, memory, disk and network figures
+    fn performance_text(&self) -> [String; 4] {
+        let stats = self.stats.vm_stats(&self.name);
+        let cur = stats.current();
+        let memory = self.live.as_ref().unwrap_or(&self.guest).memory();
+        [
+            format!("{:.0} %", cur.cpu_guest_percent),
+            format!(
+                "{} of {}",
+                pretty_mem(cur.curmem),
+                pretty_mem(memory.unwrap_or(0))
+            ),
+            format!(
+                "{:.0} KiB/s read {:.0} KiB/s write",
+                cur.disk_rd_rate, cur.disk_wr_rate
+            ),
+            format!(
+                "{:.0} KiB/s in {:.0} KiB/s out",
+                cur.net_rx_rate, cur.net_tx_rate
+            ),
+        ]
+    }
+
+    fn view_performance_page(&self) -> Element<'_, Message> {
+        let stats = self.stats.vm_stats(&self.name);
+        let [cpu, mem, disk, net] = self.performance_text();
+        let (disk_rd, disk_wr) = stats.disk_io_vectors(None, None);
+        let (net_rx, net_tx) = stats.network_traffic_vectors(None, None);
+        let graph = |label: &'static str, value: String, graph: Element<'static, Message>| {
+            column![
+                row![text(label).width(Length::Fixed(180.0)), text(value)].spacing(8),
+                graph
+            ]
+            .spacing(4)
+        };
+        column![
+            graph(
+                "CPU usage:",
+                cpu,
+                sparkline(stats.guest_cpu_vector(None)).height(64).into()
+            ),
+            graph(
+                "Memory usage:",
+                mem,
+                sparkline(stats.memory_vector(None)).height(64).into()
+            ),
+            graph(
+                "Disk I/O:",
+                disk,
+                in_out_graph(disk_rd, disk_wr).height(64).into()
+            ),
+            graph(
+                "Network I/O:",
+                net,
+                in_out_graph(net_rx, net_tx).height(64).into()
+            ),
+        ]
+        .spacing(16)
+        .into()
+    }
+
+    fn view_cpu_page(&self) -> Element<'_, Message> {
+        let mut col: Column<Message> = column![
+            Self::labeled(
+                "Maximum vCPUs:",
+                text_input("1", &self.vcpus)
+                    .on_input(|v| Message::CpuChanged(CpuMsg::VcpusChanged(v)))
+                    .padding(6),
+            ),
+            Self::labeled(
+                "Current vCPUs:",
+                text_input("1", &self.vcpus_current)
+                    .on_input(|v| Message::CpuChanged(CpuMsg::CurrentVcpusChanged(v)))
+                    .padding(6),
+            ),
+            text("Configuration").size(16),
+        ]
+        .spacing(10);
+
+        // Without domain capabilities every mode is offered
+        let caps = self.domcaps.as_ref();
+        let modes: Vec<CpuMode> = CpuMode::ALL
+            .into_iter()
+            .filter(|m| {
+                *m == self.cpu.mode
+                    || m.xml_name()
+                        .is_none_or(|name| caps.is_none_or(|c| c.supports_cpu_mode(name)))
+            })
+            .collect();
+        col = col.push(Self::labeled(
+            "Mode:",
+            pick_list(modes, Some(self.cpu.mode), |m| {
+                Message::CpuChanged(CpuMsg::ModeSelected(m))
+            }),
+        ));
+        if self.cpu.mode == CpuMode::Custom {
+            let models = caps.map(|c| c.get_cpu_models()).unwrap_or_default();
+            let model = self.cpu.model.clone().unwrap_or_default();
+            col = col.push(if models.is_empty() {
+                Self::labeled(
+                    "Model:",
+                    text_input("", &model)
+                        .on_input(|v| Message::CpuChanged(CpuMsg::ModelChanged(v)))
+                        .padding(6),
+                )
+            } else {
+                Self::labeled(
+                    "Model:",
+                    pick_list(models, Some(model), |v| {
+                        Message::CpuChanged(CpuMsg::ModelChanged(v))
+                    }),
+                )
+            });
+        }
+
+        col = col.push(text("Features").size(16));
+        for (i, f) in self.cpu.features.iter().enumerate() {
+            let policy = CPU_FEATURE_POLICIES
+                .iter()
+                .find(|p| **p == f.policy)
+                .copied();
+            col = col.push(
+                row![
+                    text(f.name.clone()).width(Length::Fixed(180.0)),
+                    pick_list(CPU_FEATURE_POLICIES, policy, move |p| {
+                        Message::CpuChanged(CpuMsg::FeaturePolicySelected(i, p))
+                    }),
+                    button(text("Remove")).on_press(Message::CpuChanged(CpuMsg::RemoveFeature(i))),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+        col = col.push(
+            row![
+                text_input("Feature name", &self.new_feature)
+                    .on_input(|v| Message::CpuChanged(CpuMsg::NewFeatureChanged(v)))
+                    .on_submit(Message::CpuChanged(CpuMsg::AddFeature))
+                    .padding(6)
+                    .width(Length::Fixed(180.0)),
+                button(text("Add")).on_press(Message::CpuChanged(CpuMsg::AddFeature)),
+            ]
+            .spacing(8),
+        );
+
+        col = col.push(text("Topology").size(16));
+        col = col.push(
+            checkbox("Manually set CPU topology", self.topology_manual)
+                .on_toggle(|v| Message::CpuChanged(CpuMsg::TopologyToggle(v))),
+        );
+        if self.topology_manual {
+            for (i, label) in TOPOLOGY_LABELS.iter().enumerate() {
+                col = col.push(Self::labeled(
+                    label,
+                    text_input("1", &self.topology[i])
+                        .on_input(move |v| Message::CpuChanged(CpuMsg::TopologyChanged(i, v)))
+                        .padding(6),
+                ));
+            }
+        }
+        col.into()
+    }
+
+    fn view_memory_page(&self) -> Element<'_, Message> {
+        let mut col: Column<Message> = column![
+            Self::labeled(
+                "Current allocation (MiB):",
+                text_input("", &self.memory_current)
+                    .on_input(|v| Message::MemoryChanged(MemoryMsg::CurrentChanged(v)))
+                    .padding(6),
+            ),
+            Self::labeled(
+                "Maximum allocation (MiB):",
+                text_input("", &self.memory_max)
+                    .on_input(|v| Message::MemoryChanged(MemoryMsg::MaximumChanged(v)))
+                    .padding(6),
+            ),
+            checkbox("Enable hugepages", self.hugepages)
+                .on_toggle(|v| Message::MemoryChanged(MemoryMsg::HugepagesToggle(v))),
+            checkbox("Enable shared memory", self.shared_memory)
+                .on_toggle(|v| Message::MemoryChanged(MemoryMsg::SharedToggle(v))),
+            text("NUMA cells").size(16),
+        ]
+        .spacing(10);
+        for (i, cell) in self.cells.iter().enumerate() {
+            col = col.push(
+                row![
+                    text(format!("Cell {}", cell.id)).width(Length::Fixed(80.0)),
+                    text_input("CPUs, e.g. 0-3", &cell.cpus)
+                        .on_input(move |v| Message::MemoryChanged(MemoryMsg::CellCpusChanged(i, v)))
+                        .padding(6),
+                    text_input("Memory (MiB)", &cell.memory)
+                        .on_input(
+                            move |v| Message::MemoryChanged(MemoryMsg::CellMemoryChanged(i, v))
+                        )
+                        .padding(6),
+                    button(text("Remove"))
+                        .on_press(Message::MemoryChanged(MemoryMsg::RemoveCell(i))),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+        col =
+            col.push(button(text("Add cell")).on_press(Message::MemoryChanged(MemoryMsg::AddCell)));
+
+        let mut modes = vec![NUMATUNE_DEFAULT.to_string()];
+        modes.extend(NUMATUNE_MODES.iter().map(|m| m.to_string()));
+        col.push(text("NUMA memory placement").size(16))
+            .push(Self::labeled(
+                "Mode:",
+                pick_list(modes, Some(self.numatune_mode.clone()), |v| {
+                    Message::MemoryChanged(MemoryMsg::NumatuneModeSelected(v))
+                }),
+            ))
+            .push(Self::labeled(
+                "Host nodeset:",
+                text_input("auto", &self.numatune_nodeset)
+                    .on_input(|v| Message::MemoryChanged(MemoryMsg::NumatuneNodesetChanged(v)))
+                    .padding(6),
+            ))
+            .into()
+    }
+
+    /// Firmware the VM can switch to, from the domain capabilities
+    fn firmware_choices(&self) -> Vec<FirmwareChoice> {
+        let choice = |label: &str, firmware| FirmwareChoice {
+            label: label.to_string(),
+            firmware,
+        };
+        let caps = self.domcaps.as_ref();
+        let mut ret = vec![choice(
+            &caps.map_or("BIOS".to_string(), |c| c.label_for_firmware_path(None)),
+            Firmware::Bios,
+        )];
+        if caps.is_none_or(|c| c.supports_firmware_efi()) {
+            ret.push(choice("UEFI", Firmware::Efi { secure_boot: false }));
+            ret.push(choice(
+                "UEFI with secure boot",
+                Firmware::Efi { secure_boot: true },
+            ));
+        }
+        if let Some(caps) = caps.filter(|c| c.supports_uefi_loader()) {
+            for path in caps.loader_values() {
+                ret.push(choice(
+                    &caps.label_for_firmware_path(Some(&path)),
+                    Firmware::Loader {
+                        path,
+                        nvram_template: None,
+                    },
+                ));
+            }
+        }
+        let current = firmware_kind(&Firmware::from_xml(&self.guest.xml));
+        if !ret.iter().any(|c| c.firmware == current)
+            && let Firmware::Loader { path, .. } = &current
+        {
+            ret.push(choice(&format!("Custom: {}", path), current.clone()));
+        }
+        ret
+    }
+
+    fn view_boot_page(&self) -> Element<'_, Message> {
+        let boot_msg = Message::BootChanged;
+        let mut col: Column<Message> = column![].spacing(10);
+        if let Some(autostart) = self.autostart {
+            col = col.push(
+                checkbox("Start virtual machine on host boot up", autostart)
+                    .on_toggle(move |v| boot_msg(BootMsg::AutostartToggle(v))),
+            );
+        }
+
+        if self.guest.is_container() {
+            return col
+                .push(text("Init").size(16))
+                .push(Self::labeled(
+                    "Init path:",
+                    text_input("/sbin/init", &self.init)
+                        .on_input(move |v| boot_msg(BootMsg::InitChanged(v)))
+                        .padding(6),
+                ))
+                .push(Self::labeled(
+                    "Init args:",
+                    text_input("", &self.init_args)
+                        .on_input(move |v| boot_msg(BootMsg::InitArgsChanged(v)))
+                        .padding(6),
+                ))
+                .into();
+        }
+
+        let choices = self.firmware_choices();
+        let selected = choices
+            .iter()
+            .find(|c| c.firmware == firmware_kind(&self.firmware))
+            .cloned();
+        col = col.push(text("Firmware").size(16)).push(Self::labeled(
+            "Firmware:",
+            pick_list(choices, selected, move |c| {
+                boot_msg(BootMsg::FirmwareSelected(c))
+            }),
+        ));
+        if matches!(self.firmware, Firmware::Loader { .. }) {
+            col = col.push(Self::labeled(
+                "NVRAM template:",
+                text_input("Default", &self.nvram_template)
+                    .on_input(move |v| boot_msg(BootMsg::NvramTemplateChanged(v)))
+                    .padding(6),
+            ));
+        }
+        if !is_uefi(&self.guest.xml) && self.firmware != Firmware::Bios {
+            col = col.push(text(REINSTALL_MSG).size(14));
+        }
+
+        col = col.push(text("Boot device order").size(16)).push(
+            checkbox("Enable boot menu", self.boot_menu)
+                .on_toggle(move |v| boot_msg(BootMsg::MenuToggle(v))),
+        );
+        // Enabled devices first, in boot order
+        let bootable = self.guest.bootable_devices();
+        let mut devs = self.boot_devs.clone();
+        devs.extend(
+            bootable
+                .iter()
+                .filter(|d| !self.boot_devs.contains(d))
+                .cloned(),
+        );
+        if devs.is_empty() {
+            col = col.push(text("No bootable devices"));
+        }
+        let nenabled = self.boot_devs.len();
+        for (i, (tag, idx)) in devs.into_iter().enumerate() {
+            let item = HwItem::Device(tag.clone(), idx);
+            let label = self
+                .items
+                .iter()
+                .find(|(it, _)| *it == item)
+                .map_or_else(|| format!("{} {}", tag, idx + 1), |(_, l)| l.clone());
+            let enabled = i < nenabled;
+            let dev = (tag, idx);
+            let mut r = row![].spacing(8).align_y(Alignment::Center);
+            if enabled {
+                r = r.push(
+                    mouse_area(text("::"))
+                        .on_press(boot_msg(BootMsg::DragStart(i)))
+                        .interaction(mouse::Interaction::Grab),
+                );
+            }
+            r = r.push(
+                checkbox(label, enabled)
+                    .on_toggle(move |v| boot_msg(BootMsg::DeviceToggle(dev.clone(), v)))
+                    .width(Length::Fill),
+            );
+            if enabled {
+                r = r
+                    .push(
+                        button(text("Up"))
+                            .on_press_maybe((i > 0).then_some(boot_msg(BootMsg::MoveUp(i)))),
+                    )
+                    .push(button(text("Down")).on_press_maybe(
+                        (i + 1 < nenabled).then_some(boot_msg(BootMsg::MoveDown(i))),
+                    ));
+                col = col.push(mouse_area(r).on_enter(boot_msg(BootMsg::DragOver(i))));
+            } else {
+                col = col.push(r);
+            }
+        }
+
+        col = col.push(text("Direct kernel boot").size(16)).push(
+            checkbox("Enable direct kernel boot", self.direct_kernel)
+                .on_toggle(move |v| boot_msg(BootMsg::KernelToggle(v))),
+        );
+        if self.direct_kernel {
+            let field = |label, value, msg: fn(String) -> BootMsg| {
+                Self::labeled(
+                    label,
+                    text_input("", value)
+                        .on_input(move |v| boot_msg(msg(v)))
+                        .padding(6),
+                )
+            };
+            let path_field = |label, value, msg: fn(String) -> BootMsg| {
+                Self::labeled(
+                    label,
+                    row![
+                        text_input("", value)
+                            .on_input(move |v| boot_msg(msg(v)))
+                            .padding(6),
+                        button(text("Browse...")).on_press(Message::Browse(msg)),
+                    ]
+                    .spacing(8)
+                    .align_y(Alignment::Center),
+                )
+            };
+            col = col
+                .push(path_field(
+                    "Kernel path:",
+                    &self.kernel,
+                    BootMsg::KernelChanged,
+                ))
+                .push(path_field(
+                    "Initrd path:",
+                    &self.initrd,
+                    BootMsg::InitrdChanged,
+                ))
+                .push(path_field("DTB path:", &self.dtb, BootMsg::DtbChanged))
+                .push(field(
+                    "Kernel args:",
+                    &self.kernel_args,
+                    BootMsg::KernelArgsChanged,
+                ));
+        }
+        col.into()
+    }
+
+    /// Devices without an editing page show their XML
+    fn view_device_xml(&self, item: &HwItem) -> Element<'_, Message> {
+        let HwItem::Device(tag, idx) = item else {
+            return column![].into();
+        };
+        let xml = self
+            .guest
+            .devices(tag)
+            .get(*idx)
+            .map(|d| unindent_device_xml(&d.to_xml()))
+            .unwrap_or_default();
+        column![
+            text("This device has no editable settings yet."),
+            text(xml).font(iced::Font::MONOSPACE).size(13),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    // Static adapter functions for iced::application (public for embedding)
+    pub fn update_static(state: &mut Self, msg: Message) -> Task<Message> {
+        state.update(msg)
+    }
+    pub fn view_static(state: &Self) -> Element<'_, Message> {
+        state.view()
+    }
+    pub fn subscription(&self) -> Subscription<Message> {
+        let options = StatsOptions {
+            flags: StatsFlags::all(),
+            ..Default::default()
+        };
+        // The drop can land anywhere, not just on a boot device
+        let drop = match self.boot_drag {
+            Some(_) => event::listen_with(|event, _, _| match event {
+                iced::Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                    Some(Message::BootChanged(BootMsg::DragEnd))
+                }
+                _ => None,
+            }),
+            None => Subscription::none(),
+        };
+        Subscription::batch([
+            self.console.subscription().map(Message::Console),
+            statsmanager::poll(self.conn.clone(), options).map(Message::Stats),
+            connevents::watch(self.conn.clone()).map(Message::Event),
+            drop,
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+
+    fn open(name: &str) -> DetailsApp {
+        let conn = testdriver();
+        DetailsApp::new(Arc::new(conn), name).unwrap()
+    }
+
+    /// Deliver the refresh task result, as the runtime would
+    fn load(app: &mut DetailsApp) {
+        let ret = DetailsApp::load_vm(app.conn.as_ref(), &app.name, false);
+        let _ = app.update(Message::Loaded(ret));
+    }
+
+    /// Send an Apply or SwitchApply, then the results of the save and
+    /// refresh tasks it starts
+    fn apply(app: &mut DetailsApp, msg: Message) {
+        let change = app.config_change();
+        let _ = app.update(msg);
+        if let Ok(change) = change {
+            let ret = DetailsApp::save_config(app.conn.as_ref(), &app.name, &change);
+            let _ = app.update(Message::Saved(change, ret));
+            load(app);
+        }
+    }
+
+    fn select(app: &mut DetailsApp, label: &str) {
+        let idx = app.items.iter().position(|(_, l)| l == label).unwrap();
+        let _ = app.update(Message::Select(idx));
+    }
+
+    #[test]
+    fn test_hw_list() {
+        let app = open("test-many-devices");
+        let labels: Vec<&str> = app.items.iter().map(|(_, l)| l.as_str()).collect();
+        assert_eq!(
+            labels[..5],
+            ["Overview", "Performance", "CPUs", "Memory", "Boot Options"]
+        );
+        for want in [
+            "Floppy 1",
+            "Floppy 2",
+            "IDE CDROM 1",
+            "Display VNC",
+            "Display Spice",
+        ] {
+            assert!(labels.contains(&want), "{} missing from {:?}", want, labels);
+        }
+        assert!(labels.iter().any(|l| l.starts_with("NIC :")));
+
+        // Hotplug events from elsewhere reload the list
+        let mut app = app;
+        let count = app.items.len();
+        let xml = "<rng model='virtio'><backend model='random'>/dev/urandom</backend></rng>";
+        app.conn
+            .attach_device("test-many-devices", xml, AffectFlags::Both)
+            .unwrap();
+        app.tab = Tab::Details;
+        let _ = app.update(Message::Event(ConnEvent::DeviceAdded {
+            domain: "test-many-devices".into(),
+            alias: String::new(),
+        }));
+        load(&mut app);
+        assert_eq!(app.items.len(), count + 1);
+    }
+
+    #[test]
+    fn test_performance_page() {
+        let mut app = open("test");
+        select(&mut app, "Performance");
+        assert_eq!(app.current_item(), HwItem::Performance);
+        for _ in 0..2 {
+            let sample = StatsSample::take(app.conn.as_ref(), StatsFlags::all(), 4);
+            let _ = app.update(Message::Stats(sample));
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let [cpu, mem, disk, net] = app.performance_text();
+        // Half a CPU second per poll in a few milliseconds pegs the CPU
+        assert_eq!(cpu, "100 %");
+        assert!(mem.ends_with(" MiB of 8192 MiB"), "{}", mem);
+        assert_eq!(disk, "0 KiB/s read 0 KiB/s write");
+        assert_eq!(net, "0 KiB/s in 0 KiB/s out");
+        assert_eq!(pretty_mem(16 * 1024 * 1024), "16.00 GiB");
+    }
+
+    #[test]
+    fn test_apply_and_remove_on_running_vm() {
+        let mut app = open("test-many-devices");
+        assert!(app.is_active());
+
+        // NIC model changes update the running VM too
+        let nic = app
+            .items
+            .iter()
+            .find(|(_, l)| l.starts_with("NIC :"))
+            .unwrap()
+            .1
+            .clone();
+        select(&mut app, &nic);
+        let _ = app.update(Message::Hardware(AddHwMsg::NetworkChanged(
+            crate::addhardware::NetworkMsg::ModelChanged("e1000".into()),
+        )));
+        assert!(app.pending);
+        apply(&mut app, Message::Apply);
+        assert_eq!(app.error, None);
+        assert!(!app.pending);
+        let live = app.live.as_ref().unwrap();
+        assert_eq!(
+            live.devices("interface")[0].get("./model/@type").as_deref(),
+            Some("e1000")
+        );
+        assert!(app.needs_reboot.is_empty());
+
+        // Memory only changes the config
+        select(&mut app, "Memory");
+        let _ = app.update(Message::MemoryChanged(MemoryMsg::MaximumChanged(
+            "2048".into(),
+        )));
+        let _ = app.update(Message::MemoryChanged(MemoryMsg::CurrentChanged(
+            "4096".into(),
+        )));
+        apply(&mut app, Message::Apply);
+        assert_eq!(
+            app.error.as_deref(),
+            Some("Current allocation cannot exceed the maximum allocation.")
+        );
+        let _ = app.update(Message::MemoryChanged(MemoryMsg::CurrentChanged(
+            "1024".into(),
+        )));
+        // Switching pages asks about the pending edit first
+        let _ = app.update(Message::Select(0));
+        assert_eq!(app.switch_to, Some(0));
+        apply(&mut app, Message::SwitchApply);
+        assert_eq!(app.current_item(), HwItem::Overview);
+        assert_eq!(app.guest.current_memory(), Some(1024 * 1024));
+        assert_ne!(
+            app.live.as_ref().unwrap().current_memory(),
+            Some(1024 * 1024)
+        );
+        assert_eq!(app.needs_reboot, vec![HwItem::Memory]);
+
+        // Removing a device unplugs it from both
+        let ndisks = app.guest.devices("disk").len();
+        select(&mut app, "Floppy 1");
+        let _ = app.update(Message::Remove);
+        let removal = app.device_removal().unwrap().unwrap();
+        let _ = app.update(Message::RemoveConfirmed(true));
+        let ret = DetailsApp::remove_device(app.conn.as_ref(), &app.name, &removal);
+        let _ = app.update(Message::Removed(removal, ret));
+        load(&mut app);
+        assert_eq!(app.error, None);
+        assert_eq!(app.guest.devices("disk").len(), ndisks - 1);
+        assert_eq!(app.live.as_ref().unwrap().devices("disk").len(), ndisks - 1);
+    }
+
+    #[test]
+    fn test_cpu_and_memory_pages() {
+        let mut app = open("test-many-devices");
+        let cpu = |m| Message::CpuChanged(m);
+        select(&mut app, "CPUs");
+        assert!(app.topology_manual);
+        assert_eq!(app.cpu.mode, CpuMode::Custom);
+        let _ = app.update(cpu(CpuMsg::VcpusChanged("10".into())));
+        let _ = app.update(cpu(CpuMsg::CurrentVcpusChanged("4".into())));
+        apply(&mut app, Message::Apply);
+        assert!(
+            app.error
+                .as_deref()
+                .is_some_and(|e| e.contains("does not match vCPU count 10")),
+            "{:?}",
+            app.error
+        );
+        let _ = app.update(cpu(CpuMsg::TopologyChanged(0, "10".into())));
+        let _ = app.update(cpu(CpuMsg::ModeSelected(CpuMode::HostPassthrough)));
+        apply(&mut app, Message::Apply);
+        assert_eq!(app.error, None);
+        let xml = &app.guest.xml;
+        assert_eq!(xml.get("./vcpu").as_deref(), Some("10"));
+        assert_eq!(xml.get("./vcpu/@current").as_deref(), Some("4"));
+        assert_eq!(xml.get("./cpu/topology/@sockets").as_deref(), Some("10"));
+        assert_eq!(xml.get("./cpu/@mode").as_deref(), Some("host-passthrough"));
+        assert!(xml.find("./cpu/model").is_none());
+
+        select(&mut app, "Memory");
+        assert!(app.hugepages);
+        assert_eq!(app.cells.len(), 2);
+        let mem = |m| Message::MemoryChanged(m);
+        let _ = app.update(mem(MemoryMsg::MaximumChanged("2048".into())));
+        let _ = app.update(mem(MemoryMsg::CurrentChanged("1024".into())));
+        let _ = app.update(mem(MemoryMsg::HugepagesToggle(false)));
+        let _ = app.update(mem(MemoryMsg::AddCell));
+        let _ = app.update(mem(MemoryMsg::CellCpusChanged(2, "8-10".into())));
+        let _ = app.update(mem(MemoryMsg::CellMemoryChanged(2, "128".into())));
+        apply(&mut app, Message::Apply);
+        assert_eq!(
+            app.error.as_deref(),
+            Some("NUMA cell 2 uses CPU 10, but the guest only has 10 vCPUs")
+        );
+        let _ = app.update(mem(MemoryMsg::CellCpusChanged(2, "9".into())));
+        apply(&mut app, Message::Apply);
+        assert_eq!(app.error, None);
+        let xml = &app.guest.xml;
+        assert!(xml.find("./memoryBacking").is_none());
+        assert_eq!(xml.count("./cpu/numa/cell"), 3);
+        assert_eq!(
+            xml.get("./cpu/numa/cell[3]/@memory").as_deref(),
+            Some("131072")
+        );
+        assert_eq!(
+            xml.get("./cpu/numa/cell[1]/@memory").as_deref(),
+            Some("219136")
+        );
+    }
+
+    #[test]
+    fn test_boot_page() {
+        let boot = |m| Message::BootChanged(m);
+        let mut app = open("test-many-devices");
+        select(&mut app, "Boot Options");
+        let order = app.guest.boot_order();
+        assert_eq!(app.boot_devs, order);
+        let _ = app.update(boot(BootMsg::MoveDown(0)));
+        let _ = app.update(boot(BootMsg::DeviceToggle(order[2].clone(), false)));
+        apply(&mut app, Message::Apply);
+        assert_eq!(app.error, None);
+        let mut want = order.clone();
+        want.swap(0, 1);
+        want.remove(2);
+        assert_eq!(app.guest.boot_order(), want);
+        assert_eq!(app.status, None);
+
+        // Dragging the second device over the first swaps them back
+        let _ = app.update(boot(BootMsg::DragStart(1)));
+        assert!(!app.pending);
+        let _ = app.update(boot(BootMsg::DragOver(0)));
+        let _ = app.update(boot(BootMsg::DragEnd));
+        assert_eq!(app.boot_drag, None);
+        let _ = app.update(boot(BootMsg::DragOver(1)));
+        apply(&mut app, Message::Apply);
+        want.swap(0, 1);
+        assert_eq!(app.guest.boot_order(), want);
+
+        let mut app = open("test");
+        select(&mut app, "Boot Options");
+        assert_eq!(app.autostart, Some(false));
+        assert_eq!(app.firmware, Firmware::Bios);
+        let efi = app
+            .firmware_choices()
+            .into_iter()
+            .find(|c| c.firmware == Firmware::Efi { secure_boot: false })
+            .unwrap();
+        let _ = app.update(boot(BootMsg::FirmwareSelected(efi)));
+        let _ = app.update(boot(BootMsg::AutostartToggle(true)));
+        let _ = app.update(boot(BootMsg::InitrdChanged("/boot/initrd".into())));
+        apply(&mut app, Message::Apply);
+        assert_eq!(app.error, None);
+        assert_eq!(app.status.as_deref(), Some(REINSTALL_MSG));
+        assert!(is_uefi(&app.guest.xml));
+        assert!(app.guest.xml.find("./os/initrd").is_none());
+        assert_eq!(app.conn.domain_autostart("test"), Ok(true));
+    }
+}
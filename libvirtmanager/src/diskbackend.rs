@@ -0,0 +1,682 @@
+// Disk storage inside and outside of storage pools (port of
+// virtinst/diskbackend.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Where a disk path lives: a volume of a storage pool, a local file or
+//! block device, or a network URL. Storage for new paths is created in
+//! the pool managing their directory, with a directory pool made on the
+//! fly for unmanaged ones, or as a local file when the backend has no
+//! pool APIs. The directories leading to a path can also be checked
+//! and fixed for search access by the emulator's user.
+
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom};
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::process::Command;
+
+use log::debug;
+
+use crate::connection::Connection;
+use crate::qcow2;
+use crate::storage::{StoragePool, StorageVolume, lookup_volume_by_path};
+
+/// Whether `path` is a URL like `http://...` or `gluster+tcp://...`
+pub fn path_is_url(path: &str) -> bool {
+    let Some((scheme, _)) = path.split_once("://") else {
+        return false;
+    };
+    let (proto, transport) = scheme.split_once('+').unwrap_or((scheme, "a"));
+    let alpha = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphabetic());
+    alpha(proto) && alpha(transport)
+}
+
+/// Whether `path` is the target of a network volume of `conn`
+pub fn path_is_network_vol(conn: &dyn Connection, path: &str) -> bool {
+    lookup_volume_by_path(conn, path)
+        .is_some_and(|(_, vol)| vol.vtype.as_deref() == Some("network"))
+}
+
+/// Paths we may make a directory pool for. Pooling up /dev and friends
+/// would only cause trouble.
+fn can_auto_manage(path: &str) -> bool {
+    if path_is_url(path) {
+        return false;
+    }
+    !["/dev", "/sys", "/proc"]
+        .iter()
+        .any(|prefix| path == *prefix || path.starts_with(&format!("{}/", prefix)))
+}
+
+/// Disk type implied by a local path: block for block devices, dir for
+/// directories, file for everything else including paths yet to be created
+pub fn local_dev_type(path: &str) -> &'static str {
+    match std::fs::metadata(path) {
+        Ok(m) if m.file_type().is_block_device() => "block",
+        Ok(m) if m.is_dir() => "dir",
+        _ => "file",
+    }
+}
+
+pub(crate) fn absolute_path(path: &str) -> String {
+    std::path::absolute(path)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The pool and volume `path` belongs to. A pool targeting the path's
+/// directory is returned even without a volume, so storage can be
+/// created there; it gets started and refreshed to find the volume.
+pub fn check_if_path_managed(
+    conn: &dyn Connection,
+    path: &str,
+) -> Result<(Option<StoragePool>, Option<StorageVolume>), String> {
+    if let Some((pool, vol)) = lookup_volume_by_path(conn, path) {
+        return Ok((Some(pool), Some(vol)));
+    }
+    let Some(dir) = Path::new(path).parent() else {
+        return Ok((None, None));
+    };
+    let Some(pool) = StoragePool::lookup_pool_by_path(conn, dir) else {
+        return Ok((None, None));
+    };
+    let active = conn
+        .list_pools()?
+        .iter()
+        .any(|p| p.name == pool.name && p.active);
+    if !active {
+        debug!("Starting pool '{}' to look up '{}'", pool.name, path);
+        conn.start_pool(&pool.name)
+            .map_err(|e| format!("Error starting pool '{}': {}", pool.name, e))?;
+    }
+    if let Err(e) = conn.refresh_pool(&pool.name) {
+        debug!("Error refreshing pool '{}': {}", pool.name, e);
+    }
+    if let Some((pool, vol)) = lookup_volume_by_path(conn, path) {
+        return Ok((Some(pool), Some(vol)));
+    }
+    let vol = conn
+        .volume_xml(&pool.name, &file_name(path))
+        .and_then(|xml| StorageVolume::parse(&xml))
+        .ok();
+    Ok((Some(pool), vol))
+}
+
+/// Make `path` absolute and find its pool and volume, defining,
+/// starting and autostarting a directory pool for its parent directory
+/// if nothing manages it yet
+pub fn manage_path(
+    conn: &dyn Connection,
+    path: &str,
+) -> Result<(String, Option<StoragePool>, Option<StorageVolume>), String> {
+    let path = if path_is_url(path) || path_is_network_vol(conn, path) {
+        path.to_string()
+    } else {
+        absolute_path(path)
+    };
+    let (pool, vol) = check_if_path_managed(conn, &path)?;
+    if pool.is_some() || vol.is_some() || !can_auto_manage(&path) {
+        return Ok((path, pool, vol));
+    }
+
+    let dirname = Path::new(&path)
+        .parent()
+        .map(|d| d.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "/".to_string());
+    let mut poolname = file_name(&dirname).replace(' ', "_");
+    if poolname.is_empty() {
+        poolname = "dirpool".to_string();
+    }
+    let poolname = StoragePool::find_free_name(conn, &poolname)?;
+    debug!("Attempting to build pool={} target={}", poolname, dirname);
+    let mut poolxml = StoragePool::new("dir", &poolname);
+    poolxml.set_target_path(Some(&dirname));
+    poolxml.validate(conn)?;
+    // Local directories are made if needed, like a new default pool
+    let build = !conn.is_remote() && !Path::new(&dirname).exists();
+    poolxml.install(conn, build, true, true)?;
+    let (pool, vol) = check_if_path_managed(conn, &path)?;
+    Ok((path, pool, vol))
+}
+
+/// Whether `path` is known to exist: a volume of `conn`, or a local
+/// path. Lookup errors count as not existing.
+pub fn path_definitely_exists(conn: &dyn Connection, path: &str) -> bool {
+    match check_if_path_managed(conn, path) {
+        Ok((_, Some(_))) => true,
+        Ok(_) if !conn.is_remote() => Path::new(path).exists(),
+        Ok(_) => false,
+        Err(e) => {
+            debug!("Error checking if '{}' exists: {}", path, e);
+            false
+        }
+    }
+}
+
+/// Size of a local file or block device in bytes
+fn local_size(path: &str) -> u64 {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return 0;
+    };
+    if !meta.file_type().is_block_device() {
+        return meta.len();
+    }
+    File::open(path)
+        .and_then(|mut f| f.seek(SeekFrom::End(0)))
+        .unwrap_or(0)
+}
+
+/// Free bytes for unprivileged users on the filesystem holding `dir`
+fn local_available(dir: &Path) -> Option<u64> {
+    let cpath = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: cpath is a valid C string and st a writable statvfs
+    if unsafe { libc::statvfs(cpath.as_ptr(), &mut st) } != 0 {
+        return None;
+    }
+    Some(st.f_bavail as u64 * st.f_frsize as u64)
+}
+
+/// An existing disk path, or one that doesn't exist and won't be
+/// created: a volume, a local path, or a network URL
+pub struct StorageBackend {
+    path: String,
+    pool: Option<StoragePool>,
+    vol: Option<StorageVolume>,
+    remote: bool,
+}
+
+impl StorageBackend {
+    pub fn new(conn: &dyn Connection, path: &str) -> Result<Self, String> {
+        let (pool, vol) = if path_is_url(path) {
+            (None, None)
+        } else {
+            check_if_path_managed(conn, path)?
+        };
+        Ok(Self {
+            path: path.to_string(),
+            pool,
+            vol,
+            remote: conn.is_remote(),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The pool of the volume, or the one managing the path's directory
+    pub fn pool(&self) -> Option<&StoragePool> {
+        self.pool.as_ref()
+    }
+
+    pub fn vol(&self) -> Option<&StorageVolume> {
+        self.vol.as_ref()
+    }
+
+    /// "file", "block", "dir" or "network"
+    pub fn dev_type(&self) -> String {
+        match (&self.vol, &self.pool) {
+            (Some(vol), Some(pool)) => return vol.file_type(pool).to_string(),
+            (None, Some(pool)) => return pool.disk_type().to_string(),
+            _ => {}
+        }
+        if path_is_url(&self.path) {
+            "network".to_string()
+        } else if self.remote {
+            "file".to_string()
+        } else {
+            local_dev_type(&self.path).to_string()
+        }
+    }
+
+    /// Remote paths that can't be auto managed, like /dev/sdX, are
+    /// taken on trust since there's no checking them
+    pub fn exists(&self) -> bool {
+        if self.vol.is_some() {
+            return true;
+        }
+        if !self.remote && Path::new(&self.path).exists() {
+            return true;
+        }
+        if self.pool.is_some() {
+            return false;
+        }
+        self.dev_type() == "network" || (self.remote && !can_auto_manage(&self.path))
+    }
+
+    /// Capacity in bytes, 0 if unknown
+    pub fn size(&self) -> u64 {
+        match &self.vol {
+            Some(vol) => vol.capacity(),
+            None if self.remote => 0,
+            None => local_size(&self.path),
+        }
+    }
+
+    /// The image format, when a volume reports one
+    pub fn driver_type(&self) -> Option<String> {
+        let (vol, pool) = (self.vol.as_ref()?, self.pool.as_ref()?);
+        if !vol.supports_format(pool) {
+            return None;
+        }
+        vol.format().map(str::to_string)
+    }
+}
+
+/// Storage to create for a disk path that doesn't exist yet
+pub struct StorageCreator {
+    path: String,
+    vol: StorageVolume,
+    pool: Option<StoragePool>,
+    remote: bool,
+}
+
+impl StorageCreator {
+    /// A `size` bytes volume at `path`, qcow2 by default where the pool
+    /// takes formats. Nothing is created until `create`.
+    pub fn new(
+        conn: &dyn Connection,
+        path: &str,
+        size: u64,
+        format: Option<&str>,
+        sparse: bool,
+    ) -> Result<Self, String> {
+        let path = absolute_path(path);
+        let (pool, _) = check_if_path_managed(conn, &path)?;
+        if pool.is_none() && !can_auto_manage(&path) {
+            return Err(format!(
+                "Don't know how to create storage for path '{}'. Use libvirt APIs to manage \
+                 the parent directory as a pool first.",
+                path
+            ));
+        }
+        let mut vol = StorageVolume::new(&file_name(&path));
+        vol.set_capacity(size);
+        vol.set_allocation(if sparse { 0 } else { size });
+        let takes_format = pool.as_ref().is_none_or(|p| vol.supports_format(p));
+        match format {
+            Some(_) if !takes_format => {
+                return Err("Format attribute not supported for this volume type".to_string());
+            }
+            Some(fmt) => vol.set_format(Some(fmt)),
+            None if takes_format => vol.set_format(Some("qcow2")),
+            None => {}
+        }
+        Ok(Self {
+            path,
+            vol,
+            pool,
+            remote: conn.is_remote(),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn dev_type(&self) -> &str {
+        match &self.pool {
+            Some(pool) => self.vol.file_type(pool),
+            None => "file",
+        }
+    }
+
+    pub fn driver_type(&self) -> Option<&str> {
+        self.vol.format()
+    }
+
+    /// Whether the volume won't fit: a fatal flag for the allocation,
+    /// and the message to show
+    pub fn is_size_conflict(&self) -> (bool, Option<String>) {
+        let avail = match &self.pool {
+            Some(pool) => Some(pool.available()),
+            None if self.remote => None,
+            None => Path::new(&self.path).ancestors().find_map(local_available),
+        };
+        match avail {
+            Some(avail) => self.vol.is_size_conflict(avail),
+            None => (false, None),
+        }
+    }
+
+    /// Create the storage: a volume of the pool managing the path's
+    /// directory, or a local file if `conn` can't manage pools
+    pub fn create(&self, conn: &dyn Connection) -> Result<(), String> {
+        if !self.remote && conn.list_pools().is_err() {
+            return self.create_local();
+        }
+        let (_, pool, vol) = manage_path(conn, &self.path)?;
+        if vol.is_some() {
+            return Err(format!("Storage volume '{}' already exists", self.path));
+        }
+        let pool = pool.ok_or_else(|| format!("No storage pool manages '{}'", self.path))?;
+        debug!(
+            "Creating volume '{}' on pool '{}'",
+            self.vol.name, pool.name
+        );
+        let mut vol = self.vol.clone();
+        vol.validate(conn, &pool.name)?;
+        vol.install(conn, &pool.name, None)
+    }
+
+    fn create_local(&self) -> Result<(), String> {
+        debug!("Creating local disk image '{}'", self.path);
+        if let Some(parent) = Path::new(&self.path).parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Error creating directory '{}': {}", parent.display(), e))?;
+        }
+        if self.vol.format() == Some("qcow2") {
+            let opts = qcow2::CreateOptions {
+                size: self.vol.capacity(),
+                ..Default::default()
+            };
+            return qcow2::create(&self.path, &opts);
+        }
+        let err = |e: std::io::Error| format!("Error creating '{}': {}", self.path, e);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.path)
+            .map_err(err)?;
+        file.set_len(self.vol.capacity()).map_err(err)?;
+        if self.vol.allocation() > 0 {
+            // SAFETY: the descriptor is open for the duration of the call
+            let ret =
+                unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, self.vol.allocation() as i64) };
+            if ret != 0 {
+                return Err(err(std::io::Error::from_raw_os_error(ret)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The user the emulator runs as, for search permission checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DacUser {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl DacUser {
+    /// Look `name` up in the passwd database
+    pub fn lookup(name: &str) -> Option<Self> {
+        let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+        passwd.lines().find_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 4 || fields[0] != name {
+                return None;
+            }
+            Some(Self {
+                name: name.to_string(),
+                uid: fields[2].parse().ok()?,
+                gid: fields[3].parse().ok()?,
+            })
+        })
+    }
+
+    /// The unprivileged user a local system qemu connection runs VMs as
+    pub fn for_connection(conn: &dyn Connection) -> Option<Self> {
+        let uri = conn.uri();
+        if conn.is_remote() || !uri.starts_with("qemu") || !uri.ends_with("/system") {
+            return None;
+        }
+        ["qemu", "libvirt-qemu"]
+            .iter()
+            .find_map(|name| Self::lookup(name))
+            .filter(|user| user.uid != 0)
+    }
+}
+
+fn is_dir_searchable(dir: &str, user: &DacUser) -> bool {
+    let Ok(meta) = std::fs::metadata(dir) else {
+        return false;
+    };
+    let flag = if meta.uid() == user.uid {
+        0o100
+    } else if meta.gid() == user.gid {
+        0o010
+    } else {
+        0o001
+    };
+    if meta.mode() & flag != 0 {
+        return true;
+    }
+
+    // Check the POSIX ACL too, since that's what we use to fix access
+    let out = match Command::new("getfacl").arg(dir).output() {
+        Ok(out) => out,
+        Err(e) => {
+            debug!("Didn't find the getfacl command: {}", e);
+            return false;
+        }
+    };
+    if !out.status.success() {
+        debug!(
+            "Cmd 'getfacl {}' failed: {}",
+            dir,
+            String::from_utf8_lossy(&out.stderr)
+        );
+        return false;
+    }
+    let prefix = format!("user:{}:", user.name);
+    String::from_utf8_lossy(&out.stdout).lines().any(|line| {
+        line.strip_prefix(&prefix)
+            .is_some_and(|perms| perms.as_bytes().get(2) == Some(&b'x'))
+    })
+}
+
+/// The directories leading to `path` that `user` can't search, from the
+/// deepest up
+pub fn is_path_searchable(path: &str, user: &DacUser) -> Vec<String> {
+    let path = Path::new(path);
+    let start = if path.is_dir() {
+        Some(path)
+    } else {
+        path.parent()
+    };
+    start
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .filter(|dir| !dir.is_empty() && !is_dir_searchable(dir, user))
+        .collect()
+}
+
+fn fix_perms_acl(dir: &str, username: &str) -> Result<(), String> {
+    let out = Command::new("setfacl")
+        .args(["--modify", &format!("user:{}:x", username), dir])
+        .output()
+        .map_err(|e| e.to_string())?;
+    debug!("Ran command 'setfacl --modify user:{}:x {}'", username, dir);
+    if !out.status.success() {
+        return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+fn fix_perms_chmod(dir: &str) -> Result<(), String> {
+    debug!("Attempting to chmod: {}", dir);
+    let mode = std::fs::metadata(dir).map_err(|e| e.to_string())?.mode();
+    let newmode = mode | 0o001;
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(newmode))
+        .map_err(|e| e.to_string())?;
+    if std::fs::metadata(dir).map_err(|e| e.to_string())?.mode() != newmode {
+        return Err(format!("Permissions on '{}' did not stick", dir));
+    }
+    Ok(())
+}
+
+/// Give `username` search access to `dirs` with an ACL, falling back
+/// to the world search bit once setfacl fails. Returns the directories
+/// that couldn't be fixed, with the errors.
+pub fn set_dirs_searchable(dirs: &[String], username: &str) -> Vec<(String, String)> {
+    let mut useacl = true;
+    let mut errors = vec![];
+    for dir in dirs {
+        let mut ret = Err(String::new());
+        if useacl {
+            ret = fix_perms_acl(dir, username);
+            if let Err(e) = &ret {
+                debug!("setfacl failed on '{}': {}", dir, e);
+                useacl = false;
+            }
+        }
+        if !useacl {
+            ret = fix_perms_chmod(dir);
+        }
+        if let Err(e) = ret {
+            errors.push((dir.clone(), e));
+        }
+    }
+    errors
+}
+
+/// The emulator user of a connection and the directories it can't
+/// search to reach a path, from the top down
+#[derive(Debug, Clone, Default)]
+pub struct SearchData {
+    pub user: Option<DacUser>,
+    pub fixlist: Vec<String>,
+}
+
+/// Check that the emulator of `conn` can search its way to `path`. Only
+/// local system connections run VMs as a user we can check.
+pub fn check_path_search(conn: &dyn Connection, path: &str) -> SearchData {
+    debug!("check_path_search path={}", path);
+    let mut data = SearchData::default();
+    if path.is_empty() || path_is_url(path) {
+        return data;
+    }
+    let Some(user) = DacUser::for_connection(conn) else {
+        return data;
+    };
+    if path_is_network_vol(conn, path) {
+        return data;
+    }
+    data.fixlist = is_path_searchable(&absolute_path(path), &user);
+    data.fixlist.reverse();
+    data.user = Some(user);
+    data
+}
+
+/// Fix what `check_path_search` found, returning the failures
+pub fn fix_path_search(data: &SearchData) -> Vec<(String, String)> {
+    match &data.user {
+        Some(user) => set_dirs_searchable(&data.fixlist, &user.name),
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+
+    #[test]
+    fn test_diskbackend_lookup() {
+        assert!(path_is_url("http://example.com/foo.iso"));
+        assert!(path_is_url("gluster+tcp://host/vol/img"));
+        assert!(!path_is_url("/tmp/foo://bar") && !path_is_url("/var/lib/foo.img"));
+        assert!(!can_auto_manage("/dev/sda") && !can_auto_manage("/dev"));
+        assert!(can_auto_manage("/devices/foo.img"));
+
+        let conn = testdriver();
+        let backend = StorageBackend::new(&conn, "/pool-dir/testvol1.img").unwrap();
+        assert!(backend.exists() && backend.vol().is_some());
+        assert_eq!(backend.pool().unwrap().name, "pool-dir");
+        assert_eq!(backend.dev_type(), "file");
+        assert!(path_definitely_exists(&conn, "/pool-dir/testvol1.img"));
+        // In a pool's directory, but not a volume of it
+        let backend = StorageBackend::new(&conn, "/pool-dir/idontexist.img").unwrap();
+        assert!(!backend.exists() && backend.pool().is_some());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("local.img");
+        std::fs::write(&path, [0u8; 4096]).unwrap();
+        let path = path.to_str().unwrap();
+        let backend = StorageBackend::new(&conn, path).unwrap();
+        assert!(backend.exists() && backend.pool().is_none());
+        assert_eq!(
+            (backend.size(), backend.dev_type().as_str()),
+            (4096, "file")
+        );
+        assert_eq!(local_dev_type(dir.path().to_str().unwrap()), "dir");
+        assert!(!path_definitely_exists(&conn, &format!("{}.new", path)));
+    }
+
+    #[test]
+    fn test_diskbackend_create() {
+        let conn = testdriver();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("new.qcow2");
+        let path = path.to_str().unwrap();
+        let creator = StorageCreator::new(&conn, path, 1 << 30, None, true).unwrap();
+        assert_eq!(creator.driver_type(), Some("qcow2"));
+        assert!(!creator.is_size_conflict().0);
+        assert!(StorageCreator::new(&conn, "/dev/idontexist", 1 << 30, None, true).is_err());
+
+        // The directory gets a pool named after it, holding the volume
+        creator.create(&conn).unwrap();
+        let backend = StorageBackend::new(&conn, path).unwrap();
+        let dirname = dir.path().file_name().unwrap().to_str().unwrap();
+        assert_eq!(backend.pool().unwrap().name, dirname);
+        assert!(conn.pool_autostart(dirname).unwrap());
+        assert_eq!(backend.size(), 1 << 30);
+        assert_eq!(backend.driver_type().as_deref(), Some("qcow2"));
+        assert!(creator.create(&conn).is_err());
+
+        // Without pool APIs the file is made locally
+        let raw = dir.path().join("sub/new.raw");
+        let raw = raw.to_str().unwrap();
+        let creator = StorageCreator::new(&conn, raw, 1 << 20, Some("raw"), false).unwrap();
+        creator.create_local().unwrap();
+        let meta = std::fs::metadata(raw).unwrap();
+        assert_eq!(
+            (meta.len(), meta.blocks() * 512 >= 1 << 20),
+            (1 << 20, true)
+        );
+        assert!(creator.create_local().is_err());
+    }
+
+    #[test]
+    fn test_diskbackend_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("a");
+        std::fs::create_dir(&sub).unwrap();
+        std::fs::set_permissions(&sub, std::fs::Permissions::from_mode(0o700)).unwrap();
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o700)).unwrap();
+        let user = DacUser {
+            name: "nobody".to_string(),
+            uid: 65534,
+            gid: 65534,
+        };
+        let path = sub.join("disk.img");
+        let path = path.to_str().unwrap();
+        let dirs = [
+            sub.to_str().unwrap().to_string(),
+            dir.path().to_str().unwrap().to_string(),
+        ];
+        // The temp dir's own parents are searchable on any sane system
+        assert_eq!(is_path_searchable(path, &user)[..2], dirs);
+        assert_eq!(is_path_searchable(sub.to_str().unwrap(), &user)[..2], dirs);
+
+        assert_eq!(set_dirs_searchable(&dirs, &user.name), vec![]);
+        let fixlist = is_path_searchable(path, &user);
+        assert!(dirs.iter().all(|d| !fixlist.contains(d)));
+    }
+}
@@ -0,0 +1,339 @@
+// Disk image copying for clones (replaces the local copy paths of
+// virtinst/cloner.py and virtinst/storage.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Copy engine for disk images. A copy-on-write clone via FICLONE is
+//! tried first; otherwise only the data regions of the source, as found
+//! with SEEK_DATA/SEEK_HOLE, are copied with copy_file_range, falling
+//! back to plain reads and writes when the kernel can't do that between
+//! the two files. Progress goes to a `Meter`, which can also cancel.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+use crate::progress::Meter;
+
+/// Largest single copy_file_range or read request
+const CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+/// Block size for the read/write fallback and zero detection
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+fn cancelled_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "Operation cancelled")
+}
+
+fn check_cancelled(meter: &dyn Meter) -> io::Result<()> {
+    if meter.is_cancelled() {
+        return Err(cancelled_error());
+    }
+    Ok(())
+}
+
+fn try_reflink(src: &File, dst: &File) -> io::Result<()> {
+    // SAFETY: both descriptors are open for the duration of the call
+    let ret = unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn lseek(file: &File, offset: u64, whence: libc::c_int) -> io::Result<Option<u64>> {
+    // SAFETY: lseek has no memory safety requirements
+    let ret = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) };
+    if ret < 0 {
+        let err = io::Error::last_os_error();
+        // ENXIO means there is no more data past offset
+        if err.raw_os_error() == Some(libc::ENXIO) {
+            return Ok(None);
+        }
+        return Err(err);
+    }
+    Ok(Some(ret as u64))
+}
+
+/// The (offset, length) regions of `file` that may hold data. Falls back
+/// to the whole file when the filesystem can't report holes.
+fn data_segments(file: &File, size: u64) -> Vec<(u64, u64)> {
+    let mut segments = vec![];
+    let mut pos = 0;
+    while pos < size {
+        let data = match lseek(file, pos, libc::SEEK_DATA) {
+            Ok(Some(data)) => data,
+            Ok(None) => break,
+            Err(e) => {
+                log::debug!("SEEK_DATA unsupported, copying everything: {}", e);
+                return vec![(0, size)];
+            }
+        };
+        let hole = match lseek(file, data, libc::SEEK_HOLE) {
+            Ok(Some(hole)) => hole.min(size),
+            _ => size,
+        };
+        if hole > data {
+            segments.push((data, hole - data));
+        }
+        pos = hole;
+    }
+    segments
+}
+
+/// Copy `len` bytes at `offset` with copy_file_range. Returns false if
+/// the kernel can't copy between these files and nothing was copied.
+fn copy_range(
+    src: &File,
+    dst: &File,
+    offset: u64,
+    len: u64,
+    meter: &dyn Meter,
+) -> io::Result<bool> {
+    let mut off_in = offset as libc::loff_t;
+    let mut off_out = offset as libc::loff_t;
+    let end = offset + len;
+    while (off_in as u64) < end {
+        check_cancelled(meter)?;
+        let want = (end - off_in as u64).min(CHUNK_SIZE) as usize;
+        // SAFETY: the offsets are valid for the duration of the call
+        let ret = unsafe {
+            libc::copy_file_range(
+                src.as_raw_fd(),
+                &mut off_in,
+                dst.as_raw_fd(),
+                &mut off_out,
+                want,
+                0,
+            )
+        };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            let unsupported = matches!(
+                err.raw_os_error(),
+                Some(libc::EXDEV | libc::ENOSYS | libc::EOPNOTSUPP | libc::EINVAL)
+            );
+            if unsupported && off_in as u64 == offset {
+                log::debug!("copy_file_range unsupported, falling back: {}", err);
+                return Ok(false);
+            }
+            return Err(err);
+        }
+        if ret == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Source file shrank during copy",
+            ));
+        }
+        meter.update(off_in as u64);
+    }
+    Ok(true)
+}
+
+/// Copy `len` bytes at `offset` with plain reads and writes. All-zero
+/// blocks are skipped when `skip_zero` is set and the destination
+/// already reads back as zeros there.
+fn rw_range(
+    src: &File,
+    dst: &File,
+    offset: u64,
+    len: u64,
+    skip_zero: bool,
+    meter: &dyn Meter,
+) -> io::Result<()> {
+    let mut buf = vec![0u8; BLOCK_SIZE];
+    let mut pos = offset;
+    let end = offset + len;
+    while pos < end {
+        check_cancelled(meter)?;
+        let want = (end - pos).min(BLOCK_SIZE as u64) as usize;
+        let count = src.read_at(&mut buf[..want], pos)?;
+        if count == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Source file shrank during copy",
+            ));
+        }
+        let block = &buf[..count];
+        if !(skip_zero && block.iter().all(|b| *b == 0)) {
+            dst.write_all_at(block, pos)?;
+        }
+        pos += count as u64;
+        meter.update(pos);
+    }
+    Ok(())
+}
+
+fn file_size(file: &mut File) -> io::Result<u64> {
+    let meta = file.metadata()?;
+    if meta.is_file() {
+        return Ok(meta.len());
+    }
+    // Block devices report their size through seeking
+    let size = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(size)
+}
+
+fn do_copy(
+    src: &str,
+    dst: &str,
+    sparse: bool,
+    reflink: bool,
+    meter: &dyn Meter,
+) -> io::Result<u64> {
+    let mut srcfile = File::open(src)?;
+    let size = file_size(&mut srcfile)?;
+    let dstfile = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(dst)?;
+    let regular = dstfile.metadata()?.is_file();
+
+    let basename = Path::new(dst)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| dst.to_string());
+    meter.start(&format!("Cloning {}", basename), Some(size));
+    check_cancelled(meter)?;
+
+    if regular {
+        match try_reflink(&srcfile, &dstfile) {
+            Ok(()) => {
+                log::debug!("Reflinked {} to {}", src, dst);
+                meter.update(size);
+                meter.end();
+                return Ok(size);
+            }
+            Err(e) if reflink => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("reflink copy failed: {}", e),
+                ));
+            }
+            Err(e) => log::debug!("reflink unavailable, copying: {}", e),
+        }
+
+        // Start from an empty file of the right size, which is all holes
+        dstfile.set_len(0)?;
+        dstfile.set_len(size)?;
+        if !sparse && size > 0 {
+            // SAFETY: fallocate has no memory safety requirements
+            let ret = unsafe { libc::fallocate(dstfile.as_raw_fd(), 0, 0, size as libc::off_t) };
+            if ret < 0 {
+                log::debug!("fallocate failed: {}", io::Error::last_os_error());
+            }
+        }
+    }
+
+    // Holes in the source only need copying when the destination
+    // can't be trusted to read back zeros there
+    let segments = if sparse && regular {
+        data_segments(&srcfile, size)
+    } else {
+        vec![(0, size)]
+    };
+    let mut use_copy_range = true;
+    for (offset, len) in segments {
+        if use_copy_range {
+            use_copy_range = copy_range(&srcfile, &dstfile, offset, len, meter)?;
+        }
+        if !use_copy_range {
+            rw_range(&srcfile, &dstfile, offset, len, sparse && regular, meter)?;
+        }
+    }
+    meter.update(size);
+    meter.end();
+    Ok(size)
+}
+
+/// Copy the disk image at `src` to `dst`, returning the number of bytes
+/// in the image. `dst` may be a new file or an existing block device.
+///
+/// With `reflink` a copy-on-write clone is required, otherwise it's only
+/// attempted. With `sparse` holes in the source stay holes, otherwise
+/// the destination is fully allocated. A partially written new `dst` is
+/// removed on failure or when `meter` cancels.
+pub fn copy_disk(
+    src: &str,
+    dst: &str,
+    sparse: bool,
+    reflink: bool,
+    meter: &dyn Meter,
+) -> Result<u64, String> {
+    log::debug!(
+        "Cloning {} to {} (sparse={}, reflink={})",
+        src,
+        dst,
+        sparse,
+        reflink
+    );
+    let created = Path::new(dst).symlink_metadata().is_err();
+    do_copy(src, dst, sparse, reflink, meter).map_err(|e| {
+        if created && let Err(rmerr) = std::fs::remove_file(dst) {
+            log::debug!("Failed to remove partial {}: {}", dst, rmerr);
+        }
+        format!("Error cloning diskimage {} to {}: {}", src, dst, e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asyncjob::AsyncJob;
+    use crate::progress::NullMeter;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_copy_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src.img");
+        let size = 16 * 1024 * 1024;
+        let srcfile = File::create(&src).unwrap();
+        srcfile.set_len(size).unwrap();
+        srcfile.write_all_at(b"bootsector", 0).unwrap();
+        srcfile.write_all_at(&[7u8; 4096], 9 * 1024 * 1024).unwrap();
+        let src = src.to_str().unwrap();
+        let expected = std::fs::read(src).unwrap();
+
+        let dst = dir.path().join("sparse.img");
+        let dst = dst.to_str().unwrap();
+        let job = AsyncJob::new("Cloning", true);
+        assert_eq!(copy_disk(src, dst, true, false, &job), Ok(size));
+        assert_eq!(std::fs::read(dst).unwrap(), expected);
+        let progress = job.progress();
+        assert_eq!(progress.text, "Cloning sparse.img");
+        assert_eq!(progress.fraction(), Some(1.0));
+        if std::fs::metadata(src).unwrap().blocks() * 512 < size {
+            // Holes survive when the filesystem has them at all
+            assert!(std::fs::metadata(dst).unwrap().blocks() * 512 < size);
+        }
+
+        let dst = dir.path().join("full.img");
+        let dst = dst.to_str().unwrap();
+        assert_eq!(copy_disk(src, dst, false, false, &NullMeter), Ok(size));
+        assert_eq!(std::fs::read(dst).unwrap(), expected);
+
+        // Cancelling removes the partial output
+        let dst = dir.path().join("cancelled.img");
+        let dst = dst.to_str().unwrap();
+        let job = AsyncJob::new("Cloning", true);
+        job.cancel();
+        let err = copy_disk(src, dst, true, false, &job).unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "Error cloning diskimage {} to {}: Operation cancelled",
+                src, dst
+            )
+        );
+        assert!(!Path::new(dst).exists());
+
+        let err = copy_disk("/nonexistent.img", dst, true, false, &NullMeter).unwrap_err();
+        assert!(err.starts_with("Error cloning diskimage /nonexistent.img"));
+        assert!(!Path::new(dst).exists());
+    }
+}
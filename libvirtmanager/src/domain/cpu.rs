@@ -0,0 +1,372 @@
+// Guest CPU model, features, topology and NUMA cells (port of virtinst/domain/cpu.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+use std::fmt;
+
+use super::{parse_cpuset, remove_if_empty};
+use crate::xmlapi::Element;
+
+/// How the guest CPU is picked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuMode {
+    /// No `<cpu>` model, whatever the hypervisor picks
+    HypervisorDefault,
+    HostPassthrough,
+    HostModel,
+    Maximum,
+    /// A named model from the domain capabilities
+    Custom,
+}
+
+impl CpuMode {
+    pub const ALL: [CpuMode; 5] = [
+        CpuMode::HypervisorDefault,
+        CpuMode::HostPassthrough,
+        CpuMode::HostModel,
+        CpuMode::Maximum,
+        CpuMode::Custom,
+    ];
+
+    /// The `<cpu mode=...>` value
+    pub fn xml_name(self) -> Option<&'static str> {
+        match self {
+            CpuMode::HypervisorDefault => None,
+            CpuMode::HostPassthrough => Some("host-passthrough"),
+            CpuMode::HostModel => Some("host-model"),
+            CpuMode::Maximum => Some("maximum"),
+            CpuMode::Custom => Some("custom"),
+        }
+    }
+}
+
+impl fmt::Display for CpuMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CpuMode::HypervisorDefault => "Hypervisor default",
+            CpuMode::HostPassthrough => "Copy host CPU (host-passthrough)",
+            CpuMode::HostModel => "Host CPU model (host-model)",
+            CpuMode::Maximum => "All supported features (maximum)",
+            CpuMode::Custom => "Custom model",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuFeature {
+    pub name: String,
+    pub policy: String,
+}
+
+/// `<cpu><topology>`. Unset values count as 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuTopology {
+    pub sockets: Option<u32>,
+    pub dies: Option<u32>,
+    pub clusters: Option<u32>,
+    pub cores: Option<u32>,
+    pub threads: Option<u32>,
+}
+
+impl CpuTopology {
+    fn fields(&self) -> [(&'static str, Option<u32>); 5] {
+        [
+            ("sockets", self.sockets),
+            ("dies", self.dies),
+            ("clusters", self.clusters),
+            ("cores", self.cores),
+            ("threads", self.threads),
+        ]
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.fields().iter().any(|(_, v)| v.is_some())
+    }
+
+    /// The CPU count the topology describes
+    pub fn total_vcpus(&self) -> u32 {
+        self.fields().iter().map(|(_, v)| v.unwrap_or(1)).product()
+    }
+
+    /// Fill missing values so the topology covers `vcpus`, preferring
+    /// cores over sockets since some OSes limit the socket count
+    pub fn set_defaults_from_vcpus(&mut self, vcpus: u32) -> Result<(), String> {
+        if self.cores.is_none() {
+            self.cores = Some((vcpus / self.total_vcpus()).max(1));
+        }
+        if self.sockets.is_none() {
+            self.sockets = Some((vcpus / self.total_vcpus()).max(1));
+        }
+        if self.dies.is_none() {
+            self.dies = Some(1);
+        }
+        if self.threads.is_none() {
+            self.threads = Some((vcpus / self.total_vcpus()).max(1));
+        }
+        self.validate(vcpus)
+    }
+
+    pub fn validate(&self, vcpus: u32) -> Result<(), String> {
+        let total = self.total_vcpus();
+        if total == vcpus {
+            return Ok(());
+        }
+        let v = |v: Option<u32>| v.unwrap_or(1);
+        Err(format!(
+            "Total CPUs implied by topology (sockets={} * dies={} * clusters={} * cores={} * \
+             threads={} == {}) does not match vCPU count {}",
+            v(self.sockets),
+            v(self.dies),
+            v(self.clusters),
+            v(self.cores),
+            v(self.threads),
+            total,
+            vcpus
+        ))
+    }
+}
+
+/// A `<cpu><numa><cell>`, with memory in KiB
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumaCell {
+    pub id: u32,
+    pub cpus: String,
+    pub memory: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainCpu {
+    pub mode: CpuMode,
+    /// Model name, used with CpuMode::Custom
+    pub model: Option<String>,
+    pub features: Vec<CpuFeature>,
+    pub topology: CpuTopology,
+    pub cells: Vec<NumaCell>,
+}
+
+impl DomainCpu {
+    pub fn from_xml(domain: &Element) -> Self {
+        let model = domain
+            .get("./cpu/model")
+            .map(|m| m.trim().to_string())
+            .filter(|m| !m.is_empty());
+        let mode = match domain.get("./cpu/@mode").as_deref() {
+            Some("host-passthrough") => CpuMode::HostPassthrough,
+            Some("host-model") => CpuMode::HostModel,
+            Some("maximum") => CpuMode::Maximum,
+            Some("custom") => CpuMode::Custom,
+            _ if model.is_some() => CpuMode::Custom,
+            _ => CpuMode::HypervisorDefault,
+        };
+        let topo = |name: &str| {
+            domain
+                .get(&format!("./cpu/topology/@{}", name))
+                .and_then(|v| v.parse().ok())
+        };
+        Self {
+            mode,
+            model: model.filter(|_| mode == CpuMode::Custom),
+            features: domain
+                .find_all("./cpu/feature")
+                .iter()
+                .filter_map(|f| {
+                    Some(CpuFeature {
+                        name: f.attr("name")?.to_string(),
+                        policy: f.attr("policy").unwrap_or("require").to_string(),
+                    })
+                })
+                .collect(),
+            topology: CpuTopology {
+                sockets: topo("sockets"),
+                dies: topo("dies"),
+                clusters: topo("clusters"),
+                cores: topo("cores"),
+                threads: topo("threads"),
+            },
+            cells: domain
+                .find_all("./cpu/numa/cell")
+                .iter()
+                .enumerate()
+                .map(|(i, c)| NumaCell {
+                    id: c
+                        .attr("id")
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or(i as u32),
+                    cpus: c.attr("cpus").unwrap_or_default().to_string(),
+                    memory: super::memory::to_kib(c.attr("memory").unwrap_or("0"), c.attr("unit"))
+                        .unwrap_or(0),
+                })
+                .collect(),
+        }
+    }
+
+    /// Check the settings against the domain's maximum vCPU count
+    pub fn validate(&self, max_vcpus: u32) -> Result<(), String> {
+        if self.mode == CpuMode::Custom && self.model.is_none() {
+            return Err("A CPU model is required for a custom CPU".to_string());
+        }
+        if self.topology.is_set() {
+            self.topology.validate(max_vcpus)?;
+        }
+        let mut used: Vec<u32> = vec![];
+        for cell in &self.cells {
+            let cpus =
+                parse_cpuset(&cell.cpus).map_err(|e| format!("NUMA cell {}: {}", cell.id, e))?;
+            if let Some(cpu) = cpus.iter().find(|c| **c >= max_vcpus) {
+                return Err(format!(
+                    "NUMA cell {} uses CPU {}, but the guest only has {} vCPUs",
+                    cell.id, cpu, max_vcpus
+                ));
+            }
+            if let Some(cpu) = cpus.iter().find(|c| used.contains(c)) {
+                return Err(format!("CPU {} is in more than one NUMA cell", cpu));
+            }
+            if cell.memory == 0 {
+                return Err(format!("NUMA cell {} needs some memory", cell.id));
+            }
+            used.extend(cpus);
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, domain: &mut Element) {
+        let old = Self::from_xml(domain);
+        if old.mode != self.mode {
+            for xpath in ["./cpu/@match", "./cpu/@check", "./cpu/@migratable"] {
+                domain.set(xpath, None);
+            }
+            if self.mode != CpuMode::Custom {
+                domain.force_remove("./cpu/model");
+                domain.force_remove("./cpu/vendor");
+            }
+            domain.set("./cpu/@mode", self.mode.xml_name());
+        }
+        if self.mode == CpuMode::Custom && old.model != self.model {
+            if domain.get("./cpu/@match").is_none() {
+                domain.set("./cpu/@match", Some("exact"));
+            }
+            domain.force_remove("./cpu/model");
+            domain.set("./cpu/model", self.model.as_deref());
+            domain.set("./cpu/model/@fallback", Some("allow"));
+        }
+
+        if old.features != self.features {
+            while domain.find("./cpu/feature").is_some() {
+                domain.force_remove("./cpu/feature");
+            }
+            for (i, f) in self.features.iter().enumerate() {
+                let xpath = format!("./cpu/feature[{}]", i + 1);
+                domain.set(&format!("{}/@policy", xpath), Some(&f.policy));
+                domain.set(&format!("{}/@name", xpath), Some(&f.name));
+            }
+        }
+
+        if old.topology != self.topology {
+            for (name, val) in self.topology.fields() {
+                let val = val.map(|v| v.to_string());
+                domain.set(&format!("./cpu/topology/@{}", name), val.as_deref());
+            }
+        }
+
+        if old.cells != self.cells {
+            // Update cells in place so distances and caches survive
+            for (i, cell) in self.cells.iter().enumerate() {
+                let xpath = format!("./cpu/numa/cell[{}]", i + 1);
+                domain.set(&format!("{}/@id", xpath), Some(&cell.id.to_string()));
+                domain.set(&format!("{}/@cpus", xpath), Some(&cell.cpus));
+                domain.set(
+                    &format!("{}/@memory", xpath),
+                    Some(&cell.memory.to_string()),
+                );
+                domain.set(&format!("{}/@unit", xpath), Some("KiB"));
+            }
+            while domain.count("./cpu/numa/cell") > self.cells.len() {
+                domain.force_remove(&format!("./cpu/numa/cell[{}]", self.cells.len() + 1));
+            }
+            remove_if_empty(domain, "./cpu/numa");
+        }
+        remove_if_empty(domain, "./cpu");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topology_defaults() {
+        let mut topo = CpuTopology::default();
+        topo.set_defaults_from_vcpus(8).unwrap();
+        assert_eq!(
+            (topo.sockets, topo.dies, topo.cores, topo.threads),
+            (Some(1), Some(1), Some(8), Some(1))
+        );
+        let mut topo = CpuTopology {
+            sockets: Some(2),
+            ..Default::default()
+        };
+        topo.set_defaults_from_vcpus(8).unwrap();
+        assert_eq!(topo.cores, Some(4));
+        let topo = CpuTopology {
+            sockets: Some(3),
+            cores: Some(2),
+            ..Default::default()
+        };
+        let err = topo.validate(8).unwrap_err();
+        assert!(err.contains("== 6) does not match vCPU count 8"), "{}", err);
+        let topo = CpuTopology {
+            clusters: Some(2),
+            ..topo
+        };
+        let err = topo.validate(8).unwrap_err();
+        assert!(
+            err.contains("dies=1 * clusters=2 * cores=2 * threads=1 == 12)"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_cpu_roundtrip() {
+        let mut domain = Element::parse(
+            "<domain><cpu match='exact'><model>core2duo</model>\
+             <feature policy='require' name='pbe'/>\
+             <topology sockets='4' cores='1' threads='1'/>\
+             <numa><cell id='0' cpus='0-1' memory='1' unit='MiB'>\
+             <distances><sibling id='1' value='21'/></distances></cell>\
+             <cell id='1' cpus='2-3' memory='1024' unit='KiB'/></numa></cpu></domain>",
+        )
+        .unwrap();
+        let mut cpu = DomainCpu::from_xml(&domain);
+        assert_eq!(cpu.mode, CpuMode::Custom);
+        assert_eq!(cpu.model.as_deref(), Some("core2duo"));
+        assert_eq!(cpu.cells[0].memory, 1024);
+        cpu.validate(4).unwrap();
+        assert!(cpu.validate(3).is_err());
+
+        cpu.cells[1].cpus = "1-3".to_string();
+        assert!(cpu.validate(4).unwrap_err().contains("more than one"));
+        cpu.cells.pop();
+        cpu.cells[0].cpus = "0-3".to_string();
+        cpu.features.clear();
+        cpu.apply(&mut domain);
+        let xml = domain.to_xml();
+        assert!(!xml.contains("feature"));
+        assert!(xml.contains("<cell id=\"0\" cpus=\"0-3\" memory=\"1024\" unit=\"KiB\">"));
+        assert!(xml.contains("sibling"));
+
+        cpu.mode = CpuMode::HostPassthrough;
+        cpu.model = None;
+        cpu.topology = CpuTopology::default();
+        cpu.cells.clear();
+        cpu.apply(&mut domain);
+        assert_eq!(
+            domain.find("./cpu").unwrap().to_xml(),
+            "<cpu mode=\"host-passthrough\"/>"
+        );
+        cpu.mode = CpuMode::HypervisorDefault;
+        cpu.apply(&mut domain);
+        assert!(domain.find("./cpu").is_none());
+    }
+}
@@ -0,0 +1,112 @@
+// Guest memory sizes (port of the memory properties of virtinst/guest.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+use crate::xmlapi::Element;
+
+/// Convert a libvirt memory value with its unit to KiB
+pub fn to_kib(value: &str, unit: Option<&str>) -> Result<u64, String> {
+    let val: u64 = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid memory value '{}'", value))?;
+    let shift = match unit.unwrap_or("KiB") {
+        "b" | "bytes" => return Ok(val / 1024),
+        "KB" => return Ok(val * 1000 / 1024),
+        "MB" => return Ok(val * 1000 * 1000 / 1024),
+        "GB" => return Ok(val * 1000 * 1000 * 1000 / 1024),
+        "k" | "KiB" => 0,
+        "M" | "MiB" => 10,
+        "G" | "GiB" => 20,
+        "T" | "TiB" => 30,
+        u => return Err(format!("Unknown memory unit '{}'", u)),
+    };
+    Ok(val << shift)
+}
+
+/// `<memory>`, `<currentMemory>` and `<maxMemory>`, all in KiB
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DomainMemory {
+    pub memory: u64,
+    pub current: u64,
+    /// Hotplug limit and DIMM slots
+    pub max_memory: Option<u64>,
+    pub slots: Option<u32>,
+}
+
+impl DomainMemory {
+    pub fn from_xml(domain: &Element) -> Self {
+        let read = |xpath: &str| {
+            let val = domain.get(xpath)?;
+            to_kib(&val, domain.get(&format!("{}/@unit", xpath)).as_deref()).ok()
+        };
+        let memory = read("./memory").unwrap_or(0);
+        Self {
+            memory,
+            current: read("./currentMemory").unwrap_or(memory),
+            max_memory: read("./maxMemory"),
+            slots: domain
+                .get("./maxMemory/@slots")
+                .and_then(|v| v.parse().ok()),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.current > self.memory {
+            return Err("Current allocation cannot exceed the maximum allocation.".to_string());
+        }
+        if self.max_memory.is_some_and(|m| m < self.memory) {
+            return Err("Memory hotplug limit cannot be below the maximum allocation.".to_string());
+        }
+        Ok(())
+    }
+
+    /// Write the values that changed, keeping the units of the others
+    pub fn apply(&self, domain: &mut Element) {
+        let old = Self::from_xml(domain);
+        let mut write = |xpath: &str, old: Option<u64>, new: Option<u64>| {
+            if old == new {
+                return;
+            }
+            let val = new.map(|v| v.to_string());
+            domain.set(xpath, val.as_deref());
+            domain.set(&format!("{}/@unit", xpath), new.map(|_| "KiB"));
+        };
+        write("./memory", Some(old.memory), Some(self.memory));
+        write("./currentMemory", Some(old.current), Some(self.current));
+        write("./maxMemory", old.max_memory, self.max_memory);
+        if old.slots != self.slots && domain.find("./maxMemory").is_some() {
+            let slots = self.slots.map(|s| s.to_string());
+            domain.set("./maxMemory/@slots", slots.as_deref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_units() {
+        assert_eq!(to_kib("2", Some("GiB")).unwrap(), 2 * 1024 * 1024);
+        assert_eq!(to_kib("1048576", Some("bytes")).unwrap(), 1024);
+        assert!(to_kib("1", Some("parsecs")).is_err());
+
+        let mut domain = Element::parse(
+            "<domain><memory unit='GiB'>2</memory>\
+             <currentMemory unit='MiB'>1024</currentMemory></domain>",
+        )
+        .unwrap();
+        let mut mem = DomainMemory::from_xml(&domain);
+        assert_eq!((mem.memory, mem.current), (2097152, 1048576));
+        mem.current = mem.memory + 1;
+        assert!(mem.validate().is_err());
+        mem.current = 524288;
+        mem.validate().unwrap();
+        mem.apply(&mut domain);
+        assert_eq!(domain.get("./memory/@unit").as_deref(), Some("GiB"));
+        assert_eq!(domain.get("./currentMemory").as_deref(), Some("524288"));
+        assert_eq!(domain.get("./currentMemory/@unit").as_deref(), Some("KiB"));
+    }
+}
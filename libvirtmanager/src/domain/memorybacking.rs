@@ -0,0 +1,118 @@
+// Guest memory backing (port of virtinst/domain/memorybacking.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+use super::remove_if_empty;
+use crate::xmlapi::Element;
+
+/// `<memoryBacking>`. Per node `<hugepages><page>` entries are kept as
+/// they are while hugepages stays enabled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DomainMemoryBacking {
+    pub hugepages: bool,
+    pub nosharepages: bool,
+    pub locked: bool,
+    pub discard: bool,
+    pub access_mode: Option<String>,
+    pub source_type: Option<String>,
+    pub allocation_mode: Option<String>,
+}
+
+impl DomainMemoryBacking {
+    pub fn from_xml(domain: &Element) -> Self {
+        Self {
+            hugepages: domain.get_bool("./memoryBacking/hugepages"),
+            nosharepages: domain.get_bool("./memoryBacking/nosharepages"),
+            locked: domain.get_bool("./memoryBacking/locked"),
+            discard: domain.get_bool("./memoryBacking/discard"),
+            access_mode: domain.get("./memoryBacking/access/@mode"),
+            source_type: domain.get("./memoryBacking/source/@type"),
+            allocation_mode: domain.get("./memoryBacking/allocation/@mode"),
+        }
+    }
+
+    /// Whether guest memory can be shared with the host, as virtiofs needs
+    pub fn is_shared(&self) -> bool {
+        self.access_mode.as_deref() == Some("shared")
+            && matches!(self.source_type.as_deref(), Some("memfd" | "file"))
+    }
+
+    /// Share guest memory through memfd, or a file when the hypervisor
+    /// lacks memfd support
+    pub fn set_shared(&mut self, shared: bool, memfd: bool) {
+        if shared {
+            self.source_type = Some(if memfd { "memfd" } else { "file" }.to_string());
+            self.access_mode = Some("shared".to_string());
+        } else {
+            self.source_type = None;
+            self.access_mode = None;
+        }
+    }
+
+    pub fn apply(&self, domain: &mut Element) {
+        for (name, val) in [
+            ("hugepages", self.hugepages),
+            ("nosharepages", self.nosharepages),
+            ("locked", self.locked),
+            ("discard", self.discard),
+        ] {
+            let xpath = format!("./memoryBacking/{}", name);
+            if domain.get_bool(&xpath) != val {
+                domain.set_bool(&xpath, val);
+            }
+        }
+        domain.set("./memoryBacking/access/@mode", self.access_mode.as_deref());
+        domain.set("./memoryBacking/source/@type", self.source_type.as_deref());
+        domain.set(
+            "./memoryBacking/allocation/@mode",
+            self.allocation_mode.as_deref(),
+        );
+        remove_if_empty(domain, "./memoryBacking");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::xmlparse_domain;
+    use super::*;
+
+    #[test]
+    fn test_memorybacking_roundtrip() {
+        let out = xmlparse_domain("change-guest-out.xml");
+        let want = DomainMemoryBacking::from_xml(&out);
+        assert!(want.hugepages && want.nosharepages && want.locked && !want.discard);
+        assert!(!want.is_shared());
+
+        let mut domain = xmlparse_domain("change-guest-in.xml");
+        assert_eq!(
+            DomainMemoryBacking::from_xml(&domain),
+            DomainMemoryBacking::default()
+        );
+        want.apply(&mut domain);
+        assert_eq!(DomainMemoryBacking::from_xml(&domain), want);
+
+        // Page sizes stay while hugepages does
+        let mut domain = out.clone();
+        let mut backing = want.clone();
+        backing.set_shared(true, true);
+        assert!(backing.is_shared());
+        backing.apply(&mut domain);
+        assert_eq!(
+            domain
+                .get("./memoryBacking/hugepages/page/@size")
+                .as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            domain.get("./memoryBacking/source/@type").as_deref(),
+            Some("memfd")
+        );
+
+        backing.set_shared(true, false);
+        assert_eq!(backing.source_type.as_deref(), Some("file"));
+        let backing = DomainMemoryBacking::default();
+        backing.apply(&mut domain);
+        assert!(domain.find("./memoryBacking").is_none());
+    }
+}
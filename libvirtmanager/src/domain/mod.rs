@@ -0,0 +1,81 @@
+// Domain-wide settings (port of virtinst/domain/)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Models for the `<domain>` children the details pages edit. Each one
+//! reads itself from the domain XML with `from_xml` and writes back with
+//! `apply`, leaving any settings it doesn't model untouched.
+
+pub mod cpu;
+pub mod memory;
+pub mod memorybacking;
+pub mod numatune;
+pub mod os;
+pub mod vcpus;
+
+pub use cpu::{CpuFeature, CpuMode, CpuTopology, DomainCpu, NumaCell};
+pub use memory::DomainMemory;
+pub use memorybacking::DomainMemoryBacking;
+pub use numatune::DomainNumatune;
+pub use os::{DomainOs, Firmware};
+pub use vcpus::DomainVcpus;
+
+use crate::xmlapi::Element;
+
+/// Expand a libvirt cpuset like `0-3,^2,8` into sorted CPU numbers
+pub fn parse_cpuset(cpuset: &str) -> Result<Vec<u32>, String> {
+    let invalid = || format!("Invalid cpuset '{}'", cpuset);
+    let mut cpus = std::collections::BTreeSet::new();
+    let mut excluded = vec![];
+    for part in cpuset.split(',').map(str::trim) {
+        if let Some(cpu) = part.strip_prefix('^') {
+            excluded.push(cpu.parse::<u32>().map_err(|_| invalid())?);
+            continue;
+        }
+        let (start, end) = part.split_once('-').unwrap_or((part, part));
+        let start: u32 = start.trim().parse().map_err(|_| invalid())?;
+        let end: u32 = end.trim().parse().map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+        cpus.extend(start..=end);
+    }
+    for cpu in excluded {
+        cpus.remove(&cpu);
+    }
+    Ok(cpus.into_iter().collect())
+}
+
+/// Drop the element at xpath if it has no attributes or child elements
+fn remove_if_empty(domain: &mut Element, xpath: &str) {
+    if domain.find(xpath).is_some_and(|e| {
+        e.attrs.is_empty() && e.child_elements().next().is_none() && e.text().trim().is_empty()
+    }) {
+        domain.force_remove(xpath);
+    }
+}
+
+/// A domain from the tests/data/xmlparse fixtures
+#[cfg(test)]
+fn xmlparse_domain(name: &str) -> Element {
+    let path = format!(
+        "{}/../tests/data/xmlparse/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    Element::parse(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpuset() {
+        assert_eq!(parse_cpuset("0-3,^2,8").unwrap(), [0, 1, 3, 8]);
+        assert_eq!(parse_cpuset("5").unwrap(), [5]);
+        assert!(parse_cpuset("3-1").is_err());
+        assert!(parse_cpuset("a").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+// Guest NUMA memory placement (port of virtinst/domain/numatune.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+use super::{parse_cpuset, remove_if_empty};
+use crate::xmlapi::Element;
+
+/// Values for `<numatune><memory mode=...>`
+pub const NUMATUNE_MODES: &[&str] = &["strict", "preferred", "interleave", "restrictive"];
+
+/// `<numatune><memory>`. Per cell `<memnode>` entries are left alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DomainNumatune {
+    pub memory_mode: Option<String>,
+    pub memory_nodeset: Option<String>,
+    pub memory_placement: Option<String>,
+}
+
+impl DomainNumatune {
+    pub fn from_xml(domain: &Element) -> Self {
+        Self {
+            memory_mode: domain.get("./numatune/memory/@mode"),
+            memory_nodeset: domain.get("./numatune/memory/@nodeset"),
+            memory_placement: domain.get("./numatune/memory/@placement"),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(nodeset) = &self.memory_nodeset {
+            parse_cpuset(nodeset).map_err(|_| format!("Invalid NUMA nodeset '{}'", nodeset))?;
+            if self.memory_placement.as_deref() == Some("auto") {
+                return Err("A NUMA nodeset can't be combined with automatic placement".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, domain: &mut Element) {
+        domain.set("./numatune/memory/@mode", self.memory_mode.as_deref());
+        domain.set("./numatune/memory/@nodeset", self.memory_nodeset.as_deref());
+        domain.set(
+            "./numatune/memory/@placement",
+            self.memory_placement.as_deref(),
+        );
+        remove_if_empty(domain, "./numatune");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::xmlparse_domain;
+    use super::*;
+
+    #[test]
+    fn test_numatune_roundtrip() {
+        let mut domain = xmlparse_domain("change-guest-in.xml");
+        let tune = DomainNumatune::from_xml(&domain);
+        assert_eq!(tune.memory_mode.as_deref(), Some("interleave"));
+        assert_eq!(tune.memory_nodeset.as_deref(), Some("1-5,^3,7"));
+        tune.validate().unwrap();
+
+        let want = DomainNumatune::from_xml(&xmlparse_domain("change-guest-out.xml"));
+        assert_eq!(want.memory_mode, None);
+        assert_eq!(want.memory_nodeset.as_deref(), Some("2,4,6"));
+        want.apply(&mut domain);
+        assert_eq!(DomainNumatune::from_xml(&domain), want);
+        assert_eq!(domain.get("./numatune/memory/@mode"), None);
+
+        DomainNumatune::default().apply(&mut domain);
+        assert!(domain.find("./numatune").is_none());
+    }
+
+    #[test]
+    fn test_numatune_validate() {
+        let tune = DomainNumatune {
+            memory_nodeset: Some("1-a".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(tune.validate().unwrap_err(), "Invalid NUMA nodeset '1-a'");
+        let tune = DomainNumatune {
+            memory_nodeset: Some("0".to_string()),
+            memory_placement: Some("auto".to_string()),
+            ..Default::default()
+        };
+        assert!(tune.validate().unwrap_err().contains("automatic placement"));
+        let tune = DomainNumatune {
+            memory_mode: Some("strict".to_string()),
+            memory_placement: Some("auto".to_string()),
+            ..Default::default()
+        };
+        tune.validate().unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+// Guest firmware and boot settings (port of virtinst/domain/os.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+use super::remove_if_empty;
+use crate::xmlapi::Element;
+
+/// Whether the domain boots UEFI, either picked by libvirt or as a pflash
+/// loader image
+pub fn is_uefi(domain: &Element) -> bool {
+    domain.get("./os/@firmware").as_deref() == Some("efi")
+        || (domain.find("./os/loader").is_some()
+            && domain.get("./os/loader/@type").as_deref() == Some("pflash"))
+}
+
+/// The firmware a guest boots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Firmware {
+    /// No loader, the machine type's default (SeaBIOS on x86)
+    Bios,
+    /// `<os firmware='efi'>`, libvirt picks a matching image
+    Efi { secure_boot: bool },
+    /// An explicit `<loader>` image
+    Loader {
+        path: String,
+        nvram_template: Option<String>,
+    },
+}
+
+impl Firmware {
+    pub fn from_xml(domain: &Element) -> Self {
+        if domain.get("./os/@firmware").as_deref() == Some("efi") {
+            return Firmware::Efi {
+                secure_boot: domain
+                    .get("./os/firmware/feature[@name='secure-boot']/@enabled")
+                    .as_deref()
+                    == Some("yes"),
+            };
+        }
+        match domain.get("./os/loader") {
+            Some(path) => Firmware::Loader {
+                path: path.trim().to_string(),
+                nvram_template: domain.get("./os/nvram/@template"),
+            },
+            None => Firmware::Bios,
+        }
+    }
+
+    /// Write the firmware settings if they changed. `x86` enables SMM for
+    /// secure boot, which x86 firmware requires.
+    pub fn apply(&self, domain: &mut Element, x86: bool) {
+        if Self::from_xml(domain) == *self {
+            return;
+        }
+        domain.force_remove("./os/firmware");
+        match self {
+            Firmware::Bios => {
+                domain.set("./os/@firmware", None);
+                domain.force_remove("./os/loader");
+                domain.force_remove("./os/nvram");
+            }
+            Firmware::Efi { secure_boot } => {
+                // Keep any nvram path, libvirt drops it if it doesn't fit
+                domain.force_remove("./os/loader");
+                domain.set("./os/@firmware", Some("efi"));
+                let enabled = if *secure_boot { "yes" } else { "no" };
+                for (i, name) in ["secure-boot", "enrolled-keys"].iter().enumerate() {
+                    let xpath = format!("./os/firmware/feature[{}]", i + 1);
+                    domain.set(&format!("{}/@enabled", xpath), Some(enabled));
+                    domain.set(&format!("{}/@name", xpath), Some(name));
+                }
+                if *secure_boot && x86 {
+                    domain.set("./features/smm/@state", Some("on"));
+                }
+            }
+            Firmware::Loader {
+                path,
+                nvram_template,
+            } => {
+                domain.set("./os/@firmware", None);
+                domain.set("./os/loader", Some(path));
+                domain.set("./os/loader/@readonly", Some("yes"));
+                domain.set("./os/loader/@type", Some("pflash"));
+                // secboot builds need SMM, and enforce secure boot with it
+                let secure = x86 && path.contains("secboot");
+                domain.set("./os/loader/@secure", secure.then_some("yes"));
+                if secure {
+                    domain.set("./features/smm/@state", Some("on"));
+                }
+                domain.set("./os/nvram/@template", nvram_template.as_deref());
+                remove_if_empty(domain, "./os/nvram");
+            }
+        }
+    }
+}
+
+/// `<os>` boot settings other than firmware and boot order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DomainOs {
+    pub bootmenu: bool,
+    pub kernel: Option<String>,
+    pub initrd: Option<String>,
+    pub dtb: Option<String>,
+    pub kernel_args: Option<String>,
+    /// Container init binary and its arguments
+    pub init: Option<String>,
+    pub init_args: Vec<String>,
+}
+
+impl DomainOs {
+    pub fn from_xml(domain: &Element) -> Self {
+        let get = |xpath: &str| domain.get(xpath).filter(|v| !v.is_empty());
+        Self {
+            bootmenu: domain.get("./os/bootmenu/@enable").as_deref() == Some("yes"),
+            kernel: get("./os/kernel"),
+            initrd: get("./os/initrd"),
+            dtb: get("./os/dtb"),
+            kernel_args: get("./os/cmdline"),
+            init: get("./os/init"),
+            init_args: domain
+                .find_all("./os/initarg")
+                .iter()
+                .map(|a| a.text())
+                .collect(),
+        }
+    }
+
+    pub fn validate(&self, container: bool) -> Result<(), String> {
+        if self.kernel.is_none() {
+            if self.initrd.is_some() {
+                return Err("Cannot set initrd without specifying a kernel path".to_string());
+            }
+            if self.kernel_args.is_some() {
+                return Err(
+                    "Cannot set kernel arguments without specifying a kernel path".to_string(),
+                );
+            }
+        }
+        if container && self.init.is_none() {
+            return Err("An init path must be specified".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, domain: &mut Element) {
+        domain.set(
+            "./os/bootmenu/@enable",
+            Some("yes").filter(|_| self.bootmenu),
+        );
+        domain.set("./os/kernel", self.kernel.as_deref());
+        domain.set("./os/initrd", self.initrd.as_deref());
+        domain.set("./os/dtb", self.dtb.as_deref());
+        domain.set("./os/cmdline", self.kernel_args.as_deref());
+        domain.set("./os/init", self.init.as_deref());
+        if Self::from_xml(domain).init_args != self.init_args {
+            while domain.find("./os/initarg").is_some() {
+                domain.force_remove("./os/initarg");
+            }
+            for (i, arg) in self.init_args.iter().enumerate() {
+                domain.set(&format!("./os/initarg[{}]", i + 1), Some(arg));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_firmware_switch() {
+        let mut domain = Element::parse(
+            "<domain><os><type arch='x86_64'>hvm</type><boot dev='hd'/></os></domain>",
+        )
+        .unwrap();
+        assert_eq!(Firmware::from_xml(&domain), Firmware::Bios);
+        assert!(!is_uefi(&domain));
+
+        let fw = Firmware::Efi { secure_boot: true };
+        fw.apply(&mut domain, true);
+        assert!(is_uefi(&domain));
+        assert_eq!(Firmware::from_xml(&domain), fw);
+        assert_eq!(domain.get("./features/smm/@state").as_deref(), Some("on"));
+
+        let fw = Firmware::Loader {
+            path: "/usr/share/OVMF/OVMF_CODE.secboot.fd".to_string(),
+            nvram_template: Some("/usr/share/OVMF/OVMF_VARS.secboot.fd".to_string()),
+        };
+        fw.apply(&mut domain, true);
+        assert_eq!(Firmware::from_xml(&domain), fw);
+        assert!(domain.find("./os/firmware").is_none());
+        assert_eq!(domain.get("./os/loader/@secure").as_deref(), Some("yes"));
+
+        Firmware::Bios.apply(&mut domain, true);
+        assert!(!is_uefi(&domain));
+        assert!(domain.find("./os/nvram").is_none());
+        assert_eq!(domain.get("./os/boot/@dev").as_deref(), Some("hd"));
+    }
+
+    #[test]
+    fn test_boot_settings() {
+        let mut domain = Element::parse("<domain><os><type>exe</type></os></domain>").unwrap();
+        let mut os = DomainOs::from_xml(&domain);
+        assert_eq!(
+            os.validate(true).unwrap_err(),
+            "An init path must be specified"
+        );
+        os.init = Some("/sbin/init".to_string());
+        os.init_args = vec!["--foo".to_string(), "bar".to_string()];
+        os.validate(true).unwrap();
+        os.apply(&mut domain);
+        assert_eq!(DomainOs::from_xml(&domain), os);
+
+        os.initrd = Some("/boot/initrd".to_string());
+        assert!(os.validate(false).is_err());
+    }
+}
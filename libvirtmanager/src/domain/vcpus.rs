@@ -0,0 +1,104 @@
+// Guest vCPU counts (port of virtinst/domain/vcpus.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+use crate::xmlapi::Element;
+
+/// `<vcpu current=...>max</vcpu>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DomainVcpus {
+    pub max: u32,
+    pub current: u32,
+}
+
+impl DomainVcpus {
+    pub fn from_xml(domain: &Element) -> Self {
+        let max = domain
+            .get("./vcpu")
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(1);
+        let current = domain
+            .get("./vcpu/@current")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(max);
+        Self { max, current }
+    }
+
+    /// `limit` is the hypervisor's maximum from the domain capabilities
+    pub fn validate(&self, limit: Option<u32>) -> Result<(), String> {
+        if self.max == 0 || self.current == 0 {
+            return Err("The guest needs at least one vCPU".to_string());
+        }
+        if self.current > self.max {
+            return Err(format!(
+                "Current vCPU count {} exceeds the maximum of {}",
+                self.current, self.max
+            ));
+        }
+        if let Some(limit) = limit.filter(|l| self.max > *l) {
+            return Err(format!(
+                "{} vCPUs is more than the hypervisor supports ({})",
+                self.max, limit
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, domain: &mut Element) {
+        let old = Self::from_xml(domain);
+        if old.max != self.max {
+            domain.set("./vcpu", Some(&self.max.to_string()));
+            // Per vCPU hotplug state only makes sense for the old count
+            domain.force_remove("./vcpus");
+        }
+        let current = Some(self.current.to_string()).filter(|_| self.current != self.max);
+        domain.set("./vcpu/@current", current.as_deref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::xmlparse_domain;
+    use super::*;
+
+    #[test]
+    fn test_vcpus_roundtrip() {
+        let mut domain = xmlparse_domain("change-guest-in.xml");
+        let vcpus = DomainVcpus::from_xml(&domain);
+        assert_eq!(vcpus, DomainVcpus { max: 5, current: 5 });
+        vcpus.validate(None).unwrap();
+
+        let want = DomainVcpus::from_xml(&xmlparse_domain("change-guest-out.xml"));
+        assert_eq!(
+            want,
+            DomainVcpus {
+                max: 12,
+                current: 10
+            }
+        );
+        want.validate(Some(16)).unwrap();
+        want.apply(&mut domain);
+        assert_eq!(DomainVcpus::from_xml(&domain), want);
+        assert_eq!(domain.get("./vcpu/@cpuset").as_deref(), Some("1-3"));
+
+        // Current equal to the maximum is left implicit
+        DomainVcpus { max: 4, current: 4 }.apply(&mut domain);
+        assert_eq!(domain.get("./vcpu").as_deref(), Some("4"));
+        assert_eq!(domain.get("./vcpu/@current"), None);
+    }
+
+    #[test]
+    fn test_vcpus_validate() {
+        let err = |max, current, limit| DomainVcpus { max, current }.validate(limit).unwrap_err();
+        assert_eq!(err(0, 0, None), "The guest needs at least one vCPU");
+        assert_eq!(
+            err(2, 4, None),
+            "Current vCPU count 4 exceeds the maximum of 2"
+        );
+        assert_eq!(
+            err(12, 10, Some(8)),
+            "12 vCPUs is more than the hypervisor supports (8)"
+        );
+    }
+}
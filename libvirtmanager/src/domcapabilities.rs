@@ -0,0 +1,286 @@
+// Domain capabilities (port of virtinst/domcapabilities.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Wraps the `<domainCapabilities>` XML libvirt reports for one emulator,
+//! arch, machine and virt type combination, and answers the questions the
+//! details pages ask of it: supported CPU modes and models, the vCPU
+//! limit, firmware and memory backing options.
+
+use crate::connection::Connection;
+use crate::guest::Guest;
+use crate::xmlapi::Element;
+
+/// UEFI image names per arch, as `*` wildcard patterns. Only used to do
+/// things automatically and to label paths, never to validate them.
+const UEFI_ARCH_PATTERNS: &[(&str, &[&str])] = &[
+    ("i686", &["*edk2-i386-*.fd", "*ovmf-ia32*"]),
+    (
+        "x86_64",
+        &[
+            "*edk2-x86_64-*.fd",
+            "*OVMF_CODE.fd",
+            "*ovmf-x64/OVMF*.fd",
+            "*ovmf-x86_64-*",
+            "*ovmf*",
+            "*OVMF*",
+        ],
+    ),
+    (
+        "aarch64",
+        &[
+            "*AAVMF_CODE.fd",
+            "*aarch64/QEMU_EFI*",
+            "*aarch64*",
+            "*edk2-aarch64-code.fd",
+        ],
+    ),
+    (
+        "armv7l",
+        &["*AAVMF32_CODE.fd", "*arm/QEMU_EFI*", "*edk2-arm-code.fd"],
+    ),
+    ("riscv64", &["*RISCV_VIRT_CODE.*", "*riscv64*"]),
+    (
+        "loongarch64",
+        &["*loongarch64/QEMU_CODE.*", "*loongarch64*"],
+    ),
+];
+
+/// Match `*` wildcards against the whole of `s`
+fn wildcard_match(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = s.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[derive(Debug, Clone)]
+pub struct DomainCapabilities {
+    pub xml: Element,
+}
+
+impl DomainCapabilities {
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        let xml = Element::parse(xml)?;
+        if xml.name != "domainCapabilities" {
+            return Err(format!(
+                "Expected a <domainCapabilities> root, found '{}'",
+                xml.name
+            ));
+        }
+        Ok(Self { xml })
+    }
+
+    pub fn lookup(
+        conn: &dyn Connection,
+        emulator: Option<&str>,
+        arch: Option<&str>,
+        machine: Option<&str>,
+        virttype: Option<&str>,
+    ) -> Result<Self, String> {
+        Self::parse(&conn.domain_capabilities(emulator, arch, machine, virttype)?)
+    }
+
+    /// Capabilities matching the emulator, arch, machine and type of a guest
+    pub fn for_guest(conn: &dyn Connection, guest: &Guest) -> Result<Self, String> {
+        Self::lookup(
+            conn,
+            guest.xml.get("./devices/emulator").as_deref(),
+            guest.arch().as_deref(),
+            guest.machine().as_deref(),
+            guest.domain_type().as_deref(),
+        )
+    }
+
+    pub fn arch(&self) -> Option<String> {
+        self.xml.get("./arch")
+    }
+
+    pub fn machine(&self) -> Option<String> {
+        self.xml.get("./machine")
+    }
+
+    pub fn domain_type(&self) -> Option<String> {
+        self.xml.get("./domain")
+    }
+
+    pub fn max_vcpus(&self) -> Option<u32> {
+        self.xml.get("./vcpu/@max").and_then(|v| v.parse().ok())
+    }
+
+    /// Values of the `<enum name=...>` below the element at xpath
+    pub fn enum_values(&self, xpath: &str, name: &str) -> Vec<String> {
+        self.xml
+            .find_all(&format!("{}/enum[@name='{}']/value", xpath, name))
+            .into_iter()
+            .map(|v| v.text())
+            .collect()
+    }
+
+    fn cpu_mode(&self, mode: &str) -> Option<&Element> {
+        self.xml
+            .find(&format!("./cpu/mode[@name='{}']", mode))
+            .filter(|m| m.attr("supported") == Some("yes"))
+    }
+
+    pub fn supports_cpu_mode(&self, mode: &str) -> bool {
+        self.cpu_mode(mode).is_some()
+    }
+
+    /// host-model that fails rather than silently falling back to a
+    /// different CPU when the host can't provide it
+    pub fn supports_safe_host_model(&self) -> bool {
+        self.cpu_mode("host-model")
+            .and_then(|m| m.find("./model"))
+            .is_some_and(|m| m.attr("fallback") == Some("forbid"))
+    }
+
+    pub fn supports_maximum_cpu_mode(&self) -> bool {
+        self.supports_cpu_mode("maximum")
+    }
+
+    /// Named CPU models usable with mode='custom' on this host
+    pub fn get_cpu_models(&self) -> Vec<String> {
+        let Some(mode) = self.cpu_mode("custom") else {
+            return vec![];
+        };
+        mode.find_all("./model")
+            .into_iter()
+            .filter(|m| m.attr("usable") != Some("no"))
+            .map(|m| m.text())
+            .collect()
+    }
+
+    /// Firmware images libvirt knows about for `<loader>`
+    pub fn loader_values(&self) -> Vec<String> {
+        self.xml
+            .find_all("./os/loader/value")
+            .into_iter()
+            .map(|v| v.text())
+            .collect()
+    }
+
+    /// Whether we know how to set up UEFI for the arch
+    pub fn arch_can_uefi(&self) -> bool {
+        let arch = self.arch().unwrap_or_default();
+        UEFI_ARCH_PATTERNS.iter().any(|(a, _)| *a == arch)
+    }
+
+    /// The loader image that best matches the arch
+    pub fn find_uefi_path_for_arch(&self) -> Option<String> {
+        let arch = self.arch().unwrap_or_default();
+        let (_, patterns) = UEFI_ARCH_PATTERNS.iter().find(|(a, _)| *a == arch)?;
+        let paths = self.loader_values();
+        patterns
+            .iter()
+            .find_map(|p| paths.iter().find(|path| wildcard_match(p, path)))
+            .cloned()
+    }
+
+    /// Pretty label for a loader path, None meaning the default firmware
+    pub fn label_for_firmware_path(&self, path: Option<&str>) -> String {
+        let Some(path) = path else {
+            return match self.arch().as_deref() {
+                Some("i686" | "x86_64") => "BIOS".to_string(),
+                _ => "Default".to_string(),
+            };
+        };
+        for (arch, patterns) in UEFI_ARCH_PATTERNS {
+            if patterns.iter().any(|p| wildcard_match(p, path)) {
+                return format!("UEFI {}: {}", arch, path);
+            }
+        }
+        format!("Custom: {}", path)
+    }
+
+    /// libvirt advertises loading UEFI images as `<loader>`
+    pub fn supports_uefi_loader(&self) -> bool {
+        self.enum_values("./os/loader", "readonly")
+            .iter()
+            .any(|v| v == "yes")
+    }
+
+    /// libvirt can pick the firmware itself with `<os firmware='efi'>`
+    pub fn supports_firmware_efi(&self) -> bool {
+        self.enum_values("./os", "firmware")
+            .iter()
+            .any(|v| v == "efi")
+    }
+
+    pub fn supports_memorybacking_memfd(&self) -> bool {
+        self.enum_values("./memoryBacking", "sourceType")
+            .iter()
+            .any(|v| v == "memfd")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str) -> DomainCapabilities {
+        let path = format!(
+            "{}/../tests/data/capabilities/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        DomainCapabilities::parse(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_cpu_and_memory_caps() {
+        let caps = load("kvm-x86_64-domcaps-latest.xml");
+        assert_eq!(caps.max_vcpus(), Some(4096));
+        assert!(caps.supports_safe_host_model());
+        assert!(caps.supports_maximum_cpu_mode());
+        assert!(caps.supports_cpu_mode("host-passthrough"));
+        let models = caps.get_cpu_models();
+        assert!(models.iter().any(|m| m == "Skylake-Client-noTSX-IBRS"));
+        assert!(!models.iter().any(|m| m == "phenom"));
+        assert!(caps.supports_memorybacking_memfd());
+
+        let caps = load("test-domcaps.xml");
+        assert_eq!(caps.max_vcpus(), Some(255));
+        assert!(caps.get_cpu_models().is_empty());
+        assert!(!caps.supports_safe_host_model());
+        assert_eq!(caps.enum_values("./os/loader", "type"), ["rom", "pflash"]);
+        assert!(caps.supports_uefi_loader());
+        assert!(!caps.supports_firmware_efi());
+        assert!(DomainCapabilities::parse("<capabilities/>").is_err());
+    }
+
+    #[test]
+    fn test_uefi_paths() {
+        let caps = load("kvm-x86_64-domcaps-latest.xml");
+        assert!(caps.supports_firmware_efi());
+        assert!(caps.arch_can_uefi());
+        assert_eq!(
+            caps.find_uefi_path_for_arch().as_deref(),
+            Some("/usr/share/edk2/ovmf/OVMF_CODE.fd")
+        );
+        assert_eq!(caps.label_for_firmware_path(None), "BIOS");
+        assert_eq!(
+            caps.label_for_firmware_path(Some("/usr/share/OVMF/OVMF_CODE.fd")),
+            "UEFI x86_64: /usr/share/OVMF/OVMF_CODE.fd"
+        );
+        assert_eq!(
+            caps.label_for_firmware_path(Some("/foo/bar")),
+            "Custom: /foo/bar"
+        );
+        assert!(wildcard_match("*edk2-i386-*.fd", "/x/edk2-i386-vars.fd"));
+        assert!(!wildcard_match("*edk2-i386-*.fd", "/x/edk2-i386-vars.fdx"));
+    }
+}
@@ -0,0 +1,327 @@
+// Pixel buffers and their display (replaces the GdkPixbuf and GtkImage
+// use in virtManager/details/snapshots.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! A `Framebuffer` holds RGB pixels, decoded from the PPM or PNG images
+//! libvirt screenshots come in. iced is built without its image support,
+//! so `FramebufferView` paints the pixels as quads, one per run of equal
+//! colored pixels in a row. That is plenty fast for thumbnails and the
+//! mostly flat content of guest screens. The console viewers draw their
+//! remote framebuffer the same way, scaled, through `draw_framebuffer`.
+
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::renderer;
+use iced::advanced::widget::{self, Widget};
+use iced::{Color, Element, Length, Point, Rectangle, Size, mouse};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    /// Row major RGB, 3 bytes per pixel
+    pub pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    /// An all black buffer
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 3],
+        }
+    }
+
+    /// Decode an image of the passed mime type
+    pub fn decode(mime: &str, data: &[u8]) -> Result<Self, String> {
+        match mime {
+            "image/x-portable-pixmap" => Self::from_ppm(data),
+            "image/png" => Self::from_png(data),
+            "image/jpeg" => Self::from_jpeg(data),
+            _ => Err(format!("Unsupported image type '{}'", mime)),
+        }
+    }
+
+    /// Parse a binary (P6) PPM image with 8 bit samples
+    pub fn from_ppm(data: &[u8]) -> Result<Self, String> {
+        let err = || "Invalid PPM image".to_string();
+        let mut pos = 0;
+        let mut fields = vec![];
+        while fields.len() < 4 {
+            // Whitespace and comments separate the header fields
+            while pos < data.len() && (data[pos].is_ascii_whitespace() || data[pos] == b'#') {
+                if data[pos] == b'#' {
+                    while pos < data.len() && data[pos] != b'\n' {
+                        pos += 1;
+                    }
+                } else {
+                    pos += 1;
+                }
+            }
+            let start = pos;
+            while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(err());
+            }
+            fields.push(String::from_utf8_lossy(&data[start..pos]).to_string());
+        }
+        if fields[0] != "P6" {
+            return Err(format!("Unsupported PPM format '{}'", fields[0]));
+        }
+        let width: u32 = fields[1].parse().map_err(|_| err())?;
+        let height: u32 = fields[2].parse().map_err(|_| err())?;
+        if fields[3] != "255" {
+            return Err(format!("Unsupported PPM max value '{}'", fields[3]));
+        }
+        // A single whitespace byte ends the header
+        let pixels = data.get(pos + 1..).ok_or_else(err)?;
+        let len = width as usize * height as usize * 3;
+        if pixels.len() < len {
+            return Err("Truncated PPM image".to_string());
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels: pixels[..len].to_vec(),
+        })
+    }
+
+    pub fn from_png(data: &[u8]) -> Result<Self, String> {
+        let err = |e: png::DecodingError| format!("Invalid PNG image: {}", e);
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(err)?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(err)?;
+        let buf = &buf[..info.buffer_size()];
+        let pixels = match info.color_type {
+            png::ColorType::Rgb => buf.to_vec(),
+            png::ColorType::Rgba => buf.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+            png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g]).collect(),
+            png::ColorType::GrayscaleAlpha => {
+                buf.chunks(2).flat_map(|p| [p[0], p[0], p[0]]).collect()
+            }
+            png::ColorType::Indexed => return Err("Unexpected indexed PNG data".to_string()),
+        };
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    pub fn from_jpeg(data: &[u8]) -> Result<Self, String> {
+        let mut decoder = jpeg_decoder::Decoder::new(data);
+        let pixels = decoder
+            .decode()
+            .map_err(|e| format!("Invalid JPEG image: {}", e))?;
+        let info = decoder.info().ok_or("Invalid JPEG image")?;
+        let pixels = match info.pixel_format {
+            jpeg_decoder::PixelFormat::RGB24 => pixels,
+            jpeg_decoder::PixelFormat::L8 => pixels.iter().flat_map(|&g| [g, g, g]).collect(),
+            other => return Err(format!("Unsupported JPEG pixel format {:?}", other)),
+        };
+        Ok(Self {
+            width: info.width as u32,
+            height: info.height as u32,
+            pixels,
+        })
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let idx = (y as usize * self.width as usize + x as usize) * 3;
+        [self.pixels[idx], self.pixels[idx + 1], self.pixels[idx + 2]]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, rgb: [u8; 3]) {
+        let idx = (y as usize * self.width as usize + x as usize) * 3;
+        self.pixels[idx..idx + 3].copy_from_slice(&rgb);
+    }
+
+    pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, rgb: [u8; 3]) {
+        for row in y..y + height {
+            for col in x..x + width {
+                self.set_pixel(col, row, rgb);
+            }
+        }
+    }
+
+    /// Copy the `width` x `height` area at `srcx`, `srcy` to `x`, `y`.
+    /// The areas may overlap.
+    pub fn copy_rect(&mut self, srcx: u32, srcy: u32, x: u32, y: u32, width: u32, height: u32) {
+        let stride = self.width as usize * 3;
+        let len = width as usize * 3;
+        let rows: Vec<u32> = if y > srcy {
+            (0..height).rev().collect()
+        } else {
+            (0..height).collect()
+        };
+        for row in rows {
+            let src = (srcy + row) as usize * stride + srcx as usize * 3;
+            let dst = (y + row) as usize * stride + x as usize * 3;
+            self.pixels.copy_within(src..src + len, dst);
+        }
+    }
+
+    /// Shrink to fit in a `maxsize` square, keeping the aspect ratio
+    pub fn scaled(&self, maxsize: u32) -> Self {
+        let big = self.width.max(self.height);
+        if big <= maxsize || big == 0 {
+            return self.clone();
+        }
+        let scale = |v: u32| ((v as u64 * maxsize as u64 / big as u64) as u32).max(1);
+        let (width, height) = (scale(self.width), scale(self.height));
+        let mut ret = Self::new(width, height);
+        for y in 0..height {
+            let srcy = (y as u64 * self.height as u64 / height as u64) as u32;
+            for x in 0..width {
+                let srcx = (x as u64 * self.width as u64 / width as u64) as u32;
+                let idx = (y as usize * width as usize + x as usize) * 3;
+                ret.pixels[idx..idx + 3].copy_from_slice(&self.pixel(srcx, srcy));
+            }
+        }
+        ret
+    }
+}
+
+/// Widget drawing a framebuffer at its pixel size
+#[derive(Debug)]
+pub struct FramebufferView<'a> {
+    fb: &'a Framebuffer,
+}
+
+pub fn framebuffer(fb: &Framebuffer) -> FramebufferView<'_> {
+    FramebufferView { fb }
+}
+
+impl<Message, Theme, Renderer> Widget<Message, Theme, Renderer> for FramebufferView<'_>
+where
+    Renderer: renderer::Renderer,
+{
+    fn size(&self) -> Size<Length> {
+        Size::new(
+            Length::Fixed(self.fb.width as f32),
+            Length::Fixed(self.fb.height as f32),
+        )
+    }
+
+    fn layout(
+        &self,
+        _tree: &mut widget::Tree,
+        _renderer: &Renderer,
+        _limits: &layout::Limits,
+    ) -> layout::Node {
+        layout::Node::new(Size::new(self.fb.width as f32, self.fb.height as f32))
+    }
+
+    fn draw(
+        &self,
+        _tree: &widget::Tree,
+        renderer: &mut Renderer,
+        _theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        draw_framebuffer(renderer, self.fb, layout.position(), 1.0, viewport);
+    }
+}
+
+/// Paint `fb` with its top left corner at `origin`, each pixel `scale`
+/// units wide, skipping the rows outside `viewport`
+pub fn draw_framebuffer<Renderer: renderer::Renderer>(
+    renderer: &mut Renderer,
+    fb: &Framebuffer,
+    origin: Point,
+    scale: f32,
+    viewport: &Rectangle,
+) {
+    let bounds = Rectangle::new(
+        origin,
+        Size::new(fb.width as f32 * scale, fb.height as f32 * scale),
+    );
+    let Some(visible) = bounds.intersection(viewport) else {
+        return;
+    };
+    let first = ((visible.y - bounds.y) / scale).max(0.0) as u32;
+    let last = (((visible.y + visible.height - bounds.y) / scale).ceil() as u32).min(fb.height);
+    for y in first..last {
+        let mut x = 0;
+        while x < fb.width {
+            let color = fb.pixel(x, y);
+            let start = x;
+            while x < fb.width && fb.pixel(x, y) == color {
+                x += 1;
+            }
+            renderer.fill_quad(
+                renderer::Quad {
+                    bounds: Rectangle {
+                        x: bounds.x + start as f32 * scale,
+                        y: bounds.y + y as f32 * scale,
+                        width: (x - start) as f32 * scale,
+                        height: scale,
+                    },
+                    ..renderer::Quad::default()
+                },
+                Color::from_rgb8(color[0], color[1], color[2]),
+            );
+        }
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<FramebufferView<'a>>
+    for Element<'a, Message, Theme, Renderer>
+where
+    Renderer: renderer::Renderer + 'a,
+{
+    fn from(view: FramebufferView<'a>) -> Self {
+        Element::new(view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let mut ppm = b"P6\n# comment\n4 2\n255\n".to_vec();
+        for i in 0..8u8 {
+            ppm.extend([i, i * 2, 255 - i]);
+        }
+        let fb = Framebuffer::decode("image/x-portable-pixmap", &ppm).unwrap();
+        assert_eq!((fb.width, fb.height), (4, 2));
+        assert_eq!(fb.pixel(1, 1), [5, 10, 250]);
+        let small = fb.scaled(2);
+        assert_eq!((small.width, small.height), (2, 1));
+        assert_eq!(small.pixel(1, 0), [2, 4, 253]);
+        assert!(Framebuffer::from_ppm(&ppm[..20]).is_err());
+
+        let mut fb = Framebuffer::new(3, 3);
+        fb.fill_rect(0, 0, 2, 2, [9, 9, 9]);
+        fb.set_pixel(1, 1, [1, 2, 3]);
+        fb.copy_rect(0, 0, 1, 1, 2, 2);
+        assert_eq!(fb.pixel(2, 2), [1, 2, 3]);
+        assert_eq!(fb.pixel(1, 1), [9, 9, 9]);
+        assert_eq!(fb.pixel(2, 0), [0, 0, 0]);
+
+        let mut png = vec![];
+        {
+            let mut enc = png::Encoder::new(&mut png, 2, 1);
+            enc.set_color(png::ColorType::Rgba);
+            enc.set_depth(png::BitDepth::Eight);
+            let mut writer = enc.write_header().unwrap();
+            writer
+                .write_image_data(&[1, 2, 3, 255, 4, 5, 6, 0])
+                .unwrap();
+        }
+        let fb = Framebuffer::decode("image/png", &png).unwrap();
+        assert_eq!(fb.pixels, [1, 2, 3, 4, 5, 6]);
+        assert!(Framebuffer::decode("image/gif", &png).is_err());
+    }
+}
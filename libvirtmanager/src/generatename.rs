@@ -0,0 +1,76 @@
+// Unique name generation (port of virtinst/generatename.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+/// Generate a new name from `base` that `collides` reports as unused.
+///
+/// Names look like `base-#suffix`: if `foobar` and `foobar-1.img` already
+/// exist, base `foobar` with suffix `.img` gives `foobar-2.img`. `sep` goes
+/// between the base and the number, and `force_num` makes the result
+/// always end with a number starting at `start_num`. Fails once 100000
+/// numbered names are all taken.
+pub fn generate_name(
+    base: &str,
+    collides: impl Fn(&str) -> bool,
+    suffix: &str,
+    start_num: u32,
+    sep: &str,
+    force_num: bool,
+) -> Result<String, String> {
+    let nums = (start_num..start_num + 100000).map(Some);
+    let first = if force_num { None } else { Some(None) };
+    for i in first.into_iter().chain(nums) {
+        let tryname = match i {
+            Some(n) => format!("{}{}{}{}", base, sep, n, suffix),
+            None => format!("{}{}", base, suffix),
+        };
+        if !collides(&tryname) {
+            return Ok(tryname);
+        }
+    }
+    Err(format!("Failed to generate a unique name from '{}'", base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_name() {
+        let taken = ["foobar", "foobar-1.img", "vm-clone", "vm-clone1"];
+        let collides = |n: &str| taken.contains(&n);
+        assert_eq!(
+            generate_name("foobar", collides, ".img", 1, "-", false).unwrap(),
+            "foobar.img"
+        );
+        assert_eq!(
+            generate_name("foobar", collides, "", 1, "-", false).unwrap(),
+            "foobar-1"
+        );
+        assert_eq!(
+            generate_name(
+                "foobar",
+                |n| n == "foobar.img" || n == "foobar-1.img",
+                ".img",
+                1,
+                "-",
+                false
+            )
+            .unwrap(),
+            "foobar-2.img"
+        );
+        assert_eq!(
+            generate_name("vm-clone", collides, "", 1, "", false).unwrap(),
+            "vm-clone2"
+        );
+        assert_eq!(
+            generate_name("vm-clone", collides, "", 5, "", true).unwrap(),
+            "vm-clone5"
+        );
+        assert_eq!(
+            generate_name("foo", |_| true, "", 1, "-", false),
+            Err("Failed to generate a unique name from 'foo'".to_string())
+        );
+    }
+}
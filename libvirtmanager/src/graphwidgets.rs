@@ -0,0 +1,202 @@
+// Sparkline graphs of VM stats (Iced port of
+// virtManager/lib/graphwidgets.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `Sparkline` draws one or more data sets of values between 0 and 1 as
+//! lines across its width, optionally filled below. Data comes newest
+//! first, like the vectors of `StatsList`, and the newest value is drawn
+//! at the right edge. The same widget serves as the small graphs of the
+//! manager list and the large ones of the Details "Performance" page.
+
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::renderer;
+use iced::advanced::widget::{self, Widget};
+use iced::{Border, Color, Element, Length, Rectangle, Size, mouse};
+
+/// Color of single graphs and of their filled area
+pub const LINE_COLOR: Color = Color::from_rgb(0.421875, 0.640625, 0.73046875);
+pub const FILL_COLOR: Color = Color::from_rgba(0.71484375, 0.84765625, 0.89453125, 0.5);
+const BORDER_COLOR: Color = Color::from_rgb(0.8828125, 0.8671875, 0.8671875);
+
+/// Colors of the two lines of read/write and in/out graphs
+pub const IN_COLOR: Color = Color::from_rgb(0x82 as f32 / 255.0, 0.0, 0x3B as f32 / 255.0);
+pub const OUT_COLOR: Color = Color::from_rgb(
+    0x29 as f32 / 255.0,
+    0x5C as f32 / 255.0,
+    0x45 as f32 / 255.0,
+);
+
+/// Indent of the graph from the border
+const GRAPH_PAD: f32 = 3.0;
+const LINE_WIDTH: f32 = 1.5;
+
+pub struct Sparkline {
+    sets: Vec<(Vec<f32>, Color)>,
+    filled: bool,
+    width: Length,
+    height: Length,
+}
+
+/// A filled graph of one data set
+pub fn sparkline(data: Vec<f32>) -> Sparkline {
+    Sparkline {
+        sets: vec![(data, LINE_COLOR)],
+        filled: true,
+        width: Length::Fill,
+        height: Length::Fixed(24.0),
+    }
+}
+
+/// Two unfilled lines, like disk reads and writes
+pub fn in_out_graph(data_in: Vec<f32>, data_out: Vec<f32>) -> Sparkline {
+    Sparkline {
+        sets: vec![(data_in, IN_COLOR), (data_out, OUT_COLOR)],
+        filled: false,
+        width: Length::Fill,
+        height: Length::Fixed(24.0),
+    }
+}
+
+impl Sparkline {
+    pub fn filled(mut self, filled: bool) -> Self {
+        self.filled = filled;
+        self
+    }
+
+    pub fn width(mut self, width: impl Into<Length>) -> Self {
+        self.width = width.into();
+        self
+    }
+
+    pub fn height(mut self, height: impl Into<Length>) -> Self {
+        self.height = height.into();
+        self
+    }
+}
+
+/// Value of `data` (newest first) at fraction `pos` of the width, from
+/// the oldest value at 0 to the newest at 1
+pub fn sample_at(data: &[f32], pos: f32) -> f32 {
+    let Some(last) = data.len().checked_sub(1) else {
+        return 0.0;
+    };
+    let at = pos.clamp(0.0, 1.0) * last as f32;
+    let (idx, frac) = (at.floor() as usize, at.fract());
+    // Index from the left, where the oldest value is
+    let value = |i: usize| data[last - i.min(last)].clamp(0.0, 1.0);
+    value(idx) * (1.0 - frac) + value(idx + 1) * frac
+}
+
+impl<Message, Theme, Renderer> Widget<Message, Theme, Renderer> for Sparkline
+where
+    Renderer: renderer::Renderer,
+{
+    fn size(&self) -> Size<Length> {
+        Size::new(self.width, self.height)
+    }
+
+    fn layout(
+        &self,
+        _tree: &mut widget::Tree,
+        _renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        layout::Node::new(limits.resolve(self.width, self.height, Size::ZERO))
+    }
+
+    fn draw(
+        &self,
+        _tree: &widget::Tree,
+        renderer: &mut Renderer,
+        _theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        let bounds = layout.bounds();
+        // Rows scrolled out of a long list cost nothing
+        if bounds.intersection(viewport).is_none() {
+            return;
+        }
+        renderer.fill_quad(
+            renderer::Quad {
+                bounds,
+                border: Border {
+                    color: BORDER_COLOR,
+                    width: 1.0,
+                    radius: 0.0.into(),
+                },
+                ..renderer::Quad::default()
+            },
+            Color::WHITE,
+        );
+        let graph = bounds.shrink(GRAPH_PAD);
+        let columns = graph.width.floor() as usize;
+        if columns < 2 || graph.height <= 0.0 {
+            return;
+        }
+        let bottom = graph.y + graph.height;
+        let quad = |x: f32, top: f32, height: f32| renderer::Quad {
+            bounds: Rectangle {
+                x,
+                y: top,
+                width: 1.0,
+                height,
+            },
+            ..renderer::Quad::default()
+        };
+
+        // One pixel column at a time, so lines can slope without paths
+        for (data, color) in &self.sets {
+            let mut prev: Option<f32> = None;
+            for col in 0..columns {
+                let value = sample_at(data, col as f32 / (columns - 1) as f32);
+                let y = bottom - value * graph.height;
+                let x = graph.x + col as f32;
+                if self.filled && value > 0.0 {
+                    renderer.fill_quad(quad(x, y, bottom - y), FILL_COLOR);
+                }
+                // No line along the baseline while the value stays zero
+                let prev_y = prev.unwrap_or(y);
+                if value > 0.0 || prev_y < bottom {
+                    let top = y.min(prev_y) - LINE_WIDTH / 2.0;
+                    let height = (y - prev_y).abs() + LINE_WIDTH;
+                    renderer.fill_quad(quad(x, top.max(graph.y), height), *color);
+                }
+                prev = Some(y);
+            }
+        }
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<Sparkline> for Element<'a, Message, Theme, Renderer>
+where
+    Renderer: renderer::Renderer + 'a,
+{
+    fn from(graph: Sparkline) -> Self {
+        Element::new(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_at() {
+        // Newest first: the right edge shows 1.0, the left edge 0.0
+        let data = [1.0, 0.5, 0.0];
+        assert_eq!(sample_at(&data, 0.0), 0.0);
+        assert_eq!(sample_at(&data, 0.25), 0.25);
+        assert_eq!(sample_at(&data, 0.5), 0.5);
+        assert_eq!(sample_at(&data, 1.0), 1.0);
+        // Out of range values stay inside the graph
+        assert_eq!(sample_at(&[7.0, -1.0], 1.0), 1.0);
+        assert_eq!(sample_at(&[7.0, -1.0], 0.0), 0.0);
+        assert_eq!(sample_at(&[], 0.5), 0.0);
+        assert_eq!(sample_at(&[0.3], 0.5), 0.3);
+    }
+}
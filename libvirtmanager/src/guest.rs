@@ -0,0 +1,987 @@
+// Domain XML model (port of the parts of virtinst/guest.py the CLIs share)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `Guest` wraps a parsed `<domain>` document. Typed accessors cover the
+//! commonly used properties; everything else is reachable through the
+//! xpath helpers on the underlying `Element`.
+
+use std::borrow::Cow;
+
+use crate::osdict::{OsVariant, osdb};
+use crate::xmlapi::Element;
+
+/// Device element names in the order libvirt and virtinst emit them
+pub const DEVICE_ORDER: &[&str] = &[
+    "disk",
+    "controller",
+    "filesystem",
+    "interface",
+    "smartcard",
+    "serial",
+    "parallel",
+    "console",
+    "channel",
+    "input",
+    "tpm",
+    "graphics",
+    "sound",
+    "audio",
+    "video",
+    "hostdev",
+    "redirdev",
+    "watchdog",
+    "memballoon",
+    "rng",
+    "panic",
+    "shmem",
+    "memory",
+    "vsock",
+    "iommu",
+    "pstore",
+];
+
+/// Where the libosinfo id of the guest OS is recorded
+const OS_ID_XPATH: &str = "./metadata/libosinfo:libosinfo/libosinfo:os/@id";
+
+/// Top level `<domain>` children in the order newly built XML uses
+/// Device types that support `<boot order>`, in boot list order
+pub const BOOTABLE_DEVICES: &[&str] = &["disk", "interface", "hostdev"];
+
+pub const DOMAIN_ORDER: &[&str] = &[
+    "name",
+    "uuid",
+    "genid",
+    "title",
+    "description",
+    "metadata",
+    "iothreads",
+    "iothreadids",
+    "defaultiothread",
+    "maxMemory",
+    "memory",
+    "currentMemory",
+    "blkiotune",
+    "memtune",
+    "memoryBacking",
+    "vcpu",
+    "vcpus",
+    "numatune",
+    "resource",
+    "sysinfo",
+    "bootloader",
+    "bootloader_args",
+    "os",
+    "idmap",
+    "features",
+    "cpu",
+    "clock",
+    "on_poweroff",
+    "on_reboot",
+    "on_crash",
+    "pm",
+    "devices",
+    "launchSecurity",
+    "seclabel",
+    "keywrap",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Guest {
+    pub xml: Element,
+    /// Device ids that drivers installed alongside the OS add support
+    /// for, like virtio drivers for an unattended Windows install
+    extra_drivers: Vec<String>,
+}
+
+impl Default for Guest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Guest {
+    /// An empty `<domain>` for building new XML
+    pub fn new() -> Self {
+        Self {
+            xml: Element::new("domain"),
+            extra_drivers: vec![],
+        }
+    }
+
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        let el = Element::parse(xml)?;
+        if el.name != "domain" {
+            return Err(format!(
+                "XML did not have expected root element name 'domain', found '{}'",
+                el.name
+            ));
+        }
+        Ok(Self::from_element(el))
+    }
+
+    pub(crate) fn from_element(xml: Element) -> Self {
+        Self {
+            xml,
+            extra_drivers: vec![],
+        }
+    }
+
+    pub fn get_xml(&self) -> String {
+        self.xml.get_xml()
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.xml.get("./name")
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.xml.set("./name", Some(name));
+    }
+
+    pub fn uuid(&self) -> Option<String> {
+        self.xml.get("./uuid")
+    }
+
+    pub fn set_uuid(&mut self, uuid: Option<&str>) {
+        self.xml.set("./uuid", uuid);
+    }
+
+    pub fn domain_type(&self) -> Option<String> {
+        self.xml.get("./@type")
+    }
+
+    pub fn os_type(&self) -> Option<String> {
+        self.xml.get("./os/type")
+    }
+
+    pub fn arch(&self) -> Option<String> {
+        self.xml.get("./os/type/@arch")
+    }
+
+    pub fn machine(&self) -> Option<String> {
+        self.xml.get("./os/type/@machine")
+    }
+
+    /// The guest OS recorded in the libosinfo metadata. None when there
+    /// is none or the OS database doesn't know it; defaults then assume
+    /// a modern OS.
+    pub fn osinfo(&self) -> Option<&'static OsVariant> {
+        let id = self.xml.get(OS_ID_XPATH)?;
+        let ret = osdb().lookup_os_by_full_id(&id);
+        if ret.is_none() {
+            log::debug!(
+                "XML had libosinfo os id={} but we didn't find any libosinfo object matching that",
+                id
+            );
+        }
+        ret
+    }
+
+    /// Count devices `devids` as supported by the guest OS when picking
+    /// defaults
+    pub fn add_extra_drivers(&mut self, devids: &[String]) {
+        self.extra_drivers.extend(devids.iter().cloned());
+    }
+
+    /// `osinfo`, extended with the devices of any extra drivers
+    fn osinfo_with_drivers(&self) -> Option<Cow<'static, OsVariant>> {
+        let osinfo = self.osinfo()?;
+        if self.extra_drivers.is_empty() {
+            return Some(Cow::Borrowed(osinfo));
+        }
+        Some(Cow::Owned(osinfo.with_extra_devices(&self.extra_drivers)))
+    }
+
+    /// Set the guest OS by short id like `fedora29`. `generic` has no
+    /// libosinfo id, so it clears the metadata.
+    pub fn set_os_name(&mut self, name: &str) -> Result<(), String> {
+        let os = osdb().require_os(name)?;
+        log::debug!("Setting Guest osinfo name {}", os.name);
+        self.xml.set(OS_ID_XPATH, os.full_id.as_deref());
+        Ok(())
+    }
+
+    pub fn is_container(&self) -> bool {
+        self.os_type().as_deref() == Some("exe")
+    }
+
+    /// Maximum memory in KiB
+    pub fn memory(&self) -> Option<u64> {
+        self.xml.get("./memory").and_then(|v| v.trim().parse().ok())
+    }
+
+    pub fn current_memory(&self) -> Option<u64> {
+        self.xml
+            .get("./currentMemory")
+            .and_then(|v| v.trim().parse().ok())
+    }
+
+    pub fn vcpus(&self) -> Option<u32> {
+        self.xml.get("./vcpu").and_then(|v| v.trim().parse().ok())
+    }
+
+    /// xpath of the nth (0 based) device of the passed element name
+    pub fn device_xpath(tag: &str, idx: usize) -> String {
+        format!("./devices/{}[{}]", tag, idx + 1)
+    }
+
+    pub fn devices(&self, tag: &str) -> Vec<&Element> {
+        self.xml.find_all(&format!("./devices/{}", tag))
+    }
+
+    /// All devices in document order, with their element names
+    pub fn all_devices(&self) -> Vec<&Element> {
+        match self.xml.find("./devices") {
+            Some(d) => d
+                .child_elements()
+                .filter(|e| DEVICE_ORDER.contains(&e.name.as_str()))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Append a device to `<devices>`, indenting it to match the document.
+    /// Returns the device index among devices with the same element name.
+    pub fn add_device(&mut self, mut dev: Element) -> usize {
+        let tag = dev.name.clone();
+        let count = self.devices(&tag).len();
+        if self.xml.find("./devices").is_none() {
+            self.xml.set_bool("./devices", true);
+        }
+        let devices_ws = {
+            let idx = self
+                .xml
+                .child_index("devices", 0)
+                .expect("devices was just created");
+            match idx.checked_sub(1).map(|i| &self.xml.children[i]) {
+                Some(crate::xmlapi::Node::Text(t)) => t.clone(),
+                _ => "\n".to_string(),
+            }
+        };
+        let indent = devices_ws.rsplit('\n').next().unwrap_or("").to_string() + "  ";
+        reindent(&mut dev, &indent);
+        let devices = self.xml.find_mut("./devices").expect("devices exists");
+        devices.add_child_pretty(Some(&devices_ws), dev);
+        count
+    }
+
+    /// Remove the nth (0 based) device with the passed element name, along
+    /// with devices that only make sense next to it: the `<console>`
+    /// duplicating a removed `<serial>`, and spice helper devices once the
+    /// last spice `<graphics>` is gone.
+    pub fn remove_device(&mut self, tag: &str, idx: usize) -> Option<Element> {
+        let dev = self.remove_device_at(tag, idx)?;
+        self.remove_duplicate_console(&dev);
+        if dev.name == "graphics" && !self.has_spice() {
+            self.remove_spice_devices();
+        }
+        Some(dev)
+    }
+
+    fn remove_device_at(&mut self, tag: &str, idx: usize) -> Option<Element> {
+        let devices = self.xml.find_mut("./devices")?;
+        let pos = devices.child_index(tag, idx)?;
+        devices.remove_child_at(pos)
+    }
+
+    /// Devices that can take a `<boot order>`, as (element name, index).
+    /// redirdev can too but is rarely used for it.
+    pub fn bootable_devices(&self) -> Vec<(String, usize)> {
+        BOOTABLE_DEVICES
+            .iter()
+            .flat_map(|tag| (0..self.devices(tag).len()).map(|i| (tag.to_string(), i)))
+            .collect()
+    }
+
+    /// The boot order as devices. Without any per device `<boot order>`
+    /// the `<os><boot dev>` list is mapped to the first matching device.
+    pub fn boot_order(&self) -> Vec<(String, usize)> {
+        let mut order: Vec<(u32, (String, usize))> = self
+            .bootable_devices()
+            .into_iter()
+            .filter_map(|(tag, idx)| {
+                let dev = self.devices(&tag)[idx];
+                let n = dev.get("./boot/@order")?.parse().ok()?;
+                Some((n, (tag, idx)))
+            })
+            .collect();
+        if !order.is_empty() {
+            order.sort_by_key(|(n, _)| *n);
+            return order.into_iter().map(|(_, d)| d).collect();
+        }
+
+        let disks = self.devices("disk");
+        let first_disk = |want: &dyn Fn(&str) -> bool| {
+            disks
+                .iter()
+                .position(|d| want(d.attr("device").unwrap_or("disk")))
+                .map(|i| ("disk".to_string(), i))
+        };
+        let mut ret = vec![];
+        for boot in self.xml.find_all("./os/boot") {
+            let dev = match boot.attr("dev") {
+                Some("hd") => first_disk(&|d| d != "cdrom" && d != "floppy"),
+                Some("cdrom") => first_disk(&|d| d == "cdrom"),
+                Some("fd") => first_disk(&|d| d == "floppy"),
+                Some("network") => {
+                    (!self.devices("interface").is_empty()).then(|| ("interface".to_string(), 0))
+                }
+                _ => None,
+            };
+            if let Some(dev) = dev.filter(|d| !ret.contains(d)) {
+                ret.push(dev);
+            }
+        }
+        ret
+    }
+
+    /// Boot from the passed devices in order, replacing both the per
+    /// device and the `<os><boot dev>` style order
+    pub fn set_boot_order(&mut self, order: &[(String, usize)]) {
+        while self.xml.find("./os/boot").is_some() {
+            self.xml.force_remove("./os/boot");
+        }
+        let Some(devices) = self.xml.find_mut("./devices") else {
+            return;
+        };
+        for dev in devices.child_elements_mut() {
+            dev.set("./boot/@order", None);
+        }
+        for (n, (tag, idx)) in order.iter().enumerate() {
+            let xpath = format!("{}/boot/@order", Self::device_xpath(tag, *idx));
+            self.xml.set(&xpath, Some(&(n + 1).to_string()));
+        }
+    }
+
+    pub fn has_spice(&self) -> bool {
+        self.devices("graphics")
+            .iter()
+            .any(|g| g.attr("type") == Some("spice"))
+    }
+
+    /// Whether the first `<console>` is just libvirt's mirror of the
+    /// `<serial>` device
+    pub fn serial_is_console_dup(&self, serial: &Element) -> bool {
+        if serial.name != "serial" {
+            return false;
+        }
+        let Some(console) = self.devices("console").first().copied() else {
+            return false;
+        };
+        let target_type = console.get("./target/@type");
+        console.attr("type") == serial.attr("type")
+            && matches!(target_type.as_deref(), None | Some("serial"))
+    }
+
+    fn remove_duplicate_console(&mut self, serial: &Element) {
+        if self.serial_is_console_dup(serial) {
+            self.remove_device_at("console", 0);
+        }
+    }
+
+    fn remove_spice_devices(&mut self) {
+        for (tag, devtype) in [
+            ("audio", "spice"),
+            ("channel", "spicevmc"),
+            ("redirdev", "spicevmc"),
+            ("serial", "spiceport"),
+            ("console", "spiceport"),
+        ] {
+            while let Some(idx) = self
+                .devices(tag)
+                .iter()
+                .position(|d| d.attr("type") == Some(devtype))
+            {
+                self.remove_device_at(tag, idx);
+            }
+        }
+    }
+
+    /// Fill in the values a newly added device needs but the user didn't
+    /// pass: a bus and target for disks, a MAC address for interfaces.
+    /// This is the minimal subset of the virtinst `set_defaults` logic.
+    pub fn set_device_defaults(&self, dev: &mut Element) -> Result<(), String> {
+        match dev.name.as_str() {
+            "disk" => self.set_disk_defaults(dev),
+            "interface" => {
+                if dev.get("./mac/@address").is_none() {
+                    dev.set("./mac/@address", Some(&generate_mac()));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn default_disk_bus(&self, device: &str) -> &'static str {
+        let hvm = self.os_type().is_none_or(|t| t == "hvm");
+        let domtype = self.domain_type().unwrap_or_default();
+        if device == "floppy" {
+            "fdc"
+        } else if domtype == "xen" && !hvm {
+            "xen"
+        } else if !hvm {
+            "ide"
+        } else if device == "disk"
+            && ["kvm", "qemu", "test"].contains(&domtype.as_str())
+            && self
+                .osinfo_with_drivers()
+                .is_none_or(|o| o.supports_virtiodisk())
+        {
+            "virtio"
+        } else if self.machine().is_some_and(|m| m.contains("q35")) {
+            "sata"
+        } else {
+            "ide"
+        }
+    }
+
+    fn set_disk_defaults(&self, dev: &mut Element) -> Result<(), String> {
+        let device = dev.attr("device").unwrap_or("disk").to_string();
+        if device == "cdrom" {
+            dev.set_bool("./readonly", true);
+        }
+        if dev.get("./target/@dev").is_some() {
+            return Ok(());
+        }
+        let bus = match dev.get("./target/@bus") {
+            Some(b) => b,
+            None => {
+                let b = self.default_disk_bus(&device);
+                dev.set("./target/@bus", Some(b));
+                b.to_string()
+            }
+        };
+        let used: Vec<String> = self
+            .devices("disk")
+            .iter()
+            .filter_map(|d| d.get("./target/@dev"))
+            .collect();
+        let target = generate_disk_target(&bus, &used)?;
+        dev.set("./target/@dev", Some(&target));
+        Ok(())
+    }
+
+    pub fn is_x86(&self) -> bool {
+        self.arch()
+            .is_some_and(|a| matches!(a.as_str(), "x86_64" | "i686" | "i386"))
+    }
+
+    /// Set `<sysinfo>` system serial `ds=nocloud`, which points cloud-init
+    /// at the NoCloud seed cdrom. An explicit smbios setup wins.
+    pub fn set_smbios_serial_cloudinit(&mut self) {
+        let domtype = self.domain_type().unwrap_or_default();
+        if !["kvm", "qemu", "test"].contains(&domtype.as_str()) {
+            return;
+        }
+        let machvirt = self.machine().is_some_and(|m| m.starts_with("virt"));
+        if !self.is_x86() && !machvirt {
+            return;
+        }
+        if !matches!(
+            self.xml.get("./os/smbios/@mode").as_deref(),
+            None | Some("sysinfo")
+        ) {
+            return;
+        }
+        let idx = self
+            .xml
+            .find_all("./sysinfo")
+            .iter()
+            .position(|s| s.attr("type") == Some("smbios"))
+            .unwrap_or(self.xml.count("./sysinfo"));
+        let base = format!("./sysinfo[{}]", idx + 1);
+        if self
+            .xml
+            .get(&format!("{}/system/entry[@name='serial']", base))
+            .is_some()
+        {
+            return;
+        }
+        self.xml.set("./os/smbios/@mode", Some("sysinfo"));
+        self.xml.set(&format!("{}/@type", base), Some("smbios"));
+        self.xml.set(
+            &format!("{}/system/entry[@name='serial']", base),
+            Some("ds=nocloud"),
+        );
+    }
+
+    fn add_default_device(&mut self, tag: &str, attrs: &[(&str, &str)]) {
+        let mut dev = Element::new(tag);
+        for (xpath, val) in attrs {
+            dev.set(xpath, Some(val));
+        }
+        self.add_device(dev);
+    }
+
+    /// Fill in everything a new VM needs that the user didn't specify:
+    /// hypervisor and machine type, uuid, features, clock, default
+    /// devices, and per device defaults. Default devices with their tag
+    /// in `skip` are left out, for `--graphics none` and friends.
+    /// This is the shared subset of the virtinst `set_defaults` logic.
+    pub fn set_defaults(
+        &mut self,
+        conn: &dyn crate::connection::Connection,
+        skip: &[&str],
+    ) -> Result<(), String> {
+        if self.domain_type().is_none() {
+            let domtype = if conn.uri().starts_with("test") {
+                "test"
+            } else {
+                "kvm"
+            };
+            self.xml.set("./@type", Some(domtype));
+        }
+        let domtype = self.domain_type().unwrap_or_default();
+        let qemu = domtype == "kvm" || domtype == "qemu";
+        let osinfo = self.osinfo_with_drivers();
+        let osinfo = osinfo.as_deref();
+        let os_supports = |f: fn(&OsVariant) -> bool| osinfo.is_none_or(f);
+        if self.uuid().is_none() {
+            self.set_uuid(Some(&generate_uuid()));
+        }
+        if self.vcpus().is_none() {
+            self.xml.set("./vcpu", Some("1"));
+        }
+        if self.current_memory().is_none()
+            && let Some(memory) = self.memory()
+        {
+            self.xml.set("./currentMemory", Some(&memory.to_string()));
+        }
+        if self.memory().is_none()
+            && let Some(memory) = self.current_memory()
+        {
+            self.xml.set("./memory", Some(&memory.to_string()));
+        }
+
+        if self.os_type().is_none() {
+            self.xml.set("./os/type", Some("hvm"));
+        }
+        if self.arch().is_none() {
+            self.xml.set("./os/type/@arch", Some(host_arch()));
+        }
+        let hvm = self.os_type().as_deref() == Some("hvm");
+        if qemu
+            && hvm
+            && self.machine().is_none()
+            && self.is_x86()
+            && os_supports(OsVariant::supports_chipset_q35)
+        {
+            self.xml.set("./os/type/@machine", Some("q35"));
+        }
+        let arch = self.arch().unwrap_or_default();
+        if qemu
+            && hvm
+            && self.xml.find("./os/@firmware").is_none()
+            && self.xml.find("./os/loader").is_none()
+            && osinfo.is_some_and(|o| o.requires_firmware_efi(&arch))
+        {
+            self.xml.set("./os/@firmware", Some("efi"));
+        }
+        let has_bootorder = self
+            .all_devices()
+            .iter()
+            .any(|d| d.get("./boot/@order").is_some());
+        if hvm && self.xml.find("./os/boot").is_none() && !has_bootorder {
+            self.xml.set("./os/boot/@dev", Some("hd"));
+        }
+
+        if hvm && self.is_x86() && self.xml.find("./features").is_none() {
+            self.xml.set_bool("./features/acpi", true);
+            self.xml.set_bool("./features/apic", true);
+        }
+        if qemu && hvm && self.xml.find("./cpu").is_none() {
+            self.xml.set("./cpu/@mode", Some("host-passthrough"));
+        }
+        if self.xml.find("./clock").is_none() {
+            let offset = osinfo.map_or("utc", OsVariant::get_clock);
+            self.xml.set("./clock/@offset", Some(offset));
+            if qemu && self.is_x86() {
+                self.xml.set("./clock/timer[1]/@name", Some("rtc"));
+                self.xml
+                    .set("./clock/timer[1]/@tickpolicy", Some("catchup"));
+                self.xml.set("./clock/timer[2]/@name", Some("pit"));
+                self.xml.set("./clock/timer[2]/@tickpolicy", Some("delay"));
+                self.xml.set("./clock/timer[3]/@name", Some("hpet"));
+                self.xml.set("./clock/timer[3]/@present", Some("no"));
+            }
+        }
+        if hvm && self.is_x86() && self.xml.find("./pm").is_none() {
+            self.xml.set("./pm/suspend-to-mem/@enabled", Some("no"));
+            self.xml.set("./pm/suspend-to-disk/@enabled", Some("no"));
+        }
+
+        let wants = |guest: &Self, tag: &str| !skip.contains(&tag) && guest.devices(tag).is_empty();
+        if hvm && self.is_x86() && wants(self, "graphics") {
+            let gtype = if qemu { "spice" } else { "vnc" };
+            self.add_default_device("graphics", &[("./@type", gtype)]);
+        }
+        if !self.devices("graphics").is_empty() {
+            if wants(self, "video") {
+                let spice = self
+                    .devices("graphics")
+                    .iter()
+                    .any(|g| g.attr("type") == Some("spice"));
+                let model = if qemu && os_supports(OsVariant::supports_virtiogpu) {
+                    "virtio"
+                } else if qemu && spice && self.is_x86() {
+                    "qxl"
+                } else {
+                    "vga"
+                };
+                self.add_default_device("video", &[("./model/@type", model)]);
+            }
+            if hvm && wants(self, "input") {
+                self.add_default_device("input", &[("./@type", "tablet"), ("./@bus", "usb")]);
+            }
+        }
+        if wants(self, "console") && self.devices("serial").is_empty() {
+            self.add_default_device("console", &[("./@type", "pty")]);
+        }
+        let has_usb = self
+            .devices("controller")
+            .iter()
+            .any(|c| c.attr("type") == Some("usb"));
+        if (qemu || domtype == "test") && hvm && !has_usb {
+            let q35 = self.machine().is_some_and(|m| m.contains("q35"));
+            let model = if q35 && os_supports(OsVariant::supports_usb3) {
+                "qemu-xhci"
+            } else {
+                "ich9-ehci1"
+            };
+            self.add_default_device("controller", &[("./@type", "usb"), ("./@model", model)]);
+        }
+        if qemu && wants(self, "channel") {
+            self.add_default_device(
+                "channel",
+                &[
+                    ("./@type", "unix"),
+                    ("./target/@type", "virtio"),
+                    ("./target/@name", "org.qemu.guest_agent.0"),
+                ],
+            );
+        }
+        if qemu && hvm && wants(self, "rng") && os_supports(OsVariant::supports_virtiorng) {
+            self.add_default_device(
+                "rng",
+                &[
+                    ("./@model", "virtio"),
+                    ("./backend/@model", "random"),
+                    ("./backend", "/dev/urandom"),
+                ],
+            );
+        }
+        if qemu
+            && hvm
+            && self.devices("memballoon").is_empty()
+            && os_supports(OsVariant::supports_virtioballoon)
+        {
+            self.add_default_device("memballoon", &[("./@model", "virtio")]);
+        }
+        // UEFI is taken as a flag the VM targets a modern platform, which
+        // should have a TPM too
+        if qemu && self.xml.get("./os/@firmware").as_deref() == Some("efi") && wants(self, "tpm") {
+            let model = if self.is_x86() { "tpm-crb" } else { "tpm-tis" };
+            self.add_default_device(
+                "tpm",
+                &[("./@model", model), ("./backend/@type", "emulator")],
+            );
+        }
+
+        // Defaults are set one device at a time, so each new disk sees
+        // the targets picked for the ones before it
+        let tags: Vec<String> = self.all_devices().iter().map(|d| d.name.clone()).collect();
+        let mut seen: Vec<String> = vec![];
+        for tag in tags {
+            let idx = seen.iter().filter(|t| **t == tag).count();
+            seen.push(tag.clone());
+            let xpath = Self::device_xpath(&tag, idx);
+            let Some(mut dev) = self.xml.find(&xpath).cloned() else {
+                continue;
+            };
+            self.set_device_defaults(&mut dev)?;
+            if let Some(slot) = self.xml.find_mut(&xpath) {
+                *slot = dev;
+            }
+        }
+        self.sort_for_build();
+        Ok(())
+    }
+
+    /// Reorder top level children to DOMAIN_ORDER, and those of `<os>`
+    /// and `<devices>` likewise. Only meant for XML we built ourselves,
+    /// where there's no user formatting to preserve.
+    pub fn sort_for_build(&mut self) {
+        sort_children(&mut self.xml, DOMAIN_ORDER);
+        for el in self.xml.child_elements_mut() {
+            match el.name.as_str() {
+                "os" => sort_children(el, OS_ORDER),
+                "devices" => sort_children(el, DEVICE_ORDER),
+                _ => {}
+            }
+        }
+        self.xml.prettify();
+    }
+}
+
+/// `<os>` children in the order newly built XML uses
+const OS_ORDER: &[&str] = &[
+    "type", "firmware", "loader", "nvram", "kernel", "initrd", "cmdline", "dtb", "acpi", "boot",
+    "bootmenu", "smbios", "bios",
+];
+
+/// Replace the children of `el` with its child elements, stably sorted
+/// to `order`. Unknown elements go last.
+fn sort_children(el: &mut Element, order: &[&str]) {
+    let mut elements: Vec<Element> = el.child_elements().cloned().collect();
+    elements.sort_by_key(|e| {
+        order
+            .iter()
+            .position(|n| *n == e.name)
+            .unwrap_or(order.len())
+    });
+    el.children = elements
+        .into_iter()
+        .map(crate::xmlapi::Node::Element)
+        .collect();
+}
+
+/// Prefix every line break inside `el` with `indent`, so a standalone
+/// element can be dropped into a document at a deeper nesting level.
+pub fn reindent(el: &mut Element, indent: &str) {
+    for c in el.children.iter_mut() {
+        match c {
+            crate::xmlapi::Node::Text(t) if t.contains('\n') => {
+                *t = t.replace('\n', &format!("\n{}", indent));
+            }
+            crate::xmlapi::Node::Element(e) => reindent(e, indent),
+            _ => {}
+        }
+    }
+}
+
+/// Convert a 1 based index to a disk name suffix: 1 is 'a', 27 is 'aa'
+fn num_to_target(mut num: usize) -> String {
+    let mut digits = Vec::new();
+    for factor in 0..3u32 {
+        let mut amt = (num % 26usize.pow(factor + 1)) / 26usize.pow(factor);
+        if amt == 0 && num >= 26usize.pow(factor + 1) {
+            amt = 26;
+        }
+        num -= amt;
+        digits.insert(0, amt);
+    }
+    digits
+        .into_iter()
+        .filter(|d| *d != 0)
+        .map(|d| (b'a' + d as u8 - 1) as char)
+        .collect()
+}
+
+/// Pick the next free target name like `vdb` for the bus, skipping
+/// `used`. As in virtinst, a free name past all used ones is preferred
+/// over filling gaps.
+pub fn generate_disk_target(bus: &str, used: &[String]) -> Result<String, String> {
+    let (prefix, maxnode) = match bus {
+        "virtio" => ("vd", 1024),
+        "scsi" | "usb" | "sata" => ("sd", 1024),
+        "xen" => ("xvd", 1024),
+        "fdc" => ("fd", 2),
+        "ide" => ("hd", 4),
+        "sd" => ("mmcblk", 1024),
+        _ => ("sd", 1024),
+    };
+    let mut skip: Vec<&str> = used
+        .iter()
+        .map(String::as_str)
+        .filter(|t| t.starts_with(prefix))
+        .collect();
+    let mut first_found = None;
+    for i in 0..maxnode {
+        let gen_t = format!("{}{}", prefix, num_to_target(i + 1));
+        if let Some(pos) = skip.iter().position(|t| *t == gen_t) {
+            skip.remove(pos);
+            continue;
+        }
+        if skip.is_empty() {
+            return Ok(gen_t);
+        }
+        first_found.get_or_insert(gen_t);
+    }
+    first_found.ok_or_else(|| {
+        format!(
+            "Only {} disk{} for bus '{}' are supported",
+            maxnode,
+            if maxnode == 1 { "" } else { "s" },
+            bus
+        )
+    })
+}
+
+/// Generate a random MAC in the 52:54:00 prefix qemu/kvm use
+pub fn generate_mac() -> String {
+    let mut buf = [0u8; 3];
+    fill_random(&mut buf);
+    format!(
+        "52:54:00:{:02x}:{:02x}:{:02x}",
+        buf[0],
+        buf[1] & 0x7f,
+        buf[2]
+    )
+}
+
+/// Error out if any domain on the connection already uses `mac`
+pub fn check_mac_in_use(conn: &dyn crate::connection::Connection, mac: &str) -> Result<(), String> {
+    for dom in conn.list_domains()? {
+        let guest = Guest::parse(&conn.domain_xml(&dom.name, false)?)?;
+        let in_use = guest.devices("interface").iter().any(|iface| {
+            iface
+                .get("./mac/@address")
+                .is_some_and(|m| m.eq_ignore_ascii_case(mac))
+        });
+        if in_use {
+            return Err(format!(
+                "The MAC address '{}' is in use by another virtual machine.",
+                mac
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// libvirt's name for the architecture this was built for
+pub fn host_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86" => "i686",
+        "powerpc64" => "ppc64",
+        "arm" => "armv7l",
+        arch => arch,
+    }
+}
+
+/// Generate a random RFC 4122 v4 UUID string
+pub fn generate_uuid() -> String {
+    let mut b = [0u8; 16];
+    fill_random(&mut b);
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    format!(
+        "{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        b[0],
+        b[1],
+        b[2],
+        b[3],
+        b[4],
+        b[5],
+        b[6],
+        b[7],
+        b[8],
+        b[9],
+        b[10],
+        b[11],
+        b[12],
+        b[13],
+        b[14],
+        b[15]
+    )
+}
+
+pub(crate) fn fill_random(buf: &mut [u8]) {
+    use std::io::Read;
+    if let Ok(mut f) = std::fs::File::open("/dev/urandom")
+        && f.read_exact(buf).is_ok()
+    {
+        return;
+    }
+    // Fallback for platforms without /dev/urandom
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let mut x = seed as u64 ^ 0x9e37_79b9_7f4a_7c15;
+    for b in buf.iter_mut() {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        *b = x as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_remove_device() {
+        let mut guest = Guest::parse(
+            "<domain type='kvm'>\n  <name>foo</name>\n  <devices>\n    <disk/>\n  </devices>\n</domain>",
+        )
+        .unwrap();
+        let mut snd = Element::new("sound");
+        snd.set("./@model", Some("ich9"));
+        snd.set("./audio/@id", Some("1"));
+        assert_eq!(guest.add_device(snd), 0);
+        assert!(guest.get_xml().contains(
+            "    <disk/>\n    <sound model=\"ich9\">\n      <audio id=\"1\"/>\n    </sound>\n  </devices>"
+        ));
+        guest.remove_device("disk", 0);
+        assert_eq!(guest.all_devices().len(), 1);
+        assert_eq!(guest.name().as_deref(), Some("foo"));
+    }
+
+    #[test]
+    fn test_boot_order() {
+        let mut guest = Guest::parse(
+            "<domain><os><boot dev='network'/><boot dev='cdrom'/><boot dev='hd'/></os>\
+             <devices><disk device='cdrom'/><disk device='disk'/><interface/></devices></domain>",
+        )
+        .unwrap();
+        let dev = |tag: &str, idx| (tag.to_string(), idx);
+        assert_eq!(
+            guest.boot_order(),
+            [dev("interface", 0), dev("disk", 0), dev("disk", 1)]
+        );
+        guest.set_boot_order(&[dev("disk", 1), dev("interface", 0)]);
+        assert!(guest.xml.find("./os/boot").is_none());
+        assert_eq!(guest.boot_order(), [dev("disk", 1), dev("interface", 0)]);
+        assert!(guest.devices("disk")[0].find("./boot").is_none());
+    }
+
+    #[test]
+    fn test_disk_target_generation() {
+        assert_eq!(num_to_target(1), "a");
+        assert_eq!(num_to_target(26), "z");
+        assert_eq!(num_to_target(27), "aa");
+        let used = vec!["vda".to_string(), "vdc".to_string(), "hda".to_string()];
+        assert_eq!(generate_disk_target("virtio", &used).unwrap(), "vdd");
+        assert_eq!(generate_disk_target("sata", &used).unwrap(), "sda");
+        let full: Vec<String> = ["hda", "hdb", "hdc", "hdd"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert!(generate_disk_target("ide", &full).is_err());
+
+        let guest = Guest::parse(
+            "<domain type='kvm'>\n  <os>\n    <type machine='q35'>hvm</type>\n  </os>\n</domain>",
+        )
+        .unwrap();
+        let mut cdrom = Element::new("disk");
+        cdrom.set("./@device", Some("cdrom"));
+        guest.set_device_defaults(&mut cdrom).unwrap();
+        assert_eq!(cdrom.get("./target/@bus").as_deref(), Some("sata"));
+        assert_eq!(cdrom.get("./target/@dev").as_deref(), Some("sda"));
+        assert!(cdrom.get_bool("./readonly"));
+    }
+
+    #[test]
+    fn test_generated_ids() {
+        let mac = generate_mac();
+        assert!(mac.starts_with("52:54:00:"));
+        assert_eq!(mac.len(), 17);
+        let uuid = generate_uuid();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+    }
+}
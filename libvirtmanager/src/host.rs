@@ -0,0 +1,143 @@
+// Connection details window (Iced port of virtManager/host.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! One tab per kind of host object the connection manages. Object events
+//! go to every tab, which each pick out their own kind.
+
+use std::sync::Arc;
+
+use iced::widget::{Space, button, column, row, text};
+use iced::{Alignment, Element, Length, Subscription, Task, Theme, window};
+use log::debug;
+
+use crate::connection::{self, ConnEvent, Connection};
+use crate::connevents;
+use crate::hostnets::{HostNetsPage, Message as HostNetsMsg};
+use crate::hoststorage::{HostStoragePage, Message as HostStorageMsg};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Networks,
+    Storage,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    ShowTab(Tab),
+    Networks(HostNetsMsg),
+    Storage(HostStorageMsg),
+    Event(ConnEvent),
+    Close,
+}
+
+/// Public facade to show the connection details window
+pub struct VmmHost;
+
+impl VmmHost {
+    pub fn show_instance(uri: Option<&str>) -> Result<(), String> {
+        debug!("Launching connection details window");
+        let conn: Arc<dyn Connection> = Arc::from(connection::open(uri)?);
+        let app = HostApp::new(conn);
+        let title = format!("{} - Connection Details", app.conn.uri());
+        iced::application(
+            move |_: &HostApp| title.clone(),
+            HostApp::update_static,
+            HostApp::view_static,
+        )
+        .theme(|_| Theme::default())
+        .subscription(HostApp::subscription)
+        .window(window::Settings {
+            size: iced::Size::new(1000.0, 700.0),
+            position: window::Position::Centered,
+            resizable: true,
+            decorations: true,
+            ..Default::default()
+        })
+        .run_with(move || (app, Task::none()))
+        .map_err(|e| format!("Error launching connection details window: {}", e))
+    }
+}
+
+pub struct HostApp {
+    conn: Arc<dyn Connection>,
+    tab: Tab,
+    networks: HostNetsPage,
+    storage: HostStoragePage,
+}
+
+impl HostApp {
+    pub fn new(conn: Arc<dyn Connection>) -> Self {
+        Self {
+            networks: HostNetsPage::new(conn.clone()),
+            storage: HostStoragePage::new(conn.clone()),
+            conn,
+            tab: Tab::Networks,
+        }
+    }
+
+    fn update(&mut self, msg: Message) -> Task<Message> {
+        match msg {
+            Message::ShowTab(tab) => {
+                self.tab = tab;
+                Task::none()
+            }
+            Message::Networks(inner) => self.networks.update(inner).map(Message::Networks),
+            Message::Storage(inner) => self.storage.update(inner).map(Message::Storage),
+            Message::Event(event) => Task::batch([
+                self.networks
+                    .update(HostNetsMsg::Event(event.clone()))
+                    .map(Message::Networks),
+                self.storage
+                    .update(HostStorageMsg::Event(event))
+                    .map(Message::Storage),
+            ]),
+            Message::Close => window::get_latest().and_then(window::close),
+        }
+    }
+
+    fn view(&self) -> Element<'_, Message> {
+        let tab = |label, tab| {
+            let style = if self.tab == tab {
+                button::primary
+            } else {
+                button::text
+            };
+            button(text(label))
+                .style(style)
+                .on_press(Message::ShowTab(tab))
+        };
+        let tabs = row![
+            tab("Virtual Networks", Tab::Networks),
+            tab("Storage", Tab::Storage),
+        ]
+        .spacing(4);
+        let content = match self.tab {
+            Tab::Networks => self.networks.view().map(Message::Networks),
+            Tab::Storage => self.storage.view().map(Message::Storage),
+        };
+        let footer = row![
+            text(self.conn.uri().to_string()).size(12),
+            Space::with_width(Length::Fill),
+            button(text("Close")).on_press(Message::Close),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+        column![tabs, content, footer]
+            .padding(16)
+            .spacing(10)
+            .into()
+    }
+
+    // Static adapter functions for iced::application (public for embedding)
+    pub fn update_static(state: &mut Self, msg: Message) -> Task<Message> {
+        state.update(msg)
+    }
+    pub fn view_static(state: &Self) -> Element<'_, Message> {
+        state.view()
+    }
+    pub fn subscription(&self) -> Subscription<Message> {
+        connevents::watch(self.conn.clone()).map(Message::Event)
+    }
+}
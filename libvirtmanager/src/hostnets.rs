@@ -0,0 +1,580 @@
+// Virtual networks page of the connection details (Iced port of
+// virtManager/hostnets.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Lists the connection's virtual networks. The selected one shows its
+//! addressing, its DHCP leases and its XML, and can be started, stopped,
+//! deleted, renamed while inactive, and set to start on boot. Add opens
+//! the new network wizard in place of the details.
+
+use std::sync::Arc;
+
+use iced::widget::{
+    Column, Space, button, checkbox, column, container, row, scrollable, text, text_editor,
+    text_input,
+};
+use iced::{Alignment, Element, Length, Task};
+use log::debug;
+
+use crate::connection::{ConnEvent, Connection, DhcpLease, ObjectInfo, ObjectKind};
+use crate::createnet::{CreateNetwork, Message as CreateNetMsg};
+use crate::network::Network;
+use crate::snapshots::format_timestamp;
+use crate::xmlapi::validate_generic_name;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Refresh,
+    Select(String),
+    NameChanged(String),
+    AutostartToggle(bool),
+    ShowXml(bool),
+    XmlEdited(text_editor::Action),
+    Apply,
+    Start,
+    Stop,
+    Delete,
+    DeleteConfirmed(bool),
+    Add,
+    CreateNet(CreateNetMsg),
+    Event(ConnEvent),
+}
+
+/// Unapplied changes of the selected network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Name,
+    Autostart,
+    Xml,
+}
+
+pub struct HostNetsPage {
+    conn: Arc<dyn Connection>,
+    networks: Vec<ObjectInfo>,
+    selected: Option<String>,
+    network: Option<Network>,
+    name: String,
+    autostart: bool,
+    leases: Result<Vec<DhcpLease>, String>,
+    show_xml: bool,
+    xml_content: text_editor::Content,
+    edits: Vec<Edit>,
+    /// The new network wizard, while open
+    create: Option<CreateNetwork>,
+    confirm_delete: bool,
+    /// Set when the list can't be read, replacing the page
+    list_error: Option<String>,
+    error: Option<String>,
+}
+
+impl HostNetsPage {
+    pub fn new(conn: Arc<dyn Connection>) -> Self {
+        let mut page = Self {
+            conn,
+            networks: vec![],
+            selected: None,
+            network: None,
+            name: String::new(),
+            autostart: false,
+            leases: Ok(vec![]),
+            show_xml: false,
+            xml_content: text_editor::Content::new(),
+            edits: vec![],
+            create: None,
+            confirm_delete: false,
+            list_error: None,
+            error: None,
+        };
+        page.refresh();
+        page
+    }
+
+    fn is_active(&self) -> bool {
+        let name = self.selected.as_deref();
+        self.networks
+            .iter()
+            .any(|n| Some(n.name.as_str()) == name && n.active)
+    }
+
+    /// Re-read the network list, keeping the selection if it still
+    /// exists. Unapplied edits of the selection are kept too.
+    pub fn refresh(&mut self) {
+        match self.conn.list_networks() {
+            Ok(mut nets) => {
+                nets.sort_by(|a, b| a.name.cmp(&b.name));
+                self.networks = nets;
+                self.list_error = None;
+            }
+            Err(e) => {
+                debug!("Error refreshing network list: {}", e);
+                self.networks.clear();
+                self.list_error = Some(format!("Error refreshing network list: {}", e));
+            }
+        }
+        let exists = self
+            .networks
+            .iter()
+            .any(|n| Some(&n.name) == self.selected.as_ref());
+        if !exists {
+            self.selected = None;
+            self.edits.clear();
+        }
+        if self.edits.is_empty() {
+            self.load_selected();
+        }
+    }
+
+    fn load_selected(&mut self) {
+        self.edits.clear();
+        self.confirm_delete = false;
+        self.network = None;
+        self.leases = Ok(vec![]);
+        let Some(name) = self.selected.clone() else {
+            return;
+        };
+        let ret = self.conn.network_xml(&name, false).and_then(|xml| {
+            let net = Network::parse(&xml)?;
+            Ok((net, xml, self.conn.network_autostart(&name)?))
+        });
+        match ret {
+            Ok((net, xml, autostart)) => {
+                self.name = net.name.clone();
+                self.autostart = autostart;
+                self.network = Some(net);
+                self.xml_content = text_editor::Content::with_text(&xml);
+            }
+            Err(e) => {
+                debug!("Error selecting network: {}", e);
+                self.error = Some(format!("Error selecting network: {}", e));
+                return;
+            }
+        }
+        self.leases = self.conn.network_dhcp_leases(&name);
+    }
+
+    fn add_edit(&mut self, edit: Edit) {
+        if !self.edits.contains(&edit) {
+            self.edits.push(edit);
+        }
+    }
+
+    /// Redefine the network under a new name, restoring it if that fails
+    fn rename(&self, oldname: &str, newname: &str) -> Result<(), String> {
+        let origxml = self.conn.network_xml(oldname, true)?;
+        let mut net = Network::parse(&origxml)?;
+        net.name = newname.to_string();
+        let newxml = net.get_xml();
+        let autostart = self.conn.network_autostart(oldname)?;
+
+        debug!("Changing network name from {} to {}", oldname, newname);
+        self.conn.undefine_network(oldname)?;
+        if let Err(e) = self.conn.define_network(&newxml) {
+            debug!("Error defining new name network XML: {}", e);
+            if let Err(fixerr) = self.conn.define_network(&origxml) {
+                return Err(format!(
+                    "network rename failed. Attempting to recover also failed.\n\n\
+                     Original error: {}\n\nRecover error: {}",
+                    e, fixerr
+                ));
+            }
+            return Err(e);
+        }
+        self.conn.set_network_autostart(newname, autostart)
+    }
+
+    fn apply(&mut self) -> Result<(), String> {
+        let Some(name) = self.selected.clone() else {
+            return Ok(());
+        };
+        debug!("Applying changes for network '{}'", name);
+        let edits = std::mem::take(&mut self.edits);
+        let ret = (|| {
+            if edits.contains(&Edit::Autostart) {
+                self.conn.set_network_autostart(&name, self.autostart)?;
+            }
+            if edits.contains(&Edit::Name) && self.name != name {
+                validate_generic_name("Network", &self.name)?;
+                self.rename(&name, &self.name)?;
+                self.selected = Some(self.name.clone());
+            }
+            if edits.contains(&Edit::Xml) {
+                let info = self.conn.define_network(&self.xml_content.text())?;
+                self.selected = Some(info.name);
+            }
+            Ok(())
+        })();
+        self.refresh();
+        ret.map_err(|e: String| format!("Error changing network settings: {}", e))
+    }
+
+    /// Start, stop or delete the selected network through `action`
+    fn lifecycle(
+        &mut self,
+        verb: &str,
+        action: fn(&dyn Connection, &str) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let Some(name) = self.selected.clone() else {
+            return Ok(());
+        };
+        let ret = action(self.conn.as_ref(), &name);
+        self.edits.clear();
+        self.refresh();
+        ret.map_err(|e| format!("Error {} network '{}': {}", verb, name, e))
+    }
+
+    fn report(&mut self, ret: Result<(), String>) {
+        self.error = ret.err();
+    }
+
+    pub fn update(&mut self, msg: Message) -> Task<Message> {
+        match msg {
+            Message::Refresh => {
+                self.error = None;
+                self.edits.clear();
+                self.refresh();
+            }
+            Message::Select(name) => {
+                self.error = None;
+                self.create = None;
+                self.selected = Some(name);
+                self.load_selected();
+            }
+            Message::NameChanged(v) => {
+                self.name = v;
+                self.add_edit(Edit::Name);
+            }
+            Message::AutostartToggle(v) => {
+                self.autostart = v;
+                self.add_edit(Edit::Autostart);
+            }
+            Message::ShowXml(v) => self.show_xml = v,
+            Message::XmlEdited(action) => {
+                let edit = action.is_edit();
+                self.xml_content.perform(action);
+                if edit {
+                    self.add_edit(Edit::Xml);
+                }
+            }
+            Message::Apply => {
+                let ret = self.apply();
+                self.report(ret);
+            }
+            Message::Start => {
+                debug!("Starting network {:?}", self.selected);
+                let ret = self.lifecycle("starting", |c, n| c.start_network(n));
+                self.report(ret);
+            }
+            Message::Stop => {
+                debug!("Stopping network {:?}", self.selected);
+                let ret = self.lifecycle("stopping", |c, n| c.destroy_network(n));
+                self.report(ret);
+            }
+            Message::Delete => self.confirm_delete = true,
+            Message::DeleteConfirmed(yes) => {
+                self.confirm_delete = false;
+                if yes {
+                    debug!("Deleting network {:?}", self.selected);
+                    let ret = self.lifecycle("deleting", |c, n| c.undefine_network(n));
+                    self.report(ret);
+                }
+            }
+            Message::Add => {
+                debug!("Launching 'Add Network'");
+                self.confirm_delete = false;
+                match CreateNetwork::new(self.conn.clone()) {
+                    Ok(wizard) => self.create = Some(wizard),
+                    Err(e) => self.error = Some(format!("Error launching network wizard: {}", e)),
+                }
+            }
+            Message::CreateNet(CreateNetMsg::Cancel) => self.create = None,
+            Message::CreateNet(CreateNetMsg::Finish) => {
+                let created = self.create.as_mut().and_then(CreateNetwork::finish);
+                if let Some(name) = created {
+                    self.create = None;
+                    self.error = None;
+                    self.selected = Some(name);
+                    self.edits.clear();
+                    self.refresh();
+                }
+            }
+            Message::CreateNet(inner) => {
+                if let Some(wizard) = &mut self.create {
+                    wizard.update(inner);
+                }
+            }
+            Message::Event(event) => {
+                if event.kind() == ObjectKind::Network {
+                    self.refresh();
+                }
+            }
+        }
+        Task::none()
+    }
+
+    fn labeled<'a>(
+        label: &'a str,
+        widget: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        row![text(label).width(Length::Fixed(120.0)), widget.into()]
+            .spacing(8)
+            .align_y(Alignment::Center)
+            .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        if let Some(err) = &self.list_error {
+            return column![
+                text(err.clone()),
+                button(text("Refresh")).on_press(Message::Refresh),
+            ]
+            .spacing(10)
+            .into();
+        }
+        let body = match &self.create {
+            Some(wizard) => wizard.view().map(Message::CreateNet),
+            None => self.view_network(),
+        };
+        let mut page: Column<Message> = column![body].spacing(10);
+        if let Some(err) = &self.error {
+            page = page.push(text(err.clone()).size(14));
+        }
+        row![
+            self.view_list(),
+            container(scrollable(page)).width(Length::Fill).padding(8),
+        ]
+        .spacing(16)
+        .height(Length::Fill)
+        .into()
+    }
+
+    fn view_list(&self) -> Element<'_, Message> {
+        let mut list: Column<Message> = column![].spacing(4);
+        for net in &self.networks {
+            let state = if net.active { "Active" } else { "Inactive" };
+            let style = if self.selected.as_ref() == Some(&net.name) {
+                button::primary
+            } else {
+                button::text
+            };
+            list = list.push(
+                button(text(format!("{}\n{}", net.name, state)).width(Length::Fill))
+                    .style(style)
+                    .padding(6)
+                    .on_press(Message::Select(net.name.clone())),
+            );
+        }
+        if self.networks.is_empty() {
+            list = list.push(text("No virtual networks"));
+        }
+        let selected = self.network.is_some() && self.create.is_none();
+        let active = self.is_active();
+        let actions = row![
+            button(text("Add")).on_press(Message::Add),
+            button(text("Start")).on_press_maybe((selected && !active).then_some(Message::Start)),
+            button(text("Stop")).on_press_maybe((selected && active).then_some(Message::Stop)),
+            button(text("Delete")).on_press_maybe((selected && !active).then_some(Message::Delete)),
+            button(text("Refresh")).on_press(Message::Refresh),
+        ]
+        .spacing(6);
+        column![scrollable(list).height(Length::Fill), actions]
+            .spacing(10)
+            .width(Length::Fixed(280.0))
+            .into()
+    }
+
+    fn view_ip(&self, net: &Network, v6: bool) -> Option<Element<'_, Message>> {
+        let (subnet, dhcp) = net.ip_network(if v6 { "ipv6" } else { "ipv4" });
+        let forwarding = match (v6, &subnet) {
+            (false, None) => return None,
+            (false, Some(_)) => net.pretty_forward_mode(),
+            (true, Some(_)) => "Routed network".into(),
+            (true, None) if net.ipv6_enabled() => "Isolated network, internal routing only".into(),
+            (true, None) => "Isolated network, routing disabled".into(),
+        };
+        let dhcp = match dhcp {
+            Some((start, end)) => format!("{} - {}", start, end),
+            None => "Disabled".into(),
+        };
+        let title = if v6 {
+            "IPv6 configuration"
+        } else {
+            "IPv4 configuration"
+        };
+        let mut col: Column<Message> = column![text(title).size(16)].spacing(8);
+        if let Some(subnet) = subnet {
+            col = col.push(Self::labeled("Network:", text(subnet)));
+            col = col.push(Self::labeled("DHCP range:", text(dhcp)));
+        }
+        Some(
+            col.push(Self::labeled("Forwarding:", text(forwarding)))
+                .into(),
+        )
+    }
+
+    fn view_leases(&self) -> Element<'_, Message> {
+        let mut col: Column<Message> = column![text("DHCP leases").size(16)].spacing(4);
+        let leases = match &self.leases {
+            Ok(leases) => leases,
+            Err(e) => return col.push(text(format!("Error: {}", e))).into(),
+        };
+        if leases.is_empty() {
+            return col.push(text("No leases")).into();
+        }
+        let cell = |s: String, width: f32| text(s).size(13).width(Length::Fixed(width));
+        col = col.push(row![
+            cell("Expiry time".into(), 160.0),
+            cell("MAC address".into(), 140.0),
+            cell("IP address".into(), 180.0),
+            cell("Hostname".into(), 120.0),
+            cell("Client ID".into(), 120.0),
+        ]);
+        for lease in leases {
+            col = col.push(row![
+                cell(format_timestamp(lease.expirytime), 160.0),
+                cell(lease.mac.clone(), 140.0),
+                cell(format!("{}/{}", lease.ipaddr, lease.prefix), 180.0),
+                cell(lease.hostname.clone().unwrap_or_default(), 120.0),
+                cell(lease.clientid.clone().unwrap_or_default(), 120.0),
+            ]);
+        }
+        col.into()
+    }
+
+    fn view_network(&self) -> Element<'_, Message> {
+        let Some(net) = &self.network else {
+            return text("No virtual network selected.").into();
+        };
+        let active = self.is_active();
+        let tab = |label, xml: bool| {
+            let style = if self.show_xml == xml {
+                button::primary
+            } else {
+                button::text
+            };
+            button(text(label))
+                .style(style)
+                .on_press(Message::ShowXml(xml))
+        };
+        let mut col: Column<Message> =
+            column![row![tab("Details", false), tab("XML", true)].spacing(4)].spacing(10);
+        if self.show_xml {
+            col = col.push(
+                text_editor(&self.xml_content)
+                    .on_action(Message::XmlEdited)
+                    .height(Length::Fixed(420.0)),
+            );
+        } else {
+            let mut name = text_input("", &self.name).padding(6);
+            if !active {
+                name = name.on_input(Message::NameChanged);
+            }
+            col = col.push(Self::labeled("Name:", name));
+            col = col.push(Self::labeled(
+                "Device:",
+                text(net.bridge_name().unwrap_or_default().to_string()),
+            ));
+            if let Some(domain) = net.domain_name() {
+                col = col.push(Self::labeled("DNS domain:", text(domain.to_string())));
+            }
+            let state = if active { "Active" } else { "Inactive" };
+            col = col.push(Self::labeled("State:", text(state)));
+            col = col.push(Self::labeled(
+                "Autostart:",
+                checkbox("On Boot", self.autostart).on_toggle(Message::AutostartToggle),
+            ));
+            col = col.extend(self.view_ip(net, false));
+            col = col.extend(self.view_ip(net, true));
+            col = col.push(self.view_leases());
+        }
+        if self.confirm_delete {
+            col = col.push(
+                row![
+                    text(format!(
+                        "Are you sure you want to permanently delete the network {}?",
+                        net.name
+                    ))
+                    .width(Length::Fill),
+                    button(text("Delete")).on_press(Message::DeleteConfirmed(true)),
+                    button(text("Cancel")).on_press(Message::DeleteConfirmed(false)),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+        col.push(row![
+            Space::with_width(Length::Fill),
+            button(text("Apply"))
+                .on_press_maybe((!self.edits.is_empty()).then_some(Message::Apply)),
+        ])
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{Lifecycle, testdriver};
+
+    fn open() -> (Arc<dyn Connection>, HostNetsPage) {
+        let conn: Arc<dyn Connection> = Arc::new(testdriver());
+        (conn.clone(), HostNetsPage::new(conn))
+    }
+
+    #[test]
+    fn test_hostnets_all_networks_parse() {
+        let (conn, mut page) = open();
+        assert!(page.networks.windows(2).all(|w| w[0].name <= w[1].name));
+        for net in conn.list_networks().unwrap() {
+            let _ = page.update(Message::Select(net.name.clone()));
+            assert!(page.network.is_some(), "{}: {:?}", net.name, page.error);
+        }
+        let _ = page.update(Message::Select("default".into()));
+        let net = page.network.as_ref().unwrap();
+        assert_eq!(net.bridge_name(), Some("virbr0"));
+        assert_eq!(page.leases.as_ref().unwrap().len(), 2);
+        assert!(page.view_ip(net, false).is_some());
+    }
+
+    #[test]
+    fn test_hostnets_actions() {
+        let (conn, mut page) = open();
+        let _ = page.update(Message::Select("default".into()));
+        let _ = page.update(Message::Stop);
+        assert_eq!(page.error, None);
+        assert!(!page.is_active());
+        assert_eq!(page.leases, Ok(vec![]));
+
+        let _ = page.update(Message::AutostartToggle(true));
+        let _ = page.update(Message::NameChanged("renamed".into()));
+        let _ = page.update(Message::Apply);
+        assert_eq!(page.error, None);
+        assert_eq!(page.selected.as_deref(), Some("renamed"));
+        assert!(conn.network_autostart("renamed").unwrap());
+        assert!(conn.network_xml("default", false).is_err());
+
+        let _ = page.update(Message::Start);
+        assert!(page.is_active());
+        let _ = page.update(Message::Stop);
+        let _ = page.update(Message::Delete);
+        let _ = page.update(Message::DeleteConfirmed(true));
+        assert_eq!(page.error, None);
+        assert!(page.selected.is_none());
+        assert!(!page.networks.iter().any(|n| n.name == "renamed"));
+
+        // Events from elsewhere show up in the list
+        let _ = page.update(Message::Add);
+        let _ = page.update(Message::CreateNet(CreateNetMsg::NameChanged(
+            "newnet".into(),
+        )));
+        let _ = page.update(Message::CreateNet(CreateNetMsg::Finish));
+        assert!(page.create.is_none());
+        assert_eq!(page.selected.as_deref(), Some("newnet"));
+        assert!(page.is_active());
+        conn.destroy_network("newnet").unwrap();
+        let event = ConnEvent::Lifecycle(ObjectKind::Network, "newnet".into(), Lifecycle::Stopped);
+        let _ = page.update(Message::Event(event));
+        assert!(!page.is_active());
+    }
+}
@@ -0,0 +1,881 @@
+// Storage page of the connection details (Iced port of
+// virtManager/hoststorage.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Lists the connection's storage pools with how full they are. The
+//! selected pool shows its sizes, location, XML and volumes, and can be
+//! started, stopped, refreshed, deleted, renamed while inactive, and set
+//! to start on boot. Volumes are listed with the domains using them, and
+//! can be created and deleted. Add opens the new pool wizard in place of
+//! the details. The storage browser embeds the page with the pool
+//! settings hidden and volumes to choose from instead.
+
+use std::sync::Arc;
+
+use iced::widget::{
+    Column, Space, button, checkbox, column, container, progress_bar, row, scrollable, text,
+    text_editor, text_input,
+};
+use iced::{Alignment, Element, Length, Task};
+use log::debug;
+
+use crate::cli::parsers::disk_source_path;
+use crate::connection::{ConnEvent, Connection, ObjectInfo, ObjectKind, uri_is_remote};
+use crate::createpool::{CreatePool, Message as CreatePoolMsg};
+use crate::createvol::{CreateVolume, Message as CreateVolMsg};
+use crate::storage::{StoragePool, StorageVolume, pretty_bytes, pretty_pool_type};
+use crate::xmlapi::{Element as XmlElement, validate_generic_name};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Refresh,
+    Select(String),
+    NameChanged(String),
+    AutostartToggle(bool),
+    ShowXml(bool),
+    XmlEdited(text_editor::Action),
+    Apply,
+    Start,
+    Stop,
+    Delete,
+    DeleteConfirmed(bool),
+    Add,
+    CreatePool(CreatePoolMsg),
+    SelectVolume(String),
+    RefreshPool,
+    AddVolume,
+    CreateVol(CreateVolMsg),
+    DeleteVolume,
+    DeleteVolumeConfirmed(bool),
+    Event(ConnEvent),
+    /// Handled by the storage browser
+    BrowseLocal,
+    /// Handled by the storage browser
+    BrowseCancel,
+    /// Handled by the storage browser, through `chosen_volume`
+    ChooseVolume,
+}
+
+/// Unapplied changes of the selected pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Name,
+    Autostart,
+    Xml,
+}
+
+/// How the storage browser embeds the page
+#[derive(Debug, Clone, Default)]
+pub struct BrowseOptions {
+    /// Name hint for the new volume wizard, like the VM's name
+    pub name_hint: Option<String>,
+    pub enable_create: bool,
+    /// The only volume format that can be chosen, if any
+    pub format: Option<&'static str>,
+}
+
+/// A pool in the list, with its sizes for the capacity bar
+#[derive(Debug, Clone)]
+pub struct PoolRow {
+    pub info: ObjectInfo,
+    pub ptype: String,
+    pub capacity: u64,
+    pub allocation: u64,
+}
+
+impl PoolRow {
+    /// Percentage of the pool in use
+    pub fn percent(&self) -> f32 {
+        if self.capacity == 0 {
+            return 0.0;
+        }
+        self.allocation as f32 * 100.0 / self.capacity as f32
+    }
+}
+
+/// A row of the volume list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeRow {
+    pub name: String,
+    pub path: Option<String>,
+    pub capacity: u64,
+    pub format: Option<String>,
+    /// Names of the domains with a disk on this volume
+    pub used_by: Vec<String>,
+}
+
+/// Domain names by the disk source paths they use
+pub fn disk_users(conn: &dyn Connection) -> Vec<(String, String)> {
+    let mut users = vec![];
+    for dom in conn.list_domains().unwrap_or_default() {
+        let Ok(xml) = conn.domain_xml(&dom.name, false) else {
+            continue;
+        };
+        let Ok(root) = XmlElement::parse(&xml) else {
+            continue;
+        };
+        for disk in root.find_all("./devices/disk") {
+            if let Some(path) = disk_source_path(disk) {
+                users.push((path, dom.name.clone()));
+            }
+        }
+    }
+    users
+}
+
+/// The volumes of `pool` sorted by name, with the domains using them
+pub fn list_volume_rows(conn: &dyn Connection, pool: &str) -> Result<Vec<VolumeRow>, String> {
+    let users = disk_users(conn);
+    let mut rows = vec![];
+    for name in conn.list_volumes(pool)? {
+        let vol = StorageVolume::parse(&conn.volume_xml(pool, &name)?)?;
+        let path = vol.target_path().map(str::to_string);
+        let used_by = users
+            .iter()
+            .filter(|(p, _)| Some(p) == path.as_ref())
+            .map(|(_, dom)| dom.clone())
+            .collect();
+        rows.push(VolumeRow {
+            name,
+            capacity: vol.capacity(),
+            format: vol.format().map(str::to_string),
+            path,
+            used_by,
+        });
+    }
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(rows)
+}
+
+pub struct HostStoragePage {
+    conn: Arc<dyn Connection>,
+    pools: Vec<PoolRow>,
+    selected: Option<String>,
+    pool: Option<StoragePool>,
+    name: String,
+    autostart: bool,
+    volumes: Result<Vec<VolumeRow>, String>,
+    selected_vol: Option<String>,
+    show_xml: bool,
+    xml_content: text_editor::Content,
+    edits: Vec<Edit>,
+    /// The new pool wizard, while open
+    create: Option<CreatePool>,
+    /// The new volume wizard, while open
+    create_vol: Option<CreateVolume>,
+    confirm_delete: bool,
+    confirm_delete_vol: bool,
+    /// Set when embedded in the storage browser
+    browse: Option<BrowseOptions>,
+    /// Set when the list can't be read, replacing the page
+    list_error: Option<String>,
+    error: Option<String>,
+}
+
+impl HostStoragePage {
+    pub fn new(conn: Arc<dyn Connection>) -> Self {
+        let mut page = Self {
+            conn,
+            pools: vec![],
+            selected: None,
+            pool: None,
+            name: String::new(),
+            autostart: false,
+            volumes: Ok(vec![]),
+            selected_vol: None,
+            show_xml: false,
+            xml_content: text_editor::Content::new(),
+            edits: vec![],
+            create: None,
+            create_vol: None,
+            confirm_delete: false,
+            confirm_delete_vol: false,
+            browse: None,
+            list_error: None,
+            error: None,
+        };
+        page.refresh();
+        page
+    }
+
+    /// Switch the page to choosing volumes for the storage browser
+    pub fn set_browse(&mut self, opts: BrowseOptions) {
+        self.browse = Some(opts);
+    }
+
+    fn vol_sensitive(&self, vol: &VolumeRow) -> bool {
+        match self.browse.as_ref().and_then(|b| b.format) {
+            Some(fmt) => vol.format.as_deref() == Some(fmt),
+            None => true,
+        }
+    }
+
+    /// The selected volume, if it can be chosen
+    pub fn chosen_volume(&self) -> Option<&VolumeRow> {
+        let name = self.selected_vol.as_ref()?;
+        let vols = self.volumes.as_ref().ok()?;
+        vols.iter()
+            .find(|v| &v.name == name)
+            .filter(|v| self.vol_sensitive(v))
+    }
+
+    fn is_active(&self) -> bool {
+        let name = self.selected.as_deref();
+        self.pools
+            .iter()
+            .any(|p| Some(p.info.name.as_str()) == name && p.info.active)
+    }
+
+    fn pool_row(conn: &dyn Connection, info: ObjectInfo) -> PoolRow {
+        let pool = conn
+            .pool_xml(&info.name, false)
+            .and_then(|xml| StoragePool::parse(&xml));
+        match pool {
+            Ok(pool) => PoolRow {
+                ptype: pool.ptype.clone(),
+                capacity: pool.capacity(),
+                allocation: pool.allocation(),
+                info,
+            },
+            Err(e) => {
+                debug!("Error reading pool '{}': {}", info.name, e);
+                PoolRow {
+                    info,
+                    ptype: String::new(),
+                    capacity: 0,
+                    allocation: 0,
+                }
+            }
+        }
+    }
+
+    /// Re-read the pool list, keeping the selection if it still exists.
+    /// Unapplied edits of the selection are kept too.
+    pub fn refresh(&mut self) {
+        match self.conn.list_pools() {
+            Ok(mut pools) => {
+                pools.sort_by(|a, b| a.name.cmp(&b.name));
+                self.pools = pools
+                    .into_iter()
+                    .map(|p| Self::pool_row(self.conn.as_ref(), p))
+                    .collect();
+                self.list_error = None;
+            }
+            Err(e) => {
+                debug!("Error refreshing pool list: {}", e);
+                self.pools.clear();
+                self.list_error = Some(format!("Error refreshing pool list: {}", e));
+            }
+        }
+        let exists = self
+            .pools
+            .iter()
+            .any(|p| Some(&p.info.name) == self.selected.as_ref());
+        if !exists {
+            self.selected = None;
+            self.edits.clear();
+        }
+        if self.edits.is_empty() {
+            self.load_selected();
+        }
+    }
+
+    fn load_selected(&mut self) {
+        self.edits.clear();
+        self.confirm_delete = false;
+        self.confirm_delete_vol = false;
+        self.pool = None;
+        self.volumes = Ok(vec![]);
+        let Some(name) = self.selected.clone() else {
+            return;
+        };
+        let ret = self.conn.pool_xml(&name, false).and_then(|xml| {
+            let pool = StoragePool::parse(&xml)?;
+            Ok((pool, xml, self.conn.pool_autostart(&name)?))
+        });
+        match ret {
+            Ok((pool, xml, autostart)) => {
+                self.name = pool.name.clone();
+                self.autostart = autostart;
+                self.pool = Some(pool);
+                self.xml_content = text_editor::Content::with_text(&xml);
+            }
+            Err(e) => {
+                debug!("Error selecting pool: {}", e);
+                self.error = Some(format!("Error selecting pool: {}", e));
+                return;
+            }
+        }
+        self.load_volumes();
+    }
+
+    fn load_volumes(&mut self) {
+        self.volumes = match (self.is_active(), &self.selected) {
+            (true, Some(name)) => list_volume_rows(self.conn.as_ref(), name),
+            _ => Ok(vec![]),
+        };
+        let exists = self.volumes.as_ref().is_ok_and(|vols| {
+            vols.iter()
+                .any(|v| Some(&v.name) == self.selected_vol.as_ref())
+        });
+        if !exists {
+            self.selected_vol = None;
+        }
+    }
+
+    fn add_edit(&mut self, edit: Edit) {
+        if !self.edits.contains(&edit) {
+            self.edits.push(edit);
+        }
+    }
+
+    /// Redefine the pool under a new name, restoring it if that fails
+    fn rename(&self, oldname: &str, newname: &str) -> Result<(), String> {
+        let origxml = self.conn.pool_xml(oldname, true)?;
+        let mut xml = XmlElement::parse(&origxml)?;
+        xml.set("./name", Some(newname));
+        xml.set("./uuid", None);
+        let newxml = xml.get_xml();
+        let autostart = self.conn.pool_autostart(oldname)?;
+
+        debug!("Changing pool name from {} to {}", oldname, newname);
+        self.conn.undefine_pool(oldname)?;
+        if let Err(e) = self.conn.define_pool(&newxml) {
+            debug!("Error defining new name pool XML: {}", e);
+            if let Err(fixerr) = self.conn.define_pool(&origxml) {
+                return Err(format!(
+                    "pool rename failed. Attempting to recover also failed.\n\n\
+                     Original error: {}\n\nRecover error: {}",
+                    e, fixerr
+                ));
+            }
+            return Err(e);
+        }
+        self.conn.set_pool_autostart(newname, autostart)
+    }
+
+    fn apply(&mut self) -> Result<(), String> {
+        let Some(name) = self.selected.clone() else {
+            return Ok(());
+        };
+        debug!("Applying changes for pool '{}'", name);
+        let edits = std::mem::take(&mut self.edits);
+        let ret = (|| {
+            if edits.contains(&Edit::Autostart) {
+                self.conn.set_pool_autostart(&name, self.autostart)?;
+            }
+            if edits.contains(&Edit::Name) && self.name != name {
+                validate_generic_name("Storage object", &self.name)?;
+                self.rename(&name, &self.name)?;
+                self.selected = Some(self.name.clone());
+            }
+            if edits.contains(&Edit::Xml) {
+                let info = self.conn.define_pool(&self.xml_content.text())?;
+                self.selected = Some(info.name);
+            }
+            Ok(())
+        })();
+        self.refresh();
+        ret.map_err(|e: String| format!("Error changing pool settings: {}", e))
+    }
+
+    /// Start, stop, refresh or delete the selected pool through `action`
+    fn lifecycle(
+        &mut self,
+        verb: &str,
+        action: fn(&dyn Connection, &str) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let Some(name) = self.selected.clone() else {
+            return Ok(());
+        };
+        let ret = action(self.conn.as_ref(), &name);
+        self.edits.clear();
+        self.refresh();
+        ret.map_err(|e| format!("Error {} pool '{}': {}", verb, name, e))
+    }
+
+    fn delete_volume(&mut self) -> Result<(), String> {
+        let (Some(pool), Some(vol)) = (self.selected.clone(), self.selected_vol.take()) else {
+            return Ok(());
+        };
+        debug!("Deleting volume '{}' of pool '{}'", vol, pool);
+        let ret = self.conn.delete_volume(&pool, &vol);
+        self.refresh();
+        ret.map_err(|e| format!("Error deleting volume: {}", e))
+    }
+
+    fn report(&mut self, ret: Result<(), String>) {
+        self.error = ret.err();
+    }
+
+    pub fn update(&mut self, msg: Message) -> Task<Message> {
+        match msg {
+            Message::Refresh => {
+                self.error = None;
+                self.edits.clear();
+                self.refresh();
+            }
+            Message::Select(name) => {
+                self.error = None;
+                self.create = None;
+                self.create_vol = None;
+                self.selected = Some(name);
+                self.selected_vol = None;
+                self.load_selected();
+            }
+            Message::NameChanged(v) => {
+                self.name = v;
+                self.add_edit(Edit::Name);
+            }
+            Message::AutostartToggle(v) => {
+                self.autostart = v;
+                self.add_edit(Edit::Autostart);
+            }
+            Message::ShowXml(v) => self.show_xml = v,
+            Message::XmlEdited(action) => {
+                let edit = action.is_edit();
+                self.xml_content.perform(action);
+                if edit {
+                    self.add_edit(Edit::Xml);
+                }
+            }
+            Message::Apply => {
+                let ret = self.apply();
+                self.report(ret);
+            }
+            Message::Start => {
+                debug!("Starting pool {:?}", self.selected);
+                let ret = self.lifecycle("starting", |c, n| c.start_pool(n));
+                self.report(ret);
+            }
+            Message::Stop => {
+                debug!("Stopping pool {:?}", self.selected);
+                let ret = self.lifecycle("stopping", |c, n| c.destroy_pool(n));
+                self.report(ret);
+            }
+            Message::RefreshPool => {
+                debug!("Refreshing pool {:?}", self.selected);
+                let ret = self.lifecycle("refreshing", |c, n| c.refresh_pool(n));
+                self.report(ret);
+            }
+            Message::Delete => self.confirm_delete = true,
+            Message::DeleteConfirmed(yes) => {
+                self.confirm_delete = false;
+                if yes {
+                    debug!("Deleting pool {:?}", self.selected);
+                    let ret = self.lifecycle("deleting", |c, n| c.undefine_pool(n));
+                    self.report(ret);
+                }
+            }
+            Message::Add => {
+                debug!("Launching 'Add Pool'");
+                self.confirm_delete = false;
+                self.create_vol = None;
+                match CreatePool::new(self.conn.clone()) {
+                    Ok(wizard) => self.create = Some(wizard),
+                    Err(e) => self.error = Some(format!("Error launching pool wizard: {}", e)),
+                }
+            }
+            Message::CreatePool(CreatePoolMsg::Cancel) => self.create = None,
+            Message::CreatePool(CreatePoolMsg::Finish) => {
+                let created = self.create.as_mut().and_then(CreatePool::finish);
+                if let Some(name) = created {
+                    self.create = None;
+                    self.error = None;
+                    self.selected = Some(name);
+                    self.edits.clear();
+                    self.refresh();
+                }
+            }
+            Message::CreatePool(inner) => {
+                if let Some(wizard) = &mut self.create {
+                    wizard.update(inner);
+                }
+            }
+            Message::SelectVolume(name) => {
+                self.confirm_delete_vol = false;
+                self.selected_vol = Some(name);
+            }
+            Message::AddVolume => {
+                let Some(pool) = self.selected.clone() else {
+                    return Task::none();
+                };
+                debug!("Launching 'Add Volume' for pool '{}'", pool);
+                let hint = self.browse.as_ref().and_then(|b| b.name_hint.as_deref());
+                match CreateVolume::new(self.conn.clone(), &pool, hint) {
+                    Ok(wizard) => self.create_vol = Some(wizard),
+                    Err(e) => self.error = Some(format!("Error launching volume wizard: {}", e)),
+                }
+            }
+            Message::CreateVol(CreateVolMsg::Cancel) => self.create_vol = None,
+            Message::CreateVol(CreateVolMsg::Finish) => {
+                let created = self.create_vol.as_mut().and_then(CreateVolume::finish);
+                if let Some(name) = created {
+                    self.create_vol = None;
+                    self.error = None;
+                    self.selected_vol = Some(name);
+                    self.edits.clear();
+                    self.refresh();
+                }
+            }
+            Message::CreateVol(inner) => {
+                if let Some(wizard) = &mut self.create_vol {
+                    wizard.update(inner);
+                }
+            }
+            Message::DeleteVolume => self.confirm_delete_vol = true,
+            Message::DeleteVolumeConfirmed(yes) => {
+                self.confirm_delete_vol = false;
+                if yes {
+                    let ret = self.delete_volume();
+                    self.report(ret);
+                }
+            }
+            Message::Event(event) => {
+                if event.kind() == ObjectKind::Pool {
+                    self.refresh();
+                }
+            }
+            Message::BrowseLocal | Message::BrowseCancel | Message::ChooseVolume => {}
+        }
+        Task::none()
+    }
+
+    fn labeled<'a>(
+        label: &'a str,
+        widget: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        row![text(label).width(Length::Fixed(120.0)), widget.into()]
+            .spacing(8)
+            .align_y(Alignment::Center)
+            .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        if let Some(err) = &self.list_error {
+            return column![
+                text(err.clone()),
+                button(text("Refresh")).on_press(Message::Refresh),
+            ]
+            .spacing(10)
+            .into();
+        }
+        let body = match (&self.create, &self.create_vol) {
+            (Some(wizard), _) => wizard.view().map(Message::CreatePool),
+            (None, Some(wizard)) => wizard.view().map(Message::CreateVol),
+            (None, None) => self.view_pool(),
+        };
+        let mut page: Column<Message> = column![body].spacing(10);
+        if let Some(err) = &self.error {
+            page = page.push(text(err.clone()).size(14));
+        }
+        let content = row![
+            self.view_list(),
+            container(scrollable(page)).width(Length::Fill).padding(8),
+        ]
+        .spacing(16)
+        .height(Length::Fill);
+        if self.browse.is_none() {
+            return content.into();
+        }
+        let local = !uri_is_remote(self.conn.uri());
+        let can_choose = self.create_vol.is_none() && self.chosen_volume().is_some();
+        let mut browse_local = button(text("Browse Local"));
+        if local {
+            browse_local = browse_local.on_press(Message::BrowseLocal);
+        }
+        let mut footer = row![browse_local].spacing(8).align_y(Alignment::Center);
+        if !local {
+            footer = footer.push(text("Cannot use local storage on remote connection.").size(12));
+        }
+        let footer = footer.push(Space::with_width(Length::Fill)).extend([
+            button(text("Cancel"))
+                .on_press(Message::BrowseCancel)
+                .into(),
+            button(text("Choose Volume"))
+                .on_press_maybe(can_choose.then_some(Message::ChooseVolume))
+                .into(),
+        ]);
+        column![content, footer].spacing(10).into()
+    }
+
+    fn view_list(&self) -> Element<'_, Message> {
+        let mut list: Column<Message> = column![].spacing(4);
+        for pool in &self.pools {
+            let style = if self.selected.as_ref() == Some(&pool.info.name) {
+                button::primary
+            } else {
+                button::text
+            };
+            let label = format!("{}\n{}", pool.info.name, pretty_pool_type(&pool.ptype));
+            let usage: Element<Message> = if pool.info.active {
+                row![
+                    progress_bar(0.0..=100.0, pool.percent())
+                        .height(Length::Fixed(8.0))
+                        .width(Length::Fixed(80.0)),
+                    text(format!("{:.0}%", pool.percent())).size(12),
+                ]
+                .spacing(6)
+                .align_y(Alignment::Center)
+                .into()
+            } else {
+                text("Inactive").size(12).into()
+            };
+            list = list.push(
+                button(
+                    row![text(label).width(Length::Fill), usage]
+                        .spacing(8)
+                        .align_y(Alignment::Center),
+                )
+                .style(style)
+                .padding(6)
+                .on_press(Message::Select(pool.info.name.clone())),
+            );
+        }
+        if self.pools.is_empty() {
+            list = list.push(text("No storage pools"));
+        }
+        let selected = self.pool.is_some() && self.create.is_none();
+        let active = self.is_active();
+        let actions = row![
+            button(text("Add")).on_press(Message::Add),
+            button(text("Start")).on_press_maybe((selected && !active).then_some(Message::Start)),
+            button(text("Stop")).on_press_maybe((selected && active).then_some(Message::Stop)),
+            button(text("Delete")).on_press_maybe((selected && !active).then_some(Message::Delete)),
+            button(text("Refresh")).on_press(Message::Refresh),
+        ]
+        .spacing(6);
+        column![scrollable(list).height(Length::Fill), actions]
+            .spacing(10)
+            .width(Length::Fixed(280.0))
+            .into()
+    }
+
+    fn view_volumes(&self, pool: &StoragePool) -> Element<'_, Message> {
+        let active = self.is_active();
+        let can_create = active
+            && pool.supports_volume_creation(false)
+            && self.browse.as_ref().is_none_or(|b| b.enable_create);
+        let can_delete = active && self.selected_vol.is_some();
+        let mut col: Column<Message> = column![
+            row![
+                text("Volumes").size(16).width(Length::Fill),
+                button(text("Add volume")).on_press_maybe(can_create.then_some(Message::AddVolume)),
+                button(text("Delete volume"))
+                    .on_press_maybe(can_delete.then_some(Message::DeleteVolume)),
+                button(text("Refresh")).on_press_maybe(active.then_some(Message::RefreshPool)),
+            ]
+            .spacing(6)
+            .align_y(Alignment::Center)
+        ]
+        .spacing(4);
+        let vols = match &self.volumes {
+            Ok(vols) => vols,
+            Err(e) => return col.push(text(format!("Error: {}", e))).into(),
+        };
+        let cell = |s: String, width: f32| text(s).size(13).width(Length::Fixed(width));
+        col = col.push(row![
+            cell("Volumes".into(), 200.0),
+            cell("Size".into(), 100.0),
+            cell("Format".into(), 80.0),
+            cell("Used By".into(), 160.0),
+        ]);
+        for vol in vols {
+            let style = if self.selected_vol.as_ref() == Some(&vol.name) {
+                button::primary
+            } else {
+                button::text
+            };
+            let sensitive = self.vol_sensitive(vol);
+            col = col.push(
+                button(row![
+                    cell(vol.name.clone(), 200.0),
+                    cell(pretty_bytes(vol.capacity), 100.0),
+                    cell(vol.format.clone().unwrap_or_default(), 80.0),
+                    cell(vol.used_by.join(", "), 160.0),
+                ])
+                .style(style)
+                .padding(2)
+                .on_press_maybe(sensitive.then(|| Message::SelectVolume(vol.name.clone()))),
+            );
+        }
+        if let (true, Some(vol)) = (self.confirm_delete_vol, &self.selected_vol) {
+            col = col.push(
+                row![
+                    text(format!(
+                        "Are you sure you want to permanently delete the volume {}?",
+                        vol
+                    ))
+                    .width(Length::Fill),
+                    button(text("Delete")).on_press(Message::DeleteVolumeConfirmed(true)),
+                    button(text("Cancel")).on_press(Message::DeleteVolumeConfirmed(false)),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+        col.into()
+    }
+
+    fn view_pool(&self) -> Element<'_, Message> {
+        let Some(pool) = &self.pool else {
+            return text("No storage pool selected.").into();
+        };
+        let active = self.is_active();
+        let tab = |label, xml: bool| {
+            let style = if self.show_xml == xml {
+                button::primary
+            } else {
+                button::text
+            };
+            button(text(label))
+                .style(style)
+                .on_press(Message::ShowXml(xml))
+        };
+        // The storage browser only shows what helps to pick a volume
+        let browse = self.browse.is_some();
+        let mut col: Column<Message> = column![].spacing(10);
+        if !browse {
+            col = col.push(row![tab("Details", false), tab("XML", true)].spacing(4));
+        }
+        if self.show_xml && !browse {
+            col = col.push(
+                text_editor(&self.xml_content)
+                    .on_action(Message::XmlEdited)
+                    .height(Length::Fixed(420.0)),
+            );
+        } else {
+            if !browse {
+                let mut name = text_input("", &self.name).padding(6);
+                if !active {
+                    name = name.on_input(Message::NameChanged);
+                }
+                col = col.push(Self::labeled("Name:", name));
+            }
+            col = col.push(Self::labeled(
+                "Size:",
+                text(format!(
+                    "{} Free / {} In Use",
+                    pretty_bytes(pool.available()),
+                    pretty_bytes(pool.allocation())
+                )),
+            ));
+            col = col.push(Self::labeled(
+                "Location:",
+                text(pool.target_path().unwrap_or_default().to_string()),
+            ));
+            if !browse {
+                let state = if active { "Active" } else { "Inactive" };
+                col = col.push(Self::labeled("State:", text(state)));
+                col = col.push(Self::labeled(
+                    "Autostart:",
+                    checkbox("On Boot", self.autostart).on_toggle(Message::AutostartToggle),
+                ));
+            }
+            col = col.push(self.view_volumes(pool));
+        }
+        if self.confirm_delete {
+            col = col.push(
+                row![
+                    text(format!(
+                        "Are you sure you want to permanently delete the pool {}?",
+                        pool.name
+                    ))
+                    .width(Length::Fill),
+                    button(text("Delete")).on_press(Message::DeleteConfirmed(true)),
+                    button(text("Cancel")).on_press(Message::DeleteConfirmed(false)),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+        if browse {
+            return col.into();
+        }
+        col.push(row![
+            Space::with_width(Length::Fill),
+            button(text("Apply"))
+                .on_press_maybe((!self.edits.is_empty()).then_some(Message::Apply)),
+        ])
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{Lifecycle, testdriver};
+
+    fn open() -> (Arc<dyn Connection>, HostStoragePage) {
+        let conn: Arc<dyn Connection> = Arc::new(testdriver());
+        (conn.clone(), HostStoragePage::new(conn))
+    }
+
+    #[test]
+    fn test_hoststorage_all_pools_parse() {
+        let (conn, mut page) = open();
+        assert!(
+            page.pools
+                .windows(2)
+                .all(|w| w[0].info.name <= w[1].info.name)
+        );
+        for pool in conn.list_pools().unwrap() {
+            let _ = page.update(Message::Select(pool.name.clone()));
+            assert!(page.pool.is_some(), "{}: {:?}", pool.name, page.error);
+            assert!(page.volumes.is_ok(), "{}: {:?}", pool.name, page.volumes);
+        }
+        let _ = page.update(Message::Select("pool-dir".into()));
+        let vols = page.volumes.as_ref().unwrap();
+        assert!(!vols.is_empty());
+        assert!(vols.iter().any(|v| !v.used_by.is_empty()));
+    }
+
+    #[test]
+    fn test_hoststorage_actions() {
+        let (conn, mut page) = open();
+        let _ = page.update(Message::Select("pool-dir".into()));
+        let _ = page.update(Message::AddVolume);
+        let _ = page.update(Message::CreateVol(CreateVolMsg::NameChanged(
+            "newvol".into(),
+        )));
+        let _ = page.update(Message::CreateVol(CreateVolMsg::Finish));
+        assert_eq!(page.error, None);
+        assert!(page.create_vol.is_none());
+        assert_eq!(page.selected_vol.as_deref(), Some("newvol.qcow2"));
+        let _ = page.update(Message::DeleteVolume);
+        let _ = page.update(Message::DeleteVolumeConfirmed(true));
+        assert_eq!(page.error, None);
+        assert!(conn.volume_xml("pool-dir", "newvol.qcow2").is_err());
+
+        let _ = page.update(Message::Stop);
+        assert_eq!(page.error, None);
+        assert!(!page.is_active());
+        assert_eq!(page.volumes, Ok(vec![]));
+        let _ = page.update(Message::AutostartToggle(true));
+        let _ = page.update(Message::NameChanged("renamed".into()));
+        let _ = page.update(Message::Apply);
+        assert_eq!(page.error, None);
+        assert_eq!(page.selected.as_deref(), Some("renamed"));
+        assert!(conn.pool_autostart("renamed").unwrap());
+        let _ = page.update(Message::Delete);
+        let _ = page.update(Message::DeleteConfirmed(true));
+        assert_eq!(page.error, None);
+        assert!(page.selected.is_none());
+
+        // Events from elsewhere show up in the list
+        let _ = page.update(Message::Add);
+        let _ = page.update(Message::CreatePool(CreatePoolMsg::NameChanged(
+            "newpool".into(),
+        )));
+        let _ = page.update(Message::CreatePool(CreatePoolMsg::Finish));
+        assert_eq!(page.error, None);
+        assert_eq!(page.selected.as_deref(), Some("newpool"));
+        assert!(page.is_active());
+        conn.destroy_pool("newpool").unwrap();
+        let event = ConnEvent::Lifecycle(ObjectKind::Pool, "newpool".into(), Lifecycle::Stopped);
+        let _ = page.update(Message::Event(event));
+        assert!(!page.is_active());
+    }
+}
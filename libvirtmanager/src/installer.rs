@@ -0,0 +1,823 @@
+// VM install orchestration (port of virtinst/install/installer.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! An `Installer` turns a fully configured `Guest` into the XML for the
+//! install boot and the XML for every boot after it, then creates the
+//! domain. Install media like a cdrom, a `--location` kernel/initrd, a
+//! cloud-init seed or unattended install scripts is used for the first
+//! boot only.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cloudinit::CloudInitData;
+use crate::connection::{Connection, DomainState, get_app_cache_dir};
+use crate::diskbackend::StorageCreator;
+use crate::guest::Guest;
+use crate::installerinject::perform_cdrom_injections;
+use crate::installertreemedia::InstallerTreeMedia;
+use crate::osdict::GENERIC;
+use crate::progress::Meter;
+use crate::unattended::{self, OsInstallScript, UnattendedData};
+use crate::urlfetcher::{CurlFetcher, Fetcher};
+use crate::xmlapi::Element;
+
+/// `--cdrom`, `--import` and friends, for the "no install method" error
+pub const INSTALL_METHODS: &str =
+    "--location URL, --cdrom CD/ISO, --pxe, --import, --boot hd|cdrom|...";
+
+/// Directory for generated install media that the hypervisor can read.
+/// The system one is used when writable, else a per user cache dir.
+pub fn make_scratchdir(conn: &dyn Connection) -> Result<PathBuf, String> {
+    let system = Path::new("/var/lib/libvirt/boot");
+    let session = conn.uri().ends_with("/session");
+    // SAFETY: access has no memory safety requirements
+    let writable = |p: &Path| {
+        let cpath = std::ffi::CString::new(p.as_os_str().as_encoded_bytes()).unwrap_or_default();
+        unsafe { libc::access(cpath.as_ptr(), libc::W_OK) == 0 }
+    };
+    if !session && system.is_dir() && writable(system) {
+        return Ok(system.to_path_buf());
+    }
+    let dir = get_app_cache_dir().join("boot");
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Error creating scratch dir '{}': {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+fn make_cdrom_device(path: Option<&str>) -> Element {
+    let mut dev = Element::new("disk");
+    dev.set("./@type", Some("file"));
+    dev.set("./@device", Some("cdrom"));
+    if let Some(path) = path {
+        let disktype = crate::diskbackend::local_dev_type(path);
+        dev.set("./@type", Some(disktype));
+        let prop = if disktype == "block" { "dev" } else { "file" };
+        let path = if crate::diskbackend::path_is_url(path) {
+            path.to_string()
+        } else {
+            crate::diskbackend::absolute_path(path)
+        };
+        dev.set(&format!("./source/@{}", prop), Some(&path));
+    }
+    dev
+}
+
+/// Index of the disk with target `target`
+fn disk_index(guest: &Guest, target: &str) -> Option<usize> {
+    guest
+        .devices("disk")
+        .iter()
+        .position(|d| d.get("./target/@dev").as_deref() == Some(target))
+}
+
+#[derive(Default)]
+pub struct Installer {
+    cdrom: Option<String>,
+    treemedia: Option<InstallerTreeMedia>,
+    /// (kernel, initrd, kernel args) fetched from the treemedia
+    treemedia_bootconfig: Option<(String, String, String)>,
+    install_bootdev: Option<String>,
+    no_install: bool,
+    cloudinit_data: Option<CloudInitData>,
+    unattended_data: Option<UnattendedData>,
+    scratchdir: Option<PathBuf>,
+    install_cdrom_device_added: bool,
+    unattended_cdrom_target: Option<String>,
+    tmpfiles: Vec<PathBuf>,
+    defaults_are_set: bool,
+    /// Disk storage to create before the domain
+    storage: Vec<StorageCreator>,
+    /// Downloads cloud-init configs and drivers, curl if unset
+    fetcher: Option<Arc<dyn Fetcher>>,
+}
+
+impl Installer {
+    /// `cdrom` is install media to boot from; `no_install` is for
+    /// `--import` style installs that just boot the disks.
+    pub fn new(cdrom: Option<&str>, install_bootdev: Option<&str>, no_install: bool) -> Self {
+        Self {
+            cdrom: cdrom.map(str::to_string),
+            install_bootdev: match cdrom {
+                Some(_) => Some("cdrom".to_string()),
+                None => install_bootdev.map(str::to_string),
+            },
+            no_install,
+            ..Default::default()
+        }
+    }
+
+    /// Install from `--location` media or a direct kernel boot rather
+    /// than a cdrom
+    pub fn set_treemedia(&mut self, treemedia: InstallerTreeMedia) {
+        self.cdrom = None;
+        if self.install_bootdev.as_deref() == Some("cdrom") {
+            self.install_bootdev = None;
+        }
+        self.treemedia = Some(treemedia);
+    }
+
+    pub fn cdrom(&self) -> Option<&str> {
+        self.cdrom.as_deref()
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.treemedia.as_ref().and_then(|t| t.location())
+    }
+
+    /// The media to attach as the install cdrom: `--cdrom`, or a
+    /// `--location` ISO
+    fn cdrom_path(&self) -> Option<&str> {
+        match &self.treemedia {
+            Some(treemedia) => treemedia.cdrom_path(),
+            None => self.cdrom.as_deref(),
+        }
+    }
+
+    pub fn set_initrd_injections(&mut self, injections: &[String]) -> Result<(), String> {
+        let Some(treemedia) = self.treemedia.as_mut() else {
+            return Err("Install method does not support initrd injections.".to_string());
+        };
+        treemedia.set_initrd_injections(injections);
+        Ok(())
+    }
+
+    pub fn set_extra_args(&mut self, extra_args: &[String]) -> Result<(), String> {
+        let Some(treemedia) = self.treemedia.as_mut() else {
+            return Err(
+                "Kernel arguments are only supported with location or kernel installs.".to_string(),
+            );
+        };
+        treemedia.set_extra_args(extra_args);
+        Ok(())
+    }
+
+    /// The OS variant detected from the install media, if any. Errors
+    /// when `--location` media can't be used at all.
+    pub fn detect_distro(&mut self, guest: &Guest) -> Result<Option<String>, String> {
+        let ret = match self.treemedia.as_mut() {
+            Some(treemedia) => {
+                let (arch, os_type) = guest_arch_and_type(guest);
+                treemedia.detect_distro(&arch, &os_type)?
+            }
+            None => {
+                log::debug!("No media for distro detection.");
+                None
+            }
+        };
+        log::debug!("installer.detect_distro returned={:?}", ret);
+        Ok(ret)
+    }
+
+    pub fn set_cloudinit_data(&mut self, data: CloudInitData) {
+        self.cloudinit_data = Some(data);
+    }
+
+    pub fn has_cloudinit(&self) -> bool {
+        self.cloudinit_data.is_some()
+    }
+
+    pub fn set_unattended_data(&mut self, data: UnattendedData) {
+        self.unattended_data = Some(data);
+    }
+
+    pub fn has_unattended(&self) -> bool {
+        self.unattended_data.is_some()
+    }
+
+    /// Use `dir` for generated media instead of `make_scratchdir`
+    pub fn set_fetcher(&mut self, fetcher: Arc<dyn Fetcher>) {
+        self.fetcher = Some(fetcher);
+    }
+
+    fn fetcher(&self) -> &dyn Fetcher {
+        self.fetcher.as_deref().unwrap_or(&CurlFetcher)
+    }
+
+    pub fn set_scratchdir(&mut self, dir: &Path) {
+        self.scratchdir = Some(dir.to_path_buf());
+    }
+
+    /// The generated root password, to show the user
+    pub fn get_generated_password(&mut self) -> Option<String> {
+        self.cloudinit_data
+            .as_mut()
+            .and_then(|d| d.get_password_if_generated())
+    }
+
+    /// Whether the install needs different XML for its first boot, and
+    /// so a poweroff before the final XML takes effect
+    pub fn requires_postboot_xml_changes(&self) -> bool {
+        if self.has_cloudinit() || self.has_unattended() {
+            return true;
+        }
+        if self.no_install {
+            return false;
+        }
+        self.cdrom.is_some() || self.install_bootdev.is_some() || self.treemedia.is_some()
+    }
+
+    /// Whether some explicit install option was passed in
+    pub fn options_specified(&self) -> bool {
+        self.no_install || self.requires_postboot_xml_changes()
+    }
+
+    fn can_set_guest_bootorder(guest: &Guest) -> bool {
+        !guest.is_container()
+            && guest.xml.get("./os/kernel").is_none()
+            && !guest
+                .all_devices()
+                .iter()
+                .any(|d| d.get("./boot/@order").is_some())
+    }
+
+    fn has_disk_device(guest: &Guest) -> bool {
+        guest
+            .devices("disk")
+            .iter()
+            .any(|d| d.attr("device").unwrap_or("disk") == "disk")
+    }
+
+    fn build_boot_order(guest: &Guest, bootdev: &str) -> Vec<String> {
+        let mut order = vec![bootdev.to_string()];
+        // Keep disks bootable, for installs that reboot into them
+        if Self::has_disk_device(guest) && bootdev != "hd" {
+            order.push("hd".to_string());
+        }
+        order
+    }
+
+    fn set_bootorder(guest: &mut Guest, order: &[String]) {
+        while guest.xml.find("./os/boot").is_some() {
+            guest.xml.force_remove("./os/boot");
+        }
+        for (i, dev) in order.iter().enumerate() {
+            guest
+                .xml
+                .set(&format!("./os/boot[{}]/@dev", i + 1), Some(dev));
+        }
+    }
+
+    fn get_postinstall_bootdev(&self, guest: &Guest) -> &'static str {
+        if self.cdrom.is_some() && self.no_install {
+            return "cdrom";
+        }
+        if let Some(bootdev) = &self.install_bootdev {
+            if Self::has_disk_device(guest) {
+                return "hd";
+            }
+            return match bootdev.as_str() {
+                "cdrom" => "cdrom",
+                "network" => "network",
+                "fd" => "fd",
+                _ => "hd",
+            };
+        }
+        match guest.devices("disk").first().and_then(|d| d.attr("device")) {
+            Some("cdrom") => "cdrom",
+            Some("floppy") => "fd",
+            _ => "hd",
+        }
+    }
+
+    fn add_install_cdrom_device(&mut self, guest: &mut Guest) {
+        let Some(path) = self.cdrom_path() else {
+            return;
+        };
+        if self.install_cdrom_device_added {
+            return;
+        }
+        // Go before other cdroms, so boot=cdrom picks the install media
+        let dev = make_cdrom_device(Some(path));
+        self.install_cdrom_device_added = true;
+        let first_cdrom = guest
+            .devices("disk")
+            .iter()
+            .position(|d| d.attr("device") == Some("cdrom"));
+        match first_cdrom {
+            Some(idx) => {
+                let mut disks: Vec<Element> = guest.devices("disk").into_iter().cloned().collect();
+                disks.insert(idx, dev);
+                while guest.remove_device("disk", 0).is_some() {}
+                for disk in disks {
+                    guest.add_device(disk);
+                }
+            }
+            None => {
+                guest.add_device(dev);
+            }
+        }
+    }
+
+    /// Add install media and boot order to `guest`, then fill in the
+    /// remaining defaults. Clients can call this ahead of `start_install`
+    /// to show the final config; later calls do nothing.
+    pub fn set_install_defaults(
+        &mut self,
+        conn: &dyn Connection,
+        guest: &mut Guest,
+        skip: &[&str],
+    ) -> Result<(), String> {
+        if self.defaults_are_set {
+            return Ok(());
+        }
+        self.add_install_cdrom_device(guest);
+        if guest.xml.find("./os/boot").is_none() && Self::can_set_guest_bootorder(guest) {
+            let bootdev = self.get_postinstall_bootdev(guest);
+            let order = Self::build_boot_order(guest, bootdev);
+            Self::set_bootorder(guest, &order);
+        }
+        guest.set_defaults(conn, skip)?;
+        self.defaults_are_set = true;
+        Ok(())
+    }
+
+    fn scratchdir(&self, conn: &dyn Connection) -> Result<PathBuf, String> {
+        match &self.scratchdir {
+            Some(dir) => Ok(dir.clone()),
+            None => make_scratchdir(conn),
+        }
+    }
+
+    fn add_unattended_install_cdrom_device(
+        &mut self,
+        guest: &mut Guest,
+        location: &str,
+    ) -> Result<(), String> {
+        let mut dev = make_cdrom_device(Some(location));
+        guest.set_device_defaults(&mut dev)?;
+        self.unattended_cdrom_target = dev.get("./target/@dev");
+        guest.add_device(dev);
+        Ok(())
+    }
+
+    fn prepare_cloudinit(
+        &mut self,
+        conn: &dyn Connection,
+        guest: &mut Guest,
+    ) -> Result<(), String> {
+        let scratchdir = self.scratchdir(conn)?;
+        let fetcher = self.fetcher.clone();
+        let Some(data) = self.cloudinit_data.as_mut() else {
+            return Ok(());
+        };
+        let filepairs =
+            data.create_files(&scratchdir, fetcher.as_deref().unwrap_or(&CurlFetcher))?;
+        self.tmpfiles
+            .extend(filepairs.iter().map(|(path, _)| path.clone()));
+        let iso = perform_cdrom_injections(&filepairs, &scratchdir, true)?;
+        self.tmpfiles.push(iso.clone());
+        self.add_unattended_install_cdrom_device(guest, &iso.to_string_lossy())
+    }
+
+    /// Render the unattended install scripts, for initrd injection with
+    /// `--location` installs and for a cdrom otherwise
+    fn prepare_unattended_scripts(&self, guest: &Guest) -> Result<Vec<OsInstallScript>, String> {
+        let Some(data) = &self.unattended_data else {
+            return Ok(vec![]);
+        };
+        let url = self.treemedia.as_ref().and_then(|t| t.is_network_url());
+        let injection_method = if self.treemedia.is_some() {
+            "initrd"
+        } else {
+            if !guest.osinfo().is_some_and(|o| o.is_windows()) {
+                log::warn!(
+                    "Attempting unattended method=cdrom injection for a non-windows OS. \
+                     If this doesn't work, try passing install media to --location"
+                );
+            }
+            "cdrom"
+        };
+        // Installs without local media have to fetch packages remotely
+        let installation_source = match self.cdrom_path() {
+            Some(_) => "media",
+            None => "network",
+        };
+        unattended::prepare_install_scripts(guest, data, url, installation_source, injection_method)
+    }
+
+    /// Put the scripts and any pre-installable drivers on a cdrom
+    fn prepare_unattended_data(
+        &mut self,
+        conn: &dyn Connection,
+        guest: &mut Guest,
+        scripts: &[OsInstallScript],
+        meter: &dyn Meter,
+    ) -> Result<(), String> {
+        if scripts.is_empty() {
+            return Ok(());
+        }
+        let scratchdir = self.scratchdir(conn)?;
+        let mut injections = vec![];
+        for script in scripts {
+            let path = script.write(&scratchdir)?;
+            self.tmpfiles.push(path.clone());
+            if let Some(cmdline) = script.generate_cmdline()? {
+                log::debug!("Generated unattended cmdline: {}", cmdline);
+            }
+            injections.push((path, script.get_expected_filename().to_string()));
+        }
+
+        if let Some(osinfo) = guest.osinfo() {
+            let arch = guest
+                .arch()
+                .unwrap_or_else(|| crate::guest::host_arch().to_string());
+            let locations = osinfo.get_pre_installable_drivers_location(&arch);
+            let drivers =
+                unattended::download_drivers(&locations, &scratchdir, meter, self.fetcher())?;
+            self.tmpfiles
+                .extend(drivers.iter().map(|(path, _)| path.clone()));
+            injections.extend(drivers);
+        }
+
+        let iso = perform_cdrom_injections(&injections, &scratchdir, false)?;
+        self.tmpfiles.push(iso.clone());
+        self.add_unattended_install_cdrom_device(guest, &iso.to_string_lossy())
+    }
+
+    fn prepare_treemedia(
+        &mut self,
+        conn: &dyn Connection,
+        guest: &Guest,
+        meter: &dyn Meter,
+        unattended_scripts: &[OsInstallScript],
+    ) -> Result<(), String> {
+        if self.treemedia.is_none() {
+            return Ok(());
+        }
+        let scratchdir = self.scratchdir(conn)?;
+        let (arch, os_type) = guest_arch_and_type(guest);
+        let os_name = guest.osinfo().map_or(GENERIC, |o| o.name.as_str());
+        let treemedia = self.treemedia.as_mut().expect("checked above");
+        let (kernel, initrd, kernel_args) = treemedia.prepare(
+            &arch,
+            &os_type,
+            os_name,
+            &scratchdir,
+            meter,
+            unattended_scripts,
+        )?;
+        self.treemedia_bootconfig = Some((
+            kernel.to_string_lossy().into_owned(),
+            initrd.to_string_lossy().into_owned(),
+            kernel_args,
+        ));
+        Ok(())
+    }
+
+    fn alter_treemedia_bootconfig(&self, guest: &mut Guest) {
+        let Some((kernel, initrd, kernel_args)) = &self.treemedia_bootconfig else {
+            return;
+        };
+        guest.xml.set("./os/kernel", Some(kernel));
+        guest.xml.set("./os/initrd", Some(initrd));
+        if !kernel_args.is_empty() {
+            guest.xml.set("./os/cmdline", Some(kernel_args));
+        }
+    }
+
+    fn alter_bootconfig(&self, guest: &mut Guest) {
+        guest.xml.set("./on_reboot", Some("destroy"));
+        self.alter_treemedia_bootconfig(guest);
+        let order = match &self.install_bootdev {
+            Some(bootdev) if Self::can_set_guest_bootorder(guest) => {
+                Self::build_boot_order(guest, bootdev)
+            }
+            _ => vec![],
+        };
+        Self::set_bootorder(guest, &order);
+    }
+
+    fn remove_install_cdrom_media(&self, guest: &mut Guest) {
+        let Some(path) = self
+            .cdrom_path()
+            .filter(|_| self.install_cdrom_device_added)
+        else {
+            return;
+        };
+        if guest.osinfo().is_some_and(|o| o.is_windows()) {
+            // Windows installs take several boots that need the media
+            return;
+        }
+        let idx = guest.devices("disk").iter().position(|d| {
+            d.attr("device") == Some("cdrom")
+                && crate::cli::parsers::disk_source_path(d).as_deref() == Some(path)
+        });
+        if let Some(idx) = idx {
+            remove_disk_source(guest, idx);
+        }
+    }
+
+    fn remove_unattended_install_cdrom_device(&self, guest: &mut Guest) {
+        if let Some(idx) = self
+            .unattended_cdrom_target
+            .as_deref()
+            .and_then(|t| disk_index(guest, t))
+        {
+            remove_disk_source(guest, idx);
+        }
+    }
+
+    /// The (initial, final) XML. Initial is None when the install doesn't
+    /// need a separate first boot.
+    fn build_xml(&self, guest: &Guest) -> Result<(Option<String>, String), String> {
+        let final_xml = guest.get_xml();
+        if !self.requires_postboot_xml_changes() {
+            log::debug!("Generated final_xml: \n{}", final_xml);
+            return Ok((None, final_xml));
+        }
+        let mut initial = Guest::parse(&final_xml)?;
+        self.alter_bootconfig(&mut initial);
+        if self.has_cloudinit() {
+            initial.set_smbios_serial_cloudinit();
+        }
+        initial.sort_for_build();
+
+        let mut final_guest = Guest::parse(&final_xml)?;
+        self.remove_install_cdrom_media(&mut final_guest);
+        self.remove_unattended_install_cdrom_device(&mut final_guest);
+        final_guest.sort_for_build();
+
+        let (initial_xml, final_xml) = (initial.get_xml(), final_guest.get_xml());
+        log::debug!("Generated initial_xml: \n{}", initial_xml);
+        log::debug!("Generated final_xml: \n{}", final_xml);
+        Ok((Some(initial_xml), final_xml))
+    }
+
+    fn cleanup(&mut self) {
+        if let Some(treemedia) = self.treemedia.as_mut() {
+            treemedia.cleanup();
+        }
+        for path in self.tmpfiles.drain(..) {
+            log::debug!("Removing {}", path.display());
+            if let Err(e) = std::fs::remove_file(&path) {
+                log::debug!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Create `creator`'s storage as part of the install
+    pub fn add_storage(&mut self, creator: StorageCreator) {
+        self.storage.push(creator);
+    }
+
+    pub fn storage(&self) -> &[StorageCreator] {
+        &self.storage
+    }
+
+    fn create_storage(&self, conn: &dyn Connection, meter: &dyn Meter) -> Result<(), String> {
+        for creator in &self.storage {
+            meter.start(&format!("Allocating '{}'", creator.path()), None);
+            creator.create(conn)?;
+            meter.end();
+        }
+        Ok(())
+    }
+
+    /// Generate the install XML and create the domain: the initial XML
+    /// is booted, and the final XML is defined for later boots. With
+    /// `return_xml` nothing is created and the (initial, final) XML is
+    /// returned instead.
+    pub fn start_install(
+        &mut self,
+        conn: &dyn Connection,
+        user_guest: &mut Guest,
+        meter: &dyn Meter,
+        return_xml: bool,
+    ) -> Result<(Option<String>, String), String> {
+        let name = user_guest.name().unwrap_or_default();
+        if !return_xml && conn.lookup_domain(&name).is_ok() {
+            return Err(format!("Guest name '{}' is already in use.", name));
+        }
+        self.set_install_defaults(conn, user_guest, &[])?;
+
+        // Installer changes go to a copy, leaving the user's guest intact
+        let mut guest = user_guest.clone();
+        let ret = self
+            .prepare_unattended_scripts(&guest)
+            .and_then(|scripts| {
+                if self.treemedia.is_some() {
+                    self.prepare_treemedia(conn, &guest, meter, &scripts)
+                } else {
+                    self.prepare_unattended_data(conn, &mut guest, &scripts, meter)
+                }
+            })
+            .and_then(|_| self.prepare_cloudinit(conn, &mut guest))
+            .and_then(|_| {
+                let (initial_xml, final_xml) = self.build_xml(&guest)?;
+                if return_xml {
+                    return Ok((initial_xml, final_xml));
+                }
+                self.create_storage(conn, meter)?;
+                meter.start("Creating domain...", None);
+                conn.create_xml(initial_xml.as_deref().unwrap_or(&final_xml))?;
+                conn.define_xml(&final_xml)?;
+                meter.end();
+                Ok((initial_xml, final_xml))
+            });
+        self.cleanup();
+        ret
+    }
+}
+
+/// How often to check whether the install boot has shut off
+pub const INSTALL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Wait for the install boot of `name` to shut off, checking every
+/// `interval`. A crashed or vanished domain is an error.
+pub fn wait_for_install(
+    conn: &dyn Connection,
+    name: &str,
+    interval: Duration,
+) -> Result<(), String> {
+    loop {
+        let info = conn
+            .lookup_domain(name)
+            .map_err(|e| format!("VM disappeared unexpectedly: {}", e))?;
+        if info.state == DomainState::Crashed {
+            return Err("Domain has crashed.".to_string());
+        }
+        if !info.state.is_active() {
+            return Ok(());
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Boot the installed guest with its final XML, once the install boot
+/// has shut off
+pub fn restart_installed(conn: &dyn Connection, name: &str, final_xml: &str) -> Result<(), String> {
+    log::debug!("Install should be completed, starting VM '{}'", name);
+    conn.define_xml(final_xml)?;
+    conn.start_domain(name)
+}
+
+/// The guest's (arch, os type), like ("x86_64", "hvm")
+fn guest_arch_and_type(guest: &Guest) -> (String, String) {
+    let arch = guest
+        .xml
+        .get("./os/type/@arch")
+        .unwrap_or_else(|| crate::guest::host_arch().to_string());
+    let os_type = guest
+        .xml
+        .get("./os/type")
+        .unwrap_or_else(|| "hvm".to_string());
+    (arch, os_type)
+}
+
+/// Empty a disk, leaving the device in place
+fn remove_disk_source(guest: &mut Guest, idx: usize) {
+    let xpath = Guest::device_xpath("disk", idx);
+    guest.xml.force_remove(&format!("{}/source", xpath));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+    use crate::iso9660::IsoImage;
+
+    #[test]
+    fn test_cloudinit_install_xml() {
+        let conn = testdriver();
+        let scratch = tempfile::tempdir().unwrap();
+        let mut guest = Guest::parse(
+            "<domain>\n  <name>cloudvm</name>\n  <memory>65536</memory>\n  <devices>\n    <disk type=\"file\" device=\"disk\">\n      <source file=\"/pool-dir/testvol1.img\"/>\n    </disk>\n  </devices>\n</domain>",
+        )
+        .unwrap();
+        let mut installer = Installer::new(None, None, true);
+        installer.set_scratchdir(scratch.path());
+        installer.set_cloudinit_data(CloudInitData {
+            root_password_generate: Some(true),
+            disable: Some(true),
+            ..Default::default()
+        });
+        assert!(installer.requires_postboot_xml_changes());
+        installer
+            .set_install_defaults(&conn, &mut guest, &[])
+            .unwrap();
+
+        // Check the seed before start_install cleans it up
+        let mut copy = guest.clone();
+        installer.prepare_cloudinit(&conn, &mut copy).unwrap();
+        let iso = copy
+            .devices("disk")
+            .iter()
+            .find_map(|d| {
+                d.get("./source/@file")
+                    .filter(|p| p.ends_with("-cloudinit.iso"))
+            })
+            .unwrap();
+        let image = IsoImage::open(&iso).unwrap();
+        assert_eq!(image.volume_id(), "cidata");
+        let userdata = String::from_utf8(image.read_file("user-data").unwrap()).unwrap();
+        let password = installer.get_generated_password().unwrap();
+        assert!(userdata.contains(&format!("root:{}\n", password)));
+        assert!(!image.exists("meta-data"));
+        installer.cleanup();
+        assert_eq!(std::fs::read_dir(scratch.path()).unwrap().count(), 0);
+
+        let (initial, final_xml) = installer
+            .start_install(&conn, &mut guest, &crate::progress::NullMeter, true)
+            .unwrap();
+        let initial = Guest::parse(&initial.unwrap()).unwrap();
+        assert_eq!(initial.xml.get("./on_reboot").as_deref(), Some("destroy"));
+        assert_eq!(
+            initial
+                .xml
+                .get("./sysinfo/system/entry[@name='serial']")
+                .as_deref(),
+            Some("ds=nocloud")
+        );
+        assert_eq!(
+            initial.xml.get("./os/smbios/@mode").as_deref(),
+            Some("sysinfo")
+        );
+        let final_guest = Guest::parse(&final_xml).unwrap();
+        assert_eq!(final_guest.xml.get("./os/boot/@dev").as_deref(), Some("hd"));
+        let cdrom = final_guest.devices("disk")[1];
+        assert_eq!(cdrom.attr("device"), Some("cdrom"));
+        assert!(cdrom.find("./source").is_none());
+        assert_eq!(std::fs::read_dir(scratch.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_unattended_windows_xml() {
+        let conn = testdriver();
+        let scratch = tempfile::tempdir().unwrap();
+        let data = format!("{}/../tests/data", env!("CARGO_MANIFEST_DIR"));
+        let mut guest = Guest::parse(
+            "<domain>\n  <name>winvm</name>\n  <memory>65536</memory>\n  <os>\n    <type arch=\"i686\">hvm</type>\n  </os>\n</domain>",
+        )
+        .unwrap();
+        guest.set_os_name("win7").unwrap();
+        let cdrom = format!("{}/fakemedia/fake-win7.iso", data);
+        let mut installer = Installer::new(Some(&cdrom), None, false);
+        installer.set_scratchdir(scratch.path());
+        installer.set_unattended_data(UnattendedData {
+            admin_password_file: Some(format!("{}/cli/unattended/admin-password.txt", data)),
+            ..Default::default()
+        });
+        let (initial, final_xml) = installer
+            .start_install(&conn, &mut guest, &crate::progress::NullMeter, true)
+            .unwrap();
+        let initial = Guest::parse(&initial.unwrap()).unwrap();
+        assert_eq!(initial.xml.get("./on_reboot").as_deref(), Some("destroy"));
+        let disks = initial.devices("disk");
+        assert_eq!(disks.len(), 2);
+        assert!(
+            disks[1]
+                .get("./source/@file")
+                .unwrap()
+                .ends_with("-unattended.iso")
+        );
+
+        // Windows keeps its install media for the later install stages
+        let final_guest = Guest::parse(&final_xml).unwrap();
+        let disks = final_guest.devices("disk");
+        assert_eq!(disks[0].get("./source/@file"), Some(cdrom));
+        assert!(disks[1].find("./source").is_none());
+        assert_eq!(std::fs::read_dir(scratch.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_restart_after_install() {
+        let conn = testdriver();
+        let mut guest = Guest::parse(
+            "<domain type=\"test\">\n  <name>instvm</name>\n  <memory>65536</memory>\n  <os>\n    <type>hvm</type>\n  </os>\n</domain>",
+        )
+        .unwrap();
+        let mut installer = Installer::new(Some("/dev/null"), None, false);
+        assert!(installer.requires_postboot_xml_changes());
+        let (_, final_xml) = installer
+            .start_install(&conn, &mut guest, &crate::progress::NullMeter, false)
+            .unwrap();
+        let live = Guest::parse(&conn.domain_xml("instvm", false).unwrap()).unwrap();
+        assert_eq!(live.xml.get("./on_reboot").as_deref(), Some("destroy"));
+        assert!(live.xml.find("./devices/disk/source").is_some());
+
+        // The install boot powering off is what lets the wait finish
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(50));
+                conn.destroy_domain("instvm").unwrap();
+            });
+            wait_for_install(&conn, "instvm", Duration::from_millis(10)).unwrap();
+        });
+        assert!(!conn.lookup_domain("instvm").unwrap().state.is_active());
+        restart_installed(&conn, "instvm", &final_xml).unwrap();
+        assert!(conn.lookup_domain("instvm").unwrap().state.is_active());
+        let live = Guest::parse(&conn.domain_xml("instvm", false).unwrap()).unwrap();
+        assert_eq!(live.xml.get("./on_reboot"), None);
+        assert!(live.xml.find("./devices/disk/source").is_none());
+        assert_eq!(conn.domain_xml("instvm", true).unwrap(), final_xml);
+
+        assert_eq!(
+            wait_for_install(&conn, "idontexist", Duration::from_millis(10)),
+            Err("VM disappeared unexpectedly: Domain not found: no domain with matching name 'idontexist'".to_string())
+        );
+    }
+}
@@ -0,0 +1,191 @@
+// Injecting files into install media (port of
+// virtinst/install/installerinject.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Generated cdroms are written by the in-tree ISO9660 writer, and
+//! initrd injections by an in-tree newc cpio writer, so no xorrisofs,
+//! genisoimage or cpio is needed on the host.
+
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::iso9660::IsoWriter;
+use crate::tmpfile;
+
+/// Append one newc record to `out`
+fn cpio_newc_entry(out: &mut Vec<u8>, ino: u32, mode: u32, name: &str, data: &[u8]) {
+    let namesize = name.len() + 1;
+    let fields = [
+        ino,
+        mode,
+        0, // uid, like cpio --owner=0:0
+        0, // gid
+        if mode & 0o040000 != 0 { 2 } else { 1 },
+        0, // mtime
+        data.len() as u32,
+        0,
+        0,
+        0,
+        0,
+        namesize as u32,
+        0, // check
+    ];
+    out.extend_from_slice(b"070701");
+    for field in fields {
+        out.extend_from_slice(format!("{:08X}", field).as_bytes());
+    }
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    // Name and data are both padded to 4 byte boundaries
+    out.resize(out.len().next_multiple_of(4), 0);
+    out.extend_from_slice(data);
+    out.resize(out.len().next_multiple_of(4), 0);
+}
+
+/// A newc cpio archive of `files`, (name, mode, data), in a root
+/// directory, like `find . | cpio --create --format=newc` gives
+fn cpio_newc_archive(files: &[(String, u32, Vec<u8>)]) -> Vec<u8> {
+    let mut out = vec![];
+    cpio_newc_entry(&mut out, 1, 0o040775, ".", &[]);
+    for (idx, (name, mode, data)) in files.iter().enumerate() {
+        cpio_newc_entry(&mut out, idx as u32 + 2, 0o100000 | mode, name, data);
+    }
+    cpio_newc_entry(&mut out, 0, 0, "TRAILER!!!", &[]);
+    out
+}
+
+/// Insert `injections`, (source path, name in the initrd) pairs, into
+/// the root directory of the initial ram disk. The kernel unpacks
+/// concatenated archives, so this appends a gzipped cpio archive.
+pub fn perform_initrd_injections(
+    initrd: &Path,
+    injections: &[(PathBuf, String)],
+) -> Result<(), String> {
+    if injections.is_empty() {
+        return Ok(());
+    }
+    let mut files = vec![];
+    for (src, dst) in injections {
+        log::debug!(
+            "Injecting src={} dst={} into media={}",
+            src.display(),
+            dst,
+            initrd.display()
+        );
+        let err = |e: std::io::Error| format!("Error reading '{}': {}", src.display(), e);
+        let data = std::fs::read(src).map_err(err)?;
+        let mode = std::fs::metadata(src).map_err(err)?.permissions().mode() & 0o7777;
+        files.push((dst.clone(), mode, data));
+    }
+
+    log::debug!("Appending to the initrd.");
+    let err = |e: std::io::Error| {
+        format!(
+            "Failed to inject files into initrd '{}': {}",
+            initrd.display(),
+            e
+        )
+    };
+    let file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(initrd)
+        .map_err(err)?;
+    let mut gz = GzEncoder::new(file, Compression::default());
+    gz.write_all(&cpio_newc_archive(&files)).map_err(err)?;
+    gz.finish().map_err(err)?;
+    Ok(())
+}
+
+/// Build an ISO in `scratchdir` holding `injections`, (source path,
+/// name on the media) pairs, in its root directory. With `cloudinit` the
+/// volume is labelled `cidata` so cloud-init's NoCloud source finds it.
+/// Returns the path of the new ISO.
+pub fn perform_cdrom_injections(
+    injections: &[(PathBuf, String)],
+    scratchdir: &Path,
+    cloudinit: bool,
+) -> Result<PathBuf, String> {
+    let (suffix, volume_id) = if cloudinit {
+        ("-cloudinit.iso", "cidata")
+    } else {
+        ("-unattended.iso", "CDROM")
+    };
+    let mut writer = IsoWriter::new(volume_id);
+    for (src, dst) in injections {
+        log::debug!("Injecting src={} dst={} into cdrom", src.display(), dst);
+        let data =
+            std::fs::read(src).map_err(|e| format!("Error reading '{}': {}", src.display(), e))?;
+        writer.add_file(dst, data)?;
+    }
+
+    let (_, iso) = tmpfile::mkstemp(scratchdir, "virtinst-", suffix)
+        .map_err(|e| format!("Error creating ISO in '{}': {}", scratchdir.display(), e))?;
+    let path = iso.to_string_lossy();
+    log::debug!("Writing generated cdrom {}", path);
+    if let Err(e) = writer.write(&path) {
+        let _ = std::fs::remove_file(&iso);
+        return Err(e);
+    }
+    Ok(iso)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iso9660::IsoImage;
+
+    #[test]
+    fn test_cdrom_injections() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("virtinst-abc-user-data");
+        std::fs::write(&src, "#cloud-config\n").unwrap();
+        let pairs = vec![(src, "user-data".to_string())];
+
+        let iso = perform_cdrom_injections(&pairs, dir.path(), true).unwrap();
+        assert!(iso.to_string_lossy().ends_with("-cloudinit.iso"));
+        let image = IsoImage::open(&iso.to_string_lossy()).unwrap();
+        assert_eq!(image.volume_id(), "cidata");
+        assert_eq!(image.read_file("user-data").unwrap(), b"#cloud-config\n");
+
+        let iso = perform_cdrom_injections(&pairs, dir.path(), false).unwrap();
+        assert!(iso.to_string_lossy().ends_with("-unattended.iso"));
+    }
+
+    #[test]
+    fn test_initrd_injections() {
+        let dir = tempfile::tempdir().unwrap();
+        let initrd = dir.path().join("initrd.img");
+        std::fs::write(&initrd, b"original").unwrap();
+        let src = PathBuf::from(format!(
+            "{}/../tests/data/inject/preseed.cfg",
+            env!("CARGO_MANIFEST_DIR")
+        ));
+        let pairs = vec![(src.clone(), "preseed.cfg".to_string())];
+        perform_initrd_injections(&initrd, &pairs).unwrap();
+
+        let data = std::fs::read(&initrd).unwrap();
+        assert!(data.starts_with(b"original"));
+        let mut cpio = vec![];
+        let mut gz = flate2::read::GzDecoder::new(&data[8..]);
+        std::io::Read::read_to_end(&mut gz, &mut cpio).unwrap();
+        assert_eq!(cpio.len() % 4, 0);
+        assert!(cpio.starts_with(b"070701"));
+        let content = std::fs::read(&src).unwrap();
+        let name = cpio
+            .windows(12)
+            .position(|w| w == b"preseed.cfg\0")
+            .unwrap();
+        let header = &cpio[name - 110..name];
+        let filesize = std::str::from_utf8(&header[54..62]).unwrap();
+        assert_eq!(usize::from_str_radix(filesize, 16).unwrap(), content.len());
+        let start = (name + 12).next_multiple_of(4);
+        assert_eq!(&cpio[start..start + content.len()], content.as_slice());
+        assert!(cpio.windows(10).any(|w| w == b"TRAILER!!!"));
+    }
+}
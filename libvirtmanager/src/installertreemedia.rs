@@ -0,0 +1,432 @@
+// --location install trees and direct kernel boot (port of
+// virtinst/install/installertreemedia.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `InstallerTreeMedia` finds the kernel/initrd of a `--location` tree,
+//! URL or ISO, or takes an explicit `--boot kernel=,initrd=` pair, and
+//! fetches them into the scratch directory for the install boot. Files
+//! from `--initrd-inject` and unattended install scripts are appended
+//! to the fetched initrd.
+
+use std::path::{Path, PathBuf};
+
+use crate::connection::Connection;
+use crate::installerinject::perform_initrd_injections;
+use crate::osdict::osdb;
+use crate::progress::Meter;
+use crate::unattended::OsInstallScript;
+use crate::urldetect::{self, MediaSource};
+use crate::urlfetcher::{self, CurlFetcher};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MediaType {
+    Dir,
+    Iso,
+    Url,
+    Kernel,
+}
+
+/// What detection found out about the media
+struct LocationData {
+    osinfo: Option<String>,
+    kernel_pairs: Vec<(String, String)>,
+}
+
+pub struct InstallerTreeMedia {
+    location: Option<String>,
+    location_kernel: Option<String>,
+    location_initrd: Option<String>,
+    install_kernel: Option<String>,
+    install_initrd: Option<String>,
+    install_kernel_args: Option<String>,
+    initrd_injections: Vec<(PathBuf, String)>,
+    extra_args: Vec<String>,
+    media_type: MediaType,
+    cached_media: Option<MediaSource>,
+    cached_data: Option<LocationData>,
+    tmpfiles: Vec<PathBuf>,
+}
+
+/// Check that local install media exists, returning its path
+pub fn validate_path(path: &str) -> Result<String, String> {
+    if let Err(e) = std::fs::metadata(path) {
+        log::debug!("Error validating install location: {}", e);
+        if path.starts_with("nfs:") {
+            log::warn!(
+                "NFS URL installs are no longer supported. Access your install media over \
+                 an alternate transport like HTTP, or manually mount the NFS share and \
+                 install from the local directory mount point."
+            );
+        }
+        return Err(format!(
+            "Validating install media '{}' failed: Must specify storage creation parameters \
+             for non-existent path '{}'.",
+            path, path
+        ));
+    }
+    Ok(path.to_string())
+}
+
+impl InstallerTreeMedia {
+    /// `location` is a tree, URL or ISO; `location_kernel`/`initrd` are
+    /// paths inside it, overriding detection. `install_kernel`/`initrd`
+    /// are local files to boot directly, without any location.
+    pub fn new(
+        conn: &dyn Connection,
+        location: Option<&str>,
+        location_kernel: Option<&str>,
+        location_initrd: Option<&str>,
+        install_kernel: Option<&str>,
+        install_initrd: Option<&str>,
+        install_kernel_args: Option<&str>,
+    ) -> Result<Self, String> {
+        if location_kernel.is_some() || location_initrd.is_some() {
+            if location.is_none() {
+                return Err(
+                    "location kernel/initrd may only be specified with a location URL/path"
+                        .to_string(),
+                );
+            }
+            if location_kernel.is_none() || location_initrd.is_none() {
+                return Err("location kernel/initrd must be specified as a pair".to_string());
+            }
+        }
+
+        let mut location = location.map(str::to_string);
+        let media_type = match location.as_deref() {
+            _ if install_kernel.is_some() || install_initrd.is_some() => MediaType::Kernel,
+            Some(loc) if !conn.is_remote() && Path::new(loc).is_dir() => {
+                location = std::path::absolute(loc)
+                    .ok()
+                    .map(|p| p.to_string_lossy().into_owned());
+                MediaType::Dir
+            }
+            Some(loc) if urlfetcher::is_network_url(loc) => MediaType::Url,
+            _ => MediaType::Iso,
+        };
+
+        if conn.is_remote() && !matches!(media_type, MediaType::Url | MediaType::Kernel) {
+            return Err(format!(
+                "Cannot access install tree on remote connection: {}",
+                location.unwrap_or_default()
+            ));
+        }
+        if media_type == MediaType::Iso {
+            validate_path(location.as_deref().unwrap_or_default())?;
+        }
+
+        Ok(Self {
+            location,
+            location_kernel: location_kernel.map(str::to_string),
+            location_initrd: location_initrd.map(str::to_string),
+            install_kernel: install_kernel.map(str::to_string),
+            install_initrd: install_initrd.map(str::to_string),
+            install_kernel_args: install_kernel_args.map(str::to_string),
+            initrd_injections: vec![],
+            extra_args: vec![],
+            media_type,
+            cached_media: None,
+            cached_data: None,
+            tmpfiles: vec![],
+        })
+    }
+
+    fn get_media(&mut self) -> Result<&MediaSource, String> {
+        if self.cached_media.is_none() {
+            let location = self.location.as_deref().unwrap_or_default();
+            self.cached_media = Some(MediaSource::open(location)?);
+        }
+        Ok(self.cached_media.as_ref().unwrap())
+    }
+
+    fn get_cached_data(&mut self, arch: &str, os_type: &str) -> Result<&LocationData, String> {
+        if self.cached_data.is_none() {
+            let has_location_kernel =
+                self.location_kernel.is_some() && self.location_initrd.is_some();
+            let mut data = LocationData {
+                osinfo: None,
+                kernel_pairs: vec![],
+            };
+            if self.media_type == MediaType::Kernel {
+                data.kernel_pairs = vec![(
+                    self.install_kernel.clone().unwrap_or_default(),
+                    self.install_initrd.clone().unwrap_or_default(),
+                )];
+            } else {
+                let media = self.get_media()?;
+                match urldetect::detect_distro(media, arch, os_type, osdb()) {
+                    Ok(store) => {
+                        data.osinfo = store.get_osdict_info().map(str::to_string);
+                        data.kernel_pairs = store.get_kernel_paths().to_vec();
+                    }
+                    // An explicit kernel/initrd doesn't need a known distro
+                    Err(e) if !has_location_kernel => return Err(e),
+                    Err(e) => log::debug!("{}", e),
+                }
+            }
+            if has_location_kernel {
+                data.kernel_pairs = vec![(
+                    self.location_kernel.clone().unwrap_or_default(),
+                    self.location_initrd.clone().unwrap_or_default(),
+                )];
+            }
+            self.cached_data = Some(data);
+        }
+        Ok(self.cached_data.as_ref().unwrap())
+    }
+
+    fn has_file(&mut self, path: &str) -> Result<bool, String> {
+        if self.media_type == MediaType::Kernel {
+            return Ok(urlfetcher::is_network_url(path) || Path::new(path).exists());
+        }
+        Ok(self.get_media()?.has_file(path))
+    }
+
+    fn acquire_file(
+        &mut self,
+        path: &str,
+        scratchdir: &Path,
+        meter: &dyn Meter,
+    ) -> Result<PathBuf, String> {
+        let tmp = if self.media_type == MediaType::Kernel {
+            urlfetcher::acquire_file(path, scratchdir, meter, &CurlFetcher)?
+        } else {
+            self.get_media()?.acquire_file(path, scratchdir, meter)?
+        };
+        self.tmpfiles.push(tmp.clone());
+        Ok(tmp)
+    }
+
+    fn prepare_kernel_url(
+        &mut self,
+        arch: &str,
+        os_type: &str,
+        scratchdir: &Path,
+        meter: &dyn Meter,
+    ) -> Result<(PathBuf, PathBuf), String> {
+        let pairs = self.get_cached_data(arch, os_type)?.kernel_pairs.clone();
+        let mut found = None;
+        for (kpath, ipath) in pairs {
+            if self.has_file(&kpath)? && self.has_file(&ipath)? {
+                found = Some((kpath, ipath));
+                break;
+            }
+        }
+        let (kpath, ipath) =
+            found.ok_or_else(|| "Couldn't find kernel for install tree.".to_string())?;
+
+        let kernel = self.acquire_file(&kpath, scratchdir, meter)?;
+        let initrd = self.acquire_file(&ipath, scratchdir, meter)?;
+        perform_initrd_injections(&initrd, &self.initrd_injections)?;
+        Ok((kernel, initrd))
+    }
+
+    /// Kernel argument pointing the installer at a network location,
+    /// for the detected OS or else the guest's
+    fn prepare_kernel_url_arg(
+        &mut self,
+        arch: &str,
+        os_type: &str,
+        os_name: &str,
+    ) -> Result<Option<String>, String> {
+        let detected = self.get_cached_data(arch, os_type)?.osinfo.clone();
+        let name = detected.as_deref().unwrap_or(os_name);
+        Ok(osdb().lookup_os(name).and_then(|o| o.get_kernel_url_arg()))
+    }
+
+    fn prepare_kernel_args(
+        &mut self,
+        arch: &str,
+        os_type: &str,
+        os_name: &str,
+        unattended_scripts: &[OsInstallScript],
+    ) -> Result<String, String> {
+        if !unattended_scripts.is_empty() {
+            // The scripts know how to point the installer at the media
+            let mut args = vec![];
+            for script in unattended_scripts {
+                args.extend(script.generate_cmdline()?);
+            }
+            if !args.is_empty() {
+                self.extra_args.push(args.join(" "));
+            }
+        } else if let Some(location) = self.is_network_url().map(str::to_string)
+            && let Some(arg) = self.prepare_kernel_url_arg(arch, os_type, os_name)?
+        {
+            self.extra_args.push(format!("{}={}", arg, location));
+        }
+
+        let ret = match &self.install_kernel_args {
+            Some(args) => args.clone(),
+            None => self.extra_args.join(" "),
+        };
+        if self.media_type == MediaType::Dir && ret.is_empty() {
+            log::warn!(
+                "Directory tree installs typically do not work unless extra kernel args \
+                 are passed to point the installer at a network accessible install tree."
+            );
+        }
+        Ok(ret)
+    }
+
+    /// Write the unattended scripts and queue them for initrd injection
+    fn prepare_unattended_data(
+        &mut self,
+        scripts: &[OsInstallScript],
+        scratchdir: &Path,
+    ) -> Result<(), String> {
+        for script in scripts {
+            let path = script.write(scratchdir)?;
+            self.tmpfiles.push(path.clone());
+            self.initrd_injections
+                .push((path, script.get_expected_filename().to_string()));
+        }
+        Ok(())
+    }
+
+    /// Fetch the kernel and initrd into `scratchdir` for a guest of
+    /// `arch` and `os_type`, returning (kernel, initrd, kernel args).
+    /// `os_name` is the guest OS, for when none is detected.
+    /// `unattended_scripts` are injected into the initrd, and replace
+    /// the network location kernel argument with their own.
+    pub fn prepare(
+        &mut self,
+        arch: &str,
+        os_type: &str,
+        os_name: &str,
+        scratchdir: &Path,
+        meter: &dyn Meter,
+        unattended_scripts: &[OsInstallScript],
+    ) -> Result<(PathBuf, PathBuf, String), String> {
+        self.prepare_unattended_data(unattended_scripts, scratchdir)?;
+        let (kernel, initrd) = self.prepare_kernel_url(arch, os_type, scratchdir, meter)?;
+        let kernel_args = self.prepare_kernel_args(arch, os_type, os_name, unattended_scripts)?;
+        Ok((kernel, initrd, kernel_args))
+    }
+
+    /// Remove the fetched files
+    pub fn cleanup(&mut self) {
+        for path in self.tmpfiles.drain(..) {
+            log::debug!("Removing {}", path.display());
+            if let Err(e) = std::fs::remove_file(&path) {
+                log::debug!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Files to add to the root of the initrd, named by their basename
+    pub fn set_initrd_injections(&mut self, injections: &[String]) {
+        self.initrd_injections = injections
+            .iter()
+            .map(|path| {
+                let dst = Path::new(path)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| path.clone());
+                (PathBuf::from(path), dst)
+            })
+            .collect();
+    }
+
+    pub fn set_extra_args(&mut self, extra_args: &[String]) {
+        self.extra_args = extra_args.to_vec();
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    /// The location, when it's an ISO that gets attached as a cdrom
+    pub fn cdrom_path(&self) -> Option<&str> {
+        match self.media_type {
+            MediaType::Iso => self.location.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The location, when it's a network URL
+    pub fn is_network_url(&self) -> Option<&str> {
+        match self.media_type {
+            MediaType::Url => self.location.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The OS variant detected from the media, if any
+    pub fn detect_distro(&mut self, arch: &str, os_type: &str) -> Result<Option<String>, String> {
+        Ok(self.get_cached_data(arch, os_type)?.osinfo.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+    use crate::progress::NullMeter;
+
+    #[test]
+    fn test_tree_prepare() {
+        let conn = testdriver();
+        let data = format!("{}/../tests/data", env!("CARGO_MANIFEST_DIR"));
+        let scratch = tempfile::tempdir().unwrap();
+
+        let tree = format!("{}/fakemedia/fakefedoratree", data);
+        let mut media =
+            InstallerTreeMedia::new(&conn, Some(&tree), None, None, None, None, None).unwrap();
+        assert_eq!(media.cdrom_path(), None);
+        assert_eq!(
+            media.detect_distro("x86_64", "hvm").unwrap().as_deref(),
+            Some("fedora17")
+        );
+        let ks = format!("{}/inject/old-kickstart.ks", data);
+        media.set_initrd_injections(std::slice::from_ref(&ks));
+        media.set_extra_args(&["ks=file:/old-kickstart.ks".to_string()]);
+        let (kernel, initrd, args) = media
+            .prepare("x86_64", "hvm", "generic", scratch.path(), &NullMeter, &[])
+            .unwrap();
+        assert_eq!(args, "ks=file:/old-kickstart.ks");
+        assert!(kernel.starts_with(scratch.path()));
+        let orig = std::fs::read(format!("{}/images/pxeboot/initrd.img", tree)).unwrap();
+        let injected = std::fs::read(&initrd).unwrap();
+        assert!(injected.starts_with(&orig) && injected.len() > orig.len());
+        media.cleanup();
+        assert_eq!(std::fs::read_dir(scratch.path()).unwrap().count(), 0);
+
+        let iso = format!("{}/fakemedia/fake-no-osinfo.iso", data);
+        let mut media = InstallerTreeMedia::new(
+            &conn,
+            Some(&iso),
+            Some("frib.img"),
+            Some("/frob.img"),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(media.cdrom_path(), Some(iso.as_str()));
+        let (kernel, _, _) = media
+            .prepare("x86_64", "hvm", "generic", scratch.path(), &NullMeter, &[])
+            .unwrap();
+        assert!(kernel.to_string_lossy().ends_with("-frib.img"));
+        media.cleanup();
+
+        let err = InstallerTreeMedia::new(&conn, None, Some("foo"), Some("bar"), None, None, None)
+            .err()
+            .unwrap();
+        assert!(err.contains("may only be specified with a location URL/path"));
+        let err = InstallerTreeMedia::new(
+            &conn,
+            Some("http://example.com"),
+            Some("foo"),
+            None,
+            None,
+            None,
+            None,
+        )
+        .err()
+        .unwrap();
+        assert!(err.contains("must be specified as a pair"));
+    }
+}
@@ -0,0 +1,812 @@
+// ISO9660 image writing and reading (replaces the xorrisofs/genisoimage
+// calls in virtinst/install/installerinject.py and the isoinfo probing
+// of install media)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Small ISO9660 implementation. `IsoWriter` creates single directory
+//! images like `genisoimage -J -rational-rock` would, with Joliet and
+//! Rock Ridge names so guests see the file names unmangled. `IsoImage`
+//! reads existing images, preferring Rock Ridge names, then Joliet, then
+//! the plain ISO9660 ones.
+
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::fs::FileExt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SECTOR_SIZE: usize = 2048;
+/// Volume descriptors start after the 32 KiB system area
+const FIRST_DESCRIPTOR: u64 = 16;
+const MAX_NAME_LEN: usize = 100;
+
+const VD_PRIMARY: u8 = 1;
+const VD_SUPPLEMENTARY: u8 = 2;
+const VD_TERMINATOR: u8 = 255;
+/// Escape sequences marking a Joliet supplementary descriptor
+const JOLIET_ESCAPES: [&[u8]; 3] = [b"%/@", b"%/C", b"%/E"];
+
+const FLAG_DIRECTORY: u8 = 2;
+
+fn both16(v: u16) -> [u8; 4] {
+    let mut b = [0u8; 4];
+    b[..2].copy_from_slice(&v.to_le_bytes());
+    b[2..].copy_from_slice(&v.to_be_bytes());
+    b
+}
+
+fn both32(v: u32) -> [u8; 8] {
+    let mut b = [0u8; 8];
+    b[..4].copy_from_slice(&v.to_le_bytes());
+    b[4..].copy_from_slice(&v.to_be_bytes());
+    b
+}
+
+fn sectors(len: usize) -> u32 {
+    len.div_ceil(SECTOR_SIZE) as u32
+}
+
+/// Civil UTC date and time from a unix timestamp
+fn civil_time(secs: u64) -> (i64, u8, u8, u8, u8, u8) {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // Howard Hinnant's days_from_civil inverse
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (
+        year,
+        month,
+        day,
+        (rem / 3600) as u8,
+        (rem % 3600 / 60) as u8,
+        (rem % 60) as u8,
+    )
+}
+
+/// Mangle a name into ISO9660 level 1 `NAME.EXT;1` form
+fn iso_name(name: &str, taken: &[String]) -> String {
+    let clean = |s: &str, max: usize| -> String {
+        s.chars()
+            .map(|c| match c.to_ascii_uppercase() {
+                c @ ('A'..='Z' | '0'..='9' | '_') => c,
+                _ => '_',
+            })
+            .take(max)
+            .collect()
+    };
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((b, e)) if !b.is_empty() => (clean(b, 8), clean(e, 3)),
+        _ => (clean(name, 8), String::new()),
+    };
+    let mut candidate = format!("{}.{};1", base, ext);
+    let mut n = 0;
+    while taken.contains(&candidate) {
+        n += 1;
+        let suffix = n.to_string();
+        let keep = base.len().min(8 - suffix.len());
+        candidate = format!("{}{}.{};1", &base[..keep], suffix, ext);
+    }
+    candidate
+}
+
+fn ucs2(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(|c| c.to_be_bytes()).collect()
+}
+
+/// Space padded fixed width descriptor string
+fn padded(s: &[u8], len: usize, pad: &[u8]) -> Vec<u8> {
+    let mut out = s[..s.len().min(len)].to_vec();
+    while out.len() < len {
+        out.extend_from_slice(pad);
+    }
+    out.truncate(len);
+    out
+}
+
+struct WriterFile {
+    name: String,
+    data: Vec<u8>,
+    mode: u32,
+}
+
+/// Builder for a single directory ISO image
+pub struct IsoWriter {
+    volume_id: String,
+    files: Vec<WriterFile>,
+    timestamp: u64,
+}
+
+struct Tree<'a> {
+    joliet: bool,
+    names: Vec<Vec<u8>>,
+    files: &'a [WriterFile],
+}
+
+impl IsoWriter {
+    pub fn new(volume_id: &str) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            volume_id: volume_id.to_string(),
+            files: vec![],
+            timestamp,
+        }
+    }
+
+    /// Add a file to the root directory of the image
+    pub fn add_file(&mut self, name: &str, data: Vec<u8>) -> Result<(), String> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN || name.contains('/') {
+            return Err(format!("Invalid ISO file name '{}'", name));
+        }
+        if self.files.iter().any(|f| f.name == name) {
+            return Err(format!("Duplicate ISO file name '{}'", name));
+        }
+        self.files.push(WriterFile {
+            name: name.to_string(),
+            data,
+            mode: 0o100444,
+        });
+        Ok(())
+    }
+
+    fn date7(&self) -> [u8; 7] {
+        let (y, mo, d, h, mi, s) = civil_time(self.timestamp);
+        [(y - 1900) as u8, mo, d, h, mi, s, 0]
+    }
+
+    fn date17(&self) -> Vec<u8> {
+        let (y, mo, d, h, mi, s) = civil_time(self.timestamp);
+        let mut out = format!("{:04}{:02}{:02}{:02}{:02}{:02}00", y, mo, d, h, mi, s).into_bytes();
+        out.push(0);
+        out
+    }
+
+    fn rock_ridge(mode: u32, name: Option<&str>, root_dot: bool) -> Vec<u8> {
+        let mut su = vec![];
+        if root_dot {
+            su.extend_from_slice(&[b'S', b'P', 7, 1, 0xbe, 0xef, 0]);
+            let (id, des, src) = (
+                &b"RRIP_1991A"[..],
+                &b"POSIX FILE SYSTEM SEMANTICS"[..],
+                &b"RRIP"[..],
+            );
+            su.extend_from_slice(&[
+                b'E',
+                b'R',
+                (8 + id.len() + des.len() + src.len()) as u8,
+                1,
+                id.len() as u8,
+                des.len() as u8,
+                src.len() as u8,
+                1,
+            ]);
+            su.extend_from_slice(id);
+            su.extend_from_slice(des);
+            su.extend_from_slice(src);
+        }
+        su.extend_from_slice(&[b'P', b'X', 36, 1]);
+        let nlinks = if mode & 0o040000 != 0 { 2 } else { 1 };
+        for v in [mode, nlinks, 0, 0] {
+            su.extend_from_slice(&both32(v));
+        }
+        if let Some(name) = name {
+            su.extend_from_slice(&[b'N', b'M', (5 + name.len()) as u8, 1, 0]);
+            su.extend_from_slice(name.as_bytes());
+        }
+        su
+    }
+
+    fn dir_record(&self, name: &[u8], extent: u32, len: u32, dir: bool, su: &[u8]) -> Vec<u8> {
+        let pad = usize::from(name.len().is_multiple_of(2));
+        let mut rec = vec![0, 0];
+        rec.extend_from_slice(&both32(extent));
+        rec.extend_from_slice(&both32(len));
+        rec.extend_from_slice(&self.date7());
+        rec.push(if dir { FLAG_DIRECTORY } else { 0 });
+        rec.extend_from_slice(&[0, 0]);
+        rec.extend_from_slice(&both16(1));
+        rec.push(name.len() as u8);
+        rec.extend_from_slice(name);
+        rec.resize(rec.len() + pad, 0);
+        rec.extend_from_slice(su);
+        // Record lengths must be even
+        if rec.len() % 2 == 1 {
+            rec.push(0);
+        }
+        rec[0] = rec.len() as u8;
+        rec
+    }
+
+    /// Directory contents, with records kept from crossing sectors
+    fn directory(
+        &self,
+        tree: &Tree,
+        self_extent: u32,
+        self_len: u32,
+        file_extents: &[u32],
+    ) -> Vec<u8> {
+        let rr = !tree.joliet;
+        let dot_su = if rr {
+            Self::rock_ridge(0o040555, None, true)
+        } else {
+            vec![]
+        };
+        let dotdot_su = if rr {
+            Self::rock_ridge(0o040555, None, false)
+        } else {
+            vec![]
+        };
+        let mut records = vec![
+            self.dir_record(&[0], self_extent, self_len, true, &dot_su),
+            self.dir_record(&[1], self_extent, self_len, true, &dotdot_su),
+        ];
+        let mut order: Vec<usize> = (0..tree.files.len()).collect();
+        order.sort_by(|a, b| tree.names[*a].cmp(&tree.names[*b]));
+        for i in order {
+            let f = &tree.files[i];
+            let su = if rr {
+                Self::rock_ridge(f.mode, Some(&f.name), false)
+            } else {
+                vec![]
+            };
+            records.push(self.dir_record(
+                &tree.names[i],
+                file_extents[i],
+                f.data.len() as u32,
+                false,
+                &su,
+            ));
+        }
+
+        let mut out: Vec<u8> = vec![];
+        for rec in records {
+            let used = out.len() % SECTOR_SIZE;
+            if used + rec.len() > SECTOR_SIZE {
+                out.resize(out.len() + SECTOR_SIZE - used, 0);
+            }
+            out.extend_from_slice(&rec);
+        }
+        out
+    }
+
+    fn path_table(root_extent: u32, big_endian: bool) -> Vec<u8> {
+        let mut pt = vec![1, 0];
+        if big_endian {
+            pt.extend_from_slice(&root_extent.to_be_bytes());
+            pt.extend_from_slice(&1u16.to_be_bytes());
+        } else {
+            pt.extend_from_slice(&root_extent.to_le_bytes());
+            pt.extend_from_slice(&1u16.to_le_bytes());
+        }
+        pt.extend_from_slice(&[0, 0]);
+        pt
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn descriptor(
+        &self,
+        joliet: bool,
+        total: u32,
+        path_table_size: u32,
+        l_table: u32,
+        m_table: u32,
+        root_record: &[u8],
+    ) -> Vec<u8> {
+        let text = |s: &str, len: usize| -> Vec<u8> {
+            if joliet {
+                padded(&ucs2(s), len, &[0, b' '])
+            } else {
+                padded(s.as_bytes(), len, b" ")
+            }
+        };
+        let mut vd = vec![if joliet { VD_SUPPLEMENTARY } else { VD_PRIMARY }];
+        vd.extend_from_slice(b"CD001");
+        vd.extend_from_slice(&[1, 0]);
+        vd.extend(text("LINUX", 32));
+        vd.extend(text(&self.volume_id, 32));
+        vd.extend_from_slice(&[0; 8]);
+        vd.extend_from_slice(&both32(total));
+        let mut escapes = [0u8; 32];
+        if joliet {
+            escapes[..3].copy_from_slice(JOLIET_ESCAPES[2]);
+        }
+        vd.extend_from_slice(&escapes);
+        vd.extend_from_slice(&both16(1));
+        vd.extend_from_slice(&both16(1));
+        vd.extend_from_slice(&both16(SECTOR_SIZE as u16));
+        vd.extend_from_slice(&both32(path_table_size));
+        vd.extend_from_slice(&l_table.to_le_bytes());
+        vd.extend_from_slice(&0u32.to_le_bytes());
+        vd.extend_from_slice(&m_table.to_be_bytes());
+        vd.extend_from_slice(&0u32.to_be_bytes());
+        vd.extend_from_slice(root_record);
+        // Volume set, publisher, preparer and application identifiers
+        vd.extend(text("", 128));
+        vd.extend(text("", 128));
+        vd.extend(text("", 128));
+        vd.extend(text("VIRT-MANAGER", 128));
+        // Copyright, abstract and bibliographic file identifiers
+        vd.extend(text("", 37));
+        vd.extend(text("", 37));
+        vd.extend(text("", 37));
+        let date = self.date17();
+        vd.extend_from_slice(&date);
+        vd.extend_from_slice(&date);
+        let mut never = vec![b'0'; 16];
+        never.push(0);
+        vd.extend_from_slice(&never);
+        vd.extend_from_slice(&date);
+        vd.push(1);
+        vd.resize(SECTOR_SIZE, 0);
+        vd
+    }
+
+    /// Serialize the image
+    pub fn build(&self) -> Vec<u8> {
+        let mut taken: Vec<String> = vec![];
+        let mut iso_names = vec![];
+        for f in &self.files {
+            let n = iso_name(&f.name, &taken);
+            taken.push(n.clone());
+            iso_names.push(n.into_bytes());
+        }
+        let primary = Tree {
+            joliet: false,
+            names: iso_names,
+            files: &self.files,
+        };
+        let joliet = Tree {
+            joliet: true,
+            names: self.files.iter().map(|f| ucs2(&f.name)).collect(),
+            files: &self.files,
+        };
+
+        // System area, three descriptors, then four path table sectors
+        let path_tables = FIRST_DESCRIPTOR as u32 + 3;
+        let primary_root = path_tables + 4;
+        // Directory sizes only depend on the names, so measure first
+        let dummy = vec![0; self.files.len()];
+        let primary_len = self.directory(&primary, 0, 0, &dummy).len();
+        let joliet_len = self.directory(&joliet, 0, 0, &dummy).len();
+        let primary_size = sectors(primary_len) * SECTOR_SIZE as u32;
+        let joliet_size = sectors(joliet_len) * SECTOR_SIZE as u32;
+        let joliet_root = primary_root + sectors(primary_len);
+
+        let mut next = joliet_root + sectors(joliet_len);
+        let mut extents = vec![];
+        for f in &self.files {
+            extents.push(next);
+            next += sectors(f.data.len());
+        }
+        let total = next;
+
+        let primary_dir = self.directory(&primary, primary_root, primary_size, &extents);
+        let joliet_dir = self.directory(&joliet, joliet_root, joliet_size, &extents);
+        let pt_size = Self::path_table(0, false).len() as u32;
+
+        let mut out = vec![0u8; total as usize * SECTOR_SIZE];
+        let mut put = |sector: u32, data: &[u8]| {
+            let off = sector as usize * SECTOR_SIZE;
+            out[off..off + data.len()].copy_from_slice(data);
+        };
+        let root_rec = self.dir_record(&[0], primary_root, primary_size, true, &[]);
+        put(
+            FIRST_DESCRIPTOR as u32,
+            &self.descriptor(
+                false,
+                total,
+                pt_size,
+                path_tables,
+                path_tables + 1,
+                &root_rec,
+            ),
+        );
+        let root_rec = self.dir_record(&[0], joliet_root, joliet_size, true, &[]);
+        put(
+            FIRST_DESCRIPTOR as u32 + 1,
+            &self.descriptor(
+                true,
+                total,
+                pt_size,
+                path_tables + 2,
+                path_tables + 3,
+                &root_rec,
+            ),
+        );
+        let mut term = vec![VD_TERMINATOR];
+        term.extend_from_slice(b"CD001");
+        term.push(1);
+        put(FIRST_DESCRIPTOR as u32 + 2, &term);
+        put(path_tables, &Self::path_table(primary_root, false));
+        put(path_tables + 1, &Self::path_table(primary_root, true));
+        put(path_tables + 2, &Self::path_table(joliet_root, false));
+        put(path_tables + 3, &Self::path_table(joliet_root, true));
+        put(primary_root, &primary_dir);
+        put(joliet_root, &joliet_dir);
+        for (f, extent) in self.files.iter().zip(extents) {
+            put(extent, &f.data);
+        }
+        out
+    }
+
+    /// Write the image to a new file at `path`
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        log::debug!(
+            "Writing ISO '{}' label={} files={:?}",
+            path,
+            self.volume_id,
+            self.files.iter().map(|f| &f.name).collect::<Vec<_>>()
+        );
+        let mut file =
+            File::create(path).map_err(|e| format!("Error creating ISO '{}': {}", path, e))?;
+        file.write_all(&self.build())
+            .map_err(|e| format!("Error writing ISO '{}': {}", path, e))
+    }
+}
+
+/// Directory entry of an existing image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IsoEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    extent: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NameKind {
+    RockRidge,
+    Joliet,
+    Plain,
+}
+
+/// Read only view of an ISO9660 image
+pub struct IsoImage {
+    file: File,
+    path: String,
+    volume_id: String,
+    root: IsoEntry,
+    kind: NameKind,
+    /// Bytes to skip at the start of each Rock Ridge system use area
+    susp_skip: usize,
+}
+
+fn le32(b: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(b[off..off + 4].try_into().unwrap())
+}
+
+/// A raw directory record: name bytes, flags, extent, size, system use
+struct RawRecord<'a> {
+    name: &'a [u8],
+    flags: u8,
+    extent: u32,
+    size: u32,
+    system_use: &'a [u8],
+}
+
+fn parse_record(rec: &[u8]) -> Option<RawRecord<'_>> {
+    if rec.len() < 34 {
+        return None;
+    }
+    let name_len = rec[32] as usize;
+    let name_end = 33 + name_len;
+    if name_end > rec.len() {
+        return None;
+    }
+    let su_start = (name_end + usize::from(name_len.is_multiple_of(2))).min(rec.len());
+    Some(RawRecord {
+        name: &rec[33..name_end],
+        flags: rec[25],
+        extent: le32(rec, 2),
+        size: le32(rec, 10),
+        system_use: &rec[su_start..],
+    })
+}
+
+impl IsoImage {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let err = |msg: String| format!("Error reading ISO '{}': {}", path, msg);
+        let file = File::open(path).map_err(|e| err(e.to_string()))?;
+        let mut primary: Option<Vec<u8>> = None;
+        let mut joliet: Option<Vec<u8>> = None;
+        for sector in FIRST_DESCRIPTOR..FIRST_DESCRIPTOR + 32 {
+            let mut vd = vec![0u8; SECTOR_SIZE];
+            file.read_exact_at(&mut vd, sector * SECTOR_SIZE as u64)
+                .map_err(|e| err(e.to_string()))?;
+            if &vd[1..6] != b"CD001" {
+                return Err(err("Not an ISO9660 image".to_string()));
+            }
+            match vd[0] {
+                VD_PRIMARY if primary.is_none() => primary = Some(vd),
+                VD_SUPPLEMENTARY if JOLIET_ESCAPES.contains(&&vd[88..91]) => joliet = Some(vd),
+                VD_TERMINATOR => break,
+                _ => {}
+            }
+        }
+        let primary = primary.ok_or_else(|| err("No primary volume descriptor".to_string()))?;
+        let volume_id = String::from_utf8_lossy(&primary[40..72])
+            .trim_end()
+            .to_string();
+        let root_of = |vd: &[u8]| -> Result<IsoEntry, String> {
+            let rec = parse_record(&vd[156..190])
+                .ok_or_else(|| err("Invalid root directory record".to_string()))?;
+            Ok(IsoEntry {
+                name: String::new(),
+                is_dir: true,
+                size: rec.size as u64,
+                extent: rec.extent as u64,
+            })
+        };
+
+        let mut image = Self {
+            file,
+            path: path.to_string(),
+            volume_id,
+            root: root_of(&primary)?,
+            kind: NameKind::Plain,
+            susp_skip: 0,
+        };
+        // Rock Ridge is announced by an SP entry in the root's "." record
+        let dir = image.read_extent(&image.root)?;
+        let dot_len = dir.first().map_or(0, |l| *l as usize).min(dir.len());
+        if let Some(dot) = parse_record(&dir[..dot_len])
+            && dot.system_use.len() >= 7
+            && &dot.system_use[..2] == b"SP"
+            && dot.system_use[4..6] == [0xbe, 0xef]
+        {
+            image.kind = NameKind::RockRidge;
+            image.susp_skip = dot.system_use[6] as usize;
+        } else if let Some(vd) = joliet {
+            image.kind = NameKind::Joliet;
+            image.root = root_of(&vd)?;
+        }
+        log::debug!(
+            "Opened ISO '{}' label='{}' names={:?}",
+            path,
+            image.volume_id,
+            image.kind
+        );
+        Ok(image)
+    }
+
+    /// The volume label, like `cidata` or `Fedora-WS-Live-29`
+    pub fn volume_id(&self) -> &str {
+        &self.volume_id
+    }
+
+    fn read_extent(&self, entry: &IsoEntry) -> Result<Vec<u8>, String> {
+        let mut buf = vec![0u8; entry.size as usize];
+        self.file
+            .read_exact_at(&mut buf, entry.extent * SECTOR_SIZE as u64)
+            .map_err(|e| format!("Error reading ISO '{}': {}", self.path, e))?;
+        Ok(buf)
+    }
+
+    /// Rock Ridge NM name from a system use area, following CE
+    /// continuation areas
+    fn rock_ridge_name(&self, system_use: &[u8]) -> Option<String> {
+        let mut area = system_use.get(self.susp_skip..)?.to_vec();
+        let mut name: Vec<u8> = vec![];
+        let mut found = false;
+        for _ in 0..16 {
+            let mut pos = 0;
+            let mut continuation = None;
+            while pos + 4 <= area.len() {
+                let (sig, len) = (&area[pos..pos + 2], area[pos + 2] as usize);
+                if len < 4 || pos + len > area.len() {
+                    break;
+                }
+                let entry = &area[pos..pos + len];
+                match sig {
+                    // Flags 2 and 4 mean "." and ".."
+                    b"NM" if len >= 5 && entry[4] & 0x6 == 0 => {
+                        name.extend_from_slice(&entry[5..]);
+                        found = true;
+                    }
+                    b"CE" if len >= 28 => {
+                        continuation = Some((le32(entry, 4), le32(entry, 12), le32(entry, 20)));
+                    }
+                    b"ST" => break,
+                    _ => {}
+                }
+                pos += len;
+            }
+            let Some((block, offset, len)) = continuation else {
+                break;
+            };
+            let mut next = vec![0u8; len as usize];
+            let off = block as u64 * SECTOR_SIZE as u64 + offset as u64;
+            self.file.read_exact_at(&mut next, off).ok()?;
+            area = next;
+        }
+        found.then(|| String::from_utf8_lossy(&name).into_owned())
+    }
+
+    fn entry_name(&self, rec: &RawRecord) -> String {
+        if self.kind == NameKind::RockRidge
+            && let Some(name) = self.rock_ridge_name(rec.system_use)
+        {
+            return name;
+        }
+        let name = if self.kind == NameKind::Joliet {
+            let units: Vec<u16> = rec
+                .name
+                .chunks(2)
+                .filter(|c| c.len() == 2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        } else {
+            String::from_utf8_lossy(rec.name).into_owned()
+        };
+        let name = name.split(';').next().unwrap_or_default();
+        let name = if rec.flags & FLAG_DIRECTORY == 0 {
+            name.strip_suffix('.').unwrap_or(name)
+        } else {
+            name
+        };
+        name.to_string()
+    }
+
+    fn list(&self, dir: &IsoEntry) -> Result<Vec<IsoEntry>, String> {
+        let data = self.read_extent(dir)?;
+        let mut ret = vec![];
+        let mut pos = 0;
+        while pos < data.len() {
+            let reclen = data[pos] as usize;
+            if reclen == 0 {
+                // Records never cross sectors; the rest of this one is padding
+                pos = (pos / SECTOR_SIZE + 1) * SECTOR_SIZE;
+                continue;
+            }
+            let Some(rec) = data.get(pos..pos + reclen).and_then(parse_record) else {
+                break;
+            };
+            pos += reclen;
+            if rec.name == [0] || rec.name == [1] {
+                continue;
+            }
+            ret.push(IsoEntry {
+                name: self.entry_name(&rec),
+                is_dir: rec.flags & FLAG_DIRECTORY != 0,
+                size: rec.size as u64,
+                extent: rec.extent as u64,
+            });
+        }
+        Ok(ret)
+    }
+
+    fn names_match(&self, a: &str, b: &str) -> bool {
+        match self.kind {
+            NameKind::Plain => a.eq_ignore_ascii_case(b),
+            _ => a == b,
+        }
+    }
+
+    /// Look up an entry by `/` separated path, relative to the root
+    pub fn lookup(&self, path: &str) -> Result<Option<IsoEntry>, String> {
+        let mut cur = self.root.clone();
+        for part in path.split('/').filter(|p| !p.is_empty() && *p != ".") {
+            if !cur.is_dir {
+                return Ok(None);
+            }
+            match self
+                .list(&cur)?
+                .into_iter()
+                .find(|e| self.names_match(&e.name, part))
+            {
+                Some(e) => cur = e,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(cur))
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        matches!(self.lookup(path), Ok(Some(_)))
+    }
+
+    /// List a directory's entries, in on-disk order
+    pub fn read_dir(&self, path: &str) -> Result<Vec<IsoEntry>, String> {
+        match self.lookup(path)? {
+            Some(e) if e.is_dir => self.list(&e),
+            _ => Err(format!(
+                "Directory '{}' not found in ISO '{}'",
+                path, self.path
+            )),
+        }
+    }
+
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, String> {
+        match self.lookup(path)? {
+            Some(e) if !e.is_dir => self.read_extent(&e),
+            _ => Err(format!("File '{}' not found in ISO '{}'", path, self.path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_read() {
+        let mut writer = IsoWriter::new("cidata");
+        writer
+            .add_file("user-data", b"#cloud-config\n".to_vec())
+            .unwrap();
+        writer.add_file("meta-data", vec![]).unwrap();
+        writer
+            .add_file("network-config-longer-name.yaml", vec![b'x'; 5000])
+            .unwrap();
+        assert!(writer.add_file("meta-data", vec![]).is_err());
+        assert!(writer.add_file("a/b", vec![]).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seed.iso");
+        let path = path.to_str().unwrap();
+        writer.write(path).unwrap();
+        assert_eq!(std::fs::metadata(path).unwrap().len() % 2048, 0);
+
+        let iso = IsoImage::open(path).unwrap();
+        assert_eq!(iso.volume_id(), "cidata");
+        assert_eq!(iso.kind, NameKind::RockRidge);
+        let mut names: Vec<_> = iso
+            .read_dir("/")
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.name, e.size))
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                ("meta-data".to_string(), 0),
+                ("network-config-longer-name.yaml".to_string(), 5000),
+                ("user-data".to_string(), 14),
+            ]
+        );
+        assert_eq!(iso.read_file("user-data").unwrap(), b"#cloud-config\n");
+        assert_eq!(
+            iso.read_file("/network-config-longer-name.yaml")
+                .unwrap()
+                .len(),
+            5000
+        );
+        assert!(iso.read_file("nope").is_err());
+
+        // Without Rock Ridge only the mangled names are left
+        let mut image = iso;
+        image.kind = NameKind::Plain;
+        let names: Vec<_> = image
+            .read_dir("/")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert!(names.contains(&"USER_DAT".to_string()));
+        assert!(image.exists("network_.yam"));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(iso_name("user-data", &[]), "USER_DAT.;1");
+        assert_eq!(iso_name("autounattend.xml", &[]), "AUTOUNAT.XML;1");
+        assert_eq!(
+            iso_name("user-data2", &["USER_DAT.;1".to_string()]),
+            "USER_DA1.;1"
+        );
+        assert_eq!(civil_time(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(civil_time(1_700_000_000), (2023, 11, 14, 22, 13, 20));
+    }
+}
@@ -6,8 +6,64 @@
 pub mod about;
 pub mod addhardware;
 pub mod app;
+pub mod asyncjob;
+pub mod cli;
+pub mod cloner;
+pub mod cloudinit;
+pub mod connection;
+pub mod connevents;
+pub mod console;
+pub mod createnet;
+pub mod createpool;
+pub mod createvm;
+pub mod createvol;
+pub mod details;
+pub mod diskbackend;
+pub mod diskcopy;
+pub mod domain;
+pub mod domcapabilities;
+pub mod framebuffer;
+pub mod generatename;
+pub mod graphwidgets;
+pub mod guest;
+pub mod host;
+pub mod hostnets;
+pub mod hoststorage;
+pub mod installer;
+pub mod installerinject;
+pub mod installertreemedia;
+pub mod iso9660;
+pub mod manager;
+pub mod network;
+pub mod osdict;
+pub mod progress;
+pub mod qcow2;
+pub mod rfb;
+pub mod serialcon;
+pub mod snapshot;
+pub mod snapshots;
+pub mod spice;
+pub mod sshtunnels;
+pub mod statsmanager;
+pub mod storagebrowse;
+pub mod storage;
+pub mod terminal;
+pub mod tmpfile;
+pub mod unattended;
+pub mod uri;
+pub mod urldetect;
+pub mod urlfetcher;
+pub mod viewers;
+pub mod virtclone;
+pub mod virtinstall;
+pub mod virtxml;
+pub mod xmlapi;
+pub mod xslt;
 
 // Re-export main types for easier access
 pub use about::{AboutDialogManager, VmmAbout};
 pub use addhardware::VmmAddHardware;
+pub use createvm::VmmCreateVm;
+pub use details::VmmDetails;
+pub use host::VmmHost;
 pub use app::run as run_main_app;
@@ -0,0 +1,450 @@
+// VM list of the main window (Iced port of virtManager/manager.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Lists the VMs of the connection with their state, and graphs of
+//! their recent CPU, memory, disk and network use in optional columns.
+//! Only the stats of visible columns are polled. Domain events update the
+//! affected row alone, so the list stays live without full refreshes.
+//! Opening a VM starts a details window for it as a separate process,
+//! and so does opening the connection details.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use iced::widget::{
+    Column, Space, button, checkbox, column, container, pick_list, row, scrollable, text,
+};
+use iced::{Alignment, Element, Length, Subscription, Task};
+use log::debug;
+
+use crate::connection::{self, ConnEvent, Connection, DomainInfo, ObjectKind, StatsFlags};
+use crate::connevents;
+use crate::graphwidgets::sparkline;
+use crate::statsmanager::{self, StatsList, StatsManager, StatsOptions, StatsSample};
+
+/// Number of data points in the list graphs
+const GRAPH_LEN: usize = 40;
+const GRAPH_WIDTH: f32 = 120.0;
+/// Choices for the stats update interval, in seconds
+const INTERVALS: [u64; 5] = [1, 2, 3, 5, 10];
+
+/// The optional stats columns of the list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsColumn {
+    GuestCpu,
+    HostCpu,
+    Memory,
+    Disk,
+    Network,
+}
+
+impl StatsColumn {
+    pub const ALL: [Self; 5] = [
+        Self::GuestCpu,
+        Self::HostCpu,
+        Self::Memory,
+        Self::Disk,
+        Self::Network,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::GuestCpu => "CPU usage",
+            Self::HostCpu => "Host CPU usage",
+            Self::Memory => "Memory usage",
+            Self::Disk => "Disk I/O",
+            Self::Network => "Network I/O",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Refresh,
+    Select(String),
+    Open,
+    /// Start the connection details window
+    OpenHost,
+    ShowColumn(StatsColumn, bool),
+    IntervalChanged(u64),
+    Stats(StatsSample),
+    Event(ConnEvent),
+    /// A VM re-read after an event about it
+    VmLookedUp(String, Result<DomainInfo, String>),
+}
+
+pub struct ManagerPage {
+    uri: Option<String>,
+    conn: Option<Arc<dyn Connection>>,
+    vms: Vec<DomainInfo>,
+    selected: Option<String>,
+    /// Visible stats columns, in display order
+    columns: Vec<StatsColumn>,
+    /// Stats update interval in seconds
+    interval: u64,
+    stats: StatsManager,
+    /// Highest rates of all VMs, which the list graphs share as scale
+    max_disk_rate: f64,
+    max_net_rate: f64,
+    error: Option<String>,
+}
+
+impl ManagerPage {
+    /// Connect to `uri`, or virsh's default. Failing to connect leaves
+    /// an empty list and the error.
+    pub fn new(uri: Option<&str>) -> Self {
+        let mut page = Self::new_empty(uri);
+        match connection::open(uri) {
+            Ok(conn) => {
+                page.conn = Some(Arc::from(conn));
+                page.refresh();
+            }
+            Err(e) => {
+                page.error = Some(format!(
+                    "Unable to connect to libvirt {}.\n\n{}",
+                    uri.unwrap_or("(default)"),
+                    e
+                ));
+            }
+        }
+        page
+    }
+
+    pub fn from_connection(conn: Arc<dyn Connection>) -> Self {
+        let mut page = Self::new_empty(Some(conn.uri()));
+        page.conn = Some(conn);
+        page.refresh();
+        page
+    }
+
+    fn new_empty(uri: Option<&str>) -> Self {
+        let stats = StatsManager::new();
+        Self {
+            uri: uri.map(str::to_string),
+            conn: None,
+            vms: vec![],
+            selected: None,
+            columns: vec![StatsColumn::GuestCpu],
+            interval: StatsOptions::default().interval.as_secs(),
+            max_disk_rate: stats.max_disk_rate(),
+            max_net_rate: stats.max_net_rate(),
+            stats,
+            error: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let Some(conn) = &self.conn else {
+            return;
+        };
+        match conn.list_domains() {
+            Ok(mut vms) => {
+                vms.sort_by(|a, b| a.name.cmp(&b.name));
+                self.vms = vms;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(format!("Error listing VMs: {}", e)),
+        }
+    }
+
+    /// Re-read one VM after an event about it, off the UI thread
+    fn refresh_vm(&self, name: String) -> Task<Message> {
+        let Some(conn) = self.conn.clone() else {
+            return Task::none();
+        };
+        Task::perform(
+            async move {
+                let lookup = name.clone();
+                let ret = tokio::task::spawn_blocking(move || conn.lookup_domain(&lookup))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|r| r);
+                (name, ret)
+            },
+            |(name, ret)| Message::VmLookedUp(name, ret),
+        )
+    }
+
+    /// Update the row of a re-read VM. A VM that can't be looked up
+    /// anymore is gone.
+    fn update_vm(&mut self, name: &str, lookup: Result<DomainInfo, String>) {
+        let pos = self.vms.binary_search_by(|vm| vm.name.as_str().cmp(name));
+        match (lookup, pos) {
+            (Ok(info), Ok(idx)) => self.vms[idx] = info,
+            (Ok(info), Err(idx)) => self.vms.insert(idx, info),
+            (Err(_), Ok(idx)) => {
+                debug!("VM '{}' is gone", name);
+                self.vms.remove(idx);
+                if self.selected.as_deref() == Some(name) {
+                    self.selected = None;
+                }
+            }
+            (Err(_), Err(_)) => {}
+        }
+    }
+
+    /// What the visible columns need polled
+    pub fn stats_options(&self) -> StatsOptions {
+        let shown = |cols: &[StatsColumn]| cols.iter().any(|c| self.columns.contains(c));
+        StatsOptions {
+            interval: Duration::from_secs(self.interval),
+            flags: StatsFlags {
+                cpu: shown(&[StatsColumn::GuestCpu, StatsColumn::HostCpu]),
+                memory: shown(&[StatsColumn::Memory]),
+                disk: shown(&[StatsColumn::Disk]),
+                net: shown(&[StatsColumn::Network]),
+            },
+        }
+    }
+
+    /// Take the VM states of a sample, so the list follows VMs starting
+    /// and stopping between refreshes
+    fn update_states(&mut self, sample: &StatsSample) {
+        let Ok(allstats) = &sample.stats else {
+            return;
+        };
+        for vm in &mut self.vms {
+            if let Some(stats) = allstats.iter().find(|s| s.name == vm.name) {
+                vm.state = stats.state;
+            }
+        }
+    }
+
+    /// Start another window of ours for the connection, showing what
+    /// `args` ask for
+    fn launch(&self, what: &str, args: &[&str]) -> Result<(), String> {
+        let exe = std::env::current_exe()
+            .map_err(|e| format!("Error launching {} window: {}", what, e))?;
+        let mut cmd = std::process::Command::new(exe);
+        if let Some(conn) = &self.conn {
+            cmd.args(["--connect", conn.uri()]);
+        }
+        cmd.args(args);
+        debug!("Launching {} window: {:?}", what, cmd);
+        cmd.spawn()
+            .map(|_| ())
+            .map_err(|e| format!("Error launching {} window: {}", what, e))
+    }
+
+    /// Start a details window for the selected VM
+    fn open_selected(&self) -> Result<(), String> {
+        let Some(name) = &self.selected else {
+            return Ok(());
+        };
+        self.launch("details", &["--show-domain-editor", name])
+    }
+
+    pub fn update(&mut self, msg: Message) -> Task<Message> {
+        match msg {
+            Message::Refresh => self.refresh(),
+            Message::Select(name) => self.selected = Some(name),
+            Message::Open => {
+                if let Err(e) = self.open_selected() {
+                    self.error = Some(e);
+                }
+            }
+            Message::OpenHost => {
+                if let Err(e) = self.launch("connection details", &["--show-host-summary"]) {
+                    self.error = Some(e);
+                }
+            }
+            Message::ShowColumn(col, show) => {
+                self.columns.retain(|c| *c != col);
+                if show {
+                    self.columns.push(col);
+                    self.columns
+                        .sort_by_key(|c| StatsColumn::ALL.iter().position(|a| a == c));
+                }
+            }
+            Message::IntervalChanged(secs) => self.interval = secs,
+            Message::Stats(sample) => {
+                self.update_states(&sample);
+                self.stats.append_sample(&sample);
+                self.max_disk_rate = self.stats.max_disk_rate();
+                self.max_net_rate = self.stats.max_net_rate();
+            }
+            Message::Event(event) => {
+                if event.kind() == ObjectKind::Domain {
+                    return self.refresh_vm(event.name().to_string());
+                }
+            }
+            Message::VmLookedUp(name, ret) => self.update_vm(&name, ret),
+        }
+        Task::none()
+    }
+
+    fn graph(&self, col: StatsColumn, stats: &StatsList) -> Element<'_, Message> {
+        let limit = Some(GRAPH_LEN);
+        // Reads and writes, or in and out, share one line
+        let average = |(a, b): (Vec<f32>, Vec<f32>)| {
+            a.iter()
+                .zip(&b)
+                .map(|(x, y)| (x + y) / 2.0)
+                .collect::<Vec<f32>>()
+        };
+        let data = match col {
+            StatsColumn::GuestCpu => stats.guest_cpu_vector(limit),
+            StatsColumn::HostCpu => stats.host_cpu_vector(limit),
+            StatsColumn::Memory => stats.memory_vector(limit),
+            StatsColumn::Disk => average(stats.disk_io_vectors(limit, Some(self.max_disk_rate))),
+            StatsColumn::Network => {
+                average(stats.network_traffic_vectors(limit, Some(self.max_net_rate)))
+            }
+        };
+        sparkline(data).width(GRAPH_WIDTH).height(28).into()
+    }
+
+    fn view_row(&self, vm: &DomainInfo) -> Element<'_, Message> {
+        let stats = self.stats.vm_stats(&vm.name);
+        let mut line = row![
+            column![text(vm.name.clone()), text(vm.state.label()).size(12)].width(Length::Fill)
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+        for col in &self.columns {
+            line = line.push(self.graph(*col, stats));
+        }
+        let style = if self.selected.as_ref() == Some(&vm.name) {
+            button::primary
+        } else {
+            button::text
+        };
+        button(line)
+            .style(style)
+            .padding(4)
+            .width(Length::Fill)
+            .on_press(Message::Select(vm.name.clone()))
+            .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut options = row![text("View:")].spacing(12).align_y(Alignment::Center);
+        for col in StatsColumn::ALL {
+            options = options.push(
+                checkbox(col.label(), self.columns.contains(&col))
+                    .on_toggle(move |show| Message::ShowColumn(col, show)),
+            );
+        }
+        options = options.push(Space::with_width(Length::Fill));
+        options = options.push(text("Update interval (seconds):"));
+        options = options.push(pick_list(
+            INTERVALS,
+            Some(self.interval),
+            Message::IntervalChanged,
+        ));
+
+        let mut header = row![text("Name").width(Length::Fill)]
+            .spacing(10)
+            .padding([0, 4]);
+        for col in &self.columns {
+            header = header.push(text(col.label()).width(GRAPH_WIDTH));
+        }
+
+        let mut list: Column<Message> = column![].spacing(2);
+        for vm in &self.vms {
+            list = list.push(self.view_row(vm));
+        }
+        if self.vms.is_empty() && self.error.is_none() {
+            list = list.push(text("No virtual machines"));
+        }
+
+        let actions = row![
+            button(text("Open")).on_press_maybe(self.selected.as_ref().map(|_| Message::Open)),
+            button(text("Refresh")).on_press_maybe(self.conn.as_ref().map(|_| Message::Refresh)),
+            button(text("Connection Details"))
+                .on_press_maybe(self.conn.as_ref().map(|_| Message::OpenHost)),
+            Space::with_width(Length::Fill),
+            text(self.uri.clone().unwrap_or_default()).size(12),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+
+        let mut col = column![options, actions, header].spacing(8);
+        if let Some(err) = &self.error {
+            col = col.push(text(err.clone()));
+        }
+        col.push(container(scrollable(list)).height(Length::Fill))
+            .into()
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        let Some(conn) = &self.conn else {
+            return Subscription::none();
+        };
+        let events = connevents::watch(conn.clone()).map(Message::Event);
+        let options = self.stats_options();
+        if options.flags.is_empty() {
+            return events;
+        }
+        Subscription::batch([
+            events,
+            statsmanager::poll(conn.clone(), options).map(Message::Stats),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{DomainState, Lifecycle, testdriver};
+
+    #[test]
+    fn test_manager_stats_columns() {
+        let conn = testdriver();
+        let conn: Arc<dyn Connection> = Arc::new(conn);
+        let mut page = ManagerPage::from_connection(conn.clone());
+        assert!(page.vms.iter().any(|vm| vm.name == "test"));
+        assert!(page.vms.windows(2).all(|w| w[0].name <= w[1].name));
+
+        // Only the visible columns get polled
+        let opts = page.stats_options();
+        assert!(opts.flags.cpu && !opts.flags.disk && !opts.flags.memory);
+        let _ = page.update(Message::ShowColumn(StatsColumn::Network, true));
+        let _ = page.update(Message::ShowColumn(StatsColumn::Memory, true));
+        let _ = page.update(Message::ShowColumn(StatsColumn::GuestCpu, false));
+        assert_eq!(page.columns, [StatsColumn::Memory, StatsColumn::Network]);
+        let opts = page.stats_options();
+        assert!(!opts.flags.cpu && opts.flags.memory && opts.flags.net);
+        let _ = page.update(Message::IntervalChanged(10));
+        assert_eq!(page.stats_options().interval, Duration::from_secs(10));
+
+        // Samples update the graphs and the VM states
+        conn.destroy_domain("test-many-devices").unwrap();
+        let sample = StatsSample::take(conn.as_ref(), opts.flags, 4);
+        let _ = page.update(Message::Stats(sample));
+        let vm = page.vms.iter().find(|vm| vm.name == "test-many-devices");
+        assert_eq!(vm.unwrap().state, DomainState::Shutoff);
+        assert!(page.stats.vm_stats("test").current().curmem > 0);
+
+        // Events update single rows
+        let xml = conn.domain_xml("test-state-shutoff", true).unwrap();
+        let newxml = xml
+            .replace("<name>test-state-shutoff</name>", "<name>aaa-new</name>")
+            .replace(&conn.lookup_domain("test-state-shutoff").unwrap().uuid, "");
+        let newxml = newxml.replace("<uuid></uuid>", "");
+        conn.define_xml(&newxml).unwrap();
+        conn.undefine_domain("test-state-shutoff").unwrap();
+        page.selected = Some("test-state-shutoff".into());
+        for (name, ev) in [
+            ("aaa-new", Lifecycle::Defined),
+            ("test-state-shutoff", Lifecycle::Undefined),
+        ] {
+            let event = ConnEvent::Lifecycle(ObjectKind::Domain, name.into(), ev);
+            let _ = page.update(Message::Event(event));
+            // The lookup task result, as the runtime would deliver it
+            let _ = page.update(Message::VmLookedUp(name.into(), conn.lookup_domain(name)));
+        }
+        assert_eq!(page.vms[0].name, "aaa-new");
+        assert!(!page.vms.iter().any(|vm| vm.name == "test-state-shutoff"));
+        assert_eq!(page.selected, None);
+
+        let page = ManagerPage::new(Some("test:///idontexist.xml"));
+        assert!(
+            page.error
+                .unwrap()
+                .starts_with("Unable to connect to libvirt")
+        );
+    }
+}
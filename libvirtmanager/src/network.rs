@@ -0,0 +1,879 @@
+// Virtual network XML (port of virtinst/network.py and the address
+// helpers of virtManager/object/network.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `<network>` covering what the network wizard builds and the host
+//! networks panel shows. Like the domain models, each part reads itself
+//! with `from_xml` and `get_xml` writes the fields back over the parsed
+//! document, so elements the model doesn't know are kept.
+//!
+//! This departs from the serde structs of `storage` and `snapshot`. Those
+//! build new objects, and existing ones are edited as XML text or, for a
+//! snapshot description, a single `xmlapi` change. Networks are edited
+//! field by field from the host networks panel, and quick-xml's serde
+//! can't carry unknown children through a struct, so a serde model would
+//! drop `<dns>` hosts, `<portgroup>`s, `<bandwidth>` and the like on
+//! every edit. The `network-*-in.xml` fixtures round-trip through this
+//! model instead.
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::xmlapi::Element;
+
+/// An optional numeric attribute, failing on values that don't parse
+fn parse_attr<T: FromStr>(el: &Element, xpath: &str) -> Result<Option<T>, String> {
+    el.get(xpath)
+        .map(|v| {
+            v.parse()
+                .map_err(|_| format!("Invalid value '{}' for {}", v, xpath))
+        })
+        .transpose()
+}
+
+fn number_attr<T: ToString>(val: Option<T>) -> Option<String> {
+    val.map(|v| v.to_string())
+}
+
+/// Write `model` over the `name` child, creating it if needed, or remove
+/// the child for None
+fn apply_child<T>(
+    parent: &mut Element,
+    name: &str,
+    model: Option<&T>,
+    apply: fn(&T, &mut Element),
+) {
+    let xpath = format!("./{}", name);
+    match model {
+        Some(model) => {
+            parent.set_bool(&xpath, true);
+            apply(
+                model,
+                parent.find_mut(&xpath).expect("child was just created"),
+            );
+        }
+        None => parent.force_remove(&xpath),
+    }
+}
+
+/// Write `models` over the `name` children in order, adding or removing
+/// children so the counts match
+fn apply_list<T>(parent: &mut Element, name: &str, models: &[T], apply: fn(&T, &mut Element)) {
+    for (i, model) in models.iter().enumerate() {
+        let xpath = format!("./{}[{}]", name, i + 1);
+        parent.set_bool(&xpath, true);
+        apply(
+            model,
+            parent.find_mut(&xpath).expect("child was just created"),
+        );
+    }
+    while parent.count(&format!("./{}", name)) > models.len() {
+        parent.force_remove(&format!("./{}[{}]", name, models.len() + 1));
+    }
+}
+
+fn parse_list<T>(
+    parent: &Element,
+    name: &str,
+    from_xml: fn(&Element) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    parent
+        .find_all(&format!("./{}", name))
+        .into_iter()
+        .map(from_xml)
+        .collect()
+}
+
+fn parse_child<T>(
+    parent: &Element,
+    name: &str,
+    from_xml: fn(&Element) -> Result<T, String>,
+) -> Result<Option<T>, String> {
+    parent
+        .find(&format!("./{}", name))
+        .map(from_xml)
+        .transpose()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkDhcpRange {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+impl NetworkDhcpRange {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            start: el.get("./@start"),
+            end: el.get("./@end"),
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@start", self.start.as_deref());
+        el.set("./@end", self.end.as_deref());
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkDhcpHost {
+    pub macaddr: Option<String>,
+    /// DHCPv6 hosts go by DUID rather than MAC address
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl NetworkDhcpHost {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            macaddr: el.get("./@mac"),
+            id: el.get("./@id"),
+            name: el.get("./@name"),
+            ip: el.get("./@ip"),
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@mac", self.macaddr.as_deref());
+        el.set("./@id", self.id.as_deref());
+        el.set("./@name", self.name.as_deref());
+        el.set("./@ip", self.ip.as_deref());
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkBootp {
+    pub file: Option<String>,
+    pub server: Option<String>,
+}
+
+impl NetworkBootp {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            file: el.get("./@file"),
+            server: el.get("./@server"),
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@file", self.file.as_deref());
+        el.set("./@server", self.server.as_deref());
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkDhcp {
+    pub ranges: Vec<NetworkDhcpRange>,
+    pub hosts: Vec<NetworkDhcpHost>,
+    pub bootp: Option<NetworkBootp>,
+}
+
+impl NetworkDhcp {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            ranges: parse_list(el, "range", NetworkDhcpRange::from_xml)?,
+            hosts: parse_list(el, "host", NetworkDhcpHost::from_xml)?,
+            bootp: parse_child(el, "bootp", NetworkBootp::from_xml)?,
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        apply_list(el, "range", &self.ranges, NetworkDhcpRange::apply);
+        apply_list(el, "host", &self.hosts, NetworkDhcpHost::apply);
+        apply_child(el, "bootp", self.bootp.as_ref(), NetworkBootp::apply);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkTftp {
+    pub root: String,
+}
+
+impl NetworkTftp {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            root: el.get("./@root").unwrap_or_default(),
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@root", Some(&self.root));
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkIp {
+    /// "ipv4" or "ipv6", unset meaning ipv4
+    pub family: Option<String>,
+    pub address: Option<String>,
+    pub prefix: Option<u8>,
+    pub netmask: Option<String>,
+    pub via: Option<String>,
+    pub dhcp: Option<NetworkDhcp>,
+    pub tftp: Option<NetworkTftp>,
+}
+
+impl NetworkIp {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            family: el.get("./@family"),
+            address: el.get("./@address"),
+            prefix: parse_attr(el, "./@prefix")?,
+            netmask: el.get("./@netmask"),
+            via: el.get("./@via"),
+            dhcp: parse_child(el, "dhcp", NetworkDhcp::from_xml)?,
+            tftp: parse_child(el, "tftp", NetworkTftp::from_xml)?,
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@family", self.family.as_deref());
+        el.set("./@address", self.address.as_deref());
+        el.set("./@prefix", number_attr(self.prefix).as_deref());
+        el.set("./@netmask", self.netmask.as_deref());
+        el.set("./@via", self.via.as_deref());
+        apply_child(el, "dhcp", self.dhcp.as_ref(), NetworkDhcp::apply);
+        apply_child(el, "tftp", self.tftp.as_ref(), NetworkTftp::apply);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkRoute {
+    pub family: Option<String>,
+    pub address: Option<String>,
+    pub prefix: Option<u8>,
+    pub netmask: Option<String>,
+    pub gateway: Option<String>,
+}
+
+impl NetworkRoute {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            family: el.get("./@family"),
+            address: el.get("./@address"),
+            prefix: parse_attr(el, "./@prefix")?,
+            netmask: el.get("./@netmask"),
+            gateway: el.get("./@gateway"),
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@family", self.family.as_deref());
+        el.set("./@address", self.address.as_deref());
+        el.set("./@prefix", number_attr(self.prefix).as_deref());
+        el.set("./@netmask", self.netmask.as_deref());
+        el.set("./@gateway", self.gateway.as_deref());
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkForwardPf {
+    pub dev: String,
+}
+
+impl NetworkForwardPf {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            dev: el.get("./@dev").unwrap_or_default(),
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@dev", Some(&self.dev));
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkForward {
+    /// "nat", "route", "open", "bridge", "hostdev", ..., unset meaning
+    /// an isolated network
+    pub mode: Option<String>,
+    pub dev: Option<String>,
+    pub managed: Option<String>,
+    pub pf: Vec<NetworkForwardPf>,
+}
+
+impl NetworkForward {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            mode: el.get("./@mode"),
+            dev: el.get("./@dev"),
+            managed: el.get("./@managed"),
+            pf: parse_list(el, "pf", NetworkForwardPf::from_xml)?,
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@mode", self.mode.as_deref());
+        el.set("./@dev", self.dev.as_deref());
+        el.set("./@managed", self.managed.as_deref());
+        apply_list(el, "pf", &self.pf, NetworkForwardPf::apply);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkBridge {
+    pub name: Option<String>,
+    /// "on" or "off"
+    pub stp: Option<String>,
+    pub delay: Option<u32>,
+}
+
+impl NetworkBridge {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            name: el.get("./@name"),
+            stp: el.get("./@stp"),
+            delay: parse_attr(el, "./@delay")?,
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@name", self.name.as_deref());
+        el.set("./@stp", self.stp.as_deref());
+        el.set("./@delay", number_attr(self.delay).as_deref());
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkDomain {
+    pub name: String,
+}
+
+impl NetworkDomain {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            name: el.get("./@name").unwrap_or_default(),
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@name", Some(&self.name));
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkMac {
+    pub address: String,
+}
+
+impl NetworkMac {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            address: el.get("./@address").unwrap_or_default(),
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@address", Some(&self.address));
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VirtualPortParameters {
+    pub profileid: Option<String>,
+    pub interfaceid: Option<String>,
+}
+
+impl VirtualPortParameters {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            profileid: el.get("./@profileid"),
+            interfaceid: el.get("./@interfaceid"),
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@profileid", self.profileid.as_deref());
+        el.set("./@interfaceid", self.interfaceid.as_deref());
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VirtualPort {
+    pub vtype: Option<String>,
+    pub parameters: Option<VirtualPortParameters>,
+}
+
+impl VirtualPort {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            vtype: el.get("./@type"),
+            parameters: parse_child(el, "parameters", VirtualPortParameters::from_xml)?,
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@type", self.vtype.as_deref());
+        apply_child(
+            el,
+            "parameters",
+            self.parameters.as_ref(),
+            VirtualPortParameters::apply,
+        );
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkPortgroup {
+    pub name: String,
+    /// "yes" or "no"
+    pub default: Option<String>,
+    pub virtualport: Option<VirtualPort>,
+}
+
+impl NetworkPortgroup {
+    fn from_xml(el: &Element) -> Result<Self, String> {
+        Ok(Self {
+            name: el.get("./@name").unwrap_or_default(),
+            default: el.get("./@default"),
+            virtualport: parse_child(el, "virtualport", VirtualPort::from_xml)?,
+        })
+    }
+
+    fn apply(&self, el: &mut Element) {
+        el.set("./@name", Some(&self.name));
+        el.set("./@default", self.default.as_deref());
+        apply_child(
+            el,
+            "virtualport",
+            self.virtualport.as_ref(),
+            VirtualPort::apply,
+        );
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Network {
+    /// "yes" enables IPv6 between guests of a network without IPv6
+    /// addresses
+    pub ipv6: Option<String>,
+    pub name: String,
+    pub uuid: Option<String>,
+    pub forward: Option<NetworkForward>,
+    pub bridge: Option<NetworkBridge>,
+    pub mac: Option<NetworkMac>,
+    pub virtualport: Option<VirtualPort>,
+    pub domain: Option<NetworkDomain>,
+    pub ips: Vec<NetworkIp>,
+    pub routes: Vec<NetworkRoute>,
+    pub portgroups: Vec<NetworkPortgroup>,
+    /// The parsed document, which `get_xml` writes the fields over
+    xml: Element,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self {
+            ipv6: None,
+            name: String::new(),
+            uuid: None,
+            forward: None,
+            bridge: None,
+            mac: None,
+            virtualport: None,
+            domain: None,
+            ips: vec![],
+            routes: vec![],
+            portgroups: vec![],
+            xml: Element::new("network"),
+        }
+    }
+}
+
+impl Network {
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        let err = |e: String| format!("Error parsing network XML: {}", e);
+        let el = Element::parse(xml).map_err(err)?;
+        if el.name != "network" {
+            return Err(err(format!(
+                "XML did not have expected root element name 'network', found '{}'",
+                el.name
+            )));
+        }
+        Self::from_xml(el).map_err(err)
+    }
+
+    fn from_xml(xml: Element) -> Result<Self, String> {
+        Ok(Self {
+            ipv6: xml.get("./@ipv6"),
+            name: xml.get("./name").unwrap_or_default(),
+            uuid: xml.get("./uuid"),
+            forward: parse_child(&xml, "forward", NetworkForward::from_xml)?,
+            bridge: parse_child(&xml, "bridge", NetworkBridge::from_xml)?,
+            mac: parse_child(&xml, "mac", NetworkMac::from_xml)?,
+            virtualport: parse_child(&xml, "virtualport", VirtualPort::from_xml)?,
+            domain: parse_child(&xml, "domain", NetworkDomain::from_xml)?,
+            ips: parse_list(&xml, "ip", NetworkIp::from_xml)?,
+            routes: parse_list(&xml, "route", NetworkRoute::from_xml)?,
+            portgroups: parse_list(&xml, "portgroup", NetworkPortgroup::from_xml)?,
+            xml,
+        })
+    }
+
+    pub fn get_xml(&self) -> String {
+        let mut root = self.xml.clone();
+        let new = !root.has_content();
+        root.set("./@ipv6", self.ipv6.as_deref());
+        root.set(
+            "./name",
+            (!self.name.is_empty()).then_some(self.name.as_str()),
+        );
+        root.set("./uuid", self.uuid.as_deref());
+        apply_child(
+            &mut root,
+            "forward",
+            self.forward.as_ref(),
+            NetworkForward::apply,
+        );
+        apply_child(
+            &mut root,
+            "bridge",
+            self.bridge.as_ref(),
+            NetworkBridge::apply,
+        );
+        apply_child(
+            &mut root,
+            "virtualport",
+            self.virtualport.as_ref(),
+            VirtualPort::apply,
+        );
+        apply_child(&mut root, "mac", self.mac.as_ref(), NetworkMac::apply);
+        apply_child(
+            &mut root,
+            "domain",
+            self.domain.as_ref(),
+            NetworkDomain::apply,
+        );
+        apply_list(&mut root, "ip", &self.ips, NetworkIp::apply);
+        apply_list(&mut root, "route", &self.routes, NetworkRoute::apply);
+        apply_list(
+            &mut root,
+            "portgroup",
+            &self.portgroups,
+            NetworkPortgroup::apply,
+        );
+        // Nested elements added to an empty document aren't indented
+        if new {
+            root.prettify();
+        }
+        root.get_xml()
+    }
+
+    pub fn forward_mode(&self) -> Option<&str> {
+        self.forward.as_ref()?.mode.as_deref()
+    }
+
+    pub fn forward_dev(&self) -> Option<&str> {
+        self.forward.as_ref()?.dev.as_deref()
+    }
+
+    pub fn bridge_name(&self) -> Option<&str> {
+        self.bridge.as_ref()?.name.as_deref()
+    }
+
+    pub fn domain_name(&self) -> Option<&str> {
+        self.domain.as_ref().map(|d| d.name.as_str())
+    }
+
+    pub fn ipv6_enabled(&self) -> bool {
+        self.ipv6.as_deref() == Some("yes")
+    }
+
+    /// Whether guests can PXE boot off the network
+    pub fn can_pxe(&self) -> bool {
+        if self.forward_mode().is_some_and(|m| m != "nat") {
+            return true;
+        }
+        self.ips.iter().any(|ip| {
+            ip.dhcp
+                .as_ref()
+                .and_then(|d| d.bootp.as_ref())
+                .is_some_and(|b| b.file.is_some())
+        })
+    }
+
+    /// The subnet of `family` ("ipv4" or "ipv6") as "address/prefix",
+    /// and its first DHCP range. Addresses with a DHCP range win.
+    pub fn ip_network(&self, family: &str) -> (Option<String>, Option<(String, String)>) {
+        let matches = |ip: &&NetworkIp| {
+            ip.family.as_deref() == Some(family) || (family == "ipv4" && ip.family.is_none())
+        };
+        let first_range = |ip: &NetworkIp| {
+            let range = ip.dhcp.as_ref()?.ranges.first()?;
+            Some((range.start.clone()?, range.end.clone()?))
+        };
+        let ip = self
+            .ips
+            .iter()
+            .filter(matches)
+            .find(|ip| first_range(ip).is_some())
+            .or_else(|| self.ips.iter().find(matches));
+        let Some(ip) = ip else {
+            return (None, None);
+        };
+        let subnet = ip.address.as_deref().and_then(|addr| {
+            let prefix = match (ip.prefix, ip.netmask.as_deref()) {
+                (Some(prefix), _) => prefix,
+                (None, Some(mask)) => netmask_prefix(mask)?,
+                (None, None) => {
+                    if addr.contains(':') {
+                        128
+                    } else {
+                        32
+                    }
+                }
+            };
+            let (net, prefix) = parse_ip_network(&format!("{}/{}", addr, prefix))?;
+            Some(format!("{}/{}", net, prefix))
+        });
+        (subnet, first_range(ip))
+    }
+
+    /// "NAT to eth0", "Isolated network" and the like
+    pub fn pretty_forward_mode(&self) -> String {
+        let dev = self.forward_dev();
+        match (self.forward_mode(), dev) {
+            (None, _) => "Isolated network".to_string(),
+            (Some("nat"), Some(dev)) => format!("NAT to {}", dev),
+            (Some("nat"), None) => "NAT".to_string(),
+            (Some("route"), Some(dev)) => format!("Route to {}", dev),
+            (Some("route"), None) => "Routed network".to_string(),
+            (Some(mode), _) => {
+                let mut chars = mode.chars();
+                let first = chars.next().map(|c| c.to_uppercase().to_string());
+                format!("{}{} network", first.unwrap_or_default(), chars.as_str())
+            }
+        }
+    }
+}
+
+/// Prefix length of a netmask like 255.255.255.0
+pub fn netmask_prefix(mask: &str) -> Option<u8> {
+    let bits = match mask.parse::<IpAddr>().ok()? {
+        IpAddr::V4(a) => u128::from(u32::from(a)) << 96,
+        IpAddr::V6(a) => u128::from(a),
+    };
+    let prefix = bits.leading_ones();
+    // Only contiguous masks are valid
+    (bits.checked_shl(prefix).unwrap_or(0) == 0).then_some(prefix as u8)
+}
+
+/// Netmask of an IPv4 prefix length
+pub fn prefix_netmask(prefix: u8) -> String {
+    let bits = u32::MAX
+        .checked_shl(32 - u32::from(prefix.min(32)))
+        .unwrap_or(0);
+    std::net::Ipv4Addr::from(bits).to_string()
+}
+
+/// Parse "address/prefix", or a plain address, into the network
+/// address and prefix length, like python's non-strict ip_network
+pub fn parse_ip_network(text: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match text.trim().split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (text.trim(), None),
+    };
+    let addr = addr.parse::<IpAddr>().ok()?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(p) => p.parse::<u8>().ok().filter(|p| *p <= max)?,
+        None => max,
+    };
+    Some((ip_add(addr, 0, prefix), prefix))
+}
+
+/// Address `offset` hosts into the network of `addr`/`prefix`
+pub fn ip_add(addr: IpAddr, offset: u128, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(a) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V4((u32::from(a) & mask).wrapping_add(offset as u32).into())
+        }
+        IpAddr::V6(a) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V6((u128::from(a) & mask).wrapping_add(offset).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(name: &str) -> String {
+        std::fs::read_to_string(format!(
+            "{}/../tests/data/xmlparse/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        ))
+        .unwrap()
+    }
+
+    fn check_out(net: &Network, basename: &str) {
+        let xml = net.get_xml();
+        assert_eq!(xml, data(&format!("{}-out.xml", basename)));
+        assert_eq!(Network::parse(&xml).unwrap().get_xml(), xml);
+    }
+
+    #[test]
+    fn test_network_multi() {
+        let mut net = Network::parse(&data("network-multi-in.xml")).unwrap();
+        assert_eq!(net.name, "ipv6_multirange");
+        net.name = "new-foo".into();
+        net.uuid = Some("41b4afe4-87bb-8087-6724-5e208a2d1111".into());
+        let bridge = net.bridge.as_mut().unwrap();
+        assert_eq!(bridge.name.as_deref(), Some("virbr3"));
+        assert_eq!((bridge.stp.as_deref(), bridge.delay), (Some("on"), Some(0)));
+        bridge.name = Some("virbr3new".into());
+        bridge.stp = Some("off".into());
+        bridge.delay = Some(2);
+        assert_eq!(net.domain_name(), Some("net7"));
+        net.domain = Some(NetworkDomain {
+            name: "newdom".into(),
+        });
+        assert!(!net.ipv6_enabled());
+        net.ipv6 = Some("yes".into());
+        net.mac = Some(NetworkMac {
+            address: "52:54:00:69:eb:FF".into(),
+        });
+        net.virtualport = Some(VirtualPort {
+            vtype: Some("openvswitch".into()),
+            parameters: None,
+        });
+
+        assert_eq!(net.portgroups.len(), 2);
+        let pg = &mut net.portgroups[0];
+        assert_eq!(
+            (pg.name.as_str(), pg.default.as_deref()),
+            ("engineering", Some("yes"))
+        );
+        pg.name = "foo".into();
+        pg.default = Some("no".into());
+
+        assert_eq!(net.ips.len(), 4);
+        assert_eq!(
+            net.ip_network("ipv4"),
+            (
+                Some("192.168.7.0/24".into()),
+                Some(("192.168.7.128".into(), "192.168.7.254".into()))
+            )
+        );
+        let ip = &mut net.ips[0];
+        ip.address = Some("192.168.8.1".into());
+        ip.netmask = Some("255.255.254.0".into());
+        assert!(!net.can_pxe());
+        let ip = &mut net.ips[0];
+        ip.tftp = Some(NetworkTftp {
+            root: "/var/lib/tftproot".into(),
+        });
+        let dhcp = ip.dhcp.as_mut().unwrap();
+        dhcp.bootp = Some(NetworkBootp {
+            file: Some("pxeboot.img".into()),
+            server: Some("1.2.3.4".into()),
+        });
+        assert!(net.can_pxe());
+
+        assert_eq!(net.forward_mode(), Some("nat"));
+        net.forward = Some(NetworkForward {
+            mode: Some("route".into()),
+            dev: Some("eth22".into()),
+            ..Default::default()
+        });
+        assert_eq!(net.pretty_forward_mode(), "Route to eth22");
+
+        let dhcp = net.ips[0].dhcp.as_mut().unwrap();
+        dhcp.ranges[0] = NetworkDhcpRange {
+            start: Some("192.168.8.128".into()),
+            end: Some("192.168.8.254".into()),
+        };
+        let host = &mut dhcp.hosts[1];
+        assert_eq!(host.macaddr.as_deref(), Some("52:54:00:69:eb:91"));
+        host.macaddr = Some("52:54:00:69:eb:92".into());
+        host.name = Some("newname".into());
+        host.ip = Some("192.168.8.3".into());
+
+        assert_eq!(net.ips[1].family.as_deref(), Some("ipv6"));
+        assert_eq!(net.ips[1].prefix, Some(64));
+        net.ips[1].prefix = Some(63);
+        assert_eq!(
+            net.ip_network("ipv6").0.as_deref(),
+            Some("fd00:beef:10:6::/63")
+        );
+
+        net.routes.push(NetworkRoute {
+            family: Some("ipv4".into()),
+            address: Some("192.168.8.0".into()),
+            prefix: Some(24),
+            gateway: Some("192.168.8.10".into()),
+            ..Default::default()
+        });
+        check_out(&net, "network-multi");
+    }
+
+    #[test]
+    fn test_network_open_and_vf_pool() {
+        let mut net = Network::parse(&data("network-open-in.xml")).unwrap();
+        assert_eq!(net.pretty_forward_mode(), "Open network");
+        assert_eq!(net.forward_dev(), None);
+        net.name = "new-foo".into();
+        net.domain = Some(NetworkDomain {
+            name: "newdom".into(),
+        });
+        let ip = &mut net.ips[0];
+        assert_eq!(ip.address.as_deref(), Some("192.168.100.1"));
+        ip.address = Some("192.168.101.1".into());
+        ip.netmask = Some("255.255.254.0".into());
+        ip.dhcp.as_mut().unwrap().ranges[0] = NetworkDhcpRange {
+            start: Some("192.168.101.128".into()),
+            end: Some("192.168.101.254".into()),
+        };
+        check_out(&net, "network-open");
+
+        let mut net = Network::parse(&data("network-vf-pool-in.xml")).unwrap();
+        let forward = net.forward.as_ref().unwrap();
+        assert_eq!(forward.mode.as_deref(), Some("hostdev"));
+        assert_eq!(forward.managed.as_deref(), Some("yes"));
+        assert_eq!(forward.pf[0].dev, "eth3");
+        net.name = "new-foo".into();
+        check_out(&net, "network-vf-pool");
+    }
+
+    #[test]
+    fn test_unknown_elements_kept() {
+        let mut net = Network::parse(
+            "<network>\n  <name>foo</name>\n  <mtu size=\"9000\"/>\n  \
+             <ip address=\"10.0.0.1\" prefix=\"24\">\n    <dns enable=\"no\"/>\n  </ip>\n\
+             </network>",
+        )
+        .unwrap();
+        net.name = "bar".into();
+        net.ips[0].prefix = Some(16);
+        net.ips[0].dhcp = Some(NetworkDhcp::default());
+        assert_eq!(
+            net.get_xml(),
+            "<network>\n  <name>bar</name>\n  <mtu size=\"9000\"/>\n  \
+             <ip address=\"10.0.0.1\" prefix=\"16\">\n    <dns enable=\"no\"/>\n    \
+             <dhcp/>\n  </ip>\n</network>\n"
+        );
+        assert!(Network::parse("<network><bridge delay='x'/></network>").is_err());
+        assert!(Network::parse("<pool/>").is_err());
+    }
+
+    #[test]
+    fn test_ip_helpers() {
+        assert_eq!(netmask_prefix("255.255.254.0"), Some(23));
+        assert_eq!(netmask_prefix("255.0.255.0"), None);
+        assert_eq!(prefix_netmask(24), "255.255.255.0");
+        let (net, prefix) = parse_ip_network("192.168.100.7/24").unwrap();
+        assert_eq!((net.to_string().as_str(), prefix), ("192.168.100.0", 24));
+        assert_eq!(ip_add(net, 128, prefix).to_string(), "192.168.100.128");
+        let (net, prefix) = parse_ip_network("fd00:beef::/64").unwrap();
+        assert_eq!(ip_add(net, 256, prefix).to_string(), "fd00:beef::100");
+        assert_eq!(parse_ip_network("192.168.1.1/33"), None);
+        assert_eq!(parse_ip_network("bogus"), None);
+    }
+}
@@ -0,0 +1,1152 @@
+// OS database from osinfo-db XML (port of virtinst/osdict.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `OsDb` reads the osinfo-db XML files libosinfo uses, straight from
+//! their directory layout, and answers the per OS questions virtinst asks
+//! libosinfo: recommended resources, virtio and q35 support, firmware,
+//! tree URLs, install scripts and so on. `osdb()` is the shared instance
+//! loaded from the standard osinfo paths.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::urldetect::{CatalogOs, OsCatalog};
+use crate::xmlapi::Element;
+
+/// Short id of the built in fallback OS
+pub const GENERIC: &str = "generic";
+
+/// A device from the osinfo-db `devices/` files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OsDevice {
+    pub id: String,
+    pub class: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ResourceValues {
+    n_cpus: Option<u64>,
+    ram: Option<u64>,
+    storage: Option<u64>,
+}
+
+impl ResourceValues {
+    fn parse(el: Option<&Element>) -> Self {
+        let Some(el) = el else {
+            return Self::default();
+        };
+        let val = |name: &str| {
+            el.get(&format!("./{}", name))
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .filter(|v| *v > 0)
+                .map(|v| v as u64)
+        };
+        Self {
+            n_cpus: val("n-cpus"),
+            ram: val("ram"),
+            storage: val("storage"),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<u64> {
+        match key {
+            "n-cpus" => self.n_cpus,
+            "ram" => self.ram,
+            _ => self.storage,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Resources {
+    arch: String,
+    minimum: ResourceValues,
+    recommended: ResourceValues,
+    network_install: ResourceValues,
+}
+
+#[derive(Debug, Clone)]
+struct Tree {
+    arch: String,
+    url: String,
+    /// Names of the OS variants the tree is for, like `Fedora Server`
+    variants: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Firmware {
+    arch: String,
+    firmware_type: String,
+    supported: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Driver {
+    arch: String,
+    location: String,
+    pre_installable: bool,
+    files: Vec<String>,
+    devices: Vec<String>,
+}
+
+/// An `<install-script>` from the osinfo-db `install-script/` files: an
+/// XSLT template rendering a kickstart, preseed, autounattend.xml and
+/// the like from an install config
+#[derive(Debug, Clone, Default)]
+pub struct InstallScript {
+    pub id: String,
+    /// Like `jeos` or `desktop`
+    pub profile: String,
+    /// The name the installer looks for the script under
+    pub expected_filename: String,
+    /// Config params the template uses, with whether each is required
+    params: Vec<(String, bool)>,
+    /// Like `cdrom`, `initrd` or `floppy`
+    injection_methods: Vec<String>,
+    template: Option<Element>,
+}
+
+impl InstallScript {
+    pub fn supports_injection_method(&self, method: &str) -> bool {
+        self.injection_methods.iter().any(|m| m == method)
+    }
+
+    /// Whether the template needs config param `name` to be set
+    pub fn requires_param(&self, name: &str) -> bool {
+        self.params
+            .iter()
+            .any(|(n, required)| n == name && *required)
+    }
+
+    /// The `xsl:stylesheet` element
+    pub fn template(&self) -> Option<&Element> {
+        self.template.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Relationship {
+    DerivesFrom,
+    Clones,
+    Upgrades,
+}
+
+/// An `<os>` as read from the XML, before references to other entries
+/// are resolved
+struct RawOs {
+    variant: OsVariant,
+    device_refs: Vec<(String, bool)>,
+    related: Vec<(Relationship, String)>,
+    script_refs: Vec<String>,
+}
+
+/// Minimum and recommended resources of an OS. Values are per arch,
+/// falling back to the `all` arch entry.
+pub struct OsResources<'a> {
+    resources: &'a [Resources],
+}
+
+impl OsResources<'_> {
+    fn get_key(&self, key: &str, arch: &str, minimum: bool) -> Option<u64> {
+        [arch, "all"].iter().find_map(|checkarch| {
+            let res = self.resources.iter().find(|r| r.arch == *checkarch)?;
+            let vals = if minimum {
+                &res.minimum
+            } else {
+                &res.recommended
+            };
+            vals.get(key)
+        })
+    }
+
+    fn get_recommended_key(&self, key: &str, arch: &str) -> Option<u64> {
+        if let Some(val) = self.get_key(key, arch, false) {
+            return Some(val);
+        }
+        // If we are looking for a recommended value, but the OS DB only
+        // has minimum resources tracked, double the minimum value as an
+        // approximation at a 'recommended' value
+        let val = self.get_key(key, arch, true)?;
+        log::debug!(
+            "No recommended value found for key='{}', using minimum={} * 2",
+            key,
+            val
+        );
+        Some(val * 2)
+    }
+
+    pub fn get_minimum_ram(&self, arch: &str) -> Option<u64> {
+        self.get_key("ram", arch, true)
+    }
+
+    pub fn get_recommended_ram(&self, arch: &str) -> Option<u64> {
+        self.get_recommended_key("ram", arch)
+    }
+
+    pub fn get_recommended_ncpus(&self, arch: &str) -> Option<u64> {
+        self.get_recommended_key("n-cpus", arch)
+    }
+
+    pub fn get_recommended_storage(&self, arch: &str) -> Option<u64> {
+        self.get_recommended_key("storage", arch)
+    }
+}
+
+/// One OS of the database
+#[derive(Debug, Clone, Default)]
+pub struct OsVariant {
+    /// The first short id, like `fedora29`
+    pub name: String,
+    /// Every short id, sorted
+    pub all_names: Vec<String>,
+    /// Like `http://fedoraproject.org/fedora/29`. None for `generic`
+    pub full_id: Option<String>,
+    /// Like `Fedora 29`
+    pub label: String,
+    pub codename: String,
+    pub distro: String,
+    pub version: Option<String>,
+    /// Past its end of life date
+    pub eol: bool,
+    family: String,
+    kernel_url_argument: Option<String>,
+    /// Own devices plus those of the OSes this one derives from or clones
+    devices: Vec<OsDevice>,
+    /// Short ids of every OS this one derives from, clones or upgrades,
+    /// directly or not
+    related: Vec<String>,
+    resources: Vec<Resources>,
+    trees: Vec<Tree>,
+    firmwares: Vec<Firmware>,
+    drivers: Vec<Driver>,
+    install_scripts: Vec<InstallScript>,
+}
+
+// virtio-block and virtio1.0-block, and so on
+const VIRTIO_DISK: &[&str] = &[
+    "http://pcisig.com/pci/1af4/1001",
+    "http://pcisig.com/pci/1af4/1042",
+];
+const VIRTIO_SCSI: &[&str] = &[
+    "http://pcisig.com/pci/1af4/1004",
+    "http://pcisig.com/pci/1af4/1048",
+];
+const VIRTIO_NET: &[&str] = &[
+    "http://pcisig.com/pci/1af4/1000",
+    "http://pcisig.com/pci/1af4/1041",
+];
+const VIRTIO_RNG: &[&str] = &[
+    "http://pcisig.com/pci/1af4/1005",
+    "http://pcisig.com/pci/1af4/1044",
+];
+const VIRTIO_GPU: &[&str] = &["http://pcisig.com/pci/1af4/1050"];
+const VIRTIO_BALLOON: &[&str] = &[
+    "http://pcisig.com/pci/1af4/1002",
+    "http://pcisig.com/pci/1af4/1045",
+];
+const VIRTIO_SERIAL: &[&str] = &[
+    "http://pcisig.com/pci/1af4/1003",
+    "http://pcisig.com/pci/1af4/1043",
+];
+const VIRTIO_INPUT: &[&str] = &["http://pcisig.com/pci/1af4/1052"];
+// qemu-xhci
+const USB3: &[&str] = &["http://pcisig.com/pci/1b36/0004"];
+// Use virtio1.0-net device as a proxy for virtio1.0 as a whole
+const VIRTIO1: &[&str] = &["http://pcisig.com/pci/1af4/1041"];
+const Q35: &[&str] = &["http://qemu.org/chipset/x86/q35"];
+
+impl OsVariant {
+    fn generic() -> Self {
+        Self {
+            name: GENERIC.to_string(),
+            all_names: vec![GENERIC.to_string()],
+            label: "Generic or unknown OS. Usage is not recommended.".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether this OS or any it derives from, clones or upgrades has a
+    /// short id in `names`
+    pub fn is_related_to(&self, names: &[&str]) -> bool {
+        names.contains(&self.name.as_str())
+            || self.related.iter().any(|r| names.contains(&r.as_str()))
+    }
+
+    fn device_filter(&self, devids: &[&str], cls: Option<&str>) -> Vec<&str> {
+        self.devices
+            .iter()
+            .filter(|d| devids.is_empty() || devids.contains(&d.id.as_str()))
+            .filter(|d| cls.is_none_or(|c| d.class.starts_with(c)))
+            .map(|d| d.name.as_str())
+            .collect()
+    }
+
+    fn has_device(&self, devids: &[&str]) -> bool {
+        !self.device_filter(devids, None).is_empty()
+    }
+
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
+    pub fn is_generic(&self) -> bool {
+        self.name == GENERIC
+    }
+
+    /// Like `linux2020`
+    pub fn is_linux_generic(&self) -> bool {
+        self.name
+            .strip_prefix("linux")
+            .is_some_and(|s| s.len() >= 4 && s.bytes().take(4).all(|b| b.is_ascii_digit()))
+    }
+
+    pub fn is_windows(&self) -> bool {
+        ["win9x", "winnt", "win16"].contains(&self.family.as_str())
+    }
+
+    /// The `<clock offset=...>` the OS expects
+    pub fn get_clock(&self) -> &'static str {
+        if self.is_windows() || self.family == "solaris" {
+            "localtime"
+        } else {
+            "utc"
+        }
+    }
+
+    pub fn supported_netmodels(&self) -> Vec<&str> {
+        self.device_filter(&[], Some("net"))
+    }
+
+    pub fn supports_virtiodisk(&self) -> bool {
+        self.has_device(VIRTIO_DISK)
+    }
+
+    pub fn supports_virtioscsi(&self) -> bool {
+        self.has_device(VIRTIO_SCSI)
+    }
+
+    pub fn supports_virtionet(&self) -> bool {
+        self.has_device(VIRTIO_NET)
+    }
+
+    pub fn supports_virtiorng(&self) -> bool {
+        self.has_device(VIRTIO_RNG)
+    }
+
+    pub fn supports_virtiogpu(&self) -> bool {
+        self.has_device(VIRTIO_GPU)
+    }
+
+    pub fn supports_virtioballoon(&self) -> bool {
+        self.has_device(VIRTIO_BALLOON)
+    }
+
+    pub fn supports_virtioserial(&self) -> bool {
+        // osinfo data was wrong for RHEL/centos here until Oct 2018
+        self.has_device(VIRTIO_SERIAL) || self.is_related_to(&["rhel6.0"])
+    }
+
+    pub fn supports_virtioinput(&self) -> bool {
+        self.has_device(VIRTIO_INPUT)
+    }
+
+    pub fn supports_usb3(&self) -> bool {
+        self.has_device(USB3)
+    }
+
+    pub fn supports_virtio1(&self) -> bool {
+        self.has_device(VIRTIO1)
+    }
+
+    /// The union of q35 and virtio1.0 support, since legacy virtio
+    /// devices don't work well on q35
+    pub fn supports_chipset_q35(&self) -> bool {
+        if self.supports_virtionet() && !self.supports_virtio1() {
+            return false;
+        }
+        self.has_device(Q35)
+    }
+
+    fn supports_firmware_type(&self, name: &str, arch: &str, default: bool) -> bool {
+        self.firmwares
+            .iter()
+            .find(|f| f.arch == arch && f.firmware_type == name)
+            .map_or(default, |f| f.supported)
+    }
+
+    /// Whether the OS only boots with UEFI on `arch`
+    pub fn requires_firmware_efi(&self, arch: &str) -> bool {
+        self.supports_firmware_type("efi", arch, false)
+            && !self.supports_firmware_type("bios", arch, true)
+    }
+
+    pub fn get_recommended_resources(&self) -> OsResources<'_> {
+        OsResources {
+            resources: &self.resources,
+        }
+    }
+
+    pub fn get_network_install_required_ram(&self, arch: &str) -> Option<u64> {
+        self.resources
+            .iter()
+            .find(|r| r.arch == arch || r.arch == "all")
+            .and_then(|r| r.network_install.ram)
+    }
+
+    /// Kernel argument name the distro's installer uses to reference a
+    /// network source, possibly bypassing some installer prompts
+    pub fn get_kernel_url_arg(&self) -> Option<String> {
+        if let Some(arg) = &self.kernel_url_argument {
+            return Some(arg.clone());
+        }
+        if ["caasp", "sle", "sled", "sles", "opensuse"].contains(&self.distro.as_str()) {
+            return Some("install".to_string());
+        }
+        if !["centos", "rhel", "fedora"].contains(&self.distro.as_str()) {
+            return None;
+        }
+        // Default for RH distros, in case the osinfo data isn't complete
+        Some("inst.repo".to_string())
+    }
+
+    /// The most generic install tree URL for `arch`. `profile` jeos or
+    /// desktop prefers the Server or Workstation tree.
+    pub fn get_location(&self, arch: &str, profile: Option<&str>) -> Result<String, String> {
+        if self.trees.is_empty() {
+            return Err(format!("OS '{}' does not have a URL location", self.name));
+        }
+        let profile = match profile {
+            Some("jeos") => "Server",
+            Some("desktop") => "Workstation",
+            Some(p) if !p.is_empty() => p,
+            _ => "Everything",
+        };
+        let mut fallback = None;
+        for tree in self.trees.iter().filter(|t| t.arch == arch) {
+            if tree.variants.iter().any(|v| v.contains(profile)) {
+                return Ok(tree.url.clone());
+            }
+            fallback = Some(tree);
+        }
+        fallback.map(|t| t.url.clone()).ok_or_else(|| {
+            format!(
+                "OS '{}' does not have a URL location for the architecture '{}'",
+                self.name, arch
+            )
+        })
+    }
+
+    fn pre_installable_drivers(&self, arch: &str) -> impl Iterator<Item = &Driver> {
+        self.drivers
+            .iter()
+            .filter(move |d| arch == "all" || d.arch == arch)
+            .filter(|d| d.pre_installable)
+    }
+
+    /// Files of the drivers that can be loaded during an unattended
+    /// install
+    pub fn get_pre_installable_drivers_location(&self, arch: &str) -> Vec<String> {
+        self.pre_installable_drivers(arch)
+            .flat_map(|d| {
+                d.files
+                    .iter()
+                    .map(|f| format!("{}/{}", d.location.trim_end_matches('/'), f))
+            })
+            .collect()
+    }
+
+    /// Device ids the pre-installable drivers add support for
+    pub fn get_pre_installable_devices(&self, arch: &str) -> Vec<String> {
+        self.pre_installable_drivers(arch)
+            .flat_map(|d| d.devices.iter().cloned())
+            .collect()
+    }
+
+    pub fn supports_unattended_drivers(&self, arch: &str) -> bool {
+        self.pre_installable_drivers(arch).next().is_some()
+    }
+
+    pub fn get_install_script_list(&self) -> &[InstallScript] {
+        &self.install_scripts
+    }
+
+    /// A copy that also supports devices `devids`, like those the
+    /// drivers of an unattended install add
+    pub fn with_extra_devices(&self, devids: &[String]) -> Self {
+        let mut ret = self.clone();
+        for id in devids {
+            if !ret.devices.iter().any(|d| d.id == *id) {
+                ret.devices.push(OsDevice {
+                    id: id.clone(),
+                    class: String::new(),
+                    name: id.clone(),
+                });
+            }
+        }
+        ret
+    }
+}
+
+/// How `OsDb::list_os` orders its result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OsSortKey {
+    Name,
+    Label,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SortPart {
+    Num(i128),
+    Text(String),
+}
+
+/// Human sort key, with numbers sorted in reverse so newer versions
+/// come first
+fn alphanum_key(val: &str) -> Vec<SortPart> {
+    let mut ret = vec![];
+    let mut cur = String::new();
+    let mut digits = false;
+    let flush = |cur: &mut String, digits: bool, ret: &mut Vec<SortPart>| {
+        if digits {
+            ret.push(SortPart::Num(-cur.parse::<i128>().unwrap_or(0)));
+        } else {
+            ret.push(SortPart::Text(cur.to_lowercase()));
+        }
+        cur.clear();
+    };
+    for c in val.chars() {
+        if c.is_ascii_digit() != digits {
+            flush(&mut cur, digits, &mut ret);
+            digits = !digits;
+        }
+        cur.push(c);
+    }
+    flush(&mut cur, digits, &mut ret);
+    ret
+}
+
+/// Days since the epoch of a `YYYY-MM-DD` date
+fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.trim().splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (parts.next()??, parts.next()??, parts.next()??);
+    // Howard Hinnant's days_from_civil
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146097 + doe - 719468)
+}
+
+fn today() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| (d.as_secs() / 86400) as i64)
+        .unwrap_or(0)
+}
+
+/// Whether an OS is past its end of life. Without an EOL date, assume
+/// it is 10 years after the release, unless it's a rolling release.
+fn compute_eol(release: Option<&str>, eol: Option<&str>, status: Option<&str>) -> bool {
+    let now = today();
+    if let Some(eol) = eol.and_then(parse_date) {
+        return now > eol;
+    }
+    if status == Some("rolling") {
+        return false;
+    }
+    release
+        .and_then(parse_date)
+        .is_some_and(|rel| now > rel + 365 * 10)
+}
+
+/// Text of the untranslated `name` child
+fn child_text(el: &Element, name: &str) -> Option<String> {
+    el.child_elements()
+        .find(|c| c.name == name && c.attr("xml:lang").is_none())
+        .map(|c| c.text().trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+fn parse_device(el: &Element) -> Option<OsDevice> {
+    Some(OsDevice {
+        id: el.attr("id")?.to_string(),
+        class: child_text(el, "class").unwrap_or_default(),
+        name: child_text(el, "name").unwrap_or_default(),
+    })
+}
+
+fn parse_os(el: &Element) -> Option<RawOs> {
+    let full_id = el.attr("id")?.to_string();
+    let mut short_ids: Vec<String> = el
+        .child_elements()
+        .filter(|c| c.name == "short-id")
+        .map(|c| c.text().trim().to_string())
+        .collect();
+    if short_ids.is_empty() {
+        log::debug!("osinfo os id={} has no short-id, skipping", full_id);
+        return None;
+    }
+    let name = short_ids[0].clone();
+    short_ids.sort();
+    short_ids.dedup();
+
+    let variant_names: HashMap<String, String> = el
+        .child_elements()
+        .filter(|c| c.name == "variant")
+        .filter_map(|c| Some((c.attr("id")?.to_string(), child_text(c, "name")?)))
+        .collect();
+
+    let mut raw = RawOs {
+        variant: OsVariant {
+            name,
+            all_names: short_ids,
+            label: child_text(el, "name").unwrap_or_default(),
+            codename: child_text(el, "codename").unwrap_or_default(),
+            distro: child_text(el, "distro").unwrap_or_default(),
+            version: child_text(el, "version"),
+            eol: compute_eol(
+                child_text(el, "release-date").as_deref(),
+                child_text(el, "eol-date").as_deref(),
+                child_text(el, "release-status").as_deref(),
+            ),
+            family: child_text(el, "family").unwrap_or_default(),
+            kernel_url_argument: child_text(el, "kernel-url-argument"),
+            full_id: Some(full_id),
+            ..Default::default()
+        },
+        device_refs: vec![],
+        related: vec![],
+        script_refs: vec![],
+    };
+
+    for child in el.child_elements() {
+        let arch = child.attr("arch").unwrap_or("all").to_string();
+        match child.name.as_str() {
+            "derives-from" | "clones" | "upgrades" => {
+                let rel = match child.name.as_str() {
+                    "derives-from" => Relationship::DerivesFrom,
+                    "clones" => Relationship::Clones,
+                    _ => Relationship::Upgrades,
+                };
+                if let Some(id) = child.attr("id") {
+                    raw.related.push((rel, id.to_string()));
+                }
+            }
+            "devices" => {
+                for dev in child.child_elements().filter(|d| d.name == "device") {
+                    if let Some(id) = dev.attr("id") {
+                        raw.device_refs
+                            .push((id.to_string(), dev.attr("supported") != Some("false")));
+                    }
+                }
+            }
+            "resources" => raw.variant.resources.push(Resources {
+                arch,
+                minimum: ResourceValues::parse(child.find("./minimum")),
+                recommended: ResourceValues::parse(child.find("./recommended")),
+                network_install: ResourceValues::parse(child.find("./network-install")),
+            }),
+            "tree" => {
+                let Some(url) = child_text(child, "url") else {
+                    continue;
+                };
+                let variants = child
+                    .child_elements()
+                    .filter(|c| c.name == "variant")
+                    .filter_map(|c| variant_names.get(c.attr("id")?).cloned())
+                    .collect();
+                raw.variant.trees.push(Tree {
+                    arch,
+                    url,
+                    variants,
+                });
+            }
+            "installer" => raw.script_refs.extend(
+                child
+                    .child_elements()
+                    .filter(|c| c.name == "script")
+                    .filter_map(|c| c.attr("id").map(str::to_string)),
+            ),
+            "firmware" => raw.variant.firmwares.push(Firmware {
+                arch,
+                firmware_type: child.attr("type").unwrap_or_default().to_string(),
+                supported: child.attr("supported") != Some("false"),
+            }),
+            "driver" => raw.variant.drivers.push(Driver {
+                arch,
+                location: child.attr("location").unwrap_or_default().to_string(),
+                pre_installable: child.attr("pre-installable") == Some("true"),
+                files: child
+                    .child_elements()
+                    .filter(|c| c.name == "file")
+                    .map(|c| c.text().trim().to_string())
+                    .collect(),
+                devices: child
+                    .child_elements()
+                    .filter(|c| c.name == "device")
+                    .filter_map(|c| c.attr("id").map(str::to_string))
+                    .collect(),
+            }),
+            _ => {}
+        }
+    }
+    Some(raw)
+}
+
+fn parse_install_script(el: &Element) -> Option<InstallScript> {
+    Some(InstallScript {
+        id: el.attr("id")?.to_string(),
+        profile: child_text(el, "profile").unwrap_or_default(),
+        expected_filename: child_text(el, "expected-filename").unwrap_or_default(),
+        params: el
+            .find_all("./config/param")
+            .into_iter()
+            .filter_map(|p| {
+                Some((
+                    p.attr("name")?.to_string(),
+                    p.attr("policy") == Some("required"),
+                ))
+            })
+            .collect(),
+        injection_methods: el
+            .child_elements()
+            .filter(|c| c.name == "injection-method")
+            .map(|c| c.text().trim().to_string())
+            .collect(),
+        template: el
+            .find("./template")
+            .and_then(|t| t.child_elements().next())
+            .cloned(),
+    })
+}
+
+/// Every `.xml` file below `dir`, sorted so loading is deterministic
+fn find_xml_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            find_xml_files(&path, out);
+        } else if path.extension().is_some_and(|e| e == "xml") {
+            out.push(path);
+        }
+    }
+}
+
+/// The OS database
+pub struct OsDb {
+    oses: Vec<OsVariant>,
+}
+
+impl OsDb {
+    /// Load every osinfo-db XML file found below `dirs`. Entries in later
+    /// directories replace those with the same id in earlier ones, like
+    /// libosinfo's local and user directories override the system one.
+    pub fn load<P: AsRef<Path>>(dirs: &[P]) -> Self {
+        let mut devices: HashMap<String, OsDevice> = HashMap::new();
+        let mut raws: Vec<RawOs> = vec![];
+        let mut scripts: HashMap<String, InstallScript> = HashMap::new();
+        for dir in dirs {
+            let mut files = vec![];
+            find_xml_files(dir.as_ref(), &mut files);
+            for path in files {
+                let root = match std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|xml| Element::parse(&xml))
+                {
+                    Ok(root) => root,
+                    Err(e) => {
+                        log::warn!("Failed to load osinfo file {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+                for el in root.child_elements() {
+                    match el.name.as_str() {
+                        "device" => {
+                            if let Some(dev) = parse_device(el) {
+                                devices.insert(dev.id.clone(), dev);
+                            }
+                        }
+                        "install-script" => {
+                            if let Some(script) = parse_install_script(el) {
+                                scripts.insert(script.id.clone(), script);
+                            }
+                        }
+                        "os" => {
+                            let Some(raw) = parse_os(el) else {
+                                continue;
+                            };
+                            raws.retain(|r| r.variant.full_id != raw.variant.full_id);
+                            raws.push(raw);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let by_id: HashMap<&str, usize> = raws
+            .iter()
+            .enumerate()
+            .filter_map(|(i, r)| Some((r.variant.full_id.as_deref()?, i)))
+            .collect();
+        let mut oses: Vec<OsVariant> = raws
+            .iter()
+            .enumerate()
+            .map(|(idx, raw)| {
+                let mut variant = raw.variant.clone();
+                variant.devices = resolve_devices(&raws, &by_id, &devices, idx);
+                variant.related = resolve_related(&raws, &by_id, idx);
+                variant.install_scripts = raw
+                    .script_refs
+                    .iter()
+                    .filter_map(|id| scripts.get(id).cloned())
+                    .collect();
+                variant
+            })
+            .collect();
+        oses.push(OsVariant::generic());
+        Self { oses }
+    }
+
+    /// Load from the standard osinfo paths, honoring the same environment
+    /// overrides as libosinfo
+    pub fn load_default() -> Self {
+        Self::load(&default_paths())
+    }
+
+    pub fn lookup_os(&self, key: &str) -> Option<&OsVariant> {
+        self.oses
+            .iter()
+            .find(|o| o.all_names.iter().any(|n| n == key))
+    }
+
+    /// Like `lookup_os`, but an unknown name is an error
+    pub fn require_os(&self, key: &str) -> Result<&OsVariant, String> {
+        self.lookup_os(key).ok_or_else(|| {
+            format!(
+                "Unknown OS name '{}'. See `--osinfo list` for valid values.",
+                key
+            )
+        })
+    }
+
+    pub fn lookup_os_by_full_id(&self, full_id: &str) -> Option<&OsVariant> {
+        self.oses
+            .iter()
+            .find(|o| o.full_id.as_deref() == Some(full_id))
+    }
+
+    /// Like `lookup_os_by_full_id`, but an unknown id is an error
+    pub fn require_os_by_full_id(&self, full_id: &str) -> Result<&OsVariant, String> {
+        self.lookup_os_by_full_id(full_id)
+            .ok_or_else(|| format!("Unknown libosinfo ID '{}'", full_id))
+    }
+
+    /// Every OS including `generic`, human sorted by `sortkey`
+    pub fn list_os(&self, sortkey: OsSortKey) -> Vec<&OsVariant> {
+        let mut ret: Vec<&OsVariant> = self.oses.iter().collect();
+        ret.sort_by_cached_key(|o| match sortkey {
+            OsSortKey::Name => alphanum_key(&o.name),
+            OsSortKey::Label => alphanum_key(&o.label),
+        });
+        ret
+    }
+
+    /// OSes for a search as you type list: those with `text` in their
+    /// label or name, case insensitively, sorted by label. EOL OSes are
+    /// left out unless `include_eol`. The generic entries always match
+    /// and go last.
+    pub fn search_os(&self, text: &str, include_eol: bool) -> Vec<&OsVariant> {
+        let text = text.trim().to_lowercase();
+        let always_show = |o: &OsVariant| o.is_generic() || o.is_linux_generic();
+        let mut ret: Vec<&OsVariant> = self
+            .list_os(OsSortKey::Label)
+            .into_iter()
+            .filter(|o| include_eol || !o.eol)
+            .filter(|o| {
+                always_show(o)
+                    || o.label.to_lowercase().contains(&text)
+                    || o.name.to_lowercase().contains(&text)
+            })
+            .collect();
+        ret.sort_by_key(|o| always_show(o));
+        ret
+    }
+}
+
+impl OsCatalog for OsDb {
+    fn lookup_os(&self, name: &str) -> bool {
+        OsDb::lookup_os(self, name).is_some()
+    }
+
+    fn list_os(&self) -> Vec<CatalogOs> {
+        self.oses
+            .iter()
+            .filter(|o| !o.is_generic())
+            .map(|o| CatalogOs {
+                name: o.name.clone(),
+                codename: Some(o.codename.clone()).filter(|c| !c.is_empty()),
+                label: o.label.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Devices of the OS at `idx` and, recursively, of those it derives
+/// from or clones. Devices the OS marks `supported="false"` are dropped
+/// even when an ancestor has them.
+fn resolve_devices(
+    raws: &[RawOs],
+    by_id: &HashMap<&str, usize>,
+    devices: &HashMap<String, OsDevice>,
+    idx: usize,
+) -> Vec<OsDevice> {
+    let mut ret: Vec<OsDevice> = vec![];
+    let mut unsupported: HashSet<&str> = HashSet::new();
+    let mut seen = HashSet::new();
+    let mut queue = vec![idx];
+    while let Some(cur) = queue.pop() {
+        if !seen.insert(cur) {
+            continue;
+        }
+        let raw = &raws[cur];
+        for (id, supported) in &raw.device_refs {
+            if !supported {
+                unsupported.insert(id);
+                continue;
+            }
+            if unsupported.contains(id.as_str()) || ret.iter().any(|d| d.id == *id) {
+                continue;
+            }
+            ret.push(devices.get(id).cloned().unwrap_or_else(|| OsDevice {
+                id: id.clone(),
+                class: String::new(),
+                name: id.clone(),
+            }));
+        }
+        for (rel, id) in &raw.related {
+            if *rel == Relationship::Upgrades {
+                continue;
+            }
+            if let Some(next) = by_id.get(id.as_str()) {
+                queue.push(*next);
+            }
+        }
+    }
+    ret
+}
+
+/// Short ids of every OS the one at `idx` derives from, clones or
+/// upgrades, directly or not
+fn resolve_related(raws: &[RawOs], by_id: &HashMap<&str, usize>, idx: usize) -> Vec<String> {
+    let mut ret = vec![];
+    let mut seen = HashSet::from([idx]);
+    let mut queue: Vec<usize> = vec![idx];
+    while let Some(cur) = queue.pop() {
+        for (_, id) in &raws[cur].related {
+            let Some(next) = by_id.get(id.as_str()) else {
+                continue;
+            };
+            if seen.insert(*next) {
+                ret.push(raws[*next].variant.name.clone());
+                queue.push(*next);
+            }
+        }
+    }
+    ret
+}
+
+/// The system, local and user osinfo directories
+fn default_paths() -> Vec<PathBuf> {
+    let env_or = |key: &str, default: &str| {
+        std::env::var_os(key)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(default))
+    };
+    let user = std::env::var_os("OSINFO_USER_DIR")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("XDG_CONFIG_HOME").map(|d| PathBuf::from(d).join("osinfo")))
+        .or_else(|| std::env::var_os("HOME").map(|d| PathBuf::from(d).join(".config/osinfo")));
+    let mut ret = vec![
+        env_or("OSINFO_SYSTEM_DIR", "/usr/share/osinfo"),
+        env_or("OSINFO_LOCAL_DIR", "/etc/osinfo"),
+    ];
+    ret.extend(user);
+    ret
+}
+
+static OSDB: OnceLock<OsDb> = OnceLock::new();
+
+/// The shared OS database, loaded on first use
+pub fn osdb() -> &'static OsDb {
+    OSDB.get_or_init(load_shared)
+}
+
+#[cfg(not(test))]
+fn load_shared() -> OsDb {
+    OsDb::load_default()
+}
+
+/// Tests share the osinfo-db copy in tests/data rather than whatever
+/// the host has installed
+#[cfg(test)]
+fn load_shared() -> OsDb {
+    OsDb::load(&[format!(
+        "{}/../tests/data/osinfo-db",
+        env!("CARGO_MANIFEST_DIR")
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mirrors tests/test_osdict.py
+    #[test]
+    fn test_osdict() {
+        let db = osdb();
+        let names: Vec<&str> = db
+            .list_os(OsSortKey::Name)
+            .iter()
+            .map(|o| o.name.as_str())
+            .collect();
+        assert!(names.contains(&"generic"));
+        let pos = |n: &str| names.iter().position(|x| *x == n).unwrap();
+        assert!(pos("fedora29") < pos("fedora26"));
+        assert!(db.lookup_os("fedora-unknown").is_some());
+        assert!(
+            db.require_os("idontexist")
+                .unwrap_err()
+                .contains("--osinfo list")
+        );
+        assert_eq!(
+            db.lookup_os_by_full_id("http://fedoraproject.org/fedora/29")
+                .unwrap()
+                .name,
+            "fedora29"
+        );
+
+        // test_recommended_resources
+        let res = db.lookup_os("generic").unwrap().get_recommended_resources();
+        assert_eq!(res.get_recommended_ram("x86_64"), None);
+        let res = db
+            .lookup_os("fedora21")
+            .unwrap()
+            .get_recommended_resources();
+        assert_eq!(res.get_recommended_ncpus("x86_64"), Some(2));
+        // Only a minimum is tracked, so it's doubled
+        let res = db
+            .lookup_os("fedora17")
+            .unwrap()
+            .get_recommended_resources();
+        assert_eq!(res.get_recommended_ram("x86_64"), Some(1536 * 1024 * 1024));
+
+        // test_tree_url
+        let f26 = db.lookup_os("fedora26").unwrap();
+        let f29 = db.lookup_os("fedora29").unwrap();
+        let winxp = db.lookup_os("winxp").unwrap();
+        assert!(
+            f26.get_location("x86_64", None)
+                .unwrap()
+                .contains("fedoraproject.org")
+        );
+        assert!(
+            f29.get_location("x86_64", None)
+                .unwrap()
+                .contains("Everything")
+        );
+        assert!(
+            f29.get_location("x86_64", Some("jeos"))
+                .unwrap()
+                .contains("Server")
+        );
+        assert!(
+            f29.get_location("x86_64", Some("desktop"))
+                .unwrap()
+                .contains("Workstation")
+        );
+        assert!(f26.get_location("ia64", None).unwrap_err().contains("ia64"));
+        assert!(
+            winxp
+                .get_location("x86_64", None)
+                .unwrap_err()
+                .ends_with("URL location")
+        );
+
+        // test_kernel_url
+        let karg = |n: &str| db.lookup_os(n).unwrap().get_kernel_url_arg();
+        assert_eq!(karg("rhel7-unknown").as_deref(), Some("inst.repo"));
+        assert_eq!(karg("rhel6-unknown").as_deref(), Some("method"));
+        assert_eq!(karg("fedora-rawhide").as_deref(), Some("inst.repo"));
+        assert_eq!(karg("fedora20").as_deref(), Some("inst.repo"));
+        assert_eq!(karg("generic"), None);
+        assert_eq!(karg("win10"), None);
+        assert_eq!(karg("sle15").as_deref(), Some("install"));
+
+        // test_related_to
+        let win10 = db.lookup_os("win10").unwrap();
+        assert!(win10.is_related_to(&["winxp"]));
+        assert!(win10.is_related_to(&["win10"]));
+        assert!(!win10.is_related_to(&["fedora26"]));
+
+        // test_drivers
+        let win7 = db.lookup_os("win7").unwrap();
+        let generic = db.lookup_os("generic").unwrap();
+        assert!(!generic.supports_unattended_drivers("x86_64"));
+        assert!(win7.supports_unattended_drivers("x86_64"));
+        assert!(!win7.supports_unattended_drivers("fakearch"));
+        assert!(
+            !win7
+                .get_pre_installable_drivers_location("x86_64")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_os_flags() {
+        let db = osdb();
+        let f29 = db.lookup_os("fedora29").unwrap();
+        assert!(f29.supports_chipset_q35() && f29.supports_virtiogpu());
+        assert!(f29.eol && !f29.is_windows());
+        assert!(!db.lookup_os("fedora-rawhide").unwrap().eol);
+        assert!(db.lookup_os("linux2020").unwrap().is_linux_generic());
+        // rhel6.0 derives its devices from fedora17, minus virtio-scsi
+        let rhel6 = db.lookup_os("rhel6.0").unwrap();
+        assert!(rhel6.supports_virtiodisk() && rhel6.supports_virtioserial());
+        assert!(!rhel6.supports_virtioscsi() && !rhel6.supports_chipset_q35());
+        let win7 = db.lookup_os("win7").unwrap();
+        assert!(win7.is_windows() && !win7.supports_virtiodisk());
+        assert_eq!(win7.get_clock(), "localtime");
+        assert_eq!(win7.supported_netmodels(), vec!["e1000"]);
+
+        let found: Vec<&str> = db
+            .search_os("FEDORA 2", true)
+            .iter()
+            .map(|o| o.name.as_str())
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                "fedora29",
+                "fedora28",
+                "fedora26",
+                "fedora21",
+                "fedora20",
+                "linux2020",
+                "generic"
+            ]
+        );
+        let found = db.search_os("fedora", false);
+        assert!(found.iter().all(|o| !o.eol));
+        assert!(found.iter().any(|o| o.name == "fedora-rawhide"));
+    }
+}
@@ -0,0 +1,203 @@
+// Progress reporting for long running operations (port of
+// virtinst/progress.py and the parts of virtinst/_progresspriv.py we use)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Long operations like disk copies report through the `Meter` trait.
+//! The CLIs print a `TextMeter`, the GUI hands in an `AsyncJob`, and
+//! everything else can pass a `NullMeter`.
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Receiver of progress updates. A meter can also ask the operation to
+/// stop, which is how jobs are cancelled.
+pub trait Meter {
+    /// Begin a new operation. `size` is the expected total, if known.
+    fn start(&self, text: &str, size: Option<u64>);
+
+    /// `amount` is the total done so far, not an increment
+    fn update(&self, amount: u64);
+
+    fn end(&self);
+
+    /// Whether the operation should stop as soon as it safely can
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// Meter that discards everything
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullMeter;
+
+impl Meter for NullMeter {
+    fn start(&self, _text: &str, _size: Option<u64>) {}
+    fn update(&self, _amount: u64) {}
+    fn end(&self) {}
+}
+
+struct TextMeterState {
+    text: String,
+    size: Option<u64>,
+    start: Instant,
+    last_update: Option<Instant>,
+    amount: u64,
+}
+
+/// Meter printing a single self-overwriting status line
+pub struct TextMeter<'a> {
+    output: RefCell<&'a mut dyn Write>,
+    state: RefCell<TextMeterState>,
+}
+
+impl<'a> TextMeter<'a> {
+    /// How often the status line is redrawn
+    const UPDATE_PERIOD: Duration = Duration::from_millis(300);
+
+    pub fn new(output: &'a mut dyn Write) -> Self {
+        Self {
+            output: RefCell::new(output),
+            state: RefCell::new(TextMeterState {
+                text: String::new(),
+                size: None,
+                start: Instant::now(),
+                last_update: None,
+                amount: 0,
+            }),
+        }
+    }
+
+    fn write(&self, line: &str) {
+        let mut out = self.output.borrow_mut();
+        let _ = out.write_all(line.as_bytes());
+        let _ = out.flush();
+    }
+}
+
+impl Meter for TextMeter<'_> {
+    fn start(&self, text: &str, size: Option<u64>) {
+        let mut state = self.state.borrow_mut();
+        state.text = text.to_string();
+        state.size = size;
+        state.start = Instant::now();
+        state.last_update = None;
+        state.amount = 0;
+    }
+
+    fn update(&self, amount: u64) {
+        let line = {
+            let mut state = self.state.borrow_mut();
+            state.amount = amount;
+            let now = Instant::now();
+            if state
+                .last_update
+                .is_some_and(|t| now < t + Self::UPDATE_PERIOD)
+            {
+                return;
+            }
+            state.last_update = Some(now);
+            let elapsed = now.duration_since(state.start).as_secs_f64();
+            match state.size {
+                Some(size) if size > 0 => {
+                    let frac = amount as f64 / size as f64;
+                    let remaining = if amount > 0 && elapsed > 0.0 {
+                        Some((size - amount.min(size)) as f64 * elapsed / amount as f64)
+                    } else {
+                        None
+                    };
+                    format!(
+                        "\r{} {:3}% | {:>5}B  {} ETA\r",
+                        state.text,
+                        (frac * 100.0) as u32,
+                        format_number(amount),
+                        format_time(remaining)
+                    )
+                }
+                _ => format!(
+                    "\r{} | {:>5}B  {}\r",
+                    state.text,
+                    format_number(amount),
+                    format_time(Some(elapsed))
+                ),
+            }
+        };
+        self.write(&line);
+    }
+
+    fn end(&self) {
+        let line = {
+            let state = self.state.borrow();
+            let elapsed = state.start.elapsed().as_secs_f64();
+            format!(
+                "\r{} | {:>5}B  {}\n",
+                state.text,
+                format_number(state.amount),
+                format_time(Some(elapsed))
+            )
+        };
+        self.write(&line);
+    }
+}
+
+/// Format seconds as `MM:SS`, or `--:--` when unknown
+pub fn format_time(seconds: Option<f64>) -> String {
+    match seconds {
+        Some(s) if s >= 0.0 && s.is_finite() => {
+            let s = s as u64;
+            format!("{:02}:{:02}", s / 60, s % 60)
+        }
+        _ => "--:--".to_string(),
+    }
+}
+
+/// Turn byte counts into human-readable metric-like numbers, like `1.5 G`
+pub fn format_number(number: u64) -> String {
+    const SYMBOLS: &[&str] = &["", "k", "M", "G", "T", "P", "E", "Z", "Y"];
+    const THRESH: f64 = 999.0;
+
+    if number as f64 <= THRESH {
+        return format!("{} ", number);
+    }
+    let mut value = number as f64;
+    let mut depth = 0;
+    while value > THRESH && depth < SYMBOLS.len() - 1 {
+        depth += 1;
+        value /= 1024.0;
+    }
+    // Show one decimal place for small values, like 1.5 k
+    if value < 9.95 {
+        format!("{:.1} {}", value, SYMBOLS[depth])
+    } else {
+        format!("{:.0} {}", value, SYMBOLS[depth])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_number(0), "0 ");
+        assert_eq!(format_number(999), "999 ");
+        assert_eq!(format_number(1536), "1.5 k");
+        assert_eq!(format_number(20 * 1024 * 1024 * 1024), "20 G");
+        assert_eq!(format_time(None), "--:--");
+        assert_eq!(format_time(Some(125.4)), "02:05");
+
+        let mut out = Vec::new();
+        {
+            let meter = TextMeter::new(&mut out);
+            meter.start("Cloning foo.img", Some(2048));
+            meter.update(1024);
+            meter.update(2048);
+            meter.end();
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("\rCloning foo.img  50% | 1.0 kB"));
+        assert!(out.ends_with("\rCloning foo.img | 2.0 kB  00:00\n"));
+    }
+}
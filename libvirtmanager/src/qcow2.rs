@@ -0,0 +1,551 @@
+// qcow2 image creation and header inspection (replaces the qemu-img
+// calls used for local disk images)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Just enough of the qcow2 format to create empty images and read back
+//! the header fields we care about. New images are version 3 with 16 bit
+//! refcounts and look like what `qemu-img create -f qcow2` writes: the
+//! header cluster, the refcount table, the refcount blocks and an empty
+//! L1 table. Everything on disk is big endian.
+
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"QFI\xfb";
+const V2_HEADER_LENGTH: u32 = 72;
+const V3_HEADER_LENGTH: u32 = 104;
+/// qemu's refcount_order for 16 bit refcounts
+const REFCOUNT_ORDER: u32 = 4;
+const EXT_END: u32 = 0;
+const EXT_BACKING_FORMAT: u32 = 0xe279_2aca;
+/// qemu refuses longer backing file names
+const MAX_BACKING_FILE_SIZE: usize = 1023;
+/// qemu refuses larger L1 tables
+const MAX_L1_SIZE: u64 = 32 * 1024 * 1024;
+const MAX_CHAIN_DEPTH: usize = 64;
+
+const INCOMPAT_DIRTY: u64 = 1 << 0;
+const INCOMPAT_CORRUPT: u64 = 1 << 1;
+
+pub const DEFAULT_CLUSTER_SIZE: u32 = 65536;
+
+/// Settings for a new qcow2 image
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateOptions {
+    /// Virtual disk size in bytes, rounded up to whole sectors
+    pub size: u64,
+    /// Power of two between 512 bytes and 2 MiB
+    pub cluster_size: u32,
+    /// Stored as given, so relative paths are relative to the new image
+    pub backing_file: Option<String>,
+    pub backing_format: Option<String>,
+}
+
+impl Default for CreateOptions {
+    fn default() -> Self {
+        Self {
+            size: 0,
+            cluster_size: DEFAULT_CLUSTER_SIZE,
+            backing_file: None,
+            backing_format: None,
+        }
+    }
+}
+
+/// Image details, like a small `qemu-img info`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageInfo {
+    pub path: String,
+    /// "qcow2" or "raw"
+    pub format: String,
+    pub virtual_size: u64,
+    /// qcow2 compat level, "0.10" for version 2 and "1.1" for version 3
+    pub compat: Option<String>,
+    pub cluster_size: Option<u32>,
+    pub backing_file: Option<String>,
+    pub backing_format: Option<String>,
+    pub encrypted: bool,
+    /// Refcounts may be stale after a crash with lazy refcounts
+    pub dirty: bool,
+    /// qemu found an inconsistency and marked the image unusable
+    pub corrupt: bool,
+}
+
+impl ImageInfo {
+    /// The backing file path, resolved relative to this image's directory
+    pub fn backing_path(&self) -> Option<String> {
+        let backing = self.backing_file.as_deref()?;
+        if backing.starts_with('/') {
+            return Some(backing.to_string());
+        }
+        let dir = Path::new(&self.path).parent().unwrap_or(Path::new(""));
+        Some(dir.join(backing).to_string_lossy().into_owned())
+    }
+}
+
+/// Cluster counts for each metadata area of a new image
+#[derive(Debug, PartialEq)]
+struct Layout {
+    l1_size: u64,
+    l1_clusters: u64,
+    refcount_table_clusters: u64,
+    refcount_blocks: u64,
+}
+
+impl Layout {
+    fn new(size: u64, cluster_size: u64) -> Result<Self, String> {
+        let l2_entries = cluster_size / 8;
+        let l1_size = size.div_ceil(cluster_size * l2_entries);
+        if l1_size * 8 > MAX_L1_SIZE {
+            return Err("Image size is too large for this cluster size".to_string());
+        }
+        let l1_clusters = (l1_size * 8).div_ceil(cluster_size).max(1);
+
+        // The refcount blocks have to count themselves and the table
+        // pointing at them, so grow both until everything fits
+        let refcounts_per_block = cluster_size * 8 / (1 << REFCOUNT_ORDER);
+        let mut refcount_blocks = 1;
+        let mut refcount_table_clusters = 1;
+        loop {
+            let total = 1 + refcount_table_clusters + refcount_blocks + l1_clusters;
+            let blocks = total.div_ceil(refcounts_per_block);
+            let table_clusters = (blocks * 8).div_ceil(cluster_size);
+            if blocks == refcount_blocks && table_clusters == refcount_table_clusters {
+                break;
+            }
+            refcount_blocks = blocks;
+            refcount_table_clusters = table_clusters;
+        }
+        Ok(Self {
+            l1_size,
+            l1_clusters,
+            refcount_table_clusters,
+            refcount_blocks,
+        })
+    }
+
+    fn total_clusters(&self) -> u64 {
+        1 + self.refcount_table_clusters + self.refcount_blocks + self.l1_clusters
+    }
+}
+
+fn header_extension(buf: &mut Vec<u8>, ext_type: u32, data: &[u8]) {
+    buf.extend_from_slice(&ext_type.to_be_bytes());
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+    buf.resize(buf.len().next_multiple_of(8), 0);
+}
+
+/// Build the header cluster contents, up to the end of the backing name
+fn build_header(opts: &CreateOptions, size: u64, layout: &Layout) -> Vec<u8> {
+    let cluster_size = opts.cluster_size as u64;
+    let refcount_table_offset = cluster_size;
+    let l1_table_offset =
+        cluster_size * (1 + layout.refcount_table_clusters + layout.refcount_blocks);
+
+    let mut buf = Vec::with_capacity(V3_HEADER_LENGTH as usize);
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(&3u32.to_be_bytes());
+    // backing_file_offset and backing_file_size, filled in below
+    buf.extend_from_slice(&0u64.to_be_bytes());
+    buf.extend_from_slice(&0u32.to_be_bytes());
+    buf.extend_from_slice(&opts.cluster_size.trailing_zeros().to_be_bytes());
+    buf.extend_from_slice(&size.to_be_bytes());
+    // crypt_method
+    buf.extend_from_slice(&0u32.to_be_bytes());
+    buf.extend_from_slice(&(layout.l1_size as u32).to_be_bytes());
+    buf.extend_from_slice(&l1_table_offset.to_be_bytes());
+    buf.extend_from_slice(&refcount_table_offset.to_be_bytes());
+    buf.extend_from_slice(&(layout.refcount_table_clusters as u32).to_be_bytes());
+    // nb_snapshots and snapshots_offset
+    buf.extend_from_slice(&0u32.to_be_bytes());
+    buf.extend_from_slice(&0u64.to_be_bytes());
+    // incompatible, compatible and autoclear features
+    buf.extend_from_slice(&0u64.to_be_bytes());
+    buf.extend_from_slice(&0u64.to_be_bytes());
+    buf.extend_from_slice(&0u64.to_be_bytes());
+    buf.extend_from_slice(&REFCOUNT_ORDER.to_be_bytes());
+    buf.extend_from_slice(&V3_HEADER_LENGTH.to_be_bytes());
+
+    if let Some(fmt) = &opts.backing_format {
+        header_extension(&mut buf, EXT_BACKING_FORMAT, fmt.as_bytes());
+    }
+    header_extension(&mut buf, EXT_END, &[]);
+
+    if let Some(backing) = &opts.backing_file {
+        let offset = buf.len() as u64;
+        buf[8..16].copy_from_slice(&offset.to_be_bytes());
+        buf[16..20].copy_from_slice(&(backing.len() as u32).to_be_bytes());
+        buf.extend_from_slice(backing.as_bytes());
+    }
+    buf
+}
+
+fn write_image(file: &File, opts: &CreateOptions, size: u64) -> Result<(), String> {
+    let cluster_size = opts.cluster_size as u64;
+    let layout = Layout::new(size, cluster_size)?;
+    let header = build_header(opts, size, &layout);
+    if header.len() as u64 > cluster_size {
+        return Err("Header does not fit in the first cluster".to_string());
+    }
+
+    let total = layout.total_clusters();
+    file.set_len(total * cluster_size)
+        .map_err(|e| e.to_string())?;
+    file.write_all_at(&header, 0).map_err(|e| e.to_string())?;
+
+    // Refcount table entries point at the refcount blocks, which follow it
+    let first_block = 1 + layout.refcount_table_clusters;
+    let table: Vec<u8> = (0..layout.refcount_blocks)
+        .flat_map(|i| ((first_block + i) * cluster_size).to_be_bytes())
+        .collect();
+    file.write_all_at(&table, cluster_size)
+        .map_err(|e| e.to_string())?;
+
+    // Every metadata cluster is in use exactly once
+    let refcounts: Vec<u8> = (0..total).flat_map(|_| 1u16.to_be_bytes()).collect();
+    file.write_all_at(&refcounts, first_block * cluster_size)
+        .map_err(|e| e.to_string())?;
+
+    // The L1 table stays all zeros: no clusters are allocated yet
+    file.sync_all().map_err(|e| e.to_string())
+}
+
+/// Create a new, empty qcow2 image at `path`. Existing files are never
+/// overwritten.
+pub fn create(path: &str, opts: &CreateOptions) -> Result<(), String> {
+    let err = |msg: String| format!("Error creating qcow2 image '{}': {}", path, msg);
+    let cs = opts.cluster_size;
+    if !cs.is_power_of_two() || !(512..=2 * 1024 * 1024).contains(&cs) {
+        return Err(err(
+            "Cluster size must be a power of two between 512 and 2M".to_string(),
+        ));
+    }
+    if let Some(backing) = &opts.backing_file
+        && (backing.is_empty() || backing.len() > MAX_BACKING_FILE_SIZE)
+    {
+        return Err(err(format!("Invalid backing file name '{}'", backing)));
+    }
+    if opts.backing_format.is_some() && opts.backing_file.is_none() {
+        return Err(err("Backing format requires a backing file".to_string()));
+    }
+    let size = opts.size.next_multiple_of(512);
+    log::debug!(
+        "Creating qcow2 image {} size={} cluster_size={} backing={:?}",
+        path,
+        size,
+        cs,
+        opts.backing_file
+    );
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| err(e.to_string()))?;
+    write_image(&file, opts, size).map_err(|e| {
+        let _ = std::fs::remove_file(path);
+        err(e)
+    })
+}
+
+fn be32(buf: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn be64(buf: &[u8], off: usize) -> u64 {
+    u64::from_be_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+fn read_string(file: &File, offset: u64, len: usize) -> Result<String, String> {
+    let mut buf = vec![0u8; len];
+    file.read_exact_at(&mut buf, offset)
+        .map_err(|e| e.to_string())?;
+    String::from_utf8(buf).map_err(|_| "Header string is not valid UTF-8".to_string())
+}
+
+fn parse_qcow2(file: &File, info: &mut ImageInfo) -> Result<(), String> {
+    let mut buf = [0u8; V3_HEADER_LENGTH as usize];
+    file.read_exact_at(&mut buf[..V2_HEADER_LENGTH as usize], 0)
+        .map_err(|e| format!("Truncated qcow2 header: {}", e))?;
+    let version = be32(&buf, 4);
+    let header_length = match version {
+        2 => V2_HEADER_LENGTH,
+        3 => {
+            file.read_exact_at(
+                &mut buf[V2_HEADER_LENGTH as usize..],
+                V2_HEADER_LENGTH as u64,
+            )
+            .map_err(|e| format!("Truncated qcow2 header: {}", e))?;
+            be32(&buf, 100)
+        }
+        v => return Err(format!("Unsupported qcow2 version {}", v)),
+    };
+    let cluster_bits = be32(&buf, 20);
+    if !(9..=21).contains(&cluster_bits) {
+        return Err(format!("Invalid qcow2 cluster bits {}", cluster_bits));
+    }
+    let cluster_size = 1u64 << cluster_bits;
+    if header_length < V2_HEADER_LENGTH || header_length as u64 > cluster_size {
+        return Err(format!("Invalid qcow2 header length {}", header_length));
+    }
+
+    info.compat = Some(if version == 2 { "0.10" } else { "1.1" }.to_string());
+    info.cluster_size = Some(cluster_size as u32);
+    info.virtual_size = be64(&buf, 24);
+    info.encrypted = be32(&buf, 32) != 0;
+    if version >= 3 {
+        let incompatible = be64(&buf, 72);
+        info.dirty = incompatible & INCOMPAT_DIRTY != 0;
+        info.corrupt = incompatible & INCOMPAT_CORRUPT != 0;
+    }
+
+    let backing_offset = be64(&buf, 8);
+    let backing_size = be32(&buf, 16) as usize;
+    if backing_offset != 0 && backing_size > 0 {
+        if backing_size > MAX_BACKING_FILE_SIZE {
+            return Err("Backing file name too long".to_string());
+        }
+        info.backing_file = Some(read_string(file, backing_offset, backing_size)?);
+    }
+
+    // Header extensions follow the header until the end marker, and
+    // never run into the backing file name
+    let ext_end = match backing_offset {
+        0 => cluster_size,
+        off => off.min(cluster_size),
+    };
+    let mut pos = header_length as u64;
+    while pos + 8 <= ext_end {
+        let mut ext = [0u8; 8];
+        file.read_exact_at(&mut ext, pos)
+            .map_err(|e| format!("Truncated qcow2 header extension: {}", e))?;
+        let (ext_type, len) = (be32(&ext, 0), be32(&ext, 4) as u64);
+        if ext_type == EXT_END {
+            break;
+        }
+        if pos + 8 + len > ext_end {
+            return Err("Invalid qcow2 header extension".to_string());
+        }
+        if ext_type == EXT_BACKING_FORMAT {
+            info.backing_format = Some(read_string(file, pos + 8, len as usize)?);
+        }
+        pos += 8 + len.next_multiple_of(8);
+    }
+    Ok(())
+}
+
+/// Read the image header at `path`. `format` forces the format when it's
+/// known, like for a backing file with a recorded format; otherwise it's
+/// probed, and anything that isn't qcow2 is raw.
+pub fn inspect_as(path: &str, format: Option<&str>) -> Result<ImageInfo, String> {
+    let err = |msg: String| format!("Error reading image '{}': {}", path, msg);
+    let mut file = File::open(path).map_err(|e| err(e.to_string()))?;
+    let mut magic = [0u8; 4];
+    let is_qcow2 = file.read_exact_at(&mut magic, 0).is_ok() && &magic == MAGIC;
+    let format = match format {
+        Some(f) => f,
+        None if is_qcow2 => "qcow2",
+        None => "raw",
+    };
+
+    let mut info = ImageInfo {
+        path: path.to_string(),
+        format: format.to_string(),
+        ..Default::default()
+    };
+    match format {
+        "qcow2" if is_qcow2 => parse_qcow2(&file, &mut info).map_err(err)?,
+        "qcow2" => return Err(err("Image is not in qcow2 format".to_string())),
+        "raw" => {
+            use std::io::{Seek, SeekFrom};
+            // Seeking also works for block devices
+            info.virtual_size = file
+                .seek(SeekFrom::End(0))
+                .map_err(|e| err(e.to_string()))?;
+        }
+        f => return Err(err(format!("Unsupported image format '{}'", f))),
+    }
+    Ok(info)
+}
+
+/// Read the image header at `path`, probing its format
+pub fn inspect(path: &str) -> Result<ImageInfo, String> {
+    inspect_as(path, None)
+}
+
+/// Inspect `path` and every image it's backed by, top image first
+pub fn backing_chain(path: &str) -> Result<Vec<ImageInfo>, String> {
+    let mut chain = vec![inspect(path)?];
+    let mut seen = vec![std::fs::canonicalize(path).map_err(|e| e.to_string())?];
+    while let Some(top) = chain.last() {
+        let Some(backing) = top.backing_path() else {
+            break;
+        };
+        if !Path::new(&backing).exists() {
+            return Err(format!(
+                "Backing file '{}' of '{}' does not exist",
+                backing, top.path
+            ));
+        }
+        let real = std::fs::canonicalize(&backing).map_err(|e| e.to_string())?;
+        if seen.contains(&real) || chain.len() >= MAX_CHAIN_DEPTH {
+            return Err(format!("Backing chain of '{}' loops or is too deep", path));
+        }
+        let info = inspect_as(&backing, top.backing_format.as_deref())?;
+        seen.push(real);
+        chain.push(info);
+    }
+    Ok(chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sum the refcounts of every cluster, walking the on-disk tables
+    fn check_refcounts(path: &str) -> u64 {
+        let file = File::open(path).unwrap();
+        let mut header = [0u8; 104];
+        file.read_exact_at(&mut header, 0).unwrap();
+        let cluster_size = 1u64 << be32(&header, 20);
+        let table_offset = be64(&header, 48);
+        let table_clusters = be32(&header, 56) as u64;
+        let len = file.metadata().unwrap().len();
+        assert_eq!(len % cluster_size, 0);
+
+        let mut table = vec![0u8; (table_clusters * cluster_size) as usize];
+        file.read_exact_at(&mut table, table_offset).unwrap();
+        let mut total = 0;
+        for entry in table.chunks(8) {
+            let block_offset = be64(entry, 0);
+            if block_offset == 0 {
+                continue;
+            }
+            let mut block = vec![0u8; cluster_size as usize];
+            file.read_exact_at(&mut block, block_offset).unwrap();
+            total += block
+                .chunks(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]) as u64)
+                .sum::<u64>();
+        }
+        assert_eq!(total, len / cluster_size);
+        total
+    }
+
+    #[test]
+    fn test_create_inspect() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("base.qcow2");
+        let base = base.to_str().unwrap();
+        let opts = CreateOptions {
+            size: 1024 * 1024 * 1024,
+            ..Default::default()
+        };
+        create(base, &opts).unwrap();
+        assert_eq!(check_refcounts(base), 4);
+        let info = inspect(base).unwrap();
+        assert_eq!(info.format, "qcow2");
+        assert_eq!(info.virtual_size, 1024 * 1024 * 1024);
+        assert_eq!(info.compat.as_deref(), Some("1.1"));
+        assert_eq!(info.cluster_size, Some(65536));
+        assert_eq!(info.backing_file, None);
+        assert!(!info.encrypted && !info.dirty && !info.corrupt);
+
+        let err = create(base, &opts).unwrap_err();
+        assert!(err.starts_with(&format!("Error creating qcow2 image '{}'", base)));
+
+        // Small clusters need several refcount blocks and L1 clusters
+        let small = dir.path().join("small.qcow2");
+        let small = small.to_str().unwrap();
+        let opts = CreateOptions {
+            size: 10 * 1024 * 1024 * 1024 + 1,
+            cluster_size: 512,
+            ..Default::default()
+        };
+        create(small, &opts).unwrap();
+        assert!(check_refcounts(small) > 2500);
+        let info = inspect(small).unwrap();
+        assert_eq!(info.virtual_size, 10 * 1024 * 1024 * 1024 + 512);
+
+        let opts = CreateOptions {
+            size: 1024,
+            cluster_size: 1000,
+            ..Default::default()
+        };
+        let bad = dir.path().join("bad.qcow2");
+        assert!(create(bad.to_str().unwrap(), &opts).is_err());
+        assert!(!bad.exists());
+    }
+
+    #[test]
+    fn test_backing_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let raw = dir.path().join("base.img");
+        File::create(&raw)
+            .unwrap()
+            .set_len(10 * 1024 * 1024)
+            .unwrap();
+        let mid = dir.path().join("mid.qcow2");
+        let mid = mid.to_str().unwrap();
+        create(
+            mid,
+            &CreateOptions {
+                size: 10 * 1024 * 1024,
+                backing_file: Some("base.img".to_string()),
+                backing_format: Some("raw".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let top = dir.path().join("top.qcow2");
+        let top = top.to_str().unwrap();
+        create(
+            top,
+            &CreateOptions {
+                size: 20 * 1024 * 1024,
+                cluster_size: 2 * 1024 * 1024,
+                backing_file: Some(mid.to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let chain = backing_chain(top).unwrap();
+        let summary: Vec<_> = chain
+            .iter()
+            .map(|i| {
+                (
+                    i.format.as_str(),
+                    i.virtual_size,
+                    i.backing_format.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("qcow2", 20 * 1024 * 1024, None),
+                ("qcow2", 10 * 1024 * 1024, Some("raw")),
+                ("raw", 10 * 1024 * 1024, None),
+            ]
+        );
+        assert_eq!(chain[1].backing_file.as_deref(), Some("base.img"));
+        assert_eq!(chain[2].path, raw.to_str().unwrap());
+
+        // Dirty and encrypted flags straight from the header
+        let file = OpenOptions::new().write(true).open(mid).unwrap();
+        file.write_all_at(&1u32.to_be_bytes(), 32).unwrap();
+        file.write_all_at(&INCOMPAT_DIRTY.to_be_bytes(), 72)
+            .unwrap();
+        let info = inspect(mid).unwrap();
+        assert!(info.encrypted && info.dirty && !info.corrupt);
+
+        std::fs::remove_file(&raw).unwrap();
+        let err = backing_chain(top).unwrap_err();
+        assert!(err.contains("base.img' of '"));
+    }
+}
@@ -0,0 +1,922 @@
+// RFB 3.8 (VNC) client protocol (replaces the gtk-vnc use in
+// virtManager/details/viewers.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! A blocking RFB client. It reads from one stream and writes through a
+//! shared writer, so a reader thread can own the client while the UI sends
+//! input with an `RfbSender`. The client asks for 32 bit true color pixels
+//! and keeps a `Framebuffer` up to date from the raw, copyrect, tight and
+//! ZRLE encodings, following server side desktop resizes.
+
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+use des::cipher::{Block, BlockEncrypt, KeyInit};
+use flate2::{Decompress, FlushDecompress, Status};
+use log::debug;
+
+use crate::framebuffer::Framebuffer;
+
+const ENCODING_RAW: i32 = 0;
+const ENCODING_COPYRECT: i32 = 1;
+const ENCODING_TIGHT: i32 = 7;
+const ENCODING_ZRLE: i32 = 16;
+/// Pseudo encoding allowing tight to send JPEG, at quality level 8 of 0-9
+const ENCODING_JPEG_QUALITY: i32 = -24;
+const ENCODING_DESKTOP_SIZE: i32 = -223;
+const ENCODING_LAST_RECT: i32 = -224;
+
+/// Upper bound for lengths sent by the server, so garbage can't make us
+/// allocate the world
+const MAX_LENGTH: usize = 64 * 1024 * 1024;
+
+pub type SharedWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Security type picked from the ones the server offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Auth {
+    None,
+    Vnc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RfbEvent {
+    /// Framebuffer contents changed
+    Update,
+    /// The server changed the desktop size
+    Resize(u32, u32),
+    Bell,
+    /// The guest clipboard changed
+    CutText(String),
+}
+
+/// X11 keysym of a key name like "Control_L" or "F5"
+pub fn keysym_from_name(name: &str) -> Option<u32> {
+    let sym = match name {
+        "BackSpace" => 0xff08,
+        "Tab" => 0xff09,
+        "Return" => 0xff0d,
+        "Pause" => 0xff13,
+        "Scroll_Lock" => 0xff14,
+        "Escape" => 0xff1b,
+        "Home" => 0xff50,
+        "Left" => 0xff51,
+        "Up" => 0xff52,
+        "Right" => 0xff53,
+        "Down" => 0xff54,
+        "Page_Up" => 0xff55,
+        "Page_Down" => 0xff56,
+        "End" => 0xff57,
+        "Print" => 0xff61,
+        "Insert" => 0xff63,
+        "Menu" => 0xff67,
+        "Num_Lock" => 0xff7f,
+        "Shift_L" => 0xffe1,
+        "Shift_R" => 0xffe2,
+        "Control_L" => 0xffe3,
+        "Control_R" => 0xffe4,
+        "Caps_Lock" => 0xffe5,
+        "Alt_L" => 0xffe9,
+        "Alt_R" => 0xffea,
+        "Super_L" => 0xffeb,
+        "Super_R" => 0xffec,
+        "Delete" => 0xffff,
+        "space" => 0x20,
+        _ => {
+            let num: u32 = name.strip_prefix('F')?.parse().ok()?;
+            return (1..=24).contains(&num).then_some(0xffbe + num - 1);
+        }
+    };
+    Some(sym)
+}
+
+/// X11 keysym typing the character `c`
+pub fn keysym_from_char(c: char) -> u32 {
+    match c as u32 {
+        cp @ (0x20..=0x7e | 0xa0..=0xff) => cp,
+        cp => 0x0100_0000 + cp,
+    }
+}
+
+/// Response to a VNC authentication challenge: the challenge DES
+/// encrypted with the password as key, each key byte bit reversed
+pub fn vnc_auth_response(challenge: &[u8; 16], password: &str) -> [u8; 16] {
+    let mut key = [0u8; 8];
+    for (k, b) in key.iter_mut().zip(password.bytes()) {
+        *k = b.reverse_bits();
+    }
+    let cipher = des::Des::new_from_slice(&key).expect("DES keys are 8 bytes");
+    let mut ret = *challenge;
+    for chunk in ret.chunks_mut(8) {
+        let mut block = Block::<des::Des>::default();
+        block.copy_from_slice(chunk);
+        cipher.encrypt_block(&mut block);
+        chunk.copy_from_slice(&block);
+    }
+    ret
+}
+
+fn io_err(e: std::io::Error) -> String {
+    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+        "Connection closed by the VNC server".to_string()
+    } else {
+        format!("Error talking to the VNC server: {}", e)
+    }
+}
+
+fn read_bytes(r: &mut dyn Read, len: usize) -> Result<Vec<u8>, String> {
+    if len > MAX_LENGTH {
+        return Err(format!("Invalid length {} sent by the VNC server", len));
+    }
+    let mut buf = vec![0; len];
+    r.read_exact(&mut buf).map_err(io_err)?;
+    Ok(buf)
+}
+
+fn read_u8(r: &mut dyn Read) -> Result<u8, String> {
+    Ok(read_bytes(r, 1)?[0])
+}
+
+fn read_u16(r: &mut dyn Read) -> Result<u16, String> {
+    let b = read_bytes(r, 2)?;
+    Ok(u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u32(r: &mut dyn Read) -> Result<u32, String> {
+    let b = read_bytes(r, 4)?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// A u32 length followed by that many latin-1 bytes
+fn read_string(r: &mut dyn Read) -> Result<String, String> {
+    let len = read_u32(r)? as usize;
+    Ok(read_bytes(r, len)?.iter().map(|&b| b as char).collect())
+}
+
+/// Tight's 1 to 3 byte length
+fn read_compact_len(r: &mut dyn Read) -> Result<usize, String> {
+    let mut len = 0;
+    for i in 0..3 {
+        let b = read_u8(r)? as usize;
+        if i == 2 {
+            return Ok(len | b << 14);
+        }
+        len |= (b & 0x7f) << (7 * i);
+        if b & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(len)
+}
+
+/// Feed `data` to a persistent zlib stream, returning what came out
+fn inflate(z: &mut Decompress, data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(data.len() * 4 + 1024);
+    let mut pos = 0;
+    loop {
+        if out.len() == out.capacity() {
+            out.reserve(out.capacity());
+        }
+        let (before_in, before_out) = (z.total_in(), out.len());
+        let status = z
+            .decompress_vec(&data[pos..], &mut out, FlushDecompress::Sync)
+            .map_err(|e| format!("Error decompressing VNC data: {}", e))?;
+        pos += (z.total_in() - before_in) as usize;
+        let stalled = z.total_in() == before_in && out.len() == before_out;
+        if status == Status::StreamEnd
+            || stalled
+            || (pos >= data.len() && out.len() < out.capacity())
+        {
+            return Ok(out);
+        }
+    }
+}
+
+/// Cursor over decompressed rectangle data
+struct Buf<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Buf<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let ret = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| "Truncated VNC rectangle data".to_string())?;
+        self.pos += len;
+        Ok(ret)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// ZRLE's 3 byte pixel: the low bytes of our little endian format
+    fn cpixel(&mut self) -> Result<[u8; 3], String> {
+        let b = self.take(3)?;
+        Ok([b[2], b[1], b[0]])
+    }
+
+    /// ZRLE run length: 255 bytes add up until a smaller one
+    fn run_len(&mut self) -> Result<usize, String> {
+        let mut len = 1;
+        loop {
+            let b = self.u8()?;
+            len += b as usize;
+            if b != 255 {
+                return Ok(len);
+            }
+        }
+    }
+}
+
+/// Rectangle the server sends pixels for
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+impl Rect {
+    /// Pixel `i` of the rectangle in row major order
+    fn at(&self, i: usize) -> (u32, u32) {
+        (
+            self.x + (i % self.w as usize) as u32,
+            self.y + (i / self.w as usize) as u32,
+        )
+    }
+
+    fn area(&self) -> usize {
+        self.w as usize * self.h as usize
+    }
+}
+
+/// Write side of a connection, for the UI to send input with
+#[derive(Clone)]
+pub struct RfbSender {
+    writer: SharedWriter,
+}
+
+impl RfbSender {
+    fn send(&self, msg: &[u8]) -> Result<(), String> {
+        let mut writer = self.writer.lock().unwrap();
+        writer
+            .write_all(msg)
+            .and_then(|_| writer.flush())
+            .map_err(io_err)
+    }
+
+    pub fn key_event(&self, down: bool, keysym: u32) -> Result<(), String> {
+        let mut msg = vec![4, down as u8, 0, 0];
+        msg.extend(keysym.to_be_bytes());
+        self.send(&msg)
+    }
+
+    /// `buttons` has bit 0 for the left button, 1 middle, 2 right and
+    /// 3 and 4 for wheel up and down
+    pub fn pointer_event(&self, buttons: u8, x: u16, y: u16) -> Result<(), String> {
+        let mut msg = vec![5, buttons];
+        msg.extend(x.to_be_bytes());
+        msg.extend(y.to_be_bytes());
+        self.send(&msg)
+    }
+
+    /// Set the guest clipboard. RFB only carries latin-1.
+    pub fn cut_text(&self, text: &str) -> Result<(), String> {
+        let data: Vec<u8> = text
+            .chars()
+            .map(|c| u8::try_from(c as u32).unwrap_or(b'?'))
+            .collect();
+        let mut msg = vec![6, 0, 0, 0];
+        msg.extend((data.len() as u32).to_be_bytes());
+        msg.extend(data);
+        self.send(&msg)
+    }
+
+    fn update_request(&self, incremental: bool, width: u32, height: u32) -> Result<(), String> {
+        let mut msg = vec![3, incremental as u8, 0, 0, 0, 0];
+        msg.extend((width as u16).to_be_bytes());
+        msg.extend((height as u16).to_be_bytes());
+        self.send(&msg)
+    }
+}
+
+pub struct RfbClient {
+    reader: Box<dyn Read + Send>,
+    sender: RfbSender,
+    auth: Auth,
+    /// Desktop name from the server
+    pub name: String,
+    fb: Arc<Mutex<Framebuffer>>,
+    zrle: Decompress,
+    tight: [Decompress; 4],
+}
+
+impl RfbClient {
+    /// Agree on the protocol version and security type
+    pub fn connect(
+        mut reader: Box<dyn Read + Send>,
+        writer: Box<dyn Write + Send>,
+    ) -> Result<Self, String> {
+        let version = read_bytes(reader.as_mut(), 12)?;
+        let version = String::from_utf8_lossy(&version).to_string();
+        let parsed = version
+            .strip_prefix("RFB ")
+            .and_then(|v| v.trim_end().split_once('.'))
+            .and_then(|(major, minor)| {
+                Some((major.parse::<u32>().ok()?, minor.parse::<u32>().ok()?))
+            });
+        match parsed {
+            Some(v) if v >= (3, 8) => {}
+            Some(_) => {
+                return Err(format!(
+                    "Unsupported RFB protocol version '{}'",
+                    version.trim_end()
+                ));
+            }
+            None => return Err("Not a VNC server".to_string()),
+        }
+        let sender = RfbSender {
+            writer: Arc::new(Mutex::new(writer)),
+        };
+        sender.send(b"RFB 003.008\n")?;
+
+        let count = read_u8(reader.as_mut())? as usize;
+        if count == 0 {
+            let reason = read_string(reader.as_mut())?;
+            return Err(format!("VNC server refused the connection: {}", reason));
+        }
+        let types = read_bytes(reader.as_mut(), count)?;
+        debug!("VNC server security types: {:?}", types);
+        let auth = if types.contains(&1) {
+            Auth::None
+        } else if types.contains(&2) {
+            Auth::Vnc
+        } else {
+            return Err(format!(
+                "The VNC server requires an unsupported authentication type: {:?}",
+                types
+            ));
+        };
+        Ok(Self {
+            reader,
+            sender,
+            auth,
+            name: String::new(),
+            fb: Arc::new(Mutex::new(Framebuffer::default())),
+            zrle: Decompress::new(true),
+            tight: std::array::from_fn(|_| Decompress::new(true)),
+        })
+    }
+
+    pub fn needs_password(&self) -> bool {
+        self.auth == Auth::Vnc
+    }
+
+    pub fn authenticate(&mut self, password: Option<&str>) -> Result<(), String> {
+        let reader = self.reader.as_mut();
+        match self.auth {
+            Auth::None => self.sender.send(&[1])?,
+            Auth::Vnc => {
+                self.sender.send(&[2])?;
+                let challenge: [u8; 16] = read_bytes(reader, 16)?.try_into().unwrap();
+                let response = vnc_auth_response(&challenge, password.unwrap_or_default());
+                self.sender.send(&response)?;
+            }
+        }
+        if read_u32(reader)? != 0 {
+            let reason = read_string(reader)?;
+            return Err(format!("Authentication failed: {}", reason));
+        }
+        Ok(())
+    }
+
+    /// Finish the handshake and ask for the first full update
+    pub fn init(&mut self, shared: bool) -> Result<(), String> {
+        self.sender.send(&[shared as u8])?;
+        let reader = self.reader.as_mut();
+        let width = read_u16(reader)? as u32;
+        let height = read_u16(reader)? as u32;
+        // We set our own pixel format below
+        read_bytes(reader, 16)?;
+        self.name = read_string(reader)?;
+        debug!("VNC desktop '{}' {}x{}", self.name, width, height);
+        *self.fb.lock().unwrap() = Framebuffer::new(width, height);
+
+        // 32bpp, depth 24, little endian true color, shifts 16/8/0
+        let mut msg = vec![0, 0, 0, 0, 32, 24, 0, 1, 0, 255, 0, 255, 0, 255, 16, 8, 0];
+        msg.extend([0, 0, 0]);
+        self.sender.send(&msg)?;
+
+        let encodings = [
+            ENCODING_ZRLE,
+            ENCODING_TIGHT,
+            ENCODING_COPYRECT,
+            ENCODING_RAW,
+            ENCODING_JPEG_QUALITY,
+            ENCODING_DESKTOP_SIZE,
+            ENCODING_LAST_RECT,
+        ];
+        let mut msg = vec![2, 0];
+        msg.extend((encodings.len() as u16).to_be_bytes());
+        for enc in encodings {
+            msg.extend(enc.to_be_bytes());
+        }
+        self.sender.send(&msg)?;
+        self.sender.update_request(false, width, height)
+    }
+
+    pub fn sender(&self) -> RfbSender {
+        self.sender.clone()
+    }
+
+    /// The framebuffer, updated as server messages are read
+    pub fn framebuffer(&self) -> Arc<Mutex<Framebuffer>> {
+        self.fb.clone()
+    }
+
+    /// Block for the next server message
+    pub fn next_events(&mut self) -> Result<Vec<RfbEvent>, String> {
+        let msgtype = read_u8(self.reader.as_mut())?;
+        match msgtype {
+            0 => self.read_update(),
+            1 => {
+                // Colour map entries, meaningless for true color
+                let reader = self.reader.as_mut();
+                read_bytes(reader, 3)?;
+                let count = read_u16(reader)? as usize;
+                read_bytes(reader, count * 6)?;
+                Ok(vec![])
+            }
+            2 => Ok(vec![RfbEvent::Bell]),
+            3 => {
+                let reader = self.reader.as_mut();
+                read_bytes(reader, 3)?;
+                Ok(vec![RfbEvent::CutText(read_string(reader)?)])
+            }
+            _ => Err(format!("Unknown VNC server message type {}", msgtype)),
+        }
+    }
+
+    fn read_update(&mut self) -> Result<Vec<RfbEvent>, String> {
+        let reader = self.reader.as_mut();
+        read_u8(reader)?;
+        let count = read_u16(reader)?;
+        let mut events = vec![];
+        for _ in 0..count {
+            let reader = self.reader.as_mut();
+            let rect = Rect {
+                x: read_u16(reader)? as u32,
+                y: read_u16(reader)? as u32,
+                w: read_u16(reader)? as u32,
+                h: read_u16(reader)? as u32,
+            };
+            let encoding = read_u32(reader)? as i32;
+            match encoding {
+                ENCODING_DESKTOP_SIZE => {
+                    debug!("VNC desktop resized to {}x{}", rect.w, rect.h);
+                    *self.fb.lock().unwrap() = Framebuffer::new(rect.w, rect.h);
+                    events.push(RfbEvent::Resize(rect.w, rect.h));
+                    continue;
+                }
+                ENCODING_LAST_RECT => break,
+                _ => {}
+            }
+            {
+                let fb = self.fb.lock().unwrap();
+                if rect.x + rect.w > fb.width || rect.y + rect.h > fb.height {
+                    return Err("VNC server sent a rectangle outside the desktop".to_string());
+                }
+            }
+            match encoding {
+                ENCODING_RAW => self.read_raw(rect)?,
+                ENCODING_COPYRECT => {
+                    let reader = self.reader.as_mut();
+                    let srcx = read_u16(reader)? as u32;
+                    let srcy = read_u16(reader)? as u32;
+                    let mut fb = self.fb.lock().unwrap();
+                    if srcx + rect.w > fb.width || srcy + rect.h > fb.height {
+                        return Err("VNC server sent a rectangle outside the desktop".to_string());
+                    }
+                    fb.copy_rect(srcx, srcy, rect.x, rect.y, rect.w, rect.h);
+                }
+                ENCODING_ZRLE => self.read_zrle(rect)?,
+                ENCODING_TIGHT => self.read_tight(rect)?,
+                _ => return Err(format!("Unexpected VNC encoding {}", encoding)),
+            }
+        }
+        events.push(RfbEvent::Update);
+        let (width, height) = {
+            let fb = self.fb.lock().unwrap();
+            (fb.width, fb.height)
+        };
+        self.sender.update_request(true, width, height)?;
+        Ok(events)
+    }
+
+    fn read_raw(&mut self, rect: Rect) -> Result<(), String> {
+        let data = read_bytes(self.reader.as_mut(), rect.area() * 4)?;
+        let mut fb = self.fb.lock().unwrap();
+        for (i, p) in data.chunks(4).enumerate() {
+            let (x, y) = rect.at(i);
+            fb.set_pixel(x, y, [p[2], p[1], p[0]]);
+        }
+        Ok(())
+    }
+
+    fn read_zrle(&mut self, rect: Rect) -> Result<(), String> {
+        let reader = self.reader.as_mut();
+        let len = read_u32(reader)? as usize;
+        let data = read_bytes(reader, len)?;
+        let data = inflate(&mut self.zrle, &data)?;
+        let mut buf = Buf {
+            data: &data,
+            pos: 0,
+        };
+        let mut fb = self.fb.lock().unwrap();
+        for ty in (rect.y..rect.y + rect.h).step_by(64) {
+            for tx in (rect.x..rect.x + rect.w).step_by(64) {
+                let tile = Rect {
+                    x: tx,
+                    y: ty,
+                    w: 64.min(rect.x + rect.w - tx),
+                    h: 64.min(rect.y + rect.h - ty),
+                };
+                zrle_tile(&mut buf, &mut fb, tile)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Tight data of `len` bytes: short data comes as is, longer data
+    /// through one of the four zlib streams
+    fn tight_data(&mut self, stream: usize, len: usize) -> Result<Vec<u8>, String> {
+        let reader = self.reader.as_mut();
+        if len < 12 {
+            return read_bytes(reader, len);
+        }
+        let zlen = read_compact_len(reader)?;
+        let data = read_bytes(reader, zlen)?;
+        let data = inflate(&mut self.tight[stream], &data)?;
+        if data.len() < len {
+            return Err("Truncated VNC rectangle data".to_string());
+        }
+        Ok(data)
+    }
+
+    fn read_tight(&mut self, rect: Rect) -> Result<(), String> {
+        let reader = self.reader.as_mut();
+        let control = read_u8(reader)?;
+        for (i, z) in self.tight.iter_mut().enumerate() {
+            if control & (1 << i) != 0 {
+                z.reset(true);
+            }
+        }
+        match control >> 4 {
+            // Fill
+            8 => {
+                let p = read_bytes(reader, 3)?;
+                let mut fb = self.fb.lock().unwrap();
+                fb.fill_rect(rect.x, rect.y, rect.w, rect.h, [p[0], p[1], p[2]]);
+            }
+            // JPEG
+            9 => {
+                let len = read_compact_len(reader)?;
+                let data = read_bytes(reader, len)?;
+                let image = Framebuffer::from_jpeg(&data)
+                    .map_err(|e| format!("Error decoding VNC JPEG data: {}", e))?;
+                if image.width < rect.w || image.height < rect.h {
+                    return Err("Truncated VNC rectangle data".to_string());
+                }
+                let mut fb = self.fb.lock().unwrap();
+                for i in 0..rect.area() {
+                    let (x, y) = rect.at(i);
+                    fb.set_pixel(x, y, image.pixel(x - rect.x, y - rect.y));
+                }
+            }
+            basic if basic < 8 => {
+                let stream = (basic & 3) as usize;
+                let filter = if basic & 4 != 0 { read_u8(reader)? } else { 0 };
+                match filter {
+                    // Copy
+                    0 => {
+                        let data = self.tight_data(stream, rect.area() * 3)?;
+                        let mut fb = self.fb.lock().unwrap();
+                        for (i, p) in data.chunks(3).take(rect.area()).enumerate() {
+                            let (x, y) = rect.at(i);
+                            fb.set_pixel(x, y, [p[0], p[1], p[2]]);
+                        }
+                    }
+                    // Palette
+                    1 => {
+                        let count = read_u8(reader)? as usize + 1;
+                        let palette = read_bytes(reader, count * 3)?;
+                        let color = |idx: usize| -> Result<[u8; 3], String> {
+                            palette
+                                .get(idx * 3..idx * 3 + 3)
+                                .map(|p| [p[0], p[1], p[2]])
+                                .ok_or_else(|| "Invalid VNC palette index".to_string())
+                        };
+                        let rowbytes = if count == 2 {
+                            (rect.w as usize).div_ceil(8)
+                        } else {
+                            rect.w as usize
+                        };
+                        let data = self.tight_data(stream, rowbytes * rect.h as usize)?;
+                        let mut fb = self.fb.lock().unwrap();
+                        for i in 0..rect.area() {
+                            let (x, y) = rect.at(i);
+                            let (col, row) = ((x - rect.x) as usize, (y - rect.y) as usize);
+                            let idx = if count == 2 {
+                                (data[row * rowbytes + col / 8] >> (7 - col % 8)) & 1
+                            } else {
+                                data[row * rowbytes + col]
+                            };
+                            fb.set_pixel(x, y, color(idx as usize)?);
+                        }
+                    }
+                    // Gradient
+                    2 => {
+                        let data = self.tight_data(stream, rect.area() * 3)?;
+                        let w = rect.w as usize;
+                        let mut out = vec![0u8; rect.area() * 3];
+                        for i in 0..rect.area() {
+                            let (col, row) = (i % w, i / w);
+                            for c in 0..3 {
+                                let left = if col > 0 {
+                                    out[(i - 1) * 3 + c] as i32
+                                } else {
+                                    0
+                                };
+                                let up = if row > 0 {
+                                    out[(i - w) * 3 + c] as i32
+                                } else {
+                                    0
+                                };
+                                let upleft = if col > 0 && row > 0 {
+                                    out[(i - w - 1) * 3 + c] as i32
+                                } else {
+                                    0
+                                };
+                                let predicted = (left + up - upleft).clamp(0, 255) as u8;
+                                out[i * 3 + c] = data[i * 3 + c].wrapping_add(predicted);
+                            }
+                        }
+                        let mut fb = self.fb.lock().unwrap();
+                        for (i, p) in out.chunks(3).enumerate() {
+                            let (x, y) = rect.at(i);
+                            fb.set_pixel(x, y, [p[0], p[1], p[2]]);
+                        }
+                    }
+                    _ => return Err(format!("Invalid tight filter {}", filter)),
+                }
+            }
+            _ => return Err(format!("Invalid tight compression control {:#x}", control)),
+        }
+        Ok(())
+    }
+}
+
+fn zrle_tile(buf: &mut Buf, fb: &mut Framebuffer, tile: Rect) -> Result<(), String> {
+    let subencoding = buf.u8()?;
+    match subencoding {
+        0 => {
+            for i in 0..tile.area() {
+                let (x, y) = tile.at(i);
+                fb.set_pixel(x, y, buf.cpixel()?);
+            }
+        }
+        1 => {
+            let rgb = buf.cpixel()?;
+            fb.fill_rect(tile.x, tile.y, tile.w, tile.h, rgb);
+        }
+        2..=16 => {
+            let palette = (0..subencoding)
+                .map(|_| buf.cpixel())
+                .collect::<Result<Vec<_>, _>>()?;
+            let bits = match subencoding {
+                2 => 1,
+                3 | 4 => 2,
+                _ => 4,
+            };
+            let rowbytes = (tile.w as usize * bits).div_ceil(8);
+            let packed = buf.take(rowbytes * tile.h as usize)?;
+            for i in 0..tile.area() {
+                let (x, y) = tile.at(i);
+                let (col, row) = ((x - tile.x) as usize, (y - tile.y) as usize);
+                let bit = col * bits;
+                let byte = packed[row * rowbytes + bit / 8];
+                let idx = (byte >> (8 - bits - bit % 8)) & ((1 << bits) - 1);
+                let rgb = palette
+                    .get(idx as usize)
+                    .ok_or_else(|| "Invalid VNC palette index".to_string())?;
+                fb.set_pixel(x, y, *rgb);
+            }
+        }
+        128 | 130..=255 => {
+            let palette = if subencoding == 128 {
+                vec![]
+            } else {
+                (0..subencoding - 128)
+                    .map(|_| buf.cpixel())
+                    .collect::<Result<Vec<_>, _>>()?
+            };
+            let mut i = 0;
+            while i < tile.area() {
+                let (rgb, len) = if palette.is_empty() {
+                    (buf.cpixel()?, buf.run_len()?)
+                } else {
+                    let idx = buf.u8()?;
+                    let len = if idx & 128 != 0 { buf.run_len()? } else { 1 };
+                    let rgb = *palette
+                        .get((idx & 127) as usize)
+                        .ok_or_else(|| "Invalid VNC palette index".to_string())?;
+                    (rgb, len)
+                };
+                if i + len > tile.area() {
+                    return Err("Invalid ZRLE run length".to_string());
+                }
+                for j in i..i + len {
+                    let (x, y) = tile.at(j);
+                    fb.set_pixel(x, y, rgb);
+                }
+                i += len;
+            }
+        }
+        _ => return Err(format!("Invalid ZRLE subencoding {}", subencoding)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viewers::ViewerInput;
+    use flate2::{Compress, Compression, FlushCompress};
+    use std::net::{TcpListener, TcpStream};
+
+    fn deflate(z: &mut Compress, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() + 64);
+        z.compress_vec(data, &mut out, FlushCompress::Sync).unwrap();
+        out
+    }
+
+    fn rect(x: u16, y: u16, w: u16, h: u16, encoding: i32) -> Vec<u8> {
+        let mut ret = vec![];
+        for v in [x, y, w, h] {
+            ret.extend(v.to_be_bytes());
+        }
+        ret.extend(encoding.to_be_bytes());
+        ret
+    }
+
+    /// Plays the server side of one session, returning the client
+    /// messages sent after the handshake
+    fn serve(mut s: TcpStream) -> Vec<u8> {
+        let read = |s: &mut TcpStream, len| {
+            let mut buf = vec![0; len];
+            s.read_exact(&mut buf).unwrap();
+            buf
+        };
+        s.write_all(b"RFB 003.008\n").unwrap();
+        assert_eq!(read(&mut s, 12), b"RFB 003.008\n");
+        s.write_all(&[1, 2]).unwrap();
+        assert_eq!(read(&mut s, 1), [2]);
+        let challenge: Vec<u8> = (0..16).collect();
+        s.write_all(&challenge).unwrap();
+        let response = read(&mut s, 16);
+        let expected = [
+            0xee, 0x22, 0x53, 0x9f, 0x33, 0xa5, 0x98, 0x3e, 0xc1, 0x2f, 0x9c, 0x2e, 0xdb, 0xc9,
+            0x95, 0xdd,
+        ];
+        assert_eq!(response, expected);
+        s.write_all(&0u32.to_be_bytes()).unwrap();
+
+        assert_eq!(read(&mut s, 1), [1]);
+        let mut init = vec![0, 8, 0, 4];
+        init.extend([0; 16]);
+        init.extend(7u32.to_be_bytes());
+        init.extend(b"test-vm");
+        s.write_all(&init).unwrap();
+        // SetPixelFormat, SetEncodings, FramebufferUpdateRequest
+        assert_eq!(read(&mut s, 20)[..8], [0, 0, 0, 0, 32, 24, 0, 1]);
+        let head = read(&mut s, 4);
+        read(&mut s, u16::from_be_bytes([head[2], head[3]]) as usize * 4);
+        assert_eq!(read(&mut s, 10), [3, 0, 0, 0, 0, 0, 0, 8, 0, 4]);
+
+        let mut msg = vec![0, 0, 0, 6];
+        // ZRLE: a plain RLE tile over the whole desktop, in two runs
+        let mut zrle = Compress::new(Compression::default(), true);
+        let tile = [128, 0, 0, 255, 19, 0, 255, 0, 11];
+        let data = deflate(&mut zrle, &tile);
+        msg.extend(rect(0, 0, 8, 4, ENCODING_ZRLE));
+        msg.extend((data.len() as u32).to_be_bytes());
+        msg.extend(data);
+        // Raw: two pixels, sent as BGRX
+        msg.extend(rect(0, 0, 2, 1, ENCODING_RAW));
+        msg.extend([1, 2, 3, 0, 4, 5, 6, 0]);
+        // Copy them down a row
+        msg.extend(rect(0, 1, 2, 1, ENCODING_COPYRECT));
+        msg.extend([0, 0, 0, 0]);
+        // Tight fill
+        msg.extend(rect(7, 3, 1, 1, ENCODING_TIGHT));
+        msg.extend([0x80, 10, 20, 30]);
+        // Tight two color palette, short enough to skip zlib
+        msg.extend(rect(0, 2, 8, 1, ENCODING_TIGHT));
+        msg.extend([0x40, 1, 1, 0, 0, 0, 255, 255, 255, 0b1010_0000]);
+        // Tight copy filter through zlib stream 0
+        let mut tight = Compress::new(Compression::default(), true);
+        let pixels: Vec<u8> = (0..12).collect();
+        let data = deflate(&mut tight, &pixels);
+        msg.extend(rect(4, 0, 4, 1, ENCODING_TIGHT));
+        msg.push(0x00);
+        msg.push(data.len() as u8);
+        msg.extend(data);
+        s.write_all(&msg).unwrap();
+        assert_eq!(read(&mut s, 10), [3, 1, 0, 0, 0, 0, 0, 8, 0, 4]);
+
+        let mut msg = vec![0, 0, 0, 1];
+        msg.extend(rect(0, 0, 16, 10, ENCODING_DESKTOP_SIZE));
+        msg.extend([3, 0, 0, 0, 0, 0, 0, 5]);
+        msg.extend(b"hello");
+        msg.push(2);
+        s.write_all(&msg).unwrap();
+        assert_eq!(read(&mut s, 10), [3, 1, 0, 0, 0, 0, 0, 16, 0, 10]);
+
+        let mut buf = vec![];
+        s.read_to_end(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_rfb_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || serve(listener.accept().unwrap().0));
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let writer = stream.try_clone().unwrap();
+        let mut client = RfbClient::connect(Box::new(stream), Box::new(writer)).unwrap();
+        assert!(client.needs_password());
+        client.authenticate(Some("secret")).unwrap();
+        client.init(true).unwrap();
+        assert_eq!(client.name, "test-vm");
+
+        assert_eq!(client.next_events().unwrap(), [RfbEvent::Update]);
+        {
+            let fb = client.framebuffer();
+            let fb = fb.lock().unwrap();
+            assert_eq!(fb.pixel(0, 0), [3, 2, 1]);
+            assert_eq!(fb.pixel(1, 1), [6, 5, 4]);
+            assert_eq!(fb.pixel(2, 1), [255, 0, 0]);
+            assert_eq!(fb.pixel(4, 3), [0, 255, 0]);
+            assert_eq!(fb.pixel(7, 3), [10, 20, 30]);
+            assert_eq!(fb.pixel(0, 2), [255, 255, 255]);
+            assert_eq!(fb.pixel(1, 2), [0, 0, 0]);
+            assert_eq!(fb.pixel(2, 2), [255, 255, 255]);
+            assert_eq!(fb.pixel(5, 0), [3, 4, 5]);
+        }
+        assert_eq!(
+            client.next_events().unwrap(),
+            [RfbEvent::Resize(16, 10), RfbEvent::Update]
+        );
+        assert_eq!(
+            client.next_events().unwrap(),
+            [RfbEvent::CutText("hello".into())]
+        );
+        assert_eq!(client.next_events().unwrap(), [RfbEvent::Bell]);
+
+        let sender = client.sender();
+        let ctrl = keysym_from_name("Control_L").unwrap();
+        sender.send_keys(&[ctrl, keysym_from_char('c')]).unwrap();
+        sender.pointer_event(1, 3, 4).unwrap();
+        sender.cut_text("hé€").unwrap();
+        drop(sender);
+        drop(client);
+
+        let sent = server.join().unwrap();
+        let mut expected = vec![];
+        for (down, sym) in [(1, ctrl), (1, 0x63), (0, 0x63), (0, ctrl)] {
+            expected.extend([4, down, 0, 0]);
+            expected.extend(sym.to_be_bytes());
+        }
+        expected.extend([5, 1, 0, 3, 0, 4]);
+        expected.extend([6, 0, 0, 0, 0, 0, 0, 3, b'h', 0xe9, b'?']);
+        assert_eq!(sent, expected);
+    }
+
+    #[test]
+    fn test_keysyms() {
+        assert_eq!(keysym_from_name("F1"), Some(0xffbe));
+        assert_eq!(keysym_from_name("F12"), Some(0xffc9));
+        assert_eq!(keysym_from_name("Delete"), Some(0xffff));
+        assert_eq!(keysym_from_name("F0"), None);
+        assert_eq!(keysym_from_char('é'), 0xe9);
+        assert_eq!(keysym_from_char('€'), 0x0100_20ac);
+    }
+}
@@ -0,0 +1,274 @@
+// Text consoles of serial and console devices (Iced port of
+// virtManager/details/serialcon.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `get_serialcon_devices` lists the `<serial>` and `<console>` devices
+//! of a guest that a text console can be opened on. A `SerialConsole` is
+//! an open stream to one of them, through the connection's
+//! `open_console` (libvirt's virDomainOpenConsole). A thread reads what
+//! the guest writes and hands it out as `SerialEvent`s through an iced
+//! subscription.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use iced::Subscription;
+use iced::futures::channel::mpsc::{UnboundedReceiver, unbounded};
+use iced::futures::{StreamExt, future, stream};
+use log::debug;
+
+use crate::connection::Connection;
+use crate::guest::Guest;
+
+/// Character device types a text console can be opened on
+const USABLE_TYPES: [&str; 2] = ["pty", "unix"];
+
+/// A serial or console device of the guest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialDevice {
+    /// "serial" or "console"
+    pub devtype: String,
+    /// Index among the devices of the same kind
+    pub idx: usize,
+    /// Character device type, like "pty"
+    pub chartype: String,
+    /// Device alias, set in the XML of a running guest
+    pub alias: Option<String>,
+    pub path: Option<String>,
+}
+
+impl SerialDevice {
+    pub fn label(&self) -> String {
+        if self.devtype == "console" {
+            format!("Text Console {}", self.idx + 1)
+        } else {
+            format!("Serial {}", self.idx + 1)
+        }
+    }
+
+    /// Why a text console can't be opened on the device, if it can't
+    pub fn can_connect(&self) -> Option<String> {
+        (!USABLE_TYPES.contains(&self.chartype.as_str())).then(|| {
+            format!(
+                "Console for device type '{}' is not supported",
+                self.chartype
+            )
+        })
+    }
+}
+
+/// Serial devices, then consoles, leaving out the console that mirrors
+/// the first serial device
+pub fn get_serialcon_devices(guest: &Guest) -> Vec<SerialDevice> {
+    let serials = guest.devices("serial");
+    let skip = serials
+        .first()
+        .is_some_and(|serial| guest.serial_is_console_dup(serial));
+    let consoles = guest
+        .devices("console")
+        .into_iter()
+        .enumerate()
+        .skip(usize::from(skip));
+    serials
+        .into_iter()
+        .enumerate()
+        .map(|(idx, dev)| ("serial", idx, dev))
+        .chain(consoles.map(|(idx, dev)| ("console", idx, dev)))
+        .map(|(devtype, idx, dev)| SerialDevice {
+            devtype: devtype.to_string(),
+            idx,
+            chartype: dev.attr("type").unwrap_or_default().to_string(),
+            alias: dev.get("./alias/@name"),
+            path: dev.get("./source/@path"),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SerialEvent {
+    /// Output of the guest
+    Data(Vec<u8>),
+    /// The stream ended, with the error if it failed
+    Closed(Option<String>),
+}
+
+static NEXT_CONSOLE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// How often the reader thread checks whether the console was closed
+const POLL_TIMEOUT_MS: i32 = 200;
+
+/// An open text console stream
+pub struct SerialConsole {
+    id: usize,
+    events: Arc<Mutex<Option<UnboundedReceiver<SerialEvent>>>>,
+    writer: Mutex<File>,
+    closed: Arc<AtomicBool>,
+}
+
+impl SerialConsole {
+    /// Open the console of `dev` on VM `name`, or the default one
+    pub fn open(
+        conn: &dyn Connection,
+        name: &str,
+        dev: Option<&SerialDevice>,
+    ) -> Result<Self, String> {
+        let alias = dev.and_then(|d| d.alias.as_deref());
+        debug!("Opening console stream for dev={:?} alias={:?}", dev, alias);
+        let fd = conn.open_console(name, alias)?;
+        let writer = File::from(fd);
+        let mut reader = writer
+            .try_clone()
+            .map_err(|e| format!("Error using the console stream: {}", e))?;
+        let closed = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = unbounded();
+        let stop = closed.clone();
+        std::thread::spawn(move || {
+            let ret = read_console(&mut reader, &stop, |data| {
+                tx.unbounded_send(SerialEvent::Data(data)).is_ok()
+            });
+            debug!("Console stream ended: {:?}", ret);
+            let _ = tx.unbounded_send(SerialEvent::Closed(ret.err()));
+        });
+        Ok(Self {
+            id: NEXT_CONSOLE_ID.fetch_add(1, Ordering::Relaxed),
+            events: Arc::new(Mutex::new(Some(rx))),
+            writer: Mutex::new(writer),
+            closed,
+        })
+    }
+
+    /// Send typed or pasted bytes to the guest
+    pub fn send(&self, data: &[u8]) -> Result<(), String> {
+        self.writer
+            .lock()
+            .unwrap()
+            .write_all(data)
+            .map_err(|e| format!("Error writing to text console: {}", e))
+    }
+
+    /// Events of this stream, for as long as it lasts
+    pub fn subscription(&self) -> Subscription<SerialEvent> {
+        let events = self.events.clone();
+        let events = stream::once(async move { events.lock().unwrap().take() })
+            .filter_map(future::ready)
+            .flatten();
+        Subscription::run_with_id(("serialcon", self.id), events)
+    }
+
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for SerialConsole {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Pass what `reader` gives to `on_data` until the stream ends, `stop`
+/// is set or `on_data` returns false
+fn read_console(
+    reader: &mut File,
+    stop: &AtomicBool,
+    mut on_data: impl FnMut(Vec<u8>) -> bool,
+) -> Result<(), String> {
+    let mut buf = vec![0; 4096];
+    while !stop.load(Ordering::Relaxed) {
+        let mut pfd = libc::pollfd {
+            fd: reader.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ret = unsafe { libc::poll(&mut pfd, 1, POLL_TIMEOUT_MS) };
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(format!("Error reading from text console: {}", err));
+        }
+        if ret == 0 {
+            continue;
+        }
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            // A pty reports the other end going away as EIO
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => break,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(format!("Error reading from text console: {}", e)),
+            Ok(n) => {
+                if !on_data(buf[..n].to_vec()) {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{TestConnection, testdriver};
+    use iced::futures::executor::block_on;
+
+    fn guest(conn: &TestConnection, name: &str) -> Guest {
+        Guest::parse(&conn.domain_xml(name, false).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_serialcon_devices() {
+        let conn = testdriver();
+        let devs = get_serialcon_devices(&guest(&conn, "test-many-devices"));
+        let labels: Vec<String> = devs.iter().map(SerialDevice::label).collect();
+        assert_eq!(
+            labels,
+            [
+                "Serial 1",
+                "Serial 2",
+                "Serial 3",
+                "Text Console 1",
+                "Text Console 2"
+            ]
+        );
+        assert_eq!(
+            devs[1].can_connect().as_deref(),
+            Some("Console for device type 'tcp' is not supported")
+        );
+        assert_eq!(devs[3].can_connect(), None);
+
+        // The console mirroring the serial port isn't listed twice
+        let devs = get_serialcon_devices(&guest(&conn, "test-alternate-devs"));
+        assert_eq!(devs.len(), 1);
+        assert_eq!(devs[0].label(), "Serial 1");
+        assert!(get_serialcon_devices(&guest(&conn, "test")).is_empty());
+    }
+
+    #[test]
+    fn test_serial_console_stream() {
+        let conn = testdriver();
+        assert!(SerialConsole::open(&conn, "test", None).is_err());
+        assert!(SerialConsole::open(&conn, "test-clone-full", None).is_err());
+
+        let console = SerialConsole::open(&conn, "test-alternate-devs", None).unwrap();
+        let mut events = console.events.lock().unwrap().take().unwrap();
+        console.send(b"ls\r").unwrap();
+        let mut got = vec![];
+        while got.len() < 4 {
+            match block_on(events.next()) {
+                Some(SerialEvent::Data(data)) => got.extend(data),
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+        assert_eq!(got, b"ls\r\n");
+
+        console.close();
+        assert_eq!(block_on(events.next()), Some(SerialEvent::Closed(None)));
+    }
+}
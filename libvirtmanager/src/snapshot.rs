@@ -0,0 +1,254 @@
+// Domain snapshot XML (port of virtinst/snapshot.py and the
+// vmmDomainSnapshot helpers of virtManager/object/domain.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `<domainsnapshot>` as serde structs. The `<domain>` copy libvirt
+//! embeds is arbitrary domain XML, so it is kept as an `xmlapi` element
+//! next to the serde fields and spliced back in when writing the XML.
+
+use quick_xml::de::from_str as from_xml_str;
+use quick_xml::se::to_string as to_xml_string;
+use serde::{Deserialize, Serialize};
+
+use crate::connection::DomainState;
+use crate::xmlapi::{Element, Node};
+
+/// `<state>` value for a captured domain state
+pub fn state_name(state: DomainState) -> &'static str {
+    match state {
+        DomainState::NoState => "nostate",
+        DomainState::Running => "running",
+        DomainState::Blocked => "blocked",
+        DomainState::Paused => "paused",
+        DomainState::Shutdown => "shutdown",
+        DomainState::Shutoff => "shutoff",
+        DomainState::Crashed => "crashed",
+        DomainState::PmSuspended => "pmsuspended",
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotParent {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotMemory {
+    /// "no", "internal" or "external"
+    #[serde(rename = "@snapshot", skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+    #[serde(rename = "@file", skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotDiskSource {
+    #[serde(rename = "@file", skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(rename = "@dev", skip_serializing_if = "Option::is_none")]
+    pub dev: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotDisk {
+    #[serde(rename = "@name")]
+    pub name: String,
+    /// "no", "internal" or "external"
+    #[serde(rename = "@snapshot", skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+    #[serde(rename = "@type", skip_serializing_if = "Option::is_none")]
+    pub source_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<SnapshotDiskSource>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotDisks {
+    #[serde(rename = "disk", default)]
+    pub disks: Vec<SnapshotDisk>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename = "domainsnapshot")]
+pub struct DomainSnapshot {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<SnapshotParent>,
+    #[serde(rename = "creationTime", skip_serializing_if = "Option::is_none")]
+    pub creation_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<SnapshotMemory>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disks: Option<SnapshotDisks>,
+    /// The domain config at snapshot time
+    #[serde(skip)]
+    pub domain: Option<Element>,
+    /// Set in the redefine format of the current snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<u8>,
+}
+
+impl DomainSnapshot {
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        let mut snap: Self =
+            from_xml_str(xml).map_err(|e| format!("Error parsing snapshot XML: {}", e))?;
+        snap.domain = Element::parse(xml)?.find("./domain").cloned();
+        Ok(snap)
+    }
+
+    pub fn get_xml(&self) -> Result<String, String> {
+        let xml = to_xml_string(self).map_err(|e| format!("Error writing snapshot XML: {}", e))?;
+        let mut root = Element::parse(&xml)?;
+        if let Some(domain) = &self.domain {
+            let idx = root.child_index("active", 0).unwrap_or(root.children.len());
+            root.children.insert(idx, Node::Element(domain.clone()));
+        }
+        root.prettify();
+        Ok(root.get_xml())
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or_default()
+    }
+
+    /// `xml` with only its `<description>` replaced. Edits go through
+    /// this rather than `get_xml`, which drops what the model doesn't
+    /// cover, like `<cookie>` and `<inactiveDomain>`.
+    pub fn set_description(xml: &str, description: Option<&str>) -> Result<String, String> {
+        let mut root = Element::parse(xml)?;
+        root.set("./description", description);
+        Ok(root.get_xml())
+    }
+
+    pub fn parent_name(&self) -> Option<&str> {
+        self.parent.as_ref().map(|p| p.name.as_str())
+    }
+
+    pub fn set_parent(&mut self, name: Option<&str>) {
+        self.parent = name.map(|n| SnapshotParent {
+            name: n.to_string(),
+        });
+    }
+
+    pub fn memory_type(&self) -> Option<&str> {
+        self.memory.as_ref()?.snapshot.as_deref()
+    }
+
+    pub fn memory_file(&self) -> Option<&str> {
+        self.memory.as_ref()?.file.as_deref()
+    }
+
+    pub fn disks(&self) -> &[SnapshotDisk] {
+        self.disks.as_ref().map_or(&[], |d| d.disks.as_slice())
+    }
+
+    /// The domain state captured, unknown values reading as shutoff
+    pub fn domain_state(&self) -> DomainState {
+        match self.state.as_deref() {
+            Some("nostate") => DomainState::NoState,
+            Some("running") => DomainState::Running,
+            Some("blocked") => DomainState::Blocked,
+            Some("paused") => DomainState::Paused,
+            Some("shutdown") => DomainState::Shutdown,
+            Some("crashed") => DomainState::Crashed,
+            Some("pmsuspended") => DomainState::PmSuspended,
+            _ => DomainState::Shutoff,
+        }
+    }
+
+    /// Captured state contains run state in addition to disk state
+    pub fn has_run_state(&self) -> bool {
+        matches!(
+            self.domain_state(),
+            DomainState::Running | DomainState::Paused
+        )
+    }
+
+    pub fn is_external(&self) -> bool {
+        self.memory_type() == Some("external")
+            || self
+                .disks()
+                .iter()
+                .any(|d| d.snapshot.as_deref() == Some("external"))
+    }
+
+    /// What an external snapshot captured
+    pub fn external_mode_label(&self) -> &'static str {
+        let mem = self.memory_type() == Some("external");
+        let disk = self
+            .disks()
+            .iter()
+            .any(|d| d.snapshot.as_deref() == Some("external"));
+        match (mem, disk) {
+            (true, true) => "External disk and memory",
+            (true, false) => "External memory only",
+            _ => "External disk only",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(name: &str) -> String {
+        std::fs::read_to_string(format!(
+            "{}/../tests/data/xmlparse/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_change_snapshot() {
+        let mut snap = DomainSnapshot::parse(&data("change-snapshot-in.xml")).unwrap();
+        assert_eq!(snap.name(), "offline-root-child1");
+        assert_eq!(snap.state.as_deref(), Some("shutoff"));
+        assert_eq!(snap.description.as_deref(), Some("offline desk"));
+        assert_eq!(snap.parent_name(), Some("offline-root"));
+        assert_eq!(snap.creation_time, Some(1375905916));
+        assert_eq!(snap.memory_type(), Some("no"));
+        assert_eq!(snap.memory_file(), None);
+        assert!(!snap.is_external());
+        assert!(!snap.has_run_state());
+
+        snap.name = Some("name-foo".to_string());
+        snap.state = Some("somestate".to_string());
+        snap.description = Some("foo\nnewline\n   indent".to_string());
+        snap.set_parent(Some("newparent"));
+        snap.creation_time = Some(1234);
+        snap.memory = Some(SnapshotMemory {
+            snapshot: Some("external".to_string()),
+            file: Some("/some/path/to/memory.img".to_string()),
+        });
+        let disk = &mut snap.disks.as_mut().unwrap().disks[0];
+        assert_eq!(disk.name, "hda");
+        assert_eq!(disk.snapshot.as_deref(), Some("internal"));
+        disk.name = "hdb".to_string();
+        disk.snapshot = Some("no".to_string());
+
+        assert!(snap.is_external());
+        assert_eq!(snap.external_mode_label(), "External memory only");
+        assert_eq!(snap.get_xml().unwrap(), data("change-snapshot-out.xml"));
+    }
+
+    #[test]
+    fn test_set_description() {
+        let xml = DomainSnapshot::set_description(
+            &data("snapshot-description-in.xml"),
+            Some("after edit\nsecond line"),
+        )
+        .unwrap();
+        assert_eq!(xml, data("snapshot-description-out.xml"));
+        let snap = DomainSnapshot::parse(&xml).unwrap();
+        assert_eq!(snap.description.as_deref(), Some("after edit\nsecond line"));
+    }
+}
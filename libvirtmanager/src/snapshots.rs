@@ -0,0 +1,867 @@
+// VM snapshots page (Iced port of virtManager/details/snapshots.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Lists the snapshots of one VM as a parent/child tree. Selecting one
+//! shows what it captured, its screenshot and its description, which can
+//! be edited in place. The list actions create, run (revert to) and
+//! delete snapshots. libvirt doesn't store screenshots, so the one taken
+//! when snapshotting a running VM is kept in the connection cache dir.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use iced::widget::{
+    Column, Space, button, checkbox, column, container, row, scrollable, text, text_input,
+};
+use iced::{Alignment, Element, Length, Task};
+use log::{debug, warn};
+
+use crate::connection::{Connection, SnapshotFlags, get_cache_dir};
+use crate::framebuffer::{Framebuffer, framebuffer};
+use crate::generatename::generate_name;
+use crate::guest::Guest;
+use crate::snapshot::{DomainSnapshot, SnapshotMemory};
+use crate::xmlapi::validate_generic_name;
+
+/// Screenshot file extensions by mime type
+const MIMEMAP: &[(&str, &str)] = &[("image/x-portable-pixmap", "ppm"), ("image/png", "png")];
+
+/// Screenshots are shrunk to fit in a square this size
+const SCREENSHOT_MAXSIZE: u32 = 450;
+
+const MIXED_MODES_MSG: &str =
+    "Mixing external and internal snapshots for the same VM is not recommended.";
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Refresh,
+    Select(String),
+    DescriptionChanged(String),
+    ApplyDescription,
+    New,
+    NewCancel,
+    NewFinish,
+    NewNameChanged(String),
+    NewDescriptionChanged(String),
+    NewExternalToggle(bool),
+    NewMemoryToggle(bool),
+    NewMemoryPathChanged(String),
+    NewQuiesceToggle(bool),
+    Run,
+    RunConfirmed(bool),
+    Delete,
+    DeleteChildrenToggle(bool),
+    DeleteConfirmed(bool),
+}
+
+/// State of the new snapshot form
+#[derive(Debug, Clone, Default)]
+struct NewSnapshot {
+    name: String,
+    description: String,
+    external: bool,
+    /// Save the memory of a running VM, rather than only the disks
+    memory: bool,
+    memory_path: String,
+    /// memory_path follows the name until it is edited
+    memory_path_auto: bool,
+    quiesce: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Confirm {
+    Run,
+    Delete,
+}
+
+/// Depth first order of the snapshot tree, as (depth, index) pairs.
+/// Snapshots whose parent is gone count as roots. Siblings list internal
+/// snapshots before external ones, then go by name.
+pub fn snapshot_tree(snaps: &[DomainSnapshot]) -> Vec<(usize, usize)> {
+    fn add(
+        snaps: &[DomainSnapshot],
+        parent: Option<&str>,
+        depth: usize,
+        ret: &mut Vec<(usize, usize)>,
+    ) {
+        let mut children: Vec<usize> = (0..snaps.len())
+            .filter(|&i| match (parent, snaps[i].parent_name()) {
+                (Some(parent), p) => p == Some(parent),
+                (None, p) => p.is_none_or(|p| !snaps.iter().any(|s| s.name() == p)),
+            })
+            .collect();
+        children.sort_by_key(|&i| (snaps[i].is_external(), snaps[i].name()));
+        for i in children {
+            ret.push((depth, i));
+            add(snaps, Some(snaps[i].name()), depth + 1, ret);
+        }
+    }
+    let mut ret = vec![];
+    add(snaps, None, 0, &mut ret);
+    ret
+}
+
+/// Local time of a unix timestamp, like python's str(datetime)
+pub fn format_timestamp(secs: i64) -> String {
+    let t = secs as libc::time_t;
+    // SAFETY: localtime_r only writes the passed tm
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&t, &mut tm) }.is_null() {
+        return secs.to_string();
+    }
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+pub struct SnapshotsPage {
+    conn: Arc<dyn Connection>,
+    name: String,
+    /// Where the screenshots of the VM's snapshots are kept
+    pub cache_dir: PathBuf,
+    snapshots: Vec<DomainSnapshot>,
+    /// The XML of each snapshot as libvirt returned it, by name
+    xmls: HashMap<String, String>,
+    current: Option<String>,
+    selected: Option<String>,
+    description: String,
+    /// The description has unapplied edits
+    pending: bool,
+    screenshot: Option<Framebuffer>,
+    new: Option<NewSnapshot>,
+    confirm: Option<Confirm>,
+    delete_children: bool,
+    /// Set when the list can't be read, replacing the page
+    list_error: Option<String>,
+    error: Option<String>,
+}
+
+impl SnapshotsPage {
+    pub fn new(conn: Arc<dyn Connection>, name: &str) -> Self {
+        let uuid = conn
+            .lookup_domain(name)
+            .map_or_else(|_| name.to_string(), |info| info.uuid);
+        let cache_dir = get_cache_dir(conn.uri()).join(uuid);
+        let mut page = Self {
+            conn,
+            name: name.to_string(),
+            cache_dir,
+            snapshots: vec![],
+            xmls: HashMap::new(),
+            current: None,
+            selected: None,
+            description: String::new(),
+            pending: false,
+            screenshot: None,
+            new: None,
+            confirm: None,
+            delete_children: false,
+            list_error: None,
+            error: None,
+        };
+        page.refresh();
+        page
+    }
+
+    fn is_active(&self) -> bool {
+        self.conn
+            .lookup_domain(&self.name)
+            .is_ok_and(|info| info.state.is_active())
+    }
+
+    fn selected_snapshot(&self) -> Option<&DomainSnapshot> {
+        let name = self.selected.as_deref()?;
+        self.snapshots.iter().find(|s| s.name() == name)
+    }
+
+    /// Re-read the snapshot list, keeping the selection if it still exists
+    pub fn refresh(&mut self) {
+        let ret = self.conn.list_snapshots(&self.name).and_then(|xmls| {
+            let snaps = xmls
+                .iter()
+                .map(|x| DomainSnapshot::parse(x))
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok((snaps, xmls, self.conn.current_snapshot(&self.name)?))
+        });
+        match ret {
+            Ok((snaps, xmls, current)) => {
+                self.xmls = snaps
+                    .iter()
+                    .map(|s| s.name().to_string())
+                    .zip(xmls)
+                    .collect();
+                self.snapshots = snaps;
+                self.current = current;
+                self.list_error = None;
+            }
+            Err(e) => {
+                debug!("Error refreshing snapshot list: {}", e);
+                self.snapshots.clear();
+                self.xmls.clear();
+                self.current = None;
+                self.list_error = Some(format!("Error refreshing snapshot list: {}", e));
+            }
+        }
+        if self.selected_snapshot().is_none() {
+            self.selected = None;
+        }
+        self.load_selected();
+    }
+
+    fn load_selected(&mut self) {
+        self.pending = false;
+        self.confirm = None;
+        self.description = self
+            .selected_snapshot()
+            .and_then(|s| s.description.clone())
+            .unwrap_or_default();
+        self.screenshot = self
+            .selected
+            .as_deref()
+            .and_then(|name| self.read_screenshot(name));
+    }
+
+    fn screenshot_path(&self, name: &str, ext: &str) -> PathBuf {
+        self.cache_dir
+            .join(format!("snap-screenshot-{}.{}", name, ext))
+    }
+
+    fn read_screenshot(&self, name: &str) -> Option<Framebuffer> {
+        MIMEMAP.iter().find_map(|(mime, ext)| {
+            let data = std::fs::read(self.screenshot_path(name, ext)).ok()?;
+            Framebuffer::decode(mime, &data)
+                .map_err(|e| debug!("Error reading screenshot of '{}': {}", name, e))
+                .ok()
+                .map(|fb| fb.scaled(SCREENSHOT_MAXSIZE))
+        })
+    }
+
+    /// Replace the saved screenshot of a snapshot, removing any stale one
+    fn save_screenshot(&self, name: &str, screenshot: Option<(String, Vec<u8>)>) {
+        for (_, ext) in MIMEMAP {
+            let _ = std::fs::remove_file(self.screenshot_path(name, ext));
+        }
+        let Some((mime, data)) = screenshot else {
+            return;
+        };
+        let Some((_, ext)) = MIMEMAP.iter().find(|(m, _)| *m == mime) else {
+            debug!("Don't know how to convert mime={} to extension", mime);
+            return;
+        };
+        let path = self.screenshot_path(name, ext);
+        debug!("Writing screenshot to {}", path.display());
+        if let Err(e) =
+            std::fs::create_dir_all(&self.cache_dir).and_then(|_| std::fs::write(&path, data))
+        {
+            warn!("Error saving screenshot '{}': {}", path.display(), e);
+        }
+    }
+
+    fn take_screenshot(&self) -> Option<(String, Vec<u8>)> {
+        if !self.is_active() {
+            debug!("Skipping screenshot since VM is not active");
+            return None;
+        }
+        let has_graphics = self
+            .conn
+            .domain_xml(&self.name, false)
+            .and_then(|xml| Guest::parse(&xml))
+            .is_ok_and(|g| !g.devices("graphics").is_empty());
+        if !has_graphics {
+            debug!("Skipping screenshot since VM has no graphics");
+            return None;
+        }
+        // Take two, qemu + qxl screenshots tend to show the data of the
+        // previous request: https://bugs.launchpad.net/qemu/+bug/1314293
+        let _ = self.conn.domain_screenshot(&self.name);
+        self.conn
+            .domain_screenshot(&self.name)
+            .map_err(|e| warn!("Error taking screenshot: {}", e))
+            .ok()
+    }
+
+    /// Snapshots are internal or external the same as the current one
+    fn current_is_external(&self) -> Option<bool> {
+        let current = self.current.as_deref()?;
+        self.snapshots
+            .iter()
+            .find(|s| s.name() == current)
+            .map(DomainSnapshot::is_external)
+    }
+
+    /// Default memory state file: next to the VM's first disk image
+    fn default_memory_path(&self, snapname: &str) -> String {
+        let Ok(guest) = self
+            .conn
+            .domain_xml(&self.name, true)
+            .and_then(|xml| Guest::parse(&xml))
+        else {
+            return String::new();
+        };
+        guest
+            .devices("disk")
+            .iter()
+            .filter_map(|d| d.get("./source/@file").or_else(|| d.get("./source/@dev")))
+            .find_map(|path| {
+                let dir = std::path::Path::new(&path).parent()?;
+                let memname = format!("{}-mem.{}", self.name, snapname);
+                Some(dir.join(memname).to_string_lossy().to_string())
+            })
+            .unwrap_or_default()
+    }
+
+    fn new_snapshot_form(&self) -> Result<NewSnapshot, String> {
+        let taken: Vec<&str> = self.snapshots.iter().map(DomainSnapshot::name).collect();
+        let name = generate_name("snapshot", |n| taken.contains(&n), "", 1, "", true)?;
+        Ok(NewSnapshot {
+            memory_path: self.default_memory_path(&name),
+            name,
+            external: self.current_is_external() != Some(false),
+            memory: true,
+            memory_path_auto: true,
+            ..Default::default()
+        })
+    }
+
+    fn validate_new(&self, new: &NewSnapshot) -> Result<(DomainSnapshot, SnapshotFlags), String> {
+        let err = |e: String| format!("Error validating snapshot: {}", e);
+        validate_generic_name("Snapshot", &new.name).map_err(err)?;
+        let mut snap = DomainSnapshot {
+            name: Some(new.name.clone()),
+            description: Some(new.description.clone()).filter(|d| !d.is_empty()),
+            ..Default::default()
+        };
+        let mut flags = SnapshotFlags::default();
+        if new.external {
+            let active = self.is_active();
+            if active && new.memory {
+                if new.memory_path.is_empty() {
+                    return Err(err("A memory state file must be specified".to_string()));
+                }
+                snap.memory = Some(SnapshotMemory {
+                    snapshot: Some("external".to_string()),
+                    file: Some(new.memory_path.clone()),
+                });
+            } else {
+                flags.disk_only = true;
+                flags.quiesce = active && new.quiesce;
+            }
+        }
+        Ok((snap, flags))
+    }
+
+    fn create(&mut self) -> Result<(), String> {
+        let Some(new) = &self.new else {
+            return Ok(());
+        };
+        let (snap, flags) = self.validate_new(new)?;
+        let screenshot = self.take_screenshot();
+        let xml = snap.get_xml()?;
+        debug!("Creating snapshot flags={:?} xml=\n{}", flags, xml);
+        let name = self
+            .conn
+            .create_snapshot(&self.name, &xml, flags)
+            .map_err(|e| format!("Error creating snapshot: {}", e))?;
+        self.save_screenshot(&name, screenshot);
+        self.new = None;
+        self.selected = Some(name);
+        self.refresh();
+        Ok(())
+    }
+
+    fn apply_description(&mut self) -> Result<(), String> {
+        let Some(snap) = self.selected_snapshot() else {
+            return Ok(());
+        };
+        let description = Some(self.description.as_str()).filter(|d| !d.is_empty());
+        if snap.description.as_deref() != description
+            && let Some(origxml) = self.xmls.get(snap.name())
+        {
+            let newxml = DomainSnapshot::set_description(origxml, description)?;
+            let flags = SnapshotFlags {
+                redefine: true,
+                ..Default::default()
+            };
+            self.conn.create_snapshot(&self.name, &newxml, flags)?;
+        }
+        self.refresh();
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        let Some(snap) = self.selected.clone() else {
+            return Ok(());
+        };
+        debug!("Running snapshot '{}'", snap);
+        self.conn
+            .revert_snapshot(&self.name, &snap)
+            .map_err(|e| format!("Error running snapshot '{}': {}", snap, e))?;
+        self.refresh();
+        Ok(())
+    }
+
+    fn delete(&mut self) -> Result<(), String> {
+        let Some(snap) = self.selected.clone() else {
+            return Ok(());
+        };
+        let mut deleted = vec![snap.clone()];
+        if self.delete_children {
+            let tree = snapshot_tree(&self.snapshots);
+            if let Some(pos) = tree
+                .iter()
+                .position(|(_, i)| self.snapshots[*i].name() == snap)
+            {
+                let depth = tree[pos].0;
+                deleted.extend(
+                    tree[pos + 1..]
+                        .iter()
+                        .take_while(|(d, _)| *d > depth)
+                        .map(|(_, i)| self.snapshots[*i].name().to_string()),
+                );
+            }
+        }
+        debug!("Deleting snapshot '{}'", snap);
+        self.conn
+            .delete_snapshot(&self.name, &snap, self.delete_children)
+            .map_err(|e| format!("Error deleting snapshot '{}': {}", snap, e))?;
+        for name in deleted {
+            self.save_screenshot(&name, None);
+        }
+        self.selected = None;
+        self.refresh();
+        Ok(())
+    }
+
+    fn report(&mut self, ret: Result<(), String>) {
+        self.error = ret.err();
+    }
+
+    pub fn update(&mut self, msg: Message) -> Task<Message> {
+        match msg {
+            Message::Refresh => {
+                self.error = None;
+                self.refresh();
+            }
+            Message::Select(name) => {
+                if self.pending {
+                    let ret = self.apply_description();
+                    self.report(ret);
+                }
+                self.selected = Some(name);
+                self.load_selected();
+            }
+            Message::DescriptionChanged(v) => {
+                self.pending = self
+                    .selected_snapshot()
+                    .is_some_and(|s| s.description.as_deref().unwrap_or_default() != v);
+                self.description = v;
+            }
+            Message::ApplyDescription => {
+                let ret = self.apply_description();
+                self.report(ret);
+            }
+            Message::New => {
+                self.confirm = None;
+                match self.new_snapshot_form() {
+                    Ok(form) => self.new = Some(form),
+                    Err(e) => self.error = Some(format!("Error creating snapshot: {}", e)),
+                }
+            }
+            Message::NewCancel => self.new = None,
+            Message::NewFinish => {
+                let ret = self.create();
+                self.report(ret);
+            }
+            Message::NewNameChanged(v) => {
+                let path = self.default_memory_path(&v);
+                if let Some(new) = &mut self.new {
+                    if new.memory_path_auto {
+                        new.memory_path = path;
+                    }
+                    new.name = v;
+                }
+            }
+            Message::NewDescriptionChanged(v) => {
+                if let Some(new) = &mut self.new {
+                    new.description = v;
+                }
+            }
+            Message::NewExternalToggle(v) => {
+                if let Some(new) = &mut self.new {
+                    new.external = v;
+                }
+            }
+            Message::NewMemoryToggle(v) => {
+                if let Some(new) = &mut self.new {
+                    new.memory = v;
+                }
+            }
+            Message::NewMemoryPathChanged(v) => {
+                if let Some(new) = &mut self.new {
+                    new.memory_path = v;
+                    new.memory_path_auto = false;
+                }
+            }
+            Message::NewQuiesceToggle(v) => {
+                if let Some(new) = &mut self.new {
+                    new.quiesce = v;
+                }
+            }
+            Message::Run => self.confirm = Some(Confirm::Run),
+            Message::RunConfirmed(yes) => {
+                self.confirm = None;
+                if yes {
+                    let ret = self.run();
+                    self.report(ret);
+                }
+            }
+            Message::Delete => {
+                self.delete_children = false;
+                self.confirm = Some(Confirm::Delete);
+            }
+            Message::DeleteChildrenToggle(v) => self.delete_children = v,
+            Message::DeleteConfirmed(yes) => {
+                self.confirm = None;
+                if yes {
+                    let ret = self.delete();
+                    self.report(ret);
+                }
+            }
+        }
+        Task::none()
+    }
+
+    fn labeled<'a>(
+        label: &'a str,
+        widget: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        row![text(label).width(Length::Fixed(120.0)), widget.into()]
+            .spacing(8)
+            .align_y(Alignment::Center)
+            .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        if let Some(err) = &self.list_error {
+            return column![
+                text(err.clone()),
+                button(text("Refresh")).on_press(Message::Refresh),
+            ]
+            .spacing(10)
+            .into();
+        }
+        let body = match &self.new {
+            Some(new) => self.view_new(new),
+            None => self.view_snapshot(),
+        };
+        let mut page: Column<Message> = column![body].spacing(10);
+        if let Some(err) = &self.error {
+            page = page.push(text(err.clone()).size(14));
+        }
+        row![
+            self.view_list(),
+            container(scrollable(page)).width(Length::Fill).padding(8),
+        ]
+        .spacing(16)
+        .height(Length::Fill)
+        .into()
+    }
+
+    fn view_list(&self) -> Element<'_, Message> {
+        let mut list: Column<Message> = column![].spacing(4);
+        for (depth, idx) in snapshot_tree(&self.snapshots) {
+            let snap = &self.snapshots[idx];
+            let mut name = snap.name().to_string();
+            if self.current.as_deref() == Some(snap.name()) {
+                name.push_str(" (current)");
+            }
+            let external = if snap.is_external() {
+                " (External)"
+            } else {
+                ""
+            };
+            let label = format!(
+                "{}\nVM State: {}{}",
+                name,
+                snap.domain_state().label(),
+                external
+            );
+            let style = if self.selected.as_deref() == Some(snap.name()) {
+                button::primary
+            } else {
+                button::text
+            };
+            list = list.push(row![
+                Space::with_width(Length::Fixed(16.0 * depth as f32)),
+                button(text(label).width(Length::Fill))
+                    .style(style)
+                    .padding(6)
+                    .on_press(Message::Select(snap.name().to_string())),
+            ]);
+        }
+        if self.snapshots.is_empty() {
+            list = list.push(text("No snapshots"));
+        }
+        let selected = self.selected.is_some() && self.new.is_none();
+        let actions = row![
+            button(text("New")).on_press(Message::New),
+            button(text("Run")).on_press_maybe(selected.then_some(Message::Run)),
+            button(text("Delete")).on_press_maybe(selected.then_some(Message::Delete)),
+            button(text("Refresh")).on_press(Message::Refresh),
+        ]
+        .spacing(6);
+        column![scrollable(list).height(Length::Fill), actions]
+            .spacing(10)
+            .width(Length::Fixed(280.0))
+            .into()
+    }
+
+    fn view_snapshot(&self) -> Element<'_, Message> {
+        let Some(snap) = self.selected_snapshot() else {
+            return text("No snapshot selected.").into();
+        };
+        let mut col: Column<Message> = column![
+            text(format!("Snapshot '{}':", snap.name())).size(18),
+            Self::labeled("Status:", text(snap.domain_state().label())),
+            Self::labeled(
+                "Timestamp:",
+                text(snap.creation_time.map(format_timestamp).unwrap_or_default()),
+            ),
+        ]
+        .spacing(10);
+        if snap.is_external() {
+            col = col.push(Self::labeled("Mode:", text(snap.external_mode_label())));
+        }
+        if self.current.as_deref() == Some(snap.name()) {
+            col = col.push(text("This is the current snapshot."));
+        }
+        col = col.push(Self::labeled(
+            "Description:",
+            text_input("", &self.description)
+                .on_input(Message::DescriptionChanged)
+                .padding(6),
+        ));
+        col = col.push(text("Screenshot").size(16));
+        col = col.push(match &self.screenshot {
+            Some(fb) => Element::from(framebuffer(fb)),
+            None => text("No screenshot available").into(),
+        });
+
+        match self.confirm {
+            Some(Confirm::Run) => {
+                let changes = if self.is_active() {
+                    "disk"
+                } else {
+                    "disk and configuration"
+                };
+                col = col.push(
+                    row![
+                        text(format!(
+                            "Are you sure you want to run the snapshot '{}'? All the {} \
+                             changes since the last snapshot was created will be discarded.",
+                            snap.name(),
+                            changes
+                        ))
+                        .width(Length::Fill),
+                        button(text("Run")).on_press(Message::RunConfirmed(true)),
+                        button(text("Cancel")).on_press(Message::RunConfirmed(false)),
+                    ]
+                    .spacing(8)
+                    .align_y(Alignment::Center),
+                );
+            }
+            Some(Confirm::Delete) => {
+                let has_children = self
+                    .snapshots
+                    .iter()
+                    .any(|s| s.parent_name() == Some(snap.name()));
+                col = col.push(text(
+                    "Are you sure you want to permanently delete the selected snapshot?",
+                ));
+                if has_children {
+                    col = col.push(
+                        checkbox("Delete its child snapshots too", self.delete_children)
+                            .on_toggle(Message::DeleteChildrenToggle),
+                    );
+                }
+                col = col.push(
+                    row![
+                        button(text("Delete")).on_press(Message::DeleteConfirmed(true)),
+                        button(text("Cancel")).on_press(Message::DeleteConfirmed(false)),
+                    ]
+                    .spacing(8),
+                );
+            }
+            None => {}
+        }
+        col.push(row![
+            Space::with_width(Length::Fill),
+            button(text("Apply")).on_press_maybe(self.pending.then_some(Message::ApplyDescription)),
+        ])
+        .into()
+    }
+
+    fn view_new<'a>(&'a self, new: &'a NewSnapshot) -> Element<'a, Message> {
+        let active = self.is_active();
+        let state = self
+            .conn
+            .lookup_domain(&self.name)
+            .map_or("Unknown", |info| info.state.label());
+        let mut col: Column<Message> = column![
+            text("Create snapshot").size(18),
+            Self::labeled(
+                "Name:",
+                text_input("", &new.name)
+                    .on_input(Message::NewNameChanged)
+                    .on_submit(Message::NewFinish)
+                    .padding(6),
+            ),
+            Self::labeled(
+                "Description:",
+                text_input("", &new.description)
+                    .on_input(Message::NewDescriptionChanged)
+                    .padding(6),
+            ),
+            Self::labeled("VM State:", text(state)),
+            checkbox("External snapshot", new.external).on_toggle(Message::NewExternalToggle),
+        ]
+        .spacing(10);
+        if new.external && active {
+            col = col.push(
+                checkbox("Save the memory state", new.memory).on_toggle(Message::NewMemoryToggle),
+            );
+            if new.memory {
+                col = col.push(Self::labeled(
+                    "Memory file:",
+                    text_input("", &new.memory_path)
+                        .on_input(Message::NewMemoryPathChanged)
+                        .padding(6),
+                ));
+            } else {
+                col = col.push(
+                    checkbox("Quiesce guest filesystems", new.quiesce)
+                        .on_toggle(Message::NewQuiesceToggle),
+                );
+            }
+        } else if new.external {
+            col = col.push(text("Only disk state will be saved."));
+        }
+        if self
+            .current_is_external()
+            .is_some_and(|ext| ext != new.external)
+        {
+            col = col.push(text(MIXED_MODES_MSG).size(14));
+        }
+        col.push(
+            row![
+                Space::with_width(Length::Fill),
+                button(text("Cancel")).on_press(Message::NewCancel),
+                button(text("Finish"))
+                    .on_press_maybe((!new.name.is_empty()).then_some(Message::NewFinish)),
+            ]
+            .spacing(8),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+
+    fn open() -> (SnapshotsPage, tempfile::TempDir) {
+        let conn = testdriver();
+        let mut page = SnapshotsPage::new(Arc::new(conn), "test-snapshots");
+        let dir = tempfile::tempdir().unwrap();
+        page.cache_dir = dir.path().join("cache");
+        (page, dir)
+    }
+
+    #[test]
+    fn test_snapshot_tree() {
+        let (page, _dir) = open();
+        let tree: Vec<(usize, &str)> = snapshot_tree(&page.snapshots)
+            .into_iter()
+            .map(|(d, i)| (d, page.snapshots[i].name()))
+            .collect();
+        assert_eq!(tree.len(), 10);
+        assert_eq!(tree[0], (0, "internal-root"));
+        assert_eq!(tree[1], (1, "internal-root-child1&"));
+        assert_eq!(tree[2], (0, "offline-root"));
+        assert_eq!(tree[3], (1, "offline-root-child1"));
+        assert_eq!(tree.last().unwrap(), &(0, "zzz-external-diskonly"));
+        assert_eq!(format_timestamp(0).len(), 19);
+    }
+
+    #[test]
+    fn test_snapshot_actions() {
+        let (mut page, _dir) = open();
+        let _ = page.update(Message::Select("internal-root".into()));
+        assert_eq!(page.description, "FOO\ndesc line #2\nba");
+        let _ = page.update(Message::DescriptionChanged("new desc".into()));
+        assert!(page.pending);
+        let _ = page.update(Message::ApplyDescription);
+        assert_eq!(page.error, None);
+        assert_eq!(
+            page.selected_snapshot().unwrap().description.as_deref(),
+            Some("new desc")
+        );
+
+        // The current snapshot is internal, so new ones default to that
+        let _ = page.update(Message::New);
+        assert_eq!(page.new.as_ref().unwrap().name, "snapshot1");
+        assert!(!page.new.as_ref().unwrap().external);
+        let _ = page.update(Message::NewNameChanged("bad/name".into()));
+        let _ = page.update(Message::NewFinish);
+        assert!(
+            page.error
+                .as_deref()
+                .unwrap()
+                .contains("can not contain '/'")
+        );
+        let _ = page.update(Message::NewNameChanged("snap".into()));
+        let _ = page.update(Message::NewFinish);
+        assert_eq!(page.error, None);
+        assert!(page.new.is_none());
+        assert_eq!(page.current.as_deref(), Some("snap"));
+        assert_eq!(page.selected.as_deref(), Some("snap"));
+        assert!(page.cache_dir.join("snap-screenshot-snap.ppm").exists());
+        assert_eq!(page.screenshot.as_ref().map(|s| s.width), Some(64));
+
+        // A disk only external snapshot of the running VM
+        let _ = page.update(Message::New);
+        let _ = page.update(Message::NewExternalToggle(true));
+        let _ = page.update(Message::NewMemoryToggle(false));
+        let _ = page.update(Message::NewFinish);
+        let snap = page.selected_snapshot().unwrap();
+        assert_eq!(snap.name(), "snapshot1");
+        assert_eq!(snap.parent_name(), Some("snap"));
+        assert!(snap.is_external());
+
+        let _ = page.update(Message::Select("offline-root".into()));
+        let _ = page.update(Message::Run);
+        let _ = page.update(Message::RunConfirmed(true));
+        assert_eq!(page.error, None);
+        assert!(!page.is_active());
+        assert_eq!(page.current.as_deref(), Some("offline-root"));
+
+        let _ = page.update(Message::Select("internal-root".into()));
+        let _ = page.update(Message::Delete);
+        let _ = page.update(Message::DeleteChildrenToggle(true));
+        let _ = page.update(Message::DeleteConfirmed(true));
+        assert_eq!(page.error, None);
+        assert!(!page.cache_dir.join("snap-screenshot-snap.ppm").exists());
+        assert_eq!(page.snapshots.len(), 8);
+        assert!(page.selected.is_none());
+    }
+}
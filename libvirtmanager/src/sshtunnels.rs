@@ -0,0 +1,257 @@
+// SSH tunnels to graphical consoles (port of the tunnel parts of
+// virtManager/details/sshtunnels.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! With a `qemu+ssh://` connection, displays listening on the remote
+//! host's loopback or on a unix socket there are reached by running
+//! `ssh` to that host with `nc` on the other end. Each tunnel gets one
+//! end of a socket pair as its stdin and stdout, and the viewer talks
+//! over the other end. Whatever ssh prints on stderr is kept, so a
+//! failed connection can say why. SPICE opens a tunnel per channel, so
+//! SSH keys or an agent save answering several password prompts.
+
+use std::io::Read;
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+
+use crate::viewers::{ConnectionInfo, ViewerStream};
+
+/// The ssh argv for tunnels to the `ginfo` display, if it needs one
+pub fn make_ssh_command(ginfo: &ConnectionInfo) -> Option<Vec<String>> {
+    if !ginfo.need_tunnel() {
+        return None;
+    }
+    let (host, port) = ginfo.get_tunnel_host();
+    let mut argv = vec!["ssh".to_string()];
+    if !port.is_empty() {
+        argv.extend(["-p".to_string(), port]);
+    }
+    if !ginfo.connuser.is_empty() {
+        argv.extend(["-l".to_string(), ginfo.connuser.clone()]);
+    }
+    if let Some(keyfile) = &ginfo.connkeyfile {
+        argv.extend(["-i".to_string(), keyfile.clone()]);
+    }
+    argv.push(host);
+
+    // Debian and SUSE netcats need -q to exit on EOF, or the remote nc
+    // lingers after the tunnel closes and later connections hang.
+    // Fedora's nc doesn't have the option and behaves that way already.
+    let nc_params = match &ginfo.gsocket {
+        Some(socket) => format!("-U {}", socket),
+        None => format!(
+            "{} {}",
+            ginfo.gaddr,
+            ginfo.gport.as_deref().unwrap_or_default()
+        ),
+    };
+    let nc_cmd = format!(
+        "nc -q 2>&1 | grep \"requires an argument\" >/dev/null;\
+         if [ $? -eq 0 ] ; then   CMD=\"nc -q 0 {p}\";\
+         else   CMD=\"nc {p}\";\
+         fi;eval \"$CMD\";",
+        p = nc_params
+    );
+    argv.push("sh -c".to_string());
+    argv.push(format!("'{}'", nc_cmd));
+    debug!(
+        "Pre-generated ssh command for ginfo: {}",
+        argv[1..].join(" ")
+    );
+    Some(argv)
+}
+
+struct Tunnel {
+    child: Child,
+    /// What the process printed on stderr so far, filled by a reader
+    /// thread as it arrives
+    errout: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Tunnel {
+    fn close(&mut self) {
+        debug!("Close tunnel PID={}", self.child.id());
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+
+    fn err_output(&self) -> String {
+        String::from_utf8_lossy(&self.errout.lock().unwrap()).to_string()
+    }
+}
+
+/// The tunnels of one graphical console
+pub struct SshTunnels {
+    command: Option<Vec<String>>,
+    tunnels: Mutex<Vec<Tunnel>>,
+}
+
+impl SshTunnels {
+    pub fn new(ginfo: &ConnectionInfo) -> Self {
+        Self {
+            command: make_ssh_command(ginfo),
+            tunnels: Mutex::new(vec![]),
+        }
+    }
+
+    /// Tunnels run with `ssh_cmd` in place of the `ssh` on PATH
+    #[cfg(test)]
+    fn with_ssh_cmd(ginfo: &ConnectionInfo, ssh_cmd: &str) -> Self {
+        let mut tunnels = Self::new(ginfo);
+        if let Some(argv) = tunnels.command.as_mut() {
+            argv[0] = ssh_cmd.to_string();
+        }
+        tunnels
+    }
+
+    /// Start another tunnel, returning the viewer's end of it
+    pub fn open_new(&self) -> Result<ViewerStream, String> {
+        let Some(argv) = &self.command else {
+            return Err("The console doesn't need an SSH tunnel".to_string());
+        };
+        let err = |e: std::io::Error| format!("Failed to open SSH tunnel: {}", e);
+        let (viewer, ssh) = UnixStream::pair().map_err(err)?;
+        let sshin = OwnedFd::from(ssh.try_clone().map_err(err)?);
+        let mut child = Command::new(&argv[0])
+            .args(&argv[1..])
+            .stdin(Stdio::from(sshin))
+            .stdout(Stdio::from(OwnedFd::from(ssh)))
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run '{}': {}", argv[0], e))?;
+        let mut stderr = child.stderr.take().unwrap();
+        let errout = Arc::new(Mutex::new(vec![]));
+        let buffer = errout.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; 1024];
+            while let Ok(n) = stderr.read(&mut buf)
+                && n > 0
+            {
+                buffer.lock().unwrap().extend_from_slice(&buf[..n]);
+            }
+        });
+        debug!("Opened tunnel PID={}", child.id());
+        self.tunnels.lock().unwrap().push(Tunnel { child, errout });
+        ViewerStream::fd(viewer.into())
+    }
+
+    pub fn close_all(&self) {
+        for mut tunnel in self.tunnels.lock().unwrap().drain(..) {
+            tunnel.close();
+        }
+    }
+
+    /// Distinct error output of all tunnels, as far as it has been read
+    pub fn get_err_output(&self) -> String {
+        let mut errstrings: Vec<String> = vec![];
+        for tunnel in self.tunnels.lock().unwrap().iter() {
+            let e = tunnel.err_output().trim().to_string();
+            if !e.is_empty() && !errstrings.contains(&e) {
+                errstrings.push(e);
+            }
+        }
+        errstrings.join("\n")
+    }
+}
+
+impl Drop for SshTunnels {
+    fn drop(&mut self) {
+        self.close_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xmlapi::Element;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::PermissionsExt;
+
+    fn ginfo(uri: &str, gdev: &str) -> ConnectionInfo {
+        ConnectionInfo::new(uri, &Element::parse(gdev).unwrap(), 0)
+    }
+
+    #[test]
+    fn test_ssh_command() {
+        let gdev = "<graphics type='vnc' port='5900' listen='0.0.0.0'/>";
+        assert_eq!(
+            make_ssh_command(&ginfo("qemu+ssh://host/system", gdev)),
+            None
+        );
+
+        let gdev = "<graphics type='vnc' port='5901'/>";
+        let argv = make_ssh_command(&ginfo(
+            "qemu+ssh://root@example.com:2222/system?keyfile=/root/.ssh/id",
+            gdev,
+        ))
+        .unwrap();
+        assert_eq!(
+            argv[..9],
+            [
+                "ssh",
+                "-p",
+                "2222",
+                "-l",
+                "root",
+                "-i",
+                "/root/.ssh/id",
+                "example.com",
+                "sh -c"
+            ]
+        );
+        assert!(argv[9].contains("CMD=\"nc -q 0 127.0.0.1 5901\""));
+
+        let gdev = "<graphics type='spice'><listen type='socket' socket='/run/spice.sock'/>\
+                    </graphics>";
+        let argv = make_ssh_command(&ginfo("qemu+ssh://example.com/system", gdev)).unwrap();
+        assert_eq!(argv[1], "example.com");
+        assert!(argv[3].contains("CMD=\"nc -U /run/spice.sock\""));
+    }
+
+    #[test]
+    fn test_tunnel_fake_ssh() {
+        // A fake ssh that reports its arguments on stderr and answers
+        // one line
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("ssh");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\necho \"ssh: $1 $2 $3\" >&2\nread line\necho \"got $line\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let tunnels = SshTunnels::with_ssh_cmd(
+            &ginfo(
+                "qemu+ssh://example.com:2222/system",
+                "<graphics type='vnc' port='5901'/>",
+            ),
+            script.to_str().unwrap(),
+        );
+        let mut stream = tunnels.open_new().unwrap();
+        stream.writer.write_all(b"hello\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(&mut stream.reader)
+            .read_line(&mut line)
+            .unwrap();
+        assert_eq!(line, "got hello\n");
+        // The process exiting closes the viewer's end
+        assert_eq!(stream.reader.read(&mut [0; 16]).unwrap(), 0);
+        // The reader thread may still be catching up with stderr
+        let start = std::time::Instant::now();
+        while tunnels.get_err_output().is_empty()
+            && start.elapsed() < std::time::Duration::from_secs(5)
+        {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(tunnels.get_err_output(), "ssh: -p 2222 example.com");
+        tunnels.close_all();
+        assert_eq!(tunnels.get_err_output(), "");
+    }
+}
@@ -0,0 +1,463 @@
+// Per-VM performance statistics (Iced port of
+// virtManager/lib/statsmanager.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `poll` starts a background thread that fetches the counters of every
+//! domain of a connection with one `all_domain_stats` call per interval,
+//! so the cost stays flat as the number of VMs grows. `StatsManager`
+//! turns consecutive samples into CPU percentages, memory use and
+//! disk/network rates, keeping the last `STATS_HISTORY_LENGTH` records
+//! of each VM for the graphs.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use iced::Subscription;
+use iced::futures::channel::mpsc::{UnboundedReceiver, unbounded};
+use iced::futures::{StreamExt, stream};
+use log::debug;
+
+use crate::connection::{Connection, DomainState, DomainStats, StatsFlags};
+
+/// Records kept per VM
+pub const STATS_HISTORY_LENGTH: usize = 120;
+
+/// Rates below this never set the scale of a graph, in KiB/s
+const MIN_MAX_RATE: f64 = 10.0;
+
+/// What to poll and how often
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StatsOptions {
+    pub interval: Duration,
+    pub flags: StatsFlags,
+}
+
+impl Default for StatsOptions {
+    /// The defaults of the stats preferences: CPU only, every 3 seconds
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(3),
+            flags: StatsFlags {
+                cpu: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// The counters of all domains at one point in time
+#[derive(Debug, Clone)]
+pub struct StatsSample {
+    /// Seconds since the epoch
+    pub timestamp: f64,
+    pub host_cpus: u32,
+    pub flags: StatsFlags,
+    pub stats: Result<Vec<DomainStats>, String>,
+}
+
+impl StatsSample {
+    /// Fetch the counters of every domain now
+    pub fn take(conn: &dyn Connection, flags: StatsFlags, host_cpus: u32) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let stats = if flags.is_empty() {
+            Ok(vec![])
+        } else {
+            conn.all_domain_stats(flags)
+        };
+        Self {
+            timestamp,
+            host_cpus,
+            flags,
+            stats,
+        }
+    }
+}
+
+/// Stats of one VM at one timestamp
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsRecord {
+    pub timestamp: f64,
+    /// CPU time used since the previous record, in nanoseconds
+    pub cpu_time: u64,
+    pub cpu_time_abs: u64,
+    pub cpu_host_percent: f64,
+    pub cpu_guest_percent: f64,
+    /// Memory in use by the guest, in KiB
+    pub curmem: u64,
+    pub mem_percent: f64,
+    disk_rd_kib: u64,
+    disk_wr_kib: u64,
+    net_rx_kib: u64,
+    net_tx_kib: u64,
+    /// Rates since the previous record, in KiB/s
+    pub disk_rd_rate: f64,
+    pub disk_wr_rate: f64,
+    pub net_rx_rate: f64,
+    pub net_tx_rate: f64,
+}
+
+/// The history of one VM, newest record first
+#[derive(Debug, Clone, Default)]
+pub struct StatsList {
+    records: VecDeque<StatsRecord>,
+    disk_rd_max_rate: f64,
+    disk_wr_max_rate: f64,
+    net_rx_max_rate: f64,
+    net_tx_max_rate: f64,
+}
+
+static EMPTY_STATS: StatsList = StatsList::new();
+
+impl StatsList {
+    pub const fn new() -> Self {
+        Self {
+            records: VecDeque::new(),
+            disk_rd_max_rate: MIN_MAX_RATE,
+            disk_wr_max_rate: MIN_MAX_RATE,
+            net_rx_max_rate: MIN_MAX_RATE,
+            net_tx_max_rate: MIN_MAX_RATE,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Add a record, filling in its rates from the previous one
+    pub fn append(&mut self, mut record: StatsRecord) {
+        self.records.truncate(STATS_HISTORY_LENGTH);
+        if let Some(old) = self.records.front() {
+            let timediff = record.timestamp - old.timestamp;
+            let rate = |new: u64, old: u64| {
+                if timediff > 0.0 {
+                    ((new as f64 - old as f64) / timediff).max(0.0)
+                } else {
+                    0.0
+                }
+            };
+            record.disk_rd_rate = rate(record.disk_rd_kib, old.disk_rd_kib);
+            record.disk_wr_rate = rate(record.disk_wr_kib, old.disk_wr_kib);
+            record.net_rx_rate = rate(record.net_rx_kib, old.net_rx_kib);
+            record.net_tx_rate = rate(record.net_tx_kib, old.net_tx_kib);
+        }
+        self.disk_rd_max_rate = self.disk_rd_max_rate.max(record.disk_rd_rate);
+        self.disk_wr_max_rate = self.disk_wr_max_rate.max(record.disk_wr_rate);
+        self.net_rx_max_rate = self.net_rx_max_rate.max(record.net_rx_rate);
+        self.net_tx_max_rate = self.net_tx_max_rate.max(record.net_tx_rate);
+        self.records.push_front(record);
+    }
+
+    /// The newest record, all zero before the first one
+    pub fn current(&self) -> StatsRecord {
+        self.records.front().cloned().unwrap_or_default()
+    }
+
+    /// Values of `field` divided by `ceil`, newest first, padded with
+    /// zeros to the history length or `limit`
+    pub fn vector(
+        &self,
+        limit: Option<usize>,
+        ceil: f64,
+        field: impl Fn(&StatsRecord) -> f64,
+    ) -> Vec<f32> {
+        let len = limit.map_or(STATS_HISTORY_LENGTH + 1, |l| {
+            l.min(STATS_HISTORY_LENGTH + 1)
+        });
+        (0..len)
+            .map(|i| {
+                self.records
+                    .get(i)
+                    .map_or(0.0, |r| (field(r) / ceil) as f32)
+            })
+            .collect()
+    }
+
+    pub fn guest_cpu_vector(&self, limit: Option<usize>) -> Vec<f32> {
+        self.vector(limit, 100.0, |r| r.cpu_guest_percent)
+    }
+
+    pub fn host_cpu_vector(&self, limit: Option<usize>) -> Vec<f32> {
+        self.vector(limit, 100.0, |r| r.cpu_host_percent)
+    }
+
+    pub fn memory_vector(&self, limit: Option<usize>) -> Vec<f32> {
+        self.vector(limit, 100.0, |r| r.mem_percent)
+    }
+
+    pub fn disk_io_rate(&self) -> f64 {
+        let cur = self.current();
+        cur.disk_rd_rate + cur.disk_wr_rate
+    }
+
+    pub fn network_traffic_rate(&self) -> f64 {
+        let cur = self.current();
+        cur.net_rx_rate + cur.net_tx_rate
+    }
+
+    pub fn disk_io_max_rate(&self) -> f64 {
+        self.disk_rd_max_rate.max(self.disk_wr_max_rate)
+    }
+
+    pub fn network_traffic_max_rate(&self) -> f64 {
+        self.net_rx_max_rate.max(self.net_tx_max_rate)
+    }
+
+    /// Read and write rates, scaled to `ceil` or to the highest rate seen
+    pub fn disk_io_vectors(&self, limit: Option<usize>, ceil: Option<f64>) -> (Vec<f32>, Vec<f32>) {
+        let ceil = ceil.unwrap_or_else(|| self.disk_io_max_rate());
+        (
+            self.vector(limit, ceil, |r| r.disk_rd_rate),
+            self.vector(limit, ceil, |r| r.disk_wr_rate),
+        )
+    }
+
+    /// Receive and transmit rates, scaled like disk_io_vectors
+    pub fn network_traffic_vectors(
+        &self,
+        limit: Option<usize>,
+        ceil: Option<f64>,
+    ) -> (Vec<f32>, Vec<f32>) {
+        let ceil = ceil.unwrap_or_else(|| self.network_traffic_max_rate());
+        (
+            self.vector(limit, ceil, |r| r.net_rx_rate),
+            self.vector(limit, ceil, |r| r.net_tx_rate),
+        )
+    }
+}
+
+/// The stats histories of the VMs of one connection
+#[derive(Debug, Clone, Default)]
+pub struct StatsManager {
+    vm_stats: HashMap<String, StatsList>,
+}
+
+impl StatsManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The history of VM `name`, empty if there is none yet
+    pub fn vm_stats(&self, name: &str) -> &StatsList {
+        self.vm_stats.get(name).unwrap_or(&EMPTY_STATS)
+    }
+
+    /// Record a sample for every VM in it. VMs missing from it are gone
+    /// and their history is dropped.
+    pub fn append_sample(&mut self, sample: &StatsSample) {
+        let allstats = match &sample.stats {
+            Ok(allstats) => allstats,
+            Err(e) => {
+                debug!("Error fetching domain stats: {}", e);
+                return;
+            }
+        };
+        self.vm_stats
+            .retain(|name, _| allstats.iter().any(|s| s.name == *name));
+        for stats in allstats {
+            let statslist = self.vm_stats.entry(stats.name.clone()).or_default();
+            let record = make_record(statslist, stats, sample);
+            statslist.append(record);
+        }
+    }
+
+    /// Highest disk rate of all VMs, to scale the list graphs alike
+    pub fn max_disk_rate(&self) -> f64 {
+        self.vm_stats
+            .values()
+            .map(StatsList::disk_io_max_rate)
+            .fold(MIN_MAX_RATE, f64::max)
+    }
+
+    pub fn max_net_rate(&self) -> f64 {
+        self.vm_stats
+            .values()
+            .map(StatsList::network_traffic_max_rate)
+            .fold(MIN_MAX_RATE, f64::max)
+    }
+}
+
+fn make_record(statslist: &StatsList, stats: &DomainStats, sample: &StatsSample) -> StatsRecord {
+    let mut record = StatsRecord {
+        timestamp: sample.timestamp,
+        ..Default::default()
+    };
+    let active = stats.state.is_active() && stats.state != DomainState::Crashed;
+    if !active {
+        return record;
+    }
+
+    if sample.flags.cpu {
+        let prev = statslist.records.front();
+        record.cpu_time_abs = stats.cpu_time;
+        record.cpu_time = stats
+            .cpu_time
+            .saturating_sub(prev.map_or(0, |p| p.cpu_time_abs));
+        // Nothing to compare the first sample with
+        if let Some(prev) = prev.filter(|p| sample.timestamp > p.timestamp) {
+            let pcentbase =
+                record.cpu_time as f64 * 100.0 / ((sample.timestamp - prev.timestamp) * 1e9);
+            record.cpu_host_percent = pcentbase / f64::from(sample.host_cpus.max(1));
+            if stats.vcpus > 0 {
+                record.cpu_guest_percent = pcentbase / f64::from(stats.vcpus);
+            }
+        }
+        record.cpu_host_percent = record.cpu_host_percent.clamp(0.0, 100.0);
+        record.cpu_guest_percent = record.cpu_guest_percent.clamp(0.0, 100.0);
+    }
+
+    if sample.flags.memory {
+        let totalmem = stats.balloon_current.max(1);
+        record.curmem = totalmem.saturating_sub(stats.balloon_unused.unwrap_or(totalmem));
+        record.mem_percent = (record.curmem as f64 / totalmem as f64 * 100.0).clamp(0.0, 100.0);
+    }
+
+    if sample.flags.disk {
+        record.disk_rd_kib = stats.disk_rd_bytes / 1024;
+        record.disk_wr_kib = stats.disk_wr_bytes / 1024;
+    }
+    if sample.flags.net {
+        record.net_rx_kib = stats.net_rx_bytes / 1024;
+        record.net_tx_kib = stats.net_tx_bytes / 1024;
+    }
+    record
+}
+
+/// Poll `conn` from a background thread, which stops once the
+/// subscription ends
+fn start_poller(
+    conn: Arc<dyn Connection>,
+    options: StatsOptions,
+) -> UnboundedReceiver<StatsSample> {
+    let (tx, rx) = unbounded();
+    std::thread::spawn(move || {
+        let host_cpus = conn
+            .host_cpu_count()
+            .map_err(|e| debug!("Error getting host CPU count: {}", e))
+            .unwrap_or(1);
+        debug!(
+            "Starting stats poller for {} every {:?}",
+            conn.uri(),
+            options.interval
+        );
+        loop {
+            let sample = StatsSample::take(conn.as_ref(), options.flags, host_cpus);
+            if tx.unbounded_send(sample).is_err() {
+                break;
+            }
+            std::thread::sleep(options.interval);
+        }
+        debug!("Stopped stats poller for {}", conn.uri());
+    });
+    rx
+}
+
+/// Stats samples of `conn` every `options.interval`
+pub fn poll(conn: Arc<dyn Connection>, options: StatsOptions) -> Subscription<StatsSample> {
+    let id = ("statsmanager", conn.uri().to_string(), options);
+    let samples = stream::once(async move { start_poller(conn, options) }).flatten();
+    Subscription::run_with_id(id, samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+    use iced::futures::executor::block_on;
+
+    fn sample(timestamp: f64, stats: Vec<DomainStats>) -> StatsSample {
+        StatsSample {
+            timestamp,
+            host_cpus: 4,
+            flags: StatsFlags::all(),
+            stats: Ok(stats),
+        }
+    }
+
+    fn running(name: &str, secs: u64) -> DomainStats {
+        DomainStats {
+            name: name.to_string(),
+            state: DomainState::Running,
+            cpu_time: secs * 1_000_000_000,
+            vcpus: 2,
+            balloon_current: 1000,
+            balloon_unused: Some(250),
+            disk_rd_bytes: secs * 100 * 1024,
+            disk_wr_bytes: 0,
+            net_rx_bytes: secs * 20 * 1024,
+            net_tx_bytes: secs * 40 * 1024,
+        }
+    }
+
+    #[test]
+    fn test_stats_records() {
+        let mut mgr = StatsManager::new();
+        mgr.append_sample(&sample(100.0, vec![running("vm", 0), running("gone", 0)]));
+        // One busy vCPU for 2 seconds out of 2 vCPUs and 4 host CPUs
+        mgr.append_sample(&sample(102.0, vec![running("vm", 2)]));
+        assert_eq!(mgr.vm_stats("gone").len(), 0);
+
+        let stats = mgr.vm_stats("vm");
+        assert_eq!(stats.len(), 2);
+        let cur = stats.current();
+        assert_eq!(cur.cpu_guest_percent, 50.0);
+        assert_eq!(cur.cpu_host_percent, 25.0);
+        assert_eq!((cur.curmem, cur.mem_percent), (750, 75.0));
+        assert_eq!((cur.disk_rd_rate, cur.disk_wr_rate), (100.0, 0.0));
+        assert_eq!(stats.network_traffic_rate(), 60.0);
+        assert_eq!(stats.disk_io_max_rate(), 100.0);
+        assert_eq!(mgr.max_net_rate(), 40.0);
+
+        assert_eq!(stats.guest_cpu_vector(Some(3)), [0.5, 0.0, 0.0]);
+        let (rd, wr) = stats.disk_io_vectors(Some(2), Some(200.0));
+        assert_eq!((rd, wr), (vec![0.5, 0.0], vec![0.0, 0.0]));
+        assert_eq!(stats.memory_vector(None).len(), STATS_HISTORY_LENGTH + 1);
+
+        // Shutoff VMs record zeros, and the history stays bounded
+        let mut off = running("vm", 0);
+        off.state = DomainState::Shutoff;
+        for i in 0..200 {
+            mgr.append_sample(&sample(200.0 + f64::from(i), vec![off.clone()]));
+        }
+        let stats = mgr.vm_stats("vm");
+        assert_eq!(stats.len(), STATS_HISTORY_LENGTH + 1);
+        assert_eq!(
+            stats.current(),
+            StatsRecord {
+                timestamp: 399.0,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_poller() {
+        let conn = testdriver();
+        let options = StatsOptions {
+            interval: Duration::from_millis(10),
+            flags: StatsFlags::all(),
+        };
+        let mut samples = start_poller(Arc::new(conn), options);
+        let mut mgr = StatsManager::new();
+        for _ in 0..3 {
+            let sample = block_on(samples.next()).unwrap();
+            assert_eq!(sample.host_cpus, 4);
+            mgr.append_sample(&sample);
+        }
+        let stats = mgr.vm_stats("test");
+        assert_eq!(stats.len(), 3);
+        assert!(stats.current().cpu_guest_percent > 0.0);
+        assert!(stats.current().mem_percent > 0.0);
+        assert!(mgr.vm_stats("test-many-devices").disk_io_rate() > 0.0);
+        assert_eq!(mgr.vm_stats("test-state-shutoff").current().curmem, 0);
+    }
+}
@@ -0,0 +1,1057 @@
+// Storage pool and volume XML (port of virtinst/storage.py and the
+// pretty printing of virtManager/object/storagepool.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `<pool>` and `<volume>` as serde structs, with the defaults and
+//! install steps virtinst applies when building new ones. Existing pools
+//! are edited as XML text since unknown elements are dropped by the
+//! structs.
+
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use quick_xml::de::from_str as from_xml_str;
+use quick_xml::se::to_string as to_xml_string;
+use serde::{Deserialize, Serialize};
+
+use crate::connection::Connection;
+use crate::generatename::generate_name;
+use crate::xmlapi::{Element, validate_generic_name};
+
+/// The pool types the create wizard offers, in its order
+pub const POOL_TYPES: [&str; 9] = [
+    "dir", "fs", "netfs", "logical", "disk", "iscsi", "rbd", "gluster", "mpath",
+];
+
+const DEFAULT_DEV_TARGET: &str = "/dev";
+const DEFAULT_SCSI_TARGET: &str = "/dev/disk/by-path";
+const DEFAULT_MPATH_TARGET: &str = "/dev/mapper";
+
+/// Where the `default` pool keeps its images
+pub fn default_pool_path(uri: &str) -> PathBuf {
+    if uri.ends_with("/session") {
+        let data = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))
+            .unwrap_or_else(std::env::temp_dir);
+        return data.join("libvirt").join("images");
+    }
+    PathBuf::from("/var/lib/libvirt/images")
+}
+
+/// Description of a pool type, like "Filesystem Directory"
+pub fn pretty_pool_type(ptype: &str) -> String {
+    match ptype {
+        "dir" => "Filesystem Directory",
+        "fs" => "Pre-Formatted Block Device",
+        "netfs" => "Network Exported Directory",
+        "logical" => "LVM Volume Group",
+        "disk" => "Physical Disk Device",
+        "iscsi" => "iSCSI Target",
+        "scsi" => "SCSI Host Adapter",
+        "mpath" => "Multipath Device Enumerator",
+        "gluster" => "Gluster Filesystem",
+        "rbd" => "RADOS Block Device/Ceph",
+        "zfs" => "ZFS Pool",
+        _ => return format!("{} pool", ptype),
+    }
+    .to_string()
+}
+
+/// Sizes like "12.50 GiB" or "512.00 MiB"
+pub fn pretty_bytes(val: u64) -> String {
+    const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+    let val = val as f64;
+    if val > GIB {
+        format!("{:.2} GiB", val / GIB)
+    } else {
+        format!("{:.2} MiB", val / (1024.0 * 1024.0))
+    }
+}
+
+/// Convert a libvirt storage size with its unit to bytes
+pub fn to_bytes(value: u64, unit: Option<&str>) -> Result<u64, String> {
+    let scale: u64 = match unit.unwrap_or("bytes") {
+        "b" | "bytes" => 1,
+        "KB" => 1000,
+        "k" | "K" | "KiB" => 1 << 10,
+        "MB" => 1000 * 1000,
+        "M" | "MiB" => 1 << 20,
+        "GB" => 1000 * 1000 * 1000,
+        "G" | "GiB" => 1 << 30,
+        "TB" => 1000 * 1000 * 1000 * 1000,
+        "T" | "TiB" => 1 << 40,
+        "PB" => 1000 * 1000 * 1000 * 1000 * 1000,
+        "P" | "PiB" => 1 << 50,
+        u => return Err(format!("Unknown storage size unit '{}'", u)),
+    };
+    value
+        .checked_mul(scale)
+        .ok_or_else(|| format!("Storage size {} {} is too large", value, unit.unwrap_or("")))
+}
+
+/// `<capacity>` and friends, with libvirt's optional unit
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageSize {
+    #[serde(rename = "@unit", skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(rename = "$text")]
+    pub value: u64,
+}
+
+impl StorageSize {
+    pub fn new(bytes: u64) -> Self {
+        Self {
+            unit: None,
+            value: bytes,
+        }
+    }
+
+    /// The size in bytes, 0 for an unknown unit
+    pub fn bytes(&self) -> u64 {
+        to_bytes(self.value, self.unit.as_deref()).unwrap_or(0)
+    }
+}
+
+fn size_bytes(size: &Option<StorageSize>) -> u64 {
+    size.as_ref().map(StorageSize::bytes).unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormatType {
+    #[serde(rename = "@type")]
+    pub ftype: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourcePath {
+    #[serde(rename = "@path")]
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolHost {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@port", skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolAdapter {
+    /// "scsi_host" or "fc_host"
+    #[serde(rename = "@type", skip_serializing_if = "Option::is_none")]
+    pub atype: Option<String>,
+    #[serde(rename = "@name", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolIqn {
+    #[serde(rename = "@name")]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolInitiator {
+    pub iqn: PoolIqn,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolAuthSecret {
+    #[serde(rename = "@uuid", skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    #[serde(rename = "@usage", skip_serializing_if = "Option::is_none")]
+    pub usage: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolAuth {
+    /// "chap" or "ceph"
+    #[serde(rename = "@type", skip_serializing_if = "Option::is_none")]
+    pub atype: Option<String>,
+    #[serde(rename = "@username", skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<PoolAuthSecret>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolSource {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<FormatType>,
+    #[serde(rename = "host", default)]
+    pub hosts: Vec<PoolHost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<SourcePath>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adapter: Option<PoolAdapter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<SourcePath>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initiator: Option<PoolInitiator>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<PoolAuth>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Permissions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolTarget {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+}
+
+fn is_unset<T: Default + PartialEq>(val: &Option<T>) -> bool {
+    val.as_ref().is_none_or(|v| *v == T::default())
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename = "pool")]
+pub struct StoragePool {
+    #[serde(rename = "@type")]
+    pub ptype: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<StorageSize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allocation: Option<StorageSize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available: Option<StorageSize>,
+    #[serde(skip_serializing_if = "is_unset")]
+    pub source: Option<PoolSource>,
+    #[serde(skip_serializing_if = "is_unset")]
+    pub target: Option<PoolTarget>,
+}
+
+impl StoragePool {
+    pub fn new(ptype: &str, name: &str) -> Self {
+        Self {
+            ptype: ptype.to_string(),
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        from_xml_str(xml).map_err(|e| format!("Error parsing storage pool XML: {}", e))
+    }
+
+    pub fn get_xml(&self) -> Result<String, String> {
+        let xml =
+            to_xml_string(self).map_err(|e| format!("Error writing storage pool XML: {}", e))?;
+        let mut root = Element::parse(&xml)?;
+        root.prettify();
+        Ok(root.get_xml())
+    }
+
+    /// A name like `basename` that no pool of `conn` uses yet
+    pub fn find_free_name(conn: &dyn Connection, basename: &str) -> Result<String, String> {
+        let names: Vec<String> = conn
+            .list_pools()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        generate_name(
+            basename,
+            |n| names.iter().any(|x| x == n),
+            "",
+            1,
+            "-",
+            false,
+        )
+    }
+
+    /// The pool whose target directory is `path`
+    pub fn lookup_pool_by_path(conn: &dyn Connection, path: &Path) -> Option<Self> {
+        conn.list_pools()
+            .ok()?
+            .iter()
+            .filter_map(|p| {
+                conn.pool_xml(&p.name, false)
+                    .and_then(|xml| Self::parse(&xml))
+                    .ok()
+            })
+            .find(|pool| {
+                pool.target_path()
+                    .and_then(|t| std::path::absolute(t).ok())
+                    .is_some_and(|t| t == path)
+            })
+    }
+
+    /// The pool at the default image path, else the one named `default`
+    pub fn lookup_default_pool(conn: &dyn Connection) -> Option<String> {
+        let path = default_pool_path(conn.uri());
+        if let Some(pool) = Self::lookup_pool_by_path(conn, &path) {
+            return Some(pool.name);
+        }
+        conn.list_pools()
+            .ok()?
+            .into_iter()
+            .find(|p| p.name == "default")
+            .map(|p| p.name)
+    }
+
+    pub fn validate_name(conn: &dyn Connection, name: &str) -> Result<(), String> {
+        validate_generic_name("Storage object", name)?;
+        if conn.pool_xml(name, true).is_ok() {
+            return Err(format!("Name '{}' already in use by another pool.", name));
+        }
+        Ok(())
+    }
+
+    fn is_type(&self, types: &[&str]) -> bool {
+        types.contains(&self.ptype.as_str())
+    }
+
+    pub fn supports_target_path(&self) -> bool {
+        self.is_type(&["dir", "fs", "netfs", "iscsi", "scsi", "mpath"])
+    }
+
+    pub fn supports_source_name(&self) -> bool {
+        self.is_type(&["logical", "gluster", "rbd", "zfs"])
+    }
+
+    pub fn supports_source_path(&self) -> bool {
+        self.is_type(&["fs", "netfs", "disk", "iscsi", "scsi", "gluster"])
+    }
+
+    pub fn supports_hosts(&self) -> bool {
+        self.is_type(&["netfs", "iscsi", "gluster", "rbd"])
+    }
+
+    pub fn supports_format(&self) -> bool {
+        self.is_type(&["fs", "netfs", "disk"])
+    }
+
+    pub fn supports_iqn(&self) -> bool {
+        self.is_type(&["iscsi"])
+    }
+
+    /// Whether new volumes can be created in the pool, or cloned into
+    /// it with `clone`
+    pub fn supports_volume_creation(&self, clone: bool) -> bool {
+        self.is_type(&["dir", "fs", "netfs", "disk", "logical", "rbd"])
+            || (!clone && self.ptype == "zfs")
+    }
+
+    /// Volumes of the pool are files, block devices or network disks:
+    /// "file", "block" or "network"
+    pub fn disk_type(&self) -> &'static str {
+        if self.is_type(&["disk", "logical", "scsi", "mpath", "zfs"]) {
+            "block"
+        } else if self.is_type(&["gluster", "rbd", "iscsi"]) {
+            "network"
+        } else {
+            "file"
+        }
+    }
+
+    pub fn default_target_path(&self, uri: &str) -> Option<String> {
+        match self.ptype.as_str() {
+            "dir" | "fs" | "netfs" => Some(
+                default_pool_path(uri)
+                    .join(&self.name)
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+            "iscsi" | "scsi" => Some(DEFAULT_SCSI_TARGET.to_string()),
+            "mpath" => Some(DEFAULT_MPATH_TARGET.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn default_source_name(&self) -> Option<String> {
+        match self.ptype.as_str() {
+            "rbd" => Some("rbd".to_string()),
+            "gluster" => Some("gv0".to_string()),
+            _ => None,
+        }
+    }
+
+    fn source_mut(&mut self) -> &mut PoolSource {
+        self.source.get_or_insert_with(Default::default)
+    }
+
+    pub fn target_path(&self) -> Option<&str> {
+        self.target.as_ref()?.path.as_deref()
+    }
+
+    pub fn set_target_path(&mut self, path: Option<&str>) {
+        self.target.get_or_insert_with(Default::default).path = path.map(str::to_string);
+    }
+
+    /// The source directory, adapter or device, depending on the type
+    pub fn source_path(&self) -> Option<&str> {
+        let source = self.source.as_ref()?;
+        match self.ptype.as_str() {
+            "netfs" | "gluster" => source.dir.as_ref().map(|d| d.path.as_str()),
+            "scsi" => source.adapter.as_ref()?.name.as_deref(),
+            _ => source.device.as_ref().map(|d| d.path.as_str()),
+        }
+    }
+
+    pub fn set_source_path(&mut self, path: Option<&str>) {
+        let ptype = self.ptype.clone();
+        let source = self.source_mut();
+        let path = path.map(|p| SourcePath {
+            path: p.to_string(),
+        });
+        match ptype.as_str() {
+            "netfs" | "gluster" => source.dir = path,
+            "scsi" => {
+                source.adapter = path.map(|p| PoolAdapter {
+                    atype: None,
+                    name: Some(p.path),
+                })
+            }
+            _ => source.device = path,
+        }
+    }
+
+    pub fn source_name(&self) -> Option<&str> {
+        self.source.as_ref()?.name.as_deref()
+    }
+
+    pub fn set_source_name(&mut self, name: Option<&str>) {
+        self.source_mut().name = name.map(str::to_string);
+    }
+
+    pub fn format(&self) -> Option<&str> {
+        Some(self.source.as_ref()?.format.as_ref()?.ftype.as_str())
+    }
+
+    pub fn set_format(&mut self, fmt: Option<&str>) {
+        self.source_mut().format = fmt.map(|f| FormatType {
+            ftype: f.to_string(),
+        });
+    }
+
+    pub fn add_host(&mut self, name: &str) {
+        self.source_mut().hosts.push(PoolHost {
+            name: name.to_string(),
+            port: None,
+        });
+    }
+
+    pub fn iqn(&self) -> Option<&str> {
+        Some(self.source.as_ref()?.initiator.as_ref()?.iqn.name.as_str())
+    }
+
+    pub fn set_iqn(&mut self, iqn: Option<&str>) {
+        self.source_mut().initiator = iqn.map(|i| PoolInitiator {
+            iqn: PoolIqn {
+                name: i.to_string(),
+            },
+        });
+    }
+
+    pub fn capacity(&self) -> u64 {
+        size_bytes(&self.capacity)
+    }
+
+    pub fn allocation(&self) -> u64 {
+        size_bytes(&self.allocation)
+    }
+
+    pub fn available(&self) -> u64 {
+        size_bytes(&self.available)
+    }
+
+    /// Fill in the target path, source name and format defaults, after
+    /// checking the name is free on `conn`
+    pub fn validate(&mut self, conn: &dyn Connection) -> Result<(), String> {
+        Self::validate_name(conn, &self.name)?;
+        if self.target_path().is_none_or(str::is_empty) {
+            // A disk pool demands a target path, but can't handle
+            // anything other than /dev
+            let target = if self.ptype == "disk" {
+                Some(DEFAULT_DEV_TARGET.to_string())
+            } else {
+                self.default_target_path(conn.uri())
+            };
+            self.set_target_path(target.as_deref());
+        }
+        if self.source_name().is_none() {
+            let name = self.default_source_name();
+            self.set_source_name(name.as_deref());
+        }
+        if self.format().is_none() && self.supports_format() {
+            self.set_format(Some("auto"));
+        }
+        // There's no explicit "auto" for disk pools, leaving the format
+        // out does the job for existing formatted disks
+        if self.ptype == "disk" && self.format() == Some("auto") {
+            self.set_format(None);
+        }
+        Ok(())
+    }
+
+    /// Define the pool, then optionally build, start and autostart it.
+    /// On failure the new definition is removed again.
+    pub fn install(
+        &self,
+        conn: &dyn Connection,
+        build: bool,
+        create: bool,
+        autostart: bool,
+    ) -> Result<(), String> {
+        let xml = self.get_xml()?;
+        debug!("Creating storage pool '{}' with xml:\n{}", self.name, xml);
+        conn.define_pool(&xml)
+            .map_err(|e| format!("Could not define storage pool: {}", e))?;
+
+        let mut ret = Ok(());
+        if build {
+            ret = conn
+                .build_pool(&self.name)
+                .map_err(|e| format!("Could not build storage pool: {}", e));
+        }
+        if create && ret.is_ok() {
+            ret = conn
+                .start_pool(&self.name)
+                .map_err(|e| format!("Could not start storage pool: {}", e));
+        }
+        if autostart && ret.is_ok() {
+            ret = conn
+                .set_pool_autostart(&self.name, true)
+                .map_err(|e| format!("Could not set pool autostart flag: {}", e));
+        }
+        if ret.is_err()
+            && let Err(e) = conn.undefine_pool(&self.name)
+        {
+            debug!("Error cleaning up pool after failure: {}", e);
+        }
+        ret
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeLazyRefcounts {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeFeatures {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lazy_refcounts: Option<VolumeLazyRefcounts>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeTarget {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<FormatType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub features: Option<VolumeFeatures>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeBackingStore {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<FormatType>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename = "volume")]
+pub struct StorageVolume {
+    /// "file", "block", "dir", "network" or "netdir"
+    #[serde(rename = "@type", skip_serializing_if = "Option::is_none")]
+    pub vtype: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<StorageSize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allocation: Option<StorageSize>,
+    #[serde(skip_serializing_if = "is_unset")]
+    pub target: Option<VolumeTarget>,
+    #[serde(rename = "backingStore", skip_serializing_if = "Option::is_none")]
+    pub backing_store: Option<VolumeBackingStore>,
+}
+
+impl StorageVolume {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        from_xml_str(xml).map_err(|e| format!("Error parsing storage volume XML: {}", e))
+    }
+
+    pub fn get_xml(&self) -> Result<String, String> {
+        let xml =
+            to_xml_string(self).map_err(|e| format!("Error writing storage volume XML: {}", e))?;
+        let mut root = Element::parse(&xml)?;
+        root.prettify();
+        Ok(root.get_xml())
+    }
+
+    /// File name extension of images in `fmt`, like ".qcow2"
+    pub fn file_extension_for_format(fmt: Option<&str>) -> String {
+        match fmt {
+            None | Some("") => String::new(),
+            Some("raw") => ".img".to_string(),
+            Some(fmt) => format!(".{}", fmt),
+        }
+    }
+
+    /// A name like `basename` + `suffix` that no volume of `pool` uses
+    pub fn find_free_name(
+        conn: &dyn Connection,
+        pool: &str,
+        basename: &str,
+        suffix: &str,
+    ) -> Result<String, String> {
+        let names = conn.list_volumes(pool).unwrap_or_default();
+        generate_name(
+            basename,
+            |n| names.iter().any(|x| x == n),
+            suffix,
+            1,
+            "-",
+            false,
+        )
+    }
+
+    pub fn validate_name(conn: &dyn Connection, pool: &str, name: &str) -> Result<(), String> {
+        validate_generic_name("Storage object", name)?;
+        if conn.volume_xml(pool, name).is_ok() {
+            return Err(format!("Name '{}' already in use by another volume.", name));
+        }
+        Ok(())
+    }
+
+    fn target_mut(&mut self) -> &mut VolumeTarget {
+        self.target.get_or_insert_with(Default::default)
+    }
+
+    pub fn target_path(&self) -> Option<&str> {
+        self.target.as_ref()?.path.as_deref()
+    }
+
+    pub fn format(&self) -> Option<&str> {
+        Some(self.target.as_ref()?.format.as_ref()?.ftype.as_str())
+    }
+
+    pub fn set_format(&mut self, fmt: Option<&str>) {
+        self.target_mut().format = fmt.map(|f| FormatType {
+            ftype: f.to_string(),
+        });
+    }
+
+    pub fn permissions_mut(&mut self) -> &mut Permissions {
+        self.target_mut()
+            .permissions
+            .get_or_insert_with(Default::default)
+    }
+
+    pub fn backing_store_path(&self) -> Option<&str> {
+        Some(self.backing_store.as_ref()?.path.as_str())
+    }
+
+    pub fn set_backing_store(&mut self, path: Option<&str>) {
+        self.backing_store = path.map(|p| VolumeBackingStore {
+            path: p.to_string(),
+            format: None,
+        });
+    }
+
+    pub fn capacity(&self) -> u64 {
+        size_bytes(&self.capacity)
+    }
+
+    pub fn allocation(&self) -> u64 {
+        size_bytes(&self.allocation)
+    }
+
+    pub fn set_capacity(&mut self, bytes: u64) {
+        self.capacity = Some(StorageSize::new(bytes));
+    }
+
+    pub fn set_allocation(&mut self, bytes: u64) {
+        self.allocation = Some(StorageSize::new(bytes));
+    }
+
+    /// "file", "block", "network", ... from the volume or its pool
+    pub fn file_type<'a>(&'a self, pool: &StoragePool) -> &'a str {
+        self.vtype.as_deref().unwrap_or(pool.disk_type())
+    }
+
+    /// Only file volumes have an image format we know
+    pub fn supports_format(&self, pool: &StoragePool) -> bool {
+        self.file_type(pool) == "file"
+    }
+
+    /// Copy the format and sizes of the volume a clone is made from
+    pub fn set_input_vol(&mut self, input: &StorageVolume) {
+        self.set_format(input.format());
+        self.capacity = input.capacity.clone();
+        self.allocation = input.allocation.clone();
+        let mode = input
+            .target
+            .as_ref()
+            .and_then(|t| t.permissions.as_ref())
+            .and_then(|p| p.mode.clone());
+        self.permissions_mut().mode = mode;
+    }
+
+    /// Whether the sizes exceed the `available` bytes of the pool: a
+    /// fatal flag for the allocation, and the message to show
+    pub fn is_size_conflict(&self, available: u64) -> (bool, Option<String>) {
+        let mib = |v: u64| v / (1024 * 1024);
+        if self.allocation() > available {
+            let msg = format!(
+                "There is not enough free space on the storage pool to create the volume. \
+                 ({} M requested allocation > {} M available)",
+                mib(self.allocation()),
+                mib(available)
+            );
+            return (true, Some(msg));
+        }
+        if self.capacity() > available {
+            let msg = format!(
+                "The requested volume capacity will exceed the available pool space when \
+                 the volume is fully allocated. ({} M requested capacity > {} M available)",
+                mib(self.capacity()),
+                mib(available)
+            );
+            return (false, Some(msg));
+        }
+        (false, None)
+    }
+
+    /// Check the name and sizes against the current state of `pool` on
+    /// `conn` and fill in the defaults. Returns a warning about the size
+    /// that isn't fatal.
+    pub fn validate(
+        &mut self,
+        conn: &dyn Connection,
+        pool: &str,
+    ) -> Result<Option<String>, String> {
+        Self::validate_name(conn, pool, &self.name)?;
+        let pool = &StoragePool::parse(&conn.pool_xml(pool, false)?)?;
+        if self.format().is_none() && self.file_type(pool) == "file" {
+            self.set_format(Some("raw"));
+        }
+        if self.format() == Some("qcow2") {
+            let features = self
+                .target_mut()
+                .features
+                .get_or_insert_with(Default::default);
+            if features.lazy_refcounts.is_none() {
+                features.lazy_refcounts = Some(VolumeLazyRefcounts {});
+            }
+        }
+        if pool.ptype == "logical" && self.allocation() != self.capacity() {
+            log::warn!(
+                "Sparse logical volumes are not supported, setting allocation equal to capacity"
+            );
+            self.allocation = self.capacity.clone();
+        }
+        match self.is_size_conflict(pool.available()) {
+            (true, Some(msg)) => Err(msg),
+            (_, msg) => {
+                if let Some(msg) = &msg {
+                    log::warn!("{}", msg);
+                }
+                Ok(msg)
+            }
+        }
+    }
+
+    /// Create the volume in `pool`, as a copy of `input` (pool, volume)
+    /// if given. A backing store without a format gets the format of
+    /// the volume it points at.
+    pub fn install(
+        &self,
+        conn: &dyn Connection,
+        pool: &str,
+        input: Option<(&str, &str)>,
+    ) -> Result<(), String> {
+        let mut vol = self.clone();
+        if let Some(backing) = vol.backing_store.as_mut()
+            && backing.format.is_none()
+        {
+            backing.format =
+                detect_backing_format(conn, &backing.path).map(|f| FormatType { ftype: f });
+        }
+        let xml = vol.get_xml()?;
+        debug!("Creating storage volume '{}' with xml:\n{}", self.name, xml);
+        let ret = match input {
+            Some((inpool, invol)) => conn.clone_volume(pool, &xml, inpool, invol),
+            None => conn.create_volume(pool, &xml),
+        };
+        ret.map_err(|e| format!("Couldn't create storage volume '{}': '{}'", self.name, e))
+    }
+}
+
+/// The pool and volume at `path`, looking through every pool of `conn`
+pub fn lookup_volume_by_path(
+    conn: &dyn Connection,
+    path: &str,
+) -> Option<(StoragePool, StorageVolume)> {
+    for info in conn.list_pools().ok()? {
+        let Ok(pool) = conn
+            .pool_xml(&info.name, false)
+            .and_then(|x| StoragePool::parse(&x))
+        else {
+            continue;
+        };
+        for name in conn.list_volumes(&info.name).unwrap_or_default() {
+            let vol = conn
+                .volume_xml(&info.name, &name)
+                .and_then(|x| StorageVolume::parse(&x));
+            if let Ok(vol) = vol
+                && vol.target_path() == Some(path)
+            {
+                return Some((pool, vol));
+            }
+        }
+    }
+    None
+}
+
+/// Format of the backing volume at `path`, for the formats we know
+fn detect_backing_format(conn: &dyn Connection, path: &str) -> Option<String> {
+    debug!("Attempting to detect format for backing_store={}", path);
+    let Some((pool, vol)) = lookup_volume_by_path(conn, path) else {
+        debug!("Didn't find any volume for backing_store");
+        return None;
+    };
+    if !vol.supports_format(&pool) {
+        debug!("backing_store volume doesn't have a format we can specify");
+        return None;
+    }
+    vol.format().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{TestConnection, testdriver};
+
+    fn data(name: &str) -> String {
+        std::fs::read_to_string(format!(
+            "{}/../tests/data/storage/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        ))
+        .unwrap()
+    }
+
+    /// Build a pool the way tests/test_storage.py does and compare it
+    /// to its data file, then install it
+    fn create_pool(
+        conn: &TestConnection,
+        ptype: &str,
+        name: &str,
+        fmt: Option<&str>,
+        target_path: Option<&str>,
+        source_name: Option<&str>,
+        iqn: Option<&str>,
+    ) -> StoragePool {
+        let mut pool = StoragePool::new(ptype, name);
+        if pool.supports_hosts() {
+            pool.add_host("some.random.hostname");
+        }
+        if pool.supports_source_path() {
+            pool.set_source_path(Some("/some/source/path"));
+        }
+        if pool.supports_target_path() {
+            let target = target_path
+                .map(str::to_string)
+                .or_else(|| pool.default_target_path(conn.uri()));
+            pool.set_target_path(target.as_deref());
+        }
+        if fmt.is_some() && pool.supports_format() {
+            pool.set_format(fmt);
+        }
+        if pool.supports_source_name() {
+            let srcname = source_name
+                .map(str::to_string)
+                .or_else(|| pool.default_source_name());
+            pool.set_source_name(srcname.as_deref());
+        }
+        if iqn.is_some() && pool.supports_iqn() {
+            pool.set_iqn(iqn);
+        }
+        pool.validate(conn).unwrap();
+        assert_eq!(pool.get_xml().unwrap(), data(&format!("{}.xml", name)));
+        pool.install(conn, true, true, false).unwrap();
+        pool
+    }
+
+    /// A new volume, a copy of it, and a clone of its XML, like
+    /// tests/test_storage.py's createVol
+    fn create_vols(conn: &TestConnection, pool: &StoragePool) {
+        let volname = format!("{}-vol", pool.name);
+        let mut vol = StorageVolume::new(&volname);
+        vol.set_capacity(10 * 1024 * 1024 * 1024);
+        vol.set_allocation(5 * 1024 * 1024 * 1024);
+        let perms = vol.permissions_mut();
+        perms.mode = Some("0700".into());
+        perms.owner = Some("10736".into());
+        perms.group = Some("10736".into());
+        let mut input = vol.clone();
+        vol.validate(conn, &pool.name).unwrap();
+        assert_eq!(vol.get_xml().unwrap(), data(&format!("{}.xml", volname)));
+        vol.install(conn, &pool.name, None).unwrap();
+
+        input.name = format!("{}input", volname);
+        let created = StorageVolume::parse(&conn.volume_xml(&pool.name, &volname).unwrap());
+        input.set_input_vol(&created.unwrap());
+        input.validate(conn, &pool.name).unwrap();
+        assert_eq!(
+            input.get_xml().unwrap(),
+            data(&format!("{}.xml", input.name))
+        );
+        input
+            .install(conn, &pool.name, Some((&pool.name, &volname)))
+            .unwrap();
+        assert!(input.validate(conn, &pool.name).is_err());
+
+        // Clones start out from the XML libvirt reports, whose layout
+        // depends on the libvirt version, so compare the parsed volumes
+        let clone = StorageVolume::parse(&data(&format!("{}clone.xml", volname))).unwrap();
+        assert_eq!(clone.name, format!("{}clone", volname));
+        assert_eq!(
+            (clone.capacity(), clone.allocation()),
+            (vol.capacity(), vol.allocation())
+        );
+        assert_eq!(
+            StorageVolume::parse(&clone.get_xml().unwrap()).unwrap(),
+            clone
+        );
+    }
+
+    #[test]
+    fn test_storage_pools() {
+        let conn = TestConnection::open("test:///default").unwrap();
+        for ptype in ["dir", "fs", "netfs", "logical", "disk"] {
+            let name = if ptype == "dir" {
+                "pool-dir2".to_string()
+            } else {
+                format!("pool-{}", ptype)
+            };
+            let srcname = (ptype == "logical").then_some("pool-logical");
+            let fmt = (ptype == "disk").then_some("auto");
+            let target = (ptype == "disk").then_some("/some/target/path");
+            let pool = create_pool(&conn, ptype, &name, fmt, target, srcname, None);
+            create_vols(&conn, &pool);
+        }
+        create_pool(
+            &conn,
+            "logical",
+            "pool-logical-target-srcname",
+            None,
+            Some("/dev/vgfoobar"),
+            None,
+            None,
+        );
+        create_pool(
+            &conn,
+            "logical",
+            "pool-logical-srcname",
+            None,
+            None,
+            Some("vgname"),
+            None,
+        );
+        create_pool(
+            &conn,
+            "iscsi",
+            "pool-iscsi",
+            None,
+            None,
+            None,
+            Some("foo.bar.baz.iqn"),
+        );
+        for ptype in ["scsi", "mpath", "gluster", "rbd"] {
+            let name = format!("pool-{}", ptype);
+            create_pool(&conn, ptype, &name, None, None, None, None);
+        }
+
+        let mut dup = StoragePool::new("dir", "pool-rbd");
+        assert_eq!(
+            dup.validate(&conn).unwrap_err(),
+            "Name 'pool-rbd' already in use by another pool."
+        );
+        assert_eq!(
+            StoragePool::find_free_name(&conn, "pool-rbd").as_deref(),
+            Ok("pool-rbd-1")
+        );
+    }
+
+    #[test]
+    fn test_storage_testdriver_pools() {
+        let conn = testdriver();
+        let pool = StoragePool::parse(&conn.pool_xml("pool-dir", false).unwrap()).unwrap();
+        assert_eq!(pool.capacity(), 32 << 40);
+        assert_eq!(pretty_bytes(pool.available()), "32768.00 GiB");
+        assert_eq!(pool.target_path(), Some("/pool-dir"));
+        assert!(pool.supports_volume_creation(false));
+
+        let vol =
+            StorageVolume::parse(&conn.volume_xml("pool-dir", "backingl2.img").unwrap()).unwrap();
+        assert_eq!(vol.target_path(), Some("/pool-dir/backingl2.img"));
+        assert_eq!(vol.backing_store_path(), Some("/pool-dir/backingl3.img"));
+
+        let mut overlay = StorageVolume::new("overlay2.qcow2");
+        overlay.set_format(Some("qcow2"));
+        overlay.set_capacity(1000000);
+        overlay.set_backing_store(Some("/pool-dir/backingl2.img"));
+        overlay.validate(&conn, "pool-dir").unwrap();
+        overlay.install(&conn, "pool-dir", None).unwrap();
+        let xml = conn.volume_xml("pool-dir", "overlay2.qcow2").unwrap();
+        let created = StorageVolume::parse(&xml).unwrap();
+        let backing = created.backing_store.unwrap();
+        assert_eq!(backing.format.unwrap().ftype, "qcow2");
+        assert!(xml.contains("<lazy_refcounts/>"));
+
+        let mut big = StorageVolume::new("big.img");
+        big.set_capacity(u64::MAX / 2);
+        let warning = big.validate(&conn, "pool-dir").unwrap();
+        assert!(warning.unwrap().contains("requested capacity"));
+        big.set_allocation(u64::MAX / 2);
+        assert!(big.validate(&conn, "pool-dir").is_err());
+
+        assert_eq!(
+            StorageVolume::file_extension_for_format(Some("raw")),
+            ".img"
+        );
+        assert_eq!(
+            StorageVolume::find_free_name(&conn, "pool-dir", "testvol1", ".img").as_deref(),
+            Ok("testvol1-1.img")
+        );
+        assert_eq!(pretty_pool_type("iscsi"), "iSCSI Target");
+        assert_eq!(pretty_pool_type("vstorage"), "vstorage pool");
+    }
+}
@@ -0,0 +1,386 @@
+// Storage browser dialog (Iced port of virtManager/storagebrowse.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Picks a storage volume, or a local path on local connections, for a
+//! disk, media, filesystem or kernel field. It embeds the storage page in
+//! browse mode, so volumes can be created in place, and is drawn over the
+//! owner with `modal`. Like the wizards, the owner intercepts `Cancel` and
+//! `Finish` and gets the path from `finish`.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use iced::widget::{
+    Column, Space, button, center, column, container, opaque, row, scrollable, stack, text,
+    text_input,
+};
+use iced::{Alignment, Color, Element, Length, Task};
+use log::debug;
+
+use crate::connection::Connection;
+use crate::hoststorage::{BrowseOptions, HostStoragePage, Message as HostStorageMsg};
+use crate::storage::{StoragePool, default_pool_path};
+
+/// What the chosen path is for, deciding the titles, whether volumes can
+/// be created and which can be chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowseReason {
+    Image,
+    IsoMedia,
+    FloppyMedia,
+    Fs,
+}
+
+impl BrowseReason {
+    pub fn storage_title(self) -> &'static str {
+        match self {
+            Self::Image => "Locate or create storage volume",
+            Self::IsoMedia => "Locate ISO media volume",
+            Self::FloppyMedia => "Locate floppy media volume",
+            Self::Fs => "Locate directory volume",
+        }
+    }
+
+    fn local_title(self) -> &'static str {
+        match self {
+            Self::Image => "Locate existing storage",
+            Self::IsoMedia => "Locate ISO media",
+            Self::FloppyMedia => "Locate floppy media",
+            Self::Fs => "Locate directory volume",
+        }
+    }
+
+    fn enable_create(self) -> bool {
+        self == Self::Image
+    }
+
+    /// Directories rather than files are chosen locally
+    fn dirs_only(self) -> bool {
+        self == Self::Fs
+    }
+
+    /// Where browsing local files starts
+    fn start_folder(self, uri: &str) -> PathBuf {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let dir = match self {
+            Self::Image => Some(default_pool_path(uri)),
+            _ => home.clone(),
+        };
+        dir.filter(|d| d.is_dir())
+            .or(home)
+            .unwrap_or_else(|| PathBuf::from("/"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Storage(HostStorageMsg),
+    LocalUp,
+    /// An entry of the local directory was clicked
+    LocalOpen(String),
+    LocalPathChanged(String),
+    LocalCancel,
+    /// Handled by the owner, which drops the browser
+    Cancel,
+    /// Handled by the owner through `finish`
+    Finish,
+}
+
+/// Local file chooser, listing one directory at a time
+struct LocalChooser {
+    dir: PathBuf,
+    /// Names and whether they are directories, directories first
+    entries: Vec<(String, bool)>,
+    path: String,
+    error: Option<String>,
+}
+
+impl LocalChooser {
+    fn new(dir: PathBuf, dirs_only: bool) -> Self {
+        let mut chooser = Self {
+            dir: PathBuf::new(),
+            entries: vec![],
+            path: String::new(),
+            error: None,
+        };
+        chooser.chdir(dir, dirs_only);
+        chooser
+    }
+
+    fn chdir(&mut self, dir: PathBuf, dirs_only: bool) {
+        let read = std::fs::read_dir(&dir).map_err(|e| e.to_string());
+        let entries = match read {
+            Ok(read) => read,
+            Err(e) => {
+                self.error = Some(format!("Error reading '{}': {}", dir.display(), e));
+                return;
+            }
+        };
+        let mut entries: Vec<(String, bool)> = entries
+            .flatten()
+            .map(|e| {
+                (
+                    e.file_name().to_string_lossy().into_owned(),
+                    e.path().is_dir(),
+                )
+            })
+            .filter(|(name, isdir)| !name.starts_with('.') && (*isdir || !dirs_only))
+            .collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        self.entries = entries;
+        self.path = if dirs_only {
+            dir.display().to_string()
+        } else {
+            String::new()
+        };
+        self.dir = dir;
+        self.error = None;
+    }
+}
+
+pub struct StorageBrowser {
+    conn: Arc<dyn Connection>,
+    reason: BrowseReason,
+    storage: HostStoragePage,
+    /// The local file chooser, while open
+    local: Option<LocalChooser>,
+}
+
+impl StorageBrowser {
+    /// A browser for `reason`. `name_hint`, like the VM's name, names new
+    /// volumes.
+    pub fn new(conn: Arc<dyn Connection>, reason: BrowseReason, name_hint: Option<&str>) -> Self {
+        debug!("Showing storage browser");
+        let mut storage = HostStoragePage::new(conn.clone());
+        storage.set_browse(BrowseOptions {
+            name_hint: name_hint.map(str::to_string),
+            enable_create: reason.enable_create(),
+            format: (reason == BrowseReason::Fs).then_some("dir"),
+        });
+        if let Some(pool) = StoragePool::lookup_default_pool(conn.as_ref()) {
+            let _ = storage.update(HostStorageMsg::Select(pool));
+        }
+        Self {
+            conn,
+            reason,
+            storage,
+            local: None,
+        }
+    }
+
+    fn open_local(&mut self, dir: PathBuf) {
+        self.local = Some(LocalChooser::new(dir, self.reason.dirs_only()));
+    }
+
+    /// The chosen path: the local one if browsing locally, else the
+    /// selected volume's
+    pub fn finish(&mut self) -> Option<String> {
+        let path = match &self.local {
+            Some(local) => {
+                let path = local.path.trim();
+                (!path.is_empty()).then(|| path.to_string())
+            }
+            None => self.storage.chosen_volume().and_then(|v| v.path.clone()),
+        };
+        match &path {
+            Some(path) if self.local.is_some() => debug!("Browse local chose path={}", path),
+            Some(path) => debug!("Chosen volume path={}", path),
+            None => debug!("Nothing chosen in storage browser"),
+        }
+        path
+    }
+
+    pub fn update(&mut self, msg: Message) -> Task<Message> {
+        match msg {
+            Message::Storage(HostStorageMsg::BrowseLocal) => {
+                let dir = self.reason.start_folder(self.conn.uri());
+                self.open_local(dir);
+            }
+            Message::Storage(inner) => return self.storage.update(inner).map(Message::Storage),
+            Message::LocalUp => {
+                let dirs_only = self.reason.dirs_only();
+                if let Some(local) = &mut self.local
+                    && let Some(parent) = local.dir.parent()
+                {
+                    local.chdir(parent.to_path_buf(), dirs_only);
+                }
+            }
+            Message::LocalOpen(name) => {
+                let dirs_only = self.reason.dirs_only();
+                if let Some(local) = &mut self.local {
+                    let path = local.dir.join(&name);
+                    if path.is_dir() {
+                        local.chdir(path, dirs_only);
+                    } else {
+                        local.path = path.display().to_string();
+                    }
+                }
+            }
+            Message::LocalPathChanged(v) => {
+                if let Some(local) = &mut self.local {
+                    local.path = v;
+                }
+            }
+            Message::LocalCancel => self.local = None,
+            Message::Cancel | Message::Finish => {}
+        }
+        Task::none()
+    }
+
+    fn view_local<'a>(&'a self, local: &'a LocalChooser) -> Element<'a, Message> {
+        let mut list: Column<Message> = column![].spacing(2);
+        for (name, isdir) in &local.entries {
+            let label = if *isdir {
+                format!("{}/", name)
+            } else {
+                name.clone()
+            };
+            list = list.push(
+                button(text(label).size(13).width(Length::Fill))
+                    .style(button::text)
+                    .padding(2)
+                    .on_press(Message::LocalOpen(name.clone())),
+            );
+        }
+        let mut col: Column<Message> = column![
+            text(self.reason.local_title()).size(18),
+            row![
+                button(text("Up")).on_press_maybe(local.dir.parent().map(|_| Message::LocalUp)),
+                text(local.dir.display().to_string()),
+            ]
+            .spacing(8)
+            .align_y(Alignment::Center),
+            container(scrollable(list).height(Length::Fill))
+                .style(container::bordered_box)
+                .padding(4),
+            text_input("", &local.path)
+                .on_input(Message::LocalPathChanged)
+                .padding(6),
+        ]
+        .spacing(10);
+        if let Some(err) = &local.error {
+            col = col.push(text(err.clone()).size(14));
+        }
+        let choose = if self.reason.dirs_only() {
+            "Choose"
+        } else {
+            "Open"
+        };
+        col.push(
+            row![
+                Space::with_width(Length::Fill),
+                button(text("Cancel")).on_press(Message::LocalCancel),
+                button(text(choose))
+                    .on_press_maybe((!local.path.trim().is_empty()).then_some(Message::Finish)),
+            ]
+            .spacing(8),
+        )
+        .into()
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        if let Some(local) = &self.local {
+            return self.view_local(local);
+        }
+        let storage = self.storage.view().map(|msg| match msg {
+            HostStorageMsg::BrowseCancel => Message::Cancel,
+            HostStorageMsg::ChooseVolume => Message::Finish,
+            msg => Message::Storage(msg),
+        });
+        column![text(self.reason.storage_title()).size(18), storage]
+            .spacing(10)
+            .into()
+    }
+}
+
+/// Draw `dialog` over `base`, which can't be used until it's gone
+pub fn modal<'a, M: Clone + 'a>(
+    base: impl Into<Element<'a, M>>,
+    dialog: impl Into<Element<'a, M>>,
+) -> Element<'a, M> {
+    let dialog = container(dialog)
+        .style(container::bordered_box)
+        .padding(16)
+        .max_width(900)
+        .max_height(560);
+    let backdrop = center(opaque(dialog)).style(|_| container::Style {
+        background: Some(
+            Color {
+                a: 0.6,
+                ..Color::BLACK
+            }
+            .into(),
+        ),
+        ..container::Style::default()
+    });
+    stack![base.into(), opaque(backdrop)].into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+
+    fn open(reason: BrowseReason) -> StorageBrowser {
+        let conn: Arc<dyn Connection> = Arc::new(testdriver());
+        StorageBrowser::new(conn, reason, Some("myvm"))
+    }
+
+    fn select(browser: &mut StorageBrowser, pool: &str, vol: &str) {
+        let pool = HostStorageMsg::Select(pool.into());
+        let _ = browser.update(Message::Storage(pool));
+        let vol = HostStorageMsg::SelectVolume(vol.into());
+        let _ = browser.update(Message::Storage(vol));
+    }
+
+    #[test]
+    fn test_storagebrowse_volumes() {
+        let mut browser = open(BrowseReason::Image);
+        assert_eq!(browser.finish(), None);
+        select(&mut browser, "pool-dir", "testvol1.img");
+        assert_eq!(browser.finish().as_deref(), Some("/pool-dir/testvol1.img"));
+
+        // New volumes are named after the hint
+        let _ = browser.update(Message::Storage(HostStorageMsg::AddVolume));
+        let create = HostStorageMsg::CreateVol(crate::createvol::Message::Finish);
+        let _ = browser.update(Message::Storage(create));
+        assert_eq!(browser.finish().as_deref(), Some("/pool-dir/myvm.qcow2"));
+
+        // Filesystems only take directory volumes
+        let mut browser = open(BrowseReason::Fs);
+        select(&mut browser, "pool-dir", "testvol1.img");
+        assert_eq!(browser.finish(), None);
+        select(&mut browser, "pool-dir", "dir-vol");
+        assert_eq!(browser.finish().as_deref(), Some("/pool-dir/dir-vol"));
+    }
+
+    #[test]
+    fn test_storagebrowse_local() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join("sub")).unwrap();
+        std::fs::write(tmp.path().join("sub/disk.img"), b"").unwrap();
+        std::fs::write(tmp.path().join(".hidden"), b"").unwrap();
+
+        let mut browser = open(BrowseReason::Image);
+        browser.open_local(tmp.path().to_path_buf());
+        let local = browser.local.as_ref().unwrap();
+        assert_eq!(local.entries, vec![("sub".to_string(), true)]);
+        let _ = browser.update(Message::LocalOpen("sub".into()));
+        let _ = browser.update(Message::LocalOpen("disk.img".into()));
+        let path = tmp.path().join("sub/disk.img").display().to_string();
+        assert_eq!(browser.finish(), Some(path));
+
+        // Directories are chosen by entering them
+        let mut browser = open(BrowseReason::Fs);
+        browser.open_local(tmp.path().to_path_buf());
+        let _ = browser.update(Message::LocalOpen("sub".into()));
+        assert!(browser.local.as_ref().unwrap().entries.is_empty());
+        let path = tmp.path().join("sub").display().to_string();
+        assert_eq!(browser.finish(), Some(path));
+        let _ = browser.update(Message::LocalUp);
+        let _ = browser.update(Message::LocalCancel);
+        assert_eq!(browser.finish(), None);
+    }
+}
@@ -0,0 +1,1167 @@
+// VT100/ANSI terminal emulation (replaces the VTE terminal widget of
+// virtManager/details/serialcon.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `Terminal` keeps the screen of a text console: a grid of cells with
+//! colors and attributes, the cursor, and up to `SCROLLBACK_LINES` lines
+//! that scrolled off the top. `feed` runs the bytes the guest writes
+//! through a VT100/xterm escape sequence parser. The `terminal` widget
+//! draws it and turns key presses into the bytes a terminal would send,
+//! with mouse selection, wheel scrollback and Ctrl+Shift+C/V for the
+//! clipboard.
+
+use std::collections::VecDeque;
+
+use iced::advanced::clipboard::Kind;
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::text::{self, Text};
+use iced::advanced::widget::{self, Widget, tree};
+use iced::advanced::{Clipboard, Shell, renderer};
+use iced::alignment::{Horizontal, Vertical};
+use iced::keyboard::key::Named;
+use iced::keyboard::{self, Key, Modifiers};
+use iced::{
+    Background, Border, Color, Element, Event, Font, Length, Pixels, Point, Rectangle, Size, event,
+    font, mouse, window,
+};
+
+/// Lines kept after they scroll off the top, like serialcon's VTE
+pub const SCROLLBACK_LINES: usize = 1000;
+
+const FONT_SIZE: f32 = 14.0;
+const CELL_WIDTH: f32 = FONT_SIZE * 0.6;
+const CELL_HEIGHT: f32 = FONT_SIZE * 1.3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TermColor {
+    #[default]
+    Default,
+    /// Entry of the xterm 256 color palette
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl TermColor {
+    fn to_color(self, default: Color) -> Color {
+        match self {
+            Self::Default => default,
+            Self::Indexed(idx) => palette(idx),
+            Self::Rgb(r, g, b) => Color::from_rgb8(r, g, b),
+        }
+    }
+}
+
+/// The xterm palette: 16 base colors, a 6x6x6 cube and a gray ramp
+fn palette(idx: u8) -> Color {
+    const BASE: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    let (r, g, b) = match idx {
+        0..=15 => BASE[idx as usize],
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let i = idx - 16;
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let v = 8 + (idx - 232) * 10;
+            (v, v, v)
+        }
+    };
+    Color::from_rgb8(r, g, b)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Attrs {
+    pub fg: TermColor,
+    pub bg: TermColor,
+    pub bold: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub attrs: Attrs,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            attrs: Attrs::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum ParseState {
+    #[default]
+    Ground,
+    Escape,
+    Csi,
+    /// Operating system command, like a window title, which we skip
+    Osc,
+    OscEscape,
+    /// The charset designator after ESC ( and friends
+    Charset,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Cursor {
+    row: usize,
+    col: usize,
+    attrs: Attrs,
+}
+
+#[derive(Debug, Clone)]
+pub struct Terminal {
+    cols: usize,
+    rows: usize,
+    screen: Vec<Vec<Cell>>,
+    scrollback: VecDeque<Vec<Cell>>,
+    cursor: Cursor,
+    saved: Cursor,
+    /// The last column was written, the next char goes on a new line
+    wrap_pending: bool,
+    autowrap: bool,
+    cursor_visible: bool,
+    /// Arrow keys send SS3 sequences, as asked by DECCKM
+    pub app_cursor: bool,
+    /// First and last row of the scrolling region
+    top: usize,
+    bottom: usize,
+    /// The main screen and cursor while the alternate screen is up
+    alternate: Option<(Vec<Vec<Cell>>, Cursor)>,
+    state: ParseState,
+    params: String,
+    utf8: Vec<u8>,
+    /// Replies to the guest, like cursor position reports
+    output: Vec<u8>,
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Self::new(80, 24)
+    }
+}
+
+impl Terminal {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let (cols, rows) = (cols.max(1), rows.max(1));
+        Self {
+            cols,
+            rows,
+            screen: vec![vec![Cell::default(); cols]; rows],
+            scrollback: VecDeque::new(),
+            cursor: Cursor::default(),
+            saved: Cursor::default(),
+            wrap_pending: false,
+            autowrap: true,
+            cursor_visible: true,
+            app_cursor: false,
+            top: 0,
+            bottom: rows - 1,
+            alternate: None,
+            state: ParseState::Ground,
+            params: String::new(),
+            utf8: vec![],
+            output: vec![],
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    /// Screen lines and scrollback together
+    pub fn line_count(&self) -> usize {
+        self.scrollback.len() + self.rows
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.scrollback.len()
+    }
+
+    /// Line `idx` counting from the oldest scrollback line
+    pub fn line(&self, idx: usize) -> &[Cell] {
+        match idx.checked_sub(self.scrollback.len()) {
+            Some(row) => &self.screen[row],
+            None => &self.scrollback[idx],
+        }
+    }
+
+    /// Cursor row and column on screen, unless hidden
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        self.cursor_visible
+            .then_some((self.cursor.row, self.cursor.col))
+    }
+
+    /// Text between two (line, column) positions, inclusive, with
+    /// trailing blanks of each line dropped
+    pub fn text(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let (start, end) = if start <= end {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        let last = self.line_count().saturating_sub(1);
+        let mut lines = vec![];
+        for idx in start.0..=end.0.min(last) {
+            let line = self.line(idx);
+            let from = if idx == start.0 { start.1 } else { 0 };
+            let to = if idx == end.0 {
+                (end.1 + 1).min(line.len())
+            } else {
+                line.len()
+            };
+            let s: String = line
+                .get(from..to)
+                .unwrap_or_default()
+                .iter()
+                .map(|c| c.ch)
+                .collect();
+            lines.push(s.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    /// The visible screen as text, one line per row
+    pub fn screen_text(&self) -> String {
+        let first = self.scrollback.len();
+        self.text((first, 0), (first + self.rows - 1, self.cols - 1))
+    }
+
+    /// Bytes the terminal wants to send back to the guest
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    pub fn reset(&mut self) {
+        let scrollback = std::mem::take(&mut self.scrollback);
+        *self = Self::new(self.cols, self.rows);
+        self.scrollback = scrollback;
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let (cols, rows) = (cols.max(1), rows.max(1));
+        if (cols, rows) == (self.cols, self.rows) {
+            return;
+        }
+        for line in self.screen.iter_mut() {
+            line.resize(cols, Cell::default());
+        }
+        // Keep the cursor line on screen, pushing lines above it out
+        while self.screen.len() > rows {
+            if self.cursor.row > 0 {
+                let line = self.screen.remove(0);
+                self.push_scrollback(line);
+                self.cursor.row -= 1;
+            } else {
+                self.screen.pop();
+            }
+        }
+        self.screen.resize(rows, vec![Cell::default(); cols]);
+        if let Some((screen, _)) = &mut self.alternate {
+            for line in screen.iter_mut() {
+                line.resize(cols, Cell::default());
+            }
+            screen.resize(rows, vec![Cell::default(); cols]);
+        }
+        self.cols = cols;
+        self.rows = rows;
+        self.top = 0;
+        self.bottom = rows - 1;
+        self.cursor.row = self.cursor.row.min(rows - 1);
+        self.cursor.col = self.cursor.col.min(cols - 1);
+        self.wrap_pending = false;
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        for &b in data {
+            if !self.utf8.is_empty() || (b >= 0x80 && self.state == ParseState::Ground) {
+                self.feed_utf8(b);
+            } else {
+                self.feed_byte(b);
+            }
+        }
+    }
+
+    fn feed_utf8(&mut self, b: u8) {
+        if !self.utf8.is_empty() && b & 0xc0 != 0x80 {
+            // Broken sequence, drop it and start over with this byte
+            self.utf8.clear();
+            self.put_char(char::REPLACEMENT_CHARACTER);
+            return self.feed(&[b]);
+        }
+        self.utf8.push(b);
+        let len = match self.utf8[0] {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        if self.utf8.len() < len {
+            return;
+        }
+        let bytes = std::mem::take(&mut self.utf8);
+        let ch = std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        self.put_char(ch);
+    }
+
+    fn feed_byte(&mut self, b: u8) {
+        match self.state {
+            ParseState::Ground => match b {
+                0x1b => self.state = ParseState::Escape,
+                0x20..=0x7e => self.put_char(b as char),
+                _ => self.control(b),
+            },
+            ParseState::Escape => {
+                self.state = ParseState::Ground;
+                self.escape(b);
+            }
+            ParseState::Csi => match b {
+                0x1b => self.state = ParseState::Escape,
+                0x18 | 0x1a => self.state = ParseState::Ground,
+                0x30..=0x3f => self.params.push(b as char),
+                0x20..=0x2f => {}
+                0x40..=0x7e => {
+                    self.state = ParseState::Ground;
+                    let params = std::mem::take(&mut self.params);
+                    self.csi(&params, b as char);
+                }
+                _ => self.control(b),
+            },
+            ParseState::Osc => match b {
+                0x07 | 0x18 | 0x1a => self.state = ParseState::Ground,
+                0x1b => self.state = ParseState::OscEscape,
+                _ => {}
+            },
+            ParseState::OscEscape | ParseState::Charset => self.state = ParseState::Ground,
+        }
+    }
+
+    fn control(&mut self, b: u8) {
+        match b {
+            0x08 => {
+                self.cursor.col = self.cursor.col.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            0x09 => {
+                self.cursor.col = ((self.cursor.col / 8 + 1) * 8).min(self.cols - 1);
+            }
+            0x0a..=0x0c => self.linefeed(),
+            0x0d => {
+                self.cursor.col = 0;
+                self.wrap_pending = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn escape(&mut self, b: u8) {
+        match b {
+            b'[' => {
+                self.params.clear();
+                self.state = ParseState::Csi;
+            }
+            b']' => self.state = ParseState::Osc,
+            b'(' | b')' | b'*' | b'+' => self.state = ParseState::Charset,
+            b'7' => self.saved = self.cursor,
+            b'8' => self.restore_cursor(),
+            b'D' => self.linefeed(),
+            b'E' => {
+                self.cursor.col = 0;
+                self.linefeed();
+            }
+            b'M' => self.reverse_index(),
+            b'c' => self.reset(),
+            _ => {}
+        }
+    }
+
+    fn blank(&self) -> Cell {
+        Cell {
+            ch: ' ',
+            attrs: Attrs {
+                bg: self.cursor.attrs.bg,
+                ..Attrs::default()
+            },
+        }
+    }
+
+    fn put_char(&mut self, ch: char) {
+        if self.wrap_pending {
+            self.cursor.col = 0;
+            self.linefeed();
+        }
+        let attrs = self.cursor.attrs;
+        self.screen[self.cursor.row][self.cursor.col] = Cell { ch, attrs };
+        if self.cursor.col + 1 < self.cols {
+            self.cursor.col += 1;
+        } else {
+            self.wrap_pending = self.autowrap;
+        }
+    }
+
+    fn push_scrollback(&mut self, line: Vec<Cell>) {
+        if self.alternate.is_some() {
+            return;
+        }
+        self.scrollback.push_back(line);
+        if self.scrollback.len() > SCROLLBACK_LINES {
+            self.scrollback.pop_front();
+        }
+    }
+
+    fn linefeed(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor.row == self.bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor.row == self.top {
+            self.scroll_down(1);
+        } else {
+            self.cursor.row = self.cursor.row.saturating_sub(1);
+        }
+    }
+
+    /// Move the scrolling region up, saving lines off the top of the
+    /// screen in the scrollback
+    fn scroll_up(&mut self, n: usize) {
+        for _ in 0..n.min(self.bottom - self.top + 1) {
+            let line = self.screen.remove(self.top);
+            self.screen
+                .insert(self.bottom, vec![self.blank(); self.cols]);
+            if self.top == 0 {
+                self.push_scrollback(line);
+            }
+        }
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        for _ in 0..n.min(self.bottom - self.top + 1) {
+            self.screen.remove(self.bottom);
+            self.screen.insert(self.top, vec![self.blank(); self.cols]);
+        }
+    }
+
+    fn restore_cursor(&mut self) {
+        self.cursor = self.saved;
+        self.cursor.row = self.cursor.row.min(self.rows - 1);
+        self.cursor.col = self.cursor.col.min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn erase(&mut self, row: usize, from: usize, to: usize) {
+        let blank = self.blank();
+        let to = to.min(self.cols);
+        if from < to {
+            self.screen[row][from..to].fill(blank);
+        }
+    }
+
+    fn set_mode(&mut self, mode: u32, on: bool) {
+        match mode {
+            1 => self.app_cursor = on,
+            7 => self.autowrap = on,
+            25 => self.cursor_visible = on,
+            47 | 1047 | 1049 => {
+                if on && self.alternate.is_none() {
+                    let blank = vec![vec![Cell::default(); self.cols]; self.rows];
+                    let main = std::mem::replace(&mut self.screen, blank);
+                    self.alternate = Some((main, self.cursor));
+                } else if !on && let Some((main, cursor)) = self.alternate.take() {
+                    self.screen = main;
+                    if mode == 1049 {
+                        self.cursor = cursor;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn sgr(&mut self, params: &[u32]) {
+        let attrs = &mut self.cursor.attrs;
+        if params.is_empty() {
+            *attrs = Attrs::default();
+        }
+        let mut iter = params.iter().copied();
+        while let Some(p) = iter.next() {
+            match p {
+                0 => *attrs = Attrs::default(),
+                1 => attrs.bold = true,
+                4 => attrs.underline = true,
+                7 => attrs.reverse = true,
+                22 => attrs.bold = false,
+                24 => attrs.underline = false,
+                27 => attrs.reverse = false,
+                30..=37 => attrs.fg = TermColor::Indexed(p as u8 - 30),
+                39 => attrs.fg = TermColor::Default,
+                40..=47 => attrs.bg = TermColor::Indexed(p as u8 - 40),
+                49 => attrs.bg = TermColor::Default,
+                90..=97 => attrs.fg = TermColor::Indexed(p as u8 - 90 + 8),
+                100..=107 => attrs.bg = TermColor::Indexed(p as u8 - 100 + 8),
+                38 | 48 => {
+                    let color = match iter.next() {
+                        Some(5) => iter.next().map(|i| TermColor::Indexed(i as u8)),
+                        Some(2) => {
+                            let mut c = || iter.next().unwrap_or(0) as u8;
+                            Some(TermColor::Rgb(c(), c(), c()))
+                        }
+                        _ => None,
+                    };
+                    if let Some(color) = color {
+                        if p == 38 {
+                            attrs.fg = color;
+                        } else {
+                            attrs.bg = color;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn csi(&mut self, params: &str, action: char) {
+        let private = params.starts_with(['?', '>', '<', '=']);
+        let nums: Vec<u32> = params
+            .trim_start_matches(['?', '>', '<', '='])
+            .split(';')
+            .filter(|_| !params.is_empty())
+            .map(|p| p.parse().unwrap_or(0))
+            .collect();
+        // Count or position parameter, where 0 and missing mean 1
+        let arg = |i: usize| nums.get(i).copied().filter(|&n| n > 0).unwrap_or(1) as usize;
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        self.wrap_pending = false;
+        match action {
+            'A' => self.cursor.row = row.saturating_sub(arg(0)),
+            'B' => self.cursor.row = (row + arg(0)).min(self.rows - 1),
+            'C' => self.cursor.col = (col + arg(0)).min(self.cols - 1),
+            'D' => self.cursor.col = col.saturating_sub(arg(0)),
+            'E' => {
+                self.cursor.row = (row + arg(0)).min(self.rows - 1);
+                self.cursor.col = 0;
+            }
+            'F' => {
+                self.cursor.row = row.saturating_sub(arg(0));
+                self.cursor.col = 0;
+            }
+            'G' | '`' => self.cursor.col = (arg(0) - 1).min(self.cols - 1),
+            'd' => self.cursor.row = (arg(0) - 1).min(self.rows - 1),
+            'H' | 'f' => {
+                self.cursor.row = (arg(0) - 1).min(self.rows - 1);
+                self.cursor.col = (arg(1) - 1).min(self.cols - 1);
+            }
+            'J' => {
+                let mode = nums.first().copied().unwrap_or(0);
+                let (from, to) = match mode {
+                    0 => {
+                        self.erase(row, col, self.cols);
+                        (row + 1, self.rows)
+                    }
+                    1 => {
+                        self.erase(row, 0, col + 1);
+                        (0, row)
+                    }
+                    _ => (0, self.rows),
+                };
+                for r in from..to {
+                    self.erase(r, 0, self.cols);
+                }
+                if mode == 3 {
+                    self.scrollback.clear();
+                }
+            }
+            'K' => match nums.first().copied().unwrap_or(0) {
+                0 => self.erase(row, col, self.cols),
+                1 => self.erase(row, 0, col + 1),
+                _ => self.erase(row, 0, self.cols),
+            },
+            'L' | 'M' if (self.top..=self.bottom).contains(&row) => {
+                let (top, bottom) = (self.top, self.bottom);
+                self.top = row;
+                if action == 'L' {
+                    self.scroll_down(arg(0));
+                } else {
+                    // Lines deleted inside the screen don't go to scrollback
+                    for _ in 0..arg(0).min(bottom - row + 1) {
+                        self.screen.remove(row);
+                        self.screen.insert(bottom, vec![self.blank(); self.cols]);
+                    }
+                }
+                self.top = top;
+                self.cursor.col = 0;
+            }
+            '@' => {
+                let n = arg(0).min(self.cols - col);
+                let blank = self.blank();
+                let line = &mut self.screen[row];
+                line.truncate(self.cols - n);
+                line.splice(col..col, std::iter::repeat_n(blank, n));
+            }
+            'P' => {
+                let n = arg(0).min(self.cols - col);
+                let blank = self.blank();
+                let line = &mut self.screen[row];
+                line.drain(col..col + n);
+                line.resize(self.cols, blank);
+            }
+            'X' => self.erase(row, col, col + arg(0)),
+            'S' => self.scroll_up(arg(0)),
+            'T' => self.scroll_down(arg(0)),
+            'm' if !private => self.sgr(&nums),
+            'r' if !private => {
+                let top = arg(0) - 1;
+                let bottom = nums
+                    .get(1)
+                    .copied()
+                    .filter(|&n| n > 0)
+                    .map(|n| n as usize - 1)
+                    .unwrap_or(self.rows - 1)
+                    .min(self.rows - 1);
+                if top < bottom {
+                    self.top = top;
+                    self.bottom = bottom;
+                    self.cursor.row = 0;
+                    self.cursor.col = 0;
+                }
+            }
+            's' => self.saved = self.cursor,
+            'u' => self.restore_cursor(),
+            'n' => match nums.first() {
+                Some(5) => self.output.extend_from_slice(b"\x1b[0n"),
+                Some(6) => self
+                    .output
+                    .extend(format!("\x1b[{};{}R", row + 1, col + 1).bytes()),
+                _ => {}
+            },
+            'c' if !private => self.output.extend_from_slice(b"\x1b[?1;2c"),
+            'h' | 'l' if private => {
+                for &mode in &nums {
+                    self.set_mode(mode, action == 'h');
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// What the terminal widget sends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TerminalInput {
+    /// Bytes for the guest, from typing or pasting
+    Data(Vec<u8>),
+    /// The widget has room for this many columns and rows
+    Resize(usize, usize),
+    /// Text selected with the mouse
+    Selected(String),
+}
+
+/// Bytes a terminal sends for a key press
+pub fn key_bytes(key: &Key, modifiers: Modifiers, text: Option<&str>, app_cursor: bool) -> Vec<u8> {
+    let cursor_key = |c: char| {
+        let intro = if app_cursor { "\x1bO" } else { "\x1b[" };
+        format!("{}{}", intro, c).into_bytes()
+    };
+    let seq = |s: &str| s.as_bytes().to_vec();
+    match key {
+        Key::Named(named) => match named {
+            Named::Enter => seq("\r"),
+            Named::Backspace => seq("\x7f"),
+            Named::Tab if modifiers.shift() => seq("\x1b[Z"),
+            Named::Tab => seq("\t"),
+            Named::Escape => seq("\x1b"),
+            Named::ArrowUp => cursor_key('A'),
+            Named::ArrowDown => cursor_key('B'),
+            Named::ArrowRight => cursor_key('C'),
+            Named::ArrowLeft => cursor_key('D'),
+            Named::Home => cursor_key('H'),
+            Named::End => cursor_key('F'),
+            Named::Insert => seq("\x1b[2~"),
+            Named::Delete => seq("\x1b[3~"),
+            Named::PageUp => seq("\x1b[5~"),
+            Named::PageDown => seq("\x1b[6~"),
+            Named::F1 => seq("\x1bOP"),
+            Named::F2 => seq("\x1bOQ"),
+            Named::F3 => seq("\x1bOR"),
+            Named::F4 => seq("\x1bOS"),
+            Named::F5 => seq("\x1b[15~"),
+            Named::F6 => seq("\x1b[17~"),
+            Named::F7 => seq("\x1b[18~"),
+            Named::F8 => seq("\x1b[19~"),
+            Named::F9 => seq("\x1b[20~"),
+            Named::F10 => seq("\x1b[21~"),
+            Named::F11 => seq("\x1b[23~"),
+            Named::F12 => seq("\x1b[24~"),
+            Named::Space if modifiers.control() => vec![0],
+            _ => text.map(seq).unwrap_or_default(),
+        },
+        Key::Character(c) if modifiers.control() => match c.as_bytes() {
+            [b @ (b'@'..=b'_' | b'a'..=b'z')] => vec![b & 0x1f],
+            _ => vec![],
+        },
+        _ => {
+            let mut bytes = text.map(seq).unwrap_or_default();
+            if modifiers.alt() && !bytes.is_empty() {
+                bytes.insert(0, 0x1b);
+            }
+            bytes
+        }
+    }
+}
+
+/// Widget showing a `Terminal` and taking keyboard input for it
+pub struct TerminalView<'a, Message> {
+    term: &'a Terminal,
+    on_input: Box<dyn Fn(TerminalInput) -> Message + 'a>,
+}
+
+pub fn terminal<'a, Message>(
+    term: &'a Terminal,
+    on_input: impl Fn(TerminalInput) -> Message + 'a,
+) -> TerminalView<'a, Message> {
+    TerminalView {
+        term,
+        on_input: Box::new(on_input),
+    }
+}
+
+#[derive(Debug, Default)]
+struct ViewState {
+    focused: bool,
+    /// How many lines the view is scrolled back from the bottom
+    scroll: usize,
+    /// Selection as (line, column) positions counting from the oldest
+    /// scrollback line
+    selection: Option<((usize, usize), (usize, usize))>,
+    selecting: bool,
+    /// The size last asked for, so it's only asked once
+    requested: Option<(usize, usize)>,
+}
+
+impl ViewState {
+    fn selected(&self, line: usize, col: usize) -> bool {
+        let Some((a, b)) = self.selection else {
+            return false;
+        };
+        let (start, end) = if a <= b { (a, b) } else { (b, a) };
+        start != end && (start..=end).contains(&(line, col))
+    }
+}
+
+impl<Message> TerminalView<'_, Message> {
+    /// Index of the line drawn at the top of the view
+    fn first_line(&self, state: &ViewState) -> usize {
+        self.term.scrollback_len() - state.scroll.min(self.term.scrollback_len())
+    }
+
+    /// (line, column) of the cell under a window position
+    fn cell_at(&self, state: &ViewState, bounds: Rectangle, pos: Point) -> (usize, usize) {
+        let (cols, rows) = self.term.size();
+        let col = ((pos.x - bounds.x) / CELL_WIDTH).max(0.0) as usize;
+        let row = ((pos.y - bounds.y) / CELL_HEIGHT).max(0.0) as usize;
+        (
+            self.first_line(state) + row.min(rows - 1),
+            col.min(cols - 1),
+        )
+    }
+
+    fn scroll(&self, state: &mut ViewState, lines: isize) {
+        let max = self.term.scrollback_len() as isize;
+        state.scroll = (state.scroll as isize + lines).clamp(0, max) as usize;
+    }
+
+    fn selection_text(&self, state: &ViewState) -> Option<String> {
+        let (start, end) = state.selection?;
+        (start != end).then(|| self.term.text(start, end))
+    }
+}
+
+impl<Message, Theme, Renderer> Widget<Message, Theme, Renderer> for TerminalView<'_, Message>
+where
+    Renderer: text::Renderer<Font = Font>,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<ViewState>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(ViewState::default())
+    }
+
+    fn size(&self) -> Size<Length> {
+        Size::new(Length::Fill, Length::Fill)
+    }
+
+    fn layout(
+        &self,
+        _tree: &mut widget::Tree,
+        _renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        layout::Node::new(limits.resolve(Length::Fill, Length::Fill, Size::ZERO))
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut widget::Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) -> event::Status {
+        let state = tree.state.downcast_mut::<ViewState>();
+        let bounds = layout.bounds();
+        match event {
+            Event::Window(window::Event::RedrawRequested(_)) => {
+                let cols = (bounds.width / CELL_WIDTH).floor().max(1.0) as usize;
+                let rows = (bounds.height / CELL_HEIGHT).floor().max(1.0) as usize;
+                if (cols, rows) != self.term.size() && state.requested != Some((cols, rows)) {
+                    state.requested = Some((cols, rows));
+                    shell.publish((self.on_input)(TerminalInput::Resize(cols, rows)));
+                }
+                return event::Status::Ignored;
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let Some(pos) = cursor.position_over(bounds) else {
+                    state.focused = false;
+                    return event::Status::Ignored;
+                };
+                state.focused = true;
+                let cell = self.cell_at(state, bounds, pos);
+                state.selection = Some((cell, cell));
+                state.selecting = true;
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) if state.selecting => {
+                let cell = self.cell_at(state, bounds, position);
+                if let Some((_, end)) = &mut state.selection {
+                    *end = cell;
+                }
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) if state.selecting => {
+                state.selecting = false;
+                if let Some(text) = self.selection_text(state) {
+                    shell.publish((self.on_input)(TerminalInput::Selected(text)));
+                }
+            }
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) if cursor.is_over(bounds) => {
+                let lines = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } => y * 3.0,
+                    mouse::ScrollDelta::Pixels { y, .. } => y / CELL_HEIGHT,
+                };
+                self.scroll(state, lines.round() as isize);
+            }
+            Event::Keyboard(keyboard::Event::KeyPressed {
+                key,
+                modifiers,
+                text,
+                ..
+            }) if state.focused => {
+                let page = self.term.size().1 as isize;
+                match &key {
+                    Key::Named(Named::PageUp) if modifiers.shift() => self.scroll(state, page),
+                    Key::Named(Named::PageDown) if modifiers.shift() => self.scroll(state, -page),
+                    Key::Character(c) if modifiers.control() && modifiers.shift() => {
+                        match c.to_lowercase().as_str() {
+                            "c" => {
+                                if let Some(text) = self.selection_text(state) {
+                                    clipboard.write(Kind::Standard, text);
+                                }
+                            }
+                            "v" => {
+                                if let Some(text) = clipboard.read(Kind::Standard) {
+                                    state.scroll = 0;
+                                    shell.publish((self.on_input)(TerminalInput::Data(
+                                        text.into_bytes(),
+                                    )));
+                                }
+                            }
+                            _ => return event::Status::Ignored,
+                        }
+                    }
+                    _ => {
+                        let bytes =
+                            key_bytes(&key, modifiers, text.as_deref(), self.term.app_cursor);
+                        if bytes.is_empty() {
+                            return event::Status::Ignored;
+                        }
+                        state.scroll = 0;
+                        shell.publish((self.on_input)(TerminalInput::Data(bytes)));
+                    }
+                }
+            }
+            _ => return event::Status::Ignored,
+        }
+        event::Status::Captured
+    }
+
+    fn draw(
+        &self,
+        tree: &widget::Tree,
+        renderer: &mut Renderer,
+        _theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        let state = tree.state.downcast_ref::<ViewState>();
+        let bounds = layout.bounds();
+        let (default_fg, default_bg) = (palette(7), Color::BLACK);
+        renderer.fill_quad(
+            renderer::Quad {
+                bounds,
+                ..renderer::Quad::default()
+            },
+            default_bg,
+        );
+        let Some(clip) = bounds.intersection(viewport) else {
+            return;
+        };
+        let (cols, rows) = self.term.size();
+        let first = self.first_line(state);
+        let cell_rect = |row: usize, col: usize, width: usize| Rectangle {
+            x: bounds.x + col as f32 * CELL_WIDTH,
+            y: bounds.y + row as f32 * CELL_HEIGHT,
+            width: width as f32 * CELL_WIDTH,
+            height: CELL_HEIGHT,
+        };
+        for row in 0..rows {
+            let idx = first + row;
+            let line = self.term.line(idx);
+            let style = |col: usize| {
+                let attrs = line[col].attrs;
+                let inverse = attrs.reverse != state.selected(idx, col);
+                (attrs, inverse)
+            };
+            // Draw runs of cells with the same look in one go
+            let mut col = 0;
+            while col < cols.min(line.len()) {
+                let look = style(col);
+                let mut end = col + 1;
+                while end < cols.min(line.len()) && style(end) == look {
+                    end += 1;
+                }
+                let (attrs, inverse) = look;
+                let mut fg = attrs.fg.to_color(default_fg);
+                if attrs.bold
+                    && let TermColor::Indexed(i @ 0..=7) = attrs.fg
+                {
+                    fg = palette(i + 8);
+                }
+                let mut bg = attrs.bg.to_color(default_bg);
+                if inverse {
+                    std::mem::swap(&mut fg, &mut bg);
+                }
+                let rect = cell_rect(row, col, end - col);
+                if bg != default_bg {
+                    renderer.fill_quad(
+                        renderer::Quad {
+                            bounds: rect,
+                            ..renderer::Quad::default()
+                        },
+                        bg,
+                    );
+                }
+                let content: String = line[col..end].iter().map(|c| c.ch).collect();
+                if !content.trim().is_empty() {
+                    let font = Font {
+                        weight: if attrs.bold {
+                            font::Weight::Bold
+                        } else {
+                            font::Weight::Normal
+                        },
+                        ..Font::MONOSPACE
+                    };
+                    renderer.fill_text(
+                        Text {
+                            content,
+                            bounds: rect.size(),
+                            size: Pixels(FONT_SIZE),
+                            line_height: text::LineHeight::Absolute(Pixels(CELL_HEIGHT)),
+                            font,
+                            horizontal_alignment: Horizontal::Left,
+                            vertical_alignment: Vertical::Top,
+                            shaping: text::Shaping::Basic,
+                            wrapping: text::Wrapping::None,
+                        },
+                        rect.position(),
+                        fg,
+                        clip,
+                    );
+                }
+                if attrs.underline {
+                    renderer.fill_quad(
+                        renderer::Quad {
+                            bounds: Rectangle {
+                                y: rect.y + CELL_HEIGHT - 2.0,
+                                height: 1.0,
+                                ..rect
+                            },
+                            ..renderer::Quad::default()
+                        },
+                        fg,
+                    );
+                }
+                col = end;
+            }
+        }
+        if let Some((row, col)) = self.term.cursor()
+            && first + row < self.term.line_count()
+            && first == self.term.scrollback_len()
+        {
+            let quad = if state.focused {
+                (
+                    Border::default(),
+                    Color {
+                        a: 0.6,
+                        ..default_fg
+                    },
+                )
+            } else {
+                (
+                    Border::default().color(default_fg).width(1.0),
+                    Color::TRANSPARENT,
+                )
+            };
+            renderer.fill_quad(
+                renderer::Quad {
+                    bounds: cell_rect(row, col, 1),
+                    border: quad.0,
+                    ..renderer::Quad::default()
+                },
+                Background::Color(quad.1),
+            );
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        _tree: &widget::Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &Renderer,
+    ) -> mouse::Interaction {
+        if cursor.is_over(layout.bounds()) {
+            mouse::Interaction::Text
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<TerminalView<'a, Message>>
+    for Element<'a, Message, Theme, Renderer>
+where
+    Message: 'a,
+    Renderer: text::Renderer<Font = Font> + 'a,
+{
+    fn from(view: TerminalView<'a, Message>) -> Self {
+        Element::new(view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terminal_emulation() {
+        let mut term = Terminal::new(10, 3);
+        term.feed(b"hello\r\nw\xc3\xb6rld\r\n");
+        assert_eq!(term.screen_text(), "hello\nwörld\n");
+        assert_eq!(term.cursor(), Some((2, 0)));
+
+        // Scrolling pushes lines into the scrollback
+        term.feed(b"three\r\nfour");
+        assert_eq!(term.scrollback_len(), 1);
+        assert_eq!(term.text((0, 0), (0, 9)), "hello");
+        assert_eq!(term.screen_text(), "wörld\nthree\nfour");
+
+        // Cursor movement, erasing and colors
+        term.feed(b"\x1b[1;1H\x1b[K\x1b[1;31mred\x1b[0m\x1b[2;3H\x1b[1P");
+        assert_eq!(term.screen_text(), "red\nthee\nfour");
+        let red = term.line(1)[0].attrs;
+        assert_eq!(red.fg, TermColor::Indexed(1));
+        assert!(red.bold);
+        assert_eq!(term.line(1)[3].attrs, Attrs::default());
+
+        // Autowrap at the last column
+        term.feed(b"\x1b[2J\x1b[H0123456789ab");
+        assert_eq!(term.screen_text(), "0123456789\nab\n");
+
+        // Cursor position report, and the alternate screen
+        term.feed(b"\x1b[6n");
+        assert_eq!(term.take_output(), b"\x1b[2;3R");
+        term.feed(b"\x1b[?1049h\x1b[Hvi\x1b[?25l");
+        assert_eq!(term.screen_text(), "vi\n\n");
+        assert_eq!(term.cursor(), None);
+        term.feed(b"\x1b[?1049l\x1b[?25h");
+        assert_eq!(term.screen_text(), "0123456789\nab\n");
+        assert_eq!(term.cursor(), Some((1, 2)));
+
+        // Scrollback is capped
+        for i in 0..SCROLLBACK_LINES + 10 {
+            term.feed(format!("{}\r\n", i).as_bytes());
+        }
+        assert_eq!(term.scrollback_len(), SCROLLBACK_LINES);
+
+        term.resize(4, 2);
+        assert_eq!(term.size(), (4, 2));
+        assert_eq!(term.screen_text(), "1009\n");
+    }
+
+    #[test]
+    fn test_key_bytes() {
+        let none = Modifiers::empty();
+        let key = |k: Named| Key::Named(k);
+        assert_eq!(
+            key_bytes(&key(Named::Enter), none, Some("\r"), false),
+            b"\r"
+        );
+        assert_eq!(
+            key_bytes(&key(Named::ArrowUp), none, None, false),
+            b"\x1b[A"
+        );
+        assert_eq!(key_bytes(&key(Named::ArrowUp), none, None, true), b"\x1bOA");
+        let c = Key::Character("c".into());
+        assert_eq!(key_bytes(&c, Modifiers::CTRL, None, false), [3]);
+        assert_eq!(key_bytes(&c, none, Some("c"), false), b"c");
+        assert_eq!(key_bytes(&c, Modifiers::ALT, Some("c"), false), b"\x1bc");
+    }
+}
@@ -0,0 +1,90 @@
+// Temporary files (the tempfile.mkstemp uses of virtinst)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Unique files for fetched media, generated scripts and XML handed to
+//! virsh. Names get a random part between a prefix and a suffix, and
+//! files are created exclusively and readable by the owner only, like
+//! Python's `tempfile.mkstemp`.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// Tries before giving up on finding an unused name
+const MAX_TRIES: usize = 100;
+
+/// Create `dir/{prefix}XXXXXXXX{suffix}` and open it for writing. The
+/// file stays until the caller removes it.
+pub fn mkstemp(dir: &Path, prefix: &str, suffix: &str) -> io::Result<(File, PathBuf)> {
+    for _ in 0..MAX_TRIES {
+        let path = dir.join(format!("{}{:08x}{}", prefix, rand::random::<u32>(), suffix));
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("No unused temporary file name in '{}'", dir.display()),
+    ))
+}
+
+/// A file in the system temp dir, removed when dropped
+pub struct TempFile {
+    pub file: File,
+    path: PathBuf,
+}
+
+impl TempFile {
+    pub fn new(prefix: &str, suffix: &str) -> io::Result<Self> {
+        let (file, path) = mkstemp(&std::env::temp_dir(), prefix, suffix)?;
+        Ok(Self { file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_tmpfiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, a) = mkstemp(dir.path(), "virtinst-", "-initrd.img").unwrap();
+        let (_, b) = mkstemp(dir.path(), "virtinst-", "-initrd.img").unwrap();
+        assert_ne!(a, b);
+        let name = a.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("virtinst-") && name.ends_with("-initrd.img"));
+        assert_eq!(name.len(), "virtinst-".len() + 8 + "-initrd.img".len());
+        let mode = std::fs::metadata(&a).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut tmp = TempFile::new("virt-manager-", ".xml").unwrap();
+        tmp.file.write_all(b"<domain/>").unwrap();
+        let path = tmp.path().to_path_buf();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "<domain/>");
+        drop(tmp);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,636 @@
+// Unattended installs from osinfo install scripts (port of
+// virtinst/install/unattended.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `--unattended` looks up the osinfo install scripts for the guest OS
+//! and profile, and renders them with an install config built from the
+//! guest and the user's data, the way libosinfo's `InstallScript` and
+//! `InstallConfig` do. The installer passes the result to the guest by
+//! initrd injection or on a generated cdrom.
+
+use std::path::{Path, PathBuf};
+
+use crate::guest::{Guest, host_arch};
+use crate::osdict::{GENERIC, InstallScript, OsVariant, osdb};
+use crate::progress::Meter;
+use crate::tmpfile;
+use crate::urlfetcher::{self, Fetcher};
+use crate::xmlapi::{Element, Node};
+use crate::xslt::Stylesheet;
+
+/// Characters some installers refuse in a computer name
+const HOSTNAME_BAD_CHARS: &str = "{|}~[\\]^':; <=>?@!\"#$%`()+/.,*&";
+
+fn is_user_login_safe(login: &str) -> bool {
+    login != "root"
+}
+
+/// The host user's login and real name, like python's `getpass.getuser`
+fn login_from_hostuser() -> Option<(String, String)> {
+    let login = ["LOGNAME", "USER", "LNAME", "USERNAME"]
+        .iter()
+        .find_map(|key| std::env::var(key).ok().filter(|v| !v.is_empty()))?;
+    if !is_user_login_safe(&login) {
+        return None;
+    }
+    let cname = std::ffi::CString::new(login.as_str()).ok()?;
+    // SAFETY: getpwnam gets a valid C string, and the returned entry is
+    // copied out before any other call could overwrite it
+    let realname = unsafe {
+        let pw = libc::getpwnam(cname.as_ptr());
+        if pw.is_null() || (*pw).pw_gecos.is_null() {
+            String::new()
+        } else {
+            std::ffi::CStr::from_ptr((*pw).pw_gecos)
+                .to_string_lossy()
+                .into_owned()
+        }
+    };
+    Some((login, realname))
+}
+
+/// Like `America/New_York`, from where `/etc/localtime` points
+fn get_timezone() -> Option<String> {
+    let linkpath = std::fs::canonicalize("/etc/localtime").ok()?;
+    let linkpath = linkpath.to_string_lossy();
+    linkpath
+        .split_once("zoneinfo/")
+        .map(|(_, zone)| zone.to_string())
+}
+
+/// Like `en_US`, from the locale environment
+fn get_language() -> Option<String> {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|key| std::env::var(key).ok().filter(|v| !v.is_empty()))?;
+    let lang = locale.split(['.', '@']).next().unwrap_or_default();
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return None;
+    }
+    Some(lang.to_string())
+}
+
+/// What the user passed with `--unattended`
+#[derive(Debug, Clone, Default)]
+pub struct UnattendedData {
+    pub profile: Option<String>,
+    pub admin_password_file: Option<String>,
+    pub user_login: Option<String>,
+    pub user_password_file: Option<String>,
+    pub product_key: Option<String>,
+    pub reg_login: Option<String>,
+}
+
+impl UnattendedData {
+    /// The first line of a password file
+    fn get_password(path: &Option<String>) -> Result<Option<String>, String> {
+        let Some(path) = path else {
+            return Ok(None);
+        };
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Error reading password file '{}': {}", path, e))?;
+        let line = content.split('\n').next().unwrap_or_default();
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    pub fn get_user_password(&self) -> Result<Option<String>, String> {
+        Self::get_password(&self.user_password_file)
+    }
+
+    pub fn get_admin_password(&self) -> Result<Option<String>, String> {
+        Self::get_password(&self.admin_password_file)
+    }
+}
+
+/// An install script set up for one install, like a libosinfo
+/// `InstallScript` with its `InstallConfig`
+pub struct OsInstallScript {
+    script: InstallScript,
+    osname: String,
+    /// The `<os>` element of the config document
+    os_xml: Element,
+    injection_method: String,
+    installation_source: String,
+    /// Config params by their libosinfo names, like `user-login`
+    config: Vec<(&'static str, String)>,
+}
+
+impl OsInstallScript {
+    fn new(script: &InstallScript, osinfo: &OsVariant) -> Self {
+        let mut os_xml = Element::new("os");
+        let params = [
+            ("id", osinfo.full_id.clone()),
+            ("short-id", Some(osinfo.name.clone())),
+            ("name", Some(osinfo.label.clone())),
+            ("version", osinfo.version.clone()),
+            ("family", Some(osinfo.family().to_string())),
+            ("distro", Some(osinfo.distro.clone())),
+            ("codename", Some(osinfo.codename.clone())),
+        ];
+        for (key, value) in params {
+            if let Some(value) = value.filter(|v| !v.is_empty()) {
+                os_xml.set(&format!("./{}", key), Some(&value));
+            }
+        }
+        Self {
+            script: script.clone(),
+            osname: osinfo.name.clone(),
+            os_xml,
+            injection_method: "cdrom".to_string(),
+            installation_source: "media".to_string(),
+            config: vec![],
+        }
+    }
+
+    pub fn get_expected_filename(&self) -> &str {
+        &self.script.expected_filename
+    }
+
+    /// `cdrom` or `initrd`, erroring if the script doesn't support it
+    pub fn set_preferred_injection_method(&mut self, method: &str) -> Result<(), String> {
+        log::debug!("Using '{}' injection method", method);
+        if !self.script.supports_injection_method(method) {
+            return Err(format!(
+                "OS '{}' does not support required injection method '{}'",
+                self.osname, method
+            ));
+        }
+        self.injection_method = method.to_string();
+        Ok(())
+    }
+
+    /// `media` or `network`
+    pub fn set_installation_source(&mut self, source: &str) {
+        log::debug!("Using '{}' installation source", source);
+        self.installation_source = source.to_string();
+    }
+
+    pub fn requires_user_password(&self) -> bool {
+        self.script.requires_param("user-password")
+    }
+
+    pub fn requires_admin_password(&self) -> bool {
+        self.script.requires_param("admin-password")
+    }
+
+    fn config_value(&self, key: &str) -> Option<&str> {
+        self.config
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The document the template is applied to: `install-script-config`
+    /// for the script itself, `command-line` for its kernel arguments
+    fn config_document(&self, root: &str, scrub: bool) -> Element {
+        let mut doc = Element::new(root);
+        let mut config = Element::new("config");
+        for (key, value) in &self.config {
+            let scrubbed = scrub && key.ends_with("-password");
+            let value = if scrubbed { "[SCRUBBLED]" } else { value };
+            let mut param = Element::new(key);
+            param.set_text(Some(value));
+            config.children.push(Node::Element(param));
+        }
+        let mut script = Element::new("script");
+        script.set("./expected-filename", Some(self.get_expected_filename()));
+        script.set("./preferred-injection-method", Some(&self.injection_method));
+        script.set("./installation-source", Some(&self.installation_source));
+        for el in [config, self.os_xml.clone(), script] {
+            doc.children.push(Node::Element(el));
+        }
+        doc
+    }
+
+    fn stylesheet(&self) -> Result<Stylesheet, String> {
+        let template = self
+            .script
+            .template()
+            .ok_or_else(|| format!("Install script '{}' has no template", self.script.id))?;
+        Stylesheet::new(template)
+            .map_err(|e| format!("Error loading install script '{}': {}", self.script.id, e))
+    }
+
+    fn render(&self, root: &str, scrub: bool) -> Result<String, String> {
+        self.stylesheet()?
+            .transform(&self.config_document(root, scrub))
+            .map_err(|e| {
+                format!(
+                    "Error generating install script '{}': {}",
+                    self.script.id, e
+                )
+            })
+    }
+
+    pub fn generate(&self) -> Result<String, String> {
+        self.render("install-script-config", false)
+    }
+
+    /// The kernel arguments the script needs, if it has any
+    pub fn generate_cmdline(&self) -> Result<Option<String>, String> {
+        let doc = self.config_document("command-line", false);
+        if !self.stylesheet()?.has_template_for(&doc) {
+            return Ok(None);
+        }
+        let cmdline = self.render("command-line", false)?;
+        Ok(Some(cmdline.trim().to_string()).filter(|c| !c.is_empty()))
+    }
+
+    /// Write the script to a new file in `dir` and return its path
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, String> {
+        let content = self.generate()?;
+        let (_, scriptpath) = tmpfile::mkstemp(dir, "virtinst-unattended-script", "")
+            .map_err(|e| format!("Error writing unattended script: {}", e))?;
+        std::fs::write(&scriptpath, content)
+            .map_err(|e| format!("Error writing unattended script: {}", e))?;
+
+        log::debug!("Generated unattended script: {}", scriptpath.display());
+        log::debug!(
+            "Generated script contents:\n{}",
+            self.render("install-script-config", true)?
+        );
+        Ok(scriptpath)
+    }
+}
+
+/// Build the install config of `script` for `guest`
+fn make_installconfig(
+    script: &mut OsInstallScript,
+    osinfo: &OsVariant,
+    data: &UnattendedData,
+    arch: &str,
+    hostname: &str,
+    url: Option<&str>,
+) -> Result<(), String> {
+    let mut config: Vec<(&'static str, String)> = vec![];
+
+    // A user login passed in is used as the real name too, otherwise
+    // both come from the host user
+    let (login, realname) = match &data.user_login {
+        Some(login) => (Some(login.clone()), Some(login.clone())),
+        None => match login_from_hostuser() {
+            Some((login, realname)) => (Some(login), Some(realname)),
+            None => (None, None),
+        },
+    };
+    if let Some(login) = login {
+        let login = login.to_lowercase();
+        if !is_user_login_safe(&login) {
+            return Err(format!(
+                "{} cannot use '{}' as user-login.",
+                osinfo.name, login
+            ));
+        }
+        config.push(("user-login", login));
+        config.push(("user-realname", realname.unwrap_or_default()));
+    }
+
+    let user_password = data.get_user_password()?;
+    if script.requires_user_password() && user_password.is_none() {
+        return Err(format!(
+            "{} requires the user-password to be set.",
+            osinfo.name
+        ));
+    }
+    config.push(("user-password", user_password.unwrap_or_default()));
+
+    let admin_password = data.get_admin_password()?;
+    if script.requires_admin_password() && admin_password.is_none() {
+        return Err(format!(
+            "{} requires the admin-password to be set.",
+            osinfo.name
+        ));
+    }
+    config.push(("admin-password", admin_password.unwrap_or_default()));
+
+    // virtio disks are preferred when the OS supports them
+    let target = if osinfo.is_windows() {
+        "C"
+    } else if osinfo.supports_virtiodisk() {
+        "/dev/vda"
+    } else {
+        "/dev/sda"
+    };
+    config.push(("target-disk", target.to_string()));
+    config.push(("hardware-arch", arch.to_string()));
+
+    // Some installs bail on special characters in the computer name
+    let hostname: String = hostname
+        .chars()
+        .map(|c| {
+            if HOSTNAME_BAD_CHARS.contains(c) {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+    config.push(("hostname", hostname));
+
+    if let Some(timezone) = get_timezone() {
+        config.push(("l10n-timezone", timezone));
+    }
+    // The keyboard layout often doesn't match the language, but there's
+    // no desktop independent way to find it
+    if let Some(language) = get_language() {
+        config.push(("l10n-language", language.clone()));
+        config.push(("l10n-keyboard", language));
+    }
+    if let Some(url) = url {
+        config.push(("installation-url", url.to_string()));
+    }
+    if let Some(reg_login) = &data.reg_login {
+        config.push(("reg-login", reg_login.clone()));
+    }
+    if let Some(product_key) = &data.product_key {
+        config.push(("reg-product-key", product_key.clone()));
+    }
+
+    script.config = config;
+    log::debug!("InstallScriptConfig created with the following params:");
+    for key in [
+        "user-login",
+        "user-realname",
+        "target-disk",
+        "hardware-arch",
+        "hostname",
+        "l10n-timezone",
+        "l10n-language",
+        "l10n-keyboard",
+        "installation-url",
+        "reg-login",
+        "reg-product-key",
+    ] {
+        log::debug!("{}: {}", key, script.config_value(key).unwrap_or("None"));
+    }
+    Ok(())
+}
+
+/// The install scripts of `osinfo` for `profile`, defaulting to the
+/// desktop profile
+fn lookup_rawscripts<'a>(
+    osinfo: &'a OsVariant,
+    profile: Option<&str>,
+) -> Result<Vec<&'a InstallScript>, String> {
+    let script_list = osinfo.get_install_script_list();
+    if script_list.is_empty() {
+        return Err(format!(
+            "OS '{}' does not support unattended installation.",
+            osinfo.name
+        ));
+    }
+    let mut profile_names: Vec<&str> = script_list.iter().map(|s| s.profile.as_str()).collect();
+    profile_names.sort();
+    profile_names.dedup();
+
+    let profile = match profile {
+        Some(profile) => {
+            if !profile_names.contains(&profile) {
+                return Err(format!(
+                    "OS '{}' does not support unattended installation for the '{}' profile. \
+                     Available profiles: {}",
+                    osinfo.name,
+                    profile,
+                    profile_names.join(", ")
+                ));
+            }
+            profile
+        }
+        None => {
+            let profile = if profile_names.contains(&"desktop") {
+                "desktop"
+            } else {
+                profile_names[0]
+            };
+            log::warn!("Using unattended profile '{}'", profile);
+            profile
+        }
+    };
+
+    // Some OSes, like Windows, have more than one script per profile
+    let rawscripts: Vec<&InstallScript> = script_list
+        .iter()
+        .filter(|s| s.profile == profile)
+        .collect();
+    log::debug!(
+        "Install scripts found for profile '{}': {}",
+        profile,
+        rawscripts
+            .iter()
+            .map(|s| s.id.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(rawscripts)
+}
+
+/// The install scripts for `guest`, configured from `data`. `url` is a
+/// network install location; `installation_source` is `media` or
+/// `network`, and `injection_method` is `cdrom` or `initrd`.
+pub fn prepare_install_scripts(
+    guest: &Guest,
+    data: &UnattendedData,
+    url: Option<&str>,
+    installation_source: &str,
+    injection_method: &str,
+) -> Result<Vec<OsInstallScript>, String> {
+    let osinfo = match guest.osinfo() {
+        Some(osinfo) => osinfo,
+        None => osdb().require_os(GENERIC)?,
+    };
+    let arch = guest.arch().unwrap_or_else(|| host_arch().to_string());
+    let hostname = guest.name().unwrap_or_default();
+
+    let mut scripts = vec![];
+    for rawscript in lookup_rawscripts(osinfo, data.profile.as_deref())? {
+        let mut script = OsInstallScript::new(rawscript, osinfo);
+        script.set_preferred_injection_method(injection_method)?;
+        script.set_installation_source(installation_source);
+        make_installconfig(&mut script, osinfo, data, &arch, &hostname, url)?;
+        scripts.push(script);
+    }
+    Ok(scripts)
+}
+
+/// Fetch driver files into `scratchdir`, returning (path, filename)
+/// pairs for cdrom injection
+pub fn download_drivers(
+    locations: &[String],
+    scratchdir: &Path,
+    meter: &dyn Meter,
+    fetcher: &dyn Fetcher,
+) -> Result<Vec<(PathBuf, String)>, String> {
+    let mut drivers: Vec<(PathBuf, String)> = vec![];
+    for location in locations {
+        let filename = location.rsplit('/').next().unwrap_or(location).to_string();
+        match urlfetcher::acquire_file(location, scratchdir, meter, fetcher) {
+            Ok(path) => drivers.push((path, filename)),
+            Err(e) => {
+                for (path, _) in drivers {
+                    let _ = std::fs::remove_file(path);
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(drivers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datadir() -> String {
+        format!(
+            "{}/../tests/data/cli/unattended",
+            env!("CARGO_MANIFEST_DIR")
+        )
+    }
+
+    fn make_guest(name: &str, os: &str) -> Guest {
+        let mut guest = Guest::new();
+        guest.set_name(name);
+        guest.xml.set("./os/type/@arch", Some("x86_64"));
+        guest.set_os_name(os).unwrap();
+        guest
+    }
+
+    #[test]
+    fn test_kickstart() {
+        let guest = make_guest("f26 test.vm", "fedora26");
+        let data = UnattendedData {
+            profile: Some("desktop".to_string()),
+            admin_password_file: Some(format!("{}/admin-password.txt", datadir())),
+            user_password_file: Some(format!("{}/user-password.txt", datadir())),
+            user_login: Some("FooBar".to_string()),
+            ..Default::default()
+        };
+        let url = "https://example.com/fedora/26/os/";
+        let scripts =
+            prepare_install_scripts(&guest, &data, Some(url), "network", "initrd").unwrap();
+        assert_eq!(scripts.len(), 1);
+        let script = &scripts[0];
+        assert_eq!(script.get_expected_filename(), "fedora.ks");
+        assert_eq!(
+            script.generate_cmdline().unwrap().as_deref(),
+            Some("ks=file:/fedora.ks inst.repo=https://example.com/fedora/26/os/")
+        );
+        let ks = script.generate().unwrap();
+        assert!(ks.starts_with("# Kickstart file for Fedora 26 (desktop profile)\n"));
+        assert!(ks.contains(&format!("\nurl --url={}\n", url)));
+        assert!(ks.contains("\nrootpw --plaintext foobar\n"));
+        assert!(ks.contains(
+            "\nuser --name=foobar --password=blah --plaintext --groups=wheel --gecos=\"FooBar\"\n"
+        ));
+        assert!(ks.contains("--hostname=f26-test-vm "));
+        assert!(ks.contains("\nclearpart --all --initlabel --drives=vda\n"));
+        assert!(ks.ends_with("%packages\n@^workstation-product-environment\n%end\n"));
+        let scrubbed = script.render("install-script-config", true).unwrap();
+        assert!(scrubbed.contains("rootpw --plaintext [SCRUBBLED]\n"));
+
+        let scratch = tempfile::tempdir().unwrap();
+        let path = script.write(scratch.path()).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), ks);
+
+        // jeos needs only the admin password
+        let mut jeos = UnattendedData {
+            profile: Some("jeos".to_string()),
+            ..Default::default()
+        };
+        let err = prepare_install_scripts(&guest, &jeos, None, "network", "initrd")
+            .err()
+            .unwrap();
+        assert_eq!(err, "fedora26 requires the admin-password to be set.");
+        jeos.admin_password_file = data.admin_password_file.clone();
+        let scripts = prepare_install_scripts(&guest, &jeos, None, "media", "cdrom").unwrap();
+        assert_eq!(
+            scripts[0].generate_cmdline().unwrap().as_deref(),
+            Some("ks=cdrom:/fedora.ks")
+        );
+        assert!(scripts[0].generate().unwrap().contains("\ncdrom\n"));
+
+        let errors = [
+            (
+                UnattendedData {
+                    profile: Some("FRIBBER".to_string()),
+                    ..Default::default()
+                },
+                "OS 'fedora26' does not support unattended installation for the 'FRIBBER' \
+                 profile. Available profiles: desktop, jeos",
+            ),
+            (
+                UnattendedData {
+                    user_login: Some("ROOT".to_string()),
+                    ..jeos.clone()
+                },
+                "fedora26 cannot use 'root' as user-login.",
+            ),
+        ];
+        for (data, msg) in errors {
+            let err = prepare_install_scripts(&guest, &data, None, "network", "initrd")
+                .err()
+                .unwrap();
+            assert_eq!(err, msg);
+        }
+        let generic = make_guest("generic", "generic");
+        let err = prepare_install_scripts(&generic, &jeos, None, "network", "initrd")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            "OS 'generic' does not support unattended installation."
+        );
+    }
+
+    #[test]
+    fn test_windows() {
+        let guest = make_guest("win7", "win7");
+        let data = UnattendedData {
+            admin_password_file: Some(format!("{}/admin-password.txt", datadir())),
+            user_login: Some("Tester".to_string()),
+            product_key: Some("1234".to_string()),
+            ..Default::default()
+        };
+        let scripts = prepare_install_scripts(&guest, &data, None, "media", "cdrom").unwrap();
+        let names: Vec<&str> = scripts.iter().map(|s| s.get_expected_filename()).collect();
+        assert_eq!(names, ["autounattend.xml", "windows.cmd"]);
+        assert_eq!(scripts[0].generate_cmdline().unwrap(), None);
+
+        let xml = scripts[0].generate().unwrap();
+        assert!(xml.starts_with("<?xml version=\"1.0\"?>\n<unattend "));
+        let doc = Element::parse(&xml).unwrap();
+        assert_eq!(
+            doc.get("./settings[@pass='windowsPE']/component[@name='Microsoft-Windows-Setup']/UserData/ProductKey/Key")
+                .as_deref(),
+            Some("1234")
+        );
+        assert_eq!(
+            doc.get("./settings[@pass='windowsPE']/component/@processorArchitecture")
+                .as_deref(),
+            Some("amd64")
+        );
+        assert_eq!(
+            doc.get(
+                "./settings[@pass='oobeSystem']/component/UserAccounts/AdministratorPassword/Value"
+            )
+            .as_deref(),
+            Some("foobar")
+        );
+        assert_eq!(
+            doc.get("./settings[@pass='oobeSystem']/component/UserAccounts/LocalAccounts/LocalAccount/Name")
+                .as_deref(),
+            Some("tester")
+        );
+
+        let winxp = make_guest("winxp", "winxp");
+        let err = prepare_install_scripts(&winxp, &data, None, "media", "cdrom")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            "OS 'winxp' does not support required injection method 'cdrom'"
+        );
+    }
+}
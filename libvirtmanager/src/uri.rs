@@ -0,0 +1,149 @@
+// URI splitting (port of the URI class of virtinst/uri.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Splits libvirt and other URIs into their parts, the way urllib does
+//! for virt-manager. `qemu+ssh://user@host:22/system` gives scheme
+//! "qemu", transport "ssh", username "user", hostname "host", port "22"
+//! and path "/system". Missing parts are empty strings.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Uri {
+    pub uri: String,
+    pub scheme: String,
+    pub transport: String,
+    pub username: String,
+    pub hostname: String,
+    pub port: String,
+    pub path: String,
+    pub query: String,
+    pub fragment: String,
+    pub is_ipv6: bool,
+    pub host_is_ipv4_string: bool,
+}
+
+/// Decode %XX escapes, leaving malformed ones alone
+fn unquote(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s
+            .get(i + 1..i + 3)
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+impl Uri {
+    pub fn parse(uri: &str) -> Self {
+        let mut ret = Self {
+            uri: uri.to_string(),
+            ..Default::default()
+        };
+        let (mut netloc, mut rest) = ("", "");
+        if let Some((scheme, after)) = uri.split_once(':').filter(|(s, _)| !s.is_empty()) {
+            ret.scheme = scheme.to_lowercase();
+            rest = after;
+            if let Some(after) = rest.strip_prefix("//") {
+                let end = after.find(['/', '?', '#']).unwrap_or(after.len());
+                (netloc, rest) = after.split_at(end);
+                if let Some((user, host)) = netloc.split_once('@').filter(|(u, _)| !u.is_empty()) {
+                    ret.username = unquote(user);
+                    netloc = host;
+                }
+            }
+            if let Some((before, fragment)) = rest.split_once('#') {
+                ret.fragment = unquote(fragment);
+                rest = before;
+            }
+            if let Some((before, query)) = rest.split_once('?') {
+                ret.query = unquote(query);
+                rest = before;
+            }
+        }
+        ret.path = unquote(rest);
+
+        if let Some((scheme, transport)) = ret.scheme.clone().rsplit_once('+') {
+            ret.scheme = scheme.to_string();
+            ret.transport = transport.to_string();
+        }
+
+        let mut hostname = unquote(netloc);
+        if hostname.starts_with('[') && hostname.contains(']') {
+            if hostname.contains("]:") {
+                let (host, port) = hostname.rsplit_once(':').unwrap();
+                ret.port = port.to_string();
+                hostname = host.to_string();
+            }
+            hostname = hostname[1..].replacen(']', "", 1);
+            ret.is_ipv6 = true;
+        } else if let Some((host, port)) = hostname.clone().split_once(':') {
+            ret.port = port.to_string();
+            hostname = host.to_string();
+        }
+        ret.host_is_ipv4_string =
+            !hostname.is_empty() && hostname.chars().all(|c| c.is_ascii_digit() || c == '.');
+        ret.hostname = hostname;
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uris() {
+        let uri = Uri::parse("lxc://");
+        assert_eq!((uri.scheme.as_str(), uri.path.as_str()), ("lxc", ""));
+        let uri = Uri::parse("qemu:///session");
+        assert_eq!(
+            (uri.scheme.as_str(), uri.path.as_str()),
+            ("qemu", "/session")
+        );
+        assert_eq!(uri.hostname, "");
+
+        let uri = Uri::parse("http://foobar.com:5901/my/example.path#my-frag");
+        assert_eq!(uri.hostname, "foobar.com");
+        assert_eq!(uri.port, "5901");
+        assert_eq!(uri.path, "/my/example.path");
+        assert_eq!(uri.fragment, "my-frag");
+
+        let uri = Uri::parse("gluster+tcp://[1:2:3:4:5:6:7:8]:24007/testvol/dir/a.img");
+        assert_eq!(
+            (uri.scheme.as_str(), uri.transport.as_str()),
+            ("gluster", "tcp")
+        );
+        assert_eq!(uri.hostname, "1:2:3:4:5:6:7:8");
+        assert_eq!(uri.port, "24007");
+        assert!(uri.is_ipv6);
+
+        let uri = Uri::parse("qemu+ssh://root@192.168.2.3/system?no_verify=1");
+        assert_eq!(uri.transport, "ssh");
+        assert_eq!(uri.username, "root");
+        assert_eq!(uri.hostname, "192.168.2.3");
+        assert_eq!(uri.path, "/system");
+        assert_eq!(uri.query, "no_verify=1");
+        assert!(uri.host_is_ipv4_string);
+
+        assert_eq!(
+            Uri::parse("qemu+ssh://foo%5Cbar@hostname/system").username,
+            "foo\\bar"
+        );
+        let uri = Uri::parse("qemu+ssh://user%40domain.org@hostname/system");
+        assert_eq!(uri.username, "user@domain.org");
+        assert_eq!(uri.hostname, "hostname");
+    }
+}
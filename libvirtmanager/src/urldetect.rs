@@ -0,0 +1,1423 @@
+// Detecting the distro of install media (port of
+// virtinst/install/urldetect.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `MediaSource` opens an install tree, URL or local ISO image without
+//! mounting it. `detect_distro` probes it for `.treeinfo`, SUSE `content`,
+//! Debian/Ubuntu `MANIFEST` and `.disk/info`, or Mageia `VERSION` files,
+//! and returns a `DistroStore` with the OS variant and the kernel/initrd
+//! paths to boot. Variants are checked against an `OsCatalog`, so nothing
+//! is reported that the OS database doesn't know about.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::iso9660::IsoImage;
+use crate::progress::Meter;
+use crate::tmpfile;
+use crate::urlfetcher::{self, CurlFetcher};
+
+/// An OS from the OS database, as far as media detection cares
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogOs {
+    /// Short id like `debian10`
+    pub name: String,
+    /// Like `Bionic Beaver`, for Ubuntu
+    pub codename: Option<String>,
+    /// Like `Debian Buster`
+    pub label: String,
+}
+
+/// The OS names detection results are checked against
+pub trait OsCatalog {
+    fn lookup_os(&self, name: &str) -> bool;
+    fn list_os(&self) -> Vec<CatalogOs>;
+}
+
+enum Backend {
+    Tree,
+    Iso(IsoImage),
+    Url,
+}
+
+/// An install tree or ISO image: a local directory, a local ISO read
+/// without mounting it, or an http/https/ftp URL
+pub struct MediaSource {
+    location: String,
+    backend: Backend,
+}
+
+impl MediaSource {
+    /// Regular files are read as ISO images, anything else that isn't a
+    /// URL as a tree. A missing tree isn't an error here, detection
+    /// reports it.
+    pub fn open(location: &str) -> Result<Self, String> {
+        let backend = if urlfetcher::is_network_url(location) {
+            Backend::Url
+        } else if Path::new(location).is_file() {
+            Backend::Iso(IsoImage::open(location)?)
+        } else {
+            Backend::Tree
+        };
+        Ok(Self {
+            location: location.to_string(),
+            backend,
+        })
+    }
+
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    pub fn is_iso(&self) -> bool {
+        matches!(self.backend, Backend::Iso(_))
+    }
+
+    /// The ISO volume label, if this is an ISO
+    pub fn volume_id(&self) -> Option<&str> {
+        match &self.backend {
+            Backend::Iso(iso) => Some(iso.volume_id()),
+            _ => None,
+        }
+    }
+
+    pub fn can_access(&self) -> bool {
+        match &self.backend {
+            Backend::Tree => Path::new(&self.location).is_dir(),
+            Backend::Iso(_) => true,
+            Backend::Url => urlfetcher::url_exists(&self.location),
+        }
+    }
+
+    fn tree_path(&self, path: &str) -> PathBuf {
+        Path::new(&self.location).join(path.trim_start_matches('/'))
+    }
+
+    fn url_path(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.location.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+
+    pub fn has_file(&self, path: &str) -> bool {
+        match &self.backend {
+            Backend::Tree => self.tree_path(path).exists(),
+            Backend::Iso(iso) => iso.exists(path),
+            Backend::Url => urlfetcher::url_exists(&self.url_path(path)),
+        }
+    }
+
+    /// Contents of `path` relative to the media root
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, String> {
+        match &self.backend {
+            Backend::Tree => {
+                let fullpath = self.tree_path(path);
+                std::fs::read(&fullpath)
+                    .map_err(|e| format!("Couldn't acquire file {}: {}", fullpath.display(), e))
+            }
+            Backend::Iso(iso) => iso.read_file(path),
+            Backend::Url => urlfetcher::acquire_url_content(&self.url_path(path)),
+        }
+    }
+
+    pub fn acquire_file_content(&self, path: &str) -> Result<String, String> {
+        self.read_file(path)
+            .map(|data| String::from_utf8_lossy(&data).into_owned())
+    }
+
+    /// Copy `path` to a new temporary file in `scratchdir`, returning its
+    /// path
+    pub fn acquire_file(
+        &self,
+        path: &str,
+        scratchdir: &Path,
+        meter: &dyn Meter,
+    ) -> Result<PathBuf, String> {
+        let iso = match &self.backend {
+            Backend::Tree => {
+                let fullpath = self.tree_path(path);
+                return urlfetcher::acquire_file(
+                    &fullpath.to_string_lossy(),
+                    scratchdir,
+                    meter,
+                    &CurlFetcher,
+                );
+            }
+            Backend::Url => {
+                return urlfetcher::acquire_file(
+                    &self.url_path(path),
+                    scratchdir,
+                    meter,
+                    &CurlFetcher,
+                );
+            }
+            Backend::Iso(iso) => iso,
+        };
+        let basename = path.rsplit('/').next().unwrap_or(path);
+        meter.start(&format!("Retrieving '{}'", basename), None);
+        let data = iso.read_file(path)?;
+        let (mut file, tmppath) =
+            tmpfile::mkstemp(scratchdir, "virtinst-", &format!("-{}", basename))
+                .map_err(|e| format!("Couldn't acquire file {}: {}", path, e))?;
+        if let Err(e) = file.write_all(&data) {
+            let _ = std::fs::remove_file(&tmppath);
+            return Err(format!("Couldn't acquire file {}: {}", path, e));
+        }
+        meter.update(data.len() as u64);
+        meter.end();
+        log::debug!("Saved file to {}", tmppath.display());
+        Ok(tmppath)
+    }
+}
+
+/// Minimal configparser: sections of `key = value`, keys lowercased
+struct TreeInfo {
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl TreeInfo {
+    fn parse(content: &str) -> Self {
+        let mut sections: Vec<(String, Vec<(String, String)>)> = vec![];
+        for line in content.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                sections.push((name.to_string(), vec![]));
+                continue;
+            }
+            let Some((_, options)) = sections.last_mut() else {
+                continue;
+            };
+            if line.starts_with(char::is_whitespace) {
+                // Continuation of a multiline value
+                if let Some((_, value)) = options.last_mut() {
+                    value.push('\n');
+                    value.push_str(trimmed);
+                }
+                continue;
+            }
+            let Some(idx) = trimmed.find(['=', ':']) else {
+                continue;
+            };
+            let key = trimmed[..idx].trim().to_lowercase();
+            let value = trimmed[idx + 1..].trim().to_string();
+            options.retain(|(k, _)| *k != key);
+            options.push((key, value));
+        }
+        Self { sections }
+    }
+
+    fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections
+            .iter()
+            .filter(|(name, _)| name == section)
+            .flat_map(|(_, options)| options.iter())
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// The SUSE `content` file fields we care about
+struct SuseContent {
+    tree_arch: Option<String>,
+    product_name: Option<String>,
+    product_version: Option<String>,
+}
+
+impl SuseContent {
+    fn parse(content: &str) -> Self {
+        let mut fields: HashMap<&str, &str> = HashMap::new();
+        for line in content.lines() {
+            for prefix in [
+                "LABEL",
+                "DISTRO",
+                "VERSION",
+                "BASEARCHS",
+                "DEFAULTBASE",
+                "REPOID",
+            ] {
+                if let Some(rest) = line.strip_prefix(prefix)
+                    && rest.starts_with(' ')
+                {
+                    fields.insert(prefix, &rest[1..]);
+                }
+            }
+        }
+        log::debug!("SUSE content dict: {:?}", fields);
+
+        // opensuse 10.3 has DEFAULTBASE, 11.4 and 12.3 BASEARCHS, newer
+        // trees only a REPOID ending in the arch
+        let tree_arch = fields
+            .get("BASEARCHS")
+            .or_else(|| fields.get("DEFAULTBASE"))
+            .map(|s| s.to_string())
+            .or_else(|| {
+                fields
+                    .get("REPOID")
+                    .and_then(|r| r.rsplit_once('/'))
+                    .map(|(_, arch)| arch.to_string())
+            })
+            .map(|arch| {
+                let arch = arch.trim();
+                // Fix for 13.2 official oss repo
+                if arch.contains("i586-x86_64") {
+                    "x86_64".to_string()
+                } else {
+                    arch.to_string()
+                }
+            });
+
+        // LABEL like 'SUSE Linux Enterprise Server 11 SP4', or DISTRO like
+        // 'cpe:/o:opensuse:opensuse:13.2,openSUSE'
+        let distro = fields.get("DISTRO").copied().unwrap_or_default();
+        let product_name = match fields.get("LABEL") {
+            Some(label) => Some(label.to_string()),
+            None => distro.rsplit_once(',').map(|(_, name)| name.to_string()),
+        };
+
+        let product_version = product_name.as_deref().map(|name| {
+            let mut version = fields.get("VERSION").copied().unwrap_or_default();
+            // SLES-10-SP4 has 'VERSION 10.4-0'
+            if let Some((v, _)) = version.split_once('-') {
+                version = v;
+            }
+            let mut version = version.to_string();
+            if version.is_empty()
+                && let Some((cpe, name)) = distro.rsplit_once(',')
+                && name.starts_with("openSUS")
+                && cpe.contains(':')
+            {
+                version = cpe.trim().split(':').nth(4).unwrap_or_default().to_string();
+            }
+            let mut version = version.trim().to_string();
+
+            if name.contains("Enterprise") || name.contains("SLES") {
+                let words: Vec<&str> = name.trim().split(' ').collect();
+                version = words.get(4).copied().unwrap_or_default().to_string();
+                if let Some(sp) = words.get(5).and_then(|w| w.get(2..3)) {
+                    version = format!("{}.{}", version, sp);
+                }
+            }
+            version
+        });
+
+        log::debug!(
+            "SUSE content product_name={:?} product_version={:?} tree_arch={:?}",
+            product_name,
+            product_version,
+            tree_arch
+        );
+        Self {
+            tree_arch,
+            product_name,
+            product_version,
+        }
+    }
+}
+
+/// Files read from the media, shared between the distro probes
+struct DistroCache<'a> {
+    media: &'a MediaSource,
+    is_iso: bool,
+    filecache: HashMap<String, Option<String>>,
+
+    treeinfo: Option<TreeInfo>,
+    checked_for_treeinfo: bool,
+    treeinfo_family: Option<String>,
+    treeinfo_version: Option<String>,
+    treeinfo_name: Option<String>,
+    treeinfo_matched: bool,
+
+    suse_content: Option<SuseContent>,
+    checked_for_suse_content: bool,
+    debian_media_type: Option<&'static str>,
+    mageia_version: Option<String>,
+}
+
+impl<'a> DistroCache<'a> {
+    fn new(media: &'a MediaSource) -> Self {
+        Self {
+            media,
+            is_iso: media.is_iso(),
+            filecache: HashMap::new(),
+            treeinfo: None,
+            checked_for_treeinfo: false,
+            treeinfo_family: None,
+            treeinfo_version: None,
+            treeinfo_name: None,
+            treeinfo_matched: false,
+            suse_content: None,
+            checked_for_suse_content: false,
+            debian_media_type: None,
+            mageia_version: None,
+        }
+    }
+
+    fn acquire_file_content(&mut self, path: &str) -> Option<String> {
+        if let Some(content) = self.filecache.get(path) {
+            return content.clone();
+        }
+        let content = match self.media.acquire_file_content(path) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                log::debug!("Failed to acquire file={}: {}", path, e);
+                None
+            }
+        };
+        self.filecache.insert(path.to_string(), content.clone());
+        content
+    }
+
+    fn load_treeinfo(&mut self) -> bool {
+        if !self.checked_for_treeinfo {
+            self.checked_for_treeinfo = true;
+            // Red Hat satellite trees on akamai use 'treeinfo', since
+            // akamai doesn't do dotfiles. Anaconda checks both, so do we.
+            let content = self
+                .acquire_file_content(".treeinfo")
+                .or_else(|| self.acquire_file_content("treeinfo"));
+            let Some(treeinfo) = content.as_deref().map(TreeInfo::parse) else {
+                return false;
+            };
+            // Without a family we won't detect anything from it anyways
+            let Some(family) = treeinfo.get("general", "family") else {
+                log::debug!("treeinfo has no general.family");
+                return false;
+            };
+            log::debug!("treeinfo family={}", family);
+            self.treeinfo_family = Some(family.to_string());
+            self.treeinfo_version = treeinfo.get("general", "version").map(String::from);
+            self.treeinfo_name = treeinfo.get("general", "name").map(String::from);
+            log::debug!(
+                "Found treeinfo version={:?} name={:?}",
+                self.treeinfo_version,
+                self.treeinfo_name
+            );
+            self.treeinfo = Some(treeinfo);
+        }
+        self.treeinfo.is_some()
+    }
+
+    /// Whether the treeinfo family contains any of `names`
+    fn treeinfo_family_matches(&mut self, names: &[&str]) -> bool {
+        if !self.load_treeinfo() {
+            return false;
+        }
+        let family = self.treeinfo_family.as_deref().unwrap_or_default();
+        let ret = names.iter().any(|n| family.contains(n));
+        self.treeinfo_matched = ret;
+        if !ret {
+            log::debug!("Didn't match treeinfo family={:?}", names);
+        }
+        ret
+    }
+
+    /// Whether any line of `filename` satisfies `pred`
+    fn content_matches(&mut self, filename: &str, pred: impl Fn(&str) -> bool) -> bool {
+        let Some(content) = self.acquire_file_content(filename) else {
+            return false;
+        };
+        if content.lines().any(pred) {
+            return true;
+        }
+        log::debug!("found filename={} but it didn't match", filename);
+        false
+    }
+
+    /// kernel/initrd paths from the treeinfo images section
+    fn get_treeinfo_media(&self, os_type: &str) -> Vec<(String, String)> {
+        let Some(treeinfo) = &self.treeinfo else {
+            return vec![];
+        };
+        let image_type = if os_type == "xen" {
+            Some("xen")
+        } else {
+            treeinfo.get("general", "arch")
+        };
+        let section = format!("images-{}", image_type.unwrap_or_default());
+        match (
+            treeinfo.get(&section, "kernel"),
+            treeinfo.get(&section, "initrd"),
+        ) {
+            (Some(kernel), Some(initrd)) => vec![(kernel.to_string(), initrd.to_string())],
+            _ => {
+                log::debug!("Failed to parse treeinfo kernel/initrd");
+                vec![]
+            }
+        }
+    }
+
+    fn get_treeinfo_boot_iso(&self) -> Option<String> {
+        let treeinfo = self.treeinfo.as_ref()?;
+        let arch = treeinfo.get("general", "arch")?;
+        treeinfo
+            .get(&format!("images-{}", arch), "boot.iso")
+            .map(String::from)
+    }
+
+    /// Split a version like 6.9 or 7.4 in its two parts. centos
+    /// altarch's have just version=7
+    fn split_version(&self) -> (u32, i64) {
+        let verstr = self.treeinfo_version.as_deref().unwrap_or_default();
+        let mut parts = verstr.split('.');
+        let version = parts.next().and_then(|v| v.parse().ok()).unwrap_or(0);
+        let update = if verstr.contains('.') {
+            parts.next().and_then(|v| v.parse().ok()).unwrap_or(0)
+        } else {
+            0
+        };
+        log::debug!(
+            "converted verstr={} to version={} update={}",
+            verstr,
+            version,
+            update
+        );
+        (version, update)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Distro {
+    Fedora,
+    Rhel,
+    Centos,
+    Sles,
+    Sled,
+    Opensuse,
+    Debian,
+    Ubuntu,
+    Mageia,
+    GenericTreeinfo,
+}
+
+/// Probe order. Generic treeinfo is the catchall, so it stays last.
+const ALL_DISTROS: &[Distro] = &[
+    Distro::Fedora,
+    Distro::Rhel,
+    Distro::Centos,
+    Distro::Sles,
+    Distro::Sled,
+    Distro::Opensuse,
+    Distro::Debian,
+    Distro::Ubuntu,
+    Distro::Mageia,
+    Distro::GenericTreeinfo,
+];
+
+impl Distro {
+    fn pretty_name(self) -> &'static str {
+        match self {
+            Distro::Fedora => "Fedora",
+            Distro::Rhel => "Red Hat Enterprise Linux",
+            Distro::Centos => "CentOS",
+            Distro::Sles => "SLES",
+            Distro::Sled => "SLED",
+            Distro::Opensuse => "openSUSE",
+            Distro::Debian => "Debian",
+            Distro::Ubuntu => "Ubuntu",
+            Distro::Mageia => "Mageia",
+            Distro::GenericTreeinfo => "Generic Treeinfo",
+        }
+    }
+
+    /// Prefix of the OS variants, `rhel` for rhel7.6
+    fn variant_prefix(self) -> &'static str {
+        match self {
+            Distro::Rhel => "rhel",
+            Distro::Centos => "centos",
+            Distro::Sles => "sles",
+            Distro::Sled => "sled",
+            Distro::Opensuse => "opensuse",
+            Distro::Debian => "debian",
+            Distro::Ubuntu => "ubuntu",
+            _ => "",
+        }
+    }
+
+    fn is_suse(self) -> bool {
+        matches!(self, Distro::Sles | Distro::Sled | Distro::Opensuse)
+    }
+
+    fn is_valid(self, cache: &mut DistroCache) -> bool {
+        match self {
+            Distro::Fedora => cache.treeinfo_family_matches(&["Fedora"]),
+            // Also matches 'RHEL Atomic Host'
+            Distro::Rhel => cache.treeinfo_family_matches(&["Red Hat Enterprise Linux", "RHEL"]),
+            Distro::Centos => {
+                cache.treeinfo_family_matches(&["CentOS"])
+                    || cache.treeinfo_family_matches(&["Scientific"])
+            }
+            Distro::Sles | Distro::Sled | Distro::Opensuse => self.is_valid_suse(cache),
+            Distro::Debian | Distro::Ubuntu => self.is_valid_debian(cache),
+            Distro::Mageia => {
+                if cache.mageia_version.is_none() {
+                    // Like 'Mageia 5 Official-x86_64-Download 20180807 23:39'
+                    let content = cache.acquire_file_content("VERSION").unwrap_or_default();
+                    let version = content
+                        .strip_prefix("Mageia ")
+                        .and_then(|rest| rest.split_once(' '))
+                        .map(|(v, _)| v)
+                        .filter(|v| !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()));
+                    cache.mageia_version = version.map(String::from);
+                }
+                cache.mageia_version.is_some()
+            }
+            Distro::GenericTreeinfo => {
+                if cache.load_treeinfo() {
+                    cache.treeinfo_matched = true;
+                    return true;
+                }
+                false
+            }
+        }
+    }
+
+    fn is_valid_suse(self, cache: &mut DistroCache) -> bool {
+        let (family, products): (&str, &[&str]) = match self {
+            Distro::Sles => (
+                "SUSE Linux Enterprise",
+                &["SUSE Linux Enterprise Server", "SUSE SLES"],
+            ),
+            Distro::Sled => ("SUSE Linux Enterprise", &["SUSE Linux Enterprise Desktop"]),
+            _ => ("openSUSE", &["openSUSE"]),
+        };
+        if cache.treeinfo_family_matches(&[family]) {
+            return true;
+        }
+
+        if !cache.checked_for_suse_content {
+            cache.checked_for_suse_content = true;
+            match cache.acquire_file_content("content") {
+                Some(content) => cache.suse_content = Some(SuseContent::parse(&content)),
+                None => return false,
+            }
+        }
+        let Some(content) = &cache.suse_content else {
+            return false;
+        };
+        let name = content.product_name.as_deref().unwrap_or_default();
+        products.iter().any(|p| name.contains(p))
+    }
+
+    fn is_valid_debian(self, cache: &mut DistroCache) -> bool {
+        let is_ubuntu = self == Distro::Ubuntu;
+        let mut check_manifest = |mfile: &str| {
+            if cache.content_matches(mfile, |l| l.contains("ubuntu") || l.contains("Ubuntu")) {
+                return is_ubuntu;
+            }
+            cache.content_matches(mfile, |l| l.contains("debian") || l.contains("Debian"))
+        };
+
+        let media_type = if check_manifest("current/images/MANIFEST") {
+            Some("url")
+        } else if check_manifest("current/legacy-images/MANIFEST") {
+            Some("legacy_url")
+        } else if check_manifest("daily/MANIFEST") {
+            Some("daily")
+        } else if cache.content_matches(".disk/info", |l| {
+            l.starts_with(if is_ubuntu { "Ubuntu" } else { "Debian" })
+        }) {
+            // A directly accessed ISO is attached as a cdrom afterwards
+            // and its kernels expect that. An ISO mounted and exported
+            // over a URL needs the ones that boot from the network.
+            if cache.is_iso {
+                Some("disk")
+            } else {
+                Some("mounted_iso_url")
+            }
+        } else {
+            None
+        };
+
+        if media_type.is_some() {
+            cache.debian_media_type = media_type;
+        }
+        media_type.is_some()
+    }
+}
+
+/// The detection result: which distro the media is, and how to boot it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistroStore {
+    pretty_name: &'static str,
+    os_variant: Option<String>,
+    kernel_paths: Vec<(String, String)>,
+    boot_iso: Option<String>,
+}
+
+/// Builds a `DistroStore` for a distro that matched the media
+struct StoreBuilder<'a, 'b> {
+    distro: Distro,
+    location: &'b str,
+    arch: &'b str,
+    os_type: &'b str,
+    cache: &'b mut DistroCache<'a>,
+    catalog: &'b dyn OsCatalog,
+}
+
+impl StoreBuilder<'_, '_> {
+    fn build(mut self) -> DistroStore {
+        let mut os_variant = self.detect_version();
+        if let Some(variant) = &os_variant
+            && !self.catalog.lookup_os(variant)
+        {
+            log::debug!(
+                "Detected os_variant as {}, which is not in osdict.",
+                variant
+            );
+            os_variant = None;
+        }
+
+        let kernel_paths = if self.cache.treeinfo_matched {
+            self.cache.get_treeinfo_media(self.os_type)
+        } else {
+            self.manual_kernel_paths(os_variant.as_deref())
+        };
+
+        DistroStore {
+            pretty_name: self.distro.pretty_name(),
+            os_variant,
+            kernel_paths,
+            boot_iso: self.cache.get_treeinfo_boot_iso(),
+        }
+    }
+
+    fn detect_version(&mut self) -> Option<String> {
+        match self.distro {
+            Distro::Fedora => Some(self.detect_fedora_version()),
+            Distro::Rhel | Distro::Centos => {
+                if self.cache.treeinfo_version.is_none() {
+                    log::debug!("No treeinfo version? Not setting an os_variant");
+                    return None;
+                }
+                self.detect_treeinfo_update(true)
+            }
+            Distro::Sles | Distro::Sled | Distro::Opensuse => self
+                .detect_suse_from_treeinfo()
+                .or_else(|| self.detect_suse_from_url())
+                .or_else(|| self.detect_suse_from_content()),
+            Distro::Debian | Distro::Ubuntu => self.detect_debian_version(),
+            Distro::Mageia => Some(format!(
+                "mageia{}",
+                self.cache.mageia_version.as_deref().unwrap_or_default()
+            )),
+            Distro::GenericTreeinfo => {
+                log::debug!("Generic treeinfo does not implement any osdict detection");
+                None
+            }
+        }
+    }
+
+    fn detect_fedora_version(&self) -> String {
+        let latest_variant = "fedora-unknown".to_string();
+        let Some(verstr) = self.cache.treeinfo_version.as_deref() else {
+            log::debug!(
+                "No treeinfo version? Assume latest_variant={}",
+                latest_variant
+            );
+            return latest_variant;
+        };
+        // rawhide trees changed to use version=Rawhide in Apr 2016
+        if ["development", "rawhide", "Rawhide"].contains(&verstr) {
+            log::debug!(
+                "treeinfo version={}, using latest_variant={}",
+                verstr,
+                latest_variant
+            );
+            return latest_variant;
+        }
+        // treeinfo version is just an integer
+        let variant = format!("fedora{}", verstr);
+        if self.catalog.lookup_os(&variant) {
+            return variant;
+        }
+        log::debug!(
+            "variant={} from treeinfo version={} not found, using latest_variant={}",
+            variant,
+            verstr,
+            latest_variant
+        );
+        latest_variant
+    }
+
+    /// Start with like rhel7.6 and walk back through the updates to the
+    /// latest one the catalog knows, so rhel7.6 media is detected even
+    /// when the catalog only has rhel7.5. SLE doesn't use '.0' for its
+    /// initial releases, so `dot_zero` is false for it.
+    fn detect_treeinfo_update(&self, dot_zero: bool) -> Option<String> {
+        let (version, mut update) = self.cache.split_version();
+        let base = format!("{}{}", self.distro.variant_prefix(), version);
+        while update >= 0 {
+            let tryvar = if update > 0 || dot_zero {
+                format!("{}.{}", base, update)
+            } else {
+                base.clone()
+            };
+            if self.catalog.lookup_os(&tryvar) {
+                return Some(tryvar);
+            }
+            update -= 1;
+        }
+        None
+    }
+
+    fn detect_suse_from_treeinfo(&self) -> Option<String> {
+        let name = self.cache.treeinfo_name.as_deref()?;
+        if name.contains("openSUSE Tumbleweed") {
+            return Some("opensusetumbleweed".to_string());
+        }
+        self.detect_treeinfo_update(!self.distro.variant_prefix().starts_with("sle"))
+    }
+
+    fn detect_suse_from_url(&self) -> Option<String> {
+        let root = "opensuse";
+        self.catalog
+            .list_os()
+            .into_iter()
+            .filter(|os| os.name.starts_with(root))
+            .find(|os| {
+                let codename = &os.name[root.len()..];
+                self.location.contains(&format!("/{}/", codename))
+            })
+            .map(|os| os.name)
+    }
+
+    fn detect_suse_from_content(&self) -> Option<String> {
+        let distro_version = self
+            .cache
+            .suse_content
+            .as_ref()?
+            .product_version
+            .as_deref()
+            .filter(|v| !v.is_empty())?;
+        let prefix = self.distro.variant_prefix();
+        if prefix.starts_with("sles") || prefix.starts_with("sled") {
+            let (version, sp_version) = match distro_version.split_once('.') {
+                Some((version, sp)) => (version, format!("sp{}", sp.trim())),
+                None => (distro_version, String::new()),
+            };
+            return Some(format!("{}{}{}", prefix, version.trim(), sp_version));
+        }
+        Some(format!("{}{}", prefix, distro_version))
+    }
+
+    fn detect_debian_version(&mut self) -> Option<String> {
+        let debname = self.distro.variant_prefix();
+        let mut disk_info = None;
+        match self.cache.debian_media_type {
+            Some("daily") => {
+                log::debug!("Appears to be debian 'daily' URL, using latest debiantesting");
+                return Some("debiantesting".to_string());
+            }
+            Some("disk") => {
+                disk_info = self
+                    .cache
+                    .acquire_file_content(".disk/info")
+                    .map(|c| c.to_lowercase());
+            }
+            _ => {}
+        }
+
+        for os in self.catalog.list_os() {
+            if !os.name.starts_with(debname) {
+                continue;
+            }
+            // Ubuntu codenames look like 'Warty Warthog', Debian labels
+            // like 'Debian Sarge'
+            let codename = match &os.codename {
+                Some(codename) => codename.split_whitespace().next(),
+                None => os.label.split_whitespace().nth(1),
+            };
+            let Some(codename) = codename.map(str::to_lowercase) else {
+                continue;
+            };
+
+            if self.location.contains(&format!("/{}/", codename)) {
+                log::debug!("Found codename={} in the URL string", codename);
+                return Some(os.name);
+            }
+            if let Some(info) = &disk_info
+                && info.contains(&format!("\"{}\"", codename))
+            {
+                log::debug!("Found codename={} in the disk/info file", codename);
+                return Some(os.name);
+            }
+        }
+        None
+    }
+
+    fn manual_kernel_paths(&self, os_variant: Option<&str>) -> Vec<(String, String)> {
+        match self.distro {
+            d if d.is_suse() => self.suse_kernel_paths(os_variant),
+            Distro::Debian | Distro::Ubuntu => {
+                if self.cache.debian_media_type == Some("disk") {
+                    vec![self.debian_installcd_paths()]
+                } else {
+                    self.debian_url_paths()
+                }
+            }
+            Distro::Mageia => vec![(
+                format!("isolinux/{}/vmlinuz", self.arch),
+                format!("isolinux/{}/all.rdz", self.arch),
+            )],
+            _ => vec![],
+        }
+    }
+
+    fn suse_kernel_paths(&self, os_variant: Option<&str>) -> Vec<(String, String)> {
+        let mut paths = vec![];
+        let mut tree_arch = self
+            .cache
+            .suse_content
+            .as_ref()
+            .and_then(|c| c.tree_arch.clone())
+            .unwrap_or_default();
+        let bytes = tree_arch.as_bytes();
+        if bytes.len() >= 4
+            && bytes[0] == b'i'
+            && (b'4'..=b'9').contains(&bytes[1])
+            && &bytes[2..4] == b"86"
+        {
+            tree_arch = "i386".to_string();
+        }
+
+        let (mut oldkern, mut oldinit) = ("linux".to_string(), "initrd".to_string());
+        if tree_arch == "x86_64" {
+            oldkern += "64";
+            oldinit += "64";
+        }
+
+        if self.os_type == "xen" {
+            // Matches Opensuse > 10.2 and sles 10
+            paths.push((
+                format!("boot/{}/vmlinuz-xen", tree_arch),
+                format!("boot/{}/initrd-xen", tree_arch),
+            ));
+        }
+
+        let variant = os_variant.unwrap_or_default();
+        if variant.starts_with("sles11") || variant.starts_with("sled11") {
+            if tree_arch == "s390x" {
+                paths.push((
+                    "boot/s390x/vmrdr.ikr".to_string(),
+                    "boot/s390x/initrd".to_string(),
+                ));
+            }
+            if tree_arch == "ppc64" {
+                paths.push((
+                    "suseboot/linux64".to_string(),
+                    "suseboot/initrd64".to_string(),
+                ));
+            }
+        }
+
+        // Tested with SLES 12 for ppc64le, all s390x
+        paths.push((
+            format!("boot/{}/linux", tree_arch),
+            format!("boot/{}/initrd", tree_arch),
+        ));
+        // Tested with Opensuse 10.0
+        paths.push((
+            format!("boot/loader/{}", oldkern),
+            format!("boot/loader/{}", oldinit),
+        ));
+        // Tested with Opensuse >= 10.2, 11, and sles 10
+        paths.push((
+            format!("boot/{}/loader/linux", tree_arch),
+            format!("boot/{}/loader/initrd", tree_arch),
+        ));
+        paths
+    }
+
+    /// The debian arch from a URL like .../installer-amd64/ or
+    /// .../daily-images/amd64/, or an arch name anywhere in an ISO path
+    fn find_debian_treearch(&self) -> Option<String> {
+        let is_word = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_');
+        let mut parts = self
+            .location
+            .strip_suffix('/')
+            .unwrap_or(self.location)
+            .rsplit('/');
+        let last = parts.next().unwrap_or_default();
+        let parent = parts.next();
+        if let Some(arch) = last.strip_prefix("installer-")
+            && is_word(arch)
+            && parent.is_some()
+        {
+            log::debug!("Found treearch={} in uri", arch);
+            return Some(arch.to_string());
+        }
+        if parent == Some("daily-images") && is_word(last) {
+            log::debug!("Found treearch={} in uri", last);
+            return Some(last.to_string());
+        }
+
+        let arch = ["i386", "amd64", "arm64", "riscv64", "s390x", "ppc64el"]
+            .into_iter()
+            .find(|arch| self.location.contains(arch))?;
+        log::debug!("Found treearch={} in uri", arch);
+        Some(arch.to_string())
+    }
+
+    fn debian_url_paths(&self) -> Vec<(String, String)> {
+        let debname = self.distro.variant_prefix();
+        let url_prefix = match self.cache.debian_media_type {
+            Some("daily") => "daily",
+            Some("mounted_iso_url") => "install",
+            Some("legacy_url") => "current/legacy-images",
+            _ => "current/images",
+        };
+        let tree_arch = self.find_debian_treearch().unwrap_or_else(|| {
+            log::debug!("No treearch found in uri, defaulting to arch=i386");
+            "i386".to_string()
+        });
+
+        let mut hvmroot = format!(
+            "{}/netboot/{}-installer/{}/",
+            url_prefix, debname, tree_arch
+        );
+        let mut initrd_basename = "initrd.gz".to_string();
+        let mut kernel_basename = "linux".to_string();
+        if tree_arch == "ppc64el" {
+            kernel_basename = "vmlinux".to_string();
+        }
+        if tree_arch == "s390x" {
+            hvmroot = format!("{}/generic/", url_prefix);
+            kernel_basename = format!("kernel.{}", debname);
+            initrd_basename = format!("initrd.{}", debname);
+        }
+
+        let mut paths = vec![];
+        if self.os_type == "xen" {
+            let xenroot = format!("{}/netboot/xen/", url_prefix);
+            paths.push((
+                format!("{}vmlinuz", xenroot),
+                format!("{}initrd.gz", xenroot),
+            ));
+        }
+        paths.push((
+            format!("{}{}", hvmroot, kernel_basename),
+            format!("{}{}", hvmroot, initrd_basename),
+        ));
+        paths
+    }
+
+    fn debian_installcd_paths(&self) -> (String, String) {
+        let tree_arch = self.find_debian_treearch();
+        let (kernel, initrd) = match (self.distro, tree_arch.as_deref()) {
+            (Distro::Ubuntu, Some("s390x")) => ("boot/kernel.ubuntu", "boot/initrd.ubuntu"),
+            (Distro::Ubuntu, _) => ("install/vmlinuz", "install/initrd.gz"),
+            (_, Some("amd64")) => ("install.amd/vmlinuz", "install.amd/initrd.gz"),
+            (_, Some("i386")) => ("install.386/vmlinuz", "install.386/initrd.gz"),
+            (_, Some("arm64")) => ("install.a64/vmlinuz", "install.a64/initrd.gz"),
+            (_, Some("ppc64el")) => ("install/vmlinux", "install/initrd.gz"),
+            (_, Some("s390x")) => ("boot/linux_vm", "boot/root.bin"),
+            _ => ("install/vmlinuz", "install/initrd.gz"),
+        };
+        (kernel.to_string(), initrd.to_string())
+    }
+}
+
+impl DistroStore {
+    /// Like `Fedora` or `Generic Treeinfo`
+    pub fn pretty_name(&self) -> &str {
+        self.pretty_name
+    }
+
+    /// The detected OS variant, like `fedora30`
+    pub fn get_osdict_info(&self) -> Option<&str> {
+        self.os_variant.as_deref()
+    }
+
+    /// (kernel, initrd) candidates relative to the media root, best first
+    pub fn get_kernel_paths(&self) -> &[(String, String)] {
+        &self.kernel_paths
+    }
+
+    /// The boot.iso listed in the treeinfo, if any
+    pub fn get_boot_iso(&self) -> Option<&str> {
+        self.boot_iso.as_deref()
+    }
+}
+
+fn detect(
+    mut cache: DistroCache,
+    arch: &str,
+    os_type: &str,
+    catalog: &dyn OsCatalog,
+) -> Result<DistroStore, String> {
+    let location = cache.media.location().to_string();
+    log::debug!("Finding distro store for location={}", location);
+
+    for distro in ALL_DISTROS {
+        if !distro.is_valid(&mut cache) {
+            continue;
+        }
+        let store = StoreBuilder {
+            distro: *distro,
+            location: &location,
+            arch,
+            os_type,
+            cache: &mut cache,
+            catalog,
+        }
+        .build();
+        log::debug!(
+            "Detected distro={} osvariant={:?}",
+            store.pretty_name,
+            store.os_variant
+        );
+        return Ok(store);
+    }
+
+    // Give the user a hint that they maybe mistyped
+    let mut msg = format!(
+        "Could not find an installable distribution at URL '{}'",
+        location
+    );
+    if !cache.media.can_access() {
+        msg += ": The URL could not be accessed, maybe you mistyped?";
+    }
+    msg += "\n\nThe location must be the root directory of an install tree.\n\
+            See virt-install man page for various distro examples.";
+    Err(msg)
+}
+
+/// Detect the distro on `media` for a guest of `arch` and `os_type`
+/// (`hvm` or `xen`)
+pub fn detect_distro(
+    media: &MediaSource,
+    arch: &str,
+    os_type: &str,
+    catalog: &dyn OsCatalog,
+) -> Result<DistroStore, String> {
+    detect(DistroCache::new(media), arch, os_type, catalog)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The osinfo-db entries the upstream urldetect tests rely on
+    struct TestCatalog(Vec<CatalogOs>);
+
+    impl TestCatalog {
+        fn new() -> Self {
+            let mut oses = vec![];
+            for (name, codename, label) in [
+                ("debian8", None, "Debian Jessie"),
+                ("debian10", None, "Debian Buster"),
+                ("debiantesting", None, "Debian Testing"),
+                ("ubuntu18.04", Some("Bionic Beaver"), "Ubuntu 18.04 LTS"),
+                ("ubuntu20.04", Some("Focal Fossa"), "Ubuntu 20.04 LTS"),
+            ] {
+                oses.push(CatalogOs {
+                    name: name.to_string(),
+                    codename: codename.map(String::from),
+                    label: label.to_string(),
+                });
+            }
+            for name in [
+                "fedora17",
+                "fedora30",
+                "fedora-unknown",
+                "rhel7.6",
+                "rhel7.9",
+                "centos6.10",
+                "centos7.0",
+                "opensusetumbleweed",
+                "opensuse10.3",
+                "opensuse11.4",
+                "opensuse12.3",
+                "opensuse13.2",
+                "opensuse42.3",
+                "opensuse15.0",
+                "opensuse15.2",
+                "sles10sp4",
+                "sles11sp4",
+                "mageia5",
+            ] {
+                oses.push(CatalogOs {
+                    name: name.to_string(),
+                    codename: None,
+                    label: name.to_string(),
+                });
+            }
+            Self(oses)
+        }
+    }
+
+    impl OsCatalog for TestCatalog {
+        fn lookup_os(&self, name: &str) -> bool {
+            self.0.iter().any(|os| os.name == name)
+        }
+        fn list_os(&self) -> Vec<CatalogOs> {
+            self.0.clone()
+        }
+    }
+
+    fn datadir(sub: &str) -> String {
+        format!("{}/../tests/data/{}", env!("CARGO_MANIFEST_DIR"), sub)
+    }
+
+    /// Mirrors tests/test_urldetect.py
+    #[track_caller]
+    fn check(
+        path: &str,
+        distro: Option<&str>,
+        kernel: Option<&str>,
+        initrd: Option<&str>,
+        xen: bool,
+        iso: bool,
+    ) {
+        let media = MediaSource::open(&datadir(&format!("urldetect/{}", path))).unwrap();
+        let mut cache = DistroCache::new(&media);
+        cache.is_iso = iso;
+        let os_type = if xen { "xen" } else { "hvm" };
+        let store = detect(cache, "x86_64", os_type, &TestCatalog::new()).unwrap();
+        let detected = store.get_osdict_info().unwrap_or_default();
+        assert!(
+            detected.contains(distro.unwrap_or_default()),
+            "{}: detected {:?}",
+            path,
+            detected
+        );
+        assert_eq!(
+            distro.is_none(),
+            store.get_osdict_info().is_none(),
+            "{}",
+            path
+        );
+        let paths = store.get_kernel_paths();
+        if let Some(kernel) = kernel {
+            assert!(
+                paths.iter().any(|(k, _)| k.ends_with(kernel)),
+                "{}: {:?}",
+                path,
+                paths
+            );
+        }
+        if let Some(initrd) = initrd {
+            assert!(
+                paths.iter().any(|(_, i)| i.ends_with(initrd)),
+                "{}: {:?}",
+                path,
+                paths
+            );
+        }
+    }
+
+    #[test]
+    fn test_urldetect_trees() {
+        let deb = "debian/buster/main/installer-amd64";
+        check(deb, Some("debian10"), Some("linux"), None, false, false);
+        check(
+            deb,
+            Some("debian10"),
+            Some("netboot/xen/vmlinuz"),
+            None,
+            true,
+            false,
+        );
+        let s390 = "debian/buster/main/installer-s390x";
+        check(
+            s390,
+            Some("debian10"),
+            Some("kernel.debian"),
+            None,
+            false,
+            false,
+        );
+        let ppc = "debian/buster/main/installer-ppc64el";
+        check(ppc, Some("debian10"), Some("vmlinux"), None, false, false);
+        check(
+            "debian/daily-images/amd64",
+            Some("debiantesting"),
+            None,
+            None,
+            false,
+            false,
+        );
+        for (arch, kernel) in [
+            ("amd64", "install.amd/vmlinuz"),
+            ("s390x", "linux_vm"),
+            ("ppc64el", "vmlinux"),
+            ("i386", "install.386/vmlinuz"),
+            ("arm64", "install.a64/vmlinuz"),
+            ("badarch", "install/vmlinuz"),
+        ] {
+            let path = format!("debian/debian-8.10.0-{}-netinst.iso", arch);
+            check(&path, Some("debian8"), Some(kernel), None, false, true);
+        }
+        // Exported over a URL instead, which uses the netboot kernels
+        let path = "debian/debian-8.10.0-badarch-netinst.iso";
+        check(path, None, Some("linux"), None, false, false);
+
+        let ubuntu = "ubuntu/bionic/main/installer-amd64";
+        check(ubuntu, Some("ubuntu18.04"), None, None, false, false);
+        let ubuntu = "ubuntu/focal/main/installer-amd64";
+        check(ubuntu, Some("ubuntu20.04"), None, None, false, false);
+        let ubuntu = "ubuntu/ubuntu-17.10-amd64.iso";
+        check(ubuntu, None, Some("install/vmlinuz"), None, false, true);
+        let ubuntu = "ubuntu/ubuntu-17.10-s390x.iso";
+        check(ubuntu, None, Some("boot/kernel.ubuntu"), None, false, true);
+
+        check("fedora/30", Some("fedora30"), None, None, false, false);
+        check(
+            "fedora/rawhide",
+            Some("fedora-unknown"),
+            None,
+            None,
+            false,
+            false,
+        );
+        check(
+            "fedora/99",
+            Some("fedora-unknown"),
+            None,
+            None,
+            false,
+            false,
+        );
+        check(
+            "rhel/7.6",
+            Some("rhel7.6"),
+            Some("images/pxeboot/vmlinuz"),
+            None,
+            false,
+            false,
+        );
+        check("rhel/7.20", Some("rhel7."), None, None, false, false);
+        check("centos/6.10", Some("centos6.10"), None, None, false, false);
+        check("centos/sl7", Some("centos7.0"), None, None, false, false);
+
+        let tw = "opensuse/tumbleweed";
+        check(tw, Some("opensusetumbleweed"), None, None, false, false);
+        check(
+            "opensuse/10.3",
+            Some("opensuse10.3"),
+            None,
+            None,
+            false,
+            false,
+        );
+        check(
+            "opensuse/11.4",
+            Some("opensuse11.4"),
+            None,
+            None,
+            false,
+            false,
+        );
+        check(
+            "opensuse/12.3",
+            Some("opensuse12.3"),
+            None,
+            None,
+            false,
+            false,
+        );
+        check(
+            "opensuse/13.2",
+            Some("opensuse13.2"),
+            None,
+            Some("initrd-xen"),
+            true,
+            false,
+        );
+        check(
+            "opensuse/42.3/",
+            Some("opensuse42.3"),
+            None,
+            None,
+            false,
+            false,
+        );
+        check(
+            "opensuse/15.9",
+            Some("opensuse15"),
+            None,
+            None,
+            false,
+            false,
+        );
+        check("opensuse/badversion/", None, None, None, false, false);
+
+        let sles = "suse/SLES-10-SP4-DVD-x86_64-GM-DVD1.iso";
+        check(sles, Some("sles10sp4"), None, None, false, false);
+        let sles = "suse/SLES-11-SP4-DVD-s390x-GM-DVD1.iso";
+        check(
+            sles,
+            Some("sles11sp4"),
+            Some("vmrdr.ikr"),
+            None,
+            false,
+            false,
+        );
+        let sles = "suse/SLES-11-SP4-DVD-ppc64-GM-DVD1.iso";
+        check(sles, Some("sles11sp4"), Some("linux64"), None, false, false);
+
+        check(
+            "mageia/5",
+            Some("mageia5"),
+            None,
+            Some("all.rdz"),
+            false,
+            false,
+        );
+        check("mageia/8", None, None, Some("all.rdz"), false, false);
+        check(
+            "generic",
+            None,
+            Some("images/pxeboot/vmlinuz"),
+            None,
+            false,
+            false,
+        );
+
+        let media = MediaSource::open(&datadir("urldetect/empty")).unwrap();
+        let err = detect_distro(&media, "x86_64", "hvm", &TestCatalog::new()).unwrap_err();
+        assert!(err.contains("installable distribution"));
+        assert!(err.contains("mistyped"));
+    }
+
+    #[test]
+    fn test_fakemedia() {
+        let catalog = TestCatalog::new();
+        let media = MediaSource::open(&datadir("fakemedia/fake-fedora17-tree.iso")).unwrap();
+        assert!(media.is_iso());
+        assert_eq!(media.volume_id(), Some("CDROM"));
+        let store = detect_distro(&media, "x86_64", "hvm", &catalog).unwrap();
+        assert_eq!(store.pretty_name(), "Fedora");
+        assert_eq!(store.get_osdict_info(), Some("fedora17"));
+        assert_eq!(
+            store.get_kernel_paths(),
+            [(
+                "images/pxeboot/vmlinuz".to_string(),
+                "images/pxeboot/initrd.img".to_string()
+            )]
+        );
+        assert_eq!(store.get_boot_iso(), Some("images/boot.iso"));
+        let (kernel, _) = &store.get_kernel_paths()[0];
+        assert!(media.read_file(kernel).is_ok());
+
+        let media = MediaSource::open(&datadir("fakemedia/fakerhel6tree")).unwrap();
+        assert!(!media.is_iso());
+        let store = detect_distro(&media, "x86_64", "xen", &catalog).unwrap();
+        assert_eq!(store.pretty_name(), "Red Hat Enterprise Linux");
+        let (kernel, initrd) = &store.get_kernel_paths()[0];
+        assert!(media.has_file(kernel) && media.has_file(initrd));
+
+        // Nothing in the ISO itself identifies it, that's left to osinfo
+        let media = MediaSource::open(&datadir("fakemedia/fake-no-osinfo.iso")).unwrap();
+        let err = detect_distro(&media, "x86_64", "hvm", &catalog).unwrap_err();
+        assert!(!err.contains("mistyped"));
+    }
+}
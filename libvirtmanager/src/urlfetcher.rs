@@ -0,0 +1,220 @@
+// Fetching install files from local paths and URLs (port of
+// virtinst/install/urlfetcher.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Files are always copied into the scratch directory, so callers can
+//! treat the result as a temporary file they own. Network URLs are
+//! downloaded with curl.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::progress::Meter;
+use crate::tmpfile;
+
+const BLOCK_SIZE: usize = 16 * 1024;
+
+pub fn is_network_url(url: &str) -> bool {
+    ["http://", "https://", "ftp://"]
+        .iter()
+        .any(|p| url.starts_with(p))
+}
+
+/// Copy everything from `src` to `dst`, reporting the running total
+fn write_all(src: &mut dyn Read, dst: &mut File, meter: &dyn Meter) -> Result<u64, String> {
+    let mut buf = vec![0u8; BLOCK_SIZE];
+    let mut total = 0;
+    loop {
+        let count = src.read(&mut buf).map_err(|e| e.to_string())?;
+        if count == 0 {
+            break;
+        }
+        dst.write_all(&buf[..count]).map_err(|e| e.to_string())?;
+        total += count as u64;
+        meter.update(total);
+    }
+    dst.flush().map_err(|e| e.to_string())?;
+    Ok(total)
+}
+
+/// Downloads network URLs
+pub trait Fetcher: Send + Sync {
+    /// Copy `url` into `dst`, starting `meter` with `msg`
+    fn grab(&self, url: &str, dst: &mut File, meter: &dyn Meter, msg: &str) -> Result<(), String>;
+}
+
+/// The default `Fetcher`, using curl
+pub struct CurlFetcher;
+
+impl Fetcher for CurlFetcher {
+    fn grab(&self, url: &str, dst: &mut File, meter: &dyn Meter, msg: &str) -> Result<(), String> {
+        let mut child = Command::new("curl")
+            .args(["--fail", "--silent", "--show-error", "--location", url])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Couldn't acquire file {}: {}", url, e))?;
+        log::debug!("Fetching URI: {}", url);
+        meter.start(msg, None);
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let ret = write_all(&mut stdout, dst, meter);
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            let err = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("Couldn't acquire file {}: {}", url, err.trim()));
+        }
+        ret.map(|_| ())
+    }
+}
+
+/// Tests have no network, so like tests/urlfetcher_mock.py this serves
+/// a local file for every network URL
+#[cfg(test)]
+pub(crate) struct MockFetcher;
+
+#[cfg(test)]
+impl Fetcher for MockFetcher {
+    fn grab(&self, url: &str, dst: &mut File, meter: &dyn Meter, msg: &str) -> Result<(), String> {
+        let path = format!("{}/../tests/urlfetcher_mock.py", env!("CARGO_MANIFEST_DIR"));
+        grab_path(url, &path, dst, meter, msg)
+    }
+}
+
+/// Copy the local file at `path` for `url` into `dst`
+fn grab_path(
+    url: &str,
+    path: &str,
+    dst: &mut File,
+    meter: &dyn Meter,
+    msg: &str,
+) -> Result<(), String> {
+    let mut src = File::open(path).map_err(|e| format!("Couldn't acquire file {}: {}", url, e))?;
+    let size = src.metadata().ok().map(|m| m.len());
+    log::debug!("Fetching URI: {}", url);
+    meter.start(msg, size);
+    write_all(&mut src, dst, meter).map(|_| ())
+}
+
+fn grab_url(
+    url: &str,
+    dst: &mut File,
+    meter: &dyn Meter,
+    fetcher: &dyn Fetcher,
+) -> Result<(), String> {
+    let basename = url.trim_end_matches('/').rsplit('/').next().unwrap_or(url);
+    let msg = format!("Retrieving '{}'", basename);
+    if is_network_url(url) {
+        fetcher.grab(url, dst, meter, &msg)?;
+    } else {
+        let path = url.strip_prefix("file://").unwrap_or(url);
+        grab_path(url, path, dst, meter, &msg)?;
+    }
+    meter.end();
+    Ok(())
+}
+
+/// Whether a network `url` can be fetched, without downloading it
+pub fn url_exists(url: &str) -> bool {
+    let ret = Command::new("curl")
+        .args(["--fail", "--silent", "--head", "--location", url])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    log::debug!("HTTP hasFile request: {} exists={}", url, ret);
+    ret
+}
+
+/// Contents of a network `url`
+pub fn acquire_url_content(url: &str) -> Result<Vec<u8>, String> {
+    log::debug!("Fetching URI: {}", url);
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", url])
+        .output()
+        .map_err(|e| format!("Couldn't acquire file {}: {}", url, e))?;
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Couldn't acquire file {}: {}", url, err.trim()));
+    }
+    Ok(output.stdout)
+}
+
+/// Fetch `url`, a local path, `file://` URL or http/https/ftp URL, to a
+/// new temporary file in `scratchdir` and return its path. Network URLs
+/// go through `fetcher`.
+pub fn acquire_file(
+    url: &str,
+    scratchdir: &Path,
+    meter: &dyn Meter,
+    fetcher: &dyn Fetcher,
+) -> Result<PathBuf, String> {
+    if url.contains("://") && !is_network_url(url) && !url.starts_with("file://") {
+        let scheme = url.split("://").next().unwrap_or_default();
+        return Err(format!(
+            "Couldn't acquire file {}: No connection adapters were found for scheme '{}'",
+            url, scheme
+        ));
+    }
+    let basename = url.trim_end_matches('/').rsplit('/').next().unwrap_or(url);
+    let (mut file, path) = tmpfile::mkstemp(scratchdir, "virtinst-", &format!("-{}", basename))
+        .map_err(|e| format!("Couldn't acquire file {}: {}", url, e))?;
+    if let Err(e) = grab_url(url, &mut file, meter, fetcher) {
+        let _ = std::fs::remove_file(&path);
+        return Err(e);
+    }
+    log::debug!("Saved file to {}", path.display());
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NullMeter;
+
+    #[test]
+    fn test_acquire_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("user-data.txt");
+        std::fs::write(&src, "#cloud-config\n").unwrap();
+        let scratch = tempfile::tempdir().unwrap();
+
+        for url in [
+            src.display().to_string(),
+            format!("file://{}", src.display()),
+        ] {
+            let path = acquire_file(&url, scratch.path(), &NullMeter, &CurlFetcher).unwrap();
+            assert!(path.starts_with(scratch.path()));
+            assert!(path.to_string_lossy().ends_with("-user-data.txt"));
+            assert_eq!(std::fs::read_to_string(path).unwrap(), "#cloud-config\n");
+        }
+
+        let err = acquire_file(
+            "badurl://example.com",
+            scratch.path(),
+            &NullMeter,
+            &CurlFetcher,
+        )
+        .unwrap_err();
+        assert!(err.starts_with("Couldn't acquire file badurl://example.com"));
+        let err =
+            acquire_file("/nonexistent/foo", scratch.path(), &NullMeter, &CurlFetcher).unwrap_err();
+        assert!(err.starts_with("Couldn't acquire file /nonexistent/foo"));
+        assert_eq!(std::fs::read_dir(scratch.path()).unwrap().count(), 2);
+
+        // Network URLs go to the fetcher
+        let path = acquire_file(
+            "https://example.com/user-data",
+            scratch.path(),
+            &NullMeter,
+            &MockFetcher,
+        )
+        .unwrap();
+        assert!(path.to_string_lossy().ends_with("-user-data"));
+        let mock = format!("{}/../tests/urlfetcher_mock.py", env!("CARGO_MANIFEST_DIR"));
+        assert_eq!(std::fs::read(path).unwrap(), std::fs::read(mock).unwrap());
+    }
+}
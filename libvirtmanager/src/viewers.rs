@@ -0,0 +1,935 @@
+// Graphical console viewers (Iced port of virtManager/details/viewers.py,
+// with ConnectionInfo from virtManager/details/sshtunnels.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! `ConnectionInfo` works out where a VM's `<graphics>` listens. A
+//! `Viewer` runs the VNC or SPICE protocol on a thread, forwarding what the
+//! server says as `ViewerEvent`s through an iced subscription. Once
+//! connected, the `Connected` event hands over a `DisplayHandle` sharing
+//! the framebuffer and taking input. The `display` widget draws that
+//! framebuffer and turns keyboard and mouse events into `DisplayInput`.
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, TcpStream};
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::widget::{self, Widget, tree};
+use iced::advanced::{Clipboard, Shell, renderer};
+use iced::futures::channel::mpsc::{UnboundedReceiver, UnboundedSender, unbounded};
+use iced::futures::{StreamExt, future, stream};
+use iced::keyboard::key::Named;
+use iced::keyboard::{self, Key, Location};
+use iced::{Color, Element, Event, Length, Point, Rectangle, Size, Subscription, event, mouse};
+use log::debug;
+
+use crate::connection::Connection;
+use crate::framebuffer::{Framebuffer, draw_framebuffer};
+use crate::rfb::{RfbClient, RfbEvent, RfbSender, keysym_from_char, keysym_from_name};
+use crate::spice::{Opener, SpiceClient, SpiceEvent, SpiceSender};
+use crate::sshtunnels::SshTunnels;
+use crate::uri::Uri;
+use crate::xmlapi::Element as XmlElement;
+
+/// Key combinations offered by the Send Key menu, as keysym names
+pub const KEY_COMBOS: &[(&str, &[&str])] = &[
+    ("Ctrl+Alt+Backspace", &["Control_L", "Alt_L", "BackSpace"]),
+    ("Ctrl+Alt+Delete", &["Control_L", "Alt_L", "Delete"]),
+    (
+        "Ctrl+Alt+Shift+Escape",
+        &["Control_L", "Alt_L", "Shift_L", "Escape"],
+    ),
+    ("Ctrl+Alt+F1", &["Control_L", "Alt_L", "F1"]),
+    ("Ctrl+Alt+F2", &["Control_L", "Alt_L", "F2"]),
+    ("Ctrl+Alt+F3", &["Control_L", "Alt_L", "F3"]),
+    ("Ctrl+Alt+F4", &["Control_L", "Alt_L", "F4"]),
+    ("Ctrl+Alt+F5", &["Control_L", "Alt_L", "F5"]),
+    ("Ctrl+Alt+F6", &["Control_L", "Alt_L", "F6"]),
+    ("Ctrl+Alt+F7", &["Control_L", "Alt_L", "F7"]),
+    ("Ctrl+Alt+F8", &["Control_L", "Alt_L", "F8"]),
+    ("Ctrl+Alt+F9", &["Control_L", "Alt_L", "F9"]),
+    ("Ctrl+Alt+F10", &["Control_L", "Alt_L", "F10"]),
+    ("Ctrl+Alt+F11", &["Control_L", "Alt_L", "F11"]),
+    ("Ctrl+Alt+F12", &["Control_L", "Alt_L", "F12"]),
+    ("Print Screen", &["Print"]),
+];
+
+/// Where to reach one `<graphics>` device of a VM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub gtype: String,
+    /// Index of the device among the VM's graphics devices
+    pub gidx: usize,
+    pub gport: Option<String>,
+    pub gsocket: Option<String>,
+    pub gaddr: String,
+    pub gtlsport: Option<String>,
+    pub glistentype: Option<String>,
+    pub transport: String,
+    pub connuser: String,
+    /// SSH identity file, from the URI's `keyfile` parameter
+    pub connkeyfile: Option<String>,
+    connhost: String,
+    connport: String,
+}
+
+fn is_loopback(host: &str) -> bool {
+    host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+impl ConnectionInfo {
+    /// Read a graphics device from the live XML of a VM on `uri`
+    pub fn new(uri: &str, gdev: &XmlElement, gidx: usize) -> Self {
+        let uri = Uri::parse(uri);
+        // Inactive or not yet allocated ports are -1
+        let port = |xpath| {
+            gdev.get(xpath)
+                .filter(|p| p.parse::<i32>().is_ok_and(|p| p > 0))
+        };
+        let mut connhost = uri.hostname;
+        if connhost.is_empty() || connhost == "localhost" {
+            connhost = "127.0.0.1".to_string();
+        }
+        Self {
+            gtype: gdev.get("./@type").unwrap_or_default(),
+            gidx,
+            gport: port("./@port"),
+            gsocket: gdev
+                .get("./listen/@socket")
+                .or_else(|| gdev.get("./@socket")),
+            gaddr: gdev
+                .get("./@listen")
+                .unwrap_or_else(|| "127.0.0.1".to_string()),
+            gtlsport: port("./@tlsPort"),
+            glistentype: gdev.get("./listen/@type"),
+            transport: uri.transport,
+            connuser: uri.username,
+            connkeyfile: uri
+                .query
+                .split('&')
+                .find_map(|p| p.strip_prefix("keyfile="))
+                .map(String::from),
+            connhost,
+            connport: uri.port,
+        }
+    }
+
+    fn is_listen_localhost(&self) -> bool {
+        is_loopback(&self.gaddr)
+    }
+
+    fn is_listen_any(&self) -> bool {
+        self.gaddr
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_unspecified())
+    }
+
+    fn is_listen_none(&self) -> bool {
+        self.glistentype.as_deref() == Some("none")
+            || (self.gsocket.is_none() && self.gport.is_none() && self.gtlsport.is_none())
+    }
+
+    pub fn need_tunnel(&self) -> bool {
+        self.is_listen_localhost() && self.transport == "ssh"
+    }
+
+    /// Why the console can't be reached from here, if it can't
+    pub fn bad_config(&self) -> Option<String> {
+        if !self.transport.is_empty() && self.is_listen_none() {
+            return Some(
+                "Guest is on a remote host, but is only configured to allow local file \
+                 descriptor connections."
+                    .to_string(),
+            );
+        }
+        if self.need_tunnel() && self.gtlsport.is_some() && self.gport.is_none() {
+            return Some("Guest is configured for TLS only which does not work over SSH.".into());
+        }
+        if !self.need_tunnel()
+            && !self.transport.is_empty()
+            && self.is_listen_localhost()
+            && !is_loopback(&self.connhost)
+        {
+            return Some(format!(
+                "Guest is on a remote host with transport '{}' but is only configured to \
+                 listen locally. To connect remotely you will need to change the guest's \
+                 listen address.",
+                self.transport
+            ));
+        }
+        None
+    }
+
+    /// Host, port and TLS port for a direct connection
+    pub fn get_conn_host(&self) -> (String, Option<String>, Option<String>) {
+        let host = if self.is_listen_any() {
+            self.connhost.clone()
+        } else {
+            self.gaddr.clone()
+        };
+        (host, self.gport.clone(), self.gtlsport.clone())
+    }
+
+    /// Host and port of the hypervisor, for an SSH tunnel to it
+    pub fn get_tunnel_host(&self) -> (String, String) {
+        (self.connhost.clone(), self.connport.clone())
+    }
+
+    pub fn logstring(&self) -> String {
+        format!(
+            "proto={} trans={} connhost={} connuser={} connport={} gaddr={} gport={:?} \
+             gtlsport={:?} gsocket={:?}",
+            self.gtype,
+            self.transport,
+            self.connhost,
+            self.connuser,
+            self.connport,
+            self.gaddr,
+            self.gport,
+            self.gtlsport,
+            self.gsocket
+        )
+    }
+}
+
+/// Shuts a stream down from another thread
+pub type Closer = Box<dyn Fn() + Send + Sync>;
+
+/// Byte stream to a display server: its two directions and a way to shut
+/// it down from another thread
+pub struct ViewerStream {
+    pub reader: Box<dyn Read + Send>,
+    pub writer: Box<dyn Write + Send>,
+    pub close: Closer,
+}
+
+impl ViewerStream {
+    pub fn tcp(host: &str, port: &str) -> Result<Self, String> {
+        let addr = format!("{}:{}", host, port);
+        let stream = TcpStream::connect(&addr)
+            .map_err(|e| format!("Error connecting to {}: {}", addr, e))?;
+        let _ = stream.set_nodelay(true);
+        let err = |e: std::io::Error| format!("Error connecting to {}: {}", addr, e);
+        let writer = stream.try_clone().map_err(err)?;
+        let closer = stream.try_clone().map_err(err)?;
+        Ok(Self {
+            reader: Box::new(stream),
+            writer: Box::new(writer),
+            close: Box::new(move || {
+                let _ = closer.shutdown(std::net::Shutdown::Both);
+            }),
+        })
+    }
+
+    pub fn unix(path: &str) -> Result<Self, String> {
+        let err = |e: std::io::Error| format!("Error connecting to {}: {}", path, e);
+        UnixStream::connect(path)
+            .and_then(Self::from_unix)
+            .map_err(err)
+    }
+
+    /// Use a socket libvirt connected to the display for us
+    pub fn fd(fd: OwnedFd) -> Result<Self, String> {
+        Self::from_unix(UnixStream::from(fd))
+            .map_err(|e| format!("Error using the graphics connection: {}", e))
+    }
+
+    fn from_unix(stream: UnixStream) -> std::io::Result<Self> {
+        let writer = stream.try_clone()?;
+        let closer = stream.try_clone()?;
+        Ok(Self {
+            reader: Box::new(stream),
+            writer: Box::new(writer),
+            close: Box::new(move || {
+                let _ = closer.shutdown(std::net::Shutdown::Both);
+            }),
+        })
+    }
+
+    /// Connect straight to where the device listens
+    pub fn open(ginfo: &ConnectionInfo) -> Result<Self, String> {
+        if let Some(err) = ginfo.bad_config() {
+            return Err(err);
+        }
+        if let Some(socket) = &ginfo.gsocket {
+            return Self::unix(socket);
+        }
+        match ginfo.get_conn_host() {
+            (host, Some(port), _) => Self::tcp(&host, &port),
+            _ => Err(format!(
+                "Can't determine where the {} console listens",
+                ginfo.gtype
+            )),
+        }
+    }
+
+    /// How to reach the `ginfo` display of VM `name`: through an SSH
+    /// tunnel from `tunnels` when the display only listens on the remote
+    /// host, where it listens, or for a local VM listening nowhere,
+    /// through a connection libvirt makes for us
+    pub fn opener(
+        conn: Arc<dyn Connection>,
+        name: &str,
+        ginfo: &ConnectionInfo,
+        tunnels: &Arc<SshTunnels>,
+    ) -> Result<StreamOpener, String> {
+        if let Some(err) = ginfo.bad_config() {
+            return Err(err);
+        }
+        if ginfo.need_tunnel() {
+            let tunnels = tunnels.clone();
+            return Ok(Arc::new(move || tunnels.open_new()));
+        }
+        if ginfo.gsocket.is_none() && ginfo.gport.is_none() {
+            if ginfo.gtlsport.is_some() {
+                return Err("Guest is configured for TLS only, which is not supported.".into());
+            }
+            if !conn.is_remote() {
+                let (name, gidx) = (name.to_string(), ginfo.gidx);
+                return Ok(Arc::new(move || {
+                    Self::fd(conn.open_graphics_fd(&name, gidx)?)
+                }));
+            }
+        }
+        let ginfo = ginfo.clone();
+        Ok(Arc::new(move || Self::open(&ginfo)))
+    }
+}
+
+/// Opens another stream to a display. SPICE wants one per channel.
+pub type StreamOpener = Arc<dyn Fn() -> Result<ViewerStream, String> + Send + Sync>;
+
+/// What the display widget and menus send to the guest
+pub trait ViewerInput: Send + Sync {
+    fn key_event(&self, down: bool, keysym: u32) -> Result<(), String>;
+
+    /// `buttons` is a mask with bit 0 for the left button, 1 middle,
+    /// 2 right, 3 and 4 for wheel up and down
+    fn pointer_event(&self, buttons: u8, x: u32, y: u32) -> Result<(), String>;
+
+    fn cut_text(&self, text: &str) -> Result<(), String>;
+
+    /// Press the keys in order, then release them in reverse
+    fn send_keys(&self, keysyms: &[u32]) -> Result<(), String> {
+        for &sym in keysyms {
+            self.key_event(true, sym)?;
+        }
+        for &sym in keysyms.iter().rev() {
+            self.key_event(false, sym)?;
+        }
+        Ok(())
+    }
+}
+
+impl ViewerInput for RfbSender {
+    fn key_event(&self, down: bool, keysym: u32) -> Result<(), String> {
+        RfbSender::key_event(self, down, keysym)
+    }
+
+    fn pointer_event(&self, buttons: u8, x: u32, y: u32) -> Result<(), String> {
+        RfbSender::pointer_event(self, buttons, x as u16, y as u16)
+    }
+
+    fn cut_text(&self, text: &str) -> Result<(), String> {
+        RfbSender::cut_text(self, text)
+    }
+}
+
+impl ViewerInput for SpiceSender {
+    fn key_event(&self, down: bool, keysym: u32) -> Result<(), String> {
+        SpiceSender::key_event(self, down, keysym)
+    }
+
+    fn pointer_event(&self, buttons: u8, x: u32, y: u32) -> Result<(), String> {
+        SpiceSender::pointer_event(self, buttons, x, y)
+    }
+
+    fn cut_text(&self, text: &str) -> Result<(), String> {
+        SpiceSender::cut_text(self, text)
+    }
+}
+
+/// A connected display: its framebuffer and where its input goes
+#[derive(Clone)]
+pub struct DisplayHandle {
+    pub fb: Arc<Mutex<Framebuffer>>,
+    pub input: Arc<dyn ViewerInput>,
+}
+
+impl std::fmt::Debug for DisplayHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DisplayHandle").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ViewerEvent {
+    /// The server wants a password before going on
+    NeedAuth,
+    AuthError(String),
+    Connected(DisplayHandle),
+    /// Framebuffer contents changed
+    Update,
+    DesktopResize(u32, u32),
+    Bell,
+    /// The guest clipboard changed
+    CutText(String),
+    /// The connection ended, with the error if it failed
+    Disconnected(Option<String>),
+}
+
+static NEXT_VIEWER_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// One graphical console connection
+pub struct Viewer {
+    id: usize,
+    pub gtype: String,
+    events: Arc<Mutex<Option<UnboundedReceiver<ViewerEvent>>>>,
+    password: mpsc::Sender<Option<String>>,
+    close: Closer,
+}
+
+impl Viewer {
+    /// Talk VNC over `stream`
+    pub fn vnc(stream: ViewerStream) -> Self {
+        let (tx, rx) = unbounded();
+        let (password_tx, password_rx) = mpsc::channel();
+        let ViewerStream {
+            reader,
+            writer,
+            close,
+        } = stream;
+        std::thread::spawn(move || {
+            let ret = vnc_session(reader, writer, &tx, password_rx);
+            debug!("VNC session ended: {:?}", ret);
+            let _ = tx.unbounded_send(ViewerEvent::Disconnected(ret.err()));
+        });
+        Self {
+            id: NEXT_VIEWER_ID.fetch_add(1, Ordering::Relaxed),
+            gtype: "vnc".to_string(),
+            events: Arc::new(Mutex::new(Some(rx))),
+            password: password_tx,
+            close,
+        }
+    }
+
+    /// Talk SPICE, with a stream from `open` for each channel
+    pub fn spice(open: StreamOpener) -> Self {
+        let (tx, rx) = unbounded();
+        let (password_tx, password_rx) = mpsc::channel();
+        // Closers of the open channels, None once the viewer is closed
+        let closers: Arc<Mutex<Option<Vec<Closer>>>> = Arc::new(Mutex::new(Some(vec![])));
+        let opener: Opener = {
+            let closers = closers.clone();
+            Arc::new(move || {
+                let stream = open()?;
+                match &mut *closers.lock().unwrap() {
+                    Some(closers) => closers.push(stream.close),
+                    None => {
+                        (stream.close)();
+                        return Err("The viewer was closed".to_string());
+                    }
+                }
+                Ok((stream.reader, stream.writer))
+            })
+        };
+        std::thread::spawn(move || {
+            let ret = spice_session(opener, &tx, password_rx);
+            debug!("SPICE session ended: {:?}", ret);
+            let _ = tx.unbounded_send(ViewerEvent::Disconnected(ret.err()));
+        });
+        Self {
+            id: NEXT_VIEWER_ID.fetch_add(1, Ordering::Relaxed),
+            gtype: "spice".to_string(),
+            events: Arc::new(Mutex::new(Some(rx))),
+            password: password_tx,
+            close: Box::new(move || {
+                for close in closers.lock().unwrap().take().into_iter().flatten() {
+                    close();
+                }
+            }),
+        }
+    }
+
+    /// Answer `ViewerEvent::NeedAuth`
+    pub fn set_password(&self, password: Option<String>) {
+        let _ = self.password.send(password);
+    }
+
+    /// Events of this connection, for as long as it lasts
+    pub fn subscription(&self) -> Subscription<ViewerEvent> {
+        let events = self.events.clone();
+        let events = stream::once(async move { events.lock().unwrap().take() })
+            .filter_map(future::ready)
+            .flatten();
+        Subscription::run_with_id(("viewer", self.id), events)
+    }
+
+    pub fn close(&self) {
+        (self.close)();
+    }
+}
+
+impl Drop for Viewer {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn vnc_session(
+    reader: Box<dyn Read + Send>,
+    writer: Box<dyn Write + Send>,
+    tx: &UnboundedSender<ViewerEvent>,
+    password: mpsc::Receiver<Option<String>>,
+) -> Result<(), String> {
+    let mut client = RfbClient::connect(reader, writer)?;
+    let password = if client.needs_password() {
+        let _ = tx.unbounded_send(ViewerEvent::NeedAuth);
+        // The viewer went away
+        let Ok(password) = password.recv() else {
+            return Ok(());
+        };
+        password
+    } else {
+        None
+    };
+    if let Err(e) = client.authenticate(password.as_deref()) {
+        let _ = tx.unbounded_send(ViewerEvent::AuthError(e.clone()));
+        return Err(e);
+    }
+    client.init(true)?;
+    let handle = DisplayHandle {
+        fb: client.framebuffer(),
+        input: Arc::new(client.sender()),
+    };
+    let _ = tx.unbounded_send(ViewerEvent::Connected(handle));
+    loop {
+        for event in client.next_events()? {
+            let event = match event {
+                RfbEvent::Update => ViewerEvent::Update,
+                RfbEvent::Resize(w, h) => ViewerEvent::DesktopResize(w, h),
+                RfbEvent::Bell => ViewerEvent::Bell,
+                RfbEvent::CutText(text) => ViewerEvent::CutText(text),
+            };
+            if tx.unbounded_send(event).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn spice_session(
+    open: Opener,
+    tx: &UnboundedSender<ViewerEvent>,
+    password: mpsc::Receiver<Option<String>>,
+) -> Result<(), String> {
+    let mut client = SpiceClient::new(open);
+    // Only a refused login tells that the server wants a password
+    if !client.link(None)? {
+        let _ = tx.unbounded_send(ViewerEvent::NeedAuth);
+        let Ok(password) = password.recv() else {
+            return Ok(());
+        };
+        if !client.link(password.as_deref())? {
+            let e = "Authentication failed".to_string();
+            let _ = tx.unbounded_send(ViewerEvent::AuthError(e.clone()));
+            return Err(e);
+        }
+    }
+    client.init()?;
+    let handle = DisplayHandle {
+        fb: client.framebuffer(),
+        input: Arc::new(client.sender()),
+    };
+    let _ = tx.unbounded_send(ViewerEvent::Connected(handle));
+    loop {
+        for event in client.next_events()? {
+            let event = match event {
+                SpiceEvent::Update => ViewerEvent::Update,
+                SpiceEvent::Resize(w, h) => ViewerEvent::DesktopResize(w, h),
+                SpiceEvent::CutText(text) => ViewerEvent::CutText(text),
+            };
+            if tx.unbounded_send(event).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// X11 keysym of an iced key
+pub fn keysym_for_key(key: &Key, location: Location) -> Option<u32> {
+    let right = location == Location::Right;
+    let name = match key {
+        Key::Character(s) => {
+            let mut chars = s.chars();
+            let c = chars.next()?;
+            return chars.next().is_none().then(|| keysym_from_char(c));
+        }
+        Key::Named(named) => match named {
+            Named::Enter => "Return",
+            Named::Tab => "Tab",
+            Named::Space => "space",
+            Named::Backspace => "BackSpace",
+            Named::Escape => "Escape",
+            Named::Delete => "Delete",
+            Named::Insert => "Insert",
+            Named::Home => "Home",
+            Named::End => "End",
+            Named::PageUp => "Page_Up",
+            Named::PageDown => "Page_Down",
+            Named::ArrowLeft => "Left",
+            Named::ArrowUp => "Up",
+            Named::ArrowRight => "Right",
+            Named::ArrowDown => "Down",
+            Named::Shift if right => "Shift_R",
+            Named::Shift => "Shift_L",
+            Named::Control if right => "Control_R",
+            Named::Control => "Control_L",
+            Named::Alt if right => "Alt_R",
+            Named::Alt => "Alt_L",
+            Named::Super if right => "Super_R",
+            Named::Super => "Super_L",
+            Named::CapsLock => "Caps_Lock",
+            Named::NumLock => "Num_Lock",
+            Named::ScrollLock => "Scroll_Lock",
+            Named::Pause => "Pause",
+            Named::PrintScreen => "Print",
+            Named::ContextMenu => "Menu",
+            Named::F1 => "F1",
+            Named::F2 => "F2",
+            Named::F3 => "F3",
+            Named::F4 => "F4",
+            Named::F5 => "F5",
+            Named::F6 => "F6",
+            Named::F7 => "F7",
+            Named::F8 => "F8",
+            Named::F9 => "F9",
+            Named::F10 => "F10",
+            Named::F11 => "F11",
+            Named::F12 => "F12",
+            _ => return None,
+        },
+        Key::Unidentified => return None,
+    };
+    keysym_from_name(name)
+}
+
+/// Input from the display widget, in framebuffer coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayInput {
+    Key { down: bool, keysym: u32 },
+    Pointer { buttons: u8, x: u32, y: u32 },
+}
+
+/// Widget showing a remote framebuffer and capturing input for it
+pub struct Display<'a, Message> {
+    fb: &'a Framebuffer,
+    /// Shrink or grow the desktop to the available space
+    scale: bool,
+    on_input: Box<dyn Fn(DisplayInput) -> Message + 'a>,
+}
+
+pub fn display<'a, Message>(
+    fb: &'a Framebuffer,
+    scale: bool,
+    on_input: impl Fn(DisplayInput) -> Message + 'a,
+) -> Display<'a, Message> {
+    Display {
+        fb,
+        scale,
+        on_input: Box::new(on_input),
+    }
+}
+
+#[derive(Debug, Default)]
+struct DisplayState {
+    /// Keyboard input goes to the guest after a click on the display
+    focused: bool,
+    buttons: u8,
+}
+
+impl<Message> Display<'_, Message> {
+    /// Where the desktop is drawn in `bounds`, and at what scale
+    fn geometry(&self, bounds: Rectangle) -> (Point, f32) {
+        let (w, h) = (self.fb.width.max(1) as f32, self.fb.height.max(1) as f32);
+        let scale = if self.scale {
+            (bounds.width / w).min(bounds.height / h)
+        } else {
+            1.0
+        };
+        let x = bounds.x + ((bounds.width - w * scale) / 2.0).max(0.0);
+        let y = bounds.y + ((bounds.height - h * scale) / 2.0).max(0.0);
+        (Point::new(x, y), scale)
+    }
+
+    fn key_input(
+        &self,
+        down: bool,
+        key: &Key,
+        location: Location,
+        shell: &mut Shell<'_, Message>,
+    ) -> event::Status {
+        let Some(keysym) = keysym_for_key(key, location) else {
+            return event::Status::Ignored;
+        };
+        shell.publish((self.on_input)(DisplayInput::Key { down, keysym }));
+        event::Status::Captured
+    }
+
+    /// Framebuffer coordinates of a window position on the desktop
+    fn to_desktop(&self, bounds: Rectangle, pos: Point) -> Option<(u32, u32)> {
+        let (origin, scale) = self.geometry(bounds);
+        let x = ((pos.x - origin.x) / scale).floor();
+        let y = ((pos.y - origin.y) / scale).floor();
+        (x >= 0.0 && y >= 0.0 && x < self.fb.width as f32 && y < self.fb.height as f32)
+            .then_some((x as u32, y as u32))
+    }
+}
+
+impl<Message, Theme, Renderer> Widget<Message, Theme, Renderer> for Display<'_, Message>
+where
+    Renderer: renderer::Renderer,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<DisplayState>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(DisplayState::default())
+    }
+
+    fn size(&self) -> Size<Length> {
+        if self.scale {
+            Size::new(Length::Fill, Length::Fill)
+        } else {
+            Size::new(
+                Length::Fixed(self.fb.width as f32),
+                Length::Fixed(self.fb.height as f32),
+            )
+        }
+    }
+
+    fn layout(
+        &self,
+        _tree: &mut widget::Tree,
+        _renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        let size = <Self as Widget<Message, Theme, Renderer>>::size(self);
+        layout::Node::new(limits.resolve(size.width, size.height, Size::ZERO))
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut widget::Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) -> event::Status {
+        let state = tree.state.downcast_mut::<DisplayState>();
+        let bounds = layout.bounds();
+        let pos = cursor.position().and_then(|p| self.to_desktop(bounds, p));
+        let pointer = |buttons: u8, shell: &mut Shell<'_, Message>| {
+            if let Some((x, y)) = pos {
+                shell.publish((self.on_input)(DisplayInput::Pointer { buttons, x, y }));
+            }
+        };
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(button)) => {
+                state.focused = pos.is_some();
+                if pos.is_none() {
+                    return event::Status::Ignored;
+                }
+                state.buttons |= button_mask(button);
+                pointer(state.buttons, shell);
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(button)) => {
+                if state.buttons & button_mask(button) == 0 {
+                    return event::Status::Ignored;
+                }
+                state.buttons &= !button_mask(button);
+                pointer(state.buttons, shell);
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) if pos.is_some() => {
+                pointer(state.buttons, shell);
+            }
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) if pos.is_some() => {
+                let dy = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } | mouse::ScrollDelta::Pixels { y, .. } => y,
+                };
+                if dy == 0.0 {
+                    return event::Status::Ignored;
+                }
+                let wheel = if dy > 0.0 { 1 << 3 } else { 1 << 4 };
+                pointer(state.buttons | wheel, shell);
+                pointer(state.buttons, shell);
+            }
+            Event::Keyboard(keyboard::Event::KeyPressed { key, location, .. }) if state.focused => {
+                return self.key_input(true, &key, location, shell);
+            }
+            Event::Keyboard(keyboard::Event::KeyReleased { key, location, .. })
+                if state.focused =>
+            {
+                return self.key_input(false, &key, location, shell);
+            }
+            _ => return event::Status::Ignored,
+        }
+        event::Status::Captured
+    }
+
+    fn draw(
+        &self,
+        _tree: &widget::Tree,
+        renderer: &mut Renderer,
+        _theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        let bounds = layout.bounds();
+        renderer.fill_quad(
+            renderer::Quad {
+                bounds,
+                ..renderer::Quad::default()
+            },
+            Color::BLACK,
+        );
+        let (origin, scale) = self.geometry(bounds);
+        draw_framebuffer(renderer, self.fb, origin, scale, viewport);
+    }
+
+    fn mouse_interaction(
+        &self,
+        _tree: &widget::Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &Renderer,
+    ) -> mouse::Interaction {
+        match cursor.position() {
+            Some(p) if self.to_desktop(layout.bounds(), p).is_some() => {
+                mouse::Interaction::Crosshair
+            }
+            _ => mouse::Interaction::default(),
+        }
+    }
+}
+
+fn button_mask(button: mouse::Button) -> u8 {
+    match button {
+        mouse::Button::Left => 1,
+        mouse::Button::Middle => 1 << 1,
+        mouse::Button::Right => 1 << 2,
+        _ => 0,
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<Display<'a, Message>>
+    for Element<'a, Message, Theme, Renderer>
+where
+    Message: 'a,
+    Renderer: renderer::Renderer + 'a,
+{
+    fn from(display: Display<'a, Message>) -> Self {
+        Element::new(display)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iced::futures::executor::block_on;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_connection_info() {
+        let gdev = XmlElement::parse(
+            "<graphics type='vnc' port='5901' autoport='yes' listen='0.0.0.0'>\
+             <listen type='address' address='0.0.0.0'/></graphics>",
+        )
+        .unwrap();
+        let ginfo = ConnectionInfo::new("qemu+ssh://root@example.com/system", &gdev, 0);
+        assert_eq!(ginfo.gport.as_deref(), Some("5901"));
+        assert!(!ginfo.need_tunnel());
+        assert_eq!(ginfo.bad_config(), None);
+        assert_eq!(ginfo.get_conn_host().0, "example.com");
+
+        let gdev = XmlElement::parse("<graphics type='spice' port='-1'/>").unwrap();
+        let ginfo = ConnectionInfo::new("qemu+tls://example.com/system", &gdev, 1);
+        assert_eq!(ginfo.gport, None);
+        assert!(
+            ginfo
+                .bad_config()
+                .unwrap()
+                .contains("local file descriptor")
+        );
+
+        let gdev = XmlElement::parse("<graphics type='vnc' port='5900'/>").unwrap();
+        let ginfo = ConnectionInfo::new("qemu+tcp://example.com/system", &gdev, 0);
+        assert!(
+            ginfo
+                .bad_config()
+                .unwrap()
+                .contains("only configured to listen locally")
+        );
+        let ginfo = ConnectionInfo::new("qemu+ssh://example.com/system", &gdev, 0);
+        assert!(ginfo.need_tunnel());
+        let ginfo = ConnectionInfo::new("qemu:///system", &gdev, 0);
+        assert_eq!(ginfo.bad_config(), None);
+        assert_eq!(
+            ginfo.get_conn_host(),
+            ("127.0.0.1".into(), Some("5900".into()), None)
+        );
+    }
+
+    #[test]
+    fn test_vnc_viewer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 64];
+            s.write_all(b"RFB 003.008\n").unwrap();
+            s.read_exact(&mut buf[..12]).unwrap();
+            // No auth, but the server refuses the client anyway
+            s.write_all(&[1, 1]).unwrap();
+            s.read_exact(&mut buf[..1]).unwrap();
+            s.write_all(&1u32.to_be_bytes()).unwrap();
+            s.write_all(&4u32.to_be_bytes()).unwrap();
+            s.write_all(b"nope").unwrap();
+        });
+
+        let viewer = Viewer::vnc(ViewerStream::tcp("127.0.0.1", &port).unwrap());
+        let mut events = viewer.events.lock().unwrap().take().unwrap();
+        let event = block_on(events.next()).unwrap();
+        assert!(matches!(event, ViewerEvent::AuthError(e) if e == "Authentication failed: nope"));
+        let event = block_on(events.next()).unwrap();
+        assert!(matches!(event, ViewerEvent::Disconnected(Some(_))));
+        server.join().unwrap();
+
+        assert!(ViewerStream::tcp("127.0.0.1", "1").is_err());
+
+        let viewer = Viewer::spice(Arc::new(|| Err("No route to host".to_string())));
+        let mut events = viewer.events.lock().unwrap().take().unwrap();
+        let event = block_on(events.next()).unwrap();
+        assert!(matches!(event, ViewerEvent::Disconnected(Some(e)) if e == "No route to host"));
+
+        let ctrl = keysym_for_key(&Key::Named(Named::Control), Location::Right);
+        assert_eq!(ctrl, Some(0xffe4));
+        assert_eq!(
+            keysym_for_key(&Key::Character("a".into()), Location::Standard),
+            Some(0x61)
+        );
+    }
+}
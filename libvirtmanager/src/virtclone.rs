@@ -0,0 +1,692 @@
+// virt-clone: duplicate a virtual machine (port of virtinst/virtclone.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! The virt-clone-rs binary is a thin wrapper around `main`, so the test
+//! suite can drive the whole tool in memory against the test driver.
+
+use crate::cli::{CliIo, ValidationChecks};
+use crate::cloner::Cloner;
+use crate::connection::{self, Connection};
+use crate::diskbackend::path_definitely_exists;
+use crate::guest::check_mac_in_use;
+use crate::progress::{NullMeter, TextMeter};
+
+const USAGE: &str = "usage: virt-clone --original [NAME] ...
+
+Duplicate a virtual machine, changing all the unique host side configuration
+like MAC address, name, etc.
+
+The VM contents are NOT altered: virt-clone does not change anything _inside_
+the guest OS, it only duplicates disks and does host side changes. So things
+like changing passwords, changing static IP address, etc are outside the scope
+of this tool. For these types of changes, please see virt-sysprep(1).
+
+options:
+  -h, --help            show this help message and exit
+  --version             show program's version number and exit
+  -c URI, --connect URI
+                        Connect to hypervisor with libvirt URI
+
+General Options:
+  -o SRC_NAME, --original SRC_NAME
+                        Name of the original guest to clone.
+  --original-xml ORIGINAL_XML
+                        XML file to use as the original guest.
+  --auto-clone          Auto generate clone name and storage paths from the
+                        original guest configuration.
+  -n NEW_NAME, --name NEW_NAME
+                        Name for the new guest
+  --reflink             use btrfs COW lightweight copy
+
+Storage Configuration:
+  -f NEW_DISKFILE, --file NEW_DISKFILE
+                        New file to use as the disk image for the new guest
+  --force-copy TARGET   Force to copy devices (eg, if 'hdc' is a readonly
+                        cdrom device, --force-copy=hdc)
+  --skip-copy SKIP_COPY
+                        Skip copy of the device target. (eg, if 'vda' is a
+                        disk you don't want to copy and use the same path in
+                        the new VM, use --skip-copy=vda)
+  --nonsparse           Do not use a sparse file for the clone's disk image
+  --preserve-data       Do not clone storage contents to specified file paths,
+                        their contents will be left untouched. This requires
+                        specifying existing paths for every cloneable disk
+                        image.
+  --nvram NEW_NVRAM     New file to use as storage for nvram VARS
+
+Networking Configuration:
+  -m NEW_MAC, --mac NEW_MAC
+                        New fixed MAC address for the clone guest. Default is
+                        a randomly generated MAC
+
+Miscellaneous Options:
+  --replace             Don't check name collision, overwrite any guest with
+                        the same name.
+  --print-xml           Print the generated domain XML rather than create the
+                        guest.
+  --check CHECK         Enable or disable validation checks. Example:
+                        --check path_in_use=off
+                        --check all=off
+  -q, --quiet           Suppress non-error output
+  -d, --debug           Print debugging information
+";
+
+/// Long options, for resolving argparse style abbreviations like `--auto`
+const LONG_OPTIONS: &[&str] = &[
+    "--help",
+    "--version",
+    "--connect",
+    "--original",
+    "--original-xml",
+    "--auto-clone",
+    "--name",
+    "--uuid",
+    "--reflink",
+    "--file",
+    "--force-copy",
+    "--skip-copy",
+    "--nonsparse",
+    "--preserve-data",
+    "--nvram",
+    "--mac",
+    "--prompt",
+    "--force",
+    "--replace",
+    "--print-xml",
+    "--check",
+    "--quiet",
+    "--debug",
+];
+
+/// Parsed command line
+#[derive(Debug)]
+pub struct Options {
+    pub connect: Option<String>,
+    pub src_name: Option<String>,
+    pub original_xml: Option<String>,
+    pub auto_clone: bool,
+    pub new_name: Option<String>,
+    pub new_uuid: Option<String>,
+    pub reflink: bool,
+    pub new_diskfile: Vec<String>,
+    /// `--force-copy` targets
+    pub force_copy: Vec<String>,
+    pub skip_copy: Vec<String>,
+    pub sparse: bool,
+    pub preserve: bool,
+    pub new_nvram: Option<String>,
+    pub new_mac: Vec<String>,
+    pub replace: bool,
+    pub print_xml: bool,
+    pub check: Vec<String>,
+    pub force: bool,
+    pub quiet: bool,
+    pub debug: bool,
+    pub help: bool,
+    pub version: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            connect: None,
+            src_name: None,
+            original_xml: None,
+            auto_clone: false,
+            new_name: None,
+            new_uuid: None,
+            reflink: false,
+            new_diskfile: Vec::new(),
+            force_copy: Vec::new(),
+            skip_copy: Vec::new(),
+            sparse: true,
+            preserve: false,
+            new_nvram: None,
+            new_mac: Vec::new(),
+            replace: false,
+            print_xml: false,
+            check: Vec::new(),
+            force: false,
+            quiet: false,
+            debug: false,
+            help: false,
+            version: false,
+        }
+    }
+}
+
+/// Expand an unambiguous prefix of a long option to the full name
+fn expand_long_option(flag: &str) -> Result<&str, String> {
+    if LONG_OPTIONS.contains(&flag) {
+        return Ok(flag);
+    }
+    let matches: Vec<&str> = LONG_OPTIONS
+        .iter()
+        .copied()
+        .filter(|o| o.starts_with(flag))
+        .collect();
+    match matches.as_slice() {
+        [one] => Ok(one),
+        [] => Ok(flag),
+        many => Err(format!(
+            "ambiguous option: {} could match {}",
+            flag,
+            many.join(", ")
+        )),
+    }
+}
+
+/// Parse the command line arguments, without the program name
+pub fn parse_args<S: AsRef<str>>(args: &[S]) -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut i = 0;
+    let next_value = |i: &mut usize, inline: Option<&str>, flag: &str| {
+        if let Some(v) = inline {
+            return Ok(v.to_string());
+        }
+        *i += 1;
+        args.get(*i)
+            .map(|a| a.as_ref().to_string())
+            .ok_or_else(|| format!("argument {}: expected one argument", flag))
+    };
+
+    while i < args.len() {
+        let arg = args[i].as_ref();
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) if arg.starts_with("--") => (f, Some(v)),
+            _ => (arg, None),
+        };
+        let flag = if flag.starts_with("--") {
+            expand_long_option(flag)?
+        } else {
+            flag
+        };
+        match flag {
+            "-h" | "--help" => opts.help = true,
+            "--version" => opts.version = true,
+            "-c" | "--connect" => opts.connect = Some(next_value(&mut i, inline, flag)?),
+            "-o" | "--original" => opts.src_name = Some(next_value(&mut i, inline, flag)?),
+            "--original-xml" => opts.original_xml = Some(next_value(&mut i, inline, flag)?),
+            "--auto-clone" => opts.auto_clone = true,
+            "-n" | "--name" => opts.new_name = Some(next_value(&mut i, inline, flag)?),
+            "-u" | "--uuid" => opts.new_uuid = Some(next_value(&mut i, inline, flag)?),
+            "--reflink" => opts.reflink = true,
+            "-f" | "--file" => opts.new_diskfile.push(next_value(&mut i, inline, flag)?),
+            "--force-copy" => opts.force_copy.push(next_value(&mut i, inline, flag)?),
+            "--skip-copy" => opts.skip_copy.push(next_value(&mut i, inline, flag)?),
+            "--nonsparse" => opts.sparse = false,
+            "--preserve-data" => opts.preserve = true,
+            "--nvram" => opts.new_nvram = Some(next_value(&mut i, inline, flag)?),
+            "-m" | "--mac" => opts.new_mac.push(next_value(&mut i, inline, flag)?),
+            "--prompt" => {}
+            "--force" => opts.force = true,
+            "--replace" => opts.replace = true,
+            "--print-xml" => opts.print_xml = true,
+            "--check" => opts.check.push(next_value(&mut i, inline, flag)?),
+            "-q" | "--quiet" => opts.quiet = true,
+            "-d" | "--debug" => opts.debug = true,
+            _ => return Err(format!("unrecognized arguments: {}", arg)),
+        }
+        i += 1;
+    }
+    Ok(opts)
+}
+
+fn process_src(opts: &Options) -> Result<(Option<String>, Option<String>), String> {
+    if let Some(path) = &opts.original_xml {
+        let xml = std::fs::read_to_string(path)
+            .map_err(|e| format!("Error reading '{}': {}", path, e))?;
+        return Ok((opts.src_name.clone(), Some(xml)));
+    }
+    if opts.src_name.is_none() {
+        return Err(
+            "An original machine name is required, use '--original src_name' and try again."
+                .to_string(),
+        );
+    }
+    Ok((opts.src_name.clone(), None))
+}
+
+fn process_macs(
+    opts: &Options,
+    cloner: &mut Cloner,
+    conn: &dyn Connection,
+    checks: &ValidationChecks,
+    io: &mut CliIo,
+) -> Result<(), String> {
+    if opts.new_mac.first().is_none_or(|m| m == "RANDOM") {
+        return Ok(());
+    }
+    for mac in &opts.new_mac {
+        // There's legitimate use cases for cloning VMs with duplicate
+        // MACs, so the collision check can be skipped with --check
+        if let Err(e) = check_mac_in_use(conn, mac) {
+            checks.optional_fail(io, &e, "mac_in_use", true)?;
+        }
+    }
+    let guest = cloner.new_guest_mut();
+    let count = guest.devices("interface").len();
+    for (i, mac) in opts.new_mac.iter().take(count).enumerate() {
+        let xpath = format!(
+            "{}/mac/@address",
+            crate::guest::Guest::device_xpath("interface", i)
+        );
+        guest.xml.set(&xpath, Some(mac));
+    }
+    Ok(())
+}
+
+fn process_disks(opts: &Options, cloner: &mut Cloner) -> Result<(), String> {
+    let mut newpaths = opts.new_diskfile.iter();
+    for diskinfo in cloner.nonshare_diskinfos_mut() {
+        let mut newpath = match newpaths.next() {
+            Some(p) => Some(p.clone()),
+            None if opts.auto_clone => break,
+            None => None,
+        };
+        if diskinfo.source_path().is_none() {
+            newpath = None;
+        }
+        diskinfo.set_new_path(newpath);
+        diskinfo.raise_error()?;
+    }
+    Ok(())
+}
+
+/// Extra CLI validation for the new disks
+fn validate_disks(
+    cloner: &Cloner,
+    conn: &dyn Connection,
+    checks: &ValidationChecks,
+    io: &mut CliIo,
+) -> Result<(), String> {
+    for diskinfo in cloner.diskinfos() {
+        diskinfo.raise_error()?;
+        let Some(path) = diskinfo.new_disk.as_ref().and_then(|d| d.path.as_ref()) else {
+            continue;
+        };
+        // Prompt if disk file already exists and preserve mode is not used
+        if !diskinfo.is_preserve_requested() && path_definitely_exists(conn, path) {
+            checks.optional_fail(
+                io,
+                &format!("This will overwrite the existing path '{}'", path),
+                "path_exists",
+                true,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Run virt-clone with parsed options. `conn` overrides opening
+/// `--connect`, for tests.
+pub fn run(mut opts: Options, io: &mut CliIo, conn: Option<&dyn Connection>) -> Result<(), String> {
+    if opts.help {
+        io.print(USAGE);
+        return Ok(());
+    }
+    if opts.version {
+        io.print(env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    opts.quiet = opts.quiet || opts.print_xml;
+    if opts.force && opts.check.is_empty() {
+        opts.check.push("all=off".to_string());
+    }
+    let checks = ValidationChecks::parse(&opts.check)?;
+
+    let opened: Box<dyn Connection>;
+    let conn = match conn {
+        Some(c) => c,
+        None => {
+            opened = connection::open(opts.connect.as_deref())?;
+            opened.as_ref()
+        }
+    };
+
+    if opts.new_diskfile.is_empty() && !opts.auto_clone {
+        return Err(
+            "Either --auto-clone or --file is required, use '--auto-clone or --file' and try again."
+                .to_string(),
+        );
+    }
+
+    let (src_name, src_xml) = process_src(&opts)?;
+    let mut cloner = Cloner::new(conn, src_name.as_deref(), src_xml.as_deref())?;
+    for msg in cloner.take_warnings() {
+        io.warn(&msg);
+    }
+
+    cloner.set_replace(opts.replace);
+    cloner.set_reflink(opts.reflink);
+    cloner.set_sparse(opts.sparse);
+    if let Some(uuid) = &opts.new_uuid {
+        cloner.set_clone_uuid(uuid);
+    }
+    if let Some(path) = &opts.new_nvram {
+        cloner.set_nvram_path(path);
+    }
+
+    for diskinfo in cloner.diskinfos_mut() {
+        let target = diskinfo.target().unwrap_or_default();
+        if opts.force_copy.contains(&target) {
+            diskinfo.set_clone_requested();
+        }
+        if opts.skip_copy.contains(&target) {
+            diskinfo.set_share_requested();
+        }
+    }
+
+    if opts.preserve {
+        for diskinfo in cloner.nonshare_diskinfos_mut() {
+            diskinfo.set_preserve_requested();
+        }
+        if let Some(diskinfo) = cloner.nvram_diskinfo_mut() {
+            diskinfo.set_preserve_requested();
+        }
+    }
+
+    match &opts.new_name {
+        Some(name) => cloner.set_clone_name(name),
+        None if !opts.auto_clone => {
+            return Err(
+                "A name is required for the new virtual machine, use '--name NEW_VM_NAME' to specify one."
+                    .to_string(),
+            );
+        }
+        None => {}
+    }
+
+    process_macs(&opts, &mut cloner, conn, &checks, io)?;
+    process_disks(&opts, &mut cloner)?;
+    cloner.prepare()?;
+    validate_disks(&cloner, conn, &checks, io)?;
+
+    if opts.print_xml {
+        io.print(&cloner.new_guest().get_xml());
+        return Ok(());
+    }
+
+    let info = if opts.quiet {
+        cloner.start_duplicate(&NullMeter)?
+    } else {
+        cloner.start_duplicate(&TextMeter::new(io.stderr))?
+    };
+    if !opts.quiet {
+        io.print("");
+        io.print(&format!("Clone '{}' created successfully.", info.name));
+    }
+    Ok(())
+}
+
+/// Command line entry point. Returns the process exit code.
+pub fn main<S: AsRef<str>>(args: &[S], io: &mut CliIo, conn: Option<&dyn Connection>) -> i32 {
+    match parse_args(args).and_then(|opts| run(opts, io, conn)) {
+        Ok(()) => 0,
+        Err(e) => {
+            io.error(&e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::cli;
+    use crate::connection::{DomainState, testdriver};
+
+    const FAKE_UUID: &str = "00000000-1111-2222-3333-444444444444";
+
+    fn datadir() -> String {
+        format!("{}/../tests/data", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    /// Scratch copies of the virtclone test XML, with the `/tmp` paths the
+    /// Python test suite uses moved into a private directory
+    struct Fixtures {
+        dir: tempfile::TempDir,
+    }
+
+    impl Fixtures {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            for name in ["exist1.img", "exist2.img"] {
+                let path = dir.path().join(format!("__virtinst_cli_{}", name));
+                std::fs::write(path, format!("{} contents", name)).unwrap();
+            }
+            Self { dir }
+        }
+
+        fn prefix(&self) -> String {
+            format!("{}/__virtinst_cli_", self.dir.path().display())
+        }
+
+        /// `--original-xml` argument for a fixture
+        fn xml(&self, name: &str) -> String {
+            let xml = std::fs::read_to_string(format!("{}/cli/virtclone/{}", datadir(), name))
+                .unwrap()
+                .replace("/tmp/__virtinst_cli_", &self.prefix());
+            let path = self.dir.path().join(name);
+            std::fs::write(&path, xml).unwrap();
+            format!("--original-xml {}", path.display())
+        }
+
+        fn unscrub(&self, out: &str) -> String {
+            out.replace(&self.prefix(), "/tmp/__virtinst_cli_")
+        }
+    }
+
+    /// Run virt-clone, returning (exit code, stdout, stderr)
+    fn run_cli(cmd: &str, conn: &dyn Connection) -> (i32, String, String) {
+        let args = cli::shlex_split(cmd).unwrap();
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let ret = {
+            let mut io = CliIo {
+                stdin: None,
+                stdout: &mut out,
+                stderr: &mut err,
+            };
+            main(&args, &mut io, Some(conn))
+        };
+        (
+            ret,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    fn compare(name: &str) -> String {
+        std::fs::read_to_string(format!("{}/cli/compare/virt-clone-{}.xml", datadir(), name))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_clone_compare() {
+        let fx = Fixtures::new();
+        let cases = [
+            (
+                format!("{} --auto-clone", fx.xml("clone-empty.xml")),
+                "empty",
+            ),
+            (
+                format!("{} --auto-clone", fx.xml("clone-disk.xml")),
+                "auto-unmanaged",
+            ),
+            (
+                format!("{} --auto-clone", fx.xml("clone-serial.xml")),
+                "serial",
+            ),
+            (
+                format!("{} --auto-clone", fx.xml("clone-nvram-auto.xml")),
+                "clone-nvram",
+            ),
+            (
+                format!(
+                    "{} --auto-clone --nvram /nvram/my-custom-path",
+                    fx.xml("clone-nvram-auto.xml")
+                ),
+                "clone-nvram-path",
+            ),
+            (
+                format!("{} --auto-clone", fx.xml("clone-nvram-missing.xml")),
+                "nvram-missing",
+            ),
+            (
+                format!(
+                    "{} --auto-clone --preserve",
+                    fx.xml("clone-nvram-missing.xml")
+                ),
+                "nvram-missing-preserve",
+            ),
+            (
+                format!(
+                    "-n clonetest {} --file {}/virt-install --file /pool-dir/testvol1.img --preserve",
+                    fx.xml("clone-disk.xml"),
+                    fx.dir.path().display()
+                ),
+                "unmanaged-preserve",
+            ),
+        ];
+        for (cmd, name) in cases {
+            let conn = testdriver();
+            let cmd = format!("{} --print-xml --uuid {}", cmd, FAKE_UUID);
+            let (ret, out, err) = run_cli(&cmd, &conn);
+            assert_eq!(ret, 0, "{}: {}", cmd, err);
+            let out = fx
+                .unscrub(&out)
+                .replace(&fx.dir.path().display().to_string(), "TESTSUITE_SCRUBBED");
+            assert_eq!(out, compare(name), "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn test_clone_define() {
+        let fx = Fixtures::new();
+        let conn = testdriver();
+        let (ret, out, err) = run_cli(
+            &format!("-n clonetest {} --auto-clone", fx.xml("clone-disk.xml")),
+            &conn,
+        );
+        assert_eq!(ret, 0, "{}", err);
+        assert_eq!(out, "\nClone 'clonetest' created successfully.\n");
+        let info = conn.lookup_domain("clonetest").unwrap();
+        assert_eq!(info.state, DomainState::Shutoff);
+        assert_ne!(info.uuid, "db69fa1f-eef0-e567-3c20-3ef16f10376b");
+        let copied = std::fs::read_to_string(format!("{}exist1-clone.img", fx.prefix())).unwrap();
+        assert_eq!(copied, "exist1.img contents");
+        assert!(!Path::new(&format!("{}exist2-clone-1.img", fx.prefix())).exists());
+
+        // The generated paths now exist, so a second clone moves on
+        let (ret, out, err) = run_cli(
+            &format!(
+                "{} --auto-clone --skip-copy=hdb --print-xml",
+                fx.xml("clone-disk.xml")
+            ),
+            &conn,
+        );
+        assert_eq!(ret, 0, "{}", err);
+        assert!(out.contains("<name>origtest-clone</name>"));
+        assert!(out.contains("__virtinst_cli_exist1-clone-1.img"));
+        assert!(!out.contains("exist2-clone"));
+
+        // Overwriting an existing running VM
+        let (ret, _, err) = run_cli(
+            &format!(
+                "-n test {} --auto-clone --replace",
+                fx.xml("clone-empty.xml")
+            ),
+            &conn,
+        );
+        assert_eq!(ret, 0, "{}", err);
+        let info = conn.lookup_domain("test").unwrap();
+        assert_eq!(info.state, DomainState::Shutoff);
+
+        // Colliding MAC, but the check is skipped
+        let (ret, _, err) = run_cli(
+            &format!(
+                "-n clonetest2 {} --auto-clone --mac 22:11:11:11:11:11 --check all=off",
+                fx.xml("clone-disk.xml")
+            ),
+            &conn,
+        );
+        assert_eq!(ret, 0, "{}", err);
+        assert!(err.contains("is in use by another virtual machine"));
+    }
+
+    #[test]
+    fn test_clone_invalid() {
+        let fx = Fixtures::new();
+        let empty = fx.xml("clone-empty.xml");
+        let unmanaged = fx.xml("clone-disk.xml");
+        let cases = [
+            (
+                "--auto-clone".to_string(),
+                "An original machine name is required",
+            ),
+            (format!("{} --file foo", empty), "use '--name NEW_VM_NAME'"),
+            (
+                format!("{} --auto-clone -n test", empty),
+                "Invalid name for new guest",
+            ),
+            ("-o test --auto-clone".to_string(), "shutoff"),
+            (
+                "-o idontexist --auto-clone".to_string(),
+                "Domain 'idontexist' was not found",
+            ),
+            (unmanaged.clone(), "Either --auto-clone or --file"),
+            (
+                format!(
+                    "-n clonetest {} --file {}exist1.img",
+                    unmanaged,
+                    fx.prefix()
+                ),
+                "overwrite the existing path",
+            ),
+            (
+                format!(
+                    "-n clonetest {} --auto-clone --mac 22:11:11:11:11:11",
+                    unmanaged
+                ),
+                "--check mac_in_use=off",
+            ),
+            (
+                format!("{} --auto-clone", fx.xml("clone-net-http.xml")),
+                "'http' is not cloneable",
+            ),
+            (
+                format!("{} --auto-clone", fx.xml("clone-net-rbd.xml")),
+                "Cloning rbd volumes is not yet supported",
+            ),
+            (
+                format!("{} --auto-clone --check foo=off", empty),
+                "Unknown --check options",
+            ),
+        ];
+        for (cmd, grep) in cases {
+            let conn = testdriver();
+            let (ret, _, err) = run_cli(&cmd, &conn);
+            assert_eq!(ret, 1, "{}", cmd);
+            assert!(err.contains(grep), "{}: {}", cmd, err);
+        }
+    }
+
+    #[test]
+    fn test_parse_abbreviations() {
+        let opts =
+            parse_args(&["--auto", "--preserve", "-o", "foo", "--force-copy", "hdc"]).unwrap();
+        assert!(opts.auto_clone);
+        assert!(opts.preserve);
+        assert_eq!(opts.force_copy, ["hdc"]);
+        assert!(
+            parse_args(&["--n"])
+                .unwrap_err()
+                .contains("ambiguous option")
+        );
+    }
+}
@@ -0,0 +1,1339 @@
+// virt-install: create a new virtual machine (port of
+// virtinst/virtinstall.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! The virt-install-rs binary is a thin wrapper around `main`, so the
+//! test suite can drive the whole tool in memory against the test driver.
+
+use std::sync::Arc;
+
+use crate::cli::parsers::{
+    OsInfoData, disk_source_path, parse_cloud_init, parse_location, parse_osinfo, parse_unattended,
+};
+use crate::cli::{
+    self, CliIo, OptionSpec, ParseResult, ValidationChecks, check_option_introspection,
+    parse_into_guest,
+};
+use crate::connection::{self, Connection};
+use crate::diskbackend::{StorageBackend, StorageCreator, check_path_search};
+use crate::generatename::generate_name;
+use crate::guest::{Guest, check_mac_in_use, host_arch};
+use crate::installer::{self, INSTALL_METHODS, INSTALL_POLL_INTERVAL, Installer};
+use crate::installertreemedia::InstallerTreeMedia;
+use crate::osdict::{OsSortKey, osdb};
+use crate::progress::{NullMeter, TextMeter};
+use crate::urlfetcher::Fetcher;
+use crate::xmlapi::Element;
+
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+const USAGE: &str = "usage: virt-install --name NAME --memory MB STORAGE INSTALL [options]
+
+Create a new virtual machine from specified install media.
+
+options:
+  -h, --help            show this help message and exit
+  --version             show program's version number and exit
+  -c URI, --connect URI
+                        Connect to hypervisor with libvirt URI
+
+General Options:
+  -n NAME, --name NAME  Name of the guest instance
+  --memory MEMORY       Configure guest memory allocation.
+  --vcpus VCPUS         Number of vCPUs to configure for your guest.
+  --cpu CPU             CPU model and features.
+  --metadata METADATA   Configure guest metadata.
+
+Installation Method Options:
+  --cdrom CDROM         CD-ROM installation media
+  -l LOCATION, --location LOCATION
+                        Distro install URL, eg. https://host/path. See man
+                        page for specific distro examples.
+  --import              Build guest around an existing disk image
+  -x EXTRA_ARGS, --extra-args EXTRA_ARGS
+                        Additional arguments to pass to the install kernel
+                        booted from --location
+  --initrd-inject INITRD_INJECT
+                        Add given file to root of initrd from --location
+  --cloud-init [CLOUD_INIT]
+                        Perform a cloud image installation, configuring
+                        cloud-init. Ex:
+                        --cloud-init (no options) Default settings
+                        --cloud-init root-password-generate=yes,disable=yes
+  --unattended [UNATTENDED]
+                        Perform an unattended installation. Ex:
+                        --unattended (no options) Default settings
+                        --unattended profile=desktop,admin-password-file=FILE
+
+OS options:
+  --osinfo OSINFO, --os-variant OSINFO
+                        The OS being installed in the guest.
+                        This is used for deciding optimal defaults like VirtIO.
+                        Example values: fedora29, rhel7.0, win10, ...
+                        Use '--osinfo list' to see a full list.
+
+Device Options:
+  --disk DISK           Specify storage with various options.
+  -w NETWORK, --network NETWORK
+                        Configure a guest network interface.
+  --graphics GRAPHICS   Configure guest display settings.
+
+Other Options:
+  --noautoconsole       Don't automatically try to connect to the guest
+                        console
+  --noreboot            Don't boot guest after completing install.
+  --print-xml           Print the generated domain XML rather than create
+                        the guest.
+  --dry-run             Run through install process, but do not create
+                        devices or define the guest.
+  --check CHECK         Enable or disable validation checks.
+  -q, --quiet           Suppress non-error output
+  -d, --debug           Print debugging information
+
+Every virt-xml option is accepted too. Use '--option=?' or
+'--option help' to see the available suboptions, for example '--disk=?'.
+";
+
+const LONG_OPTIONS: &[&str] = &[
+    "--help",
+    "--version",
+    "--connect",
+    "--name",
+    "--cdrom",
+    "--location",
+    "--import",
+    "--extra-args",
+    "--initrd-inject",
+    "--cloud-init",
+    "--unattended",
+    "--osinfo",
+    "--os-variant",
+    "--nonetworks",
+    "--noautoconsole",
+    "--noreboot",
+    "--print-xml",
+    "--dry-run",
+    "--check",
+    "--quiet",
+    "--debug",
+];
+
+/// Parsed command line
+#[derive(Default)]
+pub struct Options {
+    pub connect: Option<String>,
+    pub name: Option<String>,
+    pub cdrom: Option<String>,
+    pub location: Option<String>,
+    pub import_install: bool,
+    pub extra_args: Vec<String>,
+    pub initrd_inject: Vec<String>,
+    /// `--cloud-init` was passed, with its value unless it was bare
+    pub cloud_init: Option<Option<String>>,
+    /// `--unattended` was passed, with its value unless it was bare
+    pub unattended: Option<Option<String>>,
+    pub osinfo: Option<String>,
+    pub nonetworks: bool,
+    pub noautoconsole: bool,
+    pub noreboot: bool,
+    pub print_xml: bool,
+    pub dry_run: bool,
+    pub check: Vec<String>,
+    pub quiet: bool,
+    pub debug: bool,
+    pub help: bool,
+    pub version: bool,
+    /// XML options like `--disk`, in command line order
+    pub xmlopts: Vec<(&'static OptionSpec, Vec<String>)>,
+    /// Downloads install files from the network, curl if unset
+    pub fetcher: Option<Arc<dyn Fetcher>>,
+}
+
+impl Options {
+    fn push_xmlopt(&mut self, spec: &'static OptionSpec, value: String) {
+        match self
+            .xmlopts
+            .iter_mut()
+            .find(|(s, _)| std::ptr::eq(*s, spec))
+        {
+            Some((_, values)) => values.push(value),
+            None => self.xmlopts.push((spec, vec![value])),
+        }
+    }
+
+    fn xmlopt(&self, name: &str) -> Option<&[String]> {
+        self.xmlopts
+            .iter()
+            .find(|(s, _)| s.name == name)
+            .map(|(_, v)| v.as_slice())
+    }
+}
+
+/// Whether argparse would take `arg` as an option value rather than as
+/// the next option
+fn is_value(arg: &str) -> bool {
+    !arg.starts_with('-') || arg[1..].parse::<i64>().is_ok()
+}
+
+/// Expand an unambiguous prefix of a long option to the full name
+fn expand_long_option(flag: &str) -> Result<&str, String> {
+    if LONG_OPTIONS.contains(&flag) || cli::lookup_parser(flag).is_some() {
+        return Ok(flag);
+    }
+    let matches: Vec<&str> = LONG_OPTIONS
+        .iter()
+        .copied()
+        .filter(|o| o.starts_with(flag))
+        .collect();
+    match matches.as_slice() {
+        [one] => Ok(one),
+        [] => Ok(flag),
+        many => Err(format!(
+            "ambiguous option: {} could match {}",
+            flag,
+            many.join(", ")
+        )),
+    }
+}
+
+/// Parse the command line arguments, without the program name
+pub fn parse_args<S: AsRef<str>>(args: &[S]) -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut i = 0;
+    let next_value = |i: &mut usize, inline: Option<&str>, flag: &str| {
+        if let Some(v) = inline {
+            return Ok(v.to_string());
+        }
+        *i += 1;
+        args.get(*i)
+            .map(|a| a.as_ref().to_string())
+            .ok_or_else(|| format!("argument {}: expected one argument", flag))
+    };
+
+    while i < args.len() {
+        let arg = args[i].as_ref();
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) if arg.starts_with("--") => (f, Some(v)),
+            _ => (arg, None),
+        };
+        let flag = if flag.starts_with("--") {
+            expand_long_option(flag)?
+        } else {
+            flag
+        };
+        match flag {
+            "-h" | "--help" => opts.help = true,
+            "--version" => opts.version = true,
+            "-c" | "--connect" => opts.connect = Some(next_value(&mut i, inline, flag)?),
+            "-n" | "--name" => opts.name = Some(next_value(&mut i, inline, flag)?),
+            "--cdrom" => opts.cdrom = Some(next_value(&mut i, inline, flag)?),
+            "-l" | "--location" => opts.location = Some(next_value(&mut i, inline, flag)?),
+            "--import" => opts.import_install = true,
+            "-x" | "--extra-args" => opts.extra_args.push(next_value(&mut i, inline, flag)?),
+            "--initrd-inject" => opts.initrd_inject.push(next_value(&mut i, inline, flag)?),
+            "--cloud-init" | "--unattended" => {
+                let val = match inline {
+                    Some(v) => Some(v.to_string()),
+                    None => match args.get(i + 1).map(|a| a.as_ref()) {
+                        Some(v) if is_value(v) => {
+                            i += 1;
+                            Some(v.to_string())
+                        }
+                        _ => None,
+                    },
+                };
+                if flag == "--cloud-init" {
+                    opts.cloud_init = Some(val);
+                } else {
+                    opts.unattended = Some(val);
+                }
+            }
+            "--osinfo" | "--os-variant" => opts.osinfo = Some(next_value(&mut i, inline, flag)?),
+            "--nonetworks" => opts.nonetworks = true,
+            "--noautoconsole" => opts.noautoconsole = true,
+            "--noreboot" => opts.noreboot = true,
+            "--print-xml" => opts.print_xml = true,
+            "--dry-run" => opts.dry_run = true,
+            "--check" => opts.check.push(next_value(&mut i, inline, flag)?),
+            "-q" | "--quiet" => opts.quiet = true,
+            "-d" | "--debug" => opts.debug = true,
+            "-w" => {
+                let value = next_value(&mut i, inline, flag)?;
+                opts.push_xmlopt(
+                    cli::lookup_parser("network").expect("network parser"),
+                    value,
+                );
+            }
+            f if f.starts_with("--") => {
+                let spec = cli::lookup_parser(f)
+                    .ok_or_else(|| format!("unrecognized arguments: {}", arg))?;
+                let value = next_value(&mut i, inline, f)?;
+                opts.push_xmlopt(spec, value);
+            }
+            _ => return Err(format!("unrecognized arguments: {}", arg)),
+        }
+        i += 1;
+    }
+    Ok(opts)
+}
+
+/// Fill the guest from the XML options, in the order virtinst applies
+/// them. Returns the tags of devices the user asked for none of, like
+/// `--graphics none`, so no default one gets added.
+fn run_all_parsers(
+    opts: &Options,
+    conn: &dyn Connection,
+    guest: &mut Guest,
+    installer: &mut Installer,
+) -> Result<Vec<&'static str>, String> {
+    let mut skip = vec![];
+    for spec in cli::all_parsers() {
+        let Some(values) = opts.xmlopt(spec.name) else {
+            continue;
+        };
+        for value in values {
+            if spec.stub_none && value == "none" {
+                skip.extend(spec.tag());
+                continue;
+            }
+            let Some((xpath, res)) = parse_into_guest(guest, spec, value)? else {
+                continue;
+            };
+            if spec.is_list {
+                let mut dev = guest
+                    .xml
+                    .find(&xpath)
+                    .cloned()
+                    .expect("device was just added");
+                if spec.name == "disk" {
+                    set_disk_storage(conn, installer, &mut dev, &res)?;
+                }
+                guest.set_device_defaults(&mut dev)?;
+                if let Some(slot) = guest.xml.find_mut(&xpath) {
+                    *slot = dev;
+                }
+            }
+        }
+    }
+    Ok(skip)
+}
+
+/// Queue creating the storage of a `--disk` path that doesn't exist
+/// yet, sized by its `size=` in GiB
+fn set_disk_storage(
+    conn: &dyn Connection,
+    installer: &mut Installer,
+    dev: &mut Element,
+    res: &ParseResult,
+) -> Result<(), String> {
+    let (Some(path), Some(size)) = (disk_source_path(dev), res.extra("size")) else {
+        return Ok(());
+    };
+    if StorageBackend::new(conn, &path)?.exists() {
+        return Ok(());
+    }
+    let size: f64 = size
+        .parse()
+        .map_err(|_| format!("Improper value for 'size': '{}'", size))?;
+    let sparse = match res.extra("sparse") {
+        Some(val) => cli::on_off_convert("sparse", val)?,
+        None => true,
+    };
+    let creator = StorageCreator::new(
+        conn,
+        &path,
+        (size * GIB) as u64,
+        res.extra("format"),
+        sparse,
+    )?;
+    if creator.dev_type() == "block" {
+        dev.set("./@type", Some("block"));
+        dev.set("./source/@file", None);
+        dev.set("./source/@dev", Some(creator.path()));
+    }
+    if dev.get("./driver/@type").is_none()
+        && let Some(fmt) = creator.driver_type()
+    {
+        dev.set("./driver/@type", Some(fmt));
+    }
+    installer.add_storage(creator);
+    Ok(())
+}
+
+fn add_default_network(conn: &dyn Connection, guest: &mut Guest) -> Result<(), String> {
+    let mut dev = Element::new("interface");
+    if conn.uri().starts_with("test") {
+        dev.set("./@type", Some("user"));
+    } else {
+        dev.set("./@type", Some("network"));
+        dev.set("./source/@network", Some("default"));
+        dev.set("./model/@type", Some("virtio"));
+    }
+    guest.set_device_defaults(&mut dev)?;
+    guest.add_device(dev);
+    Ok(())
+}
+
+fn set_cli_default_name(
+    conn: &dyn Connection,
+    guest: &mut Guest,
+    io: &mut CliIo,
+    quiet: bool,
+) -> Result<(), String> {
+    if guest.name().is_some() {
+        return Ok(());
+    }
+    let name = generate_name("vm", |n| conn.lookup_domain(n).is_ok(), "", 1, "", true)?;
+    if !quiet {
+        io.print(&format!("Using default --name {}", name));
+    }
+    guest.set_name(&name);
+    Ok(())
+}
+
+fn validate_required_options(
+    opts: &Options,
+    guest: &Guest,
+    installer: &Installer,
+    skip: &[&str],
+) -> Result<(), String> {
+    // Aggregate the errors to help first time users get it right
+    let mut msg = String::new();
+    if guest.memory().is_none() && guest.current_memory().is_none() {
+        msg += "\n--memory amount in MiB is required";
+    }
+    if opts.xmlopt("disk").is_none()
+        && opts.xmlopt("filesystem").is_none()
+        && !skip.contains(&"disk")
+    {
+        msg += "\n--disk storage must be specified (override with --disk none)";
+    }
+    if !installer.options_specified() {
+        msg += &format!(
+            "\nAn install method must be specified\n({})",
+            INSTALL_METHODS
+        );
+    }
+    if !msg.is_empty() {
+        return Err(msg);
+    }
+    Ok(())
+}
+
+fn build_installer(
+    opts: &Options,
+    conn: &dyn Connection,
+    io: &mut CliIo,
+) -> Result<Installer, String> {
+    let location = opts.location.as_deref().map(parse_location).transpose()?;
+    let cdrom = opts.cdrom.as_deref().filter(|_| location.is_none());
+    let no_install = location.is_none()
+        && cdrom.is_none()
+        && (opts.import_install || opts.xmlopt("boot").is_some() || opts.print_xml);
+    let mut installer = Installer::new(cdrom, None, no_install);
+    if let Some(fetcher) = &opts.fetcher {
+        installer.set_fetcher(fetcher.clone());
+    }
+    if let Some(data) = &location {
+        installer.set_treemedia(InstallerTreeMedia::new(
+            conn,
+            data.location.as_deref(),
+            data.kernel.as_deref(),
+            data.initrd.as_deref(),
+            None,
+            None,
+            None,
+        )?);
+    }
+    if !opts.extra_args.is_empty() {
+        installer.set_extra_args(&opts.extra_args)?;
+    }
+    if !opts.initrd_inject.is_empty() {
+        installer.set_initrd_injections(&opts.initrd_inject)?;
+    }
+    if let Some(optstr) = &opts.cloud_init {
+        if optstr.is_none() {
+            io.warn("Defaulting to --cloud-init root-password-generate=yes,disable=yes");
+        }
+        installer.set_cloudinit_data(parse_cloud_init(optstr.as_deref())?);
+    }
+    if let Some(optstr) = &opts.unattended {
+        if opts.cloud_init.is_some() {
+            return Err("Cannot use --unattended and --cloud-init at the same time".to_string());
+        }
+        installer.set_unattended_data(parse_unattended(optstr.as_deref())?);
+    }
+    Ok(installer)
+}
+
+/// HVM is really the only case where the OS impacts the defaults
+fn needs_accurate_osinfo(guest: &Guest) -> bool {
+    guest.os_type().is_none_or(|t| t == "hvm")
+}
+
+/// Set the guest OS from `--osinfo` or the install media, and fail if
+/// that leaves an HVM guest without one
+fn installer_detect_distro(
+    guest: &mut Guest,
+    installer: &mut Installer,
+    osdata: &OsInfoData,
+    io: &mut CliIo,
+) -> Result<(), String> {
+    let mut os_set = false;
+    // The OS name has to be set first, so it's respected when the
+    // installer creates the distro store
+    if let Some(name) = &osdata.name {
+        os_set = true;
+        guest.set_os_name(name)?;
+    }
+    // This also validates the install location
+    let autodistro = installer
+        .detect_distro(guest)
+        .map_err(|e| format!("Error validating install location: {}", e))?;
+    if osdata.detect {
+        if let Some(distro) = autodistro {
+            os_set = true;
+            guest.set_os_name(&distro)?;
+        } else if let Some(name) = &osdata.name {
+            io.warn(&format!(
+                "Failed to detect osinfo OS name from install media, using fallback name \
+                 '{}'.\nPlease file a bug against virt-install if you expected the detection \
+                 to succeed.",
+                name
+            ));
+        }
+    }
+
+    let msg = "--osinfo/--os-variant OS name is required, but no value was\nset or detected.";
+    if os_set {
+        return Ok(());
+    }
+    if osdata.require {
+        return Err(msg.to_string());
+    }
+    if !needs_accurate_osinfo(guest) {
+        return Ok(());
+    }
+
+    let mut fail_msg = format!(
+        "{}\n\nThis is now a fatal error. Specifying an OS name is required\n\
+         for modern, performant, and secure virtual machine defaults.\n",
+        msg
+    );
+    if installer.location().is_some() || installer.cdrom().is_some() {
+        fail_msg += "\nIf you expected virt-install to detect an OS name from the\n\
+                     install media, you can set a fallback OS name with:\n\n  \
+                     --osinfo detect=on,name=OSNAME\n";
+    }
+    fail_msg += "\nYou can see a full list of possible OS name values with:\n\n   \
+                 virt-install --osinfo list\n";
+    let generic_linux: Vec<&str> = osdb()
+        .list_os(OsSortKey::Name)
+        .into_iter()
+        .filter(|o| o.is_linux_generic())
+        .map(|o| o.name.as_str())
+        .collect();
+    if !generic_linux.is_empty() {
+        fail_msg += &format!(
+            "\nIf your Linux distro is not listed, try one of generic values\n\
+             such as: {}\n",
+            generic_linux.join(", ")
+        );
+    }
+    let envkey = "VIRTINSTALL_OSINFO_DISABLE_REQUIRE";
+    fail_msg += &format!(
+        "\nIf you just need to get the old behavior back, you can use:\n\n  \
+         --osinfo detect=on,require=off\n\nOr export {}=1\n",
+        envkey
+    );
+    let fail_msg = format!("\n{}", fail_msg);
+    if std::env::var_os(envkey).is_some() {
+        io.warn(&fail_msg);
+        io.warn(&format!("{} set. Skipping fatal error.", envkey));
+        return Ok(());
+    }
+    Err(fail_msg)
+}
+
+/// Default memory and vCPUs to the OS recommendations. Unattended
+/// Windows installs also get the drivers osinfo can preinstall, so
+/// their devices count as supported.
+fn set_cli_defaults(guest: &mut Guest, installer: &Installer, io: &mut CliIo, quiet: bool) {
+    let Some(osinfo) = guest.osinfo() else {
+        return;
+    };
+    let arch = guest.arch().unwrap_or_else(|| host_arch().to_string());
+    if installer.has_unattended()
+        && osinfo.is_windows()
+        && osinfo.supports_unattended_drivers(&arch)
+    {
+        guest.add_extra_drivers(&osinfo.get_pre_installable_devices(&arch));
+    }
+    let res = osinfo.get_recommended_resources();
+    if let Some(ram) = res.get_recommended_ram(&arch)
+        && guest.memory().is_none()
+        && guest.current_memory().is_none()
+    {
+        let mbram = format!("{}", ram as f64 / (1024.0 * 1024.0));
+        if !quiet {
+            io.print(&format!("Using {} default --memory {}", osinfo.name, mbram));
+        }
+        guest
+            .xml
+            .set("./currentMemory", Some(&(ram / 1024).to_string()));
+    }
+    if let Some(ncpus) = res.get_recommended_ncpus(&arch)
+        && guest.vcpus().is_none()
+    {
+        guest.xml.set("./vcpu", Some(&ncpus.to_string()));
+    }
+}
+
+fn show_guest_warnings(guest: &Guest, io: &mut CliIo) {
+    if guest.osinfo().is_none() && needs_accurate_osinfo(guest) {
+        io.warn(
+            "Using --osinfo generic, VM performance may suffer. \
+             Specify an accurate OS for optimal results.",
+        );
+    }
+    let Some(current) = guest.current_memory() else {
+        return;
+    };
+    let rammb = current / 1024;
+    let arch = guest.arch().unwrap_or_else(|| host_arch().to_string());
+    let minram = guest
+        .osinfo()
+        .and_then(|o| o.get_recommended_resources().get_minimum_ram(&arch));
+    match minram {
+        Some(minram) if minram / 1024 > current => io.warn(&format!(
+            "Requested memory {} MiB is less than the recommended {} MiB for OS {}",
+            rammb,
+            minram / (1024 * 1024),
+            guest.osinfo().map_or("", |o| o.name.as_str())
+        )),
+        None if rammb < 17 => io.warn(&format!(
+            "Requested memory {} MiB is abnormally low. Were you trying to specify GiB?",
+            rammb
+        )),
+        _ => {}
+    }
+}
+
+/// Disk checks before creating anything: storage to create must fit,
+/// other paths must exist, and the emulator should be able to reach
+/// them
+fn validate_disks(
+    guest: &Guest,
+    installer: &Installer,
+    conn: &dyn Connection,
+    checks: &ValidationChecks,
+    io: &mut CliIo,
+) -> Result<(), String> {
+    for disk in guest.devices("disk") {
+        let Some(path) = disk_source_path(disk) else {
+            continue;
+        };
+        match installer.storage().iter().find(|c| c.path() == path) {
+            Some(creator) => match creator.is_size_conflict() {
+                (true, Some(msg)) => return Err(msg),
+                (_, Some(msg)) => checks.optional_fail(io, &msg, "disk_size", false)?,
+                _ => {}
+            },
+            None if !StorageBackend::new(conn, &path)?.exists() => {
+                return Err(format!(
+                    "Must specify storage creation parameters for non-existent path '{}'.",
+                    path
+                ));
+            }
+            None => {}
+        }
+        let search = check_path_search(conn, &path);
+        if let Some(user) = search.user.filter(|_| !search.fixlist.is_empty()) {
+            io.warn(&format!(
+                "{} may not be accessible by the hypervisor. You will need to grant the \
+                 '{}' user search permissions for the following directories: {:?}",
+                path, user.name, search.fixlist
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn print_osinfo_list(io: &mut CliIo) {
+    for os in osdb().list_os(OsSortKey::Name) {
+        io.print(&os.all_names.join(", "));
+    }
+    io.print("\nYou can see additional information with:\n\n  osinfo-query os\n");
+}
+
+fn print_cloudinit_passwd(installer: &mut Installer, io: &mut CliIo) {
+    if let Some(passwd) = installer.get_generated_password() {
+        io.print(&format!("Password for first root login is: {}", passwd));
+    }
+}
+
+/// Build the guest and its installer from parsed options, with every
+/// default filled in, without creating anything. The Create VM wizard
+/// uses this too, so both end up with the same XML.
+pub fn build_guest(
+    opts: &Options,
+    conn: &dyn Connection,
+    io: &mut CliIo,
+) -> Result<(Guest, Installer), String> {
+    let mut guest = Guest::new();
+    if let Some(name) = &opts.name {
+        guest.set_name(name);
+    }
+    let osdata = parse_osinfo(opts.osinfo.as_deref())?;
+    let mut installer = build_installer(opts, conn, io)?;
+    set_cli_default_name(conn, &mut guest, io, opts.quiet)?;
+    let mut skip = run_all_parsers(opts, conn, &mut guest, &mut installer)?;
+    installer_detect_distro(&mut guest, &mut installer, &osdata, io)?;
+    set_cli_defaults(&mut guest, &installer, io, opts.quiet);
+    if opts.nonetworks {
+        skip.push("interface");
+    }
+    if guest.devices("interface").is_empty() && !skip.contains(&"interface") {
+        add_default_network(conn, &mut guest)?;
+    }
+    validate_required_options(opts, &guest, &installer, &skip)?;
+    show_guest_warnings(&guest, io);
+    installer.set_install_defaults(conn, &mut guest, &skip)?;
+    Ok((guest, installer))
+}
+
+/// Run virt-install with parsed options. `conn` overrides opening
+/// `--connect`, for tests.
+pub fn run(mut opts: Options, io: &mut CliIo, conn: Option<&dyn Connection>) -> Result<(), String> {
+    if opts.help {
+        io.print(USAGE);
+        return Ok(());
+    }
+    if opts.version {
+        io.print(env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    opts.quiet = opts.quiet || opts.print_xml;
+    if check_option_introspection(&opts.xmlopts, io) {
+        return Ok(());
+    }
+    if opts.osinfo.as_deref() == Some("list") {
+        print_osinfo_list(io);
+        return Ok(());
+    }
+    let checks = ValidationChecks::parse(&opts.check)?;
+
+    let opened: Box<dyn Connection>;
+    let conn = match conn {
+        Some(c) => c,
+        None => {
+            opened = connection::open(opts.connect.as_deref())?;
+            opened.as_ref()
+        }
+    };
+
+    let (mut guest, mut installer) = build_guest(&opts, conn, io)?;
+    validate_disks(&guest, &installer, conn, &checks, io)?;
+    for mac in guest
+        .devices("interface")
+        .iter()
+        .filter_map(|i| i.get("./mac/@address"))
+    {
+        if let Err(e) = check_mac_in_use(conn, &mac) {
+            checks.optional_fail(io, &e, "mac_in_use", true)?;
+        }
+    }
+
+    if opts.print_xml || opts.dry_run {
+        let (initial_xml, final_xml) =
+            installer.start_install(conn, &mut guest, &NullMeter, true)?;
+        if opts.dry_run && !opts.print_xml {
+            io.print("Dry run completed successfully");
+            return Ok(());
+        }
+        io.print(&(initial_xml.unwrap_or_default() + &final_xml));
+        return Ok(());
+    }
+
+    if !opts.quiet {
+        io.print("\nStarting install...");
+    }
+    print_cloudinit_passwd(&mut installer, io);
+    let (_, final_xml) = if opts.quiet {
+        installer.start_install(conn, &mut guest, &NullMeter, false)?
+    } else {
+        installer.start_install(conn, &mut guest, &TextMeter::new(io.stderr), false)?
+    };
+    let name = guest.name().unwrap_or_default();
+    let postboot = installer.requires_postboot_xml_changes();
+    if opts.noautoconsole && postboot {
+        io.print("\nDomain is still running. Installation may be in progress.");
+        io.print("You can reconnect to the console to complete the installation process.");
+        return Ok(());
+    }
+    if postboot && !opts.noreboot {
+        // There is no console to wait on, so wait for the install
+        // boot to power off
+        if !opts.quiet {
+            io.print("Waiting for the installation to complete.");
+        }
+        installer::wait_for_install(conn, &name, INSTALL_POLL_INTERVAL)?;
+        if !opts.quiet {
+            io.print("Domain has shutdown. Continuing.");
+        }
+    }
+    if !opts.quiet {
+        io.print("Domain creation completed.");
+    }
+    if opts.noreboot || !postboot {
+        io.print(&format!(
+            "You can restart your domain by running:\n  virsh --connect {} start {}",
+            conn.uri(),
+            name
+        ));
+        return Ok(());
+    }
+    if !opts.quiet {
+        io.print("Restarting guest.");
+    }
+    installer::restart_installed(conn, &name, &final_xml)
+}
+
+/// Command line entry point. Returns the process exit code.
+pub fn main<S: AsRef<str>>(args: &[S], io: &mut CliIo, conn: Option<&dyn Connection>) -> i32 {
+    match parse_args(args).and_then(|opts| run(opts, io, conn)) {
+        Ok(()) => 0,
+        Err(e) => {
+            io.error(&e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::testdriver;
+    use crate::urlfetcher::MockFetcher;
+
+    fn datadir() -> String {
+        format!("{}/../tests/data/cli/cloudinit", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    /// Run virt-install, returning (exit code, stdout, stderr)
+    fn run_cli(cmd: &str, conn: &dyn Connection) -> (i32, String, String) {
+        let args = cli::shlex_split(cmd).unwrap();
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let ret = {
+            let mut io = CliIo {
+                stdin: None,
+                stdout: &mut out,
+                stderr: &mut err,
+            };
+            // No network in tests, so URLs are served by a mock
+            let ret = parse_args(&args).and_then(|mut opts| {
+                opts.fetcher = Some(Arc::new(MockFetcher));
+                run(opts, &mut io, Some(conn))
+            });
+            match ret {
+                Ok(()) => 0,
+                Err(e) => {
+                    io.error(&e);
+                    1
+                }
+            }
+        };
+        (
+            ret,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    fn compare_file(name: &str) -> String {
+        std::fs::read_to_string(format!(
+            "{}/../tests/data/cli/compare/virt-install-{}.xml",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        ))
+        .unwrap()
+    }
+
+    /// The parts of each domain in virt-install output that the install
+    /// method sets up, with run specific paths scrubbed like the test
+    /// suite does. Compare files come from qemu connections with
+    /// predictable defaults the test driver doesn't have, so the rest of
+    /// the XML differs.
+    fn install_parts(out: &str) -> Vec<Vec<String>> {
+        let topdir = format!("{}/../", env!("CARGO_MANIFEST_DIR"));
+        let out = out.replace(&topdir, "TESTSUITE_SCRUBBED/");
+        // Files fetched or made for the install get a random prefix
+        let scrub = |path: &str| match path.rsplit_once("/virtinst-") {
+            Some((_, name)) => format!(
+                "/VIRTINST-TESTSUITE/{}",
+                name.split_once('-').map_or(name, |(_, n)| n)
+            ),
+            None => path.to_string(),
+        };
+        out.split("</domain>")
+            .filter_map(|doc| doc.split_once("<domain"))
+            .map(|(_, doc)| {
+                let xml = Element::parse(&format!("<domain{}</domain>", doc)).unwrap();
+                let mut parts: Vec<Element> = ["./os/kernel", "./os/initrd"]
+                    .iter()
+                    .filter_map(|xpath| xml.find(xpath).cloned())
+                    .collect();
+                for part in &mut parts {
+                    let text = scrub(&part.text());
+                    part.set_text(Some(&text));
+                }
+                for xpath in ["./os/cmdline", "./os/boot", "./os/smbios", "./sysinfo"] {
+                    parts.extend(xml.find_all(xpath).into_iter().cloned());
+                }
+                parts.extend(xml.find("./on_reboot").cloned());
+                for disk in xml.find_all("./devices/disk") {
+                    if disk.attr("device") != Some("cdrom") {
+                        continue;
+                    }
+                    let mut source = disk.find("./source").cloned();
+                    if let Some(source) = &mut source {
+                        let file = scrub(source.attr("file").unwrap_or_default());
+                        source.set_attr("file", Some(&file));
+                    }
+                    parts.push(source.unwrap_or_else(|| Element::new("nosource")));
+                }
+                parts.iter().map(Element::to_xml).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cloud_init() {
+        let conn = testdriver();
+        let admin_password = format!(
+            "{}/../tests/data/cli/unattended/admin-password.txt",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let cases = [
+            ("root-password-generate=yes,disable=yes", "", "default"),
+            (
+                "root-password-generate=yes,disable=no",
+                "--sysinfo system.serial=foobar --boot uefi",
+                "options1",
+            ),
+            (
+                &format!(
+                    "root-password-file={},root-ssh-key={1}/ssh-key.txt,\
+                     clouduser-ssh-key={1}/ssh-key2.txt",
+                    admin_password,
+                    datadir()
+                ),
+                "--boot smbios.mode=none",
+                "options2",
+            ),
+            (
+                &format!("ssh-key={}/ssh-key.txt", datadir()),
+                "",
+                "options3",
+            ),
+            (
+                &format!(
+                    "user-data={0}/user-data.txt,meta-data={0}/meta-data.txt",
+                    datadir()
+                ),
+                "",
+                "options4",
+            ),
+            (
+                &format!(
+                    "user-data={0}/user-data.txt,meta-data={0}/meta-data.txt,\
+                     network-config={0}/network-config.txt",
+                    datadir()
+                ),
+                "",
+                "options5",
+            ),
+            (
+                "user-data=https://example.com,meta-data=http://example.com,\
+                 network-config=ftp://foobar.com",
+                "",
+                "options6",
+            ),
+        ];
+        for (opts, extra, name) in cases {
+            let cmd = format!(
+                "--memory 64 --graphics none --print-xml --disk /pool-dir/testvol1.img \
+                 --os-variant fedora28 --cloud-init {} {}",
+                opts, extra
+            );
+            let (ret, out, err) = run_cli(&cmd, &conn);
+            assert_eq!(ret, 0, "{}: {}", cmd, err);
+            let expected = compare_file(&format!("cloud-init-{}", name));
+            let (userdata, xml) = expected.split_at(expected.find("<domain").unwrap());
+            assert_eq!(install_parts(&out), install_parts(xml), "{}", cmd);
+
+            // Some test suite runs print the scrubbed userdata they generate
+            if !userdata.is_empty() {
+                let mut data = parse_cloud_init(Some(opts)).unwrap();
+                let content = data.create_userdata_content().unwrap().unwrap();
+                assert_eq!(
+                    crate::cloudinit::scrub_password(&content) + "\n",
+                    userdata,
+                    "{}",
+                    cmd
+                );
+            }
+        }
+
+        // Bare --cloud-init generates a root password and prints it
+        let (ret, out, err) = run_cli(
+            "--name cloudvm2 --memory 1024 --osinfo linux2020 --disk /pool-dir/testvol1.img \
+             --cloud-init --noautoconsole",
+            &conn,
+        );
+        assert_eq!(ret, 0, "{}", err);
+        assert!(err.contains(
+            "WARNING  Defaulting to --cloud-init root-password-generate=yes,disable=yes"
+        ));
+        let passwd = out
+            .lines()
+            .find_map(|l| l.strip_prefix("Password for first root login is: "))
+            .unwrap();
+        assert_eq!(passwd.len(), 16);
+        let xml = conn.domain_xml("cloudvm2", true).unwrap();
+        assert!(!xml.contains("ds=nocloud"));
+        assert!(!xml.contains("-cloudinit.iso"));
+
+        let (ret, _, err) = run_cli(
+            "--name cloudvm3 --memory 1024 --osinfo linux2020 --disk none \
+             --cloud-init user-data=badurl://example.com",
+            &conn,
+        );
+        assert_eq!(ret, 1);
+        assert!(err.contains("Couldn't acquire file badurl://example.com"));
+
+        let (ret, _, err) = run_cli("--name foo --cloud-init idontexist=1", &conn);
+        assert_eq!(ret, 1);
+        assert!(err.contains("Unknown --cloud-init options: [\"idontexist\"]"));
+    }
+
+    #[test]
+    fn test_location_install() {
+        let conn = testdriver();
+        let data = format!("{}/../tests/data", env!("CARGO_MANIFEST_DIR"));
+        let cases = [
+            (
+                format!(
+                    "--disk none --location location={0}/fakemedia/fakefedoratree \
+                     --initrd-inject {0}/../../virt-install --extra-args ks=file:/virt-install",
+                    data
+                ),
+                "initrd-inject",
+            ),
+            (
+                // Relative like the test suite's, so the final XML keeps
+                // the media as the location doesn't match the disk path
+                "--disk /pool-dir/testvol1.img --nonetworks \
+                 --location ../tests/data/fakemedia/fake-fedora17-tree.iso"
+                    .to_string(),
+                "location-iso",
+            ),
+        ];
+        for (args, name) in cases {
+            let cmd = format!("--memory 64 --graphics none --print-xml {}", args);
+            let (ret, out, err) = run_cli(&cmd, &conn);
+            assert_eq!(ret, 0, "{}: {}", cmd, err);
+            assert_eq!(
+                install_parts(&out),
+                install_parts(&compare_file(name)),
+                "{}",
+                cmd
+            );
+            let guest = Guest::parse(&format!(
+                "{}</domain>",
+                out.split("</domain>").next().unwrap()
+            ))
+            .unwrap();
+            assert_eq!(guest.osinfo().map(|o| o.name.as_str()), Some("fedora17"));
+        }
+
+        let (ret, _, err) = run_cli(
+            "--memory 64 --disk none --location kernel=foo,initrd=bar",
+            &conn,
+        );
+        assert_eq!(ret, 1);
+        assert!(
+            err.contains("location kernel/initrd may only be specified with a location URL/path")
+        );
+        let (ret, _, err) = run_cli(
+            "--memory 64 --disk none --cdrom /dev/null --initrd-inject /dev/null",
+            &conn,
+        );
+        assert_eq!(ret, 1);
+        assert!(err.contains("Install method does not support initrd injections."));
+        let empty = tempfile::tempdir().unwrap();
+        let (ret, _, err) = run_cli(
+            &format!(
+                "--memory 64 --disk none --location {}",
+                empty.path().display()
+            ),
+            &conn,
+        );
+        assert_eq!(ret, 1);
+        assert!(err.contains(
+            "Error validating install location: Could not find an installable distribution"
+        ));
+        let (ret, _, err) = run_cli(
+            &format!(
+                "--memory 64 --disk none --location {}/urldetect/empty",
+                data
+            ),
+            &conn,
+        );
+        assert_eq!(ret, 1);
+        assert!(err.contains("Validating install media"));
+    }
+
+    #[test]
+    fn test_unattended() {
+        let conn = testdriver();
+        let data = format!("{}/../tests/data", env!("CARGO_MANIFEST_DIR"));
+        let (ret, out, err) = run_cli(
+            &format!(
+                "--name unattendedvm --memory 1024 --disk none --print-xml --osinfo fedora26 \
+                 --location {0}/fakemedia/fakefedoratree \
+                 --unattended profile=jeos,admin-password-file={0}/cli/unattended/admin-password.txt",
+                data
+            ),
+            &conn,
+        );
+        assert_eq!((ret, err.as_str()), (0, ""));
+        let mut docs = out.split("</domain>\n");
+        let initial = Guest::parse(&format!("{}</domain>", docs.next().unwrap())).unwrap();
+        assert_eq!(
+            initial.xml.get("./os/cmdline").as_deref(),
+            Some("ks=file:/fedora.ks")
+        );
+        assert_eq!(initial.xml.get("./on_reboot").as_deref(), Some("destroy"));
+
+        // The desktop profile needs a user password
+        let (ret, _, err) = run_cli(
+            &format!(
+                "--name unattendedvm --memory 1024 --disk none --osinfo fedora26 \
+                 --location {0}/fakemedia/fakefedoratree --unattended \
+                 user-login=tester,admin-password-file={0}/cli/unattended/admin-password.txt",
+                data
+            ),
+            &conn,
+        );
+        assert_eq!(ret, 1);
+        assert!(err.contains("fedora26 requires the user-password to be set."));
+
+        let (ret, _, err) = run_cli(
+            "--name foo --memory 64 --disk none --cdrom /dev/null --unattended --cloud-init",
+            &conn,
+        );
+        assert_eq!(ret, 1);
+        assert!(err.contains("Cannot use --unattended and --cloud-init at the same time"));
+    }
+
+    #[test]
+    fn test_unattended_compare() {
+        let conn = testdriver();
+        let data = format!("{}/../tests/data", env!("CARGO_MANIFEST_DIR"));
+        let cmd = format!(
+            "--memory 64 --disk none --graphics none --print-xml --osinfo win7 \
+             --cdrom {0}/fakemedia/fake-win7.iso \
+             --unattended profile=desktop,admin-password-file={0}/cli/unattended/admin-password.txt",
+            data
+        );
+        let (ret, out, err) = run_cli(&cmd, &conn);
+        assert_eq!(ret, 0, "{}", err);
+        assert_eq!(
+            install_parts(&out),
+            install_parts(&compare_file("osinfo-win7-unattended")),
+            "{}",
+            cmd
+        );
+
+        // There's no --install, so check the script the fedora26 URL
+        // install would inject
+        let mut guest = Guest::new();
+        guest.xml.set("./os/type/@arch", Some("x86_64"));
+        guest.set_os_name("fedora26").unwrap();
+        let unattended = parse_unattended(Some(&format!(
+            "profile=desktop,admin-password-file={0}/admin-password.txt,\
+             user-password-file={0}/user-password.txt,product-key=1234,\
+             user-login=foobar,reg-login=regtest",
+            format!("{}/cli/unattended", data)
+        )))
+        .unwrap();
+        let url = osdb()
+            .lookup_os("fedora26")
+            .unwrap()
+            .get_location("x86_64", unattended.profile.as_deref())
+            .unwrap();
+        let scripts = crate::unattended::prepare_install_scripts(
+            &guest,
+            &unattended,
+            Some(&url),
+            "network",
+            "initrd",
+        )
+        .unwrap();
+        let expected = compare_file("osinfo-url-unattended");
+        let expected = Element::parse(&format!(
+            "{}</domain>",
+            expected.split("</domain>").next().unwrap()
+        ))
+        .unwrap();
+        assert_eq!(
+            scripts[0].generate_cmdline().unwrap(),
+            expected.get("./os/cmdline")
+        );
+    }
+
+    #[test]
+    fn test_install_restart() {
+        let conn = testdriver();
+        // Power off the install boot once it shows up, like a finished
+        // installer would
+        let (ret, out, err) = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                while !conn
+                    .lookup_domain("restartvm")
+                    .is_ok_and(|d| d.state.is_active())
+                {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                conn.destroy_domain("restartvm").unwrap();
+            });
+            run_cli(
+                "--name restartvm --memory 64 --disk none --osinfo fedora29 --cdrom /dev/null",
+                &conn,
+            )
+        });
+        assert_eq!(ret, 0, "{}", err);
+        assert!(out.contains(
+            "Domain has shutdown. Continuing.\nDomain creation completed.\nRestarting guest."
+        ));
+        assert!(conn.lookup_domain("restartvm").unwrap().state.is_active());
+        let live = Guest::parse(&conn.domain_xml("restartvm", false).unwrap()).unwrap();
+        assert_eq!(live.xml.get("./on_reboot"), None);
+        assert!(live.xml.find("./devices/disk/source").is_none());
+
+        // --noreboot leaves the installed guest alone
+        let (ret, out, err) = run_cli(
+            "--name norebootvm --memory 64 --disk none --osinfo fedora29 --cdrom /dev/null \
+             --noreboot",
+            &conn,
+        );
+        assert_eq!(ret, 0, "{}", err);
+        assert!(out.contains("You can restart your domain by running:"));
+        assert!(!out.contains("Restarting guest."));
+    }
+
+    #[test]
+    fn test_required_options() {
+        let conn = testdriver();
+        let (ret, _, err) = run_cli("--name foo --osinfo generic", &conn);
+        assert_eq!(ret, 1);
+        assert!(err.contains("--memory amount in MiB is required"));
+        assert!(err.contains("--disk storage must be specified (override with --disk none)"));
+        assert!(err.contains("An install method must be specified"));
+
+        let (ret, out, err) = run_cli(
+            "--memory 1024 --osinfo linux2020 --disk none --import --graphics none --print-xml",
+            &conn,
+        );
+        assert_eq!((ret, err.as_str()), (0, ""));
+        let guest = Guest::parse(&out).unwrap();
+        assert_eq!(guest.name().as_deref(), Some("vm1"));
+        assert!(guest.devices("graphics").is_empty());
+        assert_eq!(guest.devices("interface")[0].attr("type"), Some("user"));
+        assert_eq!(guest.devices("console").len(), 1);
+    }
+
+    #[test]
+    fn test_osinfo() {
+        let conn = testdriver();
+        let (ret, out, _) = run_cli("--osinfo list", &conn);
+        assert_eq!(ret, 0);
+        assert!(out.lines().any(|l| l == "fedora-rawhide, fedora-unknown"));
+
+        let (ret, _, err) = run_cli("--memory 1024 --disk none --import", &conn);
+        assert_eq!(ret, 1);
+        assert!(err.contains("--osinfo/--os-variant OS name is required"));
+        assert!(err.contains("such as: linux2020"));
+        let (ret, _, err) = run_cli("--osinfo idontexist --disk none --import", &conn);
+        assert_eq!(ret, 1);
+        assert!(err.contains("Unknown OS name 'idontexist'"));
+
+        // Memory and vCPUs default to the OS recommendations
+        let (ret, out, err) = run_cli("--osinfo fedora29 --disk none --import --dry-run", &conn);
+        assert_eq!((ret, err.as_str()), (0, ""));
+        assert!(out.contains("Using fedora29 default --memory 2048"));
+        let (ret, out, _) = run_cli(
+            "--os-variant id=http://microsoft.com/win/7 --disk /pool-dir/testvol1.img \
+             --import --print-xml",
+            &conn,
+        );
+        assert_eq!(ret, 0);
+        let guest = Guest::parse(&out).unwrap();
+        assert_eq!(guest.osinfo().map(|o| o.name.as_str()), Some("win7"));
+        assert_eq!(guest.vcpus(), Some(1));
+        assert_eq!(
+            guest.xml.get("./clock/@offset").as_deref(),
+            Some("localtime")
+        );
+        // No virtio drivers on win7 media
+        assert_eq!(
+            guest.devices("disk")[0].get("./target/@bus").as_deref(),
+            Some("ide")
+        );
+
+        let (ret, _, err) = run_cli(
+            "--memory 64 --osinfo require=off --disk none --import --print-xml",
+            &conn,
+        );
+        assert_eq!(ret, 0);
+        assert!(err.contains("Using --osinfo generic, VM performance may suffer."));
+    }
+
+    #[test]
+    fn test_disk_storage() {
+        let conn = testdriver();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("new.img");
+        let path = path.to_str().unwrap();
+        let (ret, _, err) = run_cli(
+            &format!(
+                "--name newdisk --memory 64 --osinfo fedora29 --import --disk {}",
+                path
+            ),
+            &conn,
+        );
+        assert_eq!(ret, 1);
+        assert!(err.contains("Must specify storage creation parameters for non-existent path"));
+
+        // The new volume goes in a pool made for the temp dir
+        let (ret, _, err) = run_cli(
+            &format!(
+                "--name newdisk --memory 64 --osinfo fedora29 --import --noautoconsole \
+                 --disk {},size=2,format=raw",
+                path
+            ),
+            &conn,
+        );
+        assert_eq!(ret, 0, "{}", err);
+        let backend = StorageBackend::new(&conn, path).unwrap();
+        assert!(backend.exists());
+        assert_eq!(backend.size(), 2 << 30);
+        let guest = Guest::parse(&conn.domain_xml("newdisk", false).unwrap()).unwrap();
+        let disk = guest.devices("disk")[0];
+        assert_eq!(disk.get("./driver/@type").as_deref(), Some("raw"));
+    }
+}
@@ -0,0 +1,1115 @@
+// virt-xml: edit libvirt XML using command line options
+// (port of virtinst/virtxml.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! The virt-xml-rs binary is a thin wrapper around `main`. Keeping the
+//! logic here lets the test suite drive the whole tool in memory, against
+//! the test driver and XML fed through stdin.
+
+use crate::cli::{
+    self, CliIo, OptionSpec, VirtCliParser, check_option_introspection, fail_conflicting,
+    get_domain_and_guest,
+};
+use crate::connection::{self, AffectFlags, Connection, DomainInfo};
+use crate::guest::Guest;
+use crate::xmlapi::{Element, unindent_device_xml};
+
+const USAGE: &str = "usage: virt-xml [options]
+
+Edit libvirt XML using command line options.
+
+positional arguments:
+  domain                Domain name, id, or uuid
+
+options:
+  -h, --help            show this help message and exit
+  --version             show program's version number and exit
+  -c URI, --connect URI
+                        Connect to hypervisor with libvirt URI
+
+XML actions:
+  --edit [EDIT]         Edit VM XML. Examples:
+                        --edit --disk ...     (edit first disk device)
+                        --edit 2 --disk ...   (edit second disk device)
+                        --edit all --disk ... (edit all disk devices)
+                        --edit target=hda --disk ... (edit disk 'hda')
+  --remove-device       Remove specified device. Examples:
+                        --remove-device --disk 1 (remove first disk)
+                        --remove-device --disk all (remove all disks)
+                        --remove-device --disk /some/path
+  --add-device          Add specified device. Example:
+                        --add-device --disk ...
+  --build-xml           Output built device XML. Domain is optional but
+                        recommended to ensure optimal defaults.
+
+Output options:
+  --update              Apply changes to the running VM.
+                        With --add-device, this is a hotplug operation.
+                        With --remove-device, this is a hotunplug operation.
+                        With --edit, this is an update device operation.
+  --define              Force defining the domain. Only required if a --print
+                        option was specified.
+  --no-define           Force not defining the domain.
+  --start               Start the domain.
+  --print-diff          Only print the requested change, in diff format
+  --print-xml           Only print the requested change, in full XML format
+  --confirm             Require confirmation before saving any results.
+
+Miscellaneous Options:
+  -q, --quiet           Suppress non-error output
+  -d, --debug           Print debugging information
+
+XML options take the same suboptions as virt-install. Use '--option=?' or
+'--option help' to see the available suboptions, for example '--disk=?'.
+";
+
+/// One of the mutually exclusive XML actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionName {
+    Edit,
+    AddDevice,
+    RemoveDevice,
+    BuildXml,
+}
+
+impl ActionName {
+    const ALL: [ActionName; 4] = [
+        ActionName::Edit,
+        ActionName::AddDevice,
+        ActionName::RemoveDevice,
+        ActionName::BuildXml,
+    ];
+
+    pub fn cliname(&self) -> &'static str {
+        match self {
+            ActionName::Edit => "edit",
+            ActionName::AddDevice => "add-device",
+            ActionName::RemoveDevice => "remove-device",
+            ActionName::BuildXml => "build-xml",
+        }
+    }
+}
+
+/// One XML action (like `--edit`) paired with one XML option (like
+/// `--disk`) and its values
+pub struct Action {
+    pub name: ActionName,
+    /// For `--edit 1` this is "1"
+    pub selector: Option<String>,
+    pub spec: &'static OptionSpec,
+    /// The `--disk` values, one per occurrence on the command line
+    pub values: Vec<String>,
+}
+
+/// Parsed command line
+#[derive(Default)]
+pub struct Options {
+    pub connect: Option<String>,
+    pub domain: Option<String>,
+    /// `--edit` was passed, with its optional selector
+    pub edit: Option<Option<String>>,
+    pub remove_device: bool,
+    pub add_device: bool,
+    pub build_xml: bool,
+    pub update: bool,
+    /// None unless `--define` or `--no-define` was passed
+    pub define: Option<bool>,
+    pub start: bool,
+    pub print_diff: bool,
+    pub print_xml: bool,
+    pub confirm: bool,
+    pub quiet: bool,
+    pub debug: bool,
+    pub help: bool,
+    pub version: bool,
+    /// XML options like `--disk`, in command line order
+    pub xmlopts: Vec<(&'static OptionSpec, Vec<String>)>,
+}
+
+impl Options {
+    fn action_selected(&self, name: ActionName) -> bool {
+        match name {
+            ActionName::Edit => self.edit.is_some(),
+            ActionName::AddDevice => self.add_device,
+            ActionName::RemoveDevice => self.remove_device,
+            ActionName::BuildXml => self.build_xml,
+        }
+    }
+
+    fn push_xmlopt(&mut self, spec: &'static OptionSpec, value: String) {
+        match self
+            .xmlopts
+            .iter_mut()
+            .find(|(s, _)| std::ptr::eq(*s, spec))
+        {
+            Some((_, values)) => values.push(value),
+            None => self.xmlopts.push((spec, vec![value])),
+        }
+    }
+}
+
+/// Whether argparse would take `arg` as an option value rather than as
+/// the next option
+fn is_value(arg: &str) -> bool {
+    !arg.starts_with('-') || arg[1..].parse::<i64>().is_ok()
+}
+
+/// Parse the command line arguments, without the program name
+pub fn parse_args<S: AsRef<str>>(args: &[S]) -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut i = 0;
+    let next_value = |i: &mut usize, inline: Option<&str>, flag: &str| {
+        if let Some(v) = inline {
+            return Ok(v.to_string());
+        }
+        *i += 1;
+        args.get(*i)
+            .map(|a| a.as_ref().to_string())
+            .ok_or_else(|| format!("argument {}: expected one argument", flag))
+    };
+
+    while i < args.len() {
+        let arg = args[i].as_ref();
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) if arg.starts_with("--") => (f, Some(v)),
+            _ => (arg, None),
+        };
+        let set_define = |opts: &mut Options, val: bool| {
+            if opts.define.is_some_and(|d| d != val) {
+                let (a, b) = if val {
+                    ("--define", "--no-define")
+                } else {
+                    ("--no-define", "--define")
+                };
+                return Err(format!("argument {}: not allowed with argument {}", a, b));
+            }
+            opts.define = Some(val);
+            Ok(())
+        };
+        match flag {
+            "-h" | "--help" => opts.help = true,
+            "--version" => opts.version = true,
+            "-c" | "--connect" => opts.connect = Some(next_value(&mut i, inline, flag)?),
+            "--edit" => {
+                let sel = match inline {
+                    Some(v) => Some(v.to_string()),
+                    None => match args.get(i + 1).map(|a| a.as_ref()) {
+                        Some(v) if is_value(v) => {
+                            i += 1;
+                            Some(v.to_string())
+                        }
+                        _ => None,
+                    },
+                };
+                opts.edit = Some(sel);
+            }
+            "--remove-device" => opts.remove_device = true,
+            "--add-device" => opts.add_device = true,
+            "--build-xml" => opts.build_xml = true,
+            "--update" => opts.update = true,
+            "--define" => set_define(&mut opts, true)?,
+            "--no-define" => set_define(&mut opts, false)?,
+            "--start" => opts.start = true,
+            "--print-diff" => opts.print_diff = true,
+            "--print-xml" => opts.print_xml = true,
+            "--confirm" => opts.confirm = true,
+            "-q" | "--quiet" => opts.quiet = true,
+            "-d" | "--debug" => opts.debug = true,
+            f if f.starts_with("--") => {
+                let spec = cli::lookup_parser(f)
+                    .ok_or_else(|| format!("unrecognized arguments: {}", arg))?;
+                let value = next_value(&mut i, inline, f)?;
+                opts.push_xmlopt(spec, value);
+            }
+            f if f.starts_with('-') && f.len() > 1 => {
+                return Err(format!("unrecognized arguments: {}", arg));
+            }
+            _ if opts.domain.is_none() => opts.domain = Some(arg.to_string()),
+            _ => return Err(format!("unrecognized arguments: {}", arg)),
+        }
+        i += 1;
+    }
+    Ok(opts)
+}
+
+fn check_action_collision(opts: &Options) -> Result<(ActionName, Option<String>), String> {
+    let collisions: Vec<ActionName> = ActionName::ALL
+        .into_iter()
+        .filter(|a| opts.action_selected(*a))
+        .collect();
+    let flags = |names: &[ActionName]| {
+        names
+            .iter()
+            .map(|a| format!("--{}", a.cliname()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match collisions.as_slice() {
+        [] => Err(format!(
+            "One of {} must be specified.",
+            flags(&ActionName::ALL)
+        )),
+        [name] => {
+            let selector = match name {
+                ActionName::Edit => opts.edit.clone().flatten(),
+                _ => None,
+            };
+            Ok((*name, selector))
+        }
+        _ => Err(format!("Conflicting options {}", flags(&collisions))),
+    }
+}
+
+fn check_xmlopt_collision(opts: &Options) -> Result<(&'static OptionSpec, Vec<String>), String> {
+    match opts.xmlopts.as_slice() {
+        [] => Err("No change specified.".to_string()),
+        [(spec, values)] => Ok((*spec, values.clone())),
+        many => Err(format!(
+            "Only one change operation may be specified (conflicting options [{}])",
+            many.iter()
+                .map(|(s, _)| format!("'{}'", s.cli_flag_name()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn validate_action(action: &Action, opts: &Options) -> Result<(), String> {
+    let flag = action.spec.cli_flag_name();
+    if action.spec.xpath.is_none() && action.name == ActionName::BuildXml {
+        return Err(format!("--build-xml not supported for {}", flag));
+    }
+    if !action.spec.is_list {
+        if action.name == ActionName::RemoveDevice {
+            return Err(format!("Cannot use --remove-device with {}", flag));
+        }
+        if action.name == ActionName::AddDevice {
+            return Err(format!("Cannot use --add-device with {}", flag));
+        }
+    }
+    if opts.update && action.spec.xpath.is_none() {
+        return Err(format!("Don't know how to --update for {}", flag));
+    }
+    Ok(())
+}
+
+/// Pick the single XML action and XML option from the command line
+pub fn parse_action(opts: &Options) -> Result<Action, String> {
+    let (spec, values) = check_xmlopt_collision(opts)?;
+    let (name, selector) = check_action_collision(opts)?;
+    let action = Action {
+        name,
+        selector,
+        spec,
+        values,
+    };
+    validate_action(&action, opts)?;
+    Ok(action)
+}
+
+/// xpath of the nth (0 based) object of the option's type
+fn object_xpath(spec: &OptionSpec, idx: usize) -> String {
+    let xpath = spec.xpath.unwrap_or(".");
+    if spec.is_list {
+        format!("{}[{}]", xpath, idx + 1)
+    } else {
+        xpath.to_string()
+    }
+}
+
+/// Resolve an `--edit` or `--remove-device` selector to object indexes:
+/// a 1 based index (negative counts from the end), `all`, or a
+/// suboption string the objects must match.
+fn find_objects_to_edit(
+    guest: &Guest,
+    action_name: &str,
+    editval: Option<&str>,
+    spec: &'static OptionSpec,
+) -> Result<Vec<usize>, String> {
+    let count = match (spec.xpath, spec.is_list) {
+        (Some(xpath), true) => guest.xml.count(xpath),
+        _ => 1,
+    };
+    let flag = spec.cli_flag_name();
+
+    let idx = match editval {
+        None => Some(1),
+        Some(v) => v.parse::<i64>().ok(),
+    };
+    if let Some(idx) = idx {
+        if idx == 0 {
+            return Err(format!(
+                "Invalid --edit option '{}'",
+                editval.unwrap_or_default()
+            ));
+        }
+        if count == 0 {
+            return Err(format!("No {} objects found in the XML", flag));
+        }
+        if (count as u64) < idx.unsigned_abs() {
+            return Err(if count == 1 {
+                format!(
+                    "'--edit {}' requested but there's only {} {} object in the XML",
+                    idx, count, flag
+                )
+            } else {
+                format!(
+                    "'--edit {}' requested but there are only {} {} objects in the XML",
+                    idx, count, flag
+                )
+            });
+        }
+        let pos = if idx > 0 {
+            idx as usize - 1
+        } else {
+            count - idx.unsigned_abs() as usize
+        };
+        return Ok(vec![pos]);
+    }
+
+    let editval = editval.unwrap_or_default();
+    if editval == "all" {
+        return Ok((0..count).collect());
+    }
+    let found = VirtCliParser::new(spec, editval)?.lookup_objects(guest)?;
+    if found.is_empty() {
+        return Err(format!(
+            "No matching objects found for --{} {}",
+            action_name, editval
+        ));
+    }
+    Ok(found)
+}
+
+fn action_edit(action: &Action, guest: &mut Guest) -> Result<Vec<Element>, String> {
+    let spec = action.spec;
+    let selector = action.selector.as_deref();
+    let xpaths: Vec<String> = if spec.xpath.is_some() {
+        find_objects_to_edit(guest, "edit", selector, spec)?
+            .into_iter()
+            .map(|idx| object_xpath(spec, idx))
+            .collect()
+    } else {
+        if let Some(sel) = selector
+            && sel != "1"
+            && sel != "all"
+        {
+            return Err(format!(
+                "'--edit {}' doesn't make sense with {}, just use empty '--edit'",
+                sel,
+                spec.cli_flag_name()
+            ));
+        }
+        vec![".".to_string()]
+    };
+
+    // Go back to front, so a clearxml=yes removing an object doesn't
+    // shift the xpaths of the ones still to edit
+    let mut devs = Vec::new();
+    for xpath in xpaths.iter().rev() {
+        for optstr in &action.values {
+            VirtCliParser::new(spec, optstr)?.apply(&mut guest.xml, xpath, true)?;
+        }
+        if spec.xpath.is_some()
+            && let Some(dev) = guest.xml.find(xpath)
+        {
+            devs.push(dev.clone());
+        }
+    }
+    devs.reverse();
+    Ok(devs)
+}
+
+fn action_add_device(
+    action: &Action,
+    guest: &mut Guest,
+    input_devs: Option<Vec<Element>>,
+) -> Result<Vec<Element>, String> {
+    if let Some(devs) = input_devs {
+        for dev in &devs {
+            guest.add_device(dev.clone());
+        }
+        return Ok(devs);
+    }
+    let mut devs = Vec::new();
+    for optstr in &action.values {
+        let mut dev = build_device(action.spec, optstr, guest)?;
+        dev.prettify();
+        guest.add_device(dev.clone());
+        devs.push(dev);
+    }
+    Ok(devs)
+}
+
+fn action_remove_device(action: &Action, guest: &mut Guest) -> Result<Vec<Element>, String> {
+    let spec = action.spec;
+    let tag = spec.tag().unwrap_or_default();
+    let selector = action.values.last().map(String::as_str);
+    let mut indexes = find_objects_to_edit(guest, "remove-device", selector, spec)?;
+    indexes.sort_unstable();
+    let mut devs = Vec::new();
+    for idx in indexes.into_iter().rev() {
+        if let Some(dev) = guest.remove_device(tag, idx) {
+            devs.push(dev);
+        }
+    }
+    devs.reverse();
+    Ok(devs)
+}
+
+/// Build a standalone object from an option string, with defaults
+/// filled in from the guest it's meant for
+fn build_device(spec: &'static OptionSpec, optstr: &str, guest: &Guest) -> Result<Element, String> {
+    let (mut dev, _) = VirtCliParser::new(spec, optstr)?.build()?;
+    guest.set_device_defaults(&mut dev)?;
+    Ok(dev)
+}
+
+fn action_build_xml(action: &Action, guest: &Guest) -> Result<Vec<Element>, String> {
+    let mut devs = Vec::new();
+    for optstr in &action.values {
+        let mut dev = build_device(action.spec, optstr, guest)?;
+        dev.prettify();
+        devs.push(dev);
+    }
+    Ok(devs)
+}
+
+fn perform_action(
+    action: &Action,
+    guest: &mut Guest,
+    input_devs: Option<Vec<Element>>,
+) -> Result<Vec<Element>, String> {
+    match action.name {
+        ActionName::AddDevice => action_add_device(action, guest, input_devs),
+        ActionName::RemoveDevice => action_remove_device(action, guest),
+        ActionName::Edit => action_edit(action, guest),
+        ActionName::BuildXml => Err(format!(
+            "perform_action() incorrectly called with action_name={}",
+            action.name.cliname()
+        )),
+    }
+}
+
+/// Unified diff between two XML documents, empty if they're the same
+pub fn get_diff(origxml: &str, newxml: &str) -> String {
+    let diff = similar::TextDiff::from_lines(origxml, newxml)
+        .unified_diff()
+        .context_radius(3)
+        .header("Original XML", "Altered XML")
+        .to_string();
+    if diff.is_empty() {
+        log::debug!("No XML diff, didn't generate any change.");
+    } else {
+        log::debug!("XML diff:\n{}", diff);
+    }
+    diff
+}
+
+/// Perform the requested XML edits locally, without submitting them,
+/// and print the result if asked to. Returns the affected devices and
+/// the altered guest.
+fn prepare_changes(
+    orig: &Guest,
+    opts: &Options,
+    action: &Action,
+    input_devs: Option<Vec<Element>>,
+    io: &mut CliIo,
+) -> Result<(Vec<Element>, Guest), String> {
+    let origxml = orig.get_xml();
+    let mut guest = orig.clone();
+    let devs = perform_action(action, &mut guest, input_devs)?;
+    let newxml = guest.get_xml();
+    let diff = get_diff(&origxml, &newxml);
+
+    if diff.is_empty() {
+        io.warn("No XML diff was generated. The requested changes will have no effect.");
+    }
+    if opts.print_diff {
+        if !diff.is_empty() {
+            io.print(&diff);
+        }
+    } else if opts.print_xml {
+        io.print(&newxml);
+    }
+    Ok((devs, guest))
+}
+
+fn update_changes(
+    conn: &dyn Connection,
+    domain: &DomainInfo,
+    devs: &[Element],
+    action: &Action,
+    confirm: bool,
+    io: &mut CliIo,
+) -> Result<(), String> {
+    type DevOp = fn(&dyn Connection, &str, &str, AffectFlags) -> Result<(), String>;
+    let (msg_confirm, msg_success, msg_fail, op): (&str, &str, &str, DevOp) = match action.name {
+        ActionName::AddDevice => (
+            "Hotplug this device to the guest",
+            "Device hotplug successful.",
+            "Error attempting device hotplug",
+            |c, n, x, f| c.attach_device(n, x, f),
+        ),
+        ActionName::RemoveDevice => (
+            "Hotunplug this device from the guest",
+            "Device hotunplug successful.",
+            "Error attempting device hotunplug",
+            |c, n, x, f| c.detach_device(n, x, f),
+        ),
+        ActionName::Edit => (
+            "Update this device for the guest",
+            "Device update successful.",
+            "Error attempting device update",
+            |c, n, x, f| c.update_device(n, x, f),
+        ),
+        ActionName::BuildXml => {
+            return Err(format!(
+                "update_changes() incorrectly called with action={}",
+                action.name.cliname()
+            ));
+        }
+    };
+
+    for dev in devs {
+        let xml = unindent_device_xml(&dev.to_xml());
+        if confirm
+            && !io.prompt_yes_or_no(&format!(
+                "{}\n\n{} '{}'?",
+                xml.trim_end(),
+                msg_confirm,
+                domain.name
+            ))?
+        {
+            continue;
+        }
+        op(conn, &domain.name, &xml, AffectFlags::Live)
+            .map_err(|e| format!("{}: {}", msg_fail, e))?;
+        io.print(msg_success);
+        if confirm {
+            io.print("");
+        }
+    }
+    Ok(())
+}
+
+fn define_changes(
+    conn: &dyn Connection,
+    guest: &Guest,
+    confirm: bool,
+    io: &mut CliIo,
+) -> Result<Option<DomainInfo>, String> {
+    let name = guest.name().unwrap_or_default();
+    if confirm && !io.prompt_yes_or_no(&format!("Define '{}' with the changed XML?", name))? {
+        return Ok(None);
+    }
+    let info = conn.define_xml(&guest.get_xml())?;
+    io.print(&format!("Domain '{}' defined successfully.", name));
+    Ok(Some(info))
+}
+
+fn start_domain_transient(
+    conn: &dyn Connection,
+    guest: &Guest,
+    confirm: bool,
+    io: &mut CliIo,
+) -> Result<(), String> {
+    let name = guest.name().unwrap_or_default();
+    if confirm && !io.prompt_yes_or_no(&format!("Start '{}' with the changed XML?", name))? {
+        return Ok(());
+    }
+    conn.create_xml(&guest.get_xml())
+        .map_err(|e| format!("Failed starting domain '{}': {}", name, e))?;
+    io.print(&format!("Domain '{}' started successfully.", name));
+    Ok(())
+}
+
+fn defined_xml_is_unchanged(conn: &dyn Connection, name: &str, original_xml: &str) -> bool {
+    conn.domain_xml(name, true)
+        .and_then(|x| Guest::parse(&x))
+        .is_ok_and(|g| g.get_xml() == original_xml)
+}
+
+/// Run virt-xml with parsed options. `conn` overrides opening
+/// `--connect`, for tests; otherwise a connection is only opened when
+/// the command needs one.
+pub fn run(mut opts: Options, io: &mut CliIo, conn: Option<&dyn Connection>) -> Result<(), String> {
+    if opts.help {
+        io.print(USAGE);
+        return Ok(());
+    }
+    if opts.version {
+        io.print(env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    if check_option_introspection(&opts.xmlopts, io) {
+        return Ok(());
+    }
+
+    let mut stdinxml = None;
+    if opts.domain.is_none() && !opts.build_xml {
+        if io.stdin.is_none() {
+            return Err("A domain must be specified".to_string());
+        }
+        if opts.confirm {
+            return Err("Can't use --confirm with stdin input.".to_string());
+        }
+        if opts.update {
+            return Err("Can't use --update with stdin input.".to_string());
+        }
+        stdinxml = Some(io.read_stdin()?);
+    }
+
+    // Default to --define, unless:
+    //  --no-define explicitly specified
+    //  --print-* option is used
+    //  XML input came from stdin
+    if !opts.print_xml && !opts.print_diff {
+        if stdinxml.is_some() {
+            if opts.define != Some(true) {
+                opts.print_xml = true;
+            }
+        } else if opts.define.is_none() {
+            opts.define = Some(true);
+        }
+    }
+    if opts.confirm && !opts.print_xml {
+        opts.print_diff = true;
+    }
+
+    let action = parse_action(&opts)?;
+
+    let opened: Box<dyn Connection>;
+    let conn = match conn {
+        Some(c) => Some(c),
+        None if opts.domain.is_some()
+            || (!opts.build_xml && (opts.define == Some(true) || opts.start)) =>
+        {
+            opened = connection::open(opts.connect.as_deref())?;
+            Some(opened.as_ref())
+        }
+        None => None,
+    };
+    let needconn = || conn.ok_or_else(|| "No connection available".to_string());
+
+    let (domain, inactive, active) = match &opts.domain {
+        Some(domstr) => {
+            let (info, inactive, active) = get_domain_and_guest(needconn()?, domstr)?;
+            (Some(info), inactive, active)
+        }
+        None => match &stdinxml {
+            Some(xml) => (None, Guest::parse(xml)?, None),
+            None => (None, Guest::new(), None),
+        },
+    };
+
+    if action.name == ActionName::BuildXml {
+        for dev in action_build_xml(&action, &inactive)? {
+            io.print(&unindent_device_xml(&dev.get_xml()));
+        }
+        return Ok(());
+    }
+
+    let mut input_devs = None;
+    let mut performed_update = false;
+    if opts.update {
+        if opts.start {
+            return Err(fail_conflicting("--update", "--start"));
+        }
+        match (&active, &domain) {
+            (Some(active), Some(info)) => {
+                let (devs, _) = prepare_changes(active, &opts, &action, None, io)?;
+                update_changes(needconn()?, info, &devs, &action, opts.confirm, io)?;
+                input_devs = Some(devs);
+                performed_update = true;
+            }
+            _ => io.warn("The VM is not running, --update is inapplicable."),
+        }
+        if opts.define != Some(true) {
+            // --update and --no-define passed, so we are done
+            return Ok(());
+        }
+    }
+
+    let original_xml = inactive.get_xml();
+    let (_, to_define) = prepare_changes(&inactive, &opts, &action, input_devs, io)?;
+    if opts.define != Some(true) {
+        if opts.start {
+            start_domain_transient(needconn()?, &to_define, opts.confirm, io)?;
+        }
+        return Ok(());
+    }
+
+    let conn = needconn()?;
+    let Some(info) = define_changes(conn, &to_define, opts.confirm, io)? else {
+        // --confirm user said 'no'
+        return Ok(());
+    };
+
+    if opts.start {
+        conn.start_domain(&info.name)
+            .map_err(|e| format!("Failed starting domain '{}': {}", info.name, e))?;
+        io.print(&format!("Domain '{}' started successfully.", info.name));
+    } else if active.is_some() && !performed_update {
+        io.print("Changes will take effect after the domain is fully powered off.");
+    } else if defined_xml_is_unchanged(conn, &info.name, &original_xml) {
+        io.warn(
+            "XML did not change after domain define. You may have changed a value that libvirt is setting by default.",
+        );
+    }
+    Ok(())
+}
+
+/// Command line entry point. Returns the process exit code.
+pub fn main<S: AsRef<str>>(args: &[S], io: &mut CliIo, conn: Option<&dyn Connection>) -> i32 {
+    match parse_args(args).and_then(|opts| run(opts, io, conn)) {
+        Ok(()) => 0,
+        Err(e) => {
+            io.error(&e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::TestConnection;
+
+    fn datadir() -> String {
+        format!("{}/../tests/data", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    fn testsuite_conn() -> TestConnection {
+        TestConnection::open(&format!("test://{}/testdriver/testsuite.xml", datadir())).unwrap()
+    }
+
+    /// Run virt-xml, returning (exit code, stdout, stderr)
+    fn run_cli(
+        cmd: &str,
+        stdin: Option<&str>,
+        conn: Option<&dyn Connection>,
+    ) -> (i32, String, String) {
+        let args = cli::shlex_split(cmd).unwrap();
+        let mut input = std::io::Cursor::new(stdin.unwrap_or_default().as_bytes().to_vec());
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let ret = {
+            let mut io = CliIo {
+                stdin: stdin.map(|_| &mut input as &mut dyn std::io::BufRead),
+                stdout: &mut out,
+                stderr: &mut err,
+            };
+            main(&args, &mut io, conn)
+        };
+        (
+            ret,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    fn read_data(path: &str) -> String {
+        std::fs::read_to_string(format!("{}/{}", datadir(), path)).unwrap()
+    }
+
+    fn compare(path: &str) -> String {
+        read_data(&format!("cli/compare/{}", path))
+    }
+
+    /// Normalize --print-diff output the way the Python test suite does:
+    /// drop the file headers and first hunk header, and blank out the
+    /// line numbers of later hunk headers
+    fn normalize_diff(out: &str) -> String {
+        out.lines()
+            .skip(3)
+            .map(|l| if l.starts_with("@@") { "@@" } else { l })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The changed lines of a diff with indentation stripped, for
+    /// comparing against output of libvirt's reformatted XML
+    fn changed_lines(diff: &str) -> Vec<String> {
+        diff.lines()
+            .filter(|l| {
+                (l.starts_with('+') || l.starts_with('-'))
+                    && !l.starts_with("+++")
+                    && !l.starts_with("---")
+            })
+            .map(|l| format!("{}{}", &l[..1], l[1..].trim()))
+            .collect()
+    }
+
+    #[test]
+    fn test_stdin_edit() {
+        let input = read_data("cli/virtxml/virtxml-stdin-edit.xml");
+        let (ret, out, _) = run_cli("--edit --cpu host-passthrough", Some(&input), None);
+        assert_eq!(ret, 0);
+        assert_eq!(
+            out.trim_end(),
+            compare("virt-xml-stdin-edit.xml").trim_end()
+        );
+    }
+
+    #[test]
+    fn test_stdin_diffs() {
+        let input = read_data("cli/virtxml/virtxml-console-dup.xml");
+        let (ret, out, _) = run_cli(
+            "--print-diff --remove-device --serial 1",
+            Some(&input),
+            None,
+        );
+        assert_eq!(ret, 0);
+        assert_eq!(
+            normalize_diff(&out),
+            compare("virt-xml-remove-console-dup.xml").trim_end()
+        );
+
+        let input = read_data("cli/virtxml/virtxml-qemu-commandline-clear.xml");
+        let (ret, out, _) = run_cli(
+            "--edit --print-diff --qemu-commandline clearxml=yes",
+            Some(&input),
+            None,
+        );
+        assert_eq!(ret, 0);
+        assert_eq!(
+            normalize_diff(&out),
+            compare("virt-xml-edit-clearxml-qemu-commandline.xml").trim_end()
+        );
+    }
+
+    #[test]
+    fn test_build_xml() {
+        for (cmd, name) in [
+            ("--build-xml --cpu pentium3,+x2apic", "build-cpu"),
+            ("--build-xml --tpm path=/dev/tpm", "build-tpm"),
+            ("--build-xml --sound hda,audio.id=2", "build-sound"),
+        ] {
+            let (ret, out, err) = run_cli(cmd, None, None);
+            assert_eq!(ret, 0, "{}: {}", cmd, err);
+            assert_eq!(
+                out.trim_end(),
+                compare(&format!("virt-xml-{}.xml", name)).trim_end(),
+                "{}",
+                cmd
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let conn = testsuite_conn();
+        let stdin = read_data("cli/virtxml/virtxml-stdin-edit.xml");
+        for (cmd, input, grep) in [
+            (
+                "--edit --cpu host-passthrough",
+                None,
+                "A domain must be specified",
+            ),
+            (
+                "--edit --cpu host-passthrough --confirm",
+                Some(stdin.as_str()),
+                "Can't use --confirm with stdin",
+            ),
+            (
+                "--edit --cpu host-passthrough --update",
+                Some(stdin.as_str()),
+                "Can't use --update with stdin",
+            ),
+            (
+                "--build-xml --memory 10,maxmemory=20",
+                None,
+                "--build-xml not supported for --memory",
+            ),
+            (
+                "test --edit 2 --events on_poweroff=destroy",
+                None,
+                "'--edit 2' doesn't make sense with --events",
+            ),
+            (
+                "test --edit --update --events on_poweroff=destroy",
+                None,
+                "Don't know how to --update for --events",
+            ),
+            (
+                "domain-idontexist --edit --cpu host-passthrough --start",
+                None,
+                "Could not find domain",
+            ),
+            (
+                "test-state-shutoff --edit --update --boot menu=on --start",
+                None,
+                "Cannot use --update",
+            ),
+            (
+                "test --edit --disk /dev/null --network default",
+                None,
+                "Only one change operation",
+            ),
+            (
+                "test --cpu host-passthrough",
+                None,
+                "One of --edit, --add-device",
+            ),
+            (
+                "test --edit --add-device --disk /dev/null",
+                None,
+                "Conflicting options --edit, --add-device",
+            ),
+            (
+                "test --remove-device --cpu host-passthrough",
+                None,
+                "Cannot use --remove-device with --cpu",
+            ),
+            (
+                "test-for-virtxml --print-diff --edit target=vvv --disk /dev/null",
+                None,
+                "No matching objects found for --edit target=vvv",
+            ),
+            (
+                "test-for-virtxml --print-diff --edit 0 --disk /dev/null",
+                None,
+                "Invalid --edit option '0'",
+            ),
+            (
+                "test-for-virtxml --print-diff --edit 100 --disk /dev/null",
+                None,
+                "requested but there are only",
+            ),
+            (
+                "test-for-virtxml --print-diff --edit --memory 200,clearxml=yes",
+                None,
+                "Don't know how to clearxml for --memory",
+            ),
+            (
+                "test-state-shutoff --define --no-define --edit target=vda --disk boot_order=1",
+                None,
+                "argument --no-define: not allowed with argument --define",
+            ),
+        ] {
+            let (ret, _, err) = run_cli(cmd, input, Some(&conn));
+            assert_eq!(ret, 1, "{}", cmd);
+            assert!(err.contains(grep), "{}: {}", cmd, err);
+        }
+    }
+
+    #[test]
+    fn test_edit_selection() {
+        for (cmd, name) in [
+            ("--edit 3 --sound pcspk", "edit-pos-num"),
+            ("--edit -1 --sound pcspk", "edit-pos-num"),
+            ("--edit ich6 --sound pcspk", "edit-select-sound-model"),
+            (
+                "--edit target=hda --disk /dev/null",
+                "edit-select-disk-target",
+            ),
+            (
+                "--edit mac=00:11:7f:33:44:55 --network target=nic55",
+                "edit-select-network-mac",
+            ),
+        ] {
+            let conn = testsuite_conn();
+            let cmd = format!("test-for-virtxml --print-diff --define {}", cmd);
+            let (ret, out, err) = run_cli(&cmd, None, Some(&conn));
+            assert_eq!(ret, 0, "{}: {}", cmd, err);
+            let expected = compare(&format!("virt-xml-{}.xml", name));
+            assert_eq!(changed_lines(&out), changed_lines(&expected), "{}", cmd);
+            assert!(out.contains("Domain 'test-for-virtxml' defined successfully."));
+            assert!(
+                out.contains("Changes will take effect after the domain is fully powered off.")
+            );
+        }
+    }
+
+    #[test]
+    fn test_add_remove_define() {
+        let conn = testsuite_conn();
+        let (ret, _, err) = run_cli(
+            "test-state-shutoff --add-device --disk /var/lib/libvirt/images/new.img,bus=virtio --network network=default",
+            None,
+            Some(&conn),
+        );
+        assert_eq!(ret, 1);
+        assert!(err.contains("Only one change operation"));
+
+        let (ret, out, err) = run_cli(
+            "test-state-shutoff --add-device --disk /var/lib/libvirt/images/new.img,bus=virtio",
+            None,
+            Some(&conn),
+        );
+        assert_eq!(ret, 0, "{}", err);
+        assert!(out.contains("Domain 'test-state-shutoff' defined successfully."));
+        let xml = conn.domain_xml("test-state-shutoff", true).unwrap();
+        let guest = Guest::parse(&xml).unwrap();
+        let disks = guest.devices("disk");
+        let disk = disks.last().unwrap();
+        assert_eq!(disk.get("./target/@dev").as_deref(), Some("vdc"));
+        assert_eq!(
+            disk.get("./source/@file").as_deref(),
+            Some("/var/lib/libvirt/images/new.img")
+        );
+
+        let (ret, _, err) = run_cli(
+            "test-state-shutoff --remove-device --disk target=vdc --start",
+            None,
+            Some(&conn),
+        );
+        assert_eq!(ret, 0, "{}", err);
+        let guest = Guest::parse(&conn.domain_xml("test-state-shutoff", true).unwrap()).unwrap();
+        assert!(
+            guest
+                .devices("disk")
+                .iter()
+                .all(|d| d.get("./target/@dev").as_deref() != Some("vdc"))
+        );
+        assert!(
+            conn.lookup_domain("test-state-shutoff")
+                .unwrap()
+                .state
+                .is_active()
+        );
+    }
+
+    #[test]
+    fn test_update_hotplug() {
+        let conn = testsuite_conn();
+        let (ret, out, err) = run_cli(
+            "test --add-device --network network=default,mac=52:54:00:aa:bb:cc --update --confirm",
+            Some("yes\nyes\n"),
+            Some(&conn),
+        );
+        assert_eq!(ret, 0, "{}", err);
+        assert!(out.contains("Hotplug this device to the guest 'test'?"));
+        assert!(out.contains("Device hotplug successful."));
+        assert!(out.contains("Domain 'test' defined successfully."));
+        assert!(
+            conn.domain_xml("test", false)
+                .unwrap()
+                .contains("52:54:00:aa:bb:cc")
+        );
+        assert!(
+            conn.domain_xml("test", true)
+                .unwrap()
+                .contains("52:54:00:aa:bb:cc")
+        );
+
+        let (ret, _, err) = run_cli(
+            "test-state-shutoff --edit --update --boot menu=on",
+            None,
+            Some(&conn),
+        );
+        assert_eq!(ret, 0);
+        assert!(err.contains("The VM is not running"));
+    }
+
+    #[test]
+    fn test_introspection() {
+        let (ret, out, _) = run_cli("--sound=? --tpm=?", None, None);
+        assert_eq!(ret, 0);
+        assert!(out.contains("--sound options:"));
+        assert!(out.contains("--tpm options:"));
+    }
+}
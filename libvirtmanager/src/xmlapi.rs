@@ -0,0 +1,761 @@
+// XML API wrappers (port of virtinst/xmlapi.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! A small mutable XML tree with the xpath subset virtinst relies on.
+//!
+//! Unlike the serde structs used for single devices, this keeps every
+//! element, attribute and whitespace node of the parsed document, so editing
+//! an existing domain only changes what was asked for and produces minimal
+//! diffs. New nodes are indented to match their siblings, the same way the
+//! libxml2 based Python implementation does it.
+
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+
+/// Namespace prefixes that may appear in xpaths, and their URIs.
+pub const NAMESPACES: &[(&str, &str)] = &[
+    ("qemu", "http://libvirt.org/schemas/domain/qemu/1.0"),
+    ("libosinfo", "http://libosinfo.org/xmlns/libvirt/domain/1.0"),
+    ("vmm", "https://virt-manager.org/"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+    Comment(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+/// A single segment of an xpath like `baz[@somepro='someval']` or `bar[1]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct XPathSegment {
+    name: String,
+    condition_prop: Option<(String, String)>,
+    condition_num: Option<usize>,
+}
+
+impl XPathSegment {
+    fn new(full: &str) -> Self {
+        let mut name = full.to_string();
+        let mut condition_prop = None;
+        let mut condition_num = None;
+        if let Some(open) = full.find('[') {
+            name = full[..open].to_string();
+            let cond = full[open + 1..].trim_end_matches(']');
+            if let Some((p, v)) = cond.split_once('=') {
+                condition_prop = Some((
+                    p.trim_start_matches('@').to_string(),
+                    v.trim_matches('\'').trim_matches('"').to_string(),
+                ));
+            } else if let Ok(n) = cond.parse::<usize>() {
+                condition_num = Some(n);
+            }
+        }
+        Self {
+            name,
+            condition_prop,
+            condition_num,
+        }
+    }
+
+    fn matches(&self, el: &Element) -> bool {
+        if el.name != self.name {
+            return false;
+        }
+        match &self.condition_prop {
+            Some((p, v)) => el.attr(p) == Some(v.as_str()),
+            None => true,
+        }
+    }
+}
+
+/// Parsed form of xpaths like `./foo/bar[1]/@prop`
+#[derive(Debug, Clone)]
+struct XPath {
+    segments: Vec<XPathSegment>,
+    propname: Option<String>,
+}
+
+impl XPath {
+    fn new(fullxpath: &str) -> Self {
+        let mut segments: Vec<XPathSegment> = Vec::new();
+        let mut propname = None;
+        for s in fullxpath.split('/') {
+            if s.is_empty() || s == "." {
+                continue;
+            }
+            if s == ".." {
+                segments.pop();
+                continue;
+            }
+            if let Some(p) = s.strip_prefix('@') {
+                propname = Some(p.to_string());
+                continue;
+            }
+            segments.push(XPathSegment::new(s));
+        }
+        Self { segments, propname }
+    }
+}
+
+impl Element {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Parse an XML document string and return its root element.
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        let mut reader = Reader::from_str(xml);
+        reader.trim_text(false);
+        reader.expand_empty_elements(false);
+
+        let mut stack: Vec<Element> = Vec::new();
+        let mut root: Option<Element> = None;
+
+        loop {
+            let ev = reader
+                .read_event()
+                .map_err(|e| format!("XML parse error at {}: {}", reader.buffer_position(), e))?;
+            match ev {
+                Event::Start(e) => stack.push(element_from_start(&e)?),
+                Event::Empty(e) => {
+                    let el = element_from_start(&e)?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Node::Element(el)),
+                        None => {
+                            root = Some(el);
+                        }
+                    }
+                }
+                Event::End(_) => {
+                    let el = stack.pop().ok_or("Unbalanced XML end tag")?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Node::Element(el)),
+                        None => root = Some(el),
+                    }
+                }
+                Event::Text(t) => {
+                    if let Some(parent) = stack.last_mut() {
+                        let s = t
+                            .unescape()
+                            .map_err(|e| format!("XML parse error: {}", e))?;
+                        parent.push_text(&s);
+                    }
+                }
+                Event::CData(t) => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent.push_text(&String::from_utf8_lossy(&t.into_inner()));
+                    }
+                }
+                Event::Comment(t) => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent
+                            .children
+                            .push(Node::Comment(String::from_utf8_lossy(&t).to_string()));
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            if root.is_some() && stack.is_empty() {
+                break;
+            }
+        }
+
+        if !stack.is_empty() {
+            return Err("XML parse error: premature end of document".into());
+        }
+        root.ok_or_else(|| "XML parse error: no root element".to_string())
+    }
+
+    /// Serialize the element the way libxml2 does: double quoted
+    /// attributes and self closing tags for childless elements.
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        self.write_xml(&mut out);
+        out
+    }
+
+    fn write_xml(&self, out: &mut String) {
+        out.push('<');
+        out.push_str(&self.name);
+        for (k, v) in &self.attrs {
+            out.push(' ');
+            out.push_str(k);
+            out.push_str("=\"");
+            out.push_str(&escape_attr(v));
+            out.push('"');
+        }
+        if self.children.is_empty() {
+            out.push_str("/>");
+            return;
+        }
+        out.push('>');
+        for c in &self.children {
+            match c {
+                Node::Element(e) => e.write_xml(out),
+                Node::Text(t) => out.push_str(&escape_text(t)),
+                Node::Comment(t) => {
+                    out.push_str("<!--");
+                    out.push_str(t);
+                    out.push_str("-->");
+                }
+            }
+        }
+        out.push_str("</");
+        out.push_str(&self.name);
+        out.push('>');
+    }
+
+    /// Like `to_xml`, with a trailing newline for multi-line output
+    pub fn get_xml(&self) -> String {
+        let mut xml = self.to_xml();
+        if !xml.ends_with('\n') && xml.contains('\n') {
+            xml.push('\n');
+        }
+        xml
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_attr(&mut self, name: &str, value: Option<&str>) {
+        let pos = self.attrs.iter().position(|(k, _)| k == name);
+        match (pos, value) {
+            (Some(i), Some(v)) => self.attrs[i].1 = v.to_string(),
+            (None, Some(v)) => self.attrs.push((name.to_string(), v.to_string())),
+            (Some(i), None) => {
+                self.attrs.remove(i);
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Concatenated text content of the element and its descendants
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        for c in &self.children {
+            match c {
+                Node::Text(t) => out.push_str(t),
+                Node::Element(e) => out.push_str(&e.text()),
+                Node::Comment(_) => {}
+            }
+        }
+        out
+    }
+
+    /// Replace all children with the passed text, or nothing for None
+    pub fn set_text(&mut self, value: Option<&str>) {
+        self.children.clear();
+        if let Some(v) = value {
+            self.children.push(Node::Text(v.to_string()));
+        }
+    }
+
+    pub fn has_content(&self) -> bool {
+        !self.children.is_empty() || !self.attrs.is_empty()
+    }
+
+    /// Remove all attributes and children
+    pub fn clear(&mut self) {
+        self.attrs.clear();
+        self.children.clear();
+    }
+
+    pub fn child_elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|c| match c {
+            Node::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    pub fn child_elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.children.iter_mut().filter_map(|c| match c {
+            Node::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if let Some(Node::Text(last)) = self.children.last_mut() {
+            last.push_str(text);
+        } else {
+            self.children.push(Node::Text(text.to_string()));
+        }
+    }
+
+    /// Resolve the element segments of an xpath into child index chains
+    fn resolve(&self, segments: &[XPathSegment]) -> Option<Vec<usize>> {
+        let mut path = Vec::new();
+        let mut cur = self;
+        for seg in segments {
+            let mut seen = 0;
+            let mut found = None;
+            for (idx, c) in cur.children.iter().enumerate() {
+                if let Node::Element(e) = c {
+                    if !seg.matches(e) {
+                        continue;
+                    }
+                    seen += 1;
+                    if seg.condition_num.is_none_or(|n| n == seen) {
+                        found = Some(idx);
+                        break;
+                    }
+                }
+            }
+            let idx = found?;
+            path.push(idx);
+            cur = match &cur.children[idx] {
+                Node::Element(e) => e,
+                _ => unreachable!(),
+            };
+        }
+        Some(path)
+    }
+
+    fn at_path(&self, path: &[usize]) -> &Element {
+        let mut cur = self;
+        for idx in path {
+            cur = match &cur.children[*idx] {
+                Node::Element(e) => e,
+                _ => unreachable!("xmlapi path points at a non-element"),
+            };
+        }
+        cur
+    }
+
+    fn at_path_mut(&mut self, path: &[usize]) -> &mut Element {
+        let mut cur = self;
+        for idx in path {
+            cur = match &mut cur.children[*idx] {
+                Node::Element(e) => e,
+                _ => unreachable!("xmlapi path points at a non-element"),
+            };
+        }
+        cur
+    }
+
+    /// Find the element an xpath points at. A trailing `@prop` is ignored.
+    pub fn find(&self, xpath: &str) -> Option<&Element> {
+        let xp = XPath::new(xpath);
+        self.resolve(&xp.segments).map(|p| self.at_path(&p))
+    }
+
+    pub fn find_mut(&mut self, xpath: &str) -> Option<&mut Element> {
+        let xp = XPath::new(xpath);
+        let path = self.resolve(&xp.segments)?;
+        Some(self.at_path_mut(&path))
+    }
+
+    /// All elements matching the final segment of an xpath
+    pub fn find_all(&self, xpath: &str) -> Vec<&Element> {
+        let xp = XPath::new(xpath);
+        let Some((last, parents)) = xp.segments.split_last() else {
+            return vec![self];
+        };
+        let Some(parent) = self.resolve(parents).map(|p| self.at_path(&p)) else {
+            return vec![];
+        };
+        parent
+            .child_elements()
+            .filter(|e| last.matches(e))
+            .collect()
+    }
+
+    pub fn count(&self, xpath: &str) -> usize {
+        self.find_all(xpath).len()
+    }
+
+    /// Read the attribute or text content an xpath points at
+    pub fn get(&self, xpath: &str) -> Option<String> {
+        let xp = XPath::new(xpath);
+        let el = self.resolve(&xp.segments).map(|p| self.at_path(&p))?;
+        match &xp.propname {
+            Some(p) => el.attr(p).map(|s| s.to_string()),
+            None => Some(el.text()),
+        }
+    }
+
+    /// Whether the element an xpath points at exists, for boolean properties
+    pub fn get_bool(&self, xpath: &str) -> bool {
+        self.find(xpath).is_some()
+    }
+
+    /// Set the attribute or text an xpath points at. Missing elements are
+    /// created along the way; None removes the value and prunes any
+    /// elements that became empty.
+    pub fn set(&mut self, xpath: &str, value: Option<&str>) {
+        let xp = XPath::new(xpath);
+        match value {
+            Some(v) => {
+                let path = self.make_stub(&xp.segments);
+                let el = self.at_path_mut(&path);
+                match &xp.propname {
+                    Some(p) => el.set_attr(p, Some(v)),
+                    None => el.set_text(Some(v)),
+                }
+            }
+            None => {
+                if let Some(path) = self.resolve(&xp.segments) {
+                    let el = self.at_path_mut(&path);
+                    match &xp.propname {
+                        Some(p) => el.set_attr(p, None),
+                        None => el.set_text(None),
+                    }
+                }
+                self.remove_empty(&xp.segments);
+            }
+        }
+    }
+
+    /// Boolean properties: true creates the element, false removes it
+    pub fn set_bool(&mut self, xpath: &str, value: bool) {
+        let xp = XPath::new(xpath);
+        if value {
+            self.make_stub(&xp.segments);
+        } else {
+            self.force_remove(xpath);
+        }
+    }
+
+    /// Remove the element referenced by xpath including its children
+    pub fn force_remove(&mut self, xpath: &str) {
+        let xp = XPath::new(xpath);
+        if let Some(mut path) = self.resolve(&xp.segments)
+            && let Some(idx) = path.pop()
+        {
+            self.at_path_mut(&path).remove_child_at(idx);
+        }
+    }
+
+    /// Whitespace text preceding the element at path, used for indenting
+    fn prev_whitespace(&self, path: &[usize]) -> Option<String> {
+        let (idx, parent) = path.split_last()?;
+        let parent = self.at_path(parent);
+        match idx.checked_sub(1).map(|i| &parent.children[i]) {
+            Some(Node::Text(t)) => Some(t.clone()),
+            _ => None,
+        }
+    }
+
+    /// Build all missing elements of the xpath and return the index path
+    /// of the final one. `./foo[@bar='baz']` also sets bar='baz'.
+    fn make_stub(&mut self, segments: &[XPathSegment]) -> Vec<usize> {
+        let mut path: Vec<usize> = Vec::new();
+        for (i, seg) in segments.iter().enumerate() {
+            if let Some(p) = self.resolve(&segments[..=i]) {
+                path = p;
+                continue;
+            }
+            let mut newnode = Element::new(&seg.name);
+            if let Some((p, v)) = &seg.condition_prop {
+                newnode.set_attr(p, Some(v));
+            }
+            if let Some((prefix, _)) = seg.name.split_once(':')
+                && !self.ns_declared(&path, prefix)
+                && let Some((_, uri)) = NAMESPACES.iter().find(|(n, _)| *n == prefix)
+            {
+                newnode.set_attr(&format!("xmlns:{}", prefix), Some(uri));
+            }
+            // For ./foo[3] make sure foo[1] and foo[2] exist too
+            let existing = self
+                .at_path(&path)
+                .child_elements()
+                .filter(|e| seg.matches(e))
+                .count();
+            let wanted = seg.condition_num.unwrap_or(existing + 1).max(existing + 1);
+            let ws = self.prev_whitespace(&path);
+            let mut idx = 0;
+            for _ in existing..wanted {
+                idx = self
+                    .at_path_mut(&path)
+                    .add_child_pretty(ws.as_deref(), newnode.clone());
+            }
+            path.push(idx);
+        }
+        path
+    }
+
+    fn ns_declared(&self, path: &[usize], prefix: &str) -> bool {
+        let key = format!("xmlns:{}", prefix);
+        (0..=path.len()).any(|n| self.at_path(&path[..n]).attr(&key).is_some())
+    }
+
+    /// Walk back up the xpath and remove elements without children or
+    /// attributes, so we don't leave stale elements in the XML
+    fn remove_empty(&mut self, segments: &[XPathSegment]) {
+        let Some(mut path) = self.resolve(segments) else {
+            return;
+        };
+        while let Some(idx) = path.pop() {
+            let parent = self.at_path_mut(&path);
+            let child = match &parent.children[idx] {
+                Node::Element(e) => e,
+                _ => break,
+            };
+            if child.has_content() {
+                break;
+            }
+            parent.remove_child_at(idx);
+        }
+    }
+
+    /// Append a child element, indenting it to match the existing layout.
+    /// `prev_ws` is the whitespace preceding this element in its parent.
+    /// Returns the index of the new child.
+    pub fn add_child_pretty(&mut self, prev_ws: Option<&str>, child: Element) -> usize {
+        if !matches!(self.children.last(), Some(Node::Text(_))) {
+            let newlast = prev_ws.unwrap_or("\n").to_string();
+            self.push_text(&newlast);
+        }
+        let endtext = match self.children.last() {
+            Some(Node::Text(t)) => t.clone(),
+            _ => "\n".to_string(),
+        };
+        self.push_text("  ");
+        self.children.push(Node::Element(child));
+        let idx = self.children.len() - 1;
+        self.push_text(&endtext);
+        idx
+    }
+
+    /// Insert a child element right after the child at `after`, copying
+    /// that sibling's indentation. Returns the index of the new child.
+    pub fn insert_child_after(&mut self, after: usize, child: Element) -> usize {
+        let ws = match after.checked_sub(1).map(|i| &self.children[i]) {
+            Some(Node::Text(t)) => t.clone(),
+            _ => "\n".to_string(),
+        };
+        self.children.insert(after + 1, Node::Text(ws));
+        self.children.insert(after + 2, Node::Element(child));
+        self.merge_text_at(after + 1);
+        after + 2
+    }
+
+    fn merge_text_at(&mut self, idx: usize) {
+        if idx + 1 < self.children.len()
+            && let (Node::Text(_), Node::Text(_)) = (&self.children[idx], &self.children[idx + 1])
+            && let Node::Text(b) = self.children.remove(idx + 1)
+            && let Node::Text(a) = &mut self.children[idx]
+        {
+            a.push_str(&b);
+        }
+    }
+
+    /// Remove the child at idx along with its preceding whitespace
+    pub fn remove_child_at(&mut self, idx: usize) -> Option<Element> {
+        let removed = match self.children.remove(idx) {
+            Node::Element(e) => Some(e),
+            _ => None,
+        };
+        if idx > 0 && matches!(self.children[idx - 1], Node::Text(_)) {
+            self.children.remove(idx - 1);
+        }
+        if self.children.iter().all(|c| matches!(c, Node::Text(_))) {
+            self.children.clear();
+        }
+        removed
+    }
+
+    /// Index into `children` of the nth (0 based) element matching name
+    pub fn child_index(&self, name: &str, nth: usize) -> Option<usize> {
+        self.children
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| matches!(c, Node::Element(e) if e.name == name))
+            .nth(nth)
+            .map(|(i, _)| i)
+    }
+
+    /// Re-indent a standalone element so nested children are laid out with
+    /// two spaces per level, matching what add_child_pretty produces.
+    pub fn prettify(&mut self) {
+        self.prettify_level(0);
+    }
+
+    fn prettify_level(&mut self, level: usize) {
+        let has_elements = self.child_elements().next().is_some();
+        if !has_elements {
+            return;
+        }
+        let old = std::mem::take(&mut self.children);
+        let inner = format!("\n{}", "  ".repeat(level + 1));
+        for c in old {
+            match c {
+                Node::Element(mut e) => {
+                    e.prettify_level(level + 1);
+                    self.children.push(Node::Text(inner.clone()));
+                    self.children.push(Node::Element(e));
+                }
+                Node::Comment(t) => {
+                    self.children.push(Node::Text(inner.clone()));
+                    self.children.push(Node::Comment(t));
+                }
+                Node::Text(t) if !t.trim().is_empty() => self.children.push(Node::Text(t)),
+                Node::Text(_) => {}
+            }
+        }
+        self.children
+            .push(Node::Text(format!("\n{}", "  ".repeat(level))));
+    }
+}
+
+fn element_from_start(e: &BytesStart) -> Result<Element, String> {
+    let mut el = Element::new(&String::from_utf8_lossy(e.name().as_ref()));
+    for a in e.attributes() {
+        let a = a.map_err(|e| format!("XML attribute error: {}", e))?;
+        let key = String::from_utf8_lossy(a.key.as_ref()).to_string();
+        let val = a
+            .unescape_value()
+            .map_err(|e| format!("XML attribute error: {}", e))?
+            .to_string();
+        el.attrs.push((key, val));
+    }
+    Ok(el)
+}
+
+pub fn escape_text(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn escape_attr(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
+
+/// Strip the common leading indentation from a device XML snippet that was
+/// serialized out of a larger document.
+pub fn unindent_device_xml(xml: &str) -> String {
+    let mut lines = xml.lines();
+    let Some(first) = lines.next() else {
+        return xml.to_string();
+    };
+    let mut ret = String::from(first.trim_start());
+    ret.push('\n');
+    let unindent = lines
+        .clone()
+        .last()
+        .map(|l| l.len() - l.trim_start().len())
+        .unwrap_or(0);
+    for line in lines {
+        let strip = line.len() - line.trim_start_matches(' ').len();
+        ret.push_str(&line[strip.min(unindent)..]);
+        ret.push('\n');
+    }
+    ret
+}
+
+/// Check a user supplied object name, like a snapshot or pool name.
+/// `name_label` is the object type for the error message.
+pub fn validate_generic_name(name_label: &str, val: &str) -> Result<(), String> {
+    if val.is_empty() {
+        return Err(format!("A name must be specified for the {}", name_label));
+    }
+    // Only character that shouldn't work is '/', matching QEMU
+    if val.contains('/') {
+        return Err(format!(
+            "{} name '{}' can not contain '/' character.",
+            name_label, val
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOMXML: &str = "<domain type='kvm'>\n  <name>test</name>\n  <devices>\n    <disk type='file'>\n      <target dev='vda'/>\n    </disk>\n    <disk type='block'>\n      <target dev='vdb'/>\n    </disk>\n  </devices>\n</domain>\n";
+
+    #[test]
+    fn test_roundtrip_uses_double_quotes() {
+        let root = Element::parse(DOMXML).unwrap();
+        assert_eq!(root.get_xml(), DOMXML.replace('\'', "\""));
+    }
+
+    #[test]
+    fn test_get_xpaths() {
+        let root = Element::parse(DOMXML).unwrap();
+        assert_eq!(root.get("./name").as_deref(), Some("test"));
+        assert_eq!(root.get("./@type").as_deref(), Some("kvm"));
+        assert_eq!(
+            root.get("./devices/disk[2]/target/@dev").as_deref(),
+            Some("vdb")
+        );
+        assert_eq!(
+            root.get("./devices/disk[@type='block']/target/@dev")
+                .as_deref(),
+            Some("vdb")
+        );
+        assert_eq!(root.count("./devices/disk"), 2);
+        assert!(root.get("./os/type").is_none());
+    }
+
+    #[test]
+    fn test_set_creates_indented_stub() {
+        let mut root = Element::parse(DOMXML).unwrap();
+        root.set("./cpu/model", Some("qemu64"));
+        root.set("./cpu/@mode", Some("custom"));
+        assert!(root.to_xml().ends_with(
+            "  </devices>\n  <cpu mode=\"custom\">\n    <model>qemu64</model>\n  </cpu>\n</domain>"
+        ));
+    }
+
+    #[test]
+    fn test_unset_prunes_empty_parents() {
+        let mut root = Element::parse(DOMXML).unwrap();
+        root.set("./devices/disk[1]/target/@dev", None);
+        let disk = root.find("./devices/disk").unwrap();
+        assert!(disk.find("./target").is_none());
+        assert_eq!(disk.to_xml(), "<disk type=\"file\"/>");
+    }
+
+    #[test]
+    fn test_remove_last_child_collapses() {
+        let mut root = Element::parse(DOMXML).unwrap();
+        root.force_remove("./devices/disk");
+        root.force_remove("./devices/disk");
+        assert!(root.to_xml().contains("  <devices/>\n</domain>"));
+    }
+
+    #[test]
+    fn test_namespace_stub() {
+        let mut root = Element::parse("<domain>\n</domain>").unwrap();
+        root.set("./qemu:commandline/qemu:arg/@value", Some("-foo"));
+        assert_eq!(
+            root.find("./qemu:commandline").unwrap().attr("xmlns:qemu"),
+            Some("http://libvirt.org/schemas/domain/qemu/1.0")
+        );
+    }
+
+    #[test]
+    fn test_unindent_device_xml() {
+        let root = Element::parse(DOMXML).unwrap();
+        let disk = root.find("./devices/disk").unwrap();
+        assert_eq!(
+            unindent_device_xml(&disk.to_xml()),
+            "<disk type=\"file\">\n  <target dev=\"vda\"/>\n</disk>\n"
+        );
+    }
+}
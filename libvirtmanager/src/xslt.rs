@@ -0,0 +1,1835 @@
+// XSLT processing of osinfo install-script templates (replaces the
+// libxslt calls libosinfo makes for virtinst/install/unattended.py)
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+//! Small XSLT 1.0 processor. osinfo-db ships every install script as a
+//! stylesheet applied to a config document, so this covers what those
+//! stylesheets use: template matching and modes, named templates with
+//! params, variables, the flow control instructions, literal result
+//! elements with attribute value templates, and the XPath 1.0 core
+//! function library. There is no `xsl:import`, `xsl:key` or `document()`.
+
+use crate::xmlapi::{Element, Node};
+
+const MAX_DEPTH: usize = 1000;
+
+// XPath data model
+
+/// A node of the source document: child indices from the document node
+/// down, plus an attribute index for attribute nodes
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct NodeRef {
+    path: Vec<usize>,
+    attr: Option<usize>,
+}
+
+impl NodeRef {
+    fn root() -> Self {
+        Self {
+            path: vec![],
+            attr: None,
+        }
+    }
+
+    fn child(&self, idx: usize) -> Self {
+        let mut path = self.path.clone();
+        path.push(idx);
+        Self { path, attr: None }
+    }
+
+    fn parent(&self) -> Option<Self> {
+        if self.attr.is_some() {
+            return Some(Self {
+                path: self.path.clone(),
+                attr: None,
+            });
+        }
+        let mut path = self.path.clone();
+        path.pop()?;
+        Some(Self { path, attr: None })
+    }
+}
+
+enum Item<'d> {
+    Root(&'d Element),
+    Element(&'d Element),
+    Text(&'d str),
+    Comment(&'d str),
+    Attr(&'d str, &'d str),
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Nodes(Vec<NodeRef>),
+    Str(String),
+    Num(f64),
+    Bool(bool),
+}
+
+fn num_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{}", n)
+    }
+}
+
+fn str_to_num(s: &str) -> f64 {
+    let s = s.trim();
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    if digits.is_empty()
+        || !digits.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+        || digits.bytes().filter(|b| *b == b'.').count() > 1
+    {
+        return f64::NAN;
+    }
+    s.parse().unwrap_or(f64::NAN)
+}
+
+// XPath syntax
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Axis {
+    Child,
+    Attribute,
+    // `self::`
+    Current,
+    Parent,
+    Descendant,
+    DescendantOrSelf,
+    Ancestor,
+    AncestorOrSelf,
+    FollowingSibling,
+    PrecedingSibling,
+}
+
+impl Axis {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "child" => Self::Child,
+            "attribute" => Self::Attribute,
+            "self" => Self::Current,
+            "parent" => Self::Parent,
+            "descendant" => Self::Descendant,
+            "descendant-or-self" => Self::DescendantOrSelf,
+            "ancestor" => Self::Ancestor,
+            "ancestor-or-self" => Self::AncestorOrSelf,
+            "following-sibling" => Self::FollowingSibling,
+            "preceding-sibling" => Self::PrecedingSibling,
+            _ => return Err(format!("Unsupported XPath axis '{}'", name)),
+        })
+    }
+
+    fn is_reverse(self) -> bool {
+        matches!(
+            self,
+            Self::Parent | Self::Ancestor | Self::AncestorOrSelf | Self::PrecedingSibling
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum NodeTest {
+    Name(String),
+    Any,
+    Text,
+    Node,
+    Comment,
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    axis: Axis,
+    test: NodeTest,
+    preds: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Cmp(&'static str, Box<Expr>, Box<Expr>),
+    Arith(&'static str, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    Union(Box<Expr>, Box<Expr>),
+    Lit(String),
+    Num(f64),
+    Var(String),
+    Call(String, Vec<Expr>),
+    /// A location path, from the document node when `absolute`
+    Path {
+        absolute: bool,
+        steps: Vec<Step>,
+    },
+    /// A primary expression with predicates and steps after it, like
+    /// `$nodes[1]/name`
+    Filter {
+        primary: Box<Expr>,
+        preds: Vec<Expr>,
+        steps: Vec<Step>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Lit(String),
+    Num(f64),
+    Var(String),
+    Name(String),
+    /// Punctuation and operators, including `and`, `or`, `div`, `mod`
+    /// and `*` when they are operators
+    Sym(&'static str),
+}
+
+const SYMBOLS: &[&str] = &[
+    "//", "::", "..", "!=", "<=", ">=", "/", "(", ")", "[", "]", "@", ",", "|", ".", "*", "=", "<",
+    ">", "+", "-", "$",
+];
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Tok>, String> {
+    let mut toks: Vec<Tok> = vec![];
+    let chars: Vec<char> = expr.chars().collect();
+    let mut i = 0;
+    // Whether the previous token can end an operand, which makes `*`
+    // and the operator names operators rather than name tests
+    let operand_before = |toks: &[Tok]| match toks.last() {
+        None => false,
+        Some(Tok::Sym(s)) => matches!(*s, ")" | "]" | "." | ".." | "*name"),
+        Some(_) => true,
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '"' || c == '\'' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|x| *x == c)
+                .ok_or_else(|| format!("Unterminated string literal in XPath '{}'", expr))?;
+            toks.push(Tok::Lit(chars[i + 1..i + 1 + end].iter().collect()));
+            i += end + 2;
+            continue;
+        }
+        if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit()))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            toks.push(Tok::Num(str_to_num(&text)));
+            continue;
+        }
+        if c == '$' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && is_name_char(chars[i]) {
+                i += 1;
+            }
+            toks.push(Tok::Var(chars[start..i].iter().collect()));
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && is_name_char(chars[i]) {
+                // `::` ends a name, `ns:name` doesn't
+                if chars[i] == ':' && chars.get(i + 1) == Some(&':') {
+                    break;
+                }
+                i += 1;
+            }
+            let name: String = chars[start..i].iter().collect();
+            let op = ["and", "or", "div", "mod"]
+                .into_iter()
+                .find(|o| *o == name && operand_before(&toks));
+            toks.push(match op {
+                Some(op) => Tok::Sym(op),
+                None => Tok::Name(name),
+            });
+            continue;
+        }
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+        let Some(sym) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) else {
+            return Err(format!("Unexpected character '{}' in XPath '{}'", c, expr));
+        };
+        let sym = if *sym == "*" && !operand_before(&toks) {
+            "*name"
+        } else {
+            sym
+        };
+        toks.push(Tok::Sym(sym));
+        i += sym.trim_end_matches("name").chars().count();
+    }
+    Ok(toks)
+}
+
+struct Parser {
+    toks: Vec<Tok>,
+    pos: usize,
+    text: String,
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Self, String> {
+        Ok(Self {
+            toks: tokenize(text)?,
+            pos: 0,
+            text: text.to_string(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos)
+    }
+
+    fn peek_at(&self, off: usize) -> Option<&Tok> {
+        self.toks.get(self.pos + off)
+    }
+
+    fn is_sym(&self, sym: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Sym(s)) if *s == sym)
+    }
+
+    fn eat(&mut self, sym: &str) -> bool {
+        if self.is_sym(sym) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), String> {
+        if self.eat(sym) {
+            return Ok(());
+        }
+        Err(self.error(&format!("expected '{}'", sym)))
+    }
+
+    fn error(&self, msg: &str) -> String {
+        format!("Invalid XPath '{}': {}", self.text, msg)
+    }
+
+    fn parse_all(mut self) -> Result<Expr, String> {
+        let expr = self.parse_or()?;
+        if self.peek().is_some() {
+            return Err(self.error("unexpected trailing input"));
+        }
+        Ok(expr)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.parse_and()?;
+        while self.eat("or") {
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.parse_and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.parse_binary(0)?;
+        while self.eat("and") {
+            lhs = Expr::And(Box::new(lhs), Box::new(self.parse_binary(0)?));
+        }
+        Ok(lhs)
+    }
+
+    /// Equality, relational, additive and multiplicative operators, by
+    /// increasing precedence level
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, String> {
+        const LEVELS: &[&[&str]] = &[
+            &["=", "!="],
+            &["<=", ">=", "<", ">"],
+            &["+", "-"],
+            &["*", "div", "mod"],
+        ];
+        if level == LEVELS.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        loop {
+            let Some(op) = LEVELS[level].iter().find(|op| self.is_sym(op)).copied() else {
+                return Ok(lhs);
+            };
+            self.pos += 1;
+            let rhs = Box::new(self.parse_binary(level + 1)?);
+            lhs = if level < 2 {
+                Expr::Cmp(op, Box::new(lhs), rhs)
+            } else {
+                Expr::Arith(op, Box::new(lhs), rhs)
+            };
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.parse_unary()?)));
+        }
+        let mut lhs = self.parse_path()?;
+        while self.eat("|") {
+            lhs = Expr::Union(Box::new(lhs), Box::new(self.parse_path()?));
+        }
+        Ok(lhs)
+    }
+
+    fn starts_primary(&self) -> bool {
+        match self.peek() {
+            Some(Tok::Lit(_) | Tok::Num(_) | Tok::Var(_)) => true,
+            Some(Tok::Sym("(")) => true,
+            Some(Tok::Name(name)) => {
+                self.peek_at(1) == Some(&Tok::Sym("("))
+                    && !["text", "node", "comment"].contains(&name.as_str())
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_path(&mut self) -> Result<Expr, String> {
+        if self.starts_primary() {
+            let primary = self.parse_primary()?;
+            let preds = self.parse_predicates()?;
+            let mut steps = vec![];
+            if self.is_sym("/") || self.is_sym("//") {
+                self.parse_steps(&mut steps)?;
+            }
+            if preds.is_empty() && steps.is_empty() {
+                return Ok(primary);
+            }
+            return Ok(Expr::Filter {
+                primary: Box::new(primary),
+                preds,
+                steps,
+            });
+        }
+        let mut steps = vec![];
+        let absolute = self.is_sym("/") || self.is_sym("//");
+        if self.eat("/") {
+            if !self.starts_step() {
+                return Ok(Expr::Path { absolute, steps });
+            }
+        } else if self.eat("//") {
+            steps.push(descendant_or_self_step());
+        }
+        steps.push(self.parse_step()?);
+        self.parse_steps(&mut steps)?;
+        Ok(Expr::Path { absolute, steps })
+    }
+
+    fn starts_step(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Tok::Name(_) | Tok::Sym("@" | "." | ".." | "*name"))
+        )
+    }
+
+    fn parse_steps(&mut self, steps: &mut Vec<Step>) -> Result<(), String> {
+        loop {
+            if self.eat("//") {
+                steps.push(descendant_or_self_step());
+            } else if !self.eat("/") {
+                return Ok(());
+            }
+            steps.push(self.parse_step()?);
+        }
+    }
+
+    fn parse_step(&mut self) -> Result<Step, String> {
+        if self.eat(".") {
+            return Ok(Step {
+                axis: Axis::Current,
+                test: NodeTest::Node,
+                preds: vec![],
+            });
+        }
+        if self.eat("..") {
+            return Ok(Step {
+                axis: Axis::Parent,
+                test: NodeTest::Node,
+                preds: vec![],
+            });
+        }
+        let mut axis = Axis::Child;
+        if self.eat("@") {
+            axis = Axis::Attribute;
+        } else if let Some(Tok::Name(name)) = self.peek()
+            && self.peek_at(1) == Some(&Tok::Sym("::"))
+        {
+            axis = Axis::parse(name)?;
+            self.pos += 2;
+        }
+        let test = match self.peek().cloned() {
+            Some(Tok::Sym("*name")) => {
+                self.pos += 1;
+                NodeTest::Any
+            }
+            Some(Tok::Name(name)) => {
+                self.pos += 1;
+                if self.eat("(") {
+                    self.expect(")")?;
+                    match name.as_str() {
+                        "text" => NodeTest::Text,
+                        "node" => NodeTest::Node,
+                        "comment" => NodeTest::Comment,
+                        _ => return Err(self.error(&format!("unknown node test {}()", name))),
+                    }
+                } else {
+                    NodeTest::Name(name)
+                }
+            }
+            _ => return Err(self.error("expected a location step")),
+        };
+        let preds = self.parse_predicates()?;
+        Ok(Step { axis, test, preds })
+    }
+
+    fn parse_predicates(&mut self) -> Result<Vec<Expr>, String> {
+        let mut preds = vec![];
+        while self.eat("[") {
+            preds.push(self.parse_or()?);
+            self.expect("]")?;
+        }
+        Ok(preds)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        let tok = self.peek().cloned();
+        self.pos += 1;
+        match tok {
+            Some(Tok::Lit(s)) => Ok(Expr::Lit(s)),
+            Some(Tok::Num(n)) => Ok(Expr::Num(n)),
+            Some(Tok::Var(v)) => Ok(Expr::Var(v)),
+            Some(Tok::Sym("(")) => {
+                let expr = self.parse_or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Tok::Name(name)) => {
+                self.expect("(")?;
+                let mut args = vec![];
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.parse_or()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            _ => Err(self.error("expected an expression")),
+        }
+    }
+}
+
+fn descendant_or_self_step() -> Step {
+    Step {
+        axis: Axis::DescendantOrSelf,
+        test: NodeTest::Node,
+        preds: vec![],
+    }
+}
+
+fn parse_xpath(text: &str) -> Result<Expr, String> {
+    Parser::new(text)?.parse_all()
+}
+
+// Template match patterns
+
+#[derive(Debug, Clone)]
+struct PatternStep {
+    attribute: bool,
+    test: NodeTest,
+    preds: Vec<Expr>,
+    /// Joined to the step before it by `//` rather than `/`
+    any_ancestor: bool,
+}
+
+#[derive(Debug, Clone)]
+struct PatternAlt {
+    absolute: bool,
+    steps: Vec<PatternStep>,
+}
+
+impl PatternAlt {
+    fn default_priority(&self) -> f64 {
+        match self.steps.as_slice() {
+            [step] if step.preds.is_empty() => match step.test {
+                NodeTest::Name(_) => 0.0,
+                _ => -0.5,
+            },
+            _ => 0.5,
+        }
+    }
+}
+
+/// Patterns are parsed as XPath location paths, then restricted to the
+/// child and attribute axes
+fn parse_pattern(text: &str) -> Result<Vec<PatternAlt>, String> {
+    let mut alts = vec![];
+    let mut stack = vec![parse_xpath(text)?];
+    while let Some(expr) = stack.pop() {
+        let Expr::Path { absolute, steps } = expr else {
+            if let Expr::Union(a, b) = expr {
+                stack.push(*b);
+                stack.push(*a);
+                continue;
+            }
+            return Err(format!("Unsupported XSLT match pattern '{}'", text));
+        };
+        let mut alt = PatternAlt {
+            absolute,
+            steps: vec![],
+        };
+        let mut any_ancestor = false;
+        for step in steps {
+            if step.axis == Axis::DescendantOrSelf && step.test == NodeTest::Node {
+                any_ancestor = true;
+                continue;
+            }
+            if !matches!(step.axis, Axis::Child | Axis::Attribute) {
+                return Err(format!("Unsupported XSLT match pattern '{}'", text));
+            }
+            alt.steps.push(PatternStep {
+                attribute: step.axis == Axis::Attribute,
+                test: step.test,
+                preds: step.preds,
+                any_ancestor,
+            });
+            any_ancestor = false;
+        }
+        if alt.steps.first().is_some_and(|s| s.any_ancestor) {
+            alt.absolute = false;
+        }
+        alts.push(alt);
+    }
+    Ok(alts)
+}
+
+// The stylesheet
+
+struct Template {
+    pattern: Vec<PatternAlt>,
+    name: Option<String>,
+    mode: Option<String>,
+    priority: Option<f64>,
+    body: Element,
+}
+
+/// A compiled stylesheet
+pub struct Stylesheet {
+    templates: Vec<Template>,
+    /// Top level `xsl:variable` and `xsl:param` elements
+    globals: Vec<Element>,
+    method: Option<String>,
+    indent: bool,
+}
+
+/// Drop comments and whitespace only text from the stylesheet, except
+/// inside `xsl:text`
+fn strip_stylesheet(el: &mut Element) {
+    if el.name == "xsl:text" {
+        return;
+    }
+    el.children.retain(|c| match c {
+        Node::Text(t) => !t.trim().is_empty(),
+        Node::Comment(_) => false,
+        Node::Element(_) => true,
+    });
+    for child in el.child_elements_mut() {
+        strip_stylesheet(child);
+    }
+}
+
+impl Stylesheet {
+    /// Compile an `xsl:stylesheet` or `xsl:transform` element
+    pub fn new(root: &Element) -> Result<Self, String> {
+        if root.name != "xsl:stylesheet" && root.name != "xsl:transform" {
+            return Err(format!(
+                "Expected an xsl:stylesheet element, found '{}'",
+                root.name
+            ));
+        }
+        let mut root = root.clone();
+        strip_stylesheet(&mut root);
+        let mut ret = Self {
+            templates: vec![],
+            globals: vec![],
+            method: None,
+            indent: false,
+        };
+        for el in root.child_elements() {
+            match el.name.as_str() {
+                "xsl:template" => {
+                    let pattern = el.attr("match").map(parse_pattern).transpose()?;
+                    let priority = el
+                        .attr("priority")
+                        .map(|p| {
+                            p.trim()
+                                .parse::<f64>()
+                                .map_err(|_| format!("Invalid template priority '{}'", p))
+                        })
+                        .transpose()?;
+                    ret.templates.push(Template {
+                        pattern: pattern.unwrap_or_default(),
+                        name: el.attr("name").map(str::to_string),
+                        mode: el.attr("mode").map(str::to_string),
+                        priority,
+                        body: el.clone(),
+                    });
+                }
+                "xsl:variable" | "xsl:param" => ret.globals.push(el.clone()),
+                "xsl:output" => {
+                    ret.method = el.attr("method").map(str::to_string);
+                    ret.indent = el.attr("indent") == Some("yes");
+                }
+                "xsl:strip-space" | "xsl:preserve-space" => {}
+                other => {
+                    return Err(format!("Unsupported XSLT top level element '{}'", other));
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        Self::new(&Element::parse(xml)?)
+    }
+
+    fn processor<'s>(&'s self, docnode: &'s Element) -> Processor<'s> {
+        Processor {
+            sheet: self,
+            doc: docnode,
+            vars: vec![],
+            out: vec![Element::new("")],
+            depth: 0,
+        }
+    }
+
+    fn document_node(doc: &Element) -> Element {
+        let mut docnode = Element::new("");
+        docnode.children.push(Node::Element(doc.clone()));
+        docnode
+    }
+
+    /// Whether a template rule of the default mode matches the root
+    /// element of `doc`, rather than it falling to the built in rules
+    pub fn has_template_for(&self, doc: &Element) -> bool {
+        let docnode = Self::document_node(doc);
+        let mut proc = self.processor(&docnode);
+        let root = NodeRef::root().child(0);
+        proc.find_template(&root, None).is_ok_and(|t| t.is_some())
+    }
+
+    /// Apply the stylesheet to the document with root element `doc`
+    pub fn transform(&self, doc: &Element) -> Result<String, String> {
+        let docnode = Self::document_node(doc);
+        let mut proc = self.processor(&docnode);
+        let ctx = Ctx {
+            node: NodeRef::root(),
+            current: NodeRef::root(),
+            pos: 1,
+            size: 1,
+        };
+        for global in &self.globals {
+            let value = proc.variable_value(global, &ctx)?;
+            proc.vars
+                .push((global.attr("name").unwrap_or_default().to_string(), value));
+        }
+        proc.apply_templates(&[NodeRef::root()], None, &[])?;
+        let mut result = proc.out.pop().expect("result root");
+
+        let is_xml = match self.method.as_deref() {
+            Some(method) => method != "text",
+            None => result.child_elements().next().is_some(),
+        };
+        if !is_xml {
+            return Ok(result.text());
+        }
+        let mut out = String::from("<?xml version=\"1.0\"?>\n");
+        for child in result.children.iter_mut() {
+            match child {
+                Node::Element(e) => {
+                    if self.indent {
+                        e.prettify();
+                    }
+                    out.push_str(&e.to_xml());
+                    out.push('\n');
+                }
+                Node::Text(t) => out.push_str(&crate::xmlapi::escape_text(t)),
+                Node::Comment(t) => {
+                    out.push_str(&format!("<!--{}-->", t));
+                    if self.indent {
+                        out.push('\n');
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+// Evaluation
+
+#[derive(Debug, Clone)]
+struct Ctx {
+    node: NodeRef,
+    /// The XSLT current node, for `current()`
+    current: NodeRef,
+    pos: usize,
+    size: usize,
+}
+
+impl Ctx {
+    fn at(&self, node: NodeRef, pos: usize, size: usize) -> Self {
+        Self {
+            node,
+            current: self.current.clone(),
+            pos,
+            size,
+        }
+    }
+}
+
+struct Processor<'s> {
+    sheet: &'s Stylesheet,
+    doc: &'s Element,
+    vars: Vec<(String, Value)>,
+    /// Result elements under construction, innermost last
+    out: Vec<Element>,
+    depth: usize,
+}
+
+impl<'s> Processor<'s> {
+    fn item(&self, node: &NodeRef) -> Item<'s> {
+        let mut el = self.doc;
+        for (i, idx) in node.path.iter().enumerate() {
+            match &el.children[*idx] {
+                Node::Element(child) => el = child,
+                Node::Text(t) if i + 1 == node.path.len() => return Item::Text(t),
+                Node::Comment(t) if i + 1 == node.path.len() => return Item::Comment(t),
+                _ => unreachable!("node path goes through a leaf"),
+            }
+        }
+        if let Some(attr) = node.attr {
+            let (k, v) = &el.attrs[attr];
+            return Item::Attr(k, v);
+        }
+        if node.path.is_empty() {
+            Item::Root(el)
+        } else {
+            Item::Element(el)
+        }
+    }
+
+    fn element(&self, node: &NodeRef) -> Option<&'s Element> {
+        match self.item(node) {
+            Item::Root(e) | Item::Element(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn string_value(&self, node: &NodeRef) -> String {
+        match self.item(node) {
+            Item::Root(e) | Item::Element(e) => e.text(),
+            Item::Text(t) | Item::Comment(t) => t.to_string(),
+            Item::Attr(_, v) => v.to_string(),
+        }
+    }
+
+    fn node_name(&self, node: &NodeRef) -> String {
+        match self.item(node) {
+            Item::Element(e) => e.name.clone(),
+            Item::Attr(k, _) => k.to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn matches_test(&self, node: &NodeRef, test: &NodeTest, attribute: bool) -> bool {
+        let item = self.item(node);
+        match test {
+            NodeTest::Node => true,
+            NodeTest::Text => matches!(item, Item::Text(_)),
+            NodeTest::Comment => matches!(item, Item::Comment(_)),
+            NodeTest::Any if attribute => matches!(item, Item::Attr(..)),
+            NodeTest::Any => matches!(item, Item::Element(_)),
+            NodeTest::Name(name) => match item {
+                Item::Attr(k, _) if attribute => k == name,
+                Item::Element(e) if !attribute => e.name == *name,
+                _ => false,
+            },
+        }
+    }
+
+    fn children(&self, node: &NodeRef) -> Vec<NodeRef> {
+        if node.attr.is_some() {
+            return vec![];
+        }
+        self.element(node)
+            .map(|e| (0..e.children.len()).map(|i| node.child(i)).collect())
+            .unwrap_or_default()
+    }
+
+    fn descendants(&self, node: &NodeRef, out: &mut Vec<NodeRef>) {
+        for child in self.children(node) {
+            out.push(child.clone());
+            self.descendants(&child, out);
+        }
+    }
+
+    /// Nodes on `axis` from `node`, nearest first
+    fn axis_nodes(&self, node: &NodeRef, axis: Axis) -> Vec<NodeRef> {
+        let mut ret = vec![];
+        match axis {
+            Axis::Child => ret = self.children(node),
+            Axis::Attribute => {
+                if node.attr.is_none()
+                    && let Some(Item::Element(e)) = Some(self.item(node))
+                {
+                    for (i, (k, _)) in e.attrs.iter().enumerate() {
+                        if k != "xmlns" && !k.starts_with("xmlns:") {
+                            ret.push(NodeRef {
+                                path: node.path.clone(),
+                                attr: Some(i),
+                            });
+                        }
+                    }
+                }
+            }
+            Axis::Current => ret.push(node.clone()),
+            Axis::Parent => ret.extend(node.parent()),
+            Axis::Descendant => self.descendants(node, &mut ret),
+            Axis::DescendantOrSelf => {
+                ret.push(node.clone());
+                self.descendants(node, &mut ret);
+            }
+            Axis::Ancestor | Axis::AncestorOrSelf => {
+                if axis == Axis::AncestorOrSelf {
+                    ret.push(node.clone());
+                }
+                let mut cur = node.parent();
+                while let Some(p) = cur {
+                    cur = p.parent();
+                    ret.push(p);
+                }
+            }
+            Axis::FollowingSibling | Axis::PrecedingSibling => {
+                if node.attr.is_some() {
+                    return ret;
+                }
+                let (Some(parent), Some(idx)) = (node.parent(), node.path.last()) else {
+                    return ret;
+                };
+                let siblings = self.children(&parent);
+                if axis == Axis::FollowingSibling {
+                    ret.extend(siblings.into_iter().skip(idx + 1));
+                } else {
+                    ret.extend(siblings.into_iter().take(*idx).rev());
+                }
+            }
+        }
+        ret
+    }
+
+    fn filter_predicates(
+        &mut self,
+        mut nodes: Vec<NodeRef>,
+        preds: &[Expr],
+        ctx: &Ctx,
+    ) -> Result<Vec<NodeRef>, String> {
+        for pred in preds {
+            let size = nodes.len();
+            let mut kept = vec![];
+            for (i, node) in nodes.into_iter().enumerate() {
+                let pctx = ctx.at(node.clone(), i + 1, size);
+                let keep = match self.eval(pred, &pctx)? {
+                    Value::Num(n) => n == (i + 1) as f64,
+                    v => self.to_bool(&v),
+                };
+                if keep {
+                    kept.push(node);
+                }
+            }
+            nodes = kept;
+        }
+        Ok(nodes)
+    }
+
+    fn eval_steps(
+        &mut self,
+        mut nodes: Vec<NodeRef>,
+        steps: &[Step],
+        ctx: &Ctx,
+    ) -> Result<Vec<NodeRef>, String> {
+        for step in steps {
+            let mut next = vec![];
+            for node in &nodes {
+                let attribute = step.axis == Axis::Attribute;
+                let found: Vec<NodeRef> = self
+                    .axis_nodes(node, step.axis)
+                    .into_iter()
+                    .filter(|n| self.matches_test(n, &step.test, attribute))
+                    .collect();
+                next.extend(self.filter_predicates(found, &step.preds, ctx)?);
+            }
+            if steps.len() > 1 || step.axis.is_reverse() {
+                next.sort();
+                next.dedup();
+            }
+            nodes = next;
+        }
+        Ok(nodes)
+    }
+
+    fn lookup_var(&self, name: &str) -> Result<Value, String> {
+        self.vars
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| format!("Undefined XSLT variable '${}'", name))
+    }
+
+    fn to_string(&self, v: &Value) -> String {
+        match v {
+            Value::Nodes(nodes) => nodes
+                .first()
+                .map(|n| self.string_value(n))
+                .unwrap_or_default(),
+            Value::Str(s) => s.clone(),
+            Value::Num(n) => num_to_string(*n),
+            Value::Bool(b) => b.to_string(),
+        }
+    }
+
+    fn to_num(&self, v: &Value) -> f64 {
+        match v {
+            Value::Num(n) => *n,
+            Value::Bool(b) => f64::from(u8::from(*b)),
+            _ => str_to_num(&self.to_string(v)),
+        }
+    }
+
+    fn to_bool(&self, v: &Value) -> bool {
+        match v {
+            Value::Nodes(nodes) => !nodes.is_empty(),
+            Value::Str(s) => !s.is_empty(),
+            Value::Num(n) => *n != 0.0 && !n.is_nan(),
+            Value::Bool(b) => *b,
+        }
+    }
+
+    fn compare(&self, op: &str, lhs: &Value, rhs: &Value) -> bool {
+        let cmp_num = |a: f64, b: f64| match op {
+            "=" => a == b,
+            "!=" => a != b,
+            "<" => a < b,
+            "<=" => a <= b,
+            ">" => a > b,
+            _ => a >= b,
+        };
+        let equality = op == "=" || op == "!=";
+        let cmp_str = |a: &str, b: &str| match op {
+            "=" => a == b,
+            "!=" => a != b,
+            _ => cmp_num(str_to_num(a), str_to_num(b)),
+        };
+        match (lhs, rhs) {
+            (Value::Nodes(a), Value::Nodes(b)) => a.iter().any(|x| {
+                let xs = self.string_value(x);
+                b.iter().any(|y| cmp_str(&xs, &self.string_value(y)))
+            }),
+            (Value::Nodes(_), Value::Bool(_)) | (Value::Bool(_), Value::Nodes(_)) => cmp_num(
+                f64::from(u8::from(self.to_bool(lhs))),
+                f64::from(u8::from(self.to_bool(rhs))),
+            ),
+            (Value::Nodes(a), other) => a.iter().any(|x| {
+                let xs = self.string_value(x);
+                match other {
+                    Value::Num(n) => cmp_num(str_to_num(&xs), *n),
+                    _ => cmp_str(&xs, &self.to_string(other)),
+                }
+            }),
+            (other, Value::Nodes(b)) => b.iter().any(|y| {
+                let ys = self.string_value(y);
+                match other {
+                    Value::Num(n) => cmp_num(*n, str_to_num(&ys)),
+                    _ => cmp_str(&self.to_string(other), &ys),
+                }
+            }),
+            _ if equality && (matches!(lhs, Value::Bool(_)) || matches!(rhs, Value::Bool(_))) => {
+                cmp_num(
+                    f64::from(u8::from(self.to_bool(lhs))),
+                    f64::from(u8::from(self.to_bool(rhs))),
+                )
+            }
+            _ if equality && (matches!(lhs, Value::Num(_)) || matches!(rhs, Value::Num(_))) => {
+                cmp_num(self.to_num(lhs), self.to_num(rhs))
+            }
+            _ if equality => cmp_str(&self.to_string(lhs), &self.to_string(rhs)),
+            _ => cmp_num(self.to_num(lhs), self.to_num(rhs)),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr, ctx: &Ctx) -> Result<Value, String> {
+        Ok(match expr {
+            Expr::Or(a, b) => {
+                let lhs = self.eval(a, ctx)?;
+                Value::Bool(
+                    self.to_bool(&lhs) || {
+                        let rhs = self.eval(b, ctx)?;
+                        self.to_bool(&rhs)
+                    },
+                )
+            }
+            Expr::And(a, b) => {
+                let lhs = self.eval(a, ctx)?;
+                Value::Bool(
+                    self.to_bool(&lhs) && {
+                        let rhs = self.eval(b, ctx)?;
+                        self.to_bool(&rhs)
+                    },
+                )
+            }
+            Expr::Cmp(op, a, b) => {
+                let lhs = self.eval(a, ctx)?;
+                let rhs = self.eval(b, ctx)?;
+                Value::Bool(self.compare(op, &lhs, &rhs))
+            }
+            Expr::Arith(op, a, b) => {
+                let lhs = self.eval(a, ctx)?;
+                let rhs = self.eval(b, ctx)?;
+                let (x, y) = (self.to_num(&lhs), self.to_num(&rhs));
+                Value::Num(match *op {
+                    "+" => x + y,
+                    "-" => x - y,
+                    "*" => x * y,
+                    "div" => x / y,
+                    _ => x % y,
+                })
+            }
+            Expr::Neg(a) => {
+                let v = self.eval(a, ctx)?;
+                Value::Num(-self.to_num(&v))
+            }
+            Expr::Union(a, b) => {
+                let (Value::Nodes(mut x), Value::Nodes(y)) =
+                    (self.eval(a, ctx)?, self.eval(b, ctx)?)
+                else {
+                    return Err("XPath union of non node-sets".to_string());
+                };
+                x.extend(y);
+                x.sort();
+                x.dedup();
+                Value::Nodes(x)
+            }
+            Expr::Lit(s) => Value::Str(s.clone()),
+            Expr::Num(n) => Value::Num(*n),
+            Expr::Var(name) => self.lookup_var(name)?,
+            Expr::Call(name, args) => self.call(name, args, ctx)?,
+            Expr::Path { absolute, steps } => {
+                let start = if *absolute {
+                    NodeRef::root()
+                } else {
+                    ctx.node.clone()
+                };
+                Value::Nodes(self.eval_steps(vec![start], steps, ctx)?)
+            }
+            Expr::Filter {
+                primary,
+                preds,
+                steps,
+            } => {
+                let Value::Nodes(nodes) = self.eval(primary, ctx)? else {
+                    return Err("XPath predicate or path on a non node-set".to_string());
+                };
+                let nodes = self.filter_predicates(nodes, preds, ctx)?;
+                Value::Nodes(self.eval_steps(nodes, steps, ctx)?)
+            }
+        })
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], ctx: &Ctx) -> Result<Value, String> {
+        let mut vals = vec![];
+        for arg in args {
+            vals.push(self.eval(arg, ctx)?);
+        }
+        let nargs = |min: usize, max: usize| {
+            if vals.len() < min || vals.len() > max {
+                return Err(format!(
+                    "XPath function {}() called with {} arguments",
+                    name,
+                    vals.len()
+                ));
+            }
+            Ok(())
+        };
+        // The string argument, defaulting to the context node
+        let str_arg = |proc: &Self, idx: usize| match vals.get(idx) {
+            Some(v) => proc.to_string(v),
+            None => proc.string_value(&ctx.node),
+        };
+        Ok(match name {
+            "last" => Value::Num(ctx.size as f64),
+            "position" => Value::Num(ctx.pos as f64),
+            "current" => Value::Nodes(vec![ctx.current.clone()]),
+            "count" => {
+                nargs(1, 1)?;
+                match &vals[0] {
+                    Value::Nodes(n) => Value::Num(n.len() as f64),
+                    _ => return Err("count() needs a node-set".to_string()),
+                }
+            }
+            "name" | "local-name" => {
+                nargs(0, 1)?;
+                let node = match vals.first() {
+                    Some(Value::Nodes(n)) => n.first().cloned(),
+                    Some(_) => return Err(format!("{}() needs a node-set", name)),
+                    None => Some(ctx.node.clone()),
+                };
+                let full = node.map(|n| self.node_name(&n)).unwrap_or_default();
+                if name == "local-name" {
+                    Value::Str(full.rsplit(':').next().unwrap_or_default().to_string())
+                } else {
+                    Value::Str(full)
+                }
+            }
+            "string" => {
+                nargs(0, 1)?;
+                Value::Str(str_arg(self, 0))
+            }
+            "concat" => {
+                nargs(2, usize::MAX)?;
+                Value::Str(vals.iter().map(|v| self.to_string(v)).collect())
+            }
+            "starts-with" | "contains" | "substring-before" | "substring-after" => {
+                nargs(2, 2)?;
+                let (s, sub) = (self.to_string(&vals[0]), self.to_string(&vals[1]));
+                match name {
+                    "starts-with" => Value::Bool(s.starts_with(&sub)),
+                    "contains" => Value::Bool(s.contains(&sub)),
+                    "substring-before" => {
+                        Value::Str(s.split_once(&sub).map(|(a, _)| a).unwrap_or("").to_string())
+                    }
+                    _ => Value::Str(s.split_once(&sub).map(|(_, b)| b).unwrap_or("").to_string()),
+                }
+            }
+            "substring" => {
+                nargs(2, 3)?;
+                let chars: Vec<char> = self.to_string(&vals[0]).chars().collect();
+                let start = self.to_num(&vals[1]).round();
+                let end = match vals.get(2) {
+                    Some(len) => start + self.to_num(len).round(),
+                    None => f64::INFINITY,
+                };
+                Value::Str(
+                    chars
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| {
+                            let pos = (*i + 1) as f64;
+                            pos >= start && pos < end
+                        })
+                        .map(|(_, c)| c)
+                        .collect(),
+                )
+            }
+            "string-length" => {
+                nargs(0, 1)?;
+                Value::Num(str_arg(self, 0).chars().count() as f64)
+            }
+            "normalize-space" => {
+                nargs(0, 1)?;
+                Value::Str(
+                    str_arg(self, 0)
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                )
+            }
+            "translate" => {
+                nargs(3, 3)?;
+                let from: Vec<char> = self.to_string(&vals[1]).chars().collect();
+                let to: Vec<char> = self.to_string(&vals[2]).chars().collect();
+                Value::Str(
+                    self.to_string(&vals[0])
+                        .chars()
+                        .filter_map(|c| match from.iter().position(|f| *f == c) {
+                            Some(i) => to.get(i).copied(),
+                            None => Some(c),
+                        })
+                        .collect(),
+                )
+            }
+            "boolean" => {
+                nargs(1, 1)?;
+                Value::Bool(self.to_bool(&vals[0]))
+            }
+            "not" => {
+                nargs(1, 1)?;
+                Value::Bool(!self.to_bool(&vals[0]))
+            }
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "number" => {
+                nargs(0, 1)?;
+                match vals.first() {
+                    Some(v) => Value::Num(self.to_num(v)),
+                    None => Value::Num(str_to_num(&self.string_value(&ctx.node))),
+                }
+            }
+            "sum" => {
+                nargs(1, 1)?;
+                let Value::Nodes(nodes) = &vals[0] else {
+                    return Err("sum() needs a node-set".to_string());
+                };
+                Value::Num(
+                    nodes
+                        .iter()
+                        .map(|n| str_to_num(&self.string_value(n)))
+                        .sum(),
+                )
+            }
+            "floor" | "ceiling" | "round" => {
+                nargs(1, 1)?;
+                let n = self.to_num(&vals[0]);
+                Value::Num(match name {
+                    "floor" => n.floor(),
+                    "ceiling" => n.ceil(),
+                    _ => (n + 0.5).floor(),
+                })
+            }
+            _ => return Err(format!("Unsupported XPath function '{}()'", name)),
+        })
+    }
+
+    fn eval_attr(&mut self, el: &Element, attr: &str, ctx: &Ctx) -> Result<Value, String> {
+        let text = el
+            .attr(attr)
+            .ok_or_else(|| format!("{} is missing the '{}' attribute", el.name, attr))?;
+        self.eval(&parse_xpath(text)?, ctx)
+    }
+
+    /// Expand an attribute value template like `{$prefix}-disk`
+    fn avt(&mut self, text: &str, ctx: &Ctx) -> Result<String, String> {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(idx) = rest.find(['{', '}']) {
+            out.push_str(&rest[..idx]);
+            let ch = &rest[idx..idx + 1];
+            let after = &rest[idx + 1..];
+            if after.starts_with(ch) {
+                out.push_str(ch);
+                rest = &after[1..];
+                continue;
+            }
+            if ch == "}" {
+                return Err(format!("Unbalanced '}}' in attribute value '{}'", text));
+            }
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("Unbalanced '{{' in attribute value '{}'", text))?;
+            let value = self.eval(&parse_xpath(&after[..end])?, ctx)?;
+            out.push_str(&self.to_string(&value));
+            rest = &after[end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    // Result tree construction
+
+    fn emit_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let cur = self.out.last_mut().expect("result root");
+        if let Some(Node::Text(last)) = cur.children.last_mut() {
+            last.push_str(text);
+        } else {
+            cur.children.push(Node::Text(text.to_string()));
+        }
+    }
+
+    fn emit_node(&mut self, node: Node) {
+        match node {
+            Node::Text(t) => self.emit_text(&t),
+            node => self
+                .out
+                .last_mut()
+                .expect("result root")
+                .children
+                .push(node),
+        }
+    }
+
+    /// Run `body` with a fresh result element, returning it
+    fn capture(&mut self, body: &'s Element, ctx: &Ctx) -> Result<Element, String> {
+        self.out.push(Element::new(""));
+        let ret = self.exec_body(body, ctx);
+        let captured = self.out.pop().expect("captured result");
+        ret.map(|_| captured)
+    }
+
+    fn copy_node(&mut self, node: &NodeRef) {
+        match self.item(node) {
+            Item::Root(e) => {
+                for child in &e.children {
+                    self.emit_node(child.clone());
+                }
+            }
+            Item::Element(e) => self.emit_node(Node::Element(e.clone())),
+            Item::Text(t) => self.emit_text(t),
+            Item::Comment(t) => self.emit_node(Node::Comment(t.to_string())),
+            Item::Attr(k, v) => self
+                .out
+                .last_mut()
+                .expect("result root")
+                .set_attr(k, Some(v)),
+        }
+    }
+
+    // Instructions
+
+    fn variable_value(&mut self, el: &'s Element, ctx: &Ctx) -> Result<Value, String> {
+        if el.attr("select").is_some() {
+            return self.eval_attr(el, "select", ctx);
+        }
+        // A result tree fragment, used as its string value
+        Ok(Value::Str(self.capture(el, ctx)?.text()))
+    }
+
+    fn with_params(&mut self, el: &'s Element, ctx: &Ctx) -> Result<Vec<(String, Value)>, String> {
+        let mut params = vec![];
+        for child in el.child_elements().filter(|c| c.name == "xsl:with-param") {
+            let name = child.attr("name").unwrap_or_default().to_string();
+            params.push((name, self.variable_value(child, ctx)?));
+        }
+        Ok(params)
+    }
+
+    fn exec_body(&mut self, body: &'s Element, ctx: &Ctx) -> Result<(), String> {
+        let nvars = self.vars.len();
+        let ret = body
+            .children
+            .iter()
+            .try_for_each(|child| self.exec_node(child, ctx));
+        self.vars.truncate(nvars);
+        ret
+    }
+
+    fn exec_node(&mut self, node: &'s Node, ctx: &Ctx) -> Result<(), String> {
+        let el = match node {
+            Node::Text(t) => {
+                self.emit_text(t);
+                return Ok(());
+            }
+            Node::Comment(_) => return Ok(()),
+            Node::Element(el) => el,
+        };
+        let Some(instr) = el.name.strip_prefix("xsl:") else {
+            return self.exec_literal(el, ctx);
+        };
+        match instr {
+            "text" => self.emit_text(&el.text()),
+            "value-of" => {
+                let value = self.eval_attr(el, "select", ctx)?;
+                let text = self.to_string(&value);
+                self.emit_text(&text);
+            }
+            "variable" | "param" => {
+                let value = self.variable_value(el, ctx)?;
+                self.vars
+                    .push((el.attr("name").unwrap_or_default().to_string(), value));
+            }
+            "if" => {
+                let test = self.eval_attr(el, "test", ctx)?;
+                if self.to_bool(&test) {
+                    self.exec_body(el, ctx)?;
+                }
+            }
+            "choose" => {
+                for branch in el.child_elements() {
+                    let taken = match branch.name.as_str() {
+                        "xsl:when" => {
+                            let test = self.eval_attr(branch, "test", ctx)?;
+                            self.to_bool(&test)
+                        }
+                        "xsl:otherwise" => true,
+                        other => return Err(format!("Unexpected '{}' in xsl:choose", other)),
+                    };
+                    if taken {
+                        return self.exec_body(branch, ctx);
+                    }
+                }
+            }
+            "for-each" => {
+                let Value::Nodes(nodes) = self.eval_attr(el, "select", ctx)? else {
+                    return Err("xsl:for-each select is not a node-set".to_string());
+                };
+                let size = nodes.len();
+                for (i, node) in nodes.into_iter().enumerate() {
+                    let ictx = Ctx {
+                        node: node.clone(),
+                        current: node,
+                        pos: i + 1,
+                        size,
+                    };
+                    self.exec_body(el, &ictx)?;
+                }
+            }
+            "apply-templates" => {
+                let nodes = match el.attr("select") {
+                    Some(_) => match self.eval_attr(el, "select", ctx)? {
+                        Value::Nodes(nodes) => nodes,
+                        _ => return Err("xsl:apply-templates select is not a node-set".to_string()),
+                    },
+                    None => self.children(&ctx.node),
+                };
+                let params = self.with_params(el, ctx)?;
+                self.apply_templates(&nodes, el.attr("mode"), &params)?;
+            }
+            "call-template" => {
+                let name = el.attr("name").unwrap_or_default();
+                let sheet = self.sheet;
+                let template = sheet
+                    .templates
+                    .iter()
+                    .rev()
+                    .find(|t| t.name.as_deref() == Some(name))
+                    .ok_or_else(|| format!("No XSLT template named '{}'", name))?;
+                let params = self.with_params(el, ctx)?;
+                self.exec_template(template, ctx, &params)?;
+            }
+            "element" => {
+                let name = self.avt(el.attr("name").unwrap_or_default(), ctx)?;
+                self.out.push(Element::new(&name));
+                self.exec_body(el, ctx)?;
+                let built = self.out.pop().expect("built element");
+                self.emit_node(Node::Element(built));
+            }
+            "attribute" => {
+                let name = self.avt(el.attr("name").unwrap_or_default(), ctx)?;
+                let value = self.capture(el, ctx)?.text();
+                self.out
+                    .last_mut()
+                    .expect("result root")
+                    .set_attr(&name, Some(&value));
+            }
+            "copy-of" => match self.eval_attr(el, "select", ctx)? {
+                Value::Nodes(nodes) => nodes.iter().for_each(|n| self.copy_node(n)),
+                v => {
+                    let text = self.to_string(&v);
+                    self.emit_text(&text);
+                }
+            },
+            "copy" => match self.item(&ctx.node) {
+                Item::Element(e) => {
+                    self.out.push(Element::new(&e.name));
+                    self.exec_body(el, ctx)?;
+                    let built = self.out.pop().expect("built element");
+                    self.emit_node(Node::Element(built));
+                }
+                Item::Root(_) => self.exec_body(el, ctx)?,
+                _ => self.copy_node(&ctx.node.clone()),
+            },
+            "comment" => {
+                let text = self.capture(el, ctx)?.text();
+                self.emit_node(Node::Comment(text));
+            }
+            "message" => {
+                let text = self.capture(el, ctx)?.text();
+                if el.attr("terminate") == Some("yes") {
+                    return Err(text);
+                }
+                log::debug!("xsl:message: {}", text);
+            }
+            "fallback" | "sort" | "with-param" => {}
+            other => return Err(format!("Unsupported XSLT instruction 'xsl:{}'", other)),
+        }
+        Ok(())
+    }
+
+    fn exec_literal(&mut self, el: &'s Element, ctx: &Ctx) -> Result<(), String> {
+        let mut built = Element::new(&el.name);
+        for (k, v) in &el.attrs {
+            if k == "xmlns:xsl" || k == "exclude-result-prefixes" || k.starts_with("xsl:") {
+                continue;
+            }
+            let value = self.avt(v, ctx)?;
+            built.attrs.push((k.clone(), value));
+        }
+        self.out.push(built);
+        self.exec_body(el, ctx)?;
+        let built = self.out.pop().expect("built element");
+        self.emit_node(Node::Element(built));
+        Ok(())
+    }
+
+    fn exec_template(
+        &mut self,
+        template: &'s Template,
+        ctx: &Ctx,
+        params: &[(String, Value)],
+    ) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("XSLT template recursion is too deep".to_string());
+        }
+        let nvars = self.vars.len();
+        let ret = template.body.children.iter().try_for_each(|child| {
+            if let Node::Element(el) = child
+                && el.name == "xsl:param"
+            {
+                let name = el.attr("name").unwrap_or_default();
+                let value = match params.iter().find(|(n, _)| n == name) {
+                    Some((_, v)) => v.clone(),
+                    None => self.variable_value(el, ctx)?,
+                };
+                self.vars.push((name.to_string(), value));
+                return Ok(());
+            }
+            self.exec_node(child, ctx)
+        });
+        self.vars.truncate(nvars);
+        self.depth -= 1;
+        ret
+    }
+
+    fn pattern_step_matches(&mut self, node: &NodeRef, step: &PatternStep) -> Result<bool, String> {
+        if !self.matches_test(node, &step.test, step.attribute) {
+            return Ok(false);
+        }
+        if step.preds.is_empty() {
+            return Ok(true);
+        }
+        // Predicates see the node's position among the siblings that
+        // match the same test
+        let Some(parent) = node.parent() else {
+            return Ok(false);
+        };
+        let axis = if step.attribute {
+            Axis::Attribute
+        } else {
+            Axis::Child
+        };
+        let siblings: Vec<NodeRef> = self
+            .axis_nodes(&parent, axis)
+            .into_iter()
+            .filter(|n| self.matches_test(n, &step.test, step.attribute))
+            .collect();
+        let ctx = Ctx {
+            node: node.clone(),
+            current: node.clone(),
+            pos: 1,
+            size: 1,
+        };
+        let kept = self.filter_predicates(siblings, &step.preds, &ctx)?;
+        Ok(kept.contains(node))
+    }
+
+    fn pattern_matches(&mut self, alt: &PatternAlt, node: &NodeRef) -> Result<bool, String> {
+        if alt.steps.is_empty() {
+            return Ok(node.path.is_empty() && node.attr.is_none());
+        }
+        self.pattern_suffix_matches(alt, alt.steps.len(), node)
+    }
+
+    /// Whether `node` matches the first `nsteps` steps of `alt`
+    fn pattern_suffix_matches(
+        &mut self,
+        alt: &PatternAlt,
+        nsteps: usize,
+        node: &NodeRef,
+    ) -> Result<bool, String> {
+        let step = &alt.steps[nsteps - 1];
+        if !self.pattern_step_matches(node, step)? {
+            return Ok(false);
+        }
+        let Some(parent) = node.parent() else {
+            return Ok(false);
+        };
+        if nsteps == 1 {
+            return Ok(!alt.absolute || parent.path.is_empty());
+        }
+        if !step.any_ancestor {
+            return self.pattern_suffix_matches(alt, nsteps - 1, &parent);
+        }
+        let mut cur = Some(parent);
+        while let Some(anc) = cur {
+            if self.pattern_suffix_matches(alt, nsteps - 1, &anc)? {
+                return Ok(true);
+            }
+            cur = anc.parent();
+        }
+        Ok(false)
+    }
+
+    /// The template rule for `node`: the highest priority match, the
+    /// last one in the stylesheet on ties
+    fn find_template(
+        &mut self,
+        node: &NodeRef,
+        mode: Option<&str>,
+    ) -> Result<Option<&'s Template>, String> {
+        let sheet = self.sheet;
+        let mut best: Option<(f64, &'s Template)> = None;
+        for template in sheet.templates.iter().filter(|t| t.mode.as_deref() == mode) {
+            for alt in &template.pattern {
+                if !self.pattern_matches(alt, node)? {
+                    continue;
+                }
+                let priority = template.priority.unwrap_or_else(|| alt.default_priority());
+                if best.is_none_or(|(p, _)| priority >= p) {
+                    best = Some((priority, template));
+                }
+            }
+        }
+        Ok(best.map(|(_, t)| t))
+    }
+
+    fn apply_templates(
+        &mut self,
+        nodes: &[NodeRef],
+        mode: Option<&str>,
+        params: &[(String, Value)],
+    ) -> Result<(), String> {
+        let size = nodes.len();
+        for (i, node) in nodes.iter().enumerate() {
+            let ctx = Ctx {
+                node: node.clone(),
+                current: node.clone(),
+                pos: i + 1,
+                size,
+            };
+            if let Some(template) = self.find_template(node, mode)? {
+                self.exec_template(template, &ctx, params)?;
+                continue;
+            }
+            // The built in rules
+            match self.item(node) {
+                Item::Root(_) | Item::Element(_) => {
+                    let children = self.children(node);
+                    self.apply_templates(&children, mode, params)?;
+                }
+                Item::Text(t) | Item::Attr(_, t) => self.emit_text(t),
+                Item::Comment(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(sheet: &str, doc: &str) -> String {
+        let sheet = format!(
+            "<xsl:stylesheet version=\"1.0\" \
+             xmlns:xsl=\"http://www.w3.org/1999/XSL/Transform\">{}</xsl:stylesheet>",
+            sheet
+        );
+        Stylesheet::parse(&sheet)
+            .unwrap()
+            .transform(&Element::parse(doc).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_xpath() {
+        let doc = "<config><os><version>29</version><short-id>fedora29</short-id></os>\
+                   <disk>/dev/vda</disk><disk>/dev/vdb</disk></config>";
+        let check = |expr: &str, expected: &str| {
+            let sheet = format!(
+                "<xsl:output method=\"text\"/><xsl:template match=\"/config\">\
+                 <xsl:value-of select=\"{}\"/></xsl:template>",
+                crate::xmlapi::escape_attr(expr)
+            );
+            assert_eq!(transform(&sheet, doc), expected, "{}", expr);
+        };
+        check("os/version", "29");
+        check("os/version >= 20 and os/version < 30", "true");
+        check("os/version + 1", "30");
+        check("count(disk)", "2");
+        check("disk[2]", "/dev/vdb");
+        check("disk[last()]", "/dev/vdb");
+        check("substring-after(disk, '/dev/')", "vda");
+        check("translate(os/short-id, 'fedora', 'FEDOR')", "FEDOR29");
+        check(
+            "concat(name(os/*[1]), '-', string-length(os/short-id))",
+            "version-8",
+        );
+        check("not(missing) and disk = '/dev/vdb'", "true");
+        check("substring('12345', 2, 3)", "234");
+        check("normalize-space('  a   b ')", "a b");
+        check("//short-id/../version * 2 div 4", "14.5");
+    }
+
+    #[test]
+    fn test_templates() {
+        let sheet = r#"
+          <xsl:output method="xml" indent="yes"/>
+          <xsl:param name="greeting" select="'hi'"/>
+          <xsl:template match="/config">
+            <result arch="{arch}-{$greeting}">
+              <xsl:apply-templates select="user"/>
+              <xsl:call-template name="disk">
+                <xsl:with-param name="dev" select="'vda'"/>
+              </xsl:call-template>
+              <xsl:if test="arch = 'x86_64'"><xsl:element name="{arch}"/></xsl:if>
+              <xsl:choose>
+                <xsl:when test="missing">wrong</xsl:when>
+                <xsl:otherwise><xsl:text>right</xsl:text></xsl:otherwise>
+              </xsl:choose>
+            </result>
+          </xsl:template>
+          <xsl:template match="user">
+            <xsl:variable name="upper" select="translate(., 'abc', 'ABC')"/>
+            <login idx="{position()}"><xsl:value-of select="$upper"/></login>
+          </xsl:template>
+          <xsl:template match="config/user[2]" priority="1">
+            <second><xsl:copy-of select="."/></second>
+          </xsl:template>
+          <xsl:template name="disk">
+            <xsl:param name="dev"/>
+            <xsl:param name="prefix">/dev/</xsl:param>
+            <disk><xsl:attribute name="path"><xsl:value-of select="concat($prefix, $dev)"/></xsl:attribute></disk>
+          </xsl:template>
+        "#;
+        let out = transform(
+            sheet,
+            "<config><arch>x86_64</arch><user>abe</user><user>cab</user></config>",
+        );
+        assert_eq!(
+            out,
+            "<?xml version=\"1.0\"?>\n\
+             <result arch=\"x86_64-hi\">\n  \
+             <login idx=\"1\">ABe</login>\n  \
+             <second>\n    <user>cab</user>\n  </second>\n  \
+             <disk path=\"/dev/vda\"/>\n  \
+             <x86_64/>right\n\
+             </result>\n"
+        );
+
+        // Built in rules copy text through
+        assert_eq!(
+            transform(
+                "<xsl:output method=\"text\"/><xsl:template match=\"b\">[<xsl:apply-templates/>]</xsl:template>",
+                "<a>x<b>y</b>z</a>"
+            ),
+            "x[y]z"
+        );
+    }
+}
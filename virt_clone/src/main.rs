@@ -1,3 +1,20 @@
+// virt-clone-rs: duplicate a virtual machine
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+use libvirtmanager::cli::CliIo;
+
 fn main() {
-    println!("Hello, world!");
+    env_logger::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut stdout = std::io::stdout();
+    let mut stderr = std::io::stderr();
+    let mut io = CliIo {
+        stdin: None,
+        stdout: &mut stdout,
+        stderr: &mut stderr,
+    };
+    let ret = libvirtmanager::virtclone::main(&args, &mut io, None);
+    std::process::exit(ret);
 }
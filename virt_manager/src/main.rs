@@ -1,6 +1,43 @@
+use libvirtmanager::{VmmDetails, VmmHost};
+
+const USAGE: &str = "Usage: virt-manager [-c URI] [--show-domain-editor NAME] \
+                     [--show-domain-performance NAME] [--show-host-summary]";
+
+fn run() -> Result<(), String> {
+    let mut uri = None;
+    let mut editor = None;
+    let mut performance = None;
+    let mut host = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-c" | "--connect" => uri = Some(value()?),
+            "--show-domain-editor" => editor = Some(value()?),
+            "--show-domain-performance" => performance = Some(value()?),
+            "--show-host-summary" => host = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => return Err(format!("Unknown argument '{}'\n{}", arg, USAGE)),
+        }
+    }
+    if let Some(name) = editor {
+        return VmmDetails::show_instance(uri.as_deref(), &name);
+    }
+    if let Some(name) = performance {
+        return VmmDetails::show_performance(uri.as_deref(), &name);
+    }
+    if host {
+        return VmmHost::show_instance(uri.as_deref());
+    }
+    libvirtmanager::run_main_app(uri.as_deref())
+}
+
 fn main() {
     env_logger::init();
-    if let Err(e) = libvirtmanager::run_main_app() {
+    if let Err(e) = run() {
         eprintln!("Error: {}", e);
         std::process::exit(1);
     }
@@ -1,3 +1,28 @@
+// virt-xml-rs: edit libvirt XML using command line options
+//
+// Copyright (C) 2025
+// This work is licensed under the GNU GPLv2 or later.
+
+use std::io::IsTerminal;
+
+use libvirtmanager::cli::CliIo;
+
 fn main() {
-    println!("Hello, world!");
+    env_logger::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let stdin = std::io::stdin();
+    let mut stdin_lock = stdin.lock();
+    let mut stdout = std::io::stdout();
+    let mut stderr = std::io::stderr();
+    let mut io = CliIo {
+        stdin: if stdin.is_terminal() {
+            None
+        } else {
+            Some(&mut stdin_lock)
+        },
+        stdout: &mut stdout,
+        stderr: &mut stderr,
+    };
+    let ret = libvirtmanager::virtxml::main(&args, &mut io, None);
+    std::process::exit(ret);
 }